@@ -0,0 +1,118 @@
+/*
+ * C bindings for embedding a zerofs file tree.
+ *
+ * Every function but zerofs_close and zerofs_last_error_message returns ZEROFS_OK on success and
+ * one of the ZEROFS_ERROR_* codes on failure, whose message zerofs_last_error_message returns.
+ * Paths are NUL-terminated UTF-8 strings with segments separated by `/`; an empty path or `/` is
+ * the root directory.
+ */
+
+#ifndef ZEROFS_H
+#define ZEROFS_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Results. */
+#define ZEROFS_OK 0
+#define ZEROFS_ERROR_INTERNAL 1
+#define ZEROFS_ERROR_INVALID_ARGUMENT 2
+#define ZEROFS_ERROR_NOT_SUPPORTED 3
+#define ZEROFS_ERROR_UNAVAILABLE 4
+#define ZEROFS_ERROR_TIMEOUT 5
+#define ZEROFS_ERROR_INVALID_PATH 100
+#define ZEROFS_ERROR_NOT_FOUND 101
+#define ZEROFS_ERROR_ALREADY_EXISTS 102
+#define ZEROFS_ERROR_NOT_A_FILE 103
+#define ZEROFS_ERROR_NOT_A_DIRECTORY 104
+#define ZEROFS_ERROR_DIR_NOT_EMPTY 105
+#define ZEROFS_ERROR_UNAUTHENTICATED 200
+#define ZEROFS_ERROR_PERMISSION_DENIED 201
+#define ZEROFS_ERROR_INVALID_SIGNATURE 202
+#define ZEROFS_ERROR_CONCURRENT_MODIFICATION 300
+#define ZEROFS_ERROR_READ_ONLY 301
+#define ZEROFS_ERROR_IMMUTABLE 302
+#define ZEROFS_ERROR_APPEND_ONLY 303
+#define ZEROFS_ERROR_LIMIT_EXCEEDED 400
+#define ZEROFS_ERROR_PAYLOAD_TOO_LARGE 401
+#define ZEROFS_ERROR_STORE_FULL 500
+#define ZEROFS_ERROR_STORE_ERROR 501
+#define ZEROFS_ERROR_NOT_REPLICATED 502
+#define ZEROFS_ERROR_CORRUPT_DATA 503
+
+/* Kinds of entities. */
+#define ZEROFS_KIND_FILE 0
+#define ZEROFS_KIND_DIR 1
+#define ZEROFS_KIND_SYMLINK 2
+
+/* An open file system. */
+typedef struct ZerofsFs ZerofsFs;
+
+/* The status of an entity. */
+typedef struct ZerofsStat {
+    /* One of the ZEROFS_KIND_* constants. */
+    uint32_t kind;
+    /* The length of the content of a file, or the number of entries of a directory. */
+    uint64_t size;
+    /* The creation time, in milliseconds since the Unix epoch. */
+    int64_t created_at_ms;
+    /* The time of the last modification, in milliseconds since the Unix epoch. */
+    int64_t modified_at_ms;
+} ZerofsStat;
+
+/*
+ * Called with each entry of a directory; returning anything other than 0 stops the listing. It
+ * must not be NULL.
+ */
+typedef int (*ZerofsReaddirCallback)(const char *name, uint32_t kind, void *context);
+
+/* Opens an empty file system kept in memory, to be closed with zerofs_close. */
+int zerofs_open_memory(ZerofsFs **out);
+
+/*
+ * Opens the file system kept in the local directory at path, creating it if there is none yet, to
+ * be closed with zerofs_close. Changes are kept once they are committed with zerofs_commit.
+ */
+int zerofs_open_dir(const char *path, ZerofsFs **out);
+
+/* Closes a file system. Does nothing if fs is NULL. */
+void zerofs_close(ZerofsFs *fs);
+
+/* Reads up to len bytes of a file from offset into buf, and stores the count read in read. */
+int zerofs_read(const ZerofsFs *fs, const char *path, uint64_t offset, uint8_t *buf, size_t len,
+                size_t *read);
+
+/* Writes len bytes as the file at path, replacing any file there. */
+int zerofs_write(const ZerofsFs *fs, const char *path, const uint8_t *data, size_t len);
+
+/* Creates an empty directory at path, along with any missing directory above it. */
+int zerofs_mkdir(const ZerofsFs *fs, const char *path);
+
+/* Calls callback with each entry of the directory at path, in name order. */
+int zerofs_readdir(const ZerofsFs *fs, const char *path, ZerofsReaddirCallback callback,
+                   void *context);
+
+/* Fills stat with the status of the entity at path. */
+int zerofs_stat(const ZerofsFs *fs, const char *path, ZerofsStat *stat);
+
+/* Removes the entity at path, with everything under it if it is a directory. */
+int zerofs_remove(const ZerofsFs *fs, const char *path);
+
+/* Stores the current root and writes its CID into buf as a NUL-terminated string. */
+int zerofs_commit(const ZerofsFs *fs, char *buf, size_t len);
+
+/*
+ * Returns the message of the last error on the calling thread, or NULL if the last call
+ * succeeded. It stays valid until the next call on the thread.
+ */
+const char *zerofs_last_error_message(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* ZEROFS_H */
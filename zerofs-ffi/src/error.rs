@@ -0,0 +1,95 @@
+use std::{
+    cell::RefCell,
+    ffi::CString,
+    os::raw::{c_char, c_int},
+    panic::{self, AssertUnwindSafe},
+    ptr,
+};
+
+use thiserror::Error;
+use zerofs::filesystem::{ErrorCode, FsError};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// Returned by the functions that succeed.
+pub const ZEROFS_OK: c_int = 0;
+
+thread_local! {
+    /// The message of the last error returned on the thread.
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The result of a call through the C interface.
+pub(crate) type FfiResult<T> = Result<T, FfiError>;
+
+/// An error from a call through the C interface.
+#[derive(Debug, Error)]
+pub(crate) enum FfiError {
+    /// A file system error.
+    #[error(transparent)]
+    Fs(#[from] FsError),
+
+    /// An argument passed from C is not valid, like a null pointer or a path that is not UTF-8.
+    #[error("Invalid argument: {0}")]
+    InvalidArgument(String),
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl FfiError {
+    /// Returns the code the error is returned to C as.
+    pub(crate) fn code(&self) -> ErrorCode {
+        match self {
+            FfiError::Fs(e) => e.code(),
+            FfiError::InvalidArgument(_) => ErrorCode::InvalidArgument,
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Returns the message of the last error returned by a function on the calling thread, or null if
+/// the last call succeeded.
+///
+/// The message is owned by the library and stays valid until the next call on the thread.
+#[no_mangle]
+pub extern "C" fn zerofs_last_error_message() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(ptr::null(), |message| message.as_ptr())
+    })
+}
+
+/// Runs `call` and returns what it returns to C: [`ZEROFS_OK`] or the number of the code of its
+/// error, whose message is kept for [`zerofs_last_error_message`].
+///
+/// A panic is caught rather than unwound into C, and returned as [`ErrorCode::Internal`]. The
+/// file system it happened in may be left half changed, and should be closed.
+pub(crate) fn ffi_call(call: impl FnOnce() -> FfiResult<()>) -> c_int {
+    let (code, message) = match panic::catch_unwind(AssertUnwindSafe(call)) {
+        Ok(Ok(())) => (ZEROFS_OK, None),
+        Ok(Err(e)) => (e.code().number() as c_int, Some(e.to_string())),
+        Err(_) => (
+            ErrorCode::Internal.number() as c_int,
+            Some("zerofs panicked".to_owned()),
+        ),
+    };
+
+    let message = message.map(|message| {
+        CString::new(message.replace('\0', " ")).expect("the message has no NUL byte")
+    });
+    LAST_ERROR.with(|last| *last.borrow_mut() = message);
+
+    code
+}
@@ -0,0 +1,587 @@
+use std::{
+    convert::TryFrom,
+    ffi::{CStr, CString},
+    os::raw::{c_char, c_int, c_void},
+    path::PathBuf,
+    ptr, slice,
+};
+
+use tokio::io::AsyncReadExt;
+use zerofs::{
+    blocking::{BlockingRuntime, BlockingZerofs},
+    embed::{FileHead, HeadStore, MemoryHead},
+    filesystem::{Dir, DiskStore, DiskStoreConfig, Entity, EntityType, FsError, Path},
+};
+use zeroutils_store::{IpldStore, MemoryStore, Storable};
+
+use crate::{ffi_call, FfiError, FfiResult};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The kind of a regular file.
+pub const ZEROFS_KIND_FILE: u32 = 0;
+
+/// The kind of a directory.
+pub const ZEROFS_KIND_DIR: u32 = 1;
+
+/// The kind of a symbolic link.
+pub const ZEROFS_KIND_SYMLINK: u32 = 2;
+
+/// The directory the blocks of a file system opened with [`zerofs_open_dir`] are kept in.
+const BLOCKS_DIR: &str = "blocks";
+
+/// The file the HEAD of a file system opened with [`zerofs_open_dir`] is kept in.
+const HEAD_FILE: &str = "HEAD";
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// A file system opened through the C interface, only ever handed to C behind a pointer.
+pub struct ZerofsFs {
+    /// The file tree.
+    inner: ZerofsTree,
+}
+
+/// The file tree of a [`ZerofsFs`], over the store it was opened with.
+enum ZerofsTree {
+    /// A file tree kept in memory.
+    Memory(BlockingZerofs<MemoryStore>),
+
+    /// A file tree kept in a local directory.
+    Disk(BlockingZerofs<DiskStore>),
+}
+
+/// The status of an entity, filled in by [`zerofs_stat`].
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ZerofsStat {
+    /// The kind of the entity, one of the `ZEROFS_KIND_*` constants.
+    pub kind: u32,
+
+    /// The length of the content of a file, or the number of entries of a directory.
+    pub size: u64,
+
+    /// The time the entity was created, in milliseconds since the Unix epoch.
+    pub created_at_ms: i64,
+
+    /// The time of the last modification of the entity, in milliseconds since the Unix epoch.
+    pub modified_at_ms: i64,
+}
+
+/// Called by [`zerofs_readdir`] with the name and kind of each entry, and the context passed to
+/// it. Returning anything other than `0` stops the listing. A null callback is refused.
+pub type ZerofsReaddirCallback =
+    Option<extern "C" fn(name: *const c_char, kind: u32, context: *mut c_void) -> c_int>;
+
+//--------------------------------------------------------------------------------------------------
+// Macros
+//--------------------------------------------------------------------------------------------------
+
+/// Runs `$body` with `$tree` bound to the [`BlockingZerofs`] of the file system `$fs`, whatever
+/// store it was opened with.
+macro_rules! with_tree {
+    ($fs:expr, $tree:ident => $body:expr) => {
+        match &$fs.inner {
+            ZerofsTree::Memory($tree) => $body,
+            ZerofsTree::Disk($tree) => $body,
+        }
+    };
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Opens an empty file system kept in memory, and stores a pointer to it in `out`, to be closed
+/// with [`zerofs_close`].
+///
+/// # Safety
+///
+/// `out` must be a valid pointer to write to.
+#[no_mangle]
+pub unsafe extern "C" fn zerofs_open_memory(out: *mut *mut ZerofsFs) -> c_int {
+    ffi_call(|| {
+        if out.is_null() {
+            return Err(invalid("out is null"));
+        }
+
+        let tree = open_tree(MemoryStore::default(), MemoryHead::default())?;
+        *out = Box::into_raw(Box::new(ZerofsFs {
+            inner: ZerofsTree::Memory(tree),
+        }));
+        Ok(())
+    })
+}
+
+/// Opens the file system kept in the local directory at `path`, creating the directory and an
+/// empty file system in it if there is none yet, and stores a pointer to it in `out`, to be closed
+/// with [`zerofs_close`].
+///
+/// The blocks are kept in the `blocks` directory under `path` and the HEAD in its `HEAD` file.
+/// Changes are only there the next time the file system is opened once they are committed with
+/// [`zerofs_commit`].
+///
+/// # Safety
+///
+/// `path` must be a NUL-terminated string and `out` a valid pointer to write to.
+#[no_mangle]
+pub unsafe extern "C" fn zerofs_open_dir(path: *const c_char, out: *mut *mut ZerofsFs) -> c_int {
+    ffi_call(|| {
+        if out.is_null() {
+            return Err(invalid("out is null"));
+        }
+
+        let path = get_local_path(path)?;
+        std::fs::create_dir_all(&path).map_err(FsError::from)?;
+
+        let store = BlockingRuntime::shared()
+            .block_on(DiskStore::open(
+                path.join(BLOCKS_DIR),
+                DiskStoreConfig::default(),
+            ))
+            .map_err(FsError::from)?;
+
+        let tree = open_tree(store, FileHead::new(path.join(HEAD_FILE)))?;
+        *out = Box::into_raw(Box::new(ZerofsFs {
+            inner: ZerofsTree::Disk(tree),
+        }));
+        Ok(())
+    })
+}
+
+/// Closes a file system opened with [`zerofs_open_memory`] or [`zerofs_open_dir`]. Does nothing if
+/// `fs` is null.
+///
+/// # Safety
+///
+/// `fs` must be null or a pointer returned by [`zerofs_open_memory`] or [`zerofs_open_dir`] that
+/// was not closed yet.
+#[no_mangle]
+pub unsafe extern "C" fn zerofs_close(fs: *mut ZerofsFs) {
+    if !fs.is_null() {
+        drop(Box::from_raw(fs));
+    }
+}
+
+/// Reads up to `len` bytes of the file at `path` into `buf`, from `offset` on, and stores the
+/// number of bytes read in `read`, which is less than `len` at the end of the file.
+///
+/// # Safety
+///
+/// `fs` must be an open file system, `path` a NUL-terminated string, `buf` valid for writing
+/// `len` bytes and `read` a valid pointer to write to.
+#[no_mangle]
+pub unsafe extern "C" fn zerofs_read(
+    fs: *const ZerofsFs,
+    path: *const c_char,
+    offset: u64,
+    buf: *mut u8,
+    len: usize,
+    read: *mut usize,
+) -> c_int {
+    ffi_call(|| {
+        let fs = get_fs(fs)?;
+        let path = get_path(path)?;
+        if (buf.is_null() && len > 0) || read.is_null() {
+            return Err(invalid("buf or read is null"));
+        }
+
+        let buf = if len > 0 {
+            slice::from_raw_parts_mut(buf, len)
+        } else {
+            &mut []
+        };
+
+        let count = with_tree!(fs, tree => {
+            let file = tree.get_entity_at(&path)?.as_file()?;
+            tree.get_runtime().block_on(async {
+                let mut reader = file.get_content_reader_from(offset).await?;
+
+                let mut count = 0;
+                while count < buf.len() {
+                    match reader.read(&mut buf[count..]).await? {
+                        0 => break,
+                        n => count += n,
+                    }
+                }
+
+                Ok::<_, FsError>(count)
+            })?
+        });
+
+        *read = count;
+        Ok(())
+    })
+}
+
+/// Writes the `len` bytes at `data` as the file at `path`, replacing any file there, and creates
+/// any missing directory above it.
+///
+/// # Safety
+///
+/// `fs` must be an open file system, `path` a NUL-terminated string and `data` valid for reading
+/// `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn zerofs_write(
+    fs: *const ZerofsFs,
+    path: *const c_char,
+    data: *const u8,
+    len: usize,
+) -> c_int {
+    ffi_call(|| {
+        let fs = get_fs(fs)?;
+        let path = get_path(path)?;
+        let data = match len {
+            0 => &[][..],
+            _ if data.is_null() => return Err(invalid("data is null")),
+            _ => slice::from_raw_parts(data, len),
+        };
+
+        Ok(with_tree!(fs, tree => tree.write_file_at(&path, data))?)
+    })
+}
+
+/// Creates an empty directory at `path`, along with any missing directory above it.
+///
+/// # Safety
+///
+/// `fs` must be an open file system and `path` a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn zerofs_mkdir(fs: *const ZerofsFs, path: *const c_char) -> c_int {
+    ffi_call(|| {
+        let fs = get_fs(fs)?;
+        let path = get_path(path)?;
+        Ok(with_tree!(fs, tree => tree.create_dir_at(&path))?)
+    })
+}
+
+/// Calls `callback` with the name and kind of each entry of the directory at `path`, in name
+/// order, and `context`. An empty path or `/` is the root directory.
+///
+/// # Safety
+///
+/// `fs` must be an open file system and `path` a NUL-terminated string. The names passed to
+/// `callback` are only valid during the call.
+#[no_mangle]
+pub unsafe extern "C" fn zerofs_readdir(
+    fs: *const ZerofsFs,
+    path: *const c_char,
+    callback: ZerofsReaddirCallback,
+    context: *mut c_void,
+) -> c_int {
+    ffi_call(|| {
+        let fs = get_fs(fs)?;
+        let path = get_path(path)?;
+        let callback = callback.ok_or_else(|| invalid("callback is null"))?;
+
+        with_tree!(fs, tree => {
+            let dir = tree.get_entity_at(&path)?.as_dir()?;
+            let runtime = tree.get_runtime();
+            for (name, _) in dir.get_entries() {
+                let kind = runtime
+                    .block_on(dir.get_entity_type(name))?
+                    .map_or(ZEROFS_KIND_FILE, get_kind);
+                let name = CString::new(name.as_str())
+                    .map_err(|_| invalid("entry name has a NUL byte"))?;
+
+                if callback(name.as_ptr(), kind, context) != 0 {
+                    break;
+                }
+            }
+        });
+
+        Ok(())
+    })
+}
+
+/// Fills `stat` with the status of the entity at `path`.
+///
+/// # Safety
+///
+/// `fs` must be an open file system, `path` a NUL-terminated string and `stat` a valid pointer to
+/// write to.
+#[no_mangle]
+pub unsafe extern "C" fn zerofs_stat(
+    fs: *const ZerofsFs,
+    path: *const c_char,
+    stat: *mut ZerofsStat,
+) -> c_int {
+    ffi_call(|| {
+        let fs = get_fs(fs)?;
+        let path = get_path(path)?;
+        if stat.is_null() {
+            return Err(invalid("stat is null"));
+        }
+
+        *stat = with_tree!(fs, tree => {
+            let entity = tree.get_entity_at(&path)?;
+            let metadata = entity.get_metadata();
+            let size = match &entity {
+                Entity::File(file) => tree.get_runtime().block_on(file.get_size())?.len,
+                Entity::Dir(dir) => dir.len() as u64,
+                Entity::Symlink(_) => 0,
+            };
+
+            ZerofsStat {
+                kind: get_kind(metadata.entity_type),
+                size,
+                created_at_ms: metadata.created_at.timestamp_millis(),
+                modified_at_ms: metadata.modified_at.timestamp_millis(),
+            }
+        });
+
+        Ok(())
+    })
+}
+
+/// Removes the entity at `path`, with everything under it if it is a directory.
+///
+/// # Safety
+///
+/// `fs` must be an open file system and `path` a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn zerofs_remove(fs: *const ZerofsFs, path: *const c_char) -> c_int {
+    ffi_call(|| {
+        let fs = get_fs(fs)?;
+        let path = get_path(path)?;
+        Ok(with_tree!(fs, tree => tree.remove_at(&path))?)
+    })
+}
+
+/// Stores the current root and writes its CID into `buf` as a NUL-terminated string.
+///
+/// Fails with `ZEROFS_ERROR_INVALID_ARGUMENT` if `buf` cannot hold the CID and its NUL byte.
+///
+/// # Safety
+///
+/// `fs` must be an open file system and `buf` valid for writing `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn zerofs_commit(fs: *const ZerofsFs, buf: *mut c_char, len: usize) -> c_int {
+    ffi_call(|| {
+        let fs = get_fs(fs)?;
+        if buf.is_null() {
+            return Err(invalid("buf is null"));
+        }
+
+        let cid = with_tree!(fs, tree => tree.commit())?.to_string();
+        if cid.len() >= len {
+            return Err(invalid(format!(
+                "buf holds {} bytes, the CID needs {}",
+                len,
+                cid.len() + 1
+            )));
+        }
+
+        ptr::copy_nonoverlapping(cid.as_ptr() as *const c_char, buf, cid.len());
+        *buf.add(cid.len()) = 0;
+        Ok(())
+    })
+}
+
+/// Opens the file tree in `store` at the HEAD saved in `head`, committing an empty one first if no
+/// HEAD was saved yet.
+fn open_tree<S>(store: S, head: impl HeadStore + 'static) -> FfiResult<BlockingZerofs<S>>
+where
+    S: IpldStore + Clone + Send + Sync + 'static,
+{
+    let runtime = BlockingRuntime::shared();
+    runtime.block_on(async {
+        if head.load_head().await?.is_none() {
+            let root = Dir::new(store.clone()).store().await?;
+            head.save_head(&root).await?;
+        }
+
+        Ok::<_, FsError>(())
+    })?;
+
+    Ok(BlockingZerofs::open(store, head, runtime)?)
+}
+
+/// Returns the file system behind `fs`.
+unsafe fn get_fs<'a>(fs: *const ZerofsFs) -> FfiResult<&'a ZerofsFs> {
+    fs.as_ref().ok_or_else(|| invalid("fs is null"))
+}
+
+/// Parses the NUL-terminated string at `path` as a path.
+unsafe fn get_path(path: *const c_char) -> FfiResult<Path> {
+    if path.is_null() {
+        return Err(invalid("path is null"));
+    }
+
+    let path = CStr::from_ptr(path)
+        .to_str()
+        .map_err(|_| invalid("path is not UTF-8"))?;
+
+    Ok(Path::try_from(path)?)
+}
+
+/// Reads the NUL-terminated string at `path` as a local path.
+unsafe fn get_local_path(path: *const c_char) -> FfiResult<PathBuf> {
+    if path.is_null() {
+        return Err(invalid("path is null"));
+    }
+
+    let path = CStr::from_ptr(path)
+        .to_str()
+        .map_err(|_| invalid("path is not UTF-8"))?;
+
+    Ok(PathBuf::from(path))
+}
+
+/// Returns the `ZEROFS_KIND_*` constant of `entity_type`.
+fn get_kind(entity_type: EntityType) -> u32 {
+    match entity_type {
+        EntityType::File => ZEROFS_KIND_FILE,
+        EntityType::Dir => ZEROFS_KIND_DIR,
+        EntityType::Symlink => ZEROFS_KIND_SYMLINK,
+    }
+}
+
+/// Returns an [`FfiError::InvalidArgument`] with `message`.
+fn invalid(message: impl Into<String>) -> FfiError {
+    FfiError::InvalidArgument(message.into())
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use zerofs::filesystem::ErrorCode;
+
+    use crate::{zerofs_last_error_message, ZEROFS_OK};
+
+    use super::*;
+
+    extern "C" fn collect(name: *const c_char, kind: u32, context: *mut c_void) -> c_int {
+        let entries = unsafe { &mut *(context as *mut Vec<(String, u32)>) };
+        let name = unsafe { CStr::from_ptr(name) }.to_str().unwrap().to_owned();
+        entries.push((name, kind));
+        0
+    }
+
+    #[test]
+    fn test_ffi_roundtrip() {
+        let path = |path: &str| CString::new(path).unwrap();
+        unsafe {
+            let mut fs = ptr::null_mut();
+            assert_eq!(zerofs_open_memory(&mut fs), ZEROFS_OK);
+
+            let content = b"hello from C";
+            assert_eq!(zerofs_mkdir(fs, path("/docs").as_ptr()), ZEROFS_OK);
+            assert_eq!(
+                zerofs_write(
+                    fs,
+                    path("/docs/hello").as_ptr(),
+                    content.as_ptr(),
+                    content.len()
+                ),
+                ZEROFS_OK
+            );
+
+            // Reads start at the offset and stop at the end of the file.
+            let mut buf = [0u8; 32];
+            let mut read = 0;
+            let code = zerofs_read(
+                fs,
+                path("/docs/hello").as_ptr(),
+                6,
+                buf.as_mut_ptr(),
+                buf.len(),
+                &mut read,
+            );
+            assert_eq!(code, ZEROFS_OK);
+            assert_eq!(&buf[..read], b"from C");
+
+            let mut stat = ZerofsStat::default();
+            let code = zerofs_stat(fs, path("/docs/hello").as_ptr(), &mut stat);
+            assert_eq!(code, ZEROFS_OK);
+            assert_eq!((stat.kind, stat.size), (ZEROFS_KIND_FILE, 12));
+
+            let mut entries = Vec::<(String, u32)>::new();
+            let context = &mut entries as *mut _ as *mut c_void;
+            assert_eq!(
+                zerofs_readdir(fs, path("/").as_ptr(), Some(collect), context),
+                ZEROFS_OK
+            );
+            assert_eq!(entries, [("docs".to_owned(), ZEROFS_KIND_DIR)]);
+
+            // Errors come back as codes, with a message.
+            assert_eq!(
+                zerofs_mkdir(fs, path("/docs").as_ptr()),
+                ErrorCode::AlreadyExists.number() as c_int
+            );
+            assert!(!zerofs_last_error_message().is_null());
+            assert_eq!(zerofs_remove(fs, path("/docs").as_ptr()), ZEROFS_OK);
+            assert_eq!(
+                zerofs_stat(fs, path("/docs").as_ptr(), &mut stat),
+                ErrorCode::NotFound.number() as c_int
+            );
+            assert_eq!(
+                zerofs_mkdir(ptr::null(), path("/a").as_ptr()),
+                ErrorCode::InvalidArgument.number() as c_int
+            );
+
+            let mut cid = [0 as c_char; 128];
+            assert_eq!(zerofs_commit(fs, cid.as_mut_ptr(), cid.len()), ZEROFS_OK);
+            assert!(zerofs_last_error_message().is_null());
+            assert_eq!(
+                zerofs_commit(fs, cid.as_mut_ptr(), 4),
+                ErrorCode::InvalidArgument.number() as c_int
+            );
+
+            zerofs_close(fs);
+        }
+    }
+
+    #[test]
+    fn test_ffi_open_dir() {
+        let nanos = std::time::UNIX_EPOCH.elapsed().unwrap().as_nanos();
+        let dir = std::env::temp_dir().join(format!("zerofs-ffi-{}", nanos));
+        let dir_path = CString::new(dir.to_str().unwrap()).unwrap();
+        let path = |path: &str| CString::new(path).unwrap();
+        unsafe {
+            let mut fs = ptr::null_mut();
+            assert_eq!(zerofs_open_dir(dir_path.as_ptr(), &mut fs), ZEROFS_OK);
+
+            let content = b"kept on disk";
+            assert_eq!(
+                zerofs_write(fs, path("/notes").as_ptr(), content.as_ptr(), content.len()),
+                ZEROFS_OK
+            );
+            let mut cid = [0 as c_char; 128];
+            assert_eq!(zerofs_commit(fs, cid.as_mut_ptr(), cid.len()), ZEROFS_OK);
+            zerofs_close(fs);
+
+            // The committed tree is there when the directory is opened again.
+            let mut fs = ptr::null_mut();
+            assert_eq!(zerofs_open_dir(dir_path.as_ptr(), &mut fs), ZEROFS_OK);
+            let mut buf = [0u8; 32];
+            let mut read = 0;
+            let code = zerofs_read(
+                fs,
+                path("/notes").as_ptr(),
+                5,
+                buf.as_mut_ptr(),
+                buf.len(),
+                &mut read,
+            );
+            assert_eq!(code, ZEROFS_OK);
+            assert_eq!(&buf[..read], b"on disk");
+
+            // A null callback is refused rather than called.
+            assert_eq!(
+                zerofs_readdir(fs, path("/").as_ptr(), None, ptr::null_mut()),
+                ErrorCode::InvalidArgument.number() as c_int
+            );
+
+            zerofs_close(fs);
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
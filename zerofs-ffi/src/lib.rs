@@ -0,0 +1,16 @@
+#![warn(missing_docs)]
+//! C bindings for embedding a zerofs file tree in applications that are not written in Rust.
+//!
+//! The functions take and return opaque pointers to the file system, return `0` on success and
+//! the number of an [`ErrorCode`][zerofs::filesystem::ErrorCode] on failure, whose message
+//! [`zerofs_last_error_message`] returns. The header is at `include/zerofs.h`.
+
+mod error;
+mod fs;
+
+//--------------------------------------------------------------------------------------------------
+// Exports
+//--------------------------------------------------------------------------------------------------
+
+pub use error::*;
+pub use fs::*;
@@ -0,0 +1,75 @@
+//! Compares the throughput of reading file content through the copying reader and through the
+//! stream of reference-counted blocks, with every block served from a warm [`CachedStore`].
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use futures::TryStreamExt;
+use tokio::{io::AsyncReadExt, runtime::Runtime};
+use zerofs::filesystem::{CachedStore, Chunker, FastCdc, File};
+use zeroutils_store::MemoryStore;
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The sizes of the files read, in bytes.
+const SIZES: [usize; 2] = [1024 * 1024, 16 * 1024 * 1024];
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+fn bench_reads(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("read");
+
+    for size in SIZES {
+        let data = (0..size)
+            .map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8)
+            .collect::<Vec<_>>();
+
+        let file = runtime.block_on(async {
+            let mut file = File::new(CachedStore::new(MemoryStore::default()));
+            file.put_content(&data[..], &Chunker::FastCdc(FastCdc::default()))
+                .await
+                .unwrap();
+
+            // Warm the cache so both reads are served from memory.
+            file.get_content_stream()
+                .try_collect::<Vec<_>>()
+                .await
+                .unwrap();
+
+            file
+        });
+
+        group.throughput(Throughput::Bytes(size as u64));
+
+        group.bench_with_input(BenchmarkId::new("reader", size), &file, |b, file| {
+            b.to_async(&runtime).iter(|| async {
+                let mut content = Vec::with_capacity(size);
+                file.get_content_reader()
+                    .await
+                    .unwrap()
+                    .read_to_end(&mut content)
+                    .await
+                    .unwrap();
+
+                content
+            })
+        });
+
+        group.bench_with_input(BenchmarkId::new("stream", size), &file, |b, file| {
+            b.to_async(&runtime).iter(|| async {
+                file.get_content_stream()
+                    .try_collect::<Vec<_>>()
+                    .await
+                    .unwrap()
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_reads);
+criterion_main!(benches);
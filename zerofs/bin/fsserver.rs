@@ -1,9 +1,21 @@
 use std::sync::Arc;
 
+use futures::future;
+use tokio::sync::Mutex;
 use zerofs::{
-    config::ZerofsConfig,
-    service::{FsHttpServer, ServiceResult},
+    config::{ConfigLoader, Frontend, ZerofsConfig},
+    filesystem::{Dir, DiskStore, DiskStoreConfig},
+    service::{FsHttpServer, FsService, ServiceError, ServiceResult},
 };
+use zeroutils_config::MainConfig;
+use zeroutils_store::{IpldStore, MemoryStore};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The directory under the base directory the blocks are kept in.
+const STORE_DIR: &str = "store";
 
 //--------------------------------------------------------------------------------------------------
 // Main
@@ -13,7 +25,106 @@ use zerofs::{
 async fn main() -> ServiceResult<()> {
     tracing_subscriber::fmt::init();
 
-    let config = Arc::new(ZerofsConfig::default());
-    let server = FsHttpServer::new(config);
-    server.start().await
+    // The config comes from the file named by `ZEROFS_CONFIG`, `ZEROFS_`-prefixed environment
+    // variables and `--set path=value` arguments, in increasing precedence.
+    let mut loader = ConfigLoader::new().env();
+    let mut print_config = false;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--set" => loader = loader.set_str(&args.next().unwrap_or_default())?,
+            "--print-config" => print_config = true,
+            _ => {
+                return Err(ServiceError::InvalidConfig(vec![format!(
+                    "unknown argument: {}",
+                    arg
+                )]))
+            }
+        }
+    }
+
+    let config = loader.load()?;
+    if print_config {
+        config.print_effective()?;
+    }
+
+    config.validate()?;
+
+    let Some(base_dir) = &config.interface.base_dir else {
+        tracing::warn!("No base directory is set, blocks are kept in memory");
+        return serve(MemoryStore::default(), config).await;
+    };
+
+    let store = DiskStore::open(
+        base_dir.join(STORE_DIR),
+        DiskStoreConfig::from(&config.store),
+    )
+    .await?;
+
+    serve(store, config).await
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Serves the file system in `store` through the frontends enabled in `config`.
+async fn serve<S>(store: S, config: ZerofsConfig) -> ServiceResult<()>
+where
+    S: IpldStore + Clone + Send + Sync + 'static,
+{
+    // The store must be able to encode nodes with the configured codec.
+    if !store
+        .get_supported_codecs()
+        .contains(&config.store.codec.into())
+    {
+        return Err(ServiceError::InvalidConfig(vec![format!(
+            "the store does not support the {:?} node codec",
+            config.store.codec
+        )]));
+    }
+
+    let interface = config.interface.clone();
+    let service = Arc::new(Mutex::new(FsService::new(
+        Dir::new(store.clone()),
+        Arc::new(config),
+    )));
+
+    let mut servers = Vec::new();
+    for frontend in interface.frontends.iter() {
+        match frontend {
+            Frontend::Http => {
+                let server = FsHttpServer::new(Arc::clone(&service)).await;
+                servers.push(Box::pin(async move { server.start().await })
+                    as future::BoxFuture<'static, ServiceResult<()>>);
+            }
+            #[cfg(feature = "gateway")]
+            Frontend::Gateway => {
+                let address = interface.bind[frontend];
+                let mut server = zerofs::service::FsGatewayServer::new(store.clone(), address)
+                    .with_service(Arc::clone(&service));
+                for path in interface.published.iter() {
+                    server = server.with_published_path(path.clone());
+                }
+
+                servers.push(Box::pin(async move { server.start().await }));
+            }
+            frontend => {
+                tracing::warn!(
+                    "The {} frontend is not served by this build",
+                    frontend.as_str()
+                );
+            }
+        }
+    }
+
+    if servers.is_empty() {
+        return Err(ServiceError::InvalidConfig(vec![
+            "none of the enabled frontends is served by this build".to_owned(),
+        ]));
+    }
+
+    // The first server to stop stops the node.
+    let (result, _, _) = future::select_all(servers).await;
+    result
 }
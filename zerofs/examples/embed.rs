@@ -0,0 +1,66 @@
+//! Embeds a zerofs file tree in a process, without the service layer.
+//!
+//! A tree is created, written to and committed, then opened again from its HEAD file, as an
+//! application would on its next start. The blocks are kept in memory here, and would be kept in
+//! a persistent store in an application.
+//!
+//! ```sh
+//! cargo run --example embed
+//! ```
+
+use tokio::io::AsyncReadExt;
+use zerofs::{
+    embed::{FileHead, Zerofs},
+    filesystem::{Chunker, Entity, File},
+};
+use zeroutils_key::{Ed25519KeyPair, KeyPairGenerate};
+use zeroutils_store::{MemoryStore, Storable};
+
+//--------------------------------------------------------------------------------------------------
+// Main
+//--------------------------------------------------------------------------------------------------
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::init();
+
+    let store = MemoryStore::default();
+    let head_path = std::env::temp_dir().join(format!("zerofs-embed-{}", std::process::id()));
+
+    // Create a tree owned by a new key, and add a file to it.
+    let key = Ed25519KeyPair::generate(&mut rand::thread_rng())?;
+    let zerofs = Zerofs::create(store.clone(), &key, FileHead::new(&head_path)).await?;
+
+    let mut file = File::new(store.clone());
+    file.put_content(&b"written by an embedded zerofs"[..], &Chunker::Store)
+        .await?;
+    let cid = file.store().await?;
+    zerofs
+        .get_root()
+        .link_at(&"notes/hello".parse()?, cid)
+        .await?;
+
+    let head = zerofs.commit().await?;
+    tracing::info!("Committed HEAD {}", head);
+    drop(zerofs);
+
+    // Open the tree again from its HEAD and read the file back.
+    let zerofs = Zerofs::open(store, FileHead::new(&head_path)).await?;
+    let (root, _) = zerofs.get_root().load().await?;
+    let notes = match root.get_entity(&"notes".parse()?).await? {
+        Some(Entity::Dir(notes)) => notes.clone(),
+        _ => anyhow::bail!("notes is missing"),
+    };
+
+    if let Some(Entity::File(file)) = notes.get_entity(&"hello".parse()?).await? {
+        let mut content = String::new();
+        file.get_content_reader()
+            .await?
+            .read_to_string(&mut content)
+            .await?;
+        tracing::info!("Read notes/hello: {}", content);
+    }
+
+    std::fs::remove_file(&head_path)?;
+    Ok(())
+}
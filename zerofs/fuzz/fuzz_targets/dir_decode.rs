@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use zerofs::testing;
+
+fuzz_target!(|data: &[u8]| {
+    testing::check_dir_encoding(data).unwrap();
+});
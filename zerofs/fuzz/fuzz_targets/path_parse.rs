@@ -0,0 +1,10 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use zerofs::{filesystem::Path, testing};
+
+fuzz_target!(|data: &str| {
+    if let Ok(path) = data.parse::<Path>() {
+        testing::check_path(&path).unwrap();
+    }
+});
@@ -0,0 +1,342 @@
+//! Blocking wrappers over the [embedding API][crate::embed], for applications that cannot await at
+//! every call site, like command-line tools and FFI hosts.
+//!
+//! Each wrapper runs its operations to completion on a [`BlockingRuntime`], which is either a
+//! runtime shared by the whole process or a handle to one the application already runs. The
+//! blocking calls must not be made from inside that runtime, where they would panic, as with
+//! [`tokio::runtime::Handle::block_on`].
+
+use std::{future::Future, io::SeekFrom};
+
+use bytes::Bytes;
+use lazy_static::lazy_static;
+use tokio::runtime::{self, Handle, Runtime};
+use zeroutils_key::GetPublicKey;
+use zeroutils_store::{ipld::cid::Cid, IpldStore};
+
+use crate::{
+    embed::{HeadStore, Zerofs},
+    filesystem::{
+        DescriptorFlags, DirHandle, Entity, EntityStat, File, FileHandle, FsResult,
+        MemoryBufferStore, OpenFile, Path,
+    },
+};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+lazy_static! {
+    /// The runtime shared by the blocking wrappers that are not given one, started on first use.
+    static ref SHARED_RUNTIME: Runtime = runtime::Builder::new_multi_thread()
+        .enable_all()
+        .thread_name("zerofs-blocking")
+        .build()
+        .expect("failed to start the shared zerofs runtime");
+}
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The runtime the blocking wrappers run their operations on.
+#[derive(Debug, Clone)]
+pub struct BlockingRuntime {
+    /// The handle to the runtime.
+    handle: Handle,
+}
+
+/// A blocking [`Zerofs`].
+pub struct BlockingZerofs<S>
+where
+    S: IpldStore,
+{
+    /// The file tree.
+    inner: Zerofs<S>,
+
+    /// The runtime the operations run on.
+    runtime: BlockingRuntime,
+}
+
+/// A blocking [`DirHandle`].
+#[derive(Debug, Clone)]
+pub struct BlockingDirHandle<S>
+where
+    S: IpldStore + Sync,
+{
+    /// The directory handle.
+    inner: DirHandle<S, MemoryBufferStore<S>>,
+
+    /// The runtime the operations run on.
+    runtime: BlockingRuntime,
+}
+
+/// A blocking [`OpenFile`].
+#[derive(Debug)]
+pub struct BlockingOpenFile<S>
+where
+    S: IpldStore + Sync,
+{
+    /// The open file description.
+    inner: OpenFile<S, MemoryBufferStore<S>>,
+
+    /// The runtime the operations run on.
+    runtime: BlockingRuntime,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl BlockingRuntime {
+    /// Returns the runtime shared by the whole process, starting it on first use.
+    pub fn shared() -> Self {
+        Self {
+            handle: SHARED_RUNTIME.handle().clone(),
+        }
+    }
+
+    /// Runs the operations on the runtime of `handle`, like one the application already runs.
+    pub fn from_handle(handle: Handle) -> Self {
+        Self { handle }
+    }
+
+    /// Returns the handle to the runtime.
+    pub fn get_handle(&self) -> &Handle {
+        &self.handle
+    }
+
+    /// Runs `future` to completion on the runtime, blocking the current thread.
+    ///
+    /// Panics if called from inside an asynchronous context.
+    pub fn block_on<F>(&self, future: F) -> F::Output
+    where
+        F: Future,
+    {
+        self.handle.block_on(future)
+    }
+}
+
+impl<S> BlockingZerofs<S>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    /// Wraps `inner`, running its operations on `runtime`.
+    pub fn new(inner: Zerofs<S>, runtime: BlockingRuntime) -> Self {
+        Self { inner, runtime }
+    }
+
+    /// Creates an empty file tree, see [`Zerofs::create`].
+    pub fn create<K>(
+        store: S,
+        key: &K,
+        head: impl HeadStore + 'static,
+        runtime: BlockingRuntime,
+    ) -> FsResult<Self>
+    where
+        K: GetPublicKey,
+    {
+        let inner = runtime.block_on(Zerofs::create(store, key, head))?;
+        Ok(Self::new(inner, runtime))
+    }
+
+    /// Opens the file tree at the saved HEAD, see [`Zerofs::open`].
+    pub fn open(
+        store: S,
+        head: impl HeadStore + 'static,
+        runtime: BlockingRuntime,
+    ) -> FsResult<Self> {
+        let inner = runtime.block_on(Zerofs::open(store, head))?;
+        Ok(Self::new(inner, runtime))
+    }
+
+    /// Returns the wrapped file tree.
+    pub fn get_inner(&self) -> &Zerofs<S> {
+        &self.inner
+    }
+
+    /// Returns the runtime the operations run on.
+    pub fn get_runtime(&self) -> &BlockingRuntime {
+        &self.runtime
+    }
+
+    /// Creates a handle to the root directory with the given flags.
+    pub fn make_handle(&self, flags: DescriptorFlags) -> BlockingDirHandle<S> {
+        BlockingDirHandle {
+            inner: self.inner.make_handle(flags),
+            runtime: self.runtime.clone(),
+        }
+    }
+
+    /// Creates a handle to the directory at `path`, see [`Zerofs::make_handle_at`].
+    pub fn make_handle_at(
+        &self,
+        path: &Path,
+        flags: DescriptorFlags,
+        create: bool,
+    ) -> FsResult<BlockingDirHandle<S>> {
+        let inner = self
+            .runtime
+            .block_on(self.inner.make_handle_at(path, flags, create))?;
+
+        Ok(BlockingDirHandle {
+            inner,
+            runtime: self.runtime.clone(),
+        })
+    }
+
+    /// Links the entity with the given [`Cid`] at `path`, see [`RootDir::link_at`].
+    ///
+    /// [`RootDir::link_at`]: crate::filesystem::RootDir::link_at
+    pub fn link_at(&self, path: &Path, cid: Cid) -> FsResult<()> {
+        self.runtime
+            .block_on(self.inner.get_root().link_at(path, cid))
+    }
+
+    /// Returns the entity at `path`, see [`Zerofs::get_entity_at`].
+    pub fn get_entity_at(&self, path: &Path) -> FsResult<Entity<S>> {
+        self.runtime.block_on(self.inner.get_entity_at(path))
+    }
+
+    /// Creates an empty directory at `path`, see [`Zerofs::create_dir_at`].
+    pub fn create_dir_at(&self, path: &Path) -> FsResult<()> {
+        self.runtime.block_on(self.inner.create_dir_at(path))
+    }
+
+    /// Writes `content` as the file at `path`, see [`Zerofs::write_file_at`].
+    pub fn write_file_at(&self, path: &Path, content: &[u8]) -> FsResult<()> {
+        self.runtime
+            .block_on(self.inner.write_file_at(path, content))
+    }
+
+    /// Removes the entity at `path`, see [`Zerofs::remove_at`].
+    pub fn remove_at(&self, path: &Path) -> FsResult<()> {
+        self.runtime.block_on(self.inner.remove_at(path))
+    }
+
+    /// Stores the current root and saves it as the HEAD, see [`Zerofs::commit`].
+    pub fn commit(&self) -> FsResult<Cid> {
+        self.runtime.block_on(self.inner.commit())
+    }
+
+    /// Returns the saved HEAD, see [`Zerofs::get_head`].
+    pub fn get_head(&self) -> FsResult<Option<Cid>> {
+        self.runtime.block_on(self.inner.get_head())
+    }
+}
+
+impl<S> BlockingDirHandle<S>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    /// Returns the wrapped directory handle.
+    pub fn get_inner(&self) -> &DirHandle<S, MemoryBufferStore<S>> {
+        &self.inner
+    }
+
+    /// Returns the [`EntityStat`] of the entity at each of `paths`, see
+    /// [`DirHandle::stat_many`].
+    pub fn stat_many(&self, paths: &[Path]) -> FsResult<Vec<Option<EntityStat>>> {
+        self.runtime.block_on(self.inner.stat_many(paths))
+    }
+
+    /// Returns whether there is an entity at each of `paths`, see [`DirHandle::exists_many`].
+    pub fn exists_many(&self, paths: &[Path]) -> FsResult<Vec<bool>> {
+        self.runtime.block_on(self.inner.exists_many(paths))
+    }
+}
+
+impl<S> BlockingOpenFile<S>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    /// Opens the file of `handle`, see [`OpenFile::open`].
+    pub fn open(
+        handle: FileHandle<S, MemoryBufferStore<S>>,
+        runtime: BlockingRuntime,
+    ) -> FsResult<Self> {
+        let inner = runtime.block_on(OpenFile::open(handle))?;
+        Ok(Self { inner, runtime })
+    }
+
+    /// Returns the wrapped open file description.
+    pub fn get_inner(&self) -> &OpenFile<S, MemoryBufferStore<S>> {
+        &self.inner
+    }
+
+    /// Moves the position, see [`OpenFile::seek`].
+    pub fn seek(&mut self, pos: SeekFrom) -> FsResult<u64> {
+        self.inner.seek(pos)
+    }
+
+    /// Reads up to `len` bytes from the position, see [`OpenFile::read`].
+    pub fn read(&mut self, len: u64) -> FsResult<Bytes> {
+        self.runtime.block_on(self.inner.read(len))
+    }
+
+    /// Writes `bytes` at the position, see [`OpenFile::write`].
+    pub fn write(&mut self, bytes: impl AsRef<[u8]>) -> FsResult<u64> {
+        self.runtime.block_on(self.inner.write(bytes))
+    }
+
+    /// Closes the file and returns it with the writes made to it.
+    pub fn close(self) -> File<MemoryBufferStore<S>> {
+        self.inner.close()
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl Default for BlockingRuntime {
+    fn default() -> Self {
+        Self::shared()
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use zeroutils_store::{MemoryStore, Storable};
+
+    use crate::{
+        embed::MemoryHead,
+        filesystem::{Dir, File},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_blocking_zerofs() -> anyhow::Result<()> {
+        let runtime = BlockingRuntime::shared();
+        let store = MemoryStore::default();
+        let head = Arc::new(MemoryHead::default());
+        let (empty, file) = runtime.block_on(async {
+            let empty = Dir::new(store.clone()).store().await?;
+            head.save_head(&empty).await?;
+            let file = File::new(store.clone()).store().await?;
+            FsResult::Ok((empty, file))
+        })?;
+
+        let zerofs = BlockingZerofs::open(store, head, runtime)?;
+        assert_eq!(zerofs.get_head()?, Some(empty));
+
+        zerofs.link_at(&"docs/notes".parse()?, file)?;
+        let committed = zerofs.commit()?;
+        assert_ne!(committed, empty);
+        assert_eq!(zerofs.get_head()?, Some(committed));
+
+        let handle = zerofs.make_handle(DescriptorFlags::READ);
+        let paths = ["docs/notes".parse()?, "docs/missing".parse()?];
+        assert_eq!(handle.exists_many(&paths)?, [true, false]);
+        assert_eq!(handle.stat_many(&paths)?[0].as_ref().unwrap().cid, file);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,722 @@
+use std::{
+    future::Future,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use bytes::Bytes;
+use chrono::Utc;
+use futures::{
+    stream::{self, BoxStream},
+    StreamExt, TryStream,
+};
+use reqwest::{header, RequestBuilder, Response, StatusCode};
+use serde::de::DeserializeOwned;
+use typed_builder::TypedBuilder;
+use zeroutils_store::ipld::cid::Cid;
+
+use crate::{
+    filesystem::{
+        Acl, ChangeSet, DescriptorFlags, EntityStat, OpenFlags, Path, PathSegment, Ref, RefIndex,
+        RefPrecondition, SnapshotIndex, TransferStats,
+    },
+    service::{
+        handler::{AUTHN_USER_TOKEN, AUTHN_USER_TOKEN_MAP},
+        middleware::{CONSISTENCY_HEADER, IDEMPOTENCY_KEY_HEADER},
+        AclResponse, ContentCidResponse, EntityOperation, EntityOperationKind, ExistsManyResponse,
+        GetAclAt, GlobMatch, GlobResponse, Job, NodeStatus, OpenAt, PathsRequest, Problem,
+        RaftState, ReadConsistency, ReadDirEntry, ReadOnlyMode, RealpathResponse, RefUpdate,
+        SearchResponse, SetAclAt, SnapshotCreated, StatManyResponse, WriteAtResponse,
+        PROBLEM_CONTENT_TYPE, RETRYABLE_HEADER,
+    },
+};
+
+use super::{ClientError, ClientResult};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The default number of times a failed request is retried.
+pub const DEFAULT_MAX_RETRIES: u32 = 3;
+
+/// The default delay before the first retry.
+pub const DEFAULT_INITIAL_BACKOFF: Duration = Duration::from_millis(200);
+
+/// The default maximum delay between two retries.
+pub const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(10);
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// An async client for the user API of a remote `zerofs` node and, optionally, its admin API.
+///
+/// The session set up by [`authenticate`][Self::authenticate] is kept as a cookie and sent with
+/// every subsequent request. Requests that fail because the node is unreachable, overloaded or
+/// rate limiting the client are retried according to the [`RetryPolicy`], waiting for as long as
+/// the node asks to with `Retry-After`. Writes carry an idempotency key, so a retried write is
+/// applied at most once.
+///
+/// ```no_run
+/// # async fn example() -> zerofs::client::ClientResult<()> {
+/// use zerofs::client::FsClient;
+///
+/// let client = FsClient::new("http://127.0.0.1:6600")?;
+/// let written = client
+///     .write_at(&"public/notes".parse().unwrap(), "hello")
+///     .await?;
+/// println!("new root: {}", written.root);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct FsClient {
+    /// The underlying HTTP client.
+    http: reqwest::Client,
+
+    /// The base URL of the user API.
+    base_url: String,
+
+    /// The base URL of the admin API and the token to present to it.
+    admin: Option<(String, String)>,
+
+    /// How failed requests are retried.
+    retry_policy: RetryPolicy,
+
+    /// The consistency reads are asked for with, or `None` for that of the node.
+    consistency: Option<ReadConsistency>,
+}
+
+/// How an [`FsClient`] retries failed requests.
+///
+/// The delay between retries starts at `initial_backoff` and doubles after every retry, up to
+/// `max_backoff`.
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct RetryPolicy {
+    /// The number of times a failed request is retried.
+    #[builder(default = DEFAULT_MAX_RETRIES)]
+    pub max_retries: u32,
+
+    /// The delay before the first retry.
+    #[builder(default = DEFAULT_INITIAL_BACKOFF)]
+    pub initial_backoff: Duration,
+
+    /// The maximum delay between two retries.
+    #[builder(default = DEFAULT_MAX_BACKOFF)]
+    pub max_backoff: Duration,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl FsClient {
+    /// Creates a client for the user API at `base_url`.
+    pub fn new(base_url: impl Into<String>) -> ClientResult<Self> {
+        let http = reqwest::Client::builder().cookie_store(true).build()?;
+
+        Ok(Self {
+            http,
+            base_url: trim_url(base_url.into()),
+            admin: None,
+            retry_policy: RetryPolicy::default(),
+            consistency: None,
+        })
+    }
+
+    /// Sets the base URL of the admin API and the token to present to it.
+    pub fn with_admin(mut self, admin_url: impl Into<String>, token: impl Into<String>) -> Self {
+        self.admin = Some((trim_url(admin_url.into()), token.into()));
+        self
+    }
+
+    /// Sets how failed requests are retried.
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Sets the consistency the reads of every request are asked for with, to trade freshness for
+    /// latency. Nodes that cannot serve it reject the requests rather than serve older data.
+    pub fn with_consistency(mut self, consistency: ReadConsistency) -> Self {
+        self.consistency = Some(consistency);
+        self
+    }
+
+    /// Returns how failed requests are retried.
+    pub fn get_retry_policy(&self) -> &RetryPolicy {
+        &self.retry_policy
+    }
+
+    /// Authenticates with the user's UCAN and the map of the tokens it references, setting up a
+    /// session for subsequent requests.
+    pub async fn authenticate(&self, user_token: &str, token_map: &str) -> ClientResult<()> {
+        self.send(|| {
+            self.http
+                .get(self.user_url("authenticate"))
+                .header(AUTHN_USER_TOKEN, user_token)
+                .header(AUTHN_USER_TOKEN_MAP, token_map)
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    /// Opens the entity at `path`.
+    pub async fn open_at(
+        &self,
+        path: &Path,
+        open_flags: OpenFlags,
+        descriptor_flags: DescriptorFlags,
+    ) -> ClientResult<EntityOperation> {
+        let operation = EntityOperation {
+            identifier: None,
+            operation: EntityOperationKind::OpenAt(OpenAt::new(
+                path.clone(),
+                open_flags,
+                descriptor_flags,
+            )),
+        };
+
+        self.post_operation("open_at", &operation).await
+    }
+
+    /// Writes `content` as the content of the file at `path`, replacing anything already there.
+    ///
+    /// The write is retried on failure and carries an idempotency key, so it is applied at most
+    /// once.
+    pub async fn write_at(
+        &self,
+        path: &Path,
+        content: impl Into<Bytes>,
+    ) -> ClientResult<WriteAtResponse> {
+        let content = content.into();
+        let key = idempotency_key();
+
+        let response = self
+            .send(|| {
+                self.http
+                    .post(self.user_url("write_at"))
+                    .query(&[("path", path.to_string())])
+                    .header(IDEMPOTENCY_KEY_HEADER, key.as_str())
+                    .body(content.clone())
+            })
+            .await?;
+
+        Ok(response.json().await?)
+    }
+
+    /// Streams the chunks of `stream` as the content of the file at `path`, replacing anything
+    /// already there.
+    ///
+    /// The content is not buffered, so unlike [`write_at`][Self::write_at] the write is not
+    /// retried.
+    pub async fn write_at_stream<S>(&self, path: &Path, stream: S) -> ClientResult<WriteAtResponse>
+    where
+        S: TryStream + Send + 'static,
+        S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+        Bytes: From<S::Ok>,
+    {
+        let response = self
+            .http
+            .post(self.user_url("write_at"))
+            .query(&[("path", path.to_string())])
+            .header(IDEMPOTENCY_KEY_HEADER, idempotency_key())
+            .body(reqwest::Body::wrap_stream(stream))
+            .send()
+            .await?;
+
+        Ok(check_status(response).await?.json().await?)
+    }
+
+    /// Links a file at `path` whose content is the content the node already stores at
+    /// `content`, replacing anything already there, without uploading it.
+    ///
+    /// Together with [`content_cid_at`][Self::content_cid_at] this lets a client skip uploading
+    /// content the node has. Fails if the content is not stored in full.
+    pub async fn link_content_at(
+        &self,
+        path: &Path,
+        content: &Cid,
+    ) -> ClientResult<WriteAtResponse> {
+        let key = idempotency_key();
+
+        let response = self
+            .send(|| {
+                self.http
+                    .post(self.user_url("link_content_at"))
+                    .query(&[("path", path.to_string()), ("content", content.to_string())])
+                    .header(IDEMPOTENCY_KEY_HEADER, key.as_str())
+            })
+            .await?;
+
+        Ok(response.json().await?)
+    }
+
+    /// Replaces the access control list of the entity at `path`.
+    pub async fn set_acl_at(&self, path: &Path, acl: Acl) -> ClientResult<()> {
+        let request = SetAclAt::new(path.clone(), acl);
+        self.send(|| self.http.post(self.user_url("set_acl_at")).json(&request))
+            .await?;
+
+        Ok(())
+    }
+
+    /// Returns the access control list of the entity at `path`.
+    pub async fn get_acl_at(&self, path: &Path) -> ClientResult<Acl> {
+        let request = GetAclAt::new(path.clone());
+        let response = self
+            .send(|| self.http.post(self.user_url("get_acl_at")).json(&request))
+            .await?;
+
+        let response: AclResponse = response.json().await?;
+        Ok(response.acl)
+    }
+
+    /// Returns what changed in the file tree since `cursor`, up to `limit` commits at a time.
+    ///
+    /// Pass `0` to get every change, then the cursor of the returned [`ChangeSet`] to get the
+    /// changes after it.
+    pub async fn changes_since(
+        &self,
+        cursor: u64,
+        limit: Option<usize>,
+    ) -> ClientResult<ChangeSet> {
+        let mut url = format!("{}?cursor={cursor}", self.user_url("changes"));
+        if let Some(limit) = limit {
+            url.push_str(&format!("&limit={limit}"));
+        }
+
+        let response = self.send(|| self.http.get(&url)).await?;
+
+        Ok(response.json().await?)
+    }
+
+    /// Returns the paths whose names and attributes match `query`, up to `limit` of them. Fails
+    /// unless the node maintains a search index.
+    pub async fn search(&self, query: &str, limit: Option<usize>) -> ClientResult<Vec<Path>> {
+        let response = self
+            .send(|| {
+                let request = self
+                    .http
+                    .get(self.user_url("search"))
+                    .query(&[("q", query)]);
+                match limit {
+                    Some(limit) => request.query(&[("limit", limit)]),
+                    None => request,
+                }
+            })
+            .await?;
+
+        let results: SearchResponse = response.json().await?;
+        Ok(results.paths)
+    }
+
+    /// Returns the paths that match the glob `pattern`, out of those the caller can read, with the
+    /// CIDs of the entities at them.
+    pub async fn glob(&self, pattern: &str) -> ClientResult<Vec<GlobMatch>> {
+        let response = self
+            .send(|| {
+                self.http
+                    .get(self.user_url("glob"))
+                    .query(&[("pattern", pattern)])
+            })
+            .await?;
+
+        let results: GlobResponse = response.json().await?;
+        Ok(results.matches)
+    }
+
+    /// Returns the CID of the content of the file at `path`, or `None` if the file is empty.
+    ///
+    /// Content chunked the same way has the same CID wherever it is stored, so comparing it with
+    /// the CID of local content tells whether the node already has it before uploading it.
+    pub async fn content_cid_at(&self, path: &Path) -> ClientResult<Option<Cid>> {
+        let response = self
+            .send(|| {
+                self.http
+                    .get(self.user_url("content_cid_at"))
+                    .query(&[("path", path.to_string())])
+            })
+            .await?;
+
+        let result: ContentCidResponse = response.json().await?;
+        Ok(result.content)
+    }
+
+    /// Resolves `path` to its canonical form, following every symbolic link along it, and returns
+    /// it along with the CID of the entity there.
+    pub async fn realpath_at(&self, path: &Path) -> ClientResult<(Path, Cid)> {
+        let response = self
+            .send(|| {
+                self.http
+                    .get(self.user_url("realpath_at"))
+                    .query(&[("path", path.to_string())])
+            })
+            .await?;
+
+        let result: RealpathResponse = response.json().await?;
+        Ok((result.path, result.cid))
+    }
+
+    /// Streams the entries of the directory at `path` in the order of their names, starting after
+    /// the entry named `cursor` if it is given.
+    ///
+    /// Entries are decoded as the node sends them, so large directories are never buffered. If the
+    /// stream fails part way, the listing can be resumed by passing the name of the last entry
+    /// received as the cursor.
+    pub async fn read_dir(
+        &self,
+        path: &Path,
+        cursor: Option<&PathSegment>,
+    ) -> ClientResult<BoxStream<'static, ClientResult<ReadDirEntry>>> {
+        let response = self
+            .send(|| {
+                let request = self
+                    .http
+                    .get(self.user_url("read_dir"))
+                    .query(&[("path", path.to_string())]);
+                match cursor {
+                    Some(cursor) => request.query(&[("cursor", cursor.to_string())]),
+                    None => request,
+                }
+            })
+            .await?;
+
+        let entries = stream::try_unfold(
+            (response.bytes_stream(), Vec::new()),
+            |(mut body, mut buffer)| async move {
+                loop {
+                    if let Some(end) = buffer.iter().position(|byte| *byte == b'\n') {
+                        let line = buffer.drain(..=end).collect::<Vec<_>>();
+                        let entry = serde_json::from_slice(&line)?;
+                        return Ok(Some((entry, (body, buffer))));
+                    }
+
+                    match body.next().await {
+                        Some(chunk) => buffer.extend_from_slice(&chunk?),
+                        None if buffer.is_empty() => return Ok(None),
+                        None => buffer.push(b'\n'),
+                    }
+                }
+            },
+        );
+
+        Ok(entries.boxed())
+    }
+
+    /// Returns the CID and metadata of the entity at each of `paths`, in the same order, or `None`
+    /// for the paths there is nothing readable at.
+    pub async fn stat_many(&self, paths: &[Path]) -> ClientResult<Vec<Option<EntityStat>>> {
+        let request = PathsRequest {
+            paths: paths.to_vec(),
+        };
+
+        let response = self
+            .send(|| self.http.post(self.user_url("stat_many")).json(&request))
+            .await?;
+
+        let results: StatManyResponse = response.json().await?;
+        Ok(results.stats)
+    }
+
+    /// Returns whether there is a readable entity at each of `paths`, in the same order.
+    pub async fn exists_many(&self, paths: &[Path]) -> ClientResult<Vec<bool>> {
+        let request = PathsRequest {
+            paths: paths.to_vec(),
+        };
+
+        let response = self
+            .send(|| self.http.post(self.user_url("exists_many")).json(&request))
+            .await?;
+
+        let results: ExistsManyResponse = response.json().await?;
+        Ok(results.exists)
+    }
+
+    /// Returns the status of the node. Requires the admin API.
+    pub async fn get_status(&self) -> ClientResult<NodeStatus> {
+        self.admin_json(|http, url| http.get(url), "status").await
+    }
+
+    /// Returns the Raft state of the node. Requires the admin API.
+    pub async fn get_raft_state(&self) -> ClientResult<RaftState> {
+        self.admin_json(|http, url| http.get(url), "raft").await
+    }
+
+    /// Makes the file tree of the node read-only, or writable again. Requires the admin API.
+    pub async fn set_read_only(&self, read_only: bool) -> ClientResult<()> {
+        let _: ReadOnlyMode = self
+            .admin_json(
+                |http, url| http.put(url).json(&ReadOnlyMode { read_only }),
+                "read-only",
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// Returns the block transfers of the node with its peers. Requires the admin API.
+    pub async fn get_transfer_stats(&self) -> ClientResult<TransferStats> {
+        self.admin_json(|http, url| http.get(url), "transfers")
+            .await
+    }
+
+    /// Returns the background jobs of the node waiting to run, running or failed, oldest first.
+    /// Requires the admin API.
+    pub async fn get_jobs(&self) -> ClientResult<Vec<Job>> {
+        self.admin_json(|http, url| http.get(url), "jobs").await
+    }
+
+    /// Returns the snapshots of the file tree of the node. Requires the admin API.
+    pub async fn get_snapshots(&self) -> ClientResult<SnapshotIndex> {
+        self.admin_json(|http, url| http.get(url), "snapshots")
+            .await
+    }
+
+    /// Takes a snapshot of the file tree of the node and returns its ID. Requires the admin API.
+    pub async fn create_snapshot(&self) -> ClientResult<String> {
+        let created: SnapshotCreated = self
+            .admin_json(|http, url| http.post(url), "snapshots")
+            .await?;
+
+        Ok(created.id)
+    }
+
+    /// Drops the snapshot with the given ID. Requires the admin API.
+    pub async fn delete_snapshot(&self, id: &str) -> ClientResult<()> {
+        let (admin_url, token) = self.admin.as_ref().ok_or(ClientError::AdminNotConfigured)?;
+        self.send(|| {
+            self.http
+                .delete(format!("{admin_url}/snapshots/{id}"))
+                .bearer_auth(token)
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    /// Returns the named refs of the file tree of the node. Requires the admin API.
+    pub async fn get_refs(&self) -> ClientResult<RefIndex> {
+        self.admin_json(|http, url| http.get(url), "refs").await
+    }
+
+    /// Returns the named ref `name`. Requires the admin API.
+    pub async fn get_ref(&self, name: &str) -> ClientResult<Ref> {
+        self.admin_json(|http, url| http.get(url), &format!("refs/{name}"))
+            .await
+    }
+
+    /// Points the named ref `name` at `root` if the ref meets `precondition`, creating it if
+    /// needed. Requires the admin API.
+    pub async fn update_ref(
+        &self,
+        name: &str,
+        root: Cid,
+        precondition: RefPrecondition,
+    ) -> ClientResult<Ref> {
+        let update = RefUpdate { root, precondition };
+        self.admin_json(
+            |http, url| http.put(url).json(&update),
+            &format!("refs/{name}"),
+        )
+        .await
+    }
+
+    /// Drops the named ref `name`, only while it points at `expected` if set. Requires the admin
+    /// API.
+    pub async fn delete_ref(&self, name: &str, expected: Option<&Cid>) -> ClientResult<()> {
+        let (admin_url, token) = self.admin.as_ref().ok_or(ClientError::AdminNotConfigured)?;
+        let mut url = format!("{admin_url}/refs/{name}");
+        if let Some(expected) = expected {
+            url.push_str(&format!("?expected={expected}"));
+        }
+
+        self.send(|| self.http.delete(&url).bearer_auth(token))
+            .await?;
+
+        Ok(())
+    }
+
+    /// Posts an entity operation to the user API endpoint `endpoint`.
+    async fn post_operation(
+        &self,
+        endpoint: &str,
+        operation: &EntityOperation,
+    ) -> ClientResult<EntityOperation> {
+        let response = self
+            .send(|| self.http.post(self.user_url(endpoint)).json(operation))
+            .await?;
+
+        Ok(response.json().await?)
+    }
+
+    /// Sends a request to the admin API endpoint `endpoint` and decodes the JSON response.
+    async fn admin_json<T>(
+        &self,
+        method: impl Fn(&reqwest::Client, String) -> RequestBuilder,
+        endpoint: &str,
+    ) -> ClientResult<T>
+    where
+        T: DeserializeOwned,
+    {
+        let (admin_url, token) = self.admin.as_ref().ok_or(ClientError::AdminNotConfigured)?;
+        let response = self
+            .send(|| method(&self.http, format!("{admin_url}/{endpoint}")).bearer_auth(token))
+            .await?;
+
+        Ok(response.json().await?)
+    }
+
+    /// Sends the request built by `request`, rebuilding and resending it according to the retry
+    /// policy while it fails with a retryable error.
+    async fn send(&self, request: impl Fn() -> RequestBuilder) -> ClientResult<Response> {
+        retry(&self.retry_policy, || async {
+            let request = match self.consistency {
+                Some(consistency) => request().header(CONSISTENCY_HEADER, consistency.to_string()),
+                None => request(),
+            };
+
+            match request.send().await {
+                Ok(response) if is_retryable(&response) => {
+                    let retry_after = get_retry_after(&response);
+                    Err((check_status(response).await.err(), retry_after))
+                }
+                Ok(response) => Ok(check_status(response).await),
+                Err(e) if e.is_connect() || e.is_timeout() => Err((Some(e.into()), None)),
+                Err(e) => Ok(Err(e.into())),
+            }
+        })
+        .await
+    }
+
+    /// Returns the URL of the user API endpoint `endpoint`.
+    fn user_url(&self, endpoint: &str) -> String {
+        format!("{}/{endpoint}", self.base_url)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Runs `attempt` until it succeeds, fails with an error that is not retryable, or the retries
+/// allowed by `policy` run out.
+///
+/// An attempt that should be retried returns the error to report if no retries are left, along
+/// with how long the node asked to wait before retrying.
+async fn retry<F, Fut>(policy: &RetryPolicy, attempt: F) -> ClientResult<Response>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<ClientResult<Response>, (Option<ClientError>, Option<Duration>)>>,
+{
+    let mut backoff = policy.initial_backoff;
+    let mut retries = 0;
+
+    loop {
+        let (error, retry_after) = match attempt().await {
+            Ok(result) => return result,
+            Err(retryable) => retryable,
+        };
+
+        if retries >= policy.max_retries {
+            return Err(error.unwrap_or(ClientError::StatusError {
+                status: StatusCode::SERVICE_UNAVAILABLE.as_u16(),
+                body: String::new(),
+            }));
+        }
+
+        tokio::time::sleep(retry_after.unwrap_or(backoff)).await;
+        backoff = (backoff * 2).min(policy.max_backoff);
+        retries += 1;
+    }
+}
+
+/// Turns a response with an error status into a [`ClientError::Problem`] if the node described
+/// the error, or into a [`ClientError::StatusError`] otherwise.
+async fn check_status(response: Response) -> ClientResult<Response> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    let is_problem = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with(PROBLEM_CONTENT_TYPE));
+    if is_problem {
+        return Err(ClientError::Problem(response.json::<Problem>().await?));
+    }
+
+    Err(ClientError::StatusError {
+        status: status.as_u16(),
+        body: response.text().await.unwrap_or_default(),
+    })
+}
+
+/// Returns `true` if the response is worth retrying.
+///
+/// The node says whether an error is worth retrying with the [`RETRYABLE_HEADER`]. Responses
+/// without it, like those of a proxy in front of the node, are retried depending on their status.
+fn is_retryable(response: &Response) -> bool {
+    let status = response.status();
+    if status.is_success() {
+        return false;
+    }
+
+    if let Some(retryable) = response.headers().get(RETRYABLE_HEADER) {
+        return retryable == "true";
+    }
+
+    is_retryable_status(status)
+}
+
+/// Returns `true` if a response with the given status is worth retrying.
+pub(crate) fn is_retryable_status(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::TOO_MANY_REQUESTS
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+/// Returns how long the `Retry-After` header of the response asks to wait, in seconds.
+fn get_retry_after(response: &Response) -> Option<Duration> {
+    response
+        .headers()
+        .get(header::RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
+        .map(Duration::from_secs)
+}
+
+/// Returns a new idempotency key that is unique to this process.
+fn idempotency_key() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    format!(
+        "{:x}-{:x}-{:x}",
+        std::process::id(),
+        Utc::now().timestamp_nanos_opt().unwrap_or_default(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+/// Removes the trailing slashes from a base URL.
+fn trim_url(url: String) -> String {
+    url.trim_end_matches('/').to_owned()
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
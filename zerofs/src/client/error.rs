@@ -0,0 +1,73 @@
+use thiserror::Error;
+
+use reqwest::StatusCode;
+
+use crate::{filesystem::ErrorCode, service::Problem};
+
+use super::is_retryable_status;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The result of a client operation.
+pub type ClientResult<T> = Result<T, ClientError>;
+
+/// An error that occurred during a client operation.
+#[derive(Debug, Error)]
+pub enum ClientError {
+    /// HTTP error.
+    #[error("HTTP error: {0}")]
+    HttpError(#[from] reqwest::Error),
+
+    /// The response body could not be decoded.
+    #[error("Invalid response body: {0}")]
+    InvalidBody(#[from] serde_json::Error),
+
+    /// The node responded with a description of the error.
+    #[error("Request failed with status {} ({}): {}", .0.status, .0.code, .0.detail)]
+    Problem(Problem),
+
+    /// The node responded with an error status.
+    #[error("Request failed with status {status}: {body}")]
+    StatusError {
+        /// The status code of the response.
+        status: u16,
+
+        /// The body of the response.
+        body: String,
+    },
+
+    /// An admin operation was requested without an admin URL and token.
+    #[error("Admin API is not configured")]
+    AdminNotConfigured,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl ClientError {
+    /// Returns `true` if the request that failed may succeed if it is sent again as is.
+    ///
+    /// The client already retries these errors according to its
+    /// [`RetryPolicy`][super::RetryPolicy], so they are only returned once the retries ran out.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            ClientError::HttpError(e) => e.is_connect() || e.is_timeout(),
+            ClientError::Problem(problem) => problem.retryable,
+            ClientError::StatusError { status, .. } => StatusCode::from_u16(*status)
+                .map(is_retryable_status)
+                .unwrap_or(false),
+            ClientError::InvalidBody(_) | ClientError::AdminNotConfigured => false,
+        }
+    }
+
+    /// Returns the code of the error the node responded with, if it described the error.
+    pub fn code(&self) -> Option<ErrorCode> {
+        match self {
+            ClientError::Problem(problem) => Some(problem.code),
+            _ => None,
+        }
+    }
+}
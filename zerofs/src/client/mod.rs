@@ -0,0 +1,11 @@
+//! A typed client for the HTTP APIs of a remote `zerofs` node.
+
+mod client;
+mod error;
+
+//--------------------------------------------------------------------------------------------------
+// Exports
+//--------------------------------------------------------------------------------------------------
+
+pub use client::*;
+pub use error::*;
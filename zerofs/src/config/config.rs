@@ -1,9 +1,20 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    net::SocketAddr,
+    path::PathBuf,
+    time::Duration,
+};
+
 use serde::{Deserialize, Serialize};
+use serde_with::serde_as;
 use structstruck::strike;
 use typed_builder::TypedBuilder;
-use zeroutils_config::{network::NetworkConfig, ConfigResult, MainConfig};
+use zeroutils_config::{network::NetworkConfig, ConfigError, ConfigResult, MainConfig};
+use zeroutils_store::{ipld::cid::Cid, Codec};
+
+use crate::filesystem::{Chunker, DescriptorFlags, Path, Subscription};
 
-use super::FsPortDefaults;
+use super::{ConfigSources, FsPortDefaults};
 
 //--------------------------------------------------------------------------------------------------
 // Types
@@ -18,24 +29,1155 @@ strike! {
         #[builder(default)]
         pub network: ZerofsNetworkConfig,
 
-        // /// Interface configuration.
-        // pub interface: pub struct InterfaceConfig {
-        //     /// Base path for the zerofs.
-        //     pub base: PathBuf,
-        // }
+        /// Store configuration.
+        #[serde(default)]
+        #[builder(default)]
+        pub store: ZerofsStoreConfig,
+
+        /// Trash configuration.
+        #[serde(default)]
+        #[builder(default)]
+        pub trash: ZerofsTrashConfig,
+
+        /// Maintenance configuration.
+        #[serde(default)]
+        #[builder(default)]
+        pub maintenance: ZerofsMaintenanceConfig,
+
+        /// Admin API configuration.
+        #[serde(default)]
+        #[builder(default)]
+        pub admin: ZerofsAdminConfig,
+
+        /// Rate limit configuration.
+        #[serde(default)]
+        #[builder(default)]
+        pub rate_limit: ZerofsRateLimitConfig,
+
+        /// Upload configuration.
+        #[serde(default)]
+        #[builder(default)]
+        pub upload: ZerofsUploadConfig,
+
+        /// Idempotency configuration.
+        #[serde(default)]
+        #[builder(default)]
+        pub idempotency: ZerofsIdempotencyConfig,
+
+        /// Replication configuration.
+        #[serde(default)]
+        #[builder(default)]
+        pub replication: ZerofsReplicationConfig,
+
+        /// Peer transfer configuration.
+        #[serde(default)]
+        #[builder(default)]
+        pub transfer: ZerofsTransferConfig,
+
+        /// Search index configuration.
+        #[serde(default)]
+        #[builder(default)]
+        pub search: ZerofsSearchConfig,
+
+        /// Background job configuration.
+        #[serde(default)]
+        #[builder(default)]
+        pub jobs: ZerofsJobsConfig,
+
+        /// Tree shape limits.
+        #[serde(default)]
+        #[builder(default)]
+        pub limits: ZerofsLimitsConfig,
+
+        /// Read replica configuration.
+        #[serde(default)]
+        #[builder(default)]
+        pub replica: ZerofsReplicaConfig,
+
+        /// Startup configuration.
+        #[serde(default)]
+        #[builder(default)]
+        pub startup: ZerofsStartupConfig,
+
+        /// Operation timeout configuration.
+        #[serde(default)]
+        #[builder(default)]
+        pub timeouts: ZerofsTimeoutsConfig,
+
+        /// Consensus log configuration.
+        #[serde(default)]
+        #[builder(default)]
+        pub log: ZerofsLogConfig,
+
+        /// Cluster membership configuration.
+        #[serde(default)]
+        #[builder(default)]
+        pub cluster: ZerofsClusterConfig,
+
+        /// Peer discovery configuration.
+        #[serde(default)]
+        #[builder(default)]
+        pub discovery: ZerofsDiscoveryConfig,
+
+        /// WASI guest configuration.
+        #[serde(default)]
+        #[builder(default)]
+        pub wasi: ZerofsWasiConfig,
+
+        /// Interface configuration.
+        #[serde(default)]
+        #[builder(default)]
+        pub interface: ZerofsInterfaceConfig,
+
+        /// Whether the file tree starts out read-only. It can be switched at runtime through the
+        /// admin API.
+        #[serde(default)]
+        #[builder(default)]
+        pub read_only: bool,
+
+        /// Where each value came from, when the configuration was read with a
+        /// [`ConfigLoader`][super::ConfigLoader].
+        #[serde(skip)]
+        #[builder(default)]
+        pub sources: ConfigSources,
+
     }
 }
 
 /// Network configuration for the zerofs service.
 pub type ZerofsNetworkConfig = NetworkConfig<'static, FsPortDefaults>;
 
+/// Store configuration for the zerofs service.
+///
+/// The stores of this crate are set up from it, like a
+/// [`DiskStore`][crate::filesystem::DiskStore] with a `DiskStoreConfig` made with
+/// `DiskStoreConfig::from`, so they create CIDs with the configured hash function. Stores from
+/// elsewhere must be set up alike: the service refuses stores that cannot encode nodes with the
+/// configured codec, and roots that were not produced with these settings.
+#[derive(Debug, Clone, Deserialize, Serialize, TypedBuilder, Default)]
+pub struct ZerofsStoreConfig {
+    /// The hash function used to create the CIDs of stored blocks.
+    #[serde(default)]
+    #[builder(default)]
+    pub hash: HashFunction,
+
+    /// The codec used to encode stored nodes.
+    #[serde(default)]
+    #[builder(default)]
+    pub codec: NodeCodec,
+
+    /// How file contents are split into blocks when no other chunker is asked for.
+    #[serde(default)]
+    #[builder(default)]
+    pub chunker: Chunker,
+}
+
+/// Trash configuration for the zerofs service.
+///
+/// When the trash is enabled, removed entities are moved under
+/// [`TRASH_PATH`][crate::filesystem::TRASH_PATH] instead of being unlinked, so they can be restored
+/// until they are purged.
+#[derive(Debug, Clone, Deserialize, Serialize, TypedBuilder)]
+pub struct ZerofsTrashConfig {
+    /// Whether removed entities are moved into the trash.
+    #[serde(default)]
+    #[builder(default)]
+    pub enabled: bool,
+
+    /// How long removed entities stay in the trash before they are purged automatically, in
+    /// seconds. `None` keeps them until they are purged explicitly.
+    #[serde(default)]
+    #[builder(default, setter(strip_option))]
+    pub retention: Option<u64>,
+
+    /// The minimum time between two automatic purges, in seconds.
+    #[serde(default = "default_trash_purge_interval")]
+    #[builder(default = DEFAULT_TRASH_PURGE_INTERVAL)]
+    pub purge_interval: u64,
+}
+
+/// A maintenance task that can be scheduled with
+/// [`ZerofsMaintenanceConfig`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MaintenanceTask {
+    /// Removes blocks that are no longer reachable from the root, and drops the derived blobs whose
+    /// source is gone.
+    GarbageCollect,
+
+    /// Records a snapshot of the root.
+    Snapshot,
+
+    /// Purges entities that have been in the trash for longer than the configured retention.
+    PurgeTrash,
+
+    /// Checks that every block reachable from the root is in the store.
+    Verify,
+}
+
+/// Maintenance configuration for the zerofs service.
+///
+/// Each task listed in `intervals` runs in the background once its interval has elapsed since its
+/// last run, but only after the service has seen no operations for `quiet_period`.
+#[derive(Debug, Clone, Deserialize, Serialize, TypedBuilder)]
+pub struct ZerofsMaintenanceConfig {
+    /// The time between two runs of each scheduled task, in seconds. Tasks that are not listed do
+    /// not run in the background.
+    #[serde(default)]
+    #[builder(default)]
+    pub intervals: BTreeMap<MaintenanceTask, u64>,
+
+    /// How long the service must have been idle before scheduled tasks run, in seconds.
+    #[serde(default = "default_maintenance_quiet_period")]
+    #[builder(default = DEFAULT_MAINTENANCE_QUIET_PERIOD)]
+    pub quiet_period: u64,
+
+    /// The number of snapshots to keep. `None` keeps every snapshot.
+    #[serde(default)]
+    #[builder(default, setter(strip_option))]
+    pub keep_snapshots: Option<usize>,
+
+    /// Whether the references to each block are counted as the tree changes, so the blocks that
+    /// become unreferenced are known as soon as a change is committed. See
+    /// [`RefCountIndex`][crate::filesystem::RefCountIndex].
+    #[serde(default)]
+    #[builder(default)]
+    pub refcount_index: bool,
+}
+
+/// Admin API configuration for the zerofs service.
+///
+/// The admin API is served on its own port, on the same host as the user API, and is
+/// authenticated with a static bearer token rather than with capabilities. Without a token, every
+/// admin request is rejected.
+#[derive(Debug, Clone, Deserialize, Serialize, TypedBuilder)]
+pub struct ZerofsAdminConfig {
+    /// The port the admin API listens on.
+    #[serde(default = "default_admin_port")]
+    #[builder(default = DEFAULT_ADMIN_PORT)]
+    pub port: u16,
+
+    /// The bearer token admin requests must present. It is never serialized, so it does not leak
+    /// through config inspection.
+    #[serde(default, skip_serializing)]
+    #[builder(default, setter(strip_option, into))]
+    pub token: Option<String>,
+}
+
+/// Rate limit configuration for the user API of the zerofs service.
+///
+/// Quotas apply to each authenticated DID, or to each client IP address for requests that are not
+/// authenticated. Each quota is a budget that refills continuously at the configured rate and can
+/// hold up to one second's worth. Unset quotas are not enforced.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, TypedBuilder)]
+pub struct ZerofsRateLimitConfig {
+    /// The number of requests allowed per second. It must be above zero, and `None` allows any
+    /// number.
+    #[serde(default)]
+    #[builder(default, setter(strip_option))]
+    pub requests_per_second: Option<u32>,
+
+    /// The number of request body bytes allowed per second. It must be above zero, and `None`
+    /// allows any number.
+    #[serde(default)]
+    #[builder(default, setter(strip_option))]
+    pub bytes_per_second: Option<u64>,
+}
+
+/// Upload configuration for the user API of the zerofs service.
+///
+/// Uploads that exceed either limit are rejected with `413 Payload Too Large`.
+#[derive(Debug, Clone, Deserialize, Serialize, TypedBuilder)]
+pub struct ZerofsUploadConfig {
+    /// The maximum size of a request body, in bytes.
+    #[serde(default = "default_max_upload_size")]
+    #[builder(default = DEFAULT_MAX_UPLOAD_SIZE)]
+    pub max_upload_size: u64,
+
+    /// The maximum size of a single chunk of a streamed request body, in bytes.
+    #[serde(default = "default_max_chunk_size")]
+    #[builder(default = DEFAULT_MAX_CHUNK_SIZE)]
+    pub max_chunk_size: usize,
+}
+
+/// Idempotency configuration for the user API of the zerofs service.
+///
+/// The responses to mutating requests made with an `Idempotency-Key` header are recorded and
+/// replayed for retries with the same key until they expire.
+#[derive(Debug, Clone, Deserialize, Serialize, TypedBuilder)]
+pub struct ZerofsIdempotencyConfig {
+    /// How long recorded responses are replayed, in seconds.
+    #[serde(default = "default_idempotency_ttl")]
+    #[builder(default = DEFAULT_IDEMPOTENCY_TTL)]
+    pub ttl: u64,
+}
+
+/// Replication configuration for the zerofs service.
+///
+/// By default a node replicates the whole file tree. A node with a subscription only fetches the
+/// blocks of the subscribed subtrees, through [`migrate_store`][crate::filesystem::migrate_store].
+#[derive(Debug, Clone, Default, Deserialize, Serialize, TypedBuilder)]
+pub struct ZerofsReplicationConfig {
+    /// The subtrees this node replicates. `None` replicates the whole tree.
+    #[serde(default)]
+    #[builder(default, setter(strip_option))]
+    pub subscription: Option<Subscription>,
+}
+
+/// Peer transfer configuration for the zerofs service.
+///
+/// Block transfers with peers go through a
+/// [`TransferScheduler`][crate::filesystem::TransferScheduler] that keeps them under these caps,
+/// putting interactive reads ahead of background replication. Unset caps are not enforced.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, TypedBuilder)]
+pub struct ZerofsTransferConfig {
+    /// The number of bytes per second that can be sent to peers.
+    #[serde(default)]
+    #[builder(default, setter(strip_option))]
+    pub upload_bytes_per_second: Option<u64>,
+
+    /// The number of bytes per second that can be fetched from peers.
+    #[serde(default)]
+    #[builder(default, setter(strip_option))]
+    pub download_bytes_per_second: Option<u64>,
+}
+
+/// Search index configuration for the zerofs service.
+///
+/// When enabled, the service keeps a [`SearchIndex`][crate::filesystem::SearchIndex] of the names
+/// and attributes of the entities in the tree up to date as it changes, so clients can find files
+/// without walking the directories. With content indexing on, the text of files is indexed as well,
+/// by a background task so that commits stay fast.
+#[derive(Debug, Clone, Deserialize, Serialize, TypedBuilder)]
+pub struct ZerofsSearchConfig {
+    /// Whether the search index is maintained.
+    #[serde(default)]
+    #[builder(default)]
+    pub enabled: bool,
+
+    /// Whether the text of files is indexed along with their names.
+    #[serde(default)]
+    #[builder(default)]
+    pub content: bool,
+
+    /// The number of bytes read from the start of a file to extract its text from.
+    #[serde(default = "default_max_content_size")]
+    #[builder(default = DEFAULT_MAX_CONTENT_SIZE)]
+    pub max_content_size: u64,
+}
+
+/// Background job configuration for the zerofs service.
+///
+/// Jobs that fail are retried with an exponential backoff, starting at `base_backoff` and doubling
+/// with each attempt up to `max_backoff`, until they have failed `max_attempts` times.
+#[derive(Debug, Clone, Deserialize, Serialize, TypedBuilder)]
+pub struct ZerofsJobsConfig {
+    /// The number of jobs that run at the same time.
+    #[serde(default = "default_job_concurrency")]
+    #[builder(default = DEFAULT_JOB_CONCURRENCY)]
+    pub concurrency: usize,
+
+    /// The number of times a job is attempted before it is marked as failed.
+    #[serde(default = "default_job_max_attempts")]
+    #[builder(default = DEFAULT_JOB_MAX_ATTEMPTS)]
+    pub max_attempts: u32,
+
+    /// The time before the first retry of a failed job, in seconds.
+    #[serde(default = "default_job_base_backoff")]
+    #[builder(default = DEFAULT_JOB_BASE_BACKOFF)]
+    pub base_backoff: u64,
+
+    /// The longest time between two attempts of a job, in seconds.
+    #[serde(default = "default_job_max_backoff")]
+    #[builder(default = DEFAULT_JOB_MAX_BACKOFF)]
+    pub max_backoff: u64,
+}
+
+/// Limits on the shape of the file tree, enforced when entities are linked into it.
+///
+/// They keep a single request from growing a directory or a path past what listings and lookups
+/// can handle.
+#[derive(Debug, Clone, Deserialize, Serialize, TypedBuilder)]
+pub struct ZerofsLimitsConfig {
+    /// The largest number of entries a directory can have.
+    #[serde(default = "default_max_dir_entries")]
+    #[builder(default = DEFAULT_MAX_DIR_ENTRIES)]
+    pub max_dir_entries: usize,
+
+    /// The largest number of segments a path can have.
+    #[serde(default = "default_max_path_depth")]
+    #[builder(default = DEFAULT_MAX_PATH_DEPTH)]
+    pub max_path_depth: usize,
+
+    /// The longest name an entry can have, in bytes.
+    #[serde(default = "default_max_name_length")]
+    #[builder(default = DEFAULT_MAX_NAME_LENGTH)]
+    pub max_name_length: usize,
+}
+
+/// Read replica configuration for the zerofs service.
+///
+/// A replica does not take part in the consensus of the cluster. It follows the roots the cluster
+/// announces, serving read-only traffic from the latest one whose signatures verify against
+/// `members`, and fetches the blocks it does not have from the cluster as they are read.
+#[derive(Debug, Clone, Deserialize, Serialize, TypedBuilder)]
+pub struct ZerofsReplicaConfig {
+    /// Whether this node is a read replica.
+    #[serde(default)]
+    #[builder(default)]
+    pub enabled: bool,
+
+    /// The DIDs of the members of the cluster the replica follows.
+    #[serde(default)]
+    #[builder(default)]
+    pub members: Vec<String>,
+
+    /// The number of members that must have signed a root for the replica to follow it.
+    #[serde(default = "default_replica_quorum")]
+    #[builder(default = DEFAULT_REPLICA_QUORUM)]
+    pub quorum: usize,
+
+    /// How old the followed root can get, in seconds, before the replica stops serving requests
+    /// with `503 Service Unavailable`. `None` serves from any root, however old.
+    #[serde(default)]
+    #[builder(default, setter(strip_option))]
+    pub max_staleness: Option<u64>,
+
+    /// The peers the replica starts gossiping with, by DID. Replicas spread the latest signed
+    /// root among themselves, see [`run_gossip`][crate::service::run_gossip], so that they do not
+    /// all poll the cluster. Empty disables gossip.
+    #[serde(default)]
+    #[builder(default)]
+    pub gossip_seeds: BTreeMap<String, SocketAddr>,
+
+    /// The time between two rounds of gossip, in seconds.
+    #[serde(default = "default_gossip_interval")]
+    #[builder(default = DEFAULT_GOSSIP_INTERVAL)]
+    pub gossip_interval: u64,
+
+    /// The number of peers the replica gossips with each round.
+    #[serde(default = "default_gossip_fanout")]
+    #[builder(default = DEFAULT_GOSSIP_FANOUT)]
+    pub gossip_fanout: usize,
+}
+
+/// The hash function used to create the CIDs of stored blocks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HashFunction {
+    /// BLAKE3 with a 256-bit digest.
+    #[default]
+    Blake3,
+
+    /// SHA2 with a 256-bit digest.
+    #[serde(rename = "sha2-256")]
+    Sha2_256,
+}
+
+/// The codec used to encode stored nodes. Raw blocks are always stored with the `raw` codec.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NodeCodec {
+    /// DAG-CBOR.
+    #[default]
+    DagCbor,
+
+    /// DAG-JSON.
+    DagJson,
+}
+
+/// Startup configuration for the zerofs service.
+///
+/// These apply to a service [opened][crate::service::FsService::open] on a stored root.
+#[derive(Debug, Clone, Deserialize, Serialize, TypedBuilder)]
+pub struct ZerofsStartupConfig {
+    /// Whether the store is checked against the tree before the service starts, counting the
+    /// blocks reachable from the root and reporting the missing and orphaned ones.
+    #[serde(default)]
+    #[builder(default)]
+    pub consistency_check: bool,
+
+    /// Whether the service still starts, read-only on an empty tree, when the root cannot be read.
+    /// By default it fails to start rather than serve a broken tree.
+    #[serde(default)]
+    #[builder(default)]
+    pub serve_unreadable_root: bool,
+}
+
+/// Timeouts of the operations of the zerofs service.
+///
+/// An operation that runs past its timeout is cancelled, along with the store work it was waiting
+/// on, and fails with [`FsError::Timeout`][crate::filesystem::FsError::Timeout].
+#[derive(Debug, Clone, Deserialize, Serialize, TypedBuilder)]
+pub struct ZerofsTimeoutsConfig {
+    /// The timeout of the operations that have none of their own, in milliseconds. `None` lets
+    /// them run for as long as they take.
+    #[serde(default)]
+    #[builder(default, setter(strip_option))]
+    pub default: Option<u64>,
+
+    /// The timeouts of specific operations, in milliseconds, by the name of the operation, like
+    /// `glob` or `write_at` for the routes of the user API.
+    #[serde(default)]
+    #[builder(default)]
+    pub operations: BTreeMap<String, u64>,
+}
+
+/// Configuration of the log the consensus layer keeps on disk.
+///
+/// The log is split into segments, so the entries a snapshot covers can be dropped a segment at a
+/// time once it is taken, see [`DiskLog`][crate::service::DiskLog].
+#[derive(Debug, Clone, Deserialize, Serialize, TypedBuilder)]
+pub struct ZerofsLogConfig {
+    /// The size a segment grows to before the next entries go to a new one, in bytes.
+    #[serde(default = "default_log_segment_size")]
+    #[builder(default = DEFAULT_LOG_SEGMENT_SIZE)]
+    pub segment_size: u64,
+
+    /// Whether appended entries are flushed to disk before the append returns. Turning it off is
+    /// faster but loses the last entries if the machine crashes.
+    #[serde(default = "default_log_sync")]
+    #[builder(default = true)]
+    pub sync: bool,
+}
+
+/// Configuration of the cluster the node is a member of.
+///
+/// New nodes join a cluster with a join token minted by one of its members, rather than by adding
+/// every member to their seeds by hand, see [`FsService::mint_join_token`][crate::service::FsService::mint_join_token].
+#[derive(Debug, Clone, Deserialize, Serialize, TypedBuilder)]
+pub struct ZerofsClusterConfig {
+    /// The ID of the cluster. `None` makes the DID of the node the ID, as that of a cluster the
+    /// node founded on its own.
+    #[serde(default)]
+    #[builder(default, setter(strip_option, into))]
+    pub id: Option<String>,
+
+    /// How long the join tokens minted by the node are valid for, in seconds.
+    #[serde(default = "default_join_token_ttl")]
+    #[builder(default = DEFAULT_JOIN_TOKEN_TTL)]
+    pub join_token_ttl: u64,
+}
+
+/// Configuration of how the node finds the peers of its cluster beyond its static seeds.
+///
+/// Peers are looked up with DNS, for clusters spread over the internet, and with mDNS, for
+/// development clusters on a local network. A discovered peer is only kept as a candidate to connect
+/// to once it proves on connect that it holds the key of the DID it was advertised with, see
+/// [`run_discovery`][crate::service::run_discovery]. It does not become a member of the cluster,
+/// which takes being a seed or being admitted with a join token.
+#[derive(Debug, Clone, Deserialize, Serialize, TypedBuilder)]
+pub struct ZerofsDiscoveryConfig {
+    /// The DNS name whose SRV records point at the peers, like `_zerofs._tcp.example.com`. The
+    /// target of each record has a TXT record `did=<did>` with the DID of the peer. `None`
+    /// disables DNS discovery.
+    #[serde(default)]
+    #[builder(default, setter(strip_option, into))]
+    pub dns: Option<String>,
+
+    /// The DNS server to send the lookups to. `None` uses the first name server of the system.
+    #[serde(default)]
+    #[builder(default, setter(strip_option))]
+    pub dns_server: Option<SocketAddr>,
+
+    /// Whether peers are discovered with mDNS on the local network, and the node advertises itself
+    /// there. mDNS advertisements are not authenticated beyond the DID of the peer, so it is meant
+    /// for development clusters.
+    #[serde(default)]
+    #[builder(default)]
+    pub mdns: bool,
+
+    /// The mDNS service the peers are advertised as.
+    #[serde(default = "default_mdns_service")]
+    #[builder(default = DEFAULT_MDNS_SERVICE.to_owned(), setter(into))]
+    pub mdns_service: String,
+
+    /// The time between two rounds of discovery, in seconds.
+    #[serde(default = "default_discovery_interval")]
+    #[builder(default = DEFAULT_DISCOVERY_INTERVAL)]
+    pub interval: u64,
+
+    /// How long a lookup waits for answers, in milliseconds.
+    #[serde(default = "default_discovery_timeout")]
+    #[builder(default = DEFAULT_DISCOVERY_TIMEOUT)]
+    pub timeout: u64,
+}
+
+/// Configuration of the file system WASI guests see.
+///
+/// Guests only see the preopened directories, each at its own guest path, so embedded compute can
+/// be sandboxed onto specific subtrees. Without preopens, guests see no file system at all.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, TypedBuilder)]
+pub struct ZerofsWasiConfig {
+    /// The directories preopened for guests.
+    #[serde(default)]
+    #[builder(default)]
+    pub preopens: Vec<WasiPreopen>,
+}
+
+/// A directory preopened for WASI guests.
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, TypedBuilder)]
+pub struct WasiPreopen {
+    /// The absolute path guests see the directory at, like `/data`.
+    #[builder(setter(into))]
+    pub guest: String,
+
+    /// The path of the directory in the file tree.
+    #[serde_as(as = "serde_with::DisplayFromStr")]
+    pub path: Path,
+
+    /// The flags of the descriptor guests get for the directory, like `READ | MUTATE_DIR`.
+    #[serde(default = "default_preopen_flags")]
+    #[builder(default = DescriptorFlags::READ)]
+    pub flags: DescriptorFlags,
+
+    /// Whether the directory is created if it does not exist.
+    #[serde(default)]
+    #[builder(default)]
+    pub create: bool,
+}
+
+/// Configuration of how the node is reached and where it keeps its data.
+///
+/// This is kept apart from the [network][ZerofsConfig::network] section, which identifies the node
+/// to its peers, so a node can listen on other addresses than those it is known by, like behind a
+/// proxy.
+#[serde_as]
+#[derive(Debug, Clone, Deserialize, Serialize, TypedBuilder)]
+pub struct ZerofsInterfaceConfig {
+    /// The directory the node keeps its data in, like the blocks of a
+    /// [`DiskStore`][crate::filesystem::DiskStore]. `None` keeps nothing on disk.
+    #[serde(default)]
+    #[builder(default, setter(strip_option, into))]
+    pub base_dir: Option<PathBuf>,
+
+    /// The frontends the file system is served through.
+    #[serde(default = "default_frontends")]
+    #[builder(default = default_frontends())]
+    pub frontends: BTreeSet<Frontend>,
+
+    /// The addresses the frontends listen on, by frontend. The HTTP frontend listens on the host
+    /// and user port of the network section if it has none here, and the other network frontends
+    /// must have one.
+    #[serde(default)]
+    #[builder(default)]
+    pub bind: BTreeMap<Frontend, SocketAddr>,
+
+    /// The directory the FUSE frontend mounts the file system at. It must be set if the frontend
+    /// is enabled.
+    #[serde(default)]
+    #[builder(default, setter(strip_option, into))]
+    pub mount_point: Option<PathBuf>,
+
+    /// The paths in the file tree the gateway frontend serves. Nothing else in the store is served
+    /// through it.
+    #[serde_as(as = "Vec<serde_with::DisplayFromStr>")]
+    #[serde(default)]
+    #[builder(default)]
+    pub published: Vec<Path>,
+
+    /// The certificate and key the network frontends serve TLS with. `None` serves them in plain
+    /// text.
+    #[serde(default)]
+    #[builder(default, setter(strip_option))]
+    pub tls: Option<ZerofsTlsConfig>,
+}
+
+/// A frontend the file system is served through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Frontend {
+    /// The HTTP user API.
+    Http,
+
+    /// The gRPC user API.
+    Grpc,
+
+    /// A FUSE mount of the file system.
+    Fuse,
+
+    /// The read-only IPFS gateway, see [`FsGatewayServer`][crate::service::FsGatewayServer].
+    Gateway,
+}
+
+/// The certificate and key a frontend serves TLS with.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, TypedBuilder)]
+pub struct ZerofsTlsConfig {
+    /// The path of the PEM file with the certificate chain.
+    #[builder(setter(into))]
+    pub cert: PathBuf,
+
+    /// The path of the PEM file with the private key.
+    #[builder(setter(into))]
+    pub key: PathBuf,
+
+    /// The time between two checks of the files for a renewed certificate, in seconds. A renewed
+    /// certificate is served to new connections without dropping the open ones.
+    #[serde(default = "default_tls_reload_interval")]
+    #[builder(default = DEFAULT_TLS_RELOAD_INTERVAL)]
+    pub reload_interval: u64,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The multicodec code for raw blocks.
+pub const RAW_CODEC_CODE: u64 = 0x55;
+
+/// The default minimum time between two automatic trash purges, in seconds.
+pub const DEFAULT_TRASH_PURGE_INTERVAL: u64 = 60 * 60;
+
+/// The default time the service must have been idle before scheduled maintenance runs, in seconds.
+pub const DEFAULT_MAINTENANCE_QUIET_PERIOD: u64 = 60;
+
+/// The default port of the admin API.
+pub const DEFAULT_ADMIN_PORT: u16 = 6622;
+
+/// The default maximum size of a request body, in bytes.
+pub const DEFAULT_MAX_UPLOAD_SIZE: u64 = 1024 * 1024 * 1024;
+
+/// The default maximum size of a single chunk of a streamed request body, in bytes.
+pub const DEFAULT_MAX_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// The default time recorded idempotent responses are replayed for, in seconds.
+pub const DEFAULT_IDEMPOTENCY_TTL: u64 = 24 * 60 * 60;
+
+/// The default number of bytes read from the start of a file to extract its text from.
+pub const DEFAULT_MAX_CONTENT_SIZE: u64 = 1024 * 1024;
+
+/// The default number of background jobs that run at the same time.
+pub const DEFAULT_JOB_CONCURRENCY: usize = 4;
+
+/// The default number of times a background job is attempted before it is marked as failed.
+pub const DEFAULT_JOB_MAX_ATTEMPTS: u32 = 5;
+
+/// The default time before the first retry of a failed background job, in seconds.
+pub const DEFAULT_JOB_BASE_BACKOFF: u64 = 10;
+
+/// The default longest time between two attempts of a background job, in seconds.
+pub const DEFAULT_JOB_MAX_BACKOFF: u64 = 60 * 60;
+
+/// The default largest number of entries a directory can have.
+pub const DEFAULT_MAX_DIR_ENTRIES: usize = 65_536;
+
+/// The default largest number of segments a path can have.
+pub const DEFAULT_MAX_PATH_DEPTH: usize = 128;
+
+/// The default longest name an entry can have, in bytes.
+pub const DEFAULT_MAX_NAME_LENGTH: usize = 255;
+
+/// The default number of members that must have signed a root for a replica to follow it.
+pub const DEFAULT_REPLICA_QUORUM: usize = 1;
+
+/// The default time between two rounds of gossip between read replicas, in seconds.
+pub const DEFAULT_GOSSIP_INTERVAL: u64 = 5;
+
+/// The default number of peers a read replica gossips with each round.
+pub const DEFAULT_GOSSIP_FANOUT: usize = 3;
+
+/// The default size of a segment of the consensus log, in bytes.
+pub const DEFAULT_LOG_SEGMENT_SIZE: u64 = 64 * 1024 * 1024;
+
+/// The default time a join token is valid for, in seconds.
+pub const DEFAULT_JOIN_TOKEN_TTL: u64 = 60 * 60;
+
+/// The default mDNS service peers are advertised as.
+pub const DEFAULT_MDNS_SERVICE: &str = "_zerofs._tcp.local";
+
+/// The default time between two rounds of peer discovery, in seconds.
+pub const DEFAULT_DISCOVERY_INTERVAL: u64 = 60;
+
+/// The default time a discovery lookup waits for answers, in milliseconds.
+pub const DEFAULT_DISCOVERY_TIMEOUT: u64 = 2000;
+
+/// The default time between two checks of the TLS files for a renewed certificate, in seconds.
+pub const DEFAULT_TLS_RELOAD_INTERVAL: u64 = 60;
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl ZerofsConfig {
+    /// Returns the address the admin API listens on.
+    pub fn get_admin_address(&self) -> SocketAddr {
+        SocketAddr::new(self.network.host, self.admin.port)
+    }
+
+    /// Returns the address `frontend` listens on, or `None` if it does not listen on one.
+    ///
+    /// The HTTP frontend falls back to the user address of the network section.
+    pub fn get_frontend_address(&self, frontend: Frontend) -> Option<SocketAddr> {
+        match (self.interface.bind.get(&frontend), frontend) {
+            (Some(address), _) => Some(*address),
+            (None, Frontend::Http) => Some(self.network.get_user_address()),
+            (None, _) => None,
+        }
+    }
+}
+
+impl ZerofsInterfaceConfig {
+    /// Returns `true` if `frontend` is enabled.
+    pub fn is_enabled(&self, frontend: Frontend) -> bool {
+        self.frontends.contains(&frontend)
+    }
+
+    /// Returns the problems with the configuration, which is valid if there are none.
+    pub fn check(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        if let Some(base_dir) = &self.base_dir {
+            if base_dir.as_os_str().is_empty() {
+                problems.push("the interface base directory must not be empty".to_owned());
+            } else if base_dir.exists() && !base_dir.is_dir() {
+                problems.push(format!(
+                    "the interface base directory {} is not a directory",
+                    base_dir.display()
+                ));
+            }
+        }
+
+        if self.is_enabled(Frontend::Fuse) && self.mount_point.is_none() {
+            problems.push("the fuse frontend needs a mount point".to_owned());
+        }
+
+        for frontend in [Frontend::Grpc, Frontend::Gateway].iter() {
+            if self.is_enabled(*frontend) && !self.bind.contains_key(frontend) {
+                problems.push(format!(
+                    "the {} frontend needs a bind address",
+                    frontend.as_str()
+                ));
+            }
+        }
+
+        let bound = self.bind.iter().collect::<Vec<_>>();
+        for (i, (frontend, address)) in bound.iter().enumerate() {
+            if **frontend == Frontend::Fuse {
+                problems.push("the fuse frontend does not listen on an address".to_owned());
+            } else if !self.is_enabled(**frontend) {
+                problems.push(format!(
+                    "the {} frontend has a bind address but is not enabled",
+                    frontend.as_str()
+                ));
+            }
+
+            // Port 0 lets the system pick a free port for each listener.
+            if let Some((other, _)) = bound[..i]
+                .iter()
+                .find(|(_, other)| address.port() != 0 && other == address)
+            {
+                problems.push(format!(
+                    "the {} and {} frontends both bind {}",
+                    other.as_str(),
+                    frontend.as_str(),
+                    address
+                ));
+            }
+        }
+
+        if let Some(tls) = &self.tls {
+            for (name, path) in [("certificate", &tls.cert), ("key", &tls.key)].iter() {
+                if !path.is_file() {
+                    problems.push(format!("the TLS {} {} is not a file", name, path.display()));
+                }
+            }
+
+            if tls.reload_interval == 0 {
+                problems.push("the TLS reload interval must not be 0".to_owned());
+            }
+        }
+
+        problems
+    }
+}
+
+impl Frontend {
+    /// Returns the name of the frontend, as it is written in the configuration.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Frontend::Http => "http",
+            Frontend::Grpc => "grpc",
+            Frontend::Fuse => "fuse",
+            Frontend::Gateway => "gateway",
+        }
+    }
+}
+
+impl ZerofsStoreConfig {
+    /// Returns `true` if the CID could have been produced by a store with this configuration.
+    ///
+    /// The CID must use the configured hash function and either the configured node codec or the
+    /// `raw` codec.
+    pub fn is_consistent_with(&self, cid: &Cid) -> bool {
+        let codec = cid.codec();
+        cid.hash().code() == self.hash.code()
+            && (codec == self.codec.code() || codec == RAW_CODEC_CODE)
+    }
+}
+
+impl ZerofsTimeoutsConfig {
+    /// Returns the timeout of the operation named `operation`, if it has one.
+    pub fn get_timeout(&self, operation: &str) -> Option<Duration> {
+        self.operations
+            .get(operation)
+            .or(self.default.as_ref())
+            .map(|millis| Duration::from_millis(*millis))
+    }
+}
+
+impl HashFunction {
+    /// Returns the multihash code of the hash function.
+    pub fn code(&self) -> u64 {
+        match self {
+            HashFunction::Blake3 => 0x1e,
+            HashFunction::Sha2_256 => 0x12,
+        }
+    }
+}
+
+impl NodeCodec {
+    /// Returns the multicodec code of the codec.
+    pub fn code(&self) -> u64 {
+        match self {
+            NodeCodec::DagCbor => 0x71,
+            NodeCodec::DagJson => 0x0129,
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+fn default_trash_purge_interval() -> u64 {
+    DEFAULT_TRASH_PURGE_INTERVAL
+}
+
+fn default_maintenance_quiet_period() -> u64 {
+    DEFAULT_MAINTENANCE_QUIET_PERIOD
+}
+
+fn default_admin_port() -> u16 {
+    DEFAULT_ADMIN_PORT
+}
+
+fn default_max_upload_size() -> u64 {
+    DEFAULT_MAX_UPLOAD_SIZE
+}
+
+fn default_max_chunk_size() -> usize {
+    DEFAULT_MAX_CHUNK_SIZE
+}
+
+fn default_idempotency_ttl() -> u64 {
+    DEFAULT_IDEMPOTENCY_TTL
+}
+
+fn default_max_content_size() -> u64 {
+    DEFAULT_MAX_CONTENT_SIZE
+}
+
+fn default_job_concurrency() -> usize {
+    DEFAULT_JOB_CONCURRENCY
+}
+
+fn default_job_max_attempts() -> u32 {
+    DEFAULT_JOB_MAX_ATTEMPTS
+}
+
+fn default_job_base_backoff() -> u64 {
+    DEFAULT_JOB_BASE_BACKOFF
+}
+
+fn default_job_max_backoff() -> u64 {
+    DEFAULT_JOB_MAX_BACKOFF
+}
+
+fn default_max_dir_entries() -> usize {
+    DEFAULT_MAX_DIR_ENTRIES
+}
+
+fn default_max_path_depth() -> usize {
+    DEFAULT_MAX_PATH_DEPTH
+}
+
+fn default_max_name_length() -> usize {
+    DEFAULT_MAX_NAME_LENGTH
+}
+
+fn default_replica_quorum() -> usize {
+    DEFAULT_REPLICA_QUORUM
+}
+
+fn default_gossip_interval() -> u64 {
+    DEFAULT_GOSSIP_INTERVAL
+}
+
+fn default_gossip_fanout() -> usize {
+    DEFAULT_GOSSIP_FANOUT
+}
+
+fn default_log_segment_size() -> u64 {
+    DEFAULT_LOG_SEGMENT_SIZE
+}
+
+fn default_log_sync() -> bool {
+    true
+}
+
+fn default_join_token_ttl() -> u64 {
+    DEFAULT_JOIN_TOKEN_TTL
+}
+
+fn default_mdns_service() -> String {
+    DEFAULT_MDNS_SERVICE.to_owned()
+}
+
+fn default_preopen_flags() -> DescriptorFlags {
+    DescriptorFlags::READ
+}
+
+fn default_discovery_interval() -> u64 {
+    DEFAULT_DISCOVERY_INTERVAL
+}
+
+fn default_discovery_timeout() -> u64 {
+    DEFAULT_DISCOVERY_TIMEOUT
+}
+
+fn default_tls_reload_interval() -> u64 {
+    DEFAULT_TLS_RELOAD_INTERVAL
+}
+
+fn default_frontends() -> BTreeSet<Frontend> {
+    [Frontend::Http].iter().copied().collect()
+}
+
 //--------------------------------------------------------------------------------------------------
 // Trait Implementations
 //--------------------------------------------------------------------------------------------------
 
 impl MainConfig for ZerofsConfig {
     fn validate(&self) -> ConfigResult<()> {
-        self.network.validate()
+        self.network.validate()?;
+
+        let problems = self.interface.check();
+        if !problems.is_empty() {
+            return Err(ConfigError::custom(anyhow::anyhow!(
+                "invalid interface configuration: {}",
+                problems.join("; ")
+            )));
+        }
+
+        if self.rate_limit.requests_per_second == Some(0)
+            || self.rate_limit.bytes_per_second == Some(0)
+        {
+            return Err(ConfigError::custom(anyhow::anyhow!(
+                "invalid rate limit configuration: quotas must be above zero, or unset for no limit"
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for ZerofsInterfaceConfig {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+impl Default for ZerofsTrashConfig {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+impl Default for ZerofsMaintenanceConfig {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+impl Default for ZerofsAdminConfig {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+impl Default for ZerofsUploadConfig {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+impl Default for ZerofsIdempotencyConfig {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+impl Default for ZerofsSearchConfig {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+impl Default for ZerofsJobsConfig {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+impl Default for ZerofsLimitsConfig {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+impl Default for ZerofsReplicaConfig {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+impl Default for ZerofsStartupConfig {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+impl Default for ZerofsTimeoutsConfig {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+impl Default for ZerofsLogConfig {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+impl Default for ZerofsClusterConfig {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+impl Default for ZerofsDiscoveryConfig {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+impl From<NodeCodec> for Codec {
+    fn from(codec: NodeCodec) -> Self {
+        match codec {
+            NodeCodec::DagCbor => Codec::DagCbor,
+            NodeCodec::DagJson => Codec::DagJson,
+        }
     }
 }
 
@@ -54,11 +1196,15 @@ mod tests {
     use zeroutils_config::default::{DEFAULT_ELECTION_TIMEOUT_RANGE, DEFAULT_HEARTBEAT_INTERVAL};
     use zeroutils_did_wk::WrappedDidWebKey;
 
+    use crate::filesystem::FastCdc;
+
     use super::*;
 
     #[test]
     fn test_toml_full() -> anyhow::Result<()> {
         let toml = r#"
+        read_only = true
+
         [network]
         id = "did:wk:z6MkoVs2h6TnfyY8fx2ZqpREWSLS8rBDQmGpyXgFpg63CSUb"
         name = "alice"
@@ -73,6 +1219,83 @@ mod tests {
         [network.consensus]
         heartbeat_interval = 1000
         election_timeout_range = [150, 300]
+
+        [store]
+        hash = "sha2-256"
+        codec = "dag-json"
+
+        [store.chunker]
+        type = "fast-cdc"
+        avg_size = 32768
+
+        [trash]
+        enabled = true
+        retention = 604800
+
+        [maintenance]
+        quiet_period = 300
+        keep_snapshots = 24
+        refcount_index = true
+
+        [maintenance.intervals]
+        snapshot = 3600
+        purge-trash = 86400
+
+        [admin]
+        port = 6700
+        token = "secret"
+
+        [rate_limit]
+        requests_per_second = 50
+        bytes_per_second = 1048576
+
+        [upload]
+        max_upload_size = 10485760
+
+        [idempotency]
+        ttl = 3600
+
+        [replication]
+        subscription = ["public/photos", "/shared"]
+
+        [transfer]
+        upload_bytes_per_second = 524288
+
+        [search]
+        enabled = true
+        content = true
+
+        [jobs]
+        concurrency = 2
+        max_attempts = 3
+
+        [limits]
+        max_dir_entries = 1000
+        max_name_length = 64
+
+        [replica]
+        enabled = true
+        members = ["did:wk:z6MknLif7jhwt6jUfn14EuDnxWoSHkkajyDi28QMMH5eS1DL"]
+        max_staleness = 30
+
+        [startup]
+        consistency_check = true
+
+        [timeouts]
+        default = 30000
+
+        [timeouts.operations]
+        glob = 5000
+
+        [[wasi.preopens]]
+        guest = "/data"
+        path = "public/data"
+        flags = "READ | MUTATE_DIR"
+        create = true
+
+        [[wasi.preopens]]
+        guest = "/config"
+        path = "system/config"
         "#;
 
         let config: ZerofsConfig = toml::from_str(toml)?;
@@ -102,6 +1325,86 @@ mod tests {
         });
         assert_eq!(config.network.consensus.heartbeat_interval, 1000);
         assert_eq!(config.network.consensus.election_timeout_range, (150, 300));
+        assert_eq!(config.store.hash, HashFunction::Sha2_256);
+        assert_eq!(config.store.codec, NodeCodec::DagJson);
+        assert_eq!(
+            config.store.chunker,
+            Chunker::FastCdc(FastCdc::builder().avg_size(32768).build()?)
+        );
+        assert!(config.trash.enabled);
+        assert_eq!(config.trash.retention, Some(604800));
+        assert_eq!(config.trash.purge_interval, DEFAULT_TRASH_PURGE_INTERVAL);
+        assert_eq!(
+            config.maintenance.intervals,
+            BTreeMap::from([
+                (MaintenanceTask::Snapshot, 3600),
+                (MaintenanceTask::PurgeTrash, 86400),
+            ])
+        );
+        assert_eq!(config.maintenance.quiet_period, 300);
+        assert_eq!(config.maintenance.keep_snapshots, Some(24));
+        assert!(config.maintenance.refcount_index);
+        assert_eq!(config.admin.port, 6700);
+        assert_eq!(config.admin.token.as_deref(), Some("secret"));
+        assert!(!toml::to_string(&config.admin)?.contains("secret"));
+        assert_eq!(config.rate_limit.requests_per_second, Some(50));
+        assert_eq!(config.rate_limit.bytes_per_second, Some(1048576));
+        assert_eq!(config.upload.max_upload_size, 10485760);
+        assert_eq!(config.upload.max_chunk_size, DEFAULT_MAX_CHUNK_SIZE);
+        assert_eq!(config.idempotency.ttl, 3600);
+        assert_eq!(
+            config.replication.subscription,
+            Some(Subscription::new([
+                "public/photos".parse()?,
+                "shared".parse()?
+            ])?)
+        );
+        assert!(toml::to_string(&config.replication)?.contains(r#""/public/photos""#));
+        assert_eq!(config.transfer.upload_bytes_per_second, Some(524288));
+        assert_eq!(config.transfer.download_bytes_per_second, None);
+        assert!(config.search.enabled);
+        assert!(config.search.content);
+        assert_eq!(config.jobs.concurrency, 2);
+        assert_eq!(config.jobs.max_attempts, 3);
+        assert_eq!(config.jobs.base_backoff, DEFAULT_JOB_BASE_BACKOFF);
+        assert_eq!(config.limits.max_dir_entries, 1000);
+        assert_eq!(config.limits.max_path_depth, DEFAULT_MAX_PATH_DEPTH);
+        assert_eq!(config.limits.max_name_length, 64);
+        assert!(config.replica.enabled);
+        assert_eq!(
+            config.replica.members,
+            ["did:wk:z6MknLif7jhwt6jUfn14EuDnxWoSHkkajyDi28QMMH5eS1DL"]
+        );
+        assert_eq!(config.replica.quorum, DEFAULT_REPLICA_QUORUM);
+        assert_eq!(config.replica.max_staleness, Some(30));
+        assert!(config.replica.gossip_seeds.is_empty());
+        assert_eq!(config.replica.gossip_interval, DEFAULT_GOSSIP_INTERVAL);
+        assert!(config.startup.consistency_check);
+        assert!(!config.startup.serve_unreadable_root);
+        assert_eq!(
+            config.timeouts.get_timeout("glob"),
+            Some(Duration::from_secs(5))
+        );
+        assert_eq!(
+            config.timeouts.get_timeout("write_at"),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            config.wasi.preopens,
+            [
+                WasiPreopen::builder()
+                    .guest("/data")
+                    .path("public/data".parse()?)
+                    .flags(DescriptorFlags::READ | DescriptorFlags::MUTATE_DIR)
+                    .create(true)
+                    .build(),
+                WasiPreopen::builder()
+                    .guest("/config")
+                    .path("system/config".parse()?)
+                    .build(),
+            ]
+        );
+        assert!(config.read_only);
 
         Ok(())
     }
@@ -122,6 +1425,147 @@ mod tests {
             config.network.consensus.election_timeout_range,
             DEFAULT_ELECTION_TIMEOUT_RANGE
         );
+        assert_eq!(config.store.hash, HashFunction::Blake3);
+        assert_eq!(config.store.codec, NodeCodec::DagCbor);
+        assert_eq!(config.store.chunker, Chunker::Store);
+        assert!(!config.trash.enabled);
+        assert_eq!(config.trash.retention, None);
+        assert!(config.maintenance.intervals.is_empty());
+        assert_eq!(
+            config.maintenance.quiet_period,
+            DEFAULT_MAINTENANCE_QUIET_PERIOD
+        );
+        assert_eq!(config.maintenance.keep_snapshots, None);
+        assert!(!config.maintenance.refcount_index);
+        assert_eq!(config.admin.port, DEFAULT_ADMIN_PORT);
+        assert_eq!(config.admin.token, None);
+        assert_eq!(config.rate_limit.requests_per_second, None);
+        assert_eq!(config.rate_limit.bytes_per_second, None);
+        assert_eq!(config.upload.max_upload_size, DEFAULT_MAX_UPLOAD_SIZE);
+        assert_eq!(config.upload.max_chunk_size, DEFAULT_MAX_CHUNK_SIZE);
+        assert_eq!(config.idempotency.ttl, DEFAULT_IDEMPOTENCY_TTL);
+        assert_eq!(config.replication.subscription, None);
+        assert_eq!(config.transfer.upload_bytes_per_second, None);
+        assert_eq!(config.transfer.download_bytes_per_second, None);
+        assert!(!config.search.enabled);
+        assert!(!config.search.content);
+        assert_eq!(config.search.max_content_size, DEFAULT_MAX_CONTENT_SIZE);
+        assert_eq!(config.jobs.concurrency, DEFAULT_JOB_CONCURRENCY);
+        assert_eq!(config.jobs.max_attempts, DEFAULT_JOB_MAX_ATTEMPTS);
+        assert_eq!(config.limits.max_dir_entries, DEFAULT_MAX_DIR_ENTRIES);
+        assert_eq!(config.limits.max_path_depth, DEFAULT_MAX_PATH_DEPTH);
+        assert_eq!(config.limits.max_name_length, DEFAULT_MAX_NAME_LENGTH);
+        assert!(!config.replica.enabled);
+        assert_eq!(config.replica.max_staleness, None);
+        assert!(!config.startup.consistency_check);
+        assert!(!config.startup.serve_unreadable_root);
+        assert_eq!(config.timeouts.get_timeout("write_at"), None);
+        assert_eq!(config.log.segment_size, DEFAULT_LOG_SEGMENT_SIZE);
+        assert!(config.log.sync);
+        assert_eq!(config.cluster.id, None);
+        assert_eq!(config.cluster.join_token_ttl, DEFAULT_JOIN_TOKEN_TTL);
+        assert_eq!(config.discovery.dns, None);
+        assert!(!config.discovery.mdns);
+        assert_eq!(config.discovery.mdns_service, DEFAULT_MDNS_SERVICE);
+        assert_eq!(config.interface.base_dir, None);
+        assert!(config.interface.is_enabled(Frontend::Http));
+        assert_eq!(config.interface.frontends.len(), 1);
+        assert!(!config.read_only);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_interface_config_validation() -> anyhow::Result<()> {
+        let toml = r#"
+        [interface]
+        base_dir = "/var/lib/zerofs"
+        frontends = ["http", "gateway", "fuse"]
+        mount_point = "/mnt/zerofs"
+        published = ["public", "docs/site"]
+
+        [interface.bind]
+        http = "0.0.0.0:8080"
+        gateway = "0.0.0.0:8081"
+        "#;
+
+        let config: ZerofsConfig = toml::from_str(toml)?;
+        assert!(config.interface.check().is_empty());
+        assert!(config.validate().is_ok());
+        assert_eq!(
+            config.get_frontend_address(Frontend::Gateway),
+            Some(SocketAddr::from_str("0.0.0.0:8081")?)
+        );
+        assert_eq!(config.get_frontend_address(Frontend::Fuse), None);
+        assert_eq!(
+            config.interface.published,
+            vec!["public".parse::<Path>()?, "docs/site".parse()?]
+        );
+
+        // Each problem is reported, not just the first one.
+        let toml = r#"
+        [interface]
+        frontends = ["http", "grpc", "fuse"]
+
+        [interface.bind]
+        http = "127.0.0.1:8080"
+        gateway = "127.0.0.1:8080"
+
+        [interface.tls]
+        cert = "/nonexistent/cert.pem"
+        key = "/nonexistent/key.pem"
+        "#;
+
+        let config: ZerofsConfig = toml::from_str(toml)?;
+        let problems = config.interface.check();
+        assert_eq!(problems.len(), 6, "{:?}", problems);
+        assert!(config.validate().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rate_limit_config_validation() -> anyhow::Result<()> {
+        let config: ZerofsConfig = toml::from_str("[rate_limit]\nrequests_per_second = 10\n")?;
+        assert!(config.validate().is_ok());
+
+        // A quota of zero would never refill, so it is refused rather than blocking every request.
+        let config: ZerofsConfig = toml::from_str("[rate_limit]\nrequests_per_second = 0\n")?;
+        assert!(config.validate().is_err());
+
+        let config: ZerofsConfig = toml::from_str("[rate_limit]\nbytes_per_second = 0\n")?;
+        assert!(config.validate().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_store_config_consistency() -> anyhow::Result<()> {
+        let config = ZerofsStoreConfig::default();
+
+        // dag-cbor + blake3
+        let node_cid: Cid =
+            "bafyr4icul2stqrqqapx5zdebyjcfggyah5xsnt6m63aaoozshh6635euiy".parse()?;
+        // raw + blake3
+        let raw_cid: Cid = "bafkr4igxion64jdxhpf7uliks6kh5y3ce6yq2ebcwgsvqr7jfclfxnv73y".parse()?;
+        // raw + sha2-256
+        let sha_cid: Cid = "bafkreidgvpkjawlxz6sffxzwgooowe5yt7i6wsyg236mfoks77nywkptdq".parse()?;
+        // dag-json + sha2-256
+        let json_cid: Cid =
+            "baguqeeraak6roxzss4qdpdhihxkwug3ld5jjdjqbqllmks26bupi2jekez5a".parse()?;
+
+        assert!(config.is_consistent_with(&node_cid));
+        assert!(config.is_consistent_with(&raw_cid));
+        assert!(!config.is_consistent_with(&sha_cid));
+
+        let config = ZerofsStoreConfig::builder()
+            .hash(HashFunction::Sha2_256)
+            .codec(NodeCodec::DagJson)
+            .build();
+
+        assert!(config.is_consistent_with(&sha_cid));
+        assert!(config.is_consistent_with(&json_cid));
+        assert!(!config.is_consistent_with(&node_cid));
 
         Ok(())
     }
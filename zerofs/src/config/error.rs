@@ -0,0 +1,34 @@
+use std::path::PathBuf;
+
+use thiserror::Error;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The result of loading a configuration.
+pub type ConfigLoadResult<T> = Result<T, ConfigLoadError>;
+
+/// An error that occurred while loading a configuration.
+#[derive(Debug, Error)]
+pub enum ConfigLoadError {
+    /// The configuration file could not be read.
+    #[error("Cannot read config file {}: {1}", .0.display())]
+    ReadFile(PathBuf, #[source] std::io::Error),
+
+    /// The configuration file is not valid TOML.
+    #[error("Cannot parse config file {}: {1}", .0.display())]
+    ParseFile(PathBuf, #[source] Box<toml::de::Error>),
+
+    /// An override is not of the form `path=value`.
+    #[error("Invalid override: {0:?}")]
+    InvalidOverride(String),
+
+    /// A value of the merged configuration does not fit the field it is set to.
+    #[error("Invalid config: {0}")]
+    InvalidConfig(#[source] Box<toml::de::Error>),
+
+    /// The configuration could not be serialized to show its values.
+    #[error("Cannot serialize config: {0}")]
+    Serialize(#[from] toml::ser::Error),
+}
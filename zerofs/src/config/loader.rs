@@ -0,0 +1,422 @@
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display, Write},
+    path::PathBuf,
+};
+
+use toml::{Table, Value};
+
+use super::{ConfigLoadError, ConfigLoadResult, ZerofsConfig};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The prefix of the environment variables read by [`ConfigLoader::env`].
+pub const CONFIG_ENV_PREFIX: &str = "ZEROFS_";
+
+/// The environment variable naming the configuration file, which [`ConfigLoader::env`] reads the
+/// file from when none is set.
+pub const CONFIG_PATH_ENV_VAR: &str = "ZEROFS_CONFIG";
+
+/// What separates the sections of a configuration path in the name of an environment variable.
+const ENV_SECTION_SEPARATOR: &str = "__";
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Loads a [`ZerofsConfig`] from layered sources.
+///
+/// Values are taken, from lowest to highest precedence, from the defaults, a TOML file,
+/// environment variables, and overrides set in code or on the command line. Each source only
+/// replaces the values it sets, so a file can set a whole section and an environment variable a
+/// single value in it.
+///
+/// Environment variables are named after the path of the value they set, prefixed with
+/// [`CONFIG_ENV_PREFIX`] and with sections separated by a double underscore, so that
+/// `ZEROFS_TRASH__RETENTION` sets `trash.retention`. Their values are read as TOML values, or as
+/// strings if they are not valid TOML or the value they set is a string, so that
+/// `ZEROFS_ADMIN__TOKEN=123456` sets the token to `"123456"`.
+#[derive(Debug, Default)]
+pub struct ConfigLoader {
+    /// The TOML file read, if any.
+    file: Option<PathBuf>,
+
+    /// The environment variables read, by name.
+    env: BTreeMap<String, String>,
+
+    /// The overrides, in the order they were set.
+    overrides: Vec<(String, Value)>,
+}
+
+/// Where a value of a [`ZerofsConfig`] came from.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum ConfigSource {
+    /// The default of the value.
+    #[default]
+    Default,
+
+    /// The TOML file at the path.
+    File(PathBuf),
+
+    /// The environment variable with the name.
+    Env(String),
+
+    /// An override set in code or on the command line.
+    Override,
+}
+
+/// Where each value of a [`ZerofsConfig`] came from, by its path, like `trash.retention`. Values
+/// not listed have their default.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ConfigSources {
+    sources: BTreeMap<String, ConfigSource>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl ConfigLoader {
+    /// Creates a loader that starts from the defaults.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads the TOML file at `path`.
+    pub fn file(self, path: impl Into<PathBuf>) -> Self {
+        Self {
+            file: Some(path.into()),
+            ..self
+        }
+    }
+
+    /// Reads the environment variables of the process that start with [`CONFIG_ENV_PREFIX`], and
+    /// the file named by [`CONFIG_PATH_ENV_VAR`] if no file is set.
+    pub fn env(self) -> Self {
+        self.env_vars(std::env::vars())
+    }
+
+    /// Reads the given environment variables, ignoring those that do not start with
+    /// [`CONFIG_ENV_PREFIX`], as [`env`][Self::env] does for those of the process.
+    pub fn env_vars(mut self, vars: impl IntoIterator<Item = (String, String)>) -> Self {
+        for (name, value) in vars {
+            if name == CONFIG_PATH_ENV_VAR {
+                self.file.get_or_insert_with(|| value.into());
+            } else if name.starts_with(CONFIG_ENV_PREFIX) {
+                self.env.insert(name, value);
+            }
+        }
+
+        self
+    }
+
+    /// Sets the value at `path`, like `trash.retention`, over every other source.
+    pub fn set(mut self, path: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.overrides.push((path.into(), value.into()));
+        self
+    }
+
+    /// Sets a value from an assignment of the form `path=value`, like a command-line flag. The
+    /// value is read as with environment variables.
+    pub fn set_str(self, assignment: &str) -> ConfigLoadResult<Self> {
+        let (path, value) = assignment
+            .split_once('=')
+            .filter(|(path, _)| !path.trim().is_empty())
+            .ok_or_else(|| ConfigLoadError::InvalidOverride(assignment.to_owned()))?;
+
+        let path = path.trim();
+        Ok(self.set(path, parse_value_at(path, value)))
+    }
+
+    /// Merges the sources and returns the configuration, along with where each value came from.
+    ///
+    /// The configuration is not validated beyond the types of its values.
+    pub fn load(self) -> ConfigLoadResult<ZerofsConfig> {
+        let mut merged = Table::new();
+        let mut sources = ConfigSources::default();
+
+        if let Some(path) = self.file {
+            let content = std::fs::read_to_string(&path)
+                .map_err(|e| ConfigLoadError::ReadFile(path.clone(), e))?;
+            let table = toml::from_str::<Table>(&content)
+                .map_err(|e| ConfigLoadError::ParseFile(path.clone(), Box::new(e)))?;
+            merge(
+                &mut merged,
+                table,
+                "",
+                &ConfigSource::File(path),
+                &mut sources,
+            );
+        }
+
+        for (name, value) in self.env {
+            let path = env_path(&name);
+            merge(
+                &mut merged,
+                nest(&path, parse_value_at(&path, &value)),
+                "",
+                &ConfigSource::Env(name),
+                &mut sources,
+            );
+        }
+
+        for (path, value) in self.overrides {
+            merge(
+                &mut merged,
+                nest(&path, value),
+                "",
+                &ConfigSource::Override,
+                &mut sources,
+            );
+        }
+
+        let mut config: ZerofsConfig = Value::Table(merged)
+            .try_into()
+            .map_err(|e| ConfigLoadError::InvalidConfig(Box::new(e)))?;
+        config.sources = sources;
+
+        Ok(config)
+    }
+}
+
+impl ConfigSources {
+    /// Returns where the value at `path` came from.
+    pub fn get(&self, path: &str) -> &ConfigSource {
+        self.sources.get(path).unwrap_or(&ConfigSource::Default)
+    }
+
+    /// Returns the paths of the values that do not have their default, with where they came from.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &ConfigSource)> {
+        self.sources
+            .iter()
+            .map(|(path, source)| (path.as_str(), source))
+    }
+}
+
+impl ZerofsConfig {
+    /// Returns the resolved values of the configuration, one `path = value` line each, along with
+    /// where each came from, for debugging a deployment.
+    ///
+    /// Values that are never serialized, like the admin token, are left out.
+    pub fn format_effective(&self) -> ConfigLoadResult<String> {
+        let mut values = Vec::new();
+        flatten(&Value::try_from(self)?, "", &mut values);
+
+        let mut output = String::new();
+        for (path, value) in values {
+            let _ = writeln!(
+                output,
+                "{} = {}  # {}",
+                path,
+                value,
+                self.sources.get(&path)
+            );
+        }
+
+        Ok(output)
+    }
+
+    /// Prints the resolved values of the configuration and where each came from, see
+    /// [`format_effective`][Self::format_effective].
+    pub fn print_effective(&self) -> ConfigLoadResult<()> {
+        print!("{}", self.format_effective()?);
+        Ok(())
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Merges `layer` into `base`, recording `source` for every value it sets under `prefix`.
+///
+/// Tables are merged key by key, and any other value replaces the one in `base`.
+fn merge(
+    base: &mut Table,
+    layer: Table,
+    prefix: &str,
+    source: &ConfigSource,
+    sources: &mut ConfigSources,
+) {
+    for (key, value) in layer {
+        let path = join_path(prefix, &key);
+        match (base.get_mut(&key), value) {
+            (Some(Value::Table(base)), Value::Table(layer)) => {
+                merge(base, layer, &path, source, sources)
+            }
+            (_, value) => {
+                let mut leaves = Vec::new();
+                flatten(&value, &path, &mut leaves);
+                sources
+                    .sources
+                    .retain(|other, _| other != &path && !other.starts_with(&format!("{}.", path)));
+                for (leaf, _) in leaves {
+                    sources.sources.insert(leaf, source.clone());
+                }
+
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Collects the values under `value` that are not tables, with their paths under `prefix`.
+fn flatten(value: &Value, prefix: &str, values: &mut Vec<(String, Value)>) {
+    match value {
+        Value::Table(table) => {
+            for (key, value) in table {
+                flatten(value, &join_path(prefix, key), values);
+            }
+        }
+        value => values.push((prefix.to_owned(), value.clone())),
+    }
+}
+
+/// Returns a table with `value` at the dotted `path`.
+fn nest(path: &str, value: Value) -> Table {
+    let mut segments = path.rsplit('.');
+    let mut table = Table::new();
+    table.insert(segments.next().unwrap_or_default().to_owned(), value);
+    for segment in segments {
+        let mut parent = Table::new();
+        parent.insert(segment.to_owned(), Value::Table(table));
+        table = parent;
+    }
+
+    table
+}
+
+/// Returns the path of the value set by the environment variable `name`.
+fn env_path(name: &str) -> String {
+    name[CONFIG_ENV_PREFIX.len()..]
+        .split(ENV_SECTION_SEPARATOR)
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+/// Reads `raw` as a TOML value, or as a string if it is not one.
+fn parse_value(raw: &str) -> Value {
+    toml::from_str::<Table>(&format!("value = {}", raw))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| Value::String(raw.to_owned()))
+}
+
+/// Reads `raw` as the value at `path`, like [`parse_value`], but as a string if only a string is
+/// accepted there, like for a token made only of digits.
+///
+/// Whether a string is accepted is checked against the defaults, with nothing else set.
+fn parse_value_at(path: &str, raw: &str) -> Value {
+    let value = parse_value(raw);
+    if value.is_str() {
+        return value;
+    }
+
+    let accepts = |value: Value| {
+        Value::Table(nest(path, value))
+            .try_into::<ZerofsConfig>()
+            .is_ok()
+    };
+
+    let string = Value::String(raw.to_owned());
+    if !accepts(value.clone()) && accepts(string.clone()) {
+        return string;
+    }
+
+    value
+}
+
+fn join_path(prefix: &str, key: &str) -> String {
+    if prefix.is_empty() {
+        key.to_owned()
+    } else {
+        format!("{}.{}", prefix, key)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl Display for ConfigSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigSource::Default => write!(f, "default"),
+            ConfigSource::File(path) => write!(f, "file {}", path.display()),
+            ConfigSource::Env(name) => write!(f, "env {}", name),
+            ConfigSource::Override => write!(f, "override"),
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_loader_precedence() -> anyhow::Result<()> {
+        let path =
+            std::env::temp_dir().join(format!("zerofs-config-{}.toml", rand::random::<u64>()));
+        std::fs::write(
+            &path,
+            "read_only = true\n[trash]\nretention = 100\n[search]\nenabled = true\n",
+        )?;
+
+        let config = ConfigLoader::new()
+            .file(&path)
+            .env_vars([
+                ("ZEROFS_TRASH__RETENTION".to_owned(), "200".to_owned()),
+                ("ZEROFS_READ_ONLY".to_owned(), "false".to_owned()),
+                ("ZEROFS_ADMIN__TOKEN".to_owned(), "123456".to_owned()),
+                ("HOME".to_owned(), "/root".to_owned()),
+            ])
+            .set_str("trash.retention=300")?
+            .load()?;
+        std::fs::remove_file(&path)?;
+
+        // Overrides win over the environment, which wins over the file.
+        assert_eq!(config.trash.retention, Some(300));
+        assert!(!config.read_only);
+        assert!(config.search.enabled);
+
+        // Values read as other types are kept as strings where only a string is accepted.
+        assert_eq!(config.admin.token.as_deref(), Some("123456"));
+        assert_eq!(
+            config.sources.get("trash.retention"),
+            &ConfigSource::Override
+        );
+        assert_eq!(
+            config.sources.get("read_only"),
+            &ConfigSource::Env("ZEROFS_READ_ONLY".to_owned())
+        );
+        assert_eq!(
+            config.sources.get("search.enabled"),
+            &ConfigSource::File(path)
+        );
+        assert_eq!(
+            config.sources.get("jobs.concurrency"),
+            &ConfigSource::Default
+        );
+
+        let effective = config.format_effective()?;
+        assert!(effective.contains("trash.retention = 300  # override\n"));
+
+        assert!(matches!(
+            ConfigLoader::new().set_str("retention"),
+            Err(ConfigLoadError::InvalidOverride(_))
+        ));
+        assert!(matches!(
+            ConfigLoader::new().set("trash.retention", "soon").load(),
+            Err(ConfigLoadError::InvalidConfig(_))
+        ));
+
+        Ok(())
+    }
+}
@@ -2,6 +2,8 @@
 
 mod config;
 mod default;
+mod error;
+mod loader;
 
 //--------------------------------------------------------------------------------------------------
 // Exports
@@ -9,6 +11,8 @@ mod default;
 
 pub use config::*;
 pub use default::*;
+pub use error::*;
+pub use loader::*;
 
 //--------------------------------------------------------------------------------------------------
 // Re-exports
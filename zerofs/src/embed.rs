@@ -0,0 +1,338 @@
+//! Embedding a zerofs file tree directly in an application, without the service layer.
+
+use std::{fmt::Debug, sync::Mutex};
+
+#[cfg(feature = "native")]
+use std::{
+    path::{Path as LocalPath, PathBuf},
+    str::FromStr,
+};
+
+use futures::future::{BoxFuture, FutureExt};
+use tokio::io::AsyncRead;
+#[cfg(feature = "native")]
+use tokio::{fs, io::AsyncWriteExt};
+use zeroutils_did_wk::{Base, WrappedDidWebKey};
+use zeroutils_key::GetPublicKey;
+use zeroutils_store::{ipld::cid::Cid, IpldStore, Storable};
+
+use crate::filesystem::{
+    Acl, Chunker, DescriptorFlags, Dir, DirHandle, Entity, File, FsAction, FsError, FsResult,
+    MemoryBufferStore, Path, RootDir,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Where a [`Zerofs`] keeps its HEAD, the [`Cid`] of the last root it committed, so that the file
+/// tree can be opened again later.
+pub trait HeadStore: Debug + Send + Sync {
+    /// Returns the saved HEAD, or `None` if none was saved yet.
+    fn load_head(&self) -> BoxFuture<'_, FsResult<Option<Cid>>>;
+
+    /// Saves `cid` as the HEAD.
+    fn save_head<'a>(&'a self, cid: &'a Cid) -> BoxFuture<'a, FsResult<()>>;
+}
+
+/// A [`HeadStore`] keeping the HEAD in a local file, as the text form of the [`Cid`].
+///
+/// The file is replaced through a temporary file, so that a crash never leaves it half written.
+#[cfg(feature = "native")]
+#[derive(Debug, Clone)]
+pub struct FileHead {
+    /// The path of the file.
+    path: PathBuf,
+}
+
+/// A [`HeadStore`] keeping the HEAD in memory, for file trees that do not outlive the process.
+#[derive(Debug, Default)]
+pub struct MemoryHead {
+    /// The saved HEAD.
+    cid: Mutex<Option<Cid>>,
+}
+
+/// A zerofs file tree opened in-process, for applications that embed the file system directly
+/// rather than talk to a node over HTTP.
+///
+/// It ties a [`RootDir`] over a store to a [`HeadStore`]. Handles made from it change the root,
+/// and [`commit`][Self::commit] saves the root as the HEAD, which [`open`][Self::open] starts
+/// from the next time.
+pub struct Zerofs<S>
+where
+    S: IpldStore,
+{
+    /// The root directory of the file tree.
+    root: RootDir<S>,
+
+    /// Where the HEAD is kept.
+    head: Box<dyn HeadStore>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(feature = "native")]
+impl FileHead {
+    /// Creates a [`HeadStore`] keeping the HEAD in the file at `path`.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Returns the path of the file.
+    pub fn get_path(&self) -> &LocalPath {
+        &self.path
+    }
+}
+
+impl<S> Zerofs<S>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    /// Creates an empty file tree in `store`, owned by `key`, and commits it as the first HEAD of
+    /// `head`.
+    ///
+    /// The DID of `key` is allowed [`FsAction::Manage`] on the root. Fails with
+    /// [`FsError::HeadExists`] if `head` already has a HEAD, rather than dropping the tree it
+    /// points at.
+    pub async fn create<K>(store: S, key: &K, head: impl HeadStore + 'static) -> FsResult<Self>
+    where
+        K: GetPublicKey,
+    {
+        if let Some(cid) = head.load_head().await? {
+            return Err(FsError::HeadExists(cid));
+        }
+
+        let owner = WrappedDidWebKey::from_key(key, Base::Base58Btc)?;
+        let mut acl = Acl::new();
+        acl.allow(owner.to_string(), FsAction::Manage);
+
+        let mut dir = Dir::new(store);
+        dir.set_acl(acl);
+
+        let zerofs = Self {
+            root: RootDir::from_dir(dir),
+            head: Box::new(head),
+        };
+        zerofs.commit().await?;
+
+        Ok(zerofs)
+    }
+
+    /// Opens the file tree in `store` at the HEAD saved in `head`.
+    ///
+    /// Fails with [`FsError::HeadNotFound`] if no HEAD was saved yet.
+    pub async fn open(store: S, head: impl HeadStore + 'static) -> FsResult<Self> {
+        let cid = head.load_head().await?.ok_or(FsError::HeadNotFound)?;
+        let dir = Dir::load(&cid, store).await?;
+
+        Ok(Self {
+            root: RootDir::from_dir(dir),
+            head: Box::new(head),
+        })
+    }
+
+    /// Returns the root directory of the file tree.
+    pub fn get_root(&self) -> &RootDir<S> {
+        &self.root
+    }
+
+    /// Creates a handle to the root directory with the given flags.
+    pub fn make_handle(&self, flags: DescriptorFlags) -> DirHandle<S, MemoryBufferStore<S>> {
+        self.root.make_handle(flags)
+    }
+
+    /// Creates a handle to the directory at `path` with the given flags, see
+    /// [`RootDir::make_handle_at`].
+    pub async fn make_handle_at(
+        &self,
+        path: &Path,
+        flags: DescriptorFlags,
+        create: bool,
+    ) -> FsResult<DirHandle<S, MemoryBufferStore<S>>> {
+        self.root.make_handle_at(path, flags, create).await
+    }
+
+    /// Returns the entity at `path`, or the root directory if `path` is empty.
+    pub async fn get_entity_at(&self, path: &Path) -> FsResult<Entity<S>> {
+        self.root.get_entity_at(path).await
+    }
+
+    /// Creates an empty directory at `path`, along with any missing directory above it.
+    ///
+    /// Fails with [`FsError::PathExists`] if something is already at `path`.
+    pub async fn create_dir_at(&self, path: &Path) -> FsResult<()> {
+        self.check_vacant(path).await?;
+        let cid = Dir::new(self.root.get_store()).store().await?;
+        self.root.link_at(path, cid).await
+    }
+
+    /// Writes the content coming through `reader` as the file at `path`, replacing any file
+    /// there, and creates any missing directory above it.
+    ///
+    /// Fails with [`FsError::NotAFile`] if something other than a file is at `path`.
+    pub async fn write_file_at(
+        &self,
+        path: &Path,
+        reader: impl AsyncRead + Send + Sync,
+    ) -> FsResult<()> {
+        match self.root.get_entity_at(path).await {
+            Ok(Entity::File(_)) | Err(FsError::NotFound(_)) => {}
+            Ok(_) => return Err(FsError::NotAFile(Some(path.clone()))),
+            Err(e) => return Err(e),
+        }
+
+        let mut file = File::new(self.root.get_store());
+        file.put_content(reader, &Chunker::default()).await?;
+        let cid = file.store().await?;
+        self.root.link_at(path, cid).await
+    }
+
+    /// Removes the entity at `path`, with everything under it if it is a directory.
+    pub async fn remove_at(&self, path: &Path) -> FsResult<()> {
+        self.root.unlink_at(path).await?;
+        Ok(())
+    }
+
+    /// Stores the current root and saves its [`Cid`] as the HEAD, which it returns.
+    ///
+    /// Changes made since the last commit are lost if the process stops before the next one.
+    pub async fn commit(&self) -> FsResult<Cid> {
+        let (_, cid) = self.root.load().await?;
+        self.head.save_head(&cid).await?;
+        Ok(cid)
+    }
+
+    /// Returns the saved HEAD, which is behind the root while changes are not committed.
+    pub async fn get_head(&self) -> FsResult<Option<Cid>> {
+        self.head.load_head().await
+    }
+
+    /// Fails with [`FsError::PathExists`] if something is at `path`.
+    async fn check_vacant(&self, path: &Path) -> FsResult<()> {
+        match self.root.get_entity_at(path).await {
+            Ok(_) => Err(FsError::PathExists(path.clone())),
+            Err(FsError::NotFound(_)) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(feature = "native")]
+impl HeadStore for FileHead {
+    fn load_head(&self) -> BoxFuture<'_, FsResult<Option<Cid>>> {
+        async move {
+            let content = match fs::read_to_string(&self.path).await {
+                Ok(content) => content,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+                Err(e) => return Err(e.into()),
+            };
+
+            let cid = Cid::from_str(content.trim())
+                .map_err(|e| FsError::InvalidHead(format!("{}: {}", self.path.display(), e)))?;
+
+            Ok(Some(cid))
+        }
+        .boxed()
+    }
+
+    fn save_head<'a>(&'a self, cid: &'a Cid) -> BoxFuture<'a, FsResult<()>> {
+        async move {
+            let temp = self.path.with_extension("tmp");
+            let mut file = fs::File::create(&temp).await?;
+            file.write_all(format!("{}\n", cid).as_bytes()).await?;
+            file.sync_all().await?;
+            fs::rename(&temp, &self.path).await?;
+            Ok(())
+        }
+        .boxed()
+    }
+}
+
+impl HeadStore for MemoryHead {
+    fn load_head(&self) -> BoxFuture<'_, FsResult<Option<Cid>>> {
+        let cid = *self.cid.lock().unwrap();
+        async move { Ok(cid) }.boxed()
+    }
+
+    fn save_head<'a>(&'a self, cid: &'a Cid) -> BoxFuture<'a, FsResult<()>> {
+        *self.cid.lock().unwrap() = Some(*cid);
+        async { Ok(()) }.boxed()
+    }
+}
+
+impl<H> HeadStore for std::sync::Arc<H>
+where
+    H: HeadStore + ?Sized,
+{
+    fn load_head(&self) -> BoxFuture<'_, FsResult<Option<Cid>>> {
+        (**self).load_head()
+    }
+
+    fn save_head<'a>(&'a self, cid: &'a Cid) -> BoxFuture<'a, FsResult<()>> {
+        (**self).save_head(cid)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use zeroutils_store::MemoryStore;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_zerofs_commit_and_reopen() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let head = Arc::new(MemoryHead::default());
+        assert!(matches!(
+            Zerofs::open(store.clone(), head.clone()).await,
+            Err(FsError::HeadNotFound)
+        ));
+
+        let zerofs = Zerofs {
+            root: RootDir::new(store.clone()),
+            head: Box::new(head.clone()),
+        };
+        let first = zerofs.commit().await?;
+        assert_eq!(zerofs.get_head().await?, Some(first));
+
+        // Changes to the root only reach the HEAD once committed.
+        let file = File::new(store.clone()).store().await?;
+        zerofs
+            .get_root()
+            .link_at(&"docs/notes".parse()?, file)
+            .await?;
+        assert_eq!(head.load_head().await?, Some(first));
+        let second = zerofs.commit().await?;
+        assert_ne!(second, first);
+
+        let reopened = Zerofs::open(store, head).await?;
+        let (dir, cid) = reopened.get_root().load().await?;
+        assert_eq!(cid, second);
+        assert_eq!(dir.get_entries().count(), 1);
+
+        // The HEAD file is read back as written, and is missing until the first commit.
+        #[cfg(feature = "native")]
+        {
+            let path = std::env::temp_dir().join(format!("zerofs-head-{}", rand::random::<u64>()));
+            let file_head = FileHead::new(&path);
+            assert_eq!(file_head.load_head().await?, None);
+            file_head.save_head(&second).await?;
+            assert_eq!(file_head.load_head().await?, Some(second));
+            std::fs::remove_file(&path)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,109 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::{Deserialize, Serialize};
+
+use super::FsAction;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// An access control list stored in the [`Metadata`][super::Metadata] of an entity.
+///
+/// Unlike capabilities, which the caller carries and presents, an ACL is kept by the server next
+/// to the entity and maps DIDs to the actions they are allowed to perform. An ACL on a directory
+/// also applies to everything beneath it, the same way a capability resource covers everything
+/// beneath its path.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Acl {
+    /// The allowed actions by DID.
+    entries: BTreeMap<String, BTreeSet<FsAction>>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl Acl {
+    /// Creates an empty ACL.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allows `did` to perform `action`.
+    pub fn allow(&mut self, did: impl Into<String>, action: FsAction) {
+        self.entries.entry(did.into()).or_default().insert(action);
+    }
+
+    /// Stops allowing `did` to perform `action`.
+    pub fn revoke(&mut self, did: &str, action: FsAction) {
+        if let Some(actions) = self.entries.get_mut(did) {
+            actions.remove(&action);
+            if actions.is_empty() {
+                self.entries.remove(did);
+            }
+        }
+    }
+
+    /// Removes every action allowed to `did`.
+    pub fn remove(&mut self, did: &str) -> Option<BTreeSet<FsAction>> {
+        self.entries.remove(did)
+    }
+
+    /// Returns the actions allowed to `did`.
+    pub fn get_actions(&self, did: &str) -> Option<&BTreeSet<FsAction>> {
+        self.entries.get(did)
+    }
+
+    /// Returns an iterator over the DIDs in the ACL and their allowed actions.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &BTreeSet<FsAction>)> {
+        self.entries
+            .iter()
+            .map(|(did, actions)| (did.as_str(), actions))
+    }
+
+    /// Returns `true` if the ACL has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns `true` if the ACL allows `did` to perform `action`.
+    pub fn permits(&self, did: &str, action: FsAction) -> bool {
+        self.entries
+            .get(did)
+            .is_some_and(|actions| actions.iter().any(|allowed| allowed.permits(action)))
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALICE: &str = "did:wk:z6MkoVs2h6TnfyY8fx2ZqpREWSLS8rBDQmGpyXgFpg63CSUb";
+    const BOB: &str = "did:wk:z6MknLif7jhwt6jUfn14EuDnxWoSHkkajyDi28QMMH5eS1DL";
+
+    #[test]
+    fn test_acl_permits() -> anyhow::Result<()> {
+        let mut acl = Acl::new();
+        acl.allow(ALICE, FsAction::Read);
+        acl.allow(BOB, FsAction::Manage);
+
+        assert!(acl.permits(ALICE, FsAction::Read));
+        assert!(!acl.permits(ALICE, FsAction::Write));
+        assert!(acl.permits(BOB, FsAction::Delete));
+
+        acl.revoke(ALICE, FsAction::Read);
+        assert!(!acl.permits(ALICE, FsAction::Read));
+        assert_eq!(acl.get_actions(ALICE), None);
+
+        let serialized = toml::to_string(&acl)?;
+        assert_eq!(toml::from_str::<Acl>(&serialized)?, acl);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,258 @@
+use zeroutils_store::{ipld::cid::Cid, IpldStore, Storable};
+
+use super::{
+    current_time, merge, put_ref, ConflictReport, Dir, FsError, FsResult, Path, Ref, RefIndex,
+    RefPrecondition,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The prefix of the names of the refs that track branches.
+pub const BRANCH_REF_PREFIX: &str = "branches/";
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// A writable branch of a file tree, forked from one of its roots.
+///
+/// A branch is a ref named `branches/<name>` in the [`RefIndex`] of the main tree, pointing at the
+/// head of the branch and recording the root it was forked from. Forking is cheap since the branch
+/// shares every block with the root it was forked from until it changes them.
+///
+/// Changes made through a `Branch` only touch its own tree and are committed to the ref of the
+/// branch with [`commit`][Self::commit], leaving the main tree as it is, until the branch is folded
+/// back into it with [`merge_branch`].
+#[derive(Debug, Clone)]
+pub struct Branch<S>
+where
+    S: IpldStore,
+{
+    /// The name of the branch.
+    name: String,
+
+    /// The head of the branch when it was opened or last committed.
+    head: Cid,
+
+    /// The tree of the branch, with the changes not committed yet.
+    root_dir: Dir<S>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl<S> Branch<S>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    /// Opens the branch `name` of the tree under `root`.
+    ///
+    /// Fails with [`FsError::RefNotFound`] if there is no such branch.
+    pub async fn open(root: &Dir<S>, name: &str) -> FsResult<Self> {
+        let ref_name = get_ref_name(name);
+        let index = RefIndex::load(root).await?;
+        let head = index
+            .get_ref(&ref_name)
+            .ok_or(FsError::RefNotFound(ref_name))?
+            .root;
+
+        Ok(Self {
+            name: name.to_owned(),
+            head,
+            root_dir: Dir::load(&head, root.get_store().clone()).await?,
+        })
+    }
+
+    /// Returns the name of the branch.
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the head of the branch when it was opened or last committed.
+    pub fn get_head(&self) -> &Cid {
+        &self.head
+    }
+
+    /// Returns the tree of the branch.
+    pub fn get_root_dir(&self) -> &Dir<S> {
+        &self.root_dir
+    }
+
+    /// Links the entity with the CID `cid` at `path` in the tree of the branch.
+    pub async fn link_at(&mut self, path: &Path, cid: Cid) -> FsResult<()> {
+        self.root_dir = self.root_dir.link_at(path, cid).await?;
+        Ok(())
+    }
+
+    /// Unlinks the entity at `path` from the tree of the branch and returns its CID.
+    pub async fn unlink_at(&mut self, path: &Path) -> FsResult<Cid> {
+        let (root_dir, cid) = self.root_dir.unlink_at(path).await?;
+        self.root_dir = root_dir;
+
+        Ok(cid)
+    }
+
+    /// Points the branch in the tree under `root` at the tree of the branch and returns the
+    /// updated root.
+    ///
+    /// Fails with [`FsError::RefConflict`] if the branch was committed through another handle since
+    /// this one was opened or last committed, so that neither commit is lost.
+    pub async fn commit(&mut self, root: &Dir<S>) -> FsResult<Dir<S>> {
+        let head = self.root_dir.store().await?;
+        if head == self.head {
+            return Ok(root.clone());
+        }
+
+        let root = put_ref(
+            root,
+            &get_ref_name(&self.name),
+            &RefPrecondition::At(self.head),
+            |current| Ref {
+                root: head,
+                base: current.and_then(|entry| entry.base),
+                updated_at: current_time(),
+            },
+        )
+        .await?;
+        self.head = head;
+
+        Ok(root)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Creates the branch `name` of the tree under `root`, forked from the root `from`, and returns the
+/// updated root.
+///
+/// Fails with [`FsError::RefConflict`] if the branch exists already.
+pub async fn create_branch<S>(root: &Dir<S>, name: &str, from: Cid) -> FsResult<Dir<S>>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    put_ref(root, &get_ref_name(name), &RefPrecondition::Absent, |_| {
+        Ref {
+            root: from,
+            base: Some(from),
+            updated_at: current_time(),
+        }
+    })
+    .await
+}
+
+/// Folds the changes committed to the branch `name` since it was forked, or last merged, into the
+/// tree under `root`, and returns the merged tree along with a report of the changes that could
+/// not be applied.
+///
+/// This is a three-way [`merge`] between the root the branch was forked from, the head of the
+/// branch and `root`. The branch is then rebased on its head, so merging it again only folds in
+/// the changes committed after this merge.
+pub async fn merge_branch<S>(root: &Dir<S>, name: &str) -> FsResult<(Dir<S>, ConflictReport)>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    let ref_name = get_ref_name(name);
+    let index = RefIndex::load(root).await?;
+    let entry = index
+        .get_ref(&ref_name)
+        .ok_or_else(|| FsError::RefNotFound(ref_name.clone()))?;
+    let head = entry.root;
+    let base = entry.base.unwrap_or(head);
+
+    let (merged, report) = merge(&base, root, &head).await?;
+    let merged = put_ref(&merged, &ref_name, &RefPrecondition::At(head), |_| Ref {
+        root: head,
+        base: Some(head),
+        updated_at: current_time(),
+    })
+    .await?;
+
+    Ok((merged, report))
+}
+
+/// Returns the name of the ref that tracks the branch `name`.
+fn get_ref_name(name: &str) -> String {
+    format!("{BRANCH_REF_PREFIX}{name}")
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use zeroutils_store::MemoryStore;
+
+    use crate::filesystem::{Chunker, File, TraceResult};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_branch_commit_and_merge() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let file_with = |content: &'static [u8]| {
+            let store = store.clone();
+            async move {
+                let mut file = File::new(store);
+                file.put_content(content, &Chunker::Store).await?;
+                anyhow::Ok(file.store().await?)
+            }
+        };
+
+        let main = Dir::new(store.clone())
+            .link_at(&"docs/a".parse()?, file_with(b"a").await?)
+            .await?;
+        let main = create_branch(&main, "staging", main.store().await?).await?;
+        assert!(matches!(
+            create_branch(&main, "staging", main.store().await?).await,
+            Err(FsError::RefConflict(_))
+        ));
+
+        // Changes to the branch are committed to the branch only.
+        let mut branch = Branch::open(&main, "staging").await?;
+        let mut stale = branch.clone();
+        let staged = file_with(b"staged").await?;
+        branch.link_at(&"docs/b".parse()?, staged).await?;
+        let main = branch.commit(&main).await?;
+        assert!(matches!(
+            main.trace_entity(&"docs/b".parse()?).await?,
+            TraceResult::Incomplete { .. }
+        ));
+
+        stale.unlink_at(&"docs/a".parse()?).await?;
+        assert!(matches!(
+            stale.commit(&main).await,
+            Err(FsError::RefConflict(_))
+        ));
+
+        // Meanwhile, main moves on.
+        let main = main
+            .link_at(&"docs/c".parse()?, file_with(b"c").await?)
+            .await?;
+
+        let (main, report) = merge_branch(&main, "staging").await?;
+        assert!(report.is_clean());
+        assert_eq!(report.applied, 1);
+        for path in ["docs/a", "docs/b", "docs/c"] {
+            assert!(
+                matches!(
+                    main.trace_entity(&path.parse()?).await?,
+                    TraceResult::Found { .. }
+                ),
+                "{}",
+                path
+            );
+        }
+
+        // Merging again folds in nothing new.
+        let (_, report) = merge_branch(&main, "staging").await?;
+        assert_eq!(report.applied, 0);
+
+        Ok(())
+    }
+}
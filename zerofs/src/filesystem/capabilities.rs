@@ -1,10 +1,449 @@
-use zeroutils_ucan::Capabilities;
+use std::{
+    fmt::{self, Display},
+    iter::FromIterator,
+    str::FromStr,
+};
+
+use serde::{Deserialize, Serialize};
+use serde_with::{DeserializeFromStr, SerializeDisplay};
+
+use super::{FsError, FsResult, Path, PathSegment, PermissionError, PATH_SEPARATOR};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The URI scheme of a zerofs capability resource.
+pub const FS_RESOURCE_SCHEME: &str = "zerofs";
+
+/// The namespace of zerofs capability abilities, e.g. `zerofs/read`.
+pub const FS_ABILITY_NAMESPACE: &str = "zerofs";
+
+/// The wildcard that matches any root, any single path segment or any action.
+pub const FS_WILDCARD: &str = "*";
 
 //--------------------------------------------------------------------------------------------------
 // Types
 //--------------------------------------------------------------------------------------------------
 
-/// TODO: Add capabilities for the filesystem
-pub fn capabilities() -> Capabilities<'static> {
-    todo!()
+/// An action that can be performed on a part of the file tree.
+///
+/// In a UCAN, actions appear as abilities in the `zerofs` namespace, e.g. `zerofs/read`. The
+/// ability `*` is the same as [`FsAction::Manage`].
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, SerializeDisplay, DeserializeFromStr,
+)]
+pub enum FsAction {
+    /// Read the contents and metadata of entities.
+    Read,
+
+    /// Change the contents of existing entities.
+    Write,
+
+    /// Create new entities.
+    Create,
+
+    /// Remove entities.
+    Delete,
+
+    /// Do anything, including replacing the root. Implies every other action.
+    Manage,
+}
+
+/// A segment of a [`FsResource`] path.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum FsResourceSegment {
+    /// Matches any single path segment, denoted by `*`.
+    Wildcard,
+
+    /// Matches the path segment with the same name.
+    Named(PathSegment),
+}
+
+/// The part of a file tree that a capability applies to.
+///
+/// A resource is written as `zerofs://<root-did>/<path>`, for example
+/// `zerofs://did:wk:z6Mk.../public/photos`. The root can be `*` to match any root and any path
+/// segment can be `*` to match exactly one segment with any name.
+///
+/// A resource covers its own path and everything beneath it, so `zerofs://<root-did>/public` also
+/// covers `/public/photos/cats`, and `zerofs://<root-did>` covers the whole tree.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, SerializeDisplay, DeserializeFromStr)]
+pub struct FsResource {
+    /// The DID of the root the resource belongs to, or `None` for any root.
+    root: Option<String>,
+
+    /// The path from the root that the resource covers.
+    segments: Vec<FsResourceSegment>,
+}
+
+/// A capability that allows an action on a part of the file tree.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct FsCapability {
+    /// The part of the file tree the capability applies to.
+    pub resource: FsResource,
+
+    /// The action the capability allows.
+    pub action: FsAction,
+}
+
+/// A set of [`FsCapability`]s, usually the file system capabilities delegated by a UCAN.
+///
+/// The set can also record the DID of the invoker holding it, which lets the service fall back to
+/// the [`Acl`][super::Acl]s in the tree when no capability allows an operation.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct FsCapabilities {
+    /// The capabilities in the set.
+    capabilities: Vec<FsCapability>,
+
+    /// The DID of the invoker holding the capabilities.
+    #[serde(skip)]
+    invoker: Option<String>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl FsAction {
+    /// Returns `true` if being allowed this action also allows the `requested` action.
+    pub fn permits(&self, requested: FsAction) -> bool {
+        *self == FsAction::Manage || *self == requested
+    }
+
+    /// Returns the name of the action without the ability namespace.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FsAction::Read => "read",
+            FsAction::Write => "write",
+            FsAction::Create => "create",
+            FsAction::Delete => "delete",
+            FsAction::Manage => "manage",
+        }
+    }
+}
+
+impl FsResource {
+    /// Creates a resource that covers `path` and everything beneath it in the tree of `root`.
+    pub fn new(root: impl Into<String>, path: &Path) -> Self {
+        Self {
+            root: Some(root.into()),
+            segments: path
+                .iter()
+                .map(|segment| FsResourceSegment::Named(segment.clone()))
+                .collect(),
+        }
+    }
+
+    /// Creates a resource that covers the whole tree of `root`.
+    pub fn root(root: impl Into<String>) -> Self {
+        Self {
+            root: Some(root.into()),
+            segments: Vec::new(),
+        }
+    }
+
+    /// Returns the DID of the root the resource belongs to, or `None` if it matches any root.
+    pub fn get_root(&self) -> Option<&str> {
+        self.root.as_deref()
+    }
+
+    /// Returns the path segments of the resource.
+    pub fn get_segments(&self) -> &[FsResourceSegment] {
+        &self.segments
+    }
+
+    /// Returns `true` if the resource covers `path` in the tree of `root`.
+    ///
+    /// `path` is expected to be canonical, i.e. free of `.` and `..` segments.
+    pub fn matches(&self, root: &str, path: &Path) -> bool {
+        if let Some(own_root) = &self.root {
+            if own_root != root {
+                return false;
+            }
+        }
+
+        if path.len() < self.segments.len() {
+            return false;
+        }
+
+        self.segments
+            .iter()
+            .zip(path.iter())
+            .all(|(pattern, segment)| match pattern {
+                FsResourceSegment::Wildcard => true,
+                FsResourceSegment::Named(name) => name == segment,
+            })
+    }
+}
+
+impl FsCapability {
+    /// Creates a new capability.
+    pub fn new(resource: FsResource, action: FsAction) -> Self {
+        Self { resource, action }
+    }
+
+    /// Returns `true` if the capability allows `action` on `path` in the tree of `root`.
+    pub fn permits(&self, root: &str, path: &Path, action: FsAction) -> bool {
+        self.action.permits(action) && self.resource.matches(root, path)
+    }
+}
+
+impl FsCapabilities {
+    /// Creates an empty capability set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records `did` as the invoker holding the capabilities.
+    pub fn with_invoker(self, did: impl Into<String>) -> Self {
+        Self {
+            invoker: Some(did.into()),
+            ..self
+        }
+    }
+
+    /// Returns the DID of the invoker holding the capabilities, if known.
+    pub fn get_invoker(&self) -> Option<&str> {
+        self.invoker.as_deref()
+    }
+
+    /// Adds a capability to the set.
+    pub fn push(&mut self, capability: FsCapability) {
+        self.capabilities.push(capability);
+    }
+
+    /// Returns an iterator over the capabilities in the set.
+    pub fn iter(&self) -> impl Iterator<Item = &FsCapability> {
+        self.capabilities.iter()
+    }
+
+    /// Returns `true` if any capability in the set allows `action` on `path` in the tree of `root`.
+    ///
+    /// `path` is expected to be canonical. Use [`check`][Self::check] for paths that may contain
+    /// `.` or `..` segments.
+    pub fn permits(&self, root: &str, path: &Path, action: FsAction) -> bool {
+        self.capabilities
+            .iter()
+            .any(|capability| capability.permits(root, path, action))
+    }
+
+    /// Checks that the set allows `action` on `path` in the tree of `root`.
+    ///
+    /// The path is canonicalized first so that `..` segments cannot be used to step outside of a
+    /// granted prefix.
+    pub fn check(&self, root: &str, path: &Path, action: FsAction) -> FsResult<()> {
+        let path = path.canonicalize()?;
+        if !self.permits(root, &path, action) {
+            return Err(PermissionError::CapabilityNotGranted(
+                FsResource::new(root, &path),
+                action,
+            )
+            .into());
+        }
+
+        Ok(())
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl FromStr for FsAction {
+    type Err = FsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == FS_WILDCARD {
+            return Ok(FsAction::Manage);
+        }
+
+        let action = s
+            .strip_prefix(FS_ABILITY_NAMESPACE)
+            .and_then(|s| s.strip_prefix('/'))
+            .ok_or_else(|| FsError::InvalidFsAction(s.to_owned()))?;
+
+        match action {
+            "read" => Ok(FsAction::Read),
+            "write" => Ok(FsAction::Write),
+            "create" => Ok(FsAction::Create),
+            "delete" => Ok(FsAction::Delete),
+            "manage" | FS_WILDCARD => Ok(FsAction::Manage),
+            _ => Err(FsError::InvalidFsAction(s.to_owned())),
+        }
+    }
+}
+
+impl Display for FsAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", FS_ABILITY_NAMESPACE, self.as_str())
+    }
+}
+
+impl FromStr for FsResource {
+    type Err = FsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let rest = s
+            .strip_prefix(FS_RESOURCE_SCHEME)
+            .and_then(|s| s.strip_prefix("://"))
+            .ok_or_else(|| FsError::InvalidFsResource(s.to_owned()))?;
+
+        let (root, path) = match rest.find(PATH_SEPARATOR) {
+            Some(index) => rest.split_at(index),
+            None => (rest, ""),
+        };
+
+        let root = match root {
+            "" => return Err(FsError::InvalidFsResource(s.to_owned())),
+            FS_WILDCARD => None,
+            root => Some(root.to_owned()),
+        };
+
+        let segments = path
+            .split(PATH_SEPARATOR)
+            .filter(|segment| !segment.is_empty())
+            .map(|segment| match segment {
+                FS_WILDCARD => Ok(FsResourceSegment::Wildcard),
+                "." | ".." => Err(FsError::InvalidFsResource(s.to_owned())),
+                segment => Ok(FsResourceSegment::Named(segment.parse()?)),
+            })
+            .collect::<FsResult<_>>()?;
+
+        Ok(Self { root, segments })
+    }
+}
+
+impl Display for FsResource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}://{}",
+            FS_RESOURCE_SCHEME,
+            self.root.as_deref().unwrap_or(FS_WILDCARD)
+        )?;
+
+        for segment in &self.segments {
+            write!(f, "{}{}", PATH_SEPARATOR, segment)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for FsResourceSegment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FsResourceSegment::Wildcard => write!(f, "{}", FS_WILDCARD),
+            FsResourceSegment::Named(segment) => write!(f, "{}", segment),
+        }
+    }
+}
+
+impl FromIterator<FsCapability> for FsCapabilities {
+    fn from_iter<T: IntoIterator<Item = FsCapability>>(iter: T) -> Self {
+        Self {
+            capabilities: iter.into_iter().collect(),
+            invoker: None,
+        }
+    }
+}
+
+impl Extend<FsCapability> for FsCapabilities {
+    fn extend<T: IntoIterator<Item = FsCapability>>(&mut self, iter: T) {
+        self.capabilities.extend(iter);
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROOT: &str = "did:wk:z6MkhjKAZ8a3bzDRE95wWERcVL2Jvo6yY58enNduuWbUYGvG";
+
+    #[test]
+    fn test_resource_roundtrip() -> anyhow::Result<()> {
+        let uri = format!("zerofs://{ROOT}/public/*/photos");
+        let resource: FsResource = uri.parse()?;
+
+        assert_eq!(resource.get_root(), Some(ROOT));
+        assert_eq!(
+            resource.get_segments(),
+            &[
+                FsResourceSegment::Named("public".parse()?),
+                FsResourceSegment::Wildcard,
+                FsResourceSegment::Named("photos".parse()?),
+            ]
+        );
+        assert_eq!(resource.to_string(), uri);
+
+        let resource: FsResource = "zerofs://*".parse()?;
+        assert_eq!(resource.get_root(), None);
+        assert!(resource.get_segments().is_empty());
+
+        assert!("ipfs://bafy/public".parse::<FsResource>().is_err());
+        assert!("zerofs:///public".parse::<FsResource>().is_err());
+        assert!(format!("zerofs://{ROOT}/public/../private")
+            .parse::<FsResource>()
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_action_parsing() -> anyhow::Result<()> {
+        assert_eq!("zerofs/read".parse::<FsAction>()?, FsAction::Read);
+        assert_eq!("zerofs/delete".parse::<FsAction>()?, FsAction::Delete);
+        assert_eq!("zerofs/*".parse::<FsAction>()?, FsAction::Manage);
+        assert_eq!("*".parse::<FsAction>()?, FsAction::Manage);
+        assert_eq!(FsAction::Write.to_string(), "zerofs/write");
+
+        assert!("read".parse::<FsAction>().is_err());
+        assert!("zerofs/execute".parse::<FsAction>().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_capabilities_matching() -> anyhow::Result<()> {
+        let capabilities: FsCapabilities = vec![
+            (format!("zerofs://{ROOT}/public/photos"), FsAction::Read),
+            (format!("zerofs://{ROOT}/users/*/inbox"), FsAction::Create),
+            (format!("zerofs://{ROOT}/tmp"), FsAction::Manage),
+        ]
+        .into_iter()
+        .map(|(uri, action)| Ok(FsCapability::new(uri.parse()?, action)))
+        .collect::<FsResult<_>>()?;
+
+        // Prefix semantics.
+        assert!(capabilities.permits(ROOT, &"public/photos".parse()?, FsAction::Read));
+        assert!(capabilities.permits(ROOT, &"PUBLIC/photos/cats".parse()?, FsAction::Read));
+        assert!(!capabilities.permits(ROOT, &"public".parse()?, FsAction::Read));
+        assert!(!capabilities.permits(ROOT, &"public/photos".parse()?, FsAction::Write));
+
+        // Wildcard segments.
+        assert!(capabilities.permits(ROOT, &"users/alice/inbox/1".parse()?, FsAction::Create));
+        assert!(!capabilities.permits(ROOT, &"users/inbox".parse()?, FsAction::Create));
+
+        // Manage implies every other action.
+        assert!(capabilities.permits(ROOT, &"tmp/a".parse()?, FsAction::Delete));
+
+        // Other roots are not covered.
+        assert!(!capabilities.permits("did:wk:other", &"tmp".parse()?, FsAction::Read));
+
+        // Canonicalization stops `..` from escaping a granted prefix.
+        assert!(capabilities
+            .check(ROOT, &"tmp/../etc".parse()?, FsAction::Read)
+            .is_err());
+        assert!(capabilities
+            .check(ROOT, &"etc/../tmp/x".parse()?, FsAction::Write)
+            .is_ok());
+
+        Ok(())
+    }
 }
@@ -0,0 +1,316 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_with::serde_as;
+use zeroutils_store::{ipld::cid::Cid, IpldReferences, IpldStore, Storable};
+
+use super::{diff, Change, Dir, FsError, FsResult, Path};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The history of the roots of a file tree, from which clients catch up on what changed since they
+/// last looked.
+///
+/// Every operation that changes the tree appends a [`Commit`] recording the new root and what the
+/// operation was. Commits are nodes in the store that link the commit before them, so the history
+/// is persisted along with the tree and the [`Cid`] of the head commit is all it takes to pick it
+/// up again with [`load`][Self::load].
+///
+/// The position of a client in the history is a cursor, the sequence number of the last commit it
+/// has seen. Cursor `0` comes before the first commit, when the tree was empty.
+#[derive(Debug, Clone)]
+pub struct ChangeFeed<S>
+where
+    S: IpldStore,
+{
+    store: S,
+
+    /// The head commit along with its CID, or `None` if nothing was committed yet.
+    head: Option<(Cid, Commit)>,
+}
+
+/// A change to a file tree recorded in a [`ChangeFeed`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Commit {
+    /// The position of the commit in the history, starting at `1`.
+    pub sequence: u64,
+
+    /// The root of the tree after the change.
+    pub root: Cid,
+
+    /// The commit before this one, or `None` for the first commit.
+    pub previous: Option<Cid>,
+
+    /// The name of the operation that made the change, like `link_at`.
+    pub operation: String,
+
+    /// The path the operation was made on.
+    pub path: Path,
+
+    /// The time the change was committed.
+    pub committed_at: DateTime<Utc>,
+}
+
+/// What changed in a file tree since a cursor, as returned by
+/// [`ChangeFeed::changes_since`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChangeSet {
+    /// The cursor to ask for the next changes with.
+    pub cursor: u64,
+
+    /// Whether there are more commits after [`cursor`][Self::cursor].
+    pub has_more: bool,
+
+    /// The commits since the cursor, oldest first.
+    pub commits: Vec<CommitSummary>,
+
+    /// The changes the commits made taken together, sorted by path.
+    pub changes: Vec<Change>,
+}
+
+/// A commit as reported in a [`ChangeSet`].
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CommitSummary {
+    /// The position of the commit in the history.
+    pub sequence: u64,
+
+    /// The root of the tree after the change.
+    #[serde_as(as = "serde_with::DisplayFromStr")]
+    pub root: Cid,
+
+    /// The name of the operation that made the change.
+    pub operation: String,
+
+    /// The path the operation was made on.
+    #[serde_as(as = "serde_with::DisplayFromStr")]
+    pub path: Path,
+
+    /// The time the change was committed.
+    pub committed_at: DateTime<Utc>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl<S> ChangeFeed<S>
+where
+    S: IpldStore,
+{
+    /// Creates a feed with no commits that stores its commits in `store`.
+    pub fn new(store: S) -> Self {
+        Self { store, head: None }
+    }
+
+    /// Loads the feed whose head commit is at `head`.
+    pub async fn load(head: &Cid, store: S) -> FsResult<Self>
+    where
+        S: Send + Sync,
+    {
+        let commit = store.get_node(head).await?;
+        Ok(Self {
+            store,
+            head: Some((*head, commit)),
+        })
+    }
+
+    /// Returns the CID of the head commit, or `None` if nothing was committed yet.
+    pub fn get_head(&self) -> Option<&Cid> {
+        self.head.as_ref().map(|(cid, _)| cid)
+    }
+
+    /// Returns the cursor of the head commit, which is `0` if nothing was committed yet.
+    pub fn get_cursor(&self) -> u64 {
+        self.head.as_ref().map_or(0, |(_, commit)| commit.sequence)
+    }
+
+    /// Appends a commit recording that `operation` on `path` left the tree with the root `root`,
+    /// and returns its cursor. Nothing is committed if the root did not change.
+    pub async fn commit(
+        &mut self,
+        operation: impl Into<String>,
+        path: Path,
+        root: Cid,
+    ) -> FsResult<u64>
+    where
+        S: Send + Sync,
+    {
+        if let Some((_, head)) = &self.head {
+            if head.root == root {
+                return Ok(head.sequence);
+            }
+        }
+
+        let commit = Commit {
+            sequence: self.get_cursor() + 1,
+            root,
+            previous: self.get_head().copied(),
+            operation: operation.into(),
+            path,
+            committed_at: Utc::now(),
+        };
+        let cid = self.store.put_node(&commit).await?;
+        let sequence = commit.sequence;
+        self.head = Some((cid, commit));
+
+        Ok(sequence)
+    }
+
+    /// Returns the commits after `cursor`, up to `limit` of them, along with the changes they made.
+    ///
+    /// The changes are the [`diff`] between the root at `cursor` and the root of the last commit
+    /// returned, so a client applies them without loading either tree, and an entity changed by
+    /// several commits is only reported once. Pass the returned cursor to get the next changes,
+    /// while [`has_more`][ChangeSet::has_more] says there are any.
+    ///
+    /// Fails with [`FsError::InvalidCursor`] if `cursor` is past the head commit.
+    pub async fn changes_since(&self, cursor: u64, limit: Option<usize>) -> FsResult<ChangeSet>
+    where
+        S: Send + Sync + 'static,
+    {
+        if cursor > self.get_cursor() {
+            return Err(FsError::InvalidCursor(cursor));
+        }
+
+        // Walk back from the head to the commit at the cursor.
+        let mut commits = Vec::new();
+        let mut base = None;
+        let mut next = self.head.as_ref().map(|(_, commit)| commit.clone());
+        while let Some(commit) = next {
+            if commit.sequence <= cursor {
+                base = Some(commit.root);
+                break;
+            }
+
+            next = match &commit.previous {
+                Some(previous) => Some(self.store.get_node(previous).await?),
+                None => None,
+            };
+            commits.push(commit);
+        }
+
+        commits.reverse();
+        let limit = limit.unwrap_or(usize::MAX).max(1);
+        let has_more = commits.len() > limit;
+        commits.truncate(limit);
+
+        let changes = match commits.last() {
+            Some(last) => {
+                let old = match base {
+                    Some(root) => Dir::load(&root, self.store.clone()).await?,
+                    None => Dir::new(self.store.clone()),
+                };
+                let new = Dir::load(&last.root, self.store.clone()).await?;
+                diff(&old, &new).await?
+            }
+            None => Vec::new(),
+        };
+
+        Ok(ChangeSet {
+            cursor: commits.last().map_or(cursor, |commit| commit.sequence),
+            has_more,
+            commits: commits.into_iter().map(CommitSummary::from).collect(),
+            changes,
+        })
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl IpldReferences for Commit {
+    fn references<'a>(&'a self) -> Box<dyn Iterator<Item = &'a Cid> + Send + 'a> {
+        Box::new(std::iter::once(&self.root).chain(self.previous.iter()))
+    }
+}
+
+impl From<Commit> for CommitSummary {
+    fn from(commit: Commit) -> Self {
+        Self {
+            sequence: commit.sequence,
+            root: commit.root,
+            operation: commit.operation,
+            path: commit.path,
+            committed_at: commit.committed_at,
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use zeroutils_store::MemoryStore;
+
+    use crate::filesystem::File;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_change_feed_replays_from_cursor() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let mut feed = ChangeFeed::new(store.clone());
+        let mut root = Dir::new(store.clone());
+
+        for name in ["a", "b", "c"] {
+            let path: Path = name.parse()?;
+            root = root
+                .link_at(&path, File::new(store.clone()).store().await?)
+                .await?;
+            feed.commit("link_at", path, root.store().await?).await?;
+        }
+
+        // Committing the same root again is not a change.
+        assert_eq!(
+            feed.commit("noop", Path::default(), root.store().await?)
+                .await?,
+            3
+        );
+
+        let changes = feed.changes_since(1, None).await?;
+        assert_eq!(changes.cursor, 3);
+        assert!(!changes.has_more);
+        assert_eq!(
+            changes
+                .commits
+                .iter()
+                .map(|commit| commit.sequence)
+                .collect::<Vec<_>>(),
+            [2, 3]
+        );
+        assert_eq!(
+            changes
+                .changes
+                .iter()
+                .map(|change| change.path().to_string())
+                .collect::<Vec<_>>(),
+            ["/b", "/c"]
+        );
+
+        // A client that has seen nothing catches up page by page, from a reloaded feed.
+        let feed = ChangeFeed::load(feed.get_head().unwrap(), store).await?;
+        let page = feed.changes_since(0, Some(2)).await?;
+        assert_eq!(
+            (page.cursor, page.has_more, page.changes.len()),
+            (2, true, 2)
+        );
+        let page = feed.changes_since(page.cursor, Some(2)).await?;
+        assert_eq!(
+            (page.cursor, page.has_more, page.changes.len()),
+            (3, false, 1)
+        );
+        assert!(feed.changes_since(3, None).await?.changes.is_empty());
+
+        assert!(matches!(
+            feed.changes_since(4, None).await,
+            Err(FsError::InvalidCursor(4))
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,140 @@
+use std::{
+    fmt::Debug,
+    future::Future,
+    sync::{Arc, Mutex},
+};
+
+use chrono::{DateTime, Duration, Utc};
+
+//--------------------------------------------------------------------------------------------------
+// Traits
+//--------------------------------------------------------------------------------------------------
+
+/// A source of the current time for the timestamps stored in the file tree.
+///
+/// Entities take their timestamps from the clock of the task they are created in, as set with
+/// [`with_clock`], and from the system clock otherwise. Replicas applying the same operation with a
+/// clock fixed to the time the operation was proposed at produce the same entities, down to their
+/// CIDs.
+pub trait Clock: Debug + Send + Sync {
+    /// Returns the current time.
+    fn now(&self) -> DateTime<Utc>;
+}
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// A [`Clock`] that reads the system time.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+/// A [`Clock`] that only moves when told to.
+#[derive(Debug)]
+pub struct FixedClock {
+    now: Mutex<DateTime<Utc>>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+tokio::task_local! {
+    static CLOCK: Arc<dyn Clock>;
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl FixedClock {
+    /// Creates a clock stopped at `now`.
+    pub fn new(now: DateTime<Utc>) -> Self {
+        Self {
+            now: Mutex::new(now),
+        }
+    }
+
+    /// Moves the clock to `now`.
+    pub fn set(&self, now: DateTime<Utc>) {
+        *self.now.lock().unwrap() = now;
+    }
+
+    /// Moves the clock forward by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        *self.now.lock().unwrap() += duration;
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Runs `future` with the timestamps of the entities it creates taken from `clock`.
+pub async fn with_clock<F>(clock: Arc<dyn Clock>, future: F) -> F::Output
+where
+    F: Future,
+{
+    CLOCK.scope(clock, future).await
+}
+
+/// Returns the current time according to the clock of the current task, or the system time if
+/// there is none.
+pub fn current_time() -> DateTime<Utc> {
+    CLOCK
+        .try_with(|clock| clock.now())
+        .unwrap_or_else(|_| Utc::now())
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+impl Clock for FixedClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.now.lock().unwrap()
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use zeroutils_store::{MemoryStore, Storable};
+
+    use crate::filesystem::{Dir, File};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_entities_take_timestamps_from_task_clock() -> anyhow::Result<()> {
+        let epoch = DateTime::<Utc>::UNIX_EPOCH;
+        let clock = Arc::new(FixedClock::new(epoch));
+
+        let (first, second) = with_clock(clock.clone(), async {
+            let store = MemoryStore::default();
+            (File::new(store.clone()), File::new(store))
+        })
+        .await;
+
+        assert_eq!(first.get_metadata().created_at, epoch);
+        assert_eq!(first.store().await?, second.store().await?);
+
+        clock.advance(Duration::seconds(5));
+        let dir = with_clock(clock, async { Dir::new(MemoryStore::default()) }).await;
+        assert_eq!(dir.get_metadata().modified_at, epoch + Duration::seconds(5));
+
+        // Outside of a clock scope, the system time is used.
+        assert!(File::new(MemoryStore::default()).get_metadata().created_at > epoch);
+
+        Ok(())
+    }
+}
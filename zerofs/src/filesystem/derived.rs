@@ -0,0 +1,355 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Debug,
+    io::Cursor,
+    sync::Arc,
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_with::serde_as;
+use tokio::io::AsyncReadExt;
+use zeroutils_store::{ipld::cid::Cid, IpldStore, Storable};
+
+use super::{current_time, Chunker, Dir, Entity, File, FsError, FsResult, Path, TraceResult};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The well-known path of the file that records the derived blobs, relative to the root.
+pub const DERIVED_PATH: &str = "system/derived";
+
+/// The ID of the transform of the [`Thumbnailer`].
+#[cfg(feature = "thumbnails")]
+pub const THUMBNAIL_TRANSFORM: &str = "thumbnail";
+
+/// The largest width and height of the thumbnails made by the [`Thumbnailer`], in pixels.
+#[cfg(feature = "thumbnails")]
+pub const THUMBNAIL_SIZE: u32 = 256;
+
+//--------------------------------------------------------------------------------------------------
+// Traits
+//--------------------------------------------------------------------------------------------------
+
+/// Derives a blob, like a thumbnail, from the content of a file.
+///
+/// The blobs a transform derives are cached by the CID of their source and the ID of the transform,
+/// so a transform must always derive the same blob from the same content, and should change its ID
+/// when what it derives changes.
+pub trait Transformer: Debug + Send + Sync {
+    /// Returns the ID of the transform.
+    fn id(&self) -> &str;
+
+    /// Returns the media type of the blobs the transform derives.
+    fn content_type(&self) -> &str;
+
+    /// Returns the blob derived from `content`, or `None` if the transform does not apply to it.
+    fn transform(&self, content: &[u8]) -> Option<Vec<u8>>;
+}
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// A [`Transformer`] that scales images down to PNG thumbnails of at most [`THUMBNAIL_SIZE`]
+/// pixels a side, keeping their aspect ratio.
+#[cfg(feature = "thumbnails")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Thumbnailer;
+
+/// The blobs derived from the files of a tree, by the CID of their source and then by the ID of
+/// the transform that derived them.
+///
+/// The index is a TOML document stored at [`DERIVED_PATH`]. Like snapshots, the CIDs are recorded
+/// as text rather than links, so derived blobs do not keep their sources alive, and entries whose
+/// source is no longer in the tree are dropped by [`prune_derived`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DerivedIndex {
+    /// The derived blobs by the CID of their source and then by transform.
+    #[serde(default)]
+    sources: BTreeMap<String, BTreeMap<String, DerivedBlob>>,
+}
+
+/// A blob derived from the content of a file.
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DerivedBlob {
+    /// The CID of the file holding the blob.
+    #[serde_as(as = "serde_with::DisplayFromStr")]
+    pub blob: Cid,
+
+    /// The media type of the blob.
+    pub content_type: String,
+
+    /// The time the blob was derived.
+    pub created_at: DateTime<Utc>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl DerivedIndex {
+    /// Loads the derived index of the tree under `root`.
+    ///
+    /// A tree without a derived index has no derived blobs.
+    pub async fn load<S>(root: &Dir<S>) -> FsResult<Self>
+    where
+        S: IpldStore + Send + Sync,
+    {
+        let path: Path = DERIVED_PATH.parse()?;
+        let file = match root.trace_entity(&path).await? {
+            TraceResult::Found {
+                entity: Entity::File(file),
+                ..
+            } => file,
+            TraceResult::Found { .. } => return Err(FsError::NotAFile(Some(path))),
+            _ => return Ok(Self::default()),
+        };
+
+        let mut content = String::new();
+        file.get_content_reader()
+            .await?
+            .read_to_string(&mut content)
+            .await?;
+
+        toml::from_str(&content).map_err(|e| FsError::InvalidDerivedIndex(e.to_string()))
+    }
+
+    /// Stores the index at [`DERIVED_PATH`] under `root` and returns the updated root.
+    async fn store_at<S>(&self, root: &Dir<S>) -> FsResult<Dir<S>>
+    where
+        S: IpldStore + Send + Sync + 'static,
+    {
+        let content =
+            toml::to_string(self).map_err(|e| FsError::InvalidDerivedIndex(e.to_string()))?;
+
+        let mut file = File::new(root.get_store().clone());
+        file.put_content(Cursor::new(content.into_bytes()), &Chunker::Store)
+            .await?;
+
+        root.link_at(&DERIVED_PATH.parse()?, file.store().await?)
+            .await
+    }
+
+    /// Returns the blob derived from `source` by the transform `transform`.
+    pub fn get_blob(&self, source: &Cid, transform: &str) -> Option<&DerivedBlob> {
+        self.sources
+            .get(&source.to_string())
+            .and_then(|blobs| blobs.get(transform))
+    }
+
+    /// Returns an iterator over the derived blobs.
+    pub fn get_blobs(&self) -> impl Iterator<Item = &DerivedBlob> {
+        self.sources.values().flat_map(BTreeMap::values)
+    }
+
+    /// Returns the number of derived blobs.
+    pub fn len(&self) -> usize {
+        self.sources.values().map(BTreeMap::len).sum()
+    }
+
+    /// Returns `true` if there are no derived blobs.
+    pub fn is_empty(&self) -> bool {
+        self.sources.is_empty()
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Returns the blob `transformer` derives from the file with the CID `source`, along with the root
+/// of the tree under `root` with the blob cached in its [`DerivedIndex`].
+///
+/// A blob derived before is returned from the cache as is. Returns `None` if the transform does not
+/// apply to the file. Fails with [`FsError::NotAFile`] if `source` is not a file.
+pub async fn derive<S>(
+    root: &Dir<S>,
+    source: &Cid,
+    transformer: Arc<dyn Transformer>,
+) -> FsResult<(Dir<S>, Option<DerivedBlob>)>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    let mut index = DerivedIndex::load(root).await?;
+    if let Some(cached) = index.get_blob(source, transformer.id()) {
+        return Ok((root.clone(), Some(cached.clone())));
+    }
+
+    let store = root.get_store().clone();
+    let Entity::File(file) = Entity::load(source, store.clone()).await? else {
+        return Err(FsError::NotAFile(None));
+    };
+
+    let mut content = Vec::new();
+    file.get_content_reader()
+        .await?
+        .read_to_end(&mut content)
+        .await?;
+
+    let derived = {
+        let transformer = transformer.clone();
+        tokio::task::spawn_blocking(move || transformer.transform(&content))
+            .await
+            .unwrap_or_default()
+    };
+
+    let Some(derived) = derived else {
+        return Ok((root.clone(), None));
+    };
+
+    let mut file = File::new(store);
+    file.put_content(Cursor::new(derived), &Chunker::Store)
+        .await?;
+
+    let blob = DerivedBlob {
+        blob: file.store().await?,
+        content_type: transformer.content_type().to_owned(),
+        created_at: current_time(),
+    };
+    index
+        .sources
+        .entry(source.to_string())
+        .or_default()
+        .insert(transformer.id().to_owned(), blob.clone());
+
+    Ok((index.store_at(root).await?, Some(blob)))
+}
+
+/// Drops the derived blobs whose source is no longer in the tree under `root`, and returns the
+/// updated root along with the number of blobs dropped.
+///
+/// A source is in the tree if an entity of the tree, or the content of a file, has its CID.
+pub async fn prune_derived<S>(root: &Dir<S>) -> FsResult<(Dir<S>, usize)>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    let mut index = DerivedIndex::load(root).await?;
+    if index.is_empty() {
+        return Ok((root.clone(), 0));
+    }
+
+    let mut reachable = BTreeSet::new();
+    let mut pending = vec![root.clone()];
+    while let Some(dir) = pending.pop() {
+        for (name, link) in dir.get_entries() {
+            reachable.insert(link.get_cid().to_string());
+            match dir.get_entity(name).await? {
+                Some(Entity::Dir(child)) => pending.push(child.clone()),
+                Some(Entity::File(file)) => {
+                    reachable.extend(file.get_content().map(ToString::to_string));
+                }
+                _ => (),
+            }
+        }
+    }
+
+    let before = index.len();
+    index.sources.retain(|source, _| reachable.contains(source));
+    let pruned = before - index.len();
+    if pruned == 0 {
+        return Ok((root.clone(), 0));
+    }
+
+    Ok((index.store_at(root).await?, pruned))
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(feature = "thumbnails")]
+impl Transformer for Thumbnailer {
+    fn id(&self) -> &str {
+        THUMBNAIL_TRANSFORM
+    }
+
+    fn content_type(&self) -> &str {
+        "image/png"
+    }
+
+    fn transform(&self, content: &[u8]) -> Option<Vec<u8>> {
+        let image = image::load_from_memory(content).ok()?;
+        let thumbnail = image.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE);
+
+        let mut png = Cursor::new(Vec::new());
+        thumbnail.write_to(&mut png, image::ImageFormat::Png).ok()?;
+
+        Some(png.into_inner())
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use zeroutils_store::MemoryStore;
+
+    use super::*;
+
+    /// Derives the uppercase of text, counting how many times it ran.
+    #[derive(Debug, Default)]
+    struct Uppercase {
+        runs: AtomicUsize,
+    }
+
+    impl Transformer for Uppercase {
+        fn id(&self) -> &str {
+            "uppercase"
+        }
+
+        fn content_type(&self) -> &str {
+            "text/plain"
+        }
+
+        fn transform(&self, content: &[u8]) -> Option<Vec<u8>> {
+            self.runs.fetch_add(1, Ordering::SeqCst);
+            let text = std::str::from_utf8(content).ok()?;
+            Some(text.to_uppercase().into_bytes())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_derived_blobs_are_cached_and_pruned() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let mut file = File::new(store.clone());
+        file.put_content(&b"hello"[..], &Chunker::Store).await?;
+        let source = file.store().await?;
+        let root = Dir::new(store.clone())
+            .link_at(&"greeting".parse()?, source)
+            .await?;
+
+        let uppercase = Arc::new(Uppercase::default());
+        let (root, blob) = derive(&root, &source, uppercase.clone()).await?;
+        let blob = blob.unwrap();
+        let (root, cached) = derive(&root, &source, uppercase.clone()).await?;
+        assert_eq!(cached, Some(blob.clone()));
+        assert_eq!(uppercase.runs.load(Ordering::SeqCst), 1);
+
+        let mut content = String::new();
+        File::load(&blob.blob, store)
+            .await?
+            .get_content_reader()
+            .await?
+            .read_to_string(&mut content)
+            .await?;
+        assert_eq!(content, "HELLO");
+
+        // Nothing is pruned while the source is in the tree.
+        let (root, pruned) = prune_derived(&root).await?;
+        assert_eq!(pruned, 0);
+
+        let (root, _) = root.unlink_at(&"greeting".parse()?).await?;
+        let (root, pruned) = prune_derived(&root).await?;
+        assert_eq!(pruned, 1);
+        assert!(DerivedIndex::load(&root).await?.is_empty());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,347 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+use serde_with::serde_as;
+use zeroutils_store::{ipld::cid::Cid, IpldStore};
+
+use super::{Dir, Entity, FsResult, Path};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// A difference between two file trees found by [`diff`].
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum Change {
+    /// An entity was added at `path`.
+    Added {
+        /// The path of the entity in the new tree.
+        #[serde_as(as = "serde_with::DisplayFromStr")]
+        path: Path,
+
+        /// The CID of the entity.
+        #[serde_as(as = "serde_with::DisplayFromStr")]
+        cid: Cid,
+    },
+
+    /// The entity at `path` was removed.
+    Removed {
+        /// The path of the entity in the old tree.
+        #[serde_as(as = "serde_with::DisplayFromStr")]
+        path: Path,
+    },
+
+    /// The entity at `path` was replaced.
+    Modified {
+        /// The path of the entity in both trees.
+        #[serde_as(as = "serde_with::DisplayFromStr")]
+        path: Path,
+
+        /// The CID of the entity in the new tree.
+        #[serde_as(as = "serde_with::DisplayFromStr")]
+        cid: Cid,
+    },
+
+    /// An entity was moved from `from` to `to` without its content changing.
+    Renamed {
+        /// The path of the entity in the old tree.
+        #[serde_as(as = "serde_with::DisplayFromStr")]
+        from: Path,
+
+        /// The path of the entity in the new tree.
+        #[serde_as(as = "serde_with::DisplayFromStr")]
+        to: Path,
+
+        /// The CID of the entity in the new tree.
+        #[serde_as(as = "serde_with::DisplayFromStr")]
+        cid: Cid,
+    },
+}
+
+/// An entity found on only one side of a diff.
+struct Unmatched<S>
+where
+    S: IpldStore,
+{
+    path: Path,
+    cid: Cid,
+    entity: Option<Entity<S>>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl Change {
+    /// Returns the path the change applies to in the new tree, or in the old tree for a removal.
+    pub fn path(&self) -> &Path {
+        match self {
+            Change::Added { path, .. }
+            | Change::Removed { path }
+            | Change::Modified { path, .. } => path,
+            Change::Renamed { to, .. } => to,
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Returns the changes that turn the tree under `old` into the tree under `new`, sorted by path.
+///
+/// Subtrees with the same CID on both sides are skipped without being loaded, so the cost of a diff
+/// grows with what changed rather than with the size of the trees. A directory added or removed as
+/// a whole is reported as a single change rather than one per entity under it.
+///
+/// An entity removed from one path and added at another with the same content is reported as
+/// [`Change::Renamed`], so a receiver that already has the content only needs to relink it. Files
+/// are matched by the CID of their content, so a file moved with new metadata is still a rename, and
+/// directories and symlinks by their own CID. Empty files are never matched. Entities moved into a
+/// new directory are found under it and reported along with the addition of the directory, but
+/// entities moved out of a removed directory are not looked for.
+pub async fn diff<S>(old: &Dir<S>, new: &Dir<S>) -> FsResult<Vec<Change>>
+where
+    S: IpldStore + Send + Sync,
+{
+    let mut changes = Vec::new();
+    let mut removed = Vec::new();
+    let mut added = Vec::new();
+
+    let mut pending = vec![(Path::default(), old.clone(), new.clone())];
+    while let Some((path, old, new)) = pending.pop() {
+        for (name, link) in old.get_entries() {
+            let mut entity_path = path.clone();
+            entity_path.push(name.clone());
+
+            let Some(new_link) = new.get(name) else {
+                removed.push(Unmatched {
+                    path: entity_path,
+                    cid: *link.get_cid(),
+                    entity: old.get_entity(name).await?.cloned(),
+                });
+                continue;
+            };
+
+            if new_link.get_cid() == link.get_cid() {
+                continue;
+            }
+
+            match (old.get_entity(name).await?, new.get_entity(name).await?) {
+                (Some(Entity::Dir(old_child)), Some(Entity::Dir(new_child))) => {
+                    pending.push((entity_path, old_child.clone(), new_child.clone()));
+                }
+                _ => changes.push(Change::Modified {
+                    path: entity_path,
+                    cid: *new_link.get_cid(),
+                }),
+            }
+        }
+
+        for (name, link) in new.get_entries() {
+            if old.get(name).is_some() {
+                continue;
+            }
+
+            let mut entity_path = path.clone();
+            entity_path.push(name.clone());
+
+            added.push(Unmatched {
+                path: entity_path,
+                cid: *link.get_cid(),
+                entity: new.get_entity(name).await?.cloned(),
+            });
+        }
+    }
+
+    // Pair up removed and added entities with the same content, in path order so the pairing does
+    // not depend on the order the entries were walked in.
+    removed.sort_by(|a, b| a.path.cmp(&b.path));
+    added.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let mut sources = HashMap::<Cid, Vec<Path>>::new();
+    for unmatched in removed.iter().rev() {
+        if let Some(key) = get_match_key(&unmatched.entity, &unmatched.cid) {
+            sources.entry(key).or_default().push(unmatched.path.clone());
+        }
+    }
+
+    // Entities moved into a new directory are looked for under it, while anything is left to match.
+    let mut new_dirs = Vec::new();
+    for unmatched in added {
+        match take_source(&mut sources, &unmatched.entity, &unmatched.cid) {
+            Some(from) => changes.push(Change::Renamed {
+                from,
+                to: unmatched.path,
+                cid: unmatched.cid,
+            }),
+            None => {
+                if let Some(Entity::Dir(dir)) = unmatched.entity {
+                    new_dirs.push((unmatched.path.clone(), dir));
+                }
+
+                changes.push(Change::Added {
+                    path: unmatched.path,
+                    cid: unmatched.cid,
+                });
+            }
+        }
+    }
+
+    while let Some((path, dir)) = new_dirs.pop() {
+        if sources.values().all(Vec::is_empty) {
+            break;
+        }
+
+        for (name, link) in dir.get_entries() {
+            let mut entity_path = path.clone();
+            entity_path.push(name.clone());
+
+            let entity = dir.get_entity(name).await?.cloned();
+            match take_source(&mut sources, &entity, link.get_cid()) {
+                Some(from) => changes.push(Change::Renamed {
+                    from,
+                    to: entity_path,
+                    cid: *link.get_cid(),
+                }),
+                None => {
+                    if let Some(Entity::Dir(child)) = entity {
+                        new_dirs.push((entity_path, child));
+                    }
+                }
+            }
+        }
+    }
+
+    let renamed = changes
+        .iter()
+        .filter_map(|change| match change {
+            Change::Renamed { from, .. } => Some(from.clone()),
+            _ => None,
+        })
+        .collect::<HashSet<_>>();
+
+    changes.extend(
+        removed
+            .into_iter()
+            .filter(|unmatched| !renamed.contains(&unmatched.path))
+            .map(|unmatched| Change::Removed {
+                path: unmatched.path,
+            }),
+    );
+
+    changes.sort_by(|a, b| a.path().cmp(b.path()));
+
+    Ok(changes)
+}
+
+/// Returns the key renames are matched on: the content of a file, or the CID of any other entity.
+fn get_match_key<S>(entity: &Option<Entity<S>>, cid: &Cid) -> Option<Cid>
+where
+    S: IpldStore,
+{
+    match entity {
+        Some(Entity::File(file)) => file.get_content().copied(),
+        Some(_) => Some(*cid),
+        None => None,
+    }
+}
+
+/// Takes the path of a removed entity `entity` can be matched with, if there is one left.
+fn take_source<S>(
+    sources: &mut HashMap<Cid, Vec<Path>>,
+    entity: &Option<Entity<S>>,
+    cid: &Cid,
+) -> Option<Path>
+where
+    S: IpldStore,
+{
+    let key = get_match_key(entity, cid)?;
+    sources.get_mut(&key)?.pop()
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use zeroutils_store::{MemoryStore, Storable};
+
+    use crate::filesystem::{Chunker, File};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_diff_detects_renames() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let file_with = |content: &'static [u8]| {
+            let store = store.clone();
+            async move {
+                let mut file = File::new(store);
+                file.put_content(content, &Chunker::Store).await?;
+                anyhow::Ok(file.store().await?)
+            }
+        };
+
+        let photos = Dir::new(store.clone())
+            .link_at(&"cat".parse()?, file_with(b"meow").await?)
+            .await?
+            .store()
+            .await?;
+
+        let old = Dir::new(store.clone())
+            .link_at(&"docs/readme".parse()?, file_with(b"hello").await?)
+            .await?
+            .link_at(&"docs/notes".parse()?, file_with(b"notes").await?)
+            .await?
+            .link_at(&"photos".parse()?, photos)
+            .await?
+            .link_at(&"stale".parse()?, file_with(b"stale").await?)
+            .await?;
+
+        // The readme is recreated with new metadata but the same content elsewhere, and the
+        // photos are moved as a whole.
+        let (new, _) = old.unlink_at(&"docs/readme".parse()?).await?;
+        let (new, _) = new.unlink_at(&"photos".parse()?).await?;
+        let (new, _) = new.unlink_at(&"stale".parse()?).await?;
+        let new = new
+            .link_at(&"readme".parse()?, file_with(b"hello").await?)
+            .await?
+            .link_at(&"archive/photos".parse()?, photos)
+            .await?
+            .link_at(&"docs/notes".parse()?, file_with(b"more notes").await?)
+            .await?
+            .link_at(&"todo".parse()?, file_with(b"todo").await?)
+            .await?;
+
+        let changes = diff(&old, &new).await?;
+        let summary = changes
+            .iter()
+            .map(|change| match change {
+                Change::Added { path, .. } => format!("+{path}"),
+                Change::Removed { path } => format!("-{path}"),
+                Change::Modified { path, .. } => format!("~{path}"),
+                Change::Renamed { from, to, .. } => format!("{from}>{to}"),
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            summary,
+            [
+                "+/archive",
+                "/photos>/archive/photos",
+                "~/docs/notes",
+                "/docs/readme>/readme",
+                "-/stale",
+                "+/todo",
+            ]
+        );
+
+        assert!(diff(&new, &new).await?.is_empty());
+
+        Ok(())
+    }
+}
@@ -1,21 +1,29 @@
 use std::{
-    collections::{BTreeMap, HashMap},
+    collections::BTreeMap,
     convert::{TryFrom, TryInto},
     fmt::{self, Debug},
-    sync::{Arc, Mutex},
+    iter::FromIterator,
+    ops::Bound,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
 };
 
+use chrono::{DateTime, Utc};
+use futures::future::{BoxFuture, FutureExt};
 use serde::{
     de::{self, DeserializeSeed},
     Deserialize, Deserializer, Serialize, Serializer,
 };
+use tokio::sync::watch;
 use zeroutils_store::{
     ipld::cid::Cid, IpldReferences, IpldStore, Storable, StoreError, StoreResult,
 };
 
 use crate::filesystem::{
-    DescriptorFlags, Entity, EntityCidLink, EntityType, File, FsError, FsResult, Handle, Link,
-    MemoryBufferStore, Metadata, Path, PathDirs, PathSegment, Resolvable,
+    current_time, Acl, DescriptorFlags, Entity, EntityCidLink, EntityType, File, FsError, FsResult,
+    Handle, KeyGrants, Link, MemoryBufferStore, Metadata, Path, PathDirs, PathSegment, Resolvable,
 };
 
 //--------------------------------------------------------------------------------------------------
@@ -50,8 +58,25 @@ where
     /// The store used to persist blocks in the directory.
     pub(crate) store: S,
 
-    /// The entries in the directory.
-    pub(crate) entries: HashMap<PathSegment, EntityCidLink<S>>,
+    /// The entries in the directory, in the order of their names.
+    pub(crate) entries: BTreeMap<PathSegment, EntityCidLink<S>>,
+
+    /// Hints about the entries in the directory, so their type can be known without loading them.
+    pub(crate) hints: BTreeMap<PathSegment, EntryHint>,
+}
+
+/// What is known about a directory entry without loading it.
+///
+/// Hints are stored next to the entry's [`Cid`] and kept up to date by [`Dir::put_with_hint`]. An
+/// entry put without a hint has none, so a hint is never stale.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EntryHint {
+    /// The type of the entity.
+    pub entity_type: EntityType,
+
+    /// The size of the file content in bytes, if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
 }
 
 /// Used to represent the root directory of the file system.
@@ -60,6 +85,9 @@ where
 /// reference to the root directory of the file system. This is why the root directory is implemented
 /// as an `Arc` and `Mutex`.
 ///
+/// The root directory can be switched to read-only, which applies to every handle opened from it:
+/// operations that would change the file tree then fail with [`FsError::ReadOnlyFilesystem`].
+///
 // TODO: Should probably consider actor-style model with channels.
 #[derive(Debug, Clone)]
 pub struct RootDir<S>
@@ -67,6 +95,13 @@ where
     S: IpldStore,
 {
     inner: Arc<Mutex<Dir<S>>>,
+
+    /// The [`Cid`] of the current root directory, once it is known, which is sent to the
+    /// receivers returned by [`subscribe`][RootDir::subscribe] whenever the root changes.
+    cid: Arc<watch::Sender<Option<Cid>>>,
+
+    /// Whether the file tree is read-only.
+    read_only: Arc<AtomicBool>,
 }
 
 /// A handle for an open directory.
@@ -116,6 +151,8 @@ where
 pub(crate) struct DirSerializable {
     metadata: Metadata,
     entries: BTreeMap<String, Cid>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    hints: BTreeMap<String, EntryHint>,
 }
 
 pub(crate) struct DirDeserializeSeed<S> {
@@ -134,9 +171,37 @@ where
     pub fn new(store: S) -> Self {
         Self {
             inner: Arc::new(Mutex::new(Dir::new(store))),
+            cid: Arc::new(watch::Sender::new(None)),
+            read_only: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Creates a root directory starting out as `dir`.
+    pub fn from_dir(dir: Dir<S>) -> Self {
+        Self::from_dir_sharing_read_only(dir, Arc::new(AtomicBool::new(false)))
+    }
+
+    /// Creates a root directory starting out as `dir` that is read-only whenever `read_only` is
+    /// set, so that whoever else holds it makes the file tree read-only or writable again for every
+    /// handle opened from this root.
+    pub fn from_dir_sharing_read_only(dir: Dir<S>, read_only: Arc<AtomicBool>) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(dir)),
+            cid: Arc::new(watch::Sender::new(None)),
+            read_only,
         }
     }
 
+    /// Makes the file tree read-only, or writable again, for every handle opened from this root.
+    pub fn set_read_only(&self, read_only: bool) {
+        self.read_only.store(read_only, Ordering::SeqCst);
+    }
+
+    /// Returns `true` if the file tree is read-only.
+    pub fn is_read_only(&self) -> bool {
+        self.read_only.load(Ordering::SeqCst)
+    }
+
     /// Forks the root directory by creating a clone of it with an ephemeral buffer store.
     pub fn fork(&self) -> Dir<MemoryBufferStore<S>>
     where
@@ -147,6 +212,131 @@ where
         dir.use_store(buffer_store)
     }
 
+    /// Returns a clone of the store backing the root directory.
+    pub fn get_store(&self) -> S {
+        self.inner.lock().unwrap().get_store().clone()
+    }
+
+    /// Returns the current root directory along with its [`Cid`].
+    ///
+    /// The directory is stored the first time its [`Cid`] is asked for, and the [`Cid`] is kept
+    /// until the root changes.
+    pub async fn load(&self) -> FsResult<(Dir<S>, Cid)>
+    where
+        S: Send + Sync,
+    {
+        let dir = {
+            let current = self.inner.lock().unwrap();
+            if let Some(cid) = *self.cid.borrow() {
+                return Ok((current.clone(), cid));
+            }
+
+            current.clone()
+        };
+
+        let cid = dir.store().await?;
+        let current = self.inner.lock().unwrap();
+        if Arc::ptr_eq(&current.inner, &dir.inner) {
+            self.cid.send_replace(Some(cid));
+        }
+
+        Ok((dir, cid))
+    }
+
+    /// Replaces the root directory with `dir` if the current one has the [`Cid`] `expected`, and
+    /// returns the [`Cid`] of `dir`.
+    ///
+    /// Every change to the root goes through here, so a change made on top of a root that was
+    /// [loaded][Self::load] never overwrites one made since. Fails with [`FsError::RootChanged`]
+    /// if the root is not `expected` anymore, in which case the change should be made again on
+    /// top of the new root. The receivers returned by [`subscribe`][Self::subscribe] are sent the
+    /// new [`Cid`].
+    pub async fn compare_and_swap(&self, expected: &Cid, dir: Dir<S>) -> FsResult<Cid>
+    where
+        S: Send + Sync,
+    {
+        let cid = dir.store().await?;
+        loop {
+            let (current, current_cid) = self.load().await?;
+            if current_cid != *expected {
+                return Err(FsError::RootChanged(current_cid));
+            }
+
+            // The root may have changed since it was loaded, in which case it is checked again.
+            let mut inner = self.inner.lock().unwrap();
+            if Arc::ptr_eq(&inner.inner, &current.inner) {
+                *inner = dir;
+                self.cid.send_replace(Some(cid));
+                return Ok(cid);
+            }
+        }
+    }
+
+    /// Returns a receiver of the [`Cid`] of the root directory, which sees every new root swapped
+    /// in with [`compare_and_swap`][Self::compare_and_swap].
+    ///
+    /// The value is `None` until the [`Cid`] of the root is first known. A receiver that falls
+    /// behind only sees the latest root, skipping the ones in between.
+    pub fn subscribe(&self) -> watch::Receiver<Option<Cid>> {
+        self.cid.subscribe()
+    }
+
+    /// Links the entity with the given [`Cid`] at `path` under the root directory, creating any
+    /// missing intermediate directories.
+    ///
+    /// If the root directory is changed while the link is made, the link is made again on top of
+    /// the change rather than overwriting it.
+    pub async fn link_at(&self, path: &Path, cid: Cid) -> FsResult<()>
+    where
+        S: Send + Sync + 'static,
+    {
+        loop {
+            let (dir, root_cid) = self.load().await?;
+            let linked = dir.link_at(path, cid).await?;
+            match self.compare_and_swap(&root_cid, linked).await {
+                Ok(_) => return Ok(()),
+                Err(FsError::RootChanged(_)) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Unlinks the entity at `path` under the root directory and returns its [`Cid`].
+    ///
+    /// Like [`link_at`][Self::link_at], the unlink is made again on top of any change made to the
+    /// root directory meanwhile.
+    pub async fn unlink_at(&self, path: &Path) -> FsResult<Cid>
+    where
+        S: Send + Sync + 'static,
+    {
+        loop {
+            let (dir, root_cid) = self.load().await?;
+            let (unlinked, cid) = dir.unlink_at(path).await?;
+            match self.compare_and_swap(&root_cid, unlinked).await {
+                Ok(_) => return Ok(cid),
+                Err(FsError::RootChanged(_)) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Returns the entity at `path` under the root directory, or the root directory itself if
+    /// `path` is empty.
+    pub async fn get_entity_at(&self, path: &Path) -> FsResult<Entity<S>>
+    where
+        S: Send + Sync,
+    {
+        let (dir, _) = self.load().await?;
+        if path.is_empty() {
+            return Ok(Entity::Dir(dir));
+        }
+
+        match dir.trace_entity(path).await? {
+            TraceResult::Found { entity, .. } => Ok(entity),
+            _ => Err(FsError::NotFound(path.clone())),
+        }
+    }
+
     /// Creates a handle to the root directory with the given flags.
     pub fn make_handle(&self, flags: DescriptorFlags) -> DirHandle<S, MemoryBufferStore<S>>
     where
@@ -165,13 +355,16 @@ where
         Self {
             inner: Arc::new(DirInner {
                 metadata: Metadata::new(EntityType::Dir),
-                entries: HashMap::new(),
+                entries: BTreeMap::new(),
+                hints: BTreeMap::new(),
                 store,
             }),
         }
     }
 
     /// Adds a [`Cid`] (to an entity) and its associated name in the directory's entries.
+    ///
+    /// Any hint previously stored for the name is removed.
     pub fn put(
         &mut self,
         name: impl TryInto<PathSegment, Error: Into<FsError>>,
@@ -179,10 +372,104 @@ where
     ) -> FsResult<()> {
         let name = name.try_into().map_err(Into::into)?;
         let inner = Arc::make_mut(&mut self.inner);
+        inner.hints.remove(&name);
         inner.entries.insert(name, EntityCidLink::from(cid));
         Ok(())
     }
 
+    /// Adds a [`Cid`] (to an entity) and its associated name in the directory's entries along with
+    /// a hint about the entity.
+    pub fn put_with_hint(
+        &mut self,
+        name: impl TryInto<PathSegment, Error: Into<FsError>>,
+        cid: Cid,
+        hint: EntryHint,
+    ) -> FsResult<()> {
+        let name = name.try_into().map_err(Into::into)?;
+        let inner = Arc::make_mut(&mut self.inner);
+        inner.hints.insert(name.clone(), hint);
+        inner.entries.insert(name, EntityCidLink::from(cid));
+        Ok(())
+    }
+
+    /// Removes the entry with the given name from the directory's entries, returning its
+    /// [`EntityCidLink`] if it existed.
+    pub fn remove(&mut self, name: &PathSegment) -> Option<EntityCidLink<S>> {
+        let inner = Arc::make_mut(&mut self.inner);
+        inner.hints.remove(name);
+        inner.entries.remove(name)
+    }
+
+    /// Returns a copy of the directory with `name` linked to `cid`, along with the [`Cid`] the
+    /// copy is stored under.
+    ///
+    /// The directory itself is left as it is: directories are copy-on-write, so the copy shares
+    /// its entries with the original until it is changed, and anyone holding the original keeps
+    /// seeing the entries it had. As with [`put`][Self::put], any hint stored for the name is
+    /// removed.
+    pub async fn with_entry(
+        &self,
+        name: impl TryInto<PathSegment, Error: Into<FsError>>,
+        cid: Cid,
+    ) -> FsResult<(Dir<S>, Cid)>
+    where
+        S: Send + Sync,
+    {
+        let mut dir = self.clone();
+        dir.put(name, cid)?;
+        let cid = dir.store().await?;
+        Ok((dir, cid))
+    }
+
+    /// Returns a copy of the directory without the entry named `name`, along with the [`Cid`] the
+    /// copy is stored under.
+    ///
+    /// Like [`with_entry`][Self::with_entry], the directory itself is left as it is. Fails with
+    /// [`FsError::NotFound`] if there is no entry named `name`.
+    pub async fn without_entry(&self, name: &PathSegment) -> FsResult<(Dir<S>, Cid)>
+    where
+        S: Send + Sync,
+    {
+        let mut dir = self.clone();
+        if dir.remove(name).is_none() {
+            return Err(FsError::NotFound(Path::from_iter([name.clone()])));
+        }
+
+        let cid = dir.store().await?;
+        Ok((dir, cid))
+    }
+
+    /// Returns the name the entry matching `name` is stored under, with the case it was given.
+    ///
+    /// Names are compared case-insensitively, so this can differ from `name` in case. The
+    /// [canonical][PathSegment::canonicalize] form of either is the key the entry is looked up by.
+    pub fn get_name(&self, name: &PathSegment) -> Option<&PathSegment> {
+        self.inner
+            .entries
+            .get_key_value(name)
+            .map(|(stored, _)| stored)
+    }
+
+    /// Gets the hint stored for the entry with the given name.
+    pub fn get_hint(&self, name: &PathSegment) -> Option<&EntryHint> {
+        self.inner.hints.get(name)
+    }
+
+    /// Gets the type of the entry with the given name, loading the entry only if it has no hint.
+    pub async fn get_entity_type(&self, name: &PathSegment) -> FsResult<Option<EntityType>>
+    where
+        S: Send + Sync,
+    {
+        if let Some(hint) = self.get_hint(name) {
+            return Ok(Some(hint.entity_type));
+        }
+
+        Ok(self
+            .get_entity(name)
+            .await?
+            .map(|entity| entity.get_metadata().entity_type))
+    }
+
     /// Gets the [`EntityCidLink`] with the given name from the directory's entries.
     pub fn get(&self, name: &PathSegment) -> Option<&EntityCidLink<S>> {
         self.inner.entries.get(name)
@@ -193,16 +480,70 @@ where
         &self.inner.metadata
     }
 
-    /// Returns an iterator over the entries in the directory.
+    /// Sets the access control list of the directory.
+    pub fn set_acl(&mut self, acl: Acl) {
+        let inner = Arc::make_mut(&mut self.inner);
+        inner.metadata.acl = acl;
+        inner.metadata.mark_changed();
+    }
+
+    /// Sets the grants of the content key of the directory.
+    pub fn set_key_grants(&mut self, grants: KeyGrants) {
+        let inner = Arc::make_mut(&mut self.inner);
+        inner.metadata.grants = grants;
+        inner.metadata.mark_changed();
+    }
+
+    /// Sets the number of times the content key of the directory was rotated.
+    pub fn set_key_epoch(&mut self, epoch: u32) {
+        let inner = Arc::make_mut(&mut self.inner);
+        inner.metadata.key_epoch = epoch;
+        inner.metadata.mark_changed();
+    }
+
+    /// Sets the access and modification times of the directory, see [`Metadata::set_times`].
+    pub fn set_times(
+        &mut self,
+        accessed_at: Option<DateTime<Utc>>,
+        modified_at: Option<DateTime<Utc>>,
+    ) {
+        let inner = Arc::make_mut(&mut self.inner);
+        inner.metadata.set_times(accessed_at, modified_at);
+    }
+
+    /// Returns an iterator over the entries in the directory, in the order of their names.
     pub fn get_entries(&self) -> impl Iterator<Item = (&PathSegment, &EntityCidLink<S>)> {
         self.inner.entries.iter()
     }
 
+    /// Returns an iterator over the entries in the directory whose names come after `after`, in
+    /// the order of their names, or over all of them if `after` is `None`.
+    ///
+    /// This pages through a listing: the name of the last entry of a page is the cursor for the
+    /// next one. Since the order does not depend on when entries were added, a cursor picks up
+    /// where the previous page left off even if entries were added or removed in between.
+    pub fn get_entries_after(
+        &self,
+        after: Option<&PathSegment>,
+    ) -> impl Iterator<Item = (&PathSegment, &EntityCidLink<S>)> {
+        let start = match after {
+            Some(name) => Bound::Excluded(name),
+            None => Bound::Unbounded,
+        };
+
+        self.inner.entries.range((start, Bound::Unbounded))
+    }
+
     /// Returns the store used to persist the file.
     pub fn get_store(&self) -> &S {
         &self.inner.store
     }
 
+    /// Returns the number of entries in the directory.
+    pub fn len(&self) -> usize {
+        self.inner.entries.len()
+    }
+
     /// Returns `true` if the directory is empty.
     pub fn is_empty(&self) -> bool {
         self.inner.entries.is_empty()
@@ -219,6 +560,28 @@ where
         }
     }
 
+    /// Gets the entity with the provided name like [`get_entity`][Self::get_entity], but fails with
+    /// [`FsError::NotReplicatedLocally`] if its block is not in the store, as in the parts of the
+    /// tree a node with a [`Subscription`][crate::filesystem::Subscription] does not replicate.
+    pub(crate) async fn get_replicated_entity(
+        &self,
+        name: &PathSegment,
+        path: impl FnOnce() -> Path,
+    ) -> FsResult<Option<&Entity<S>>>
+    where
+        S: Send + Sync,
+    {
+        match self.get_entity(name).await {
+            Err(FsError::IpldStore(e)) => match self.get(name) {
+                Some(link) if !self.inner.store.has(link.get_cid()).await => {
+                    Err(FsError::NotReplicatedLocally(path()))
+                }
+                _ => Err(FsError::IpldStore(e)),
+            },
+            result => result,
+        }
+    }
+
     /// Traces a given path to locate the target entity.
     ///
     /// This function navigates through the directory structure specified by `path`,
@@ -226,6 +589,8 @@ where
     ///
     /// ## Errors
     /// - `FsError::SymLinkNotSupportedYet`: Encountered a symbolic link, which is not supported.
+    /// - `FsError::NotReplicatedLocally`: The path leads into a part of the tree whose blocks are
+    ///   not in the store.
     pub(crate) async fn trace_entity(&self, path: &Path) -> FsResult<TraceResult<S>>
     where
         S: Send + Sync,
@@ -235,7 +600,10 @@ where
 
         // First look up the intermediate directories except the last one.
         for (depth, segment) in path.slice(..path.len() - 1).iter().enumerate() {
-            match dir.get_entity(segment).await? {
+            let entity = dir
+                .get_replicated_entity(segment, || path.slice(..depth + 1).to_owned())
+                .await?;
+            match entity {
                 Some(Entity::Dir(d)) => dir = d,
                 Some(Entity::Symlink(_)) => {
                     return Err(FsError::SymLinkNotSupportedYet(
@@ -255,7 +623,7 @@ where
 
         // Then look up the last entity in the path.
         if let Some(segment) = path.last() {
-            return match dir.get_entity(segment).await? {
+            return match dir.get_replicated_entity(segment, || path.clone()).await? {
                 Some(entity) => Ok(TraceResult::Found {
                     entity: entity.clone(),
                     name: Some(segment.clone()),
@@ -321,6 +689,109 @@ where
         }
     }
 
+    /// Links the entity with the given [`Cid`] at `path`, creating any missing intermediate
+    /// directories, and returns the updated directory.
+    ///
+    /// The intermediate directories along the path are rewritten and stored so that each parent
+    /// references the new [`Cid`] of its child. The directory itself is not stored.
+    ///
+    /// Like in POSIX, a directory is modified when entries are added to or removed from it, not when
+    /// an entry is replaced with a new version of itself. So the directory the entity is linked into
+    /// has its modification time set to the [`current_time`] only if nothing was at `path`, and
+    /// the ones above it keep theirs.
+    pub fn link_at<'a>(&'a self, path: &'a Path, cid: Cid) -> BoxFuture<'a, FsResult<Dir<S>>>
+    where
+        S: Send + Sync + 'static,
+    {
+        async move {
+            let (first, rest) = match path.get_segments().split_first() {
+                Some(split) => split,
+                None => return Err(FsError::EmptyPath),
+            };
+
+            let mut dir = self.clone();
+            if rest.is_empty() {
+                if dir.get(first).is_none() {
+                    dir.set_times(None, Some(current_time()));
+                }
+
+                dir.put(first.clone(), cid)?;
+                return Ok(dir);
+            }
+
+            let hint = EntryHint {
+                entity_type: EntityType::Dir,
+                size: None,
+            };
+
+            let child = match self.get_entity(first).await? {
+                Some(Entity::Dir(child)) => child.clone(),
+                Some(_) => {
+                    return Err(FsError::NotADirectory(Some(Path::from_iter([
+                        first.clone()
+                    ]))))
+                }
+                None => Dir::new(self.inner.store.clone()),
+            };
+
+            let rest = rest.iter().cloned().collect::<Path>();
+            let child = child.link_at(&rest, cid).await?;
+            dir.put_with_hint(first.clone(), child.store().await?, hint)?;
+
+            Ok(dir)
+        }
+        .boxed()
+    }
+
+    /// Unlinks the entity at `path` and returns the updated directory along with the [`Cid`] of
+    /// the unlinked entity.
+    ///
+    /// Like [`link_at`][Self::link_at], the intermediate directories along the path are rewritten
+    /// and stored, and the directory the entity is unlinked from has its modification time set to
+    /// the [`current_time`]. The directory itself is not stored.
+    pub fn unlink_at<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, FsResult<(Dir<S>, Cid)>>
+    where
+        S: Send + Sync + 'static,
+    {
+        async move {
+            let (first, rest) = match path.get_segments().split_first() {
+                Some(split) => split,
+                None => return Err(FsError::EmptyPath),
+            };
+
+            let mut dir = self.clone();
+            if rest.is_empty() {
+                let link = dir
+                    .remove(first)
+                    .ok_or_else(|| FsError::NotFound(path.clone()))?;
+                dir.set_times(None, Some(current_time()));
+                return Ok((dir, *link.get_cid()));
+            }
+
+            let hint = EntryHint {
+                entity_type: EntityType::Dir,
+                size: None,
+            };
+
+            let child = match self.get_entity(first).await? {
+                Some(Entity::Dir(child)) => child.clone(),
+                Some(_) => {
+                    return Err(FsError::NotADirectory(Some(Path::from_iter([
+                        first.clone()
+                    ]))))
+                }
+                None => return Err(FsError::NotFound(path.clone())),
+            };
+
+            let rest = rest.iter().cloned().collect::<Path>();
+            let (child, cid) = child.unlink_at(&rest).await?;
+            dir.put_with_hint(first.clone(), child.store().await?, hint)?;
+
+            Ok((dir, cid))
+        }
+        .boxed()
+    }
+
     /// Change the store used to persist the directory.
     pub fn use_store<T>(self, store: T) -> Dir<T>
     where
@@ -339,6 +810,7 @@ where
                     .into_iter()
                     .map(|(k, v)| (k, v.use_store(&store)))
                     .collect(),
+                hints: inner.hints,
                 store,
             }),
         }
@@ -356,17 +828,26 @@ where
 
     /// Tries to create a new `Dir` from a serializable representation.
     pub(crate) fn try_from_serializable(serializable: DirSerializable, store: S) -> FsResult<Self> {
-        let entries: HashMap<_, _> = serializable
+        let entries: BTreeMap<_, _> = serializable
             .entries
             .into_iter()
             .map(|(segment, cid)| Ok((PathSegment::try_from(segment)?, Link::from(cid))))
             .collect::<FsResult<_>>()?;
 
+        // Hints for names that are not entries are dropped.
+        let hints: BTreeMap<_, _> = serializable
+            .hints
+            .into_iter()
+            .filter_map(|(segment, hint)| Some((PathSegment::try_from(segment).ok()?, hint)))
+            .filter(|(segment, _)| entries.contains_key(segment))
+            .collect();
+
         Ok(Dir {
             inner: Arc::new(DirInner {
                 metadata: serializable.metadata,
                 store,
                 entries,
+                hints,
             }),
         })
     }
@@ -441,6 +922,12 @@ where
                 .get_entries()
                 .map(|(k, v)| (k.to_string(), *v.get_cid()))
                 .collect(),
+            hints: self
+                .inner
+                .hints
+                .iter()
+                .map(|(k, v)| (k.to_string(), *v))
+                .collect(),
         };
 
         serializable.serialize(serializer)
@@ -479,6 +966,7 @@ where
         self.metadata == other.metadata
             && self.entries.len() == other.entries.len()
             && self.entries == other.entries
+            && self.hints == other.hints
     }
 }
 
@@ -561,6 +1049,33 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_dir_entries_pagination() -> anyhow::Result<()> {
+        let mut dir = Dir::new(MemoryStore::default());
+        let cid: Cid = "bafkreidgvpkjawlxz6sffxzwgooowe5yt7i6wsyg236mfoks77nywkptdq".parse()?;
+        for name in ["delta", "alpha", "charlie", "bravo"] {
+            dir.put(name, cid)?;
+        }
+
+        let page = |dir: &Dir<_>, after: Option<&str>| -> anyhow::Result<Vec<String>> {
+            let after = after.map(str::parse::<PathSegment>).transpose()?;
+            Ok(dir
+                .get_entries_after(after.as_ref())
+                .take(2)
+                .map(|(name, _)| name.to_string())
+                .collect())
+        };
+
+        assert_eq!(page(&dir, None)?, ["alpha", "bravo"]);
+
+        // Entries added before the cursor do not shift the next page.
+        dir.put("aardvark", cid)?;
+        assert_eq!(page(&dir, Some("bravo"))?, ["charlie", "delta"]);
+        assert!(page(&dir, Some("delta"))?.is_empty());
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_dir_stores_loads() -> anyhow::Result<()> {
         let store = MemoryStore::default();
@@ -578,4 +1093,84 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_dir_entry_hints() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let mut dir = Dir::new(store.clone());
+        let cid = "bafkreidgvpkjawlxz6sffxzwgooowe5yt7i6wsyg236mfoks77nywkptdq".parse()?;
+        let hint = EntryHint {
+            entity_type: EntityType::File,
+            size: Some(5),
+        };
+
+        dir.put_with_hint("file1", cid, hint)?;
+        assert_eq!(dir.get_hint(&"file1".parse()?), Some(&hint));
+
+        // The hinted type is returned without loading the entry, which is not in the store.
+        assert_eq!(
+            dir.get_entity_type(&"file1".parse()?).await?,
+            Some(EntityType::File)
+        );
+
+        let loaded_dir = Dir::load(&dir.store().await?, store).await?;
+        assert_eq!(loaded_dir.get_hint(&"file1".parse()?), Some(&hint));
+
+        // Putting without a hint clears the stale one.
+        dir.put("file1", cid)?;
+        assert_eq!(dir.get_hint(&"file1".parse()?), None);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_dir_functional_updates() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let cid = "bafkreidgvpkjawlxz6sffxzwgooowe5yt7i6wsyg236mfoks77nywkptdq".parse()?;
+        let dir = Dir::new(store.clone());
+
+        let (added, added_cid) = dir.with_entry("file1", cid).await?;
+        assert!(dir.get(&"file1".parse()?).is_none());
+        assert_eq!(added.get(&"file1".parse()?).unwrap().get_cid(), &cid);
+        assert_eq!(Dir::load(&added_cid, store.clone()).await?, added);
+
+        // Removing from the copy leaves the directory it came from alone.
+        let (removed, removed_cid) = added.without_entry(&"file1".parse()?).await?;
+        assert!(added.get(&"file1".parse()?).is_some());
+        assert!(removed.get(&"file1".parse()?).is_none());
+        assert_eq!(removed_cid, dir.store().await?);
+
+        assert!(matches!(
+            removed.without_entry(&"file1".parse()?).await,
+            Err(FsError::NotFound(_))
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_root_dir_compare_and_swap() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let root = RootDir::new(store.clone());
+        let mut updates = root.subscribe();
+        assert_eq!(*updates.borrow(), None);
+
+        let (dir, cid) = root.load().await?;
+        let (first, first_cid) = dir.with_entry("a", cid).await?;
+        assert_eq!(root.compare_and_swap(&cid, first).await?, first_cid);
+        assert_eq!(root.load().await?.1, first_cid);
+
+        updates.changed().await?;
+        assert_eq!(*updates.borrow_and_update(), Some(first_cid));
+
+        // A change made on top of the old root is refused, and the root is left alone.
+        let (second, _) = dir.with_entry("b", cid).await?;
+        assert!(matches!(
+            root.compare_and_swap(&cid, second).await,
+            Err(FsError::RootChanged(current)) if current == first_cid
+        ));
+        assert_eq!(root.load().await?.1, first_cid);
+
+        Ok(())
+    }
 }
@@ -1,9 +1,25 @@
 mod dir;
+mod op_acl_at;
+mod op_attributes_at;
+mod op_content_cid_at;
+mod op_glob_at;
+mod op_grants_at;
+mod op_keys_at;
+mod op_make_handle_at;
 #[cfg(feature = "wasi_api")]
 mod op_open_at;
+mod op_realpath_at;
+mod op_rename_at;
+mod op_stat_many;
+mod op_times_at;
+#[cfg(feature = "wasi_api")]
+mod op_write_file_atomic;
 
 //--------------------------------------------------------------------------------------------------
 // Exports
 //--------------------------------------------------------------------------------------------------
 
 pub use dir::*;
+pub use op_glob_at::*;
+pub use op_realpath_at::*;
+pub use op_stat_many::*;
@@ -0,0 +1,134 @@
+use zeroutils_store::{IpldStore, Storable};
+
+use crate::filesystem::{Acl, Dir, Entity, FsAction, FsError, FsResult, Path};
+
+use super::TraceResult;
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl<S> Dir<S>
+where
+    S: IpldStore,
+{
+    /// Returns the access control list of the entity at `path`. An empty path refers to the
+    /// directory itself.
+    pub async fn get_acl_at(&self, path: &Path) -> FsResult<Acl>
+    where
+        S: Send + Sync,
+    {
+        if path.is_empty() {
+            return Ok(self.get_metadata().acl.clone());
+        }
+
+        match self.trace_entity(path).await? {
+            TraceResult::Found { entity, .. } => Ok(entity.get_metadata().acl.clone()),
+            _ => Err(FsError::NotFound(path.clone())),
+        }
+    }
+
+    /// Sets the access control list of the entity at `path` and returns the updated directory. An
+    /// empty path refers to the directory itself.
+    ///
+    /// Like [`link_at`][Self::link_at], the directories along the path are rewritten and stored but
+    /// the directory itself is not.
+    pub async fn set_acl_at(&self, path: &Path, acl: Acl) -> FsResult<Dir<S>>
+    where
+        S: Send + Sync + 'static,
+    {
+        if path.is_empty() {
+            let mut dir = self.clone();
+            dir.set_acl(acl);
+            return Ok(dir);
+        }
+
+        let mut entity = match self.trace_entity(path).await? {
+            TraceResult::Found { entity, .. } => entity,
+            _ => return Err(FsError::NotFound(path.clone())),
+        };
+
+        entity.set_acl(acl);
+        self.link_at(path, entity.store().await?).await
+    }
+
+    /// Returns `true` if the access control list of the directory or of any existing entity along
+    /// `path` allows `did` to perform `action`.
+    ///
+    /// An ACL applies to the entity it is stored on and to everything beneath it.
+    pub async fn acl_permits(&self, path: &Path, did: &str, action: FsAction) -> FsResult<bool>
+    where
+        S: Send + Sync,
+    {
+        if self.get_metadata().acl.permits(did, action) {
+            return Ok(true);
+        }
+
+        let mut dir = self;
+        for segment in path.iter() {
+            let entity = match dir.get_entity(segment).await? {
+                Some(entity) => entity,
+                None => break,
+            };
+
+            if entity.get_metadata().acl.permits(did, action) {
+                return Ok(true);
+            }
+
+            match entity {
+                Entity::Dir(d) => dir = d,
+                _ => break,
+            }
+        }
+
+        Ok(false)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use zeroutils_store::MemoryStore;
+
+    use crate::filesystem::File;
+
+    use super::*;
+
+    const ALICE: &str = "did:wk:z6MkoVs2h6TnfyY8fx2ZqpREWSLS8rBDQmGpyXgFpg63CSUb";
+
+    #[tokio::test]
+    async fn test_dir_acl_at() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let file_cid = File::new(store.clone()).store().await?;
+        let root = Dir::new(store)
+            .link_at(&"public/notes".parse()?, file_cid)
+            .await?;
+
+        let mut acl = Acl::new();
+        acl.allow(ALICE, FsAction::Read);
+
+        let root = root.set_acl_at(&"public".parse()?, acl.clone()).await?;
+        assert_eq!(root.get_acl_at(&"public".parse()?).await?, acl);
+        assert!(root.get_acl_at(&"public/notes".parse()?).await?.is_empty());
+
+        assert!(
+            root.acl_permits(&"public/notes".parse()?, ALICE, FsAction::Read)
+                .await?
+        );
+        assert!(
+            !root
+                .acl_permits(&"public/notes".parse()?, ALICE, FsAction::Write)
+                .await?
+        );
+        assert!(
+            !root
+                .acl_permits(&Path::default(), ALICE, FsAction::Read)
+                .await?
+        );
+
+        Ok(())
+    }
+}
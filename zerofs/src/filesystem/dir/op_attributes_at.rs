@@ -0,0 +1,180 @@
+use zeroutils_store::{IpldStore, Storable};
+
+use crate::filesystem::{Dir, Entity, EntityAttributes, FsError, FsResult, Path};
+
+use super::TraceResult;
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl<S> Dir<S>
+where
+    S: IpldStore,
+{
+    /// Returns the attributes of the entity at `path`. An empty path refers to the directory
+    /// itself.
+    pub async fn get_attributes_at(&self, path: &Path) -> FsResult<EntityAttributes>
+    where
+        S: Send + Sync,
+    {
+        if path.is_empty() {
+            return Ok(self.get_metadata().attributes);
+        }
+
+        match self.trace_entity(path).await? {
+            TraceResult::Found { entity, .. } => Ok(entity.get_metadata().attributes),
+            _ => Err(FsError::NotFound(path.clone())),
+        }
+    }
+
+    /// Sets the attributes of the file at `path` and returns the updated directory.
+    ///
+    /// Like [`link_at`][Self::link_at], the directories along the path are rewritten and stored but
+    /// the directory itself is not.
+    pub async fn set_attributes_at(
+        &self,
+        path: &Path,
+        attributes: EntityAttributes,
+    ) -> FsResult<Dir<S>>
+    where
+        S: Send + Sync + 'static,
+    {
+        if path.is_empty() {
+            return Err(FsError::NotAFile(Some(path.clone())));
+        }
+
+        let mut file = match self.trace_entity(path).await? {
+            TraceResult::Found {
+                entity: Entity::File(file),
+                ..
+            } => file,
+            TraceResult::Found { .. } => return Err(FsError::NotAFile(Some(path.clone()))),
+            _ => return Err(FsError::NotFound(path.clone())),
+        };
+
+        file.set_attributes(attributes);
+        self.link_at(path, file.store().await?).await
+    }
+
+    /// Fails if the attributes of the entity at `path`, or of any entity under it, protect it from
+    /// being replaced or removed, see [`Entity::check_replaceable`]. Succeeds if there is nothing at
+    /// `path`.
+    pub async fn check_replaceable(&self, path: &Path) -> FsResult<()>
+    where
+        S: Send + Sync,
+    {
+        if path.is_empty() {
+            return Entity::Dir(self.clone()).check_replaceable(path).await;
+        }
+
+        match self.trace_entity(path).await? {
+            TraceResult::Found { entity, .. } => entity.check_replaceable(path).await,
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<S> Entity<S>
+where
+    S: IpldStore,
+{
+    /// Fails if the entity, which is at `path`, is protected from being replaced or removed by its
+    /// attributes. A directory is protected if any file under it is, since replacing or removing
+    /// the directory drops the file as well.
+    ///
+    /// The error names the path of the first protected file found.
+    pub async fn check_replaceable(&self, path: &Path) -> FsResult<()>
+    where
+        S: Send + Sync,
+    {
+        let mut pending = vec![(self.clone(), path.clone())];
+        while let Some((entity, path)) = pending.pop() {
+            let attributes = entity.get_metadata().attributes;
+            if attributes.contains(EntityAttributes::IMMUTABLE) {
+                return Err(FsError::Immutable(path));
+            }
+
+            if attributes.contains(EntityAttributes::APPEND_ONLY) {
+                return Err(FsError::AppendOnly(path));
+            }
+
+            let Entity::Dir(dir) = entity else {
+                continue;
+            };
+
+            for (name, _) in dir.get_entries() {
+                if let Some(child) = dir.get_entity(name).await? {
+                    let mut child_path = path.clone();
+                    child_path.push(name.clone());
+                    pending.push((child.clone(), child_path));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use zeroutils_store::MemoryStore;
+
+    use crate::filesystem::File;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_dir_attributes_at() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let file_cid = File::new(store.clone()).store().await?;
+        let root = Dir::new(store)
+            .link_at(&"public/log".parse()?, file_cid)
+            .await?;
+
+        let path = "public/log".parse()?;
+        assert!(root.get_attributes_at(&path).await?.is_empty());
+        root.check_replaceable(&path).await?;
+
+        let root = root
+            .set_attributes_at(&path, EntityAttributes::APPEND_ONLY)
+            .await?;
+        assert_eq!(
+            root.get_attributes_at(&path).await?,
+            EntityAttributes::APPEND_ONLY
+        );
+        assert!(matches!(
+            root.check_replaceable(&path).await,
+            Err(FsError::AppendOnly(_))
+        ));
+
+        let root = root
+            .set_attributes_at(&path, EntityAttributes::IMMUTABLE)
+            .await?;
+        assert!(matches!(
+            root.check_replaceable(&path).await,
+            Err(FsError::Immutable(_))
+        ));
+
+        // Directories cannot be protected, and a path with nothing at it can be replaced.
+        assert!(matches!(
+            root.set_attributes_at(&"public".parse()?, EntityAttributes::IMMUTABLE)
+                .await,
+            Err(FsError::NotAFile(_))
+        ));
+        root.check_replaceable(&"public/other".parse()?).await?;
+
+        // Directories with a protected file under them cannot be replaced either.
+        assert!(matches!(
+            root.check_replaceable(&"public".parse()?).await,
+            Err(FsError::Immutable(path)) if path == "public/log".parse::<Path>()?
+        ));
+        assert!(root.check_replaceable(&Path::default()).await.is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,94 @@
+use zeroutils_store::{ipld::cid::Cid, IpldStore};
+
+use crate::filesystem::{Dir, Entity, FsError, FsResult, Path};
+
+use super::TraceResult;
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl<S> Dir<S>
+where
+    S: IpldStore,
+{
+    /// Returns the [`Cid`] of the content of the file at `path`, or `None` if the file is empty.
+    ///
+    /// This is not the [`Cid`] of the file node, which changes with the metadata of the file, but
+    /// that of its content alone: a raw block, or the [`ContentChunks`][crate::filesystem::ContentChunks]
+    /// node of chunked content. Files with the same content chunked the same way have the same
+    /// content [`Cid`], so a client can tell whether content is already stored before sending it.
+    ///
+    /// Fails with [`FsError::NotAFile`] if the entity at `path` is not a file.
+    pub async fn content_cid_at(&self, path: &Path) -> FsResult<Option<Cid>>
+    where
+        S: Send + Sync,
+    {
+        if path.is_empty() {
+            return Err(FsError::NotAFile(None));
+        }
+
+        match self.trace_entity(path).await? {
+            TraceResult::Found {
+                entity: Entity::File(file),
+                ..
+            } => Ok(file.get_content().copied()),
+            TraceResult::Found { .. } => Err(FsError::NotAFile(Some(path.clone()))),
+            _ => Err(FsError::NotFound(path.clone())),
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use zeroutils_store::{MemoryStore, Storable};
+
+    use crate::filesystem::File;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_content_cid_at() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let content = store.put_raw_block(b"hello".to_vec()).await?;
+        let mut file = File::new(store.clone());
+        file.set_content(Some(content));
+
+        let mut other = file.clone();
+        other.set_modified_at(chrono::Utc::now() + chrono::Duration::seconds(60));
+
+        let root = Dir::new(store.clone())
+            .link_at(&"docs/a".parse()?, file.store().await?)
+            .await?
+            .link_at(&"docs/b".parse()?, other.store().await?)
+            .await?
+            .link_at(&"docs/empty".parse()?, File::new(store).store().await?)
+            .await?;
+
+        // Files with the same content have the same content CID, whatever their metadata.
+        assert_eq!(
+            root.content_cid_at(&"docs/a".parse()?).await?,
+            Some(content)
+        );
+        assert_eq!(
+            root.content_cid_at(&"docs/b".parse()?).await?,
+            Some(content)
+        );
+        assert_eq!(root.content_cid_at(&"docs/empty".parse()?).await?, None);
+
+        assert!(matches!(
+            root.content_cid_at(&"docs".parse()?).await,
+            Err(FsError::NotAFile(_))
+        ));
+        assert!(matches!(
+            root.content_cid_at(&"docs/c".parse()?).await,
+            Err(FsError::NotFound(_))
+        ));
+
+        Ok(())
+    }
+}
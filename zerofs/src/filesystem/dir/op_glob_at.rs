@@ -0,0 +1,160 @@
+use std::{collections::BTreeMap, convert::TryFrom};
+
+use zeroutils_store::{ipld::cid::Cid, IpldStore};
+
+use crate::filesystem::{
+    Dir, Entity, EntityType, FsError, FsResult, GlobPattern, GlobSegment, Path, PathSegment,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The default largest number of entries looked at by a [`Dir::glob_at`].
+pub const DEFAULT_GLOB_MAX_VISITED: usize = 100_000;
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl<S> Dir<S>
+where
+    S: IpldStore,
+{
+    /// Returns the paths under the directory that match `pattern`, with the CIDs of the entities at
+    /// them, in path order.
+    ///
+    /// Only the directories the pattern can reach are walked: segments without wildcards are
+    /// looked up directly, and only `**` walks a whole subtree. The walk fails with
+    /// [`FsError::GlobLimitExceeded`] once it has looked at more than `max_visited` entries, so a
+    /// broad pattern over a large tree does not run unbounded. Symbolic links are not followed.
+    pub async fn glob_at(
+        &self,
+        pattern: &GlobPattern,
+        max_visited: usize,
+    ) -> FsResult<Vec<(Path, Cid)>>
+    where
+        S: Send + Sync,
+    {
+        let segments = pattern.get_segments();
+        let mut matches = BTreeMap::new();
+        let mut visited = 0;
+        let mut pending = vec![(self.clone(), Path::default(), 0)];
+        while let Some((dir, dir_path, index)) = pending.pop() {
+            let Some(segment) = segments.get(index) else {
+                continue;
+            };
+
+            // `**` matching no segments at all.
+            if *segment == GlobSegment::AnyDepth {
+                pending.push((dir.clone(), dir_path.clone(), index + 1));
+            }
+
+            let entries = match segment.as_literal() {
+                Some(name) => {
+                    let name = PathSegment::try_from(name)?;
+                    dir.get(&name)
+                        .map(|link| (name, link))
+                        .into_iter()
+                        .collect()
+                }
+                None => dir
+                    .get_entries()
+                    .filter(|(name, _)| segment.matches(name.as_str()))
+                    .map(|(name, link)| (name.clone(), link))
+                    .collect::<Vec<_>>(),
+            };
+
+            for (name, link) in entries {
+                visited += 1;
+                if visited > max_visited {
+                    return Err(FsError::GlobLimitExceeded(pattern.to_string(), max_visited));
+                }
+
+                let mut entry_path = dir_path.clone();
+                entry_path.push(name.clone());
+
+                // `**` stays in place to match more segments below the entry.
+                let next = match segment {
+                    GlobSegment::AnyDepth => index,
+                    GlobSegment::Name(_) => index + 1,
+                };
+
+                if segments[next..]
+                    .iter()
+                    .all(|segment| *segment == GlobSegment::AnyDepth)
+                {
+                    matches.insert(entry_path.clone(), *link.get_cid());
+                }
+
+                if next >= segments.len()
+                    || dir.get_entity_type(&name).await? != Some(EntityType::Dir)
+                {
+                    continue;
+                }
+
+                if let Some(Entity::Dir(child)) = dir.get_entity(&name).await? {
+                    pending.push((child.clone(), entry_path, next));
+                }
+            }
+        }
+
+        Ok(matches.into_iter().collect())
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use zeroutils_store::{MemoryStore, Storable};
+
+    use crate::filesystem::{Chunker, File};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_glob_at() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let mut file = File::new(store.clone());
+        file.put_content(&b"data"[..], &Chunker::Store).await?;
+        let file = file.store().await?;
+
+        let mut root = Dir::new(store);
+        for path in ["src/lib", "src/fs/dir", "src/fs/dirtest", "docs/readme"] {
+            root = root.link_at(&path.parse()?, file).await?;
+        }
+
+        let glob = |pattern: &str| {
+            let root = root.clone();
+            let pattern = pattern.parse::<GlobPattern>();
+            async move {
+                let paths = root
+                    .glob_at(&pattern?, DEFAULT_GLOB_MAX_VISITED)
+                    .await?
+                    .into_iter()
+                    .map(|(path, _)| path.to_string())
+                    .collect::<Vec<_>>();
+                anyhow::Ok(paths)
+            }
+        };
+
+        assert_eq!(glob("src/*").await?, ["/src/fs", "/src/lib"]);
+        assert_eq!(glob("**/dir*").await?, ["/src/fs/dir", "/src/fs/dirtest"]);
+        assert_eq!(glob("src/**/di?").await?, ["/src/fs/dir"]);
+        assert_eq!(glob("docs/**").await?, ["/docs", "/docs/readme"]);
+        assert_eq!(glob("*/readme").await?, ["/docs/readme"]);
+
+        let (_, cid) = &root.glob_at(&"src/lib".parse()?, 2).await?[0];
+        assert_eq!(*cid, file);
+
+        assert!(matches!(
+            root.glob_at(&"**".parse()?, 3).await,
+            Err(FsError::GlobLimitExceeded(_, 3))
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,95 @@
+use zeroutils_store::{IpldStore, Storable};
+
+use crate::filesystem::{Dir, FsError, FsResult, KeyGrants, Path};
+
+use super::TraceResult;
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl<S> Dir<S>
+where
+    S: IpldStore,
+{
+    /// Returns the grants of the content key of the entity at `path`. An empty path refers to the
+    /// directory itself.
+    pub async fn get_key_grants_at(&self, path: &Path) -> FsResult<KeyGrants>
+    where
+        S: Send + Sync,
+    {
+        if path.is_empty() {
+            return Ok(self.get_metadata().grants.clone());
+        }
+
+        match self.trace_entity(path).await? {
+            TraceResult::Found { entity, .. } => Ok(entity.get_metadata().grants.clone()),
+            _ => Err(FsError::NotFound(path.clone())),
+        }
+    }
+
+    /// Sets the grants of the content key of the entity at `path` and returns the updated
+    /// directory. An empty path refers to the directory itself.
+    ///
+    /// Like [`set_acl_at`][Self::set_acl_at], the directories along the path are rewritten and
+    /// stored but the directory itself is not.
+    pub async fn set_key_grants_at(&self, path: &Path, grants: KeyGrants) -> FsResult<Dir<S>>
+    where
+        S: Send + Sync + 'static,
+    {
+        if path.is_empty() {
+            let mut dir = self.clone();
+            dir.set_key_grants(grants);
+            return Ok(dir);
+        }
+
+        let mut entity = match self.trace_entity(path).await? {
+            TraceResult::Found { entity, .. } => entity,
+            _ => return Err(FsError::NotFound(path.clone())),
+        };
+
+        entity.set_key_grants(grants);
+        self.link_at(path, entity.store().await?).await
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use zeroutils_store::MemoryStore;
+
+    use crate::filesystem::{File, KeyGrant};
+
+    use super::*;
+
+    const ALICE: &str = "did:wk:z6MkoVs2h6TnfyY8fx2ZqpREWSLS8rBDQmGpyXgFpg63CSUb";
+
+    #[tokio::test]
+    async fn test_dir_key_grants_at() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let file_cid = File::new(store.clone()).store().await?;
+        let root = Dir::new(store)
+            .link_at(&"public/notes".parse()?, file_cid)
+            .await?;
+
+        let mut grants = KeyGrants::new();
+        grants.grant(
+            ALICE,
+            KeyGrant::new(vec![1, 2, 3], "x25519-xchacha20poly1305"),
+        );
+
+        let root = root
+            .set_key_grants_at(&"public/notes".parse()?, grants.clone())
+            .await?;
+        assert_eq!(
+            root.get_key_grants_at(&"public/notes".parse()?).await?,
+            grants
+        );
+        assert!(root.get_key_grants_at(&"public".parse()?).await?.is_empty());
+
+        Ok(())
+    }
+}
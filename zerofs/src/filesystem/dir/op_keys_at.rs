@@ -0,0 +1,233 @@
+use std::collections::BTreeSet;
+
+use futures::future::{BoxFuture, FutureExt};
+use zeroutils_store::{IpldStore, Storable};
+
+use crate::filesystem::{Dir, Entity, FsError, FsResult, KeyGrant, Path};
+
+use super::TraceResult;
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl<S> Dir<S>
+where
+    S: IpldStore,
+{
+    /// Returns the key epochs of the entities along `path`, in order, as needed to derive the key
+    /// of the entity at `path` from the key of this directory, see
+    /// [`ContentKey::derive_at`][crate::filesystem::ContentKey::derive_at].
+    pub async fn get_key_epochs_at(&self, path: &Path) -> FsResult<Vec<u32>>
+    where
+        S: Send + Sync,
+    {
+        let mut epochs = Vec::with_capacity(path.len());
+        let mut dir = self;
+        for (depth, segment) in path.iter().enumerate() {
+            let entity = dir
+                .get_entity(segment)
+                .await?
+                .ok_or_else(|| FsError::NotFound(path.clone()))?;
+
+            epochs.push(entity.get_metadata().key_epoch);
+            match entity {
+                Entity::Dir(d) => dir = d,
+                _ if depth + 1 < path.len() => {
+                    return Err(FsError::NotADirectory(Some(path.clone())))
+                }
+                _ => {}
+            }
+        }
+
+        Ok(epochs)
+    }
+
+    /// Returns the grant to `did` on the entity at `path` or, failing that, on the closest
+    /// directory above it that has one, along with the path of the entity the grant is on.
+    ///
+    /// A grant on a directory covers its whole subtree, as the keys beneath it are derived from
+    /// its key. The grant on this directory itself is not looked at.
+    pub async fn find_key_grant_at(
+        &self,
+        path: &Path,
+        did: &str,
+    ) -> FsResult<Option<(Path, KeyGrant)>>
+    where
+        S: Send + Sync,
+    {
+        let mut found = None;
+        let mut dir = self;
+        for (depth, segment) in path.iter().enumerate() {
+            let entity = dir
+                .get_entity(segment)
+                .await?
+                .ok_or_else(|| FsError::NotFound(path.clone()))?;
+
+            if let Some(grant) = entity.get_metadata().grants.get(did) {
+                let granted: Path = path.iter().take(depth + 1).cloned().collect();
+                found = Some((granted, grant.clone()));
+            }
+
+            match entity {
+                Entity::Dir(d) => dir = d,
+                _ if depth + 1 < path.len() => {
+                    return Err(FsError::NotADirectory(Some(path.clone())))
+                }
+                _ => {}
+            }
+        }
+
+        Ok(found)
+    }
+
+    /// Rotates the content key of the entity at `path` and returns the updated directory along
+    /// with the DIDs whose grants were dropped.
+    ///
+    /// Rotating bumps the key epoch of the entity, which changes the keys of the entity and of its
+    /// whole subtree. The grants in the subtree were made with the old keys, so they are dropped;
+    /// the rest of the tree is left as it is. The caller is expected to grant the new key to the
+    /// DIDs that should keep access, which for a directory is a single grant on the directory.
+    ///
+    /// Like [`link_at`][Self::link_at], the directories along the path are rewritten and stored but
+    /// the directory itself is not.
+    pub async fn rotate_key_at(&self, path: &Path) -> FsResult<(Dir<S>, BTreeSet<String>)>
+    where
+        S: Send + Sync + 'static,
+    {
+        let entity = match self.trace_entity(path).await? {
+            TraceResult::Found { entity, .. } => entity,
+            _ => return Err(FsError::NotFound(path.clone())),
+        };
+
+        let epoch = entity.get_metadata().key_epoch + 1;
+        let (mut entity, revoked) = clear_key_grants(entity).await?;
+        entity.set_key_epoch(epoch);
+
+        let dir = self.link_at(path, entity.store().await?).await?;
+        Ok((dir, revoked))
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Drops the key grants on `entity` and everything beneath it, returning the updated entity and
+/// the DIDs the dropped grants were to. Directories without grants in their subtree are kept as
+/// they are.
+fn clear_key_grants<S>(
+    mut entity: Entity<S>,
+) -> BoxFuture<'static, FsResult<(Entity<S>, BTreeSet<String>)>>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    async move {
+        let mut revoked: BTreeSet<String> = entity
+            .get_metadata()
+            .grants
+            .iter()
+            .map(|(did, _)| did.to_owned())
+            .collect();
+
+        if !revoked.is_empty() {
+            entity.set_key_grants(Default::default());
+        }
+
+        if let Entity::Dir(dir) = &mut entity {
+            let names = dir
+                .get_entries()
+                .map(|(name, _)| name.clone())
+                .collect::<Vec<_>>();
+            for name in names {
+                let Some(child) = dir.get_entity(&name).await?.cloned() else {
+                    continue;
+                };
+
+                let (child, child_revoked) = clear_key_grants(child).await?;
+                if child_revoked.is_empty() {
+                    continue;
+                }
+
+                let cid = child.store().await?;
+                match dir.get_hint(&name).copied() {
+                    Some(hint) => dir.put_with_hint(name, cid, hint)?,
+                    None => dir.put(name, cid)?,
+                }
+
+                revoked.extend(child_revoked);
+            }
+        }
+
+        Ok((entity, revoked))
+    }
+    .boxed()
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use zeroutils_store::MemoryStore;
+
+    use crate::filesystem::{ContentKey, File, KeyGrants};
+
+    use super::*;
+
+    const ALICE: &str = "did:wk:z6MkoVs2h6TnfyY8fx2ZqpREWSLS8rBDQmGpyXgFpg63CSUb";
+    const BOB: &str = "did:wk:z6MknLif7jhwt6jUfn14EuDnxWoSHkkajyDi28QMMH5eS1DL";
+
+    #[tokio::test]
+    async fn test_dir_key_hierarchy() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let file_cid = File::new(store.clone()).store().await?;
+        let root = Dir::new(store)
+            .link_at(&"docs/notes/todo".parse()?, file_cid)
+            .await?
+            .link_at(&"other".parse()?, file_cid)
+            .await?;
+
+        let mut grants = KeyGrants::new();
+        grants.grant(ALICE, KeyGrant::new(vec![1], "test"));
+        let root = root.set_key_grants_at(&"docs".parse()?, grants).await?;
+
+        let mut grants = KeyGrants::new();
+        grants.grant(BOB, KeyGrant::new(vec![2], "test"));
+        let root = root
+            .set_key_grants_at(&"docs/notes/todo".parse()?, grants.clone())
+            .await?
+            .set_key_grants_at(&"other".parse()?, grants)
+            .await?;
+
+        // A grant on a directory covers its subtree.
+        let (granted, _) = root
+            .find_key_grant_at(&"docs/notes/todo".parse()?, ALICE)
+            .await?
+            .unwrap();
+        assert_eq!(granted, "docs".parse()?);
+
+        let path: Path = "docs/notes/todo".parse()?;
+        let key = ContentKey::from_bytes([7; 32]);
+        let before = key.derive_at(&path, &root.get_key_epochs_at(&path).await?)?;
+
+        // Rotating a directory changes the keys beneath it and drops the grants in its subtree
+        // only.
+        let (root, revoked) = root.rotate_key_at(&"docs/notes".parse()?).await?;
+        assert_eq!(revoked, BTreeSet::from([BOB.to_owned()]));
+        assert_eq!(root.get_key_epochs_at(&path).await?, vec![0, 1, 0]);
+        assert_ne!(
+            key.derive_at(&path, &root.get_key_epochs_at(&path).await?)?,
+            before
+        );
+        assert!(root.find_key_grant_at(&path, BOB).await?.is_none());
+        assert!(root.find_key_grant_at(&path, ALICE).await?.is_some());
+        assert!(root
+            .find_key_grant_at(&"other".parse()?, BOB)
+            .await?
+            .is_some());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,112 @@
+use zeroutils_store::IpldStore;
+
+use crate::filesystem::{
+    DescriptorFlags, DirHandle, Entity, FsError, FsResult, MemoryBufferStore, Path, RootDir,
+};
+
+use super::TraceResult;
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl<S> RootDir<S>
+where
+    S: IpldStore,
+{
+    /// Creates a handle to the directory at `path` with the given flags, like
+    /// [`make_handle`][Self::make_handle] does for the root directory.
+    ///
+    /// Handles opened from it cannot reach above the directory, which makes it a sandbox for the
+    /// subtree. Changes made through it still update the whole tree, as the handle keeps the
+    /// directories along `path`.
+    ///
+    /// A missing directory is created if `create` is `true`, once something is written under it,
+    /// and fails with [`FsError::NotFound`] otherwise. Fails with [`FsError::NotADirectory`] if
+    /// something along the path is not a directory.
+    pub async fn make_handle_at(
+        &self,
+        path: &Path,
+        flags: DescriptorFlags,
+        create: bool,
+    ) -> FsResult<DirHandle<S, MemoryBufferStore<S>>>
+    where
+        S: Send + Sync,
+    {
+        if path.is_empty() {
+            return Ok(self.make_handle(flags));
+        }
+
+        let root = self.fork();
+        let (entity, name, pathdirs) = if create {
+            root.get_or_create_entity(path, false).await?
+        } else {
+            match root.trace_entity(path).await? {
+                TraceResult::Found {
+                    entity,
+                    name,
+                    pathdirs,
+                } => (entity, name, pathdirs),
+                TraceResult::Incomplete { depth, .. } => {
+                    return Err(FsError::NotFound(path.slice(..depth).to_owned()));
+                }
+                TraceResult::NotADir { depth, .. } => {
+                    return Err(FsError::NotADirectory(Some(path.slice(..depth).to_owned())));
+                }
+            }
+        };
+
+        match entity {
+            Entity::Dir(dir) => Ok(DirHandle::from(dir, name, flags, self.clone(), pathdirs)),
+            _ => Err(FsError::NotADirectory(Some(path.clone()))),
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use zeroutils_store::{MemoryStore, Storable};
+
+    use crate::filesystem::{Dir, File};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_make_handle_at() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let file = File::new(store.clone()).store().await?;
+        let dir = Dir::new(store.clone())
+            .link_at(&"data/notes".parse()?, file)
+            .await?;
+        let root = RootDir::from_dir(dir);
+
+        let handle = root
+            .make_handle_at(&"data".parse()?, DescriptorFlags::READ, false)
+            .await?;
+        assert_eq!(handle.path(), "data".parse()?);
+        assert_eq!(handle.entity().get_entries().count(), 1);
+        assert_eq!(*handle.flags(), DescriptorFlags::READ);
+
+        // Missing directories are only created when asked to.
+        let missing = root
+            .make_handle_at(&"scratch/tmp".parse()?, DescriptorFlags::READ, false)
+            .await;
+        assert!(matches!(missing, Err(FsError::NotFound(_))));
+        let created = root
+            .make_handle_at(&"scratch/tmp".parse()?, DescriptorFlags::READ, true)
+            .await?;
+        assert_eq!(created.pathdirs().len(), 1);
+
+        // Files cannot be preopened as directories.
+        let file = root
+            .make_handle_at(&"data/notes".parse()?, DescriptorFlags::READ, false)
+            .await;
+        assert!(matches!(file, Err(FsError::NotADirectory(_))));
+
+        Ok(())
+    }
+}
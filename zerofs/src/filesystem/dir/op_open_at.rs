@@ -5,8 +5,8 @@ use zeroutils_store::IpldStore;
 use zeroutils_ucan::UcanAuth;
 
 use crate::filesystem::{
-    DescriptorFlags, DirHandle, Entity, EntityHandle, FsError, FsResult, OpenFlags, Path,
-    PermissionError,
+    DescriptorFlags, DirHandle, Entity, EntityAttributes, EntityHandle, EntityType, FsError,
+    FsResult, OpenFlags, Path, PermissionError,
 };
 
 use super::TraceResult;
@@ -41,6 +41,13 @@ where
             return Err(FsError::NeedAtLeastReadFlag(path, descriptor_flags));
         }
 
+        // Creating or truncating changes the file tree.
+        if self.root().is_read_only()
+            && (open_flags.contains(OpenFlags::CREATE) || open_flags.contains(OpenFlags::TRUNCATE))
+        {
+            return Err(FsError::ReadOnlyFilesystem(path));
+        }
+
         // Check for descriptor flag permission escalation.
         if !self.flags().contains(DescriptorFlags::MUTATE_DIR)
             && (descriptor_flags.contains(DescriptorFlags::MUTATE_DIR)
@@ -66,6 +73,30 @@ where
             return Err(FsError::InvalidOpenFlagsCombination(path, open_flags));
         }
 
+        // An entry that its parent hints is not a directory is refused before it gets loaded.
+        if open_flags.contains(OpenFlags::DIRECTORY) {
+            let mut parent_path = path.clone();
+            if let Some(name) = parent_path.pop() {
+                let entity_type = if parent_path.is_empty() {
+                    self.get_hint(&name).map(|hint| hint.entity_type)
+                } else {
+                    match self.trace_entity(&parent_path).await? {
+                        TraceResult::Found {
+                            entity: Entity::Dir(parent),
+                            ..
+                        } => parent.get_hint(&name).map(|hint| hint.entity_type),
+                        _ => None,
+                    }
+                };
+
+                if matches!(entity_type, Some(entity_type) if entity_type != EntityType::Dir) {
+                    return Err(FsError::OpenFlagsDirectoryButEntityNotADir(
+                        path, open_flags,
+                    ));
+                }
+            }
+        }
+
         // TODO: Check if user has capabilities to create a file in this directory.
 
         // Get the entity and path directories.
@@ -107,6 +138,15 @@ where
                 }
 
                 if open_flags.contains(OpenFlags::TRUNCATE) {
+                    let attributes = file.get_metadata().attributes;
+                    if attributes.contains(EntityAttributes::IMMUTABLE) {
+                        return Err(FsError::Immutable(path));
+                    }
+
+                    if attributes.contains(EntityAttributes::APPEND_ONLY) {
+                        return Err(FsError::AppendOnly(path));
+                    }
+
                     file.truncate();
                 }
 
@@ -130,7 +170,12 @@ mod tests {
     use zeroutils_key::{Ed25519KeyPair, KeyPairGenerate};
     use zeroutils_store::{MemoryStore, PlaceholderStore};
 
-    use crate::{filesystem::RootDir, utils::fixture};
+    use zeroutils_store::Storable;
+
+    use crate::{
+        filesystem::{Chunker, Dir, EntryHint, File, RootDir},
+        utils::fixture,
+    };
 
     use super::*;
 
@@ -221,8 +266,65 @@ mod tests {
             Err(FsError::InvalidOpenFlagsCombination(..))
         ));
 
-        // TODO:
-        // Opening an existing file with DIRECTORY flag should fail.
+        // Creating a file in a read-only file tree should fail.
+
+        root_dir.set_read_only(true);
+        let dir_handle = root_dir.make_handle(DescriptorFlags::READ | DescriptorFlags::MUTATE_DIR);
+        let result = dir_handle
+            .open_at(
+                "public/file",
+                OpenFlags::CREATE,
+                DescriptorFlags::READ | DescriptorFlags::WRITE,
+                fixture::mock_ucan_auth(&iss_key, PlaceholderStore)?,
+            )
+            .await;
+
+        assert!(matches!(result, Err(FsError::ReadOnlyFilesystem(..))));
+
+        Ok(())
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_open_at_directory_checks_hint_first() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let iss_key = Ed25519KeyPair::generate(&mut rand::thread_rng())?;
+
+        // The file is stored elsewhere, so only its hint in the parent is available here.
+        let mut file = File::new(MemoryStore::default());
+        file.put_content(&b"data"[..], &Chunker::Store).await?;
+        let file = file.store().await?;
+
+        let mut public = Dir::new(store.clone());
+        public.put_with_hint(
+            "file",
+            file,
+            EntryHint {
+                entity_type: EntityType::File,
+                size: Some(4),
+            },
+        )?;
+
+        let root_dir = RootDir::new(store.clone());
+        root_dir
+            .link_at(&"public".parse()?, public.store().await?)
+            .await?;
+
+        // Opening a file with DIRECTORY flag should fail without loading it.
+
+        let dir_handle = root_dir.make_handle(DescriptorFlags::READ);
+        let result = dir_handle
+            .open_at(
+                "public/file",
+                OpenFlags::DIRECTORY,
+                DescriptorFlags::READ,
+                fixture::mock_ucan_auth(&iss_key, PlaceholderStore)?,
+            )
+            .await;
+
+        assert!(matches!(
+            result,
+            Err(FsError::OpenFlagsDirectoryButEntityNotADir(..))
+        ));
 
         Ok(())
     }
@@ -0,0 +1,165 @@
+use std::collections::VecDeque;
+
+use zeroutils_store::{ipld::cid::Cid, IpldStore, Storable};
+
+use crate::filesystem::{Dir, Entity, FsError, FsResult, Path, PathSegment};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The most symbolic links followed while resolving a path, past which it is taken to be a loop.
+/// This is the limit Linux uses.
+pub const MAX_SYMLINK_HOPS: usize = 40;
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl<S> Dir<S>
+where
+    S: IpldStore,
+{
+    /// Resolves `path` to its canonical form and returns it along with the [`Cid`] of the entity
+    /// it leads to, like `realpath` does.
+    ///
+    /// Every symbolic link along the path is followed, the last one included, and `.` and `..` are
+    /// resolved against the directories they end up in, so the canonical path has no links and no
+    /// dots. The target of a link is relative to the directory the link is in. Following more than
+    /// [`MAX_SYMLINK_HOPS`] links fails with [`FsError::SymlinkLoop`].
+    ///
+    /// Fails with [`FsError::NotFound`] if something along the path does not exist, and with
+    /// [`FsError::NotADirectory`] if the path goes on past a file.
+    pub async fn realpath_at(&self, path: &Path) -> FsResult<(Path, Cid)>
+    where
+        S: Send + Sync,
+    {
+        let mut remaining = path.iter().cloned().collect::<VecDeque<_>>();
+        let mut resolved = Path::default();
+        let mut dirs = vec![(self.clone(), None)];
+        let mut file = None;
+        let mut hops = 0;
+
+        while let Some(segment) = remaining.pop_front() {
+            if file.is_some() {
+                return Err(FsError::NotADirectory(Some(resolved)));
+            }
+
+            match segment {
+                PathSegment::CurrentDir => continue,
+                PathSegment::ParentDir => {
+                    if resolved.pop().is_none() {
+                        return Err(FsError::OutOfBoundsParentDir);
+                    }
+
+                    dirs.pop();
+                    continue;
+                }
+                PathSegment::Named(_) => {}
+            }
+
+            let mut next = resolved.clone();
+            next.push(segment.clone());
+
+            let (dir, _) = dirs.last().expect("the root is never popped");
+            let Some(cid) = dir.get(&segment).map(|link| *link.get_cid()) else {
+                return Err(FsError::NotFound(next));
+            };
+
+            match dir.get_replicated_entity(&segment, || next.clone()).await? {
+                Some(Entity::Dir(child)) => {
+                    dirs.push((child.clone(), Some(cid)));
+                    resolved = next;
+                }
+                Some(Entity::Symlink(symlink)) => {
+                    hops += 1;
+                    if hops > MAX_SYMLINK_HOPS {
+                        return Err(FsError::SymlinkLoop(next, MAX_SYMLINK_HOPS));
+                    }
+
+                    for segment in symlink.get_path().get_segments().iter().rev() {
+                        remaining.push_front(segment.clone());
+                    }
+                }
+                Some(Entity::File(_)) => {
+                    file = Some(cid);
+                    resolved = next;
+                }
+                None => return Err(FsError::NotFound(next)),
+            }
+        }
+
+        let cid = match file.or_else(|| dirs.last().and_then(|(_, cid)| *cid)) {
+            Some(cid) => cid,
+            None => self.store().await?,
+        };
+
+        Ok((resolved, cid))
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use zeroutils_store::MemoryStore;
+
+    use crate::filesystem::{File, Symlink};
+
+    use super::*;
+
+    async fn symlink_to(store: &MemoryStore, target: &str) -> anyhow::Result<Cid> {
+        Ok(Symlink::new(store.clone(), target.parse()?).store().await?)
+    }
+
+    #[tokio::test]
+    async fn test_realpath_at() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let file = File::new(store.clone()).store().await?;
+        let root = Dir::new(store.clone())
+            .link_at(&"data/v2/notes".parse()?, file)
+            .await?
+            .link_at(&"data/current".parse()?, symlink_to(&store, "v2").await?)
+            .await?
+            .link_at(
+                &"docs/latest".parse()?,
+                symlink_to(&store, "../data/current/notes").await?,
+            )
+            .await?
+            .link_at(&"loop/a".parse()?, symlink_to(&store, "b").await?)
+            .await?
+            .link_at(&"loop/b".parse()?, symlink_to(&store, "a").await?)
+            .await?;
+
+        // Links are followed along the way and at the end, and dots are resolved where they land.
+        let (path, cid) = root.realpath_at(&"docs/latest".parse()?).await?;
+        assert_eq!(path, "data/v2/notes".parse()?);
+        assert_eq!(cid, file);
+
+        let (path, _) = root
+            .realpath_at(&"data/current/../current/./notes".parse()?)
+            .await?;
+        assert_eq!(path, "data/v2/notes".parse()?);
+
+        let (path, cid) = root.realpath_at(&"data/current".parse()?).await?;
+        assert_eq!(path, "data/v2".parse()?);
+        assert_eq!(cid, root.realpath_at(&"data/v2".parse()?).await?.1);
+
+        assert!(matches!(
+            root.realpath_at(&"loop/a".parse()?).await,
+            Err(FsError::SymlinkLoop(_, MAX_SYMLINK_HOPS))
+        ));
+        assert!(matches!(
+            root.realpath_at(&"data/v2/notes/more".parse()?).await,
+            Err(FsError::NotADirectory(_))
+        ));
+        assert!(matches!(
+            root.realpath_at(&"data/v3".parse()?).await,
+            Err(FsError::NotFound(_))
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,130 @@
+use zeroutils_store::{IpldStore, Storable};
+
+use crate::filesystem::{
+    commit_spine, current_time, Dir, Entity, FsError, FsResult, Path, PathSegment,
+};
+
+use super::TraceResult;
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl<S> Dir<S>
+where
+    S: IpldStore,
+{
+    /// Renames the entity at `path` to `name`, within the same directory, and returns the updated
+    /// directory.
+    ///
+    /// Names are compared case-insensitively, so a rename that only changes the case of a name,
+    /// like `readme` to `README`, keeps the entry and only changes the name it is displayed with,
+    /// see [`get_name`][Self::get_name]. The directory the entity is in has its modification time
+    /// set to the [`current_time`]. If it is not the directory itself, it and the directories above
+    /// it are rewritten with [`commit_spine`], which stores them along with the directory.
+    ///
+    /// Fails with [`FsError::PathExists`] if another entity is already named `name`.
+    pub async fn rename_at(&self, path: &Path, name: PathSegment) -> FsResult<Dir<S>>
+    where
+        S: Send + Sync + 'static,
+    {
+        let mut parent_path = path.clone();
+        let Some(old) = parent_path.pop() else {
+            return Err(FsError::EmptyPath);
+        };
+
+        if !name.is_named() {
+            return Err(FsError::InvalidPathSegment(name.to_string()));
+        }
+
+        let (mut parent, spine) = if parent_path.is_empty() {
+            (self.clone(), None)
+        } else {
+            match self.trace_entity(&parent_path).await? {
+                TraceResult::Found {
+                    entity: Entity::Dir(dir),
+                    name: Some(name),
+                    pathdirs,
+                } => (dir, Some((pathdirs, name))),
+                TraceResult::Found { .. } => return Err(FsError::NotADirectory(Some(parent_path))),
+                _ => return Err(FsError::NotFound(path.clone())),
+            }
+        };
+
+        if old != name && parent.get(&name).is_some() {
+            let mut new_path = parent_path;
+            new_path.push(name);
+            return Err(FsError::PathExists(new_path));
+        }
+
+        // The entry is removed first, since replacing it would keep the name it was stored under.
+        let hint = parent.get_hint(&old).copied();
+        let link = parent
+            .remove(&old)
+            .ok_or_else(|| FsError::NotFound(path.clone()))?;
+        let cid = *link.get_cid();
+        match hint {
+            Some(hint) => parent.put_with_hint(name, cid, hint)?,
+            None => parent.put(name, cid)?,
+        }
+        parent.set_times(None, Some(current_time()));
+
+        let Some((pathdirs, parent_name)) = spine else {
+            return Ok(parent);
+        };
+
+        let (root, _) = commit_spine(self, &pathdirs, &parent_name, parent.store().await?).await?;
+        Ok(root)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use zeroutils_store::MemoryStore;
+
+    use crate::filesystem::{Chunker, File};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_rename_at_changes_case() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let mut file = File::new(store.clone());
+        file.put_content(&b"data"[..], &Chunker::Store).await?;
+        let file = file.store().await?;
+
+        let root = Dir::new(store)
+            .link_at(&"docs/readme".parse()?, file)
+            .await?
+            .link_at(&"docs/notes".parse()?, file)
+            .await?;
+
+        let root = root
+            .rename_at(&"docs/readme".parse()?, "README".parse()?)
+            .await?;
+        let TraceResult::Found {
+            entity: Entity::Dir(docs),
+            ..
+        } = root.trace_entity(&"docs".parse()?).await?
+        else {
+            anyhow::bail!("docs is not a directory");
+        };
+
+        let name = docs.get_name(&"readme".parse()?).unwrap();
+        assert_eq!(name.as_str(), "README");
+        assert_eq!(name.canonicalize().as_str(), "readme");
+        assert_eq!(docs.len(), 2);
+
+        assert!(matches!(
+            root.rename_at(&"docs/readme".parse()?, "Notes".parse()?)
+                .await,
+            Err(FsError::PathExists(_))
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,198 @@
+use serde::{Deserialize, Serialize};
+use serde_with::serde_as;
+use zeroutils_store::{ipld::cid::Cid, IpldStore, Storable};
+
+use crate::filesystem::{
+    DescriptorFlags, Dir, DirHandle, Entity, FsError, FsResult, Metadata, Path,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The CID and metadata of an entity, as returned by [`Dir::stat_many`].
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EntityStat {
+    /// The CID of the entity.
+    #[serde_as(as = "serde_with::DisplayFromStr")]
+    pub cid: Cid,
+
+    /// The metadata of the entity.
+    pub metadata: Metadata,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl<S> Dir<S>
+where
+    S: IpldStore,
+{
+    /// Returns the [`EntityStat`] of the entity at each of `paths`, in the same order, or `None`
+    /// for the paths nothing is at. An empty path refers to the directory itself.
+    ///
+    /// The paths are resolved in name order, so the directories along a prefix that several paths
+    /// share are only loaded once rather than once per path. Symbolic links are not followed.
+    pub async fn stat_many(&self, paths: &[Path]) -> FsResult<Vec<Option<EntityStat>>>
+    where
+        S: Send + Sync,
+    {
+        let mut order = (0..paths.len()).collect::<Vec<_>>();
+        order.sort_by(|&a, &b| paths[a].get_segments().cmp(paths[b].get_segments()));
+
+        // The directories along the path resolved last, starting with this one.
+        let mut chain = vec![self.clone()];
+        let mut previous = Default::default();
+        let mut stats = vec![None; paths.len()];
+        for index in order {
+            let segments = paths[index].get_segments();
+            let Some((name, parents)) = segments.split_last() else {
+                stats[index] = Some(EntityStat {
+                    cid: self.store().await?,
+                    metadata: self.get_metadata().clone(),
+                });
+                continue;
+            };
+
+            let shared = parents
+                .iter()
+                .zip(previous)
+                .take_while(|(a, b)| a == b)
+                .count();
+            chain.truncate((shared + 1).min(chain.len()));
+            previous = parents;
+
+            for segment in &parents[chain.len() - 1..] {
+                let dir = chain.last().unwrap();
+                let Some(Entity::Dir(child)) = dir.get_entity(segment).await? else {
+                    break;
+                };
+
+                let child = child.clone();
+                chain.push(child);
+            }
+
+            if chain.len() <= parents.len() {
+                continue;
+            }
+
+            let parent = chain.last().unwrap();
+            let (Some(link), Some(entity)) = (parent.get(name), parent.get_entity(name).await?)
+            else {
+                continue;
+            };
+
+            stats[index] = Some(EntityStat {
+                cid: *link.get_cid(),
+                metadata: entity.get_metadata().clone(),
+            });
+        }
+
+        Ok(stats)
+    }
+
+    /// Returns whether there is an entity at each of `paths`, in the same order.
+    ///
+    /// Paths are resolved like in [`stat_many`][Self::stat_many].
+    pub async fn exists_many(&self, paths: &[Path]) -> FsResult<Vec<bool>>
+    where
+        S: Send + Sync,
+    {
+        let stats = self.stat_many(paths).await?;
+        Ok(stats.iter().map(Option::is_some).collect())
+    }
+}
+
+impl<S, T> DirHandle<S, T>
+where
+    S: IpldStore,
+    T: IpldStore + Send + Sync,
+{
+    /// Returns the [`EntityStat`] of the entity at each of `paths`, relative to the directory, in
+    /// the same order. See [`Dir::stat_many`].
+    pub async fn stat_many(&self, paths: &[Path]) -> FsResult<Vec<Option<EntityStat>>> {
+        self.check_readable()?;
+        self.entity().stat_many(paths).await
+    }
+
+    /// Returns whether there is an entity at each of `paths`, relative to the directory, in the
+    /// same order. See [`Dir::exists_many`].
+    pub async fn exists_many(&self, paths: &[Path]) -> FsResult<Vec<bool>> {
+        self.check_readable()?;
+        self.entity().exists_many(paths).await
+    }
+
+    /// Fails if the handle was not opened for reading.
+    fn check_readable(&self) -> FsResult<()> {
+        if !self.flags().contains(DescriptorFlags::READ) {
+            return Err(FsError::WrongFileDescriptorFlags(
+                self.path(),
+                *self.flags(),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use zeroutils_store::MemoryStore;
+
+    use crate::filesystem::{Chunker, EntityType, File};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_stat_many() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let mut file = File::new(store.clone());
+        file.put_content(&b"data"[..], &Chunker::Store).await?;
+        let file = file.store().await?;
+
+        let root = Dir::new(store)
+            .link_at(&"src/lib".parse()?, file)
+            .await?
+            .link_at(&"src/fs/dir".parse()?, file)
+            .await?;
+
+        let paths = [
+            "src/fs/dir",
+            "src/lib",
+            "src/fs",
+            "src/main",
+            "src/lib/x",
+            "",
+        ]
+        .iter()
+        .map(|path| path.parse())
+        .collect::<Result<Vec<Path>, _>>()?;
+
+        let stats = root.stat_many(&paths).await?;
+        assert_eq!(stats[0].as_ref().map(|stat| stat.cid), Some(file));
+        assert_eq!(stats[1].as_ref().map(|stat| stat.cid), Some(file));
+        assert_eq!(
+            stats[2].as_ref().map(|stat| stat.metadata.entity_type),
+            Some(EntityType::Dir)
+        );
+        assert!(stats[3].is_none());
+        assert!(stats[4].is_none());
+        assert_eq!(
+            stats[5].as_ref().map(|stat| stat.cid),
+            Some(root.store().await?)
+        );
+
+        assert_eq!(
+            root.exists_many(&paths).await?,
+            [true, true, true, false, false, true]
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,114 @@
+use chrono::{DateTime, Utc};
+use zeroutils_store::{IpldStore, Storable};
+
+use crate::filesystem::{Dir, FsError, FsResult, Path};
+
+use super::TraceResult;
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl<S> Dir<S>
+where
+    S: IpldStore,
+{
+    /// Sets the access time and the modification time of the entity at `path` to the ones given,
+    /// leaving the others as they are, and returns the updated directory. An empty path refers to
+    /// the directory itself.
+    ///
+    /// This is the equivalent of WASI `path_filestat_set_times` and POSIX `utimensat`, for tools
+    /// like `rsync` and archive extractors that restore the times of what they copy. Like
+    /// [`link_at`][Self::link_at], the directories along the path are rewritten and stored but the
+    /// directory itself is not, and their own times are kept.
+    pub async fn set_times_at(
+        &self,
+        path: &Path,
+        accessed_at: Option<DateTime<Utc>>,
+        modified_at: Option<DateTime<Utc>>,
+    ) -> FsResult<Dir<S>>
+    where
+        S: Send + Sync + 'static,
+    {
+        if path.is_empty() {
+            let mut dir = self.clone();
+            dir.set_times(accessed_at, modified_at);
+            return Ok(dir);
+        }
+
+        let mut entity = match self.trace_entity(path).await? {
+            TraceResult::Found { entity, .. } => entity,
+            _ => return Err(FsError::NotFound(path.clone())),
+        };
+
+        entity.set_times(accessed_at, modified_at);
+        self.link_at(path, entity.store().await?).await
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use chrono::TimeZone;
+    use zeroutils_store::MemoryStore;
+
+    use crate::filesystem::{with_clock, Chunker, Entity, File, FixedClock};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_times_update_policy() -> anyhow::Result<()> {
+        let clock = Arc::new(FixedClock::new(Utc.timestamp_opt(1_000, 0).unwrap()));
+        with_clock(clock.clone(), async {
+            let store = MemoryStore::default();
+            let mut file = File::new(store.clone());
+            file.put_content(&b"data"[..], &Chunker::Store).await?;
+            let file = file.store().await?;
+            let root = Dir::new(store).link_at(&"docs/a".parse()?, file).await?;
+
+            // Adding an entry modifies the directory it is added to.
+            clock.set(Utc.timestamp_opt(2_000, 0).unwrap());
+            let root = root.link_at(&"docs/b".parse()?, file).await?;
+            assert_eq!(modified_at(&root, "docs").await?, 2_000);
+
+            // Replacing an entry does not.
+            clock.set(Utc.timestamp_opt(3_000, 0).unwrap());
+            let root = root.link_at(&"docs/b".parse()?, file).await?;
+            assert_eq!(modified_at(&root, "docs").await?, 2_000);
+
+            let root = root
+                .set_times_at(
+                    &"docs/a".parse()?,
+                    None,
+                    Some(Utc.timestamp_opt(42, 0).unwrap()),
+                )
+                .await?;
+            assert_eq!(modified_at(&root, "docs/a").await?, 42);
+            assert_eq!(modified_at(&root, "docs").await?, 2_000);
+
+            // Removing an entry modifies the directory it is removed from.
+            let (root, _) = root.unlink_at(&"docs/a".parse()?).await?;
+            assert_eq!(modified_at(&root, "docs").await?, 3_000);
+
+            anyhow::Ok(())
+        })
+        .await
+    }
+
+    async fn modified_at(root: &Dir<MemoryStore>, path: &str) -> anyhow::Result<i64> {
+        let TraceResult::Found { entity, .. } = root.trace_entity(&path.parse()?).await? else {
+            anyhow::bail!("nothing at {}", path);
+        };
+
+        if let Entity::File(file) = &entity {
+            assert!(file.get_metadata().accessed_at.is_none());
+        }
+
+        Ok(entity.get_metadata().modified_at.timestamp())
+    }
+}
@@ -0,0 +1,154 @@
+use std::convert::TryInto;
+
+use tokio::io::AsyncRead;
+use zeroutils_key::GetPublicKey;
+use zeroutils_store::{ipld::cid::Cid, IpldStore, Storable};
+use zeroutils_ucan::UcanAuth;
+
+use crate::filesystem::{
+    Chunker, DescriptorFlags, DirHandle, Entity, EntityAttributes, File, FsError, FsResult, Path,
+    TraceResult,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl<S, T> DirHandle<S, T>
+where
+    S: IpldStore + Send + Sync + 'static,
+    T: IpldStore + Send + Sync + 'static,
+{
+    /// Writes the content coming through `reader` to the file at `path`, relative to the
+    /// directory, replacing the file if there is one, and returns the [`Cid`] of the new file.
+    ///
+    /// The content is split by `chunker` and stored in full before the file is linked under its
+    /// name in a single change to the root directory, like writing a temporary file and renaming
+    /// it over the old one. Readers see either the old file or the whole new one, never part of
+    /// the content, and nothing changes if reading or storing the content fails.
+    ///
+    /// Fails with [`FsError::Immutable`] or [`FsError::AppendOnly`] if the file being replaced is
+    /// protected, and with [`FsError::NotAFile`] if there is something other than a file at `path`.
+    pub async fn write_file_atomic<U, K>(
+        &self,
+        path: impl TryInto<Path, Error: Into<FsError>>,
+        reader: impl AsyncRead + Send + Sync,
+        chunker: &Chunker,
+        _ucan: UcanAuth<'_, U, K>,
+    ) -> FsResult<Cid>
+    where
+        U: IpldStore,
+        K: GetPublicKey,
+    {
+        let mut full_path = self.path();
+        full_path.extend(path.try_into().map_err(Into::into)?.iter().cloned());
+
+        let root = self.root();
+        if root.is_read_only() {
+            return Err(FsError::ReadOnlyFilesystem(full_path));
+        }
+
+        if !self.flags().contains(DescriptorFlags::MUTATE_DIR) {
+            return Err(FsError::WrongFileDescriptorFlags(full_path, *self.flags()));
+        }
+
+        // TODO: Check if user has capabilities to write the file.
+
+        if let TraceResult::Found { entity, .. } = root.fork().trace_entity(&full_path).await? {
+            let Entity::File(file) = entity else {
+                return Err(FsError::NotAFile(Some(full_path)));
+            };
+
+            let attributes = file.get_metadata().attributes;
+            if attributes.contains(EntityAttributes::IMMUTABLE) {
+                return Err(FsError::Immutable(full_path));
+            }
+
+            if attributes.contains(EntityAttributes::APPEND_ONLY) {
+                return Err(FsError::AppendOnly(full_path));
+            }
+        }
+
+        let mut file = File::new(root.get_store());
+        file.put_content(reader, chunker).await?;
+        let cid = file.store().await?;
+
+        root.link_at(&full_path, cid).await?;
+
+        Ok(cid)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{self, AsyncReadExt};
+    use zeroutils_key::{Ed25519KeyPair, KeyPairGenerate};
+    use zeroutils_store::{MemoryStore, PlaceholderStore};
+
+    use crate::{filesystem::RootDir, utils::fixture};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_write_file_atomic_replaces_whole_file() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let iss_key = Ed25519KeyPair::generate(&mut rand::thread_rng())?;
+        let auth = || fixture::mock_ucan_auth(&iss_key, PlaceholderStore);
+        let root = RootDir::new(store.clone());
+        let handle = root.make_handle(DescriptorFlags::READ | DescriptorFlags::MUTATE_DIR);
+
+        handle
+            .write_file_atomic("config", &b"old"[..], &Chunker::Store, auth()?)
+            .await?;
+
+        // A failed write leaves the old file in place.
+        let failing = io::repeat(0).take(8).chain(FailingReader);
+        let result = handle
+            .write_file_atomic("config", failing, &Chunker::Store, auth()?)
+            .await;
+        assert!(result.is_err());
+        assert_eq!(read_file(&root, "config").await?.1, "old");
+
+        let cid = handle
+            .write_file_atomic("config", &b"new"[..], &Chunker::Store, auth()?)
+            .await?;
+        assert_eq!(read_file(&root, "config").await?, (cid, "new".to_owned()));
+
+        Ok(())
+    }
+
+    async fn read_file(root: &RootDir<MemoryStore>, path: &str) -> anyhow::Result<(Cid, String)> {
+        let TraceResult::Found {
+            entity: Entity::File(file),
+            ..
+        } = root.fork().trace_entity(&path.parse()?).await?
+        else {
+            anyhow::bail!("no file at {}", path);
+        };
+
+        let mut content = String::new();
+        file.get_content_reader()
+            .await?
+            .read_to_string(&mut content)
+            .await?;
+
+        Ok((file.store().await?, content))
+    }
+
+    /// A reader that always fails.
+    struct FailingReader;
+
+    impl AsyncRead for FailingReader {
+        fn poll_read(
+            self: std::pin::Pin<&mut Self>,
+            _: &mut std::task::Context<'_>,
+            _: &mut io::ReadBuf<'_>,
+        ) -> std::task::Poll<io::Result<()>> {
+            std::task::Poll::Ready(Err(io::Error::other("connection reset")))
+        }
+    }
+}
@@ -1,10 +1,13 @@
 use core::fmt;
 use std::{fmt::Debug, ops::Deref};
 
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
 use zeroutils_store::{ipld::cid::Cid, IpldStore, Storable, StoreResult};
 
 use super::{
-    DescriptorFlags, Dir, File, FsError, FsResult, Handle, Metadata, PathSegment, RootDir, Symlink,
+    Acl, DescriptorFlags, Dir, EntityType, File, FsError, FsResult, Handle, KeyGrants, Metadata,
+    PathSegment, RootDir, Symlink,
 };
 
 //--------------------------------------------------------------------------------------------------
@@ -27,6 +30,14 @@ where
     Symlink(Symlink<S>),
 }
 
+/// The part of a serialized entity shared by all entity types.
+///
+/// Used to find out what type of entity a stored node is before loading it.
+#[derive(Debug, Deserialize)]
+pub(crate) struct EntitySerializable {
+    metadata: Metadata,
+}
+
 /// A handle for an open file system entity.
 #[derive(Debug)]
 pub struct EntityHandle<S, T>(Handle<Entity<T>, S, T>)
@@ -79,6 +90,46 @@ where
         }
     }
 
+    /// Sets the access control list of the entity.
+    pub fn set_acl(&mut self, acl: Acl) {
+        match self {
+            Entity::File(file) => file.set_acl(acl),
+            Entity::Dir(dir) => dir.set_acl(acl),
+            Entity::Symlink(symlink) => symlink.set_acl(acl),
+        }
+    }
+
+    /// Sets the grants of the content key of the entity.
+    pub fn set_key_grants(&mut self, grants: KeyGrants) {
+        match self {
+            Entity::File(file) => file.set_key_grants(grants),
+            Entity::Dir(dir) => dir.set_key_grants(grants),
+            Entity::Symlink(symlink) => symlink.set_key_grants(grants),
+        }
+    }
+
+    /// Sets the number of times the content key of the entity was rotated.
+    pub fn set_key_epoch(&mut self, epoch: u32) {
+        match self {
+            Entity::File(file) => file.set_key_epoch(epoch),
+            Entity::Dir(dir) => dir.set_key_epoch(epoch),
+            Entity::Symlink(symlink) => symlink.set_key_epoch(epoch),
+        }
+    }
+
+    /// Sets the access and modification times of the entity, see [`Metadata::set_times`].
+    pub fn set_times(
+        &mut self,
+        accessed_at: Option<DateTime<Utc>>,
+        modified_at: Option<DateTime<Utc>>,
+    ) {
+        match self {
+            Entity::File(file) => file.set_times(accessed_at, modified_at),
+            Entity::Dir(dir) => dir.set_times(accessed_at, modified_at),
+            Entity::Symlink(symlink) => symlink.set_times(accessed_at, modified_at),
+        }
+    }
+
     /// Change the store used to persist the entity.
     pub fn use_store<T>(self, store: T) -> Entity<T>
     where
@@ -188,9 +239,13 @@ where
         }
     }
 
-    async fn load(_cid: &Cid, _store: S) -> StoreResult<Self> {
-        // TODO: Implement
-        unimplemented!()
+    async fn load(cid: &Cid, store: S) -> StoreResult<Self> {
+        let serializable: EntitySerializable = store.get_node(cid).await?;
+        match serializable.metadata.entity_type {
+            EntityType::File => Ok(Entity::File(File::load(cid, store).await?)),
+            EntityType::Dir => Ok(Entity::Dir(Dir::load(cid, store).await?)),
+            EntityType::Symlink => Ok(Entity::Symlink(Symlink::load(cid, store).await?)),
+        }
     }
 }
 
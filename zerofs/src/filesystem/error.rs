@@ -1,8 +1,10 @@
-use std::{error::Error, fmt::Display};
+use std::{error::Error, fmt::Display, path::PathBuf, time::Duration};
 
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
+use zeroutils_store::ipld::cid::Cid;
 
-use super::{DescriptorFlags, OpenFlags, Path};
+use super::{DescriptorFlags, FsAction, FsResource, OpenFlags, Path};
 
 //--------------------------------------------------------------------------------------------------
 // Types
@@ -38,6 +40,10 @@ pub enum FsError {
     #[error("Not found: {0}")]
     NotFound(Path),
 
+    /// Path is empty where at least one segment is required.
+    #[error("Path is empty")]
+    EmptyPath,
+
     /// Leading `.` in path.
     #[error("Leading `.` in path")]
     LeadingCurrentDir,
@@ -100,9 +106,256 @@ pub enum FsError {
     #[error("Invalid open flags combination: path: {0}, open_flags: {1:?}")]
     InvalidOpenFlagsCombination(Path, OpenFlags),
 
+    /// Io error.
+    #[error("Io error: {0}")]
+    Io(#[from] std::io::Error),
+
+    /// Output stream has already been closed.
+    #[error("Output stream has already been closed")]
+    StreamClosed,
+
+    /// Following symbolic links on the local file system leads to a loop.
+    #[error("Symlink loop in local file system: {0:?}")]
+    LocalSymlinkLoop(PathBuf),
+
+    /// Something already exists at a local path being written to.
+    #[error("Local entry already exists: {0:?}")]
+    LocalEntryExists(PathBuf),
+
     /// Symlink not supported yet.
     #[error("Symlink not supported yet: path: {0}")]
     SymLinkNotSupportedYet(Path),
+
+    /// Invalid capability resource.
+    #[error("Invalid capability resource: {0}")]
+    InvalidFsResource(String),
+
+    /// Invalid capability action.
+    #[error("Invalid capability action: {0}")]
+    InvalidFsAction(String),
+
+    /// The group memberships document could not be read or written.
+    #[error("Invalid group document: {0}")]
+    InvalidGroupDocument(String),
+
+    /// The trash index could not be read or written.
+    #[error("Invalid trash index: {0}")]
+    InvalidTrashIndex(String),
+
+    /// The path is inside the trash.
+    #[error("Path is inside the trash: {0}")]
+    PathInTrash(Path),
+
+    /// The snapshot index could not be read or written.
+    #[error("Invalid snapshot index: {0}")]
+    InvalidSnapshotIndex(String),
+
+    /// No snapshot with the ID exists.
+    #[error("Snapshot not found: {0}")]
+    SnapshotNotFound(String),
+
+    /// Something already exists at the path.
+    #[error("Path already exists: {0}")]
+    PathExists(Path),
+
+    /// The file system is read-only.
+    #[error("Read-only file system: {0}")]
+    ReadOnlyFilesystem(Path),
+
+    /// The file is immutable.
+    #[error("Entity is immutable: {0}")]
+    Immutable(Path),
+
+    /// The file is append-only.
+    #[error("Entity is append-only: {0}")]
+    AppendOnly(Path),
+
+    /// The path leads into a part of the file tree this node does not replicate.
+    #[error("Not replicated locally: {0}")]
+    NotReplicatedLocally(Path),
+
+    /// A block got a different CID in the store it was migrated to.
+    #[error("Migrated block got a different CID in the destination store: {0}")]
+    MigrationMismatch(Cid),
+
+    /// The cursor is past the head of the change feed.
+    #[error("Invalid change feed cursor: {0}")]
+    InvalidCursor(u64),
+
+    /// The ref name is not valid.
+    #[error("Invalid ref name: {0:?}")]
+    InvalidRefName(String),
+
+    /// The ref index could not be read or written.
+    #[error("Invalid ref index: {0}")]
+    InvalidRefIndex(String),
+
+    /// No ref with the name exists.
+    #[error("Ref not found: {0}")]
+    RefNotFound(String),
+
+    /// The ref does not point at what the update expected.
+    #[error("Ref was changed concurrently: {0}")]
+    RefConflict(String),
+
+    /// The derived index could not be read or written.
+    #[error("Invalid derived index: {0}")]
+    InvalidDerivedIndex(String),
+
+    /// No HEAD has been saved yet, so there is no file tree to open.
+    #[error("HEAD not found")]
+    HeadNotFound,
+
+    /// A HEAD has already been saved, so a new file tree would replace the one it points at.
+    #[error("HEAD already exists: {0}")]
+    HeadExists(Cid),
+
+    /// The HEAD could not be read or written.
+    #[error("Invalid HEAD: {0}")]
+    InvalidHead(String),
+
+    /// The seek would move the position of an open file before its start.
+    #[error("Seek before the start of the file: {0}")]
+    InvalidSeek(Path),
+
+    /// The directory already has as many entries as it can have.
+    #[error("Too many entries in directory: {0} (limit: {1})")]
+    TooManyEntries(Path, usize),
+
+    /// The path has more segments than a path can have.
+    #[error("Path too deep: {0} (limit: {1})")]
+    PathTooDeep(Path, usize),
+
+    /// The name of the entry at the path is longer than a name can be.
+    #[error("Name too long: {0} (limit: {1})")]
+    NameTooLong(Path, usize),
+
+    /// The directory is not empty.
+    #[error("Directory not empty: {0}")]
+    DirNotEmpty(Path),
+
+    /// The entity at the path no longer has the expected CID.
+    #[error("Entity changed: {0} (now: {1})")]
+    EntityChanged(Path, Cid),
+
+    /// The root directory no longer has the expected CID.
+    #[error("Root changed (now: {0})")]
+    RootChanged(Cid),
+
+    /// The glob pattern is not valid.
+    #[error("Invalid glob pattern: {0:?}")]
+    InvalidGlobPattern(String),
+
+    /// Matching the glob pattern looked at more entries than it is allowed to.
+    #[error("Glob pattern matched too broadly: {0} (limit: {1} entries)")]
+    GlobLimitExceeded(String, usize),
+
+    /// An inclusion proof does not show the entity to be at the path under the root.
+    #[error("Invalid inclusion proof for {0}: {1}")]
+    InvalidProof(Path, String),
+
+    /// The store is too full to take new blocks. Reads are still served.
+    #[error("Store is full: {0}")]
+    StoreFull(String),
+
+    /// An operation ran past its timeout and was cancelled.
+    #[error("Operation {0} timed out after {1:?}")]
+    Timeout(String, Duration),
+
+    /// The CID to link as the content of a file is not content stored in full.
+    #[error("Invalid content: {0}")]
+    InvalidContent(String),
+
+    /// The chunk size limits of a FastCDC chunker are out of order or out of bounds.
+    #[error("Invalid chunker: {0}")]
+    InvalidChunker(String),
+
+    /// Following the symbolic links along a path takes more hops than allowed, usually because
+    /// they lead to one another.
+    #[error("Too many levels of symbolic links: {0} (limit: {1})")]
+    SymlinkLoop(Path, usize),
+}
+
+/// A stable code for the kind of an error, for clients to branch on instead of parsing messages.
+///
+/// Each code has a number and a name, which never change once released. New codes may be added,
+/// so clients should treat unknown codes like [`ErrorCode::Internal`]. Codes are grouped by
+/// hundreds: paths and entities, then access, conflicts, limits and the store.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[repr(u16)]
+pub enum ErrorCode {
+    /// An unexpected error on the node.
+    Internal = 1,
+
+    /// The request is malformed or one of its arguments is not valid.
+    InvalidArgument = 2,
+
+    /// The operation is not supported by the node.
+    NotSupported = 3,
+
+    /// The node cannot serve the request for now.
+    Unavailable = 4,
+
+    /// The operation ran past its timeout.
+    Timeout = 5,
+
+    /// A path is not valid.
+    InvalidPath = 100,
+
+    /// Nothing is at a path, or nothing has a name or ID.
+    NotFound = 101,
+
+    /// Something is already at a path.
+    AlreadyExists = 102,
+
+    /// An entity is not a file.
+    NotAFile = 103,
+
+    /// An entity is not a directory.
+    NotADirectory = 104,
+
+    /// A directory is not empty.
+    DirNotEmpty = 105,
+
+    /// The request does not say who it is from.
+    Unauthenticated = 200,
+
+    /// The caller is not allowed to do this.
+    PermissionDenied = 201,
+
+    /// A signature or proof does not verify.
+    InvalidSignature = 202,
+
+    /// Something changed since the caller last saw it.
+    ConcurrentModification = 300,
+
+    /// The file tree is read-only.
+    ReadOnly = 301,
+
+    /// An entity is immutable.
+    Immutable = 302,
+
+    /// An entity can only be appended to.
+    AppendOnly = 303,
+
+    /// A limit on the shape of the tree or on a request was exceeded.
+    LimitExceeded = 400,
+
+    /// The body of the request is too large.
+    PayloadTooLarge = 401,
+
+    /// The store is too full to take new blocks.
+    StoreFull = 500,
+
+    /// The store failed to read or write a block.
+    StoreError = 501,
+
+    /// Some of the tree is not stored on the node.
+    NotReplicated = 502,
+
+    /// Data in the store, like an index, is corrupt or does not match the configuration.
+    CorruptData = 503,
 }
 
 /// Permission error.
@@ -111,6 +364,10 @@ pub enum PermissionError {
     /// Child descriptor has higher permission than parent.
     #[error("Child descriptor has higher permission than parent: path: {0}, parent(descriptor_flags: {1:?}) child (descriptor_flags: {2:?}, open_flags: {3:?})")]
     ChildPermissionEscalation(Path, DescriptorFlags, DescriptorFlags, OpenFlags),
+
+    /// No capability allows the action on the resource.
+    #[error("Capability not granted: resource: {0}, action: {1}")]
+    CapabilityNotGranted(FsResource, FsAction),
 }
 
 /// An error that can represent any error.
@@ -130,6 +387,168 @@ impl FsError {
             error: error.into(),
         })
     }
+
+    /// Returns the stable code of the kind of the error.
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            FsError::Infallible(_) | FsError::Custom(_) | FsError::Io(_) | FsError::Did(_) => {
+                ErrorCode::Internal
+            }
+            FsError::InvalidOpenFlag(_)
+            | FsError::InvalidEntityFlag(_)
+            | FsError::InvalidPathFlag(_)
+            | FsError::InvalidOpenFlagsCombination(..)
+            | FsError::InvalidFsResource(_)
+            | FsError::InvalidFsAction(_)
+            | FsError::InvalidCursor(_)
+            | FsError::InvalidRefName(_)
+            | FsError::InvalidSeek(_)
+            | FsError::InvalidGlobPattern(_)
+            | FsError::InvalidContent(_)
+            | FsError::InvalidChunker(_)
+            | FsError::StreamClosed
+            | FsError::PathInTrash(_)
+            | FsError::LocalSymlinkLoop(_)
+            | FsError::SymlinkLoop(..) => ErrorCode::InvalidArgument,
+            FsError::SymLinkNotSupportedYet(_) => ErrorCode::NotSupported,
+            FsError::InvalidPathSegment(_)
+            | FsError::EmptyPath
+            | FsError::LeadingCurrentDir
+            | FsError::OutOfBoundsParentDir => ErrorCode::InvalidPath,
+            FsError::NotFound(_)
+            | FsError::SnapshotNotFound(_)
+            | FsError::RefNotFound(_)
+            | FsError::HeadNotFound => ErrorCode::NotFound,
+            FsError::PathExists(_)
+            | FsError::OpenFlagsExclusiveButEntityExists(..)
+            | FsError::LocalEntryExists(_)
+            | FsError::HeadExists(_) => ErrorCode::AlreadyExists,
+            FsError::NotAFile(_) => ErrorCode::NotAFile,
+            FsError::NotADirectory(_)
+            | FsError::NotAFileOrDir(_)
+            | FsError::OpenFlagsDirectoryButEntityNotADir(..) => ErrorCode::NotADirectory,
+            FsError::DirNotEmpty(_) => ErrorCode::DirNotEmpty,
+            FsError::Ucan(_)
+            | FsError::PermissionError(_)
+            | FsError::WrongFileDescriptorFlags(..)
+            | FsError::NeedAtLeastReadFlag(..) => ErrorCode::PermissionDenied,
+            FsError::InvalidProof(..) => ErrorCode::InvalidSignature,
+            FsError::RefConflict(_) | FsError::EntityChanged(..) | FsError::RootChanged(_) => {
+                ErrorCode::ConcurrentModification
+            }
+            FsError::ReadOnlyFilesystem(_) => ErrorCode::ReadOnly,
+            FsError::Immutable(_) => ErrorCode::Immutable,
+            FsError::AppendOnly(_) => ErrorCode::AppendOnly,
+            FsError::TooManyEntries(..)
+            | FsError::PathTooDeep(..)
+            | FsError::NameTooLong(..)
+            | FsError::GlobLimitExceeded(..) => ErrorCode::LimitExceeded,
+            FsError::StoreFull(_) => ErrorCode::StoreFull,
+            FsError::Timeout(..) => ErrorCode::Timeout,
+            FsError::IpldStore(_) | FsError::MigrationMismatch(_) => ErrorCode::StoreError,
+            FsError::NotReplicatedLocally(_) => ErrorCode::NotReplicated,
+            FsError::InvalidGroupDocument(_)
+            | FsError::InvalidTrashIndex(_)
+            | FsError::InvalidSnapshotIndex(_)
+            | FsError::InvalidRefIndex(_)
+            | FsError::InvalidDerivedIndex(_)
+            | FsError::InvalidHead(_) => ErrorCode::CorruptData,
+        }
+    }
+
+    /// Returns `true` if the error is transient, like a store timeout, so that the operation may
+    /// succeed if it is tried again as is. Errors from validating the operation are never
+    /// retryable.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            FsError::Io(e) => is_transient_io_error(e),
+            e => e.code().is_retryable(),
+        }
+    }
+}
+
+impl ErrorCode {
+    /// Every code, in order of their numbers.
+    pub const ALL: [ErrorCode; 24] = [
+        ErrorCode::Internal,
+        ErrorCode::InvalidArgument,
+        ErrorCode::NotSupported,
+        ErrorCode::Unavailable,
+        ErrorCode::Timeout,
+        ErrorCode::InvalidPath,
+        ErrorCode::NotFound,
+        ErrorCode::AlreadyExists,
+        ErrorCode::NotAFile,
+        ErrorCode::NotADirectory,
+        ErrorCode::DirNotEmpty,
+        ErrorCode::Unauthenticated,
+        ErrorCode::PermissionDenied,
+        ErrorCode::InvalidSignature,
+        ErrorCode::ConcurrentModification,
+        ErrorCode::ReadOnly,
+        ErrorCode::Immutable,
+        ErrorCode::AppendOnly,
+        ErrorCode::LimitExceeded,
+        ErrorCode::PayloadTooLarge,
+        ErrorCode::StoreFull,
+        ErrorCode::StoreError,
+        ErrorCode::NotReplicated,
+        ErrorCode::CorruptData,
+    ];
+
+    /// Returns the number of the code.
+    pub fn number(&self) -> u16 {
+        *self as u16
+    }
+
+    /// Returns the code with the given number, if there is one.
+    pub fn from_number(number: u16) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|code| code.number() == number)
+    }
+
+    /// Returns `true` if errors with the code are transient, see [`FsError::is_retryable`].
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            ErrorCode::Unavailable
+                | ErrorCode::Timeout
+                | ErrorCode::StoreError
+                | ErrorCode::NotReplicated
+        )
+    }
+
+    /// Returns the name of the code, as it is serialized.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::Internal => "internal",
+            ErrorCode::InvalidArgument => "invalid_argument",
+            ErrorCode::NotSupported => "not_supported",
+            ErrorCode::Unavailable => "unavailable",
+            ErrorCode::Timeout => "timeout",
+            ErrorCode::InvalidPath => "invalid_path",
+            ErrorCode::NotFound => "not_found",
+            ErrorCode::AlreadyExists => "already_exists",
+            ErrorCode::NotAFile => "not_a_file",
+            ErrorCode::NotADirectory => "not_a_directory",
+            ErrorCode::DirNotEmpty => "dir_not_empty",
+            ErrorCode::Unauthenticated => "unauthenticated",
+            ErrorCode::PermissionDenied => "permission_denied",
+            ErrorCode::InvalidSignature => "invalid_signature",
+            ErrorCode::ConcurrentModification => "concurrent_modification",
+            ErrorCode::ReadOnly => "read_only",
+            ErrorCode::Immutable => "immutable",
+            ErrorCode::AppendOnly => "append_only",
+            ErrorCode::LimitExceeded => "limit_exceeded",
+            ErrorCode::PayloadTooLarge => "payload_too_large",
+            ErrorCode::StoreFull => "store_full",
+            ErrorCode::StoreError => "store_error",
+            ErrorCode::NotReplicated => "not_replicated",
+            ErrorCode::CorruptData => "corrupt_data",
+        }
+    }
 }
 
 //--------------------------------------------------------------------------------------------------
@@ -142,6 +561,23 @@ pub fn Ok<T>(value: T) -> FsResult<T> {
     Result::Ok(value)
 }
 
+/// Returns `true` if the io error is one that may go away if the operation is tried again.
+pub(crate) fn is_transient_io_error(error: &std::io::Error) -> bool {
+    use std::io::ErrorKind;
+
+    matches!(
+        error.kind(),
+        ErrorKind::TimedOut
+            | ErrorKind::Interrupted
+            | ErrorKind::WouldBlock
+            | ErrorKind::ConnectionRefused
+            | ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionAborted
+            | ErrorKind::NotConnected
+            | ErrorKind::BrokenPipe
+    )
+}
+
 //--------------------------------------------------------------------------------------------------
 // Trait Implementations
 //--------------------------------------------------------------------------------------------------
@@ -159,3 +595,50 @@ impl Display for AnyError {
 }
 
 impl Error for AnyError {}
+
+impl Display for ErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_codes_are_stable() -> anyhow::Result<()> {
+        for code in ErrorCode::ALL.iter().copied() {
+            assert_eq!(ErrorCode::from_number(code.number()), Some(code));
+            assert_eq!(code.to_string(), code.as_str());
+        }
+
+        // Released codes must keep their numbers and names.
+        assert_eq!(ErrorCode::NotFound.number(), 101);
+        assert_eq!(
+            ErrorCode::ConcurrentModification.as_str(),
+            "concurrent_modification"
+        );
+        assert_eq!(
+            FsError::NotFound("a/b".parse()?).code(),
+            ErrorCode::NotFound
+        );
+        assert_eq!(
+            FsError::StoreFull(String::new()).code(),
+            ErrorCode::StoreFull
+        );
+
+        // Transient errors can be retried, validation failures cannot.
+        let timeout = std::io::Error::from(std::io::ErrorKind::TimedOut);
+        assert!(FsError::Io(timeout).is_retryable());
+        assert!(FsError::NotReplicatedLocally("a".parse()?).is_retryable());
+        assert!(!FsError::InvalidCursor(7).is_retryable());
+        assert!(!FsError::StoreFull(String::new()).is_retryable());
+
+        anyhow::Ok(())
+    }
+}
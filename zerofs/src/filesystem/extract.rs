@@ -0,0 +1,317 @@
+use std::{
+    collections::BTreeSet,
+    fmt::Debug,
+    sync::{Arc, Mutex},
+};
+
+use tokio::{
+    io::AsyncReadExt,
+    sync::mpsc::{self, UnboundedReceiver, UnboundedSender},
+    task::JoinHandle,
+};
+use zeroutils_store::{ipld::cid::Cid, IpldStore, Storable};
+
+use super::{tokenize, File, FsResult, Path};
+
+//--------------------------------------------------------------------------------------------------
+// Traits
+//--------------------------------------------------------------------------------------------------
+
+/// Extracts the text of files so that a [`SearchIndex`][super::SearchIndex] finds them by their
+/// content.
+///
+/// The extractors of a [`ContentWorker`] are tried in turn on the content of each file committed to
+/// the tree, and the text returned by the first one that handles it is indexed. Extraction runs on
+/// a blocking thread of the worker, so an extractor is free to take its time.
+pub trait ContentExtractor: Debug + Send + Sync {
+    /// Returns the text of `content`, or `None` if this extractor does not handle it.
+    ///
+    /// `content` is the start of the file, and may be cut short of its end.
+    fn extract(&self, content: &[u8]) -> Option<String>;
+}
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// A [`ContentExtractor`] for plain text, which handles any content that is UTF-8 without control
+/// characters other than whitespace.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TextExtractor;
+
+/// A [`ContentExtractor`] for Markdown, which handles text with headings, code fences or links,
+/// and leaves the targets of links and HTML tags out of the extracted text.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MarkdownExtractor;
+
+/// The end of a content extraction queue that a [`SearchIndex`][super::SearchIndex] hands files
+/// to and collects their extracted terms from.
+#[derive(Debug, Clone)]
+pub struct ContentQueue {
+    /// The files waiting to be extracted.
+    jobs: UnboundedSender<ContentJob>,
+
+    /// The terms extracted and not collected yet.
+    extracted: Arc<Mutex<Vec<ExtractedContent>>>,
+}
+
+/// The end of a content extraction queue that extracts the terms of the queued files in the
+/// background.
+#[derive(Debug)]
+pub struct ContentWorker<S>
+where
+    S: IpldStore,
+{
+    store: S,
+
+    /// The files waiting to be extracted.
+    jobs: UnboundedReceiver<ContentJob>,
+
+    /// The terms extracted and not collected yet.
+    extracted: Arc<Mutex<Vec<ExtractedContent>>>,
+
+    /// The extractors, in the order they are tried.
+    extractors: Vec<Arc<dyn ContentExtractor>>,
+
+    /// The number of bytes read from the start of a file to extract its text from.
+    max_size: u64,
+}
+
+/// A file waiting to be extracted.
+#[derive(Debug)]
+struct ContentJob {
+    path: Path,
+    cid: Cid,
+}
+
+/// The terms extracted from the content of a file.
+#[derive(Debug)]
+pub(super) struct ExtractedContent {
+    /// The path of the file when it was queued.
+    pub(super) path: Path,
+
+    /// The CID of the file when it was queued.
+    pub(super) cid: Cid,
+
+    /// The terms of the text of the file.
+    pub(super) terms: BTreeSet<String>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl ContentQueue {
+    /// Queues the file with the CID `cid` at `path` for extraction.
+    pub(super) fn enqueue(&self, path: Path, cid: Cid) {
+        // The worker may be gone, in which case the file is left waiting.
+        let _ = self.jobs.send(ContentJob { path, cid });
+    }
+
+    /// Takes the terms extracted since the last call.
+    pub(super) fn take_extracted(&self) -> Vec<ExtractedContent> {
+        std::mem::take(&mut *self.extracted.lock().unwrap())
+    }
+}
+
+impl<S> ContentWorker<S>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    /// Adds `extractor`, which is tried before the ones added before it and the built-in ones.
+    pub fn add_extractor(&mut self, extractor: Arc<dyn ContentExtractor>) {
+        self.extractors.insert(0, extractor);
+    }
+
+    /// Extracts the queued files until the [`ContentQueue`] is dropped.
+    pub async fn run(mut self) {
+        while let Some(job) = self.jobs.recv().await {
+            if let Err(e) = self.extract(job).await {
+                tracing::warn!("Failed to extract the content of a file: {}", e);
+            }
+        }
+    }
+
+    /// Spawns a task that [`run`][Self::run]s the worker.
+    pub fn spawn(self) -> JoinHandle<()> {
+        tokio::spawn(self.run())
+    }
+
+    /// Extracts the files queued so far and returns how many there were.
+    pub async fn run_pending(&mut self) -> FsResult<usize> {
+        let mut count = 0;
+        while let Ok(job) = self.jobs.try_recv() {
+            self.extract(job).await?;
+            count += 1;
+        }
+
+        Ok(count)
+    }
+
+    /// Extracts the terms of the file of `job` and hands them to the queue.
+    async fn extract(&self, job: ContentJob) -> FsResult<()> {
+        let file = File::load(&job.cid, self.store.clone()).await?;
+        let mut content = Vec::new();
+        file.get_content_reader()
+            .await?
+            .take(self.max_size)
+            .read_to_end(&mut content)
+            .await?;
+
+        let extractors = self.extractors.clone();
+        let terms = tokio::task::spawn_blocking(move || {
+            extractors
+                .iter()
+                .find_map(|extractor| extractor.extract(&content))
+                .map(|text| tokenize(&text).collect())
+                .unwrap_or_default()
+        })
+        .await
+        .unwrap_or_default();
+
+        // Files no extractor handles are reported too, so they stop waiting.
+        self.extracted.lock().unwrap().push(ExtractedContent {
+            path: job.path,
+            cid: job.cid,
+            terms,
+        });
+
+        Ok(())
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Creates a content extraction queue whose worker reads up to `max_size` bytes of each file from
+/// `store`, with the [`MarkdownExtractor`] and then the [`TextExtractor`].
+///
+/// The [`ContentQueue`] goes to a [`SearchIndex`][super::SearchIndex] and the [`ContentWorker`] to
+/// a background task, so that indexing the text of files does not hold up commits.
+pub fn content_queue<S>(store: S, max_size: u64) -> (ContentQueue, ContentWorker<S>)
+where
+    S: IpldStore,
+{
+    let (jobs, receiver) = mpsc::unbounded_channel();
+    let extracted = Arc::new(Mutex::new(Vec::new()));
+
+    let queue = ContentQueue {
+        jobs,
+        extracted: extracted.clone(),
+    };
+    let worker = ContentWorker {
+        store,
+        jobs: receiver,
+        extracted,
+        extractors: vec![Arc::new(MarkdownExtractor), Arc::new(TextExtractor)],
+        max_size,
+    };
+
+    (queue, worker)
+}
+
+/// Returns `content` as text if it is UTF-8 without control characters other than whitespace.
+fn decode_text(content: &[u8]) -> Option<&str> {
+    let text = match std::str::from_utf8(content) {
+        Ok(text) => text,
+        // The content was cut short in the middle of a character.
+        Err(e) if e.error_len().is_none() => {
+            std::str::from_utf8(&content[..e.valid_up_to()]).ok()?
+        }
+        Err(_) => return None,
+    };
+
+    text.chars()
+        .all(|c| !c.is_control() || c.is_whitespace())
+        .then_some(text)
+}
+
+/// Returns `true` if `line` is a Markdown heading or code fence, or has a link.
+fn is_markdown_line(line: &str) -> bool {
+    let line = line.trim_start();
+    let heading = line.trim_start_matches('#');
+
+    (heading.len() < line.len() && heading.starts_with(' '))
+        || line.starts_with("```")
+        || line.contains("](")
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl ContentExtractor for TextExtractor {
+    fn extract(&self, content: &[u8]) -> Option<String> {
+        decode_text(content).map(str::to_owned)
+    }
+}
+
+impl ContentExtractor for MarkdownExtractor {
+    fn extract(&self, content: &[u8]) -> Option<String> {
+        let text = decode_text(content)?;
+        if !text.lines().any(is_markdown_line) {
+            return None;
+        }
+
+        // The rest of the markup is punctuation, which is not indexed anyway.
+        let mut extracted = String::with_capacity(text.len());
+        let mut chars = text.chars().peekable();
+        let mut previous = None;
+        while let Some(c) = chars.next() {
+            let skip_until = match c {
+                '(' if previous == Some(']') => Some(')'),
+                '<' if chars
+                    .peek()
+                    .is_some_and(|next| next.is_ascii_alphabetic() || *next == '/') =>
+                {
+                    Some('>')
+                }
+                _ => None,
+            };
+
+            match skip_until {
+                Some(end) => {
+                    chars.by_ref().find(|c| *c == end);
+                    extracted.push(' ');
+                }
+                None => extracted.push(c),
+            }
+
+            previous = Some(c);
+        }
+
+        Some(extracted)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_extractors() {
+        let markdown =
+            b"# Quarterly Notes\n\nSee [the budget](https://example.com/secret) <b>now</b>.";
+        let extracted = MarkdownExtractor.extract(markdown).unwrap();
+        let terms = tokenize(&extracted).collect::<Vec<_>>();
+        assert_eq!(terms, ["quarterly", "notes", "see", "the", "budget", "now"]);
+
+        assert!(MarkdownExtractor.extract(b"just plain text").is_none());
+        assert_eq!(
+            TextExtractor.extract(b"just plain text").as_deref(),
+            Some("just plain text")
+        );
+
+        // Content cut short in the middle of a character is still text, binary content is not.
+        assert_eq!(
+            TextExtractor.extract("café".as_bytes().split_last().unwrap().1),
+            Some("caf".to_owned())
+        );
+        assert!(TextExtractor.extract(&[0x00, 0x01, 0xff]).is_none());
+    }
+}
@@ -0,0 +1,815 @@
+use std::{
+    collections::VecDeque,
+    convert::TryFrom,
+    ops::RangeInclusive,
+    pin::Pin,
+    sync::Mutex,
+    task::{ready, Context, Poll},
+};
+
+use bytes::{Bytes, BytesMut};
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use tokio::io::{self, AsyncRead, AsyncReadExt, ReadBuf};
+use typed_builder::TypedBuilder;
+use zeroutils_store::{ipld::cid::Cid, IpldReferences, IpldStore, StoreError, StoreResult};
+
+use crate::filesystem::{FsError, FsResult};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The default minimum size of a FastCDC chunk.
+pub const DEFAULT_FASTCDC_MIN_SIZE: usize = 16 * 1024;
+
+/// The default target average size of a FastCDC chunk.
+pub const DEFAULT_FASTCDC_AVG_SIZE: usize = 64 * 1024;
+
+/// The default maximum size of a FastCDC chunk.
+pub const DEFAULT_FASTCDC_MAX_SIZE: usize = 256 * 1024;
+
+/// The sizes the minimum size of a FastCDC chunk can be set to.
+pub const FASTCDC_MIN_SIZE_BOUNDS: RangeInclusive<usize> = 64..=1024 * 1024;
+
+/// The sizes the target average size of a FastCDC chunk can be set to.
+pub const FASTCDC_AVG_SIZE_BOUNDS: RangeInclusive<usize> = 256..=4 * 1024 * 1024;
+
+/// The sizes the maximum size of a FastCDC chunk can be set to.
+pub const FASTCDC_MAX_SIZE_BOUNDS: RangeInclusive<usize> = 1024..=16 * 1024 * 1024;
+
+/// Random values for the gear hash, one per byte value.
+const GEAR: [u64; 256] = gear_table();
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Determines how file content is split into blocks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum Chunker {
+    /// Content is handed to the store as is and chunked by the store.
+    #[default]
+    Store,
+
+    /// Content is split at content-defined boundaries with [FastCDC][fastcdc], so edits to a file
+    /// only change the chunks around them and the rest are shared with the previous version.
+    ///
+    /// [fastcdc]: https://www.usenix.org/conference/atc16/technical-sessions/presentation/xia
+    FastCdc(FastCdc),
+}
+
+/// Chunk size limits for [`Chunker::FastCdc`].
+///
+/// The limits must be in order and within [`FASTCDC_MIN_SIZE_BOUNDS`], [`FASTCDC_AVG_SIZE_BOUNDS`]
+/// and [`FASTCDC_MAX_SIZE_BOUNDS`], which the builder and deserialization check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, TypedBuilder)]
+#[serde(try_from = "FastCdcLimits")]
+#[builder(build_method(into = FsResult<FastCdc>))]
+pub struct FastCdc {
+    /// The minimum size of a chunk. Only the last chunk can be smaller.
+    #[builder(default = DEFAULT_FASTCDC_MIN_SIZE)]
+    pub min_size: usize,
+
+    /// The target average size of a chunk.
+    #[builder(default = DEFAULT_FASTCDC_AVG_SIZE)]
+    pub avg_size: usize,
+
+    /// The maximum size of a chunk.
+    #[builder(default = DEFAULT_FASTCDC_MAX_SIZE)]
+    pub max_size: usize,
+}
+
+/// The limits of a [`FastCdc`] as they are deserialized, before they are checked.
+#[derive(Deserialize)]
+struct FastCdcLimits {
+    #[serde(default = "default_fastcdc_min_size")]
+    min_size: usize,
+
+    #[serde(default = "default_fastcdc_avg_size")]
+    avg_size: usize,
+
+    #[serde(default = "default_fastcdc_max_size")]
+    max_size: usize,
+}
+
+/// The node listing the chunks of content split by a [`Chunker::FastCdc`], in order.
+///
+/// The content can be sparse: ranges that were never written are listed as holes, which read as
+/// zeros without taking up any blocks.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub(crate) struct ContentChunks {
+    chunks: Vec<Cid>,
+
+    /// The size of each chunk. Nodes stored before sizes were recorded leave it empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    sizes: Vec<u64>,
+
+    /// The holes in the content, in order.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    holes: Vec<ContentHole>,
+}
+
+/// A range of content that was never written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub(crate) struct ContentHole {
+    /// The index of the chunk the hole comes before.
+    before: usize,
+
+    /// The length of the hole.
+    len: u64,
+}
+
+/// A reader over the content listed by a [`ContentChunks`] node, which fetches each chunk only
+/// once it gets to it, so at most one chunk is held in memory at any time.
+pub(crate) struct ContentPiecesReader<'a> {
+    /// The state of the reader. It is only ever accessed through `&mut self`, the lock only makes
+    /// the reader `Sync` while the chunk being fetched is not.
+    state: Mutex<PiecesState<'a>>,
+}
+
+struct PiecesState<'a> {
+    /// The pieces left to read.
+    pieces: VecDeque<ContentPiece>,
+
+    /// Fetches a chunk.
+    fetch: Box<dyn Fn(Cid) -> BoxFuture<'a, StoreResult<Bytes>> + Send + 'a>,
+
+    /// The chunk being fetched, if any.
+    pending: Option<BoxFuture<'a, StoreResult<Bytes>>>,
+
+    /// What is left of the chunk being read.
+    chunk: Bytes,
+
+    /// What is left of the hole being read.
+    zeros: u64,
+}
+
+/// A piece of the content listed by a [`ContentChunks`] node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ContentPiece {
+    /// A chunk, with its size if the node records it.
+    Chunk(Cid, Option<u64>),
+
+    /// A hole of the given length, which reads as zeros.
+    Hole(u64),
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl FastCdc {
+    /// Returns the length of the first chunk in `data`.
+    ///
+    /// Uses the normalized chunking of FastCDC: cut points are harder to hit before the average
+    /// size and easier after it, which keeps chunk sizes close to the average.
+    pub fn cut(&self, data: &[u8]) -> usize {
+        if data.len() <= self.min_size {
+            return data.len();
+        }
+
+        let bits = self.avg_size.max(2).ilog2();
+        let mask_small = u64::MAX << (64 - (bits + 1).min(63));
+        let mask_large = u64::MAX << (64 - (bits - 1).max(1));
+
+        let max = data.len().min(self.max_size);
+        let normal = max.min(self.avg_size);
+
+        let mut hash = 0u64;
+        let mut i = self.min_size;
+        while i < normal {
+            hash = (hash << 1).wrapping_add(GEAR[data[i] as usize]);
+            if hash & mask_small == 0 {
+                return i + 1;
+            }
+
+            i += 1;
+        }
+
+        while i < max {
+            hash = (hash << 1).wrapping_add(GEAR[data[i] as usize]);
+            if hash & mask_large == 0 {
+                return i + 1;
+            }
+
+            i += 1;
+        }
+
+        max
+    }
+
+    /// Returns an error if the limits are out of order or outside of the bounds FastCDC works
+    /// within.
+    pub fn validate(&self) -> FsResult<()> {
+        let bounds = [
+            ("minimum", self.min_size, &FASTCDC_MIN_SIZE_BOUNDS),
+            ("average", self.avg_size, &FASTCDC_AVG_SIZE_BOUNDS),
+            ("maximum", self.max_size, &FASTCDC_MAX_SIZE_BOUNDS),
+        ];
+        for (name, size, bounds) in bounds.iter() {
+            if !bounds.contains(size) {
+                return Err(FsError::InvalidChunker(format!(
+                    "the {} chunk size {} is not between {} and {}",
+                    name,
+                    size,
+                    bounds.start(),
+                    bounds.end()
+                )));
+            }
+        }
+
+        if self.min_size > self.avg_size || self.avg_size > self.max_size {
+            return Err(FsError::InvalidChunker(format!(
+                "the chunk sizes must be in order, but the minimum is {}, the average {} and the \
+                 maximum {}",
+                self.min_size, self.avg_size, self.max_size
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Splits the content coming through `reader` into chunks, stores each of them in `store` and
+    /// returns the [`Cid`] of the [`ContentChunks`] node listing them.
+    pub(crate) async fn put_bytes<S>(
+        &self,
+        store: &S,
+        reader: impl AsyncRead + Send + Sync,
+    ) -> StoreResult<Cid>
+    where
+        S: IpldStore + Sync,
+    {
+        // Limits set on the fields directly skip the checks of the builder.
+        self.validate().map_err(StoreError::custom)?;
+
+        let mut reader = Box::pin(reader);
+        let mut buffer = BytesMut::with_capacity(self.max_size);
+        let mut chunks = ContentChunks::default();
+        let mut eof = false;
+        loop {
+            while !eof && buffer.len() < self.max_size {
+                eof = reader
+                    .as_mut()
+                    .take((self.max_size - buffer.len()) as u64)
+                    .read_buf(&mut buffer)
+                    .await
+                    .map_err(StoreError::custom)?
+                    == 0;
+            }
+
+            if buffer.is_empty() {
+                break;
+            }
+
+            let len = self.cut(&buffer);
+            let chunk = buffer.split_to(len);
+            let cid = store.put_bytes(&chunk[..]).await?;
+            chunks.push_chunk(cid, len as u64);
+        }
+
+        store.put_node(&chunks).await
+    }
+}
+
+impl<'a> ContentPiecesReader<'a> {
+    /// Creates a reader over `pieces`, fetching their chunks with `fetch`.
+    pub(crate) fn new(
+        pieces: impl IntoIterator<Item = ContentPiece>,
+        fetch: impl Fn(Cid) -> BoxFuture<'a, StoreResult<Bytes>> + Send + 'a,
+    ) -> Self {
+        Self {
+            state: Mutex::new(PiecesState {
+                pieces: pieces.into_iter().collect(),
+                fetch: Box::new(fetch),
+                pending: None,
+                chunk: Bytes::new(),
+                zeros: 0,
+            }),
+        }
+    }
+}
+
+impl ContentPiece {
+    /// Returns the length of the piece, which is `0` for a chunk whose size is not recorded.
+    pub(crate) fn get_len(&self) -> u64 {
+        match self {
+            ContentPiece::Chunk(_, size) => size.unwrap_or_default(),
+            ContentPiece::Hole(len) => *len,
+        }
+    }
+}
+
+impl ContentChunks {
+    /// Loads the node at `cid`, reading the sizes of its chunks from the store if it does not
+    /// record them.
+    pub(crate) async fn load<S>(store: &S, cid: &Cid) -> StoreResult<Self>
+    where
+        S: IpldStore,
+    {
+        let mut node: ContentChunks = store.get_node(cid).await?;
+        if node.sizes.len() != node.chunks.len() {
+            node.sizes = Vec::with_capacity(node.chunks.len());
+            for chunk in &node.chunks {
+                node.sizes.push(get_size(store, chunk).await?);
+            }
+        }
+
+        Ok(node)
+    }
+
+    /// Returns the content at `content` in `store` as a node, which lists it as a single chunk if
+    /// it is not `chunked`.
+    pub(crate) async fn from_content<S>(
+        store: &S,
+        content: Option<&Cid>,
+        chunked: bool,
+    ) -> StoreResult<Self>
+    where
+        S: IpldStore,
+    {
+        match content {
+            Some(cid) if chunked => Self::load(store, cid).await,
+            Some(cid) => {
+                let mut node = Self::default();
+                node.push_chunk(*cid, get_size(store, cid).await?);
+                Ok(node)
+            }
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Returns the [`Cid`]s of the chunks listed in the node at `cid`.
+    pub(crate) async fn get_chunks<S>(store: &S, cid: &Cid) -> StoreResult<Vec<Cid>>
+    where
+        S: IpldStore,
+    {
+        let node: ContentChunks = store.get_node(cid).await?;
+        Ok(node.chunks)
+    }
+
+    /// Returns the chunks and holes listed in the node at `cid`, in order.
+    pub(crate) async fn get_pieces<S>(store: &S, cid: &Cid) -> StoreResult<Vec<ContentPiece>>
+    where
+        S: IpldStore,
+    {
+        let node: ContentChunks = store.get_node(cid).await?;
+        Ok(node.pieces().collect())
+    }
+
+    /// Returns a reader over the content of the chunks listed in the node at `cid`.
+    ///
+    /// Chunks are read from the store as the reader gets to them. Holes are read as zeros.
+    pub(crate) async fn get_bytes<'a, S>(
+        store: &'a S,
+        cid: &Cid,
+    ) -> StoreResult<Pin<Box<dyn AsyncRead + Send + Sync + 'a>>>
+    where
+        S: IpldStore + Sync,
+    {
+        let node: ContentChunks = store.get_node(cid).await?;
+        let reader = ContentPiecesReader::new(node.pieces(), fetch_chunk(store));
+
+        Ok(Box::pin(reader))
+    }
+
+    /// Returns a reader over the content of the chunks listed in the node at `cid`, from `offset`.
+    ///
+    /// The pieces that end before `offset` are skipped without being read, so only the chunk it
+    /// falls in is read up to it.
+    pub(crate) async fn get_bytes_from<'a, S>(
+        store: &'a S,
+        cid: &Cid,
+        offset: u64,
+    ) -> StoreResult<Pin<Box<dyn AsyncRead + Send + Sync + 'a>>>
+    where
+        S: IpldStore + Sync,
+    {
+        if offset == 0 {
+            return Self::get_bytes(store, cid).await;
+        }
+
+        let node = Self::load(store, cid).await?;
+        let mut start = 0;
+        let mut pieces = node
+            .pieces()
+            .skip_while(|piece| {
+                let skipped = start + piece.get_len() <= offset;
+                if skipped {
+                    start += piece.get_len();
+                }
+
+                skipped
+            })
+            .collect::<VecDeque<_>>();
+
+        // Offsets past the end leave no pieces to read.
+        let skip = offset - start;
+        let skip = match pieces.front_mut() {
+            Some(ContentPiece::Hole(len)) => {
+                *len -= skip;
+                0
+            }
+            Some(ContentPiece::Chunk(..)) => skip,
+            None => 0,
+        };
+
+        let mut reader = Box::pin(ContentPiecesReader::new(pieces, fetch_chunk(store)));
+        io::copy(&mut (&mut reader).take(skip), &mut io::sink())
+            .await
+            .map_err(StoreError::custom)?;
+
+        Ok(reader)
+    }
+
+    /// Returns the chunks and holes of the node, in order.
+    pub(crate) fn pieces(&self) -> impl Iterator<Item = ContentPiece> + '_ {
+        let mut holes = self.holes.iter().peekable();
+        let mut pieces = Vec::with_capacity(self.chunks.len() + self.holes.len());
+        for (index, chunk) in self.chunks.iter().enumerate() {
+            while let Some(hole) = holes.next_if(|hole| hole.before <= index) {
+                pieces.push(ContentPiece::Hole(hole.len));
+            }
+
+            pieces.push(ContentPiece::Chunk(*chunk, self.sizes.get(index).copied()));
+        }
+
+        pieces.extend(holes.map(|hole| ContentPiece::Hole(hole.len)));
+        pieces.into_iter()
+    }
+
+    /// Adds a chunk of `size` bytes at the end of the content.
+    pub(crate) fn push_chunk(&mut self, cid: Cid, size: u64) {
+        self.chunks.push(cid);
+        self.sizes.push(size);
+    }
+
+    /// Adds a hole of `len` bytes at the end of the content.
+    pub(crate) fn push_hole(&mut self, len: u64) {
+        if len == 0 {
+            return;
+        }
+
+        match self.holes.last_mut() {
+            Some(hole) if hole.before == self.chunks.len() => hole.len += len,
+            _ => self.holes.push(ContentHole {
+                before: self.chunks.len(),
+                len,
+            }),
+        }
+    }
+
+    /// Returns the node cut to its first `len` bytes. A chunk the cut falls in is stored again with
+    /// just the bytes before it.
+    ///
+    /// The node must have been [`load`][Self::load]ed for the sizes of its chunks to be known.
+    pub(crate) async fn truncate<S>(&self, store: &S, len: u64) -> StoreResult<Self>
+    where
+        S: IpldStore,
+    {
+        let mut node = Self::default();
+        let mut end = 0;
+        for piece in self.pieces() {
+            let remaining = len - end;
+            if remaining == 0 {
+                break;
+            }
+
+            match piece {
+                ContentPiece::Hole(hole) => {
+                    node.push_hole(hole.min(remaining));
+                    end += hole.min(remaining);
+                }
+                ContentPiece::Chunk(cid, size) => {
+                    let size = size.unwrap_or_default();
+                    if size <= remaining {
+                        node.push_chunk(cid, size);
+                        end += size;
+                        continue;
+                    }
+
+                    let mut prefix = vec![];
+                    store
+                        .get_bytes(&cid)
+                        .await?
+                        .take(remaining)
+                        .read_to_end(&mut prefix)
+                        .await
+                        .map_err(zeroutils_store::StoreError::custom)?;
+
+                    node.push_chunk(store.put_bytes(&prefix[..]).await?, remaining);
+                    end += remaining;
+                }
+            }
+        }
+
+        Ok(node)
+    }
+
+    /// Returns the length of the content, holes included.
+    ///
+    /// The node must have been [`load`][Self::load]ed for the sizes of its chunks to be known.
+    pub(crate) fn get_len(&self) -> u64 {
+        self.get_allocated() + self.holes.iter().map(|hole| hole.len).sum::<u64>()
+    }
+
+    /// Returns the number of bytes of content stored in chunks, which leaves out the holes.
+    pub(crate) fn get_allocated(&self) -> u64 {
+        self.sizes.iter().sum()
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Generates the gear table with SplitMix64 so it is the same on every build.
+const fn gear_table() -> [u64; 256] {
+    let mut table = [0u64; 256];
+    let mut state = 0x5a45_524f_4653_u64;
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+
+    table
+}
+
+/// Returns a function that fetches the content of a chunk from `store`.
+fn fetch_chunk<S>(store: &S) -> impl Fn(Cid) -> BoxFuture<'_, StoreResult<Bytes>> + Send + '_
+where
+    S: IpldStore + Sync,
+{
+    move |chunk| {
+        Box::pin(async move {
+            let mut bytes = vec![];
+            store
+                .get_bytes(&chunk)
+                .await?
+                .read_to_end(&mut bytes)
+                .await
+                .map_err(StoreError::custom)?;
+
+            Ok(Bytes::from(bytes))
+        })
+    }
+}
+
+/// Returns the size of the content at `cid` in `store`.
+async fn get_size<S>(store: &S, cid: &Cid) -> StoreResult<u64>
+where
+    S: IpldStore,
+{
+    io::copy(&mut store.get_bytes(cid).await?, &mut io::sink())
+        .await
+        .map_err(StoreError::custom)
+}
+
+fn default_fastcdc_min_size() -> usize {
+    DEFAULT_FASTCDC_MIN_SIZE
+}
+
+fn default_fastcdc_avg_size() -> usize {
+    DEFAULT_FASTCDC_AVG_SIZE
+}
+
+fn default_fastcdc_max_size() -> usize {
+    DEFAULT_FASTCDC_MAX_SIZE
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl Default for FastCdc {
+    fn default() -> Self {
+        Self {
+            min_size: DEFAULT_FASTCDC_MIN_SIZE,
+            avg_size: DEFAULT_FASTCDC_AVG_SIZE,
+            max_size: DEFAULT_FASTCDC_MAX_SIZE,
+        }
+    }
+}
+
+impl From<FastCdc> for FsResult<FastCdc> {
+    fn from(fastcdc: FastCdc) -> Self {
+        fastcdc.validate()?;
+        Ok(fastcdc)
+    }
+}
+
+impl TryFrom<FastCdcLimits> for FastCdc {
+    type Error = FsError;
+
+    fn try_from(limits: FastCdcLimits) -> FsResult<Self> {
+        FastCdc::builder()
+            .min_size(limits.min_size)
+            .avg_size(limits.avg_size)
+            .max_size(limits.max_size)
+            .build()
+    }
+}
+
+impl AsyncRead for ContentPiecesReader<'_> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let state = self.get_mut().state.get_mut().unwrap();
+        loop {
+            if !state.chunk.is_empty() {
+                let len = buf.remaining().min(state.chunk.len());
+                buf.put_slice(&state.chunk.split_to(len));
+                return Poll::Ready(Ok(()));
+            }
+
+            if state.zeros > 0 {
+                let len = (buf.remaining() as u64).min(state.zeros) as usize;
+                buf.initialize_unfilled_to(len).fill(0);
+                buf.advance(len);
+                state.zeros -= len as u64;
+                return Poll::Ready(Ok(()));
+            }
+
+            if let Some(pending) = &mut state.pending {
+                let fetched = ready!(pending.as_mut().poll(cx));
+                state.pending = None;
+                state.chunk = fetched.map_err(io::Error::other)?;
+                continue;
+            }
+
+            match state.pieces.pop_front() {
+                Some(ContentPiece::Chunk(chunk, _)) => state.pending = Some((state.fetch)(chunk)),
+                Some(ContentPiece::Hole(len)) => state.zeros = len,
+                None => return Poll::Ready(Ok(())),
+            }
+        }
+    }
+}
+
+impl IpldReferences for ContentChunks {
+    fn references<'a>(&'a self) -> Box<dyn Iterator<Item = &'a Cid> + Send + 'a> {
+        Box::new(self.chunks.iter())
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use futures::TryStreamExt;
+    use rand::{rngs::StdRng, RngCore, SeedableRng};
+    use zeroutils_store::MemoryStore;
+
+    use crate::filesystem::File;
+
+    use super::*;
+
+    fn fastcdc_chunks<'a>(chunker: &FastCdc, mut data: &'a [u8]) -> Vec<&'a [u8]> {
+        let mut chunks = vec![];
+        while !data.is_empty() {
+            let (chunk, rest) = data.split_at(chunker.cut(data));
+            chunks.push(chunk);
+            data = rest;
+        }
+
+        chunks
+    }
+
+    fn shared_ratio(before: &[&[u8]], after: &[&[u8]]) -> f64 {
+        let before = before.iter().collect::<HashSet<_>>();
+        let shared = after.iter().filter(|chunk| before.contains(chunk)).count();
+        shared as f64 / after.len() as f64
+    }
+
+    #[test]
+    fn test_fastcdc_chunk_sizes() {
+        let mut data = vec![0; 4 * 1024 * 1024];
+        StdRng::seed_from_u64(0).fill_bytes(&mut data);
+
+        let chunker = FastCdc::default();
+        let chunks = fastcdc_chunks(&chunker, &data);
+
+        assert_eq!(
+            chunks.iter().map(|chunk| chunk.len()).sum::<usize>(),
+            data.len()
+        );
+        for chunk in &chunks[..chunks.len() - 1] {
+            assert!(chunk.len() >= chunker.min_size && chunk.len() <= chunker.max_size);
+        }
+
+        let avg = data.len() / chunks.len();
+        assert!(avg > chunker.min_size && avg < chunker.max_size);
+    }
+
+    #[test]
+    fn test_fastcdc_dedups_shifted_data_better_than_fixed() {
+        let mut before = vec![0; 4 * 1024 * 1024];
+        StdRng::seed_from_u64(0).fill_bytes(&mut before);
+
+        // Insert a few bytes near the start, shifting everything after them.
+        let mut after = before.clone();
+        after.splice(1000..1000, b"edit".iter().copied());
+
+        let fixed_ratio = shared_ratio(
+            &before.chunks(DEFAULT_FASTCDC_AVG_SIZE).collect::<Vec<_>>(),
+            &after.chunks(DEFAULT_FASTCDC_AVG_SIZE).collect::<Vec<_>>(),
+        );
+
+        let chunker = FastCdc::default();
+        let fastcdc_ratio = shared_ratio(
+            &fastcdc_chunks(&chunker, &before),
+            &fastcdc_chunks(&chunker, &after),
+        );
+
+        assert!(fixed_ratio < 0.05);
+        assert!(fastcdc_ratio > 0.9);
+    }
+
+    #[test]
+    fn test_fastcdc_limits_are_validated() -> anyhow::Result<()> {
+        let chunker = FastCdc::builder()
+            .min_size(1024)
+            .avg_size(4096)
+            .max_size(16 * 1024)
+            .build()?;
+        assert_eq!(chunker.max_size, 16 * 1024);
+
+        for (min, avg, max) in [(4096, 1024, 16 * 1024), (0, 4096, 16 * 1024), (64, 256, 0)] {
+            assert!(matches!(
+                FastCdc::builder()
+                    .min_size(min)
+                    .avg_size(avg)
+                    .max_size(max)
+                    .build(),
+                Err(FsError::InvalidChunker(_))
+            ));
+        }
+
+        // Deserialized limits are checked too, with the defaults filling in the missing ones.
+        let chunker: FastCdc = toml::from_str("avg_size = 32768")?;
+        assert_eq!(chunker.min_size, DEFAULT_FASTCDC_MIN_SIZE);
+        assert!(toml::from_str::<FastCdc>("max_size = 0").is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_sparse_content_reads_holes_as_zeros() -> anyhow::Result<()> {
+        let mut file = File::new(MemoryStore::default());
+        file.put_content(&b"head"[..], &Chunker::Store).await?;
+
+        file.set_len(200_000).await?;
+        let size = file.get_size().await?;
+        assert_eq!((size.len, size.allocated), (200_000, 4));
+
+        let content = file
+            .get_content_stream()
+            .try_collect::<Vec<_>>()
+            .await?
+            .concat();
+        assert_eq!(content.len(), 200_000);
+        assert_eq!(&content[..4], b"head");
+        assert!(content[4..].iter().all(|byte| *byte == 0));
+
+        // Reads from an offset start within the chunk or the hole it falls in.
+        let mut content = Vec::new();
+        file.get_content_reader_from(2)
+            .await?
+            .read_to_end(&mut content)
+            .await?;
+        assert_eq!(content.len(), 199_998);
+        assert_eq!(&content[..2], b"ad");
+
+        let mut content = Vec::new();
+        file.get_content_reader_from(150_000)
+            .await?
+            .read_to_end(&mut content)
+            .await?;
+        assert_eq!(content, vec![0; 50_000]);
+
+        // Cutting into the hole keeps the rest of it, cutting into a chunk stores what is left.
+        file.set_len(10).await?;
+        assert_eq!(file.get_size().await?.allocated, 4);
+        file.set_len(2).await?;
+        let mut content = Vec::new();
+        file.get_content_reader()
+            .await?
+            .read_to_end(&mut content)
+            .await?;
+        assert_eq!(content, b"he");
+
+        Ok(())
+    }
+}
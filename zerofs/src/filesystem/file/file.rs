@@ -1,15 +1,39 @@
 use core::fmt;
-use std::{fmt::Debug, sync::Arc};
+use std::{fmt::Debug, pin::Pin, sync::Arc};
 
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use futures::{
+    stream::{self, BoxStream},
+    StreamExt, TryStreamExt,
+};
 use serde::{
     de::{self, DeserializeSeed},
     Deserialize, Deserializer, Serialize, Serializer,
 };
+use tokio::io::{AsyncRead, AsyncReadExt};
 use zeroutils_store::{
     ipld::cid::Cid, IpldReferences, IpldStore, Storable, StoreError, StoreResult,
 };
 
-use crate::filesystem::{EntityType, FsError, FsResult, Handle, Metadata};
+use crate::{
+    config::RAW_CODEC_CODE,
+    filesystem::{
+        without_caching, AccessPattern, Acl, Chunker, ContentChunks, ContentPiece,
+        EntityAttributes, EntityType, FsError, FsResult, Handle, KeyGrants, Metadata,
+    },
+};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The number of chunks fetched ahead of the one being read when the content of a file is read
+/// with the [`AccessPattern::Sequential`] pattern.
+pub const READAHEAD_CHUNKS: usize = 4;
+
+/// The zeros holes in sparse content are read as, handed out in slices of at most this buffer.
+static ZEROS: [u8; 64 * 1024] = [0; 64 * 1024];
 
 //--------------------------------------------------------------------------------------------------
 // Types
@@ -29,6 +53,17 @@ where
     inner: Arc<FileInner<S>>,
 }
 
+/// The size of the content of a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ContentSize {
+    /// The length of the content, holes included.
+    pub len: u64,
+
+    /// The number of bytes of content stored in blocks, which leaves out the holes of sparse
+    /// content.
+    pub allocated: u64,
+}
+
 #[derive(Clone)]
 struct FileInner<S>
 where
@@ -40,6 +75,9 @@ where
     /// File content. If the file is empty, this will be `None`.
     pub(crate) content: Option<Cid>,
 
+    /// Whether `content` points to a [`ContentChunks`] node rather than the bytes themselves.
+    pub(crate) chunked: bool,
+
     /// The store used to persist blocks in the file.
     pub(crate) store: S,
 }
@@ -55,6 +93,8 @@ pub type FileHandle<S, T> = Handle<File<T>, S, T>;
 pub(crate) struct FileSerializable {
     metadata: Metadata,
     content: Option<Cid>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    chunked: bool,
 }
 
 pub(crate) struct FileDeserializeSeed<S> {
@@ -75,6 +115,7 @@ where
             inner: Arc::new(FileInner {
                 metadata: Metadata::new(EntityType::File),
                 content: None,
+                chunked: false,
                 store,
             }),
         }
@@ -85,11 +126,283 @@ where
         self.inner.content.as_ref()
     }
 
+    /// Returns `true` if the content was split by a [`Chunker::FastCdc`].
+    pub fn is_chunked(&self) -> bool {
+        self.inner.chunked
+    }
+
+    /// Returns a reader over the content of the file.
+    pub async fn get_content_reader(&self) -> FsResult<Pin<Box<dyn AsyncRead + Send + Sync + '_>>>
+    where
+        S: Sync,
+    {
+        Ok(read_content(
+            self.get_store(),
+            self.inner.content.as_ref(),
+            self.inner.chunked,
+        )
+        .await?)
+    }
+
+    /// Returns a reader over the content of the file from `offset`.
+    ///
+    /// Chunked content is read from the chunk `offset` falls in, skipping the ones before it
+    /// without reading them. Content that is not chunked is read up to `offset`.
+    pub async fn get_content_reader_from(
+        &self,
+        offset: u64,
+    ) -> FsResult<Pin<Box<dyn AsyncRead + Send + Sync + '_>>>
+    where
+        S: Sync,
+    {
+        Ok(read_content_from(
+            self.get_store(),
+            self.inner.content.as_ref(),
+            self.inner.chunked,
+            offset,
+        )
+        .await?)
+    }
+
+    /// Returns the content of the file as a stream of reference-counted byte chunks.
+    ///
+    /// Content stored in raw blocks is returned as the blocks the store hands out, so reads from a
+    /// store that keeps its blocks in memory, like a [`CachedStore`][crate::filesystem::CachedStore],
+    /// do not copy them. Content stored in other blocks is read into new buffers.
+    pub fn get_content_stream(&self) -> BoxStream<'_, FsResult<Bytes>>
+    where
+        S: Send + Sync,
+    {
+        self.get_content_stream_with(AccessPattern::Normal)
+    }
+
+    /// Returns the content of the file as a stream of reference-counted byte chunks, read the way
+    /// `pattern` suits.
+    ///
+    /// With [`AccessPattern::Sequential`], up to [`READAHEAD_CHUNKS`] chunks are fetched ahead of
+    /// the one being read. With [`AccessPattern::Random`], the chunks are read
+    /// [`without_caching`] them.
+    pub fn get_content_stream_with(&self, pattern: AccessPattern) -> BoxStream<'_, FsResult<Bytes>>
+    where
+        S: Send + Sync,
+    {
+        let store = self.get_store();
+        let cid = match self.inner.content.as_ref() {
+            Some(cid) => cid,
+            None => return stream::empty().boxed(),
+        };
+
+        let read = move |chunk| async move {
+            match pattern {
+                AccessPattern::Random => without_caching(read_block(store, chunk)).await,
+                _ => read_block(store, chunk).await,
+            }
+        };
+
+        if !self.inner.chunked {
+            return stream::once(read(*cid)).boxed();
+        }
+
+        let readahead = match pattern {
+            AccessPattern::Sequential => READAHEAD_CHUNKS,
+            _ => 1,
+        };
+
+        stream::once(ContentChunks::get_pieces(store, cid))
+            .map_ok(move |pieces| {
+                // Holes are handed out as slices of zeros, each standing in for a chunk.
+                let zeros = ZEROS.len() as u64;
+                stream::iter(pieces)
+                    .flat_map(move |piece| {
+                        let (chunk, len, slices) = match piece {
+                            ContentPiece::Chunk(chunk, _) => (Some(chunk), 0, 1),
+                            ContentPiece::Hole(len) => (None, len, len.div_ceil(zeros)),
+                        };
+
+                        stream::iter(
+                            (0..slices).map(move |i| (chunk, (len - i * zeros).min(zeros))),
+                        )
+                    })
+                    .map(move |(chunk, zeros)| async move {
+                        match chunk {
+                            Some(chunk) => read(chunk).await,
+                            None => Ok(Bytes::from_static(&ZEROS[..zeros as usize])),
+                        }
+                    })
+                    .buffered(readahead)
+            })
+            .map_err(FsError::from)
+            .try_flatten()
+            .boxed()
+    }
+
+    /// Fetches the chunks holding the `len` bytes of content from `offset` into the block cache of
+    /// the store, if it has one, so that reading them later does not wait on the store.
+    ///
+    /// The chunks before the range are skipped if the content records their sizes, and are read
+    /// to find where it starts otherwise, but not cached. Content that was not split by a
+    /// [`Chunker::FastCdc`] is fetched whole if it is a single raw block, and left alone otherwise.
+    pub async fn will_need(&self, offset: u64, len: u64) -> FsResult<()>
+    where
+        S: Send + Sync,
+    {
+        let store = self.get_store();
+        let Some(cid) = self.inner.content.as_ref() else {
+            return Ok(());
+        };
+
+        if !self.inner.chunked {
+            if cid.codec() == RAW_CODEC_CODE && len > 0 {
+                store.get_raw_block(cid).await?;
+            }
+
+            return Ok(());
+        }
+
+        let end = offset.saturating_add(len);
+        let mut start = 0;
+        for piece in ContentChunks::get_pieces(store, cid).await? {
+            if start >= end {
+                break;
+            }
+
+            let (chunk, size) = match piece {
+                ContentPiece::Hole(len) => {
+                    start += len;
+                    continue;
+                }
+                ContentPiece::Chunk(chunk, size) => (chunk, size),
+            };
+
+            let size = match size {
+                Some(size) if start + size <= offset => size,
+                Some(size) => {
+                    read_block(store, chunk).await?;
+                    size
+                }
+                None if start < offset => {
+                    let size = without_caching(read_block(store, chunk)).await?.len() as u64;
+
+                    // A chunk that straddles the start of the range is read again, now to be
+                    // cached.
+                    if start + size > offset {
+                        read_block(store, chunk).await?;
+                    }
+
+                    size
+                }
+                None => read_block(store, chunk).await?.len() as u64,
+            };
+
+            start += size;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the length of the content of the file along with the number of bytes it takes up in
+    /// blocks, which leaves out the holes of sparse content.
+    ///
+    /// Content that does not record its size is read to measure it.
+    pub async fn get_size(&self) -> FsResult<ContentSize> {
+        let node = ContentChunks::from_content(
+            self.get_store(),
+            self.inner.content.as_ref(),
+            self.inner.chunked,
+        )
+        .await?;
+
+        Ok(ContentSize {
+            len: node.get_len(),
+            allocated: node.get_allocated(),
+        })
+    }
+
+    /// Sets the length of the content of the file, like `ftruncate`.
+    ///
+    /// Content is cut to `len` bytes if it is longer. If it is shorter, it is extended with a hole
+    /// that reads as zeros but is not stored, which makes the content sparse.
+    pub async fn set_len(&mut self, len: u64) -> FsResult<()>
+    where
+        S: Sync,
+    {
+        if len == 0 {
+            self.truncate();
+            return Ok(());
+        }
+
+        let store = self.get_store();
+        let mut node =
+            ContentChunks::from_content(store, self.inner.content.as_ref(), self.inner.chunked)
+                .await?;
+
+        let current = node.get_len();
+        if len == current {
+            return Ok(());
+        }
+
+        if len > current {
+            node.push_hole(len - current);
+        } else {
+            node = node.truncate(store, len).await?;
+        }
+
+        let content = store.put_node(&node).await?;
+        self.set_chunks(content);
+
+        Ok(())
+    }
+
     /// Returns the metadata for the directory.
     pub fn get_metadata(&self) -> &Metadata {
         &self.inner.metadata
     }
 
+    /// Returns a new file with the same content as this one, sharing its blocks rather than
+    /// copying them, like a reflink.
+    ///
+    /// Only the content is shared. The new file has metadata of its own, without the access
+    /// control list and attributes of this one. Since files are copy-on-write, writes to either
+    /// file afterwards do not show in the other.
+    pub fn reflink(&self) -> Self {
+        Self {
+            inner: Arc::new(FileInner {
+                metadata: Metadata::new(EntityType::File),
+                content: self.inner.content,
+                chunked: self.inner.chunked,
+                store: self.inner.store.clone(),
+            }),
+        }
+    }
+
+    /// Sets the access control list of the file.
+    pub fn set_acl(&mut self, acl: Acl) {
+        let inner = Arc::make_mut(&mut self.inner);
+        inner.metadata.acl = acl;
+        inner.metadata.mark_changed();
+    }
+
+    /// Sets the grants of the content key of the file.
+    pub fn set_key_grants(&mut self, grants: KeyGrants) {
+        let inner = Arc::make_mut(&mut self.inner);
+        inner.metadata.grants = grants;
+        inner.metadata.mark_changed();
+    }
+
+    /// Sets the number of times the content key of the file was rotated.
+    pub fn set_key_epoch(&mut self, epoch: u32) {
+        let inner = Arc::make_mut(&mut self.inner);
+        inner.metadata.key_epoch = epoch;
+        inner.metadata.mark_changed();
+    }
+
+    /// Sets the attributes protecting the file from changes.
+    pub fn set_attributes(&mut self, attributes: EntityAttributes) {
+        let inner = Arc::make_mut(&mut self.inner);
+        inner.metadata.attributes = attributes;
+        inner.metadata.mark_changed();
+    }
+
     /// Returns the store used to persist the file.
     pub fn get_store(&self) -> &S {
         &self.inner.store
@@ -104,6 +417,101 @@ where
     pub fn truncate(&mut self) {
         let inner = Arc::make_mut(&mut self.inner);
         inner.content = None;
+        inner.chunked = false;
+    }
+
+    /// Sets the content of the file to the given [`Cid`]. `None` makes the file empty.
+    pub fn set_content(&mut self, content: Option<Cid>) {
+        let inner = Arc::make_mut(&mut self.inner);
+        inner.content = content;
+        inner.chunked = false;
+    }
+
+    /// Sets the content of the file to the [`ContentChunks`] node at `content`.
+    pub(crate) fn set_chunks(&mut self, content: Cid) {
+        let inner = Arc::make_mut(&mut self.inner);
+        inner.content = Some(content);
+        inner.chunked = true;
+    }
+
+    /// Sets the content of the file to the content already stored at `content`, which is either a
+    /// raw block or the [`ContentChunks`] node of chunked content, as returned by
+    /// [`get_content`][Self::get_content].
+    ///
+    /// Nothing is uploaded, so content already in the store can be linked into the tree again, by
+    /// a client that found the node has it or by another tenant. Fails with
+    /// [`FsError::InvalidContent`] if `content` is neither, or if any of its blocks is not in the
+    /// store.
+    pub async fn set_stored_content(&mut self, content: Cid) -> FsResult<()> {
+        let missing =
+            |cid: &Cid| FsError::InvalidContent(format!("block {cid} of {content} is missing"));
+        let store = self.get_store();
+        if content.codec() == RAW_CODEC_CODE {
+            if !store.has(&content).await {
+                return Err(missing(&content));
+            }
+
+            self.set_content(Some(content));
+            return Ok(());
+        }
+
+        let chunks = ContentChunks::get_chunks(store, &content)
+            .await
+            .map_err(|e| FsError::InvalidContent(format!("{content} is not content: {e}")))?;
+        for chunk in &chunks {
+            if chunk.codec() != RAW_CODEC_CODE {
+                return Err(FsError::InvalidContent(format!(
+                    "chunk {chunk} of {content} is not a raw block"
+                )));
+            }
+
+            if !store.has(chunk).await {
+                return Err(missing(chunk));
+            }
+        }
+
+        self.set_chunks(content);
+        Ok(())
+    }
+
+    /// Stores the content coming through `reader` split by `chunker` and sets it as the content of
+    /// the file.
+    pub async fn put_content(
+        &mut self,
+        reader: impl AsyncRead + Send + Sync,
+        chunker: &Chunker,
+    ) -> FsResult<()>
+    where
+        S: Sync,
+    {
+        let (content, chunked) = match chunker {
+            Chunker::Store => (self.inner.store.put_bytes(reader).await?, false),
+            Chunker::FastCdc(fastcdc) => {
+                (fastcdc.put_bytes(&self.inner.store, reader).await?, true)
+            }
+        };
+
+        let inner = Arc::make_mut(&mut self.inner);
+        inner.content = Some(content);
+        inner.chunked = chunked;
+
+        Ok(())
+    }
+
+    /// Sets the time of the last modification of the file.
+    pub fn set_modified_at(&mut self, modified_at: DateTime<Utc>) {
+        let inner = Arc::make_mut(&mut self.inner);
+        inner.metadata.modified_at = modified_at;
+    }
+
+    /// Sets the access and modification times of the file, see [`Metadata::set_times`].
+    pub fn set_times(
+        &mut self,
+        accessed_at: Option<DateTime<Utc>>,
+        modified_at: Option<DateTime<Utc>>,
+    ) {
+        let inner = Arc::make_mut(&mut self.inner);
+        inner.metadata.set_times(accessed_at, modified_at);
     }
 
     /// Change the store used to persist the file.
@@ -120,6 +528,7 @@ where
             inner: Arc::new(FileInner {
                 metadata: inner.metadata,
                 content: inner.content,
+                chunked: inner.chunked,
                 store,
             }),
         }
@@ -144,6 +553,7 @@ where
             inner: Arc::new(FileInner {
                 metadata: serializable.metadata,
                 content: serializable.content,
+                chunked: serializable.chunked,
                 store,
             }),
         })
@@ -160,6 +570,66 @@ impl<S> FileDeserializeSeed<S> {
     }
 }
 
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Returns a reader over the file content at `content` in `store`, which points to a
+/// [`ContentChunks`] node if `chunked` is `true`.
+pub(crate) async fn read_content<'a, S>(
+    store: &'a S,
+    content: Option<&'a Cid>,
+    chunked: bool,
+) -> StoreResult<Pin<Box<dyn AsyncRead + Send + Sync + 'a>>>
+where
+    S: IpldStore + Sync,
+{
+    match content {
+        Some(cid) if chunked => ContentChunks::get_bytes(store, cid).await,
+        Some(cid) => store.get_bytes(cid).await,
+        None => Ok(Box::pin(&[][..])),
+    }
+}
+
+/// Returns a reader over the file content at `content` in `store` from `offset`, which points to a
+/// [`ContentChunks`] node if `chunked` is `true`.
+pub(crate) async fn read_content_from<'a, S>(
+    store: &'a S,
+    content: Option<&'a Cid>,
+    chunked: bool,
+    offset: u64,
+) -> StoreResult<Pin<Box<dyn AsyncRead + Send + Sync + 'a>>>
+where
+    S: IpldStore + Sync,
+{
+    match content {
+        Some(cid) if chunked => ContentChunks::get_bytes_from(store, cid, offset).await,
+        _ => {
+            let mut reader = read_content(store, content, chunked).await?;
+            tokio::io::copy(&mut (&mut reader).take(offset), &mut tokio::io::sink())
+                .await
+                .map_err(StoreError::custom)?;
+
+            Ok(reader)
+        }
+    }
+}
+
+/// Returns the content of the block at `cid` in `store`, without copying it if it is a raw block.
+async fn read_block<S>(store: &S, cid: Cid) -> FsResult<Bytes>
+where
+    S: IpldStore,
+{
+    if cid.codec() == RAW_CODEC_CODE {
+        return Ok(store.get_raw_block(&cid).await?);
+    }
+
+    let mut bytes = Vec::new();
+    store.get_bytes(&cid).await?.read_to_end(&mut bytes).await?;
+
+    Ok(bytes.into())
+}
+
 //--------------------------------------------------------------------------------------------------
 // Trait Implementations: File
 //--------------------------------------------------------------------------------------------------
@@ -187,6 +657,7 @@ where
         let serializable = FileSerializable {
             metadata: self.inner.metadata.clone(),
             content: self.inner.content,
+            chunked: self.inner.chunked,
         };
 
         serializable.serialize(serializer)
@@ -215,6 +686,7 @@ where
         f.debug_struct("File")
             .field("metadata", &self.inner.metadata)
             .field("content", &self.inner.content)
+            .field("chunked", &self.inner.chunked)
             .finish()
     }
 }
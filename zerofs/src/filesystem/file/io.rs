@@ -1,13 +1,35 @@
-use std::{mem, pin::Pin};
+use std::{
+    future::Future,
+    mem,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
 
-use aliasable::boxed::AliasableBox;
 use async_trait::async_trait;
 use bytes::{Bytes, BytesMut};
-use tokio::io::{AsyncRead, AsyncReadExt};
-use zeroutils_store::IpldStore;
+use futures::TryStreamExt;
+use tokio::{
+    io::{self, AsyncRead, AsyncReadExt, AsyncWriteExt, DuplexStream, ReadBuf},
+    task::JoinHandle,
+};
+use zeroutils_store::{ipld::cid::Cid, IpldStore, StoreError, StoreResult};
 use zeroutils_wasi::io::{Await, InputStream, StreamError};
 
-use crate::filesystem::FileHandle;
+use crate::filesystem::{
+    AccessPattern, ContentChunks, DescriptorFlags, File, FileHandle, FsError, FsResult,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The capacity of the pipe between an output stream and the store chunking its content, used when
+/// the store does not specify a maximum raw block size.
+pub const DEFAULT_OUTPUT_PIPE_CAPACITY: usize = 256 * 1024;
+
+/// The capacity of the pipe between the task reading a file's content and its [`ContentReader`],
+/// used when the store does not specify a maximum node block size.
+pub const DEFAULT_INPUT_PIPE_CAPACITY: usize = 256 * 1024;
 
 //--------------------------------------------------------------------------------------------------
 // Types
@@ -22,29 +44,58 @@ where
     /// Temporary buffer for recently fetched chunk or a stream error.
     buffer: Result<BytesMut, StreamError>,
 
-    /// An async reader for the file content.
-    ///
-    /// ## Important
-    ///
-    /// Holds a reference to other fields in this struct. Declared first to ensure it is dropped
-    /// before the other fields.
-    reader: Pin<Box<dyn AsyncRead + Send + Sync + 'static>>,
+    /// A reader for the file content.
+    reader: ContentReader,
 
     /// The file handle.
-    ///
-    /// ## Warning
-    ///
-    /// Field must not be moved as it is referenced by `reader`.
-    handle: AliasableBox<FileHandle<S, T>>,
+    handle: FileHandle<S, T>,
+}
+
+/// A reader over the content of a file that owns everything it reads from, so it can be kept
+/// alongside the file it was created from.
+///
+/// The content is read by a task holding its own clone of the file, which pipes it through an
+/// in-memory duplex stream, so at most one pipe's worth of content is held in memory at any time.
+/// An error reading the content is returned once the pipe has been drained.
+pub struct ContentReader {
+    /// The read half of the pipe from the task.
+    reader: DuplexStream,
+
+    /// The task reading the content. `None` once its outcome has been returned.
+    task: Option<JoinHandle<io::Result<()>>>,
 }
 
 /// A file output stream.
+///
+/// Bytes written to the stream are piped to a store task that chunks them as they arrive, so at most
+/// one pipe's worth of content is held in memory at any time.
+///
+/// By default, blocks are written to the handle's store, which is usually an ephemeral buffer store.
+/// If the handle has the [`DescriptorFlags::DIRECT_WRITE`] flag set, blocks are written straight to
+/// the backing store of the root directory instead and only the index nodes are tracked in memory.
 pub struct FileOutputStream<S, T>
 where
     S: IpldStore,
     T: IpldStore,
 {
+    /// The file handle.
     handle: FileHandle<S, T>,
+
+    /// The write half of the pipe to the store task. `None` once the stream is closed.
+    writer: Option<DuplexStream>,
+
+    /// The task storing the piped content. Resolves to the new content. `None` once the stream is
+    /// closed.
+    task: Option<JoinHandle<StoreResult<PipedContent>>>,
+}
+
+/// The content stored by the task of a [`FileOutputStream`].
+enum PipedContent {
+    /// The content is stored as bytes at the [`Cid`].
+    Bytes(Cid),
+
+    /// The content is listed by the [`ContentChunks`] node at the [`Cid`].
+    Chunks(Cid),
 }
 
 //--------------------------------------------------------------------------------------------------
@@ -57,19 +108,21 @@ where
     T: IpldStore,
 {
     /// Creates an input stream for reading a file's content from its file handle.
-    pub async fn from(handle: FileHandle<S, T>) -> Self {
-        // Store the handle in the heap and make it aliasable.
-        let handle = AliasableBox::from_unique(Box::new(handle));
-
-        // If the file contains a Cid for its content, create a reader for it.
-        let reader: Pin<Box<dyn AsyncRead + Send + Sync>> = match handle.get_content() {
-            Some(cid) => handle.get_store().get_bytes(cid).await.unwrap(),
-            None => Box::pin(&[][..]),
-        };
+    pub fn from(handle: FileHandle<S, T>) -> Self
+    where
+        T: Send + Sync + 'static,
+    {
+        let capacity = handle
+            .get_store()
+            .get_node_block_max_size()
+            .map(|size| size as usize)
+            .unwrap_or(DEFAULT_INPUT_PIPE_CAPACITY);
 
-        // Unsafe magic to escape Rust ownership grip.
-        let reader: Pin<Box<dyn AsyncRead + Send + Sync + 'static>> =
-            unsafe { std::mem::transmute(reader) };
+        let reader = ContentReader::with_pattern(
+            handle.entity().clone(),
+            capacity,
+            handle.get_access_pattern(),
+        );
 
         Self {
             buffer: Ok(BytesMut::new()),
@@ -102,15 +155,196 @@ where
     }
 }
 
+impl ContentReader {
+    /// Creates a reader over the content of `file`, piped through a buffer of `capacity` bytes.
+    pub fn from<S>(file: File<S>, capacity: usize) -> Self
+    where
+        S: IpldStore + Send + Sync + 'static,
+    {
+        Self::with_pattern(file, capacity, AccessPattern::Normal)
+    }
+
+    /// Creates a reader over the content of `file`, piped through a buffer of `capacity` bytes and
+    /// read the way `pattern` suits.
+    pub fn with_pattern<S>(file: File<S>, capacity: usize, pattern: AccessPattern) -> Self
+    where
+        S: IpldStore + Send + Sync + 'static,
+    {
+        let (mut writer, reader) = io::duplex(capacity);
+        let task = tokio::spawn(async move {
+            if pattern == AccessPattern::Normal {
+                let mut content = file.get_content_reader().await.map_err(io::Error::other)?;
+                io::copy(&mut content, &mut writer).await?;
+                return writer.shutdown().await;
+            }
+
+            let mut content = file.get_content_stream_with(pattern);
+            while let Some(bytes) = content.try_next().await.map_err(io::Error::other)? {
+                writer.write_all(&bytes).await?;
+            }
+
+            writer.shutdown().await
+        });
+
+        Self {
+            reader,
+            task: Some(task),
+        }
+    }
+}
+
 impl<S, T> FileOutputStream<S, T>
 where
-    S: IpldStore,
-    T: IpldStore,
+    S: IpldStore + Send + Sync + 'static,
+    T: IpldStore + Send + Sync + 'static,
 {
-    // /// Creates a new file output stream from a file handle and an offset.
-    // pub fn from(file: FileHandle<S, T>, offset: u64) -> Self {
-    //     Self { handle: file }
-    // }
+    /// Creates an output stream for writing a file's content from its file handle.
+    ///
+    /// The written bytes replace the existing content from `offset`, and the content around them
+    /// is kept. Writing past the end of the content leaves the gap before the written bytes as a
+    /// hole that reads as zeros.
+    pub fn from(handle: FileHandle<S, T>, offset: u64) -> Self {
+        Self::start(handle, Some(offset))
+    }
+
+    /// Creates an output stream for writing after a file's existing content from its file handle.
+    pub fn append(handle: FileHandle<S, T>) -> Self {
+        Self::start(handle, None)
+    }
+
+    /// Starts the task storing the content written from `offset`, or after the existing content if
+    /// `offset` is `None`.
+    fn start(handle: FileHandle<S, T>, offset: Option<u64>) -> Self {
+        let capacity = handle
+            .get_store()
+            .get_raw_block_max_size()
+            .map(|size| size as usize)
+            .unwrap_or(DEFAULT_OUTPUT_PIPE_CAPACITY);
+
+        let (writer, reader) = io::duplex(capacity);
+        let file = handle.entity().clone();
+
+        let task = if handle.flags().contains(DescriptorFlags::DIRECT_WRITE) {
+            let store = handle.root().get_store();
+            tokio::spawn(pipe_to_store(store, file, offset, reader))
+        } else {
+            let store = file.get_store().clone();
+            tokio::spawn(pipe_to_store(store, file, offset, reader))
+        };
+
+        Self {
+            handle,
+            writer: Some(writer),
+            task: Some(task),
+        }
+    }
+
+    /// Writes the given bytes to the stream.
+    ///
+    /// This waits if the store task has not caught up with previous writes yet.
+    pub async fn write(&mut self, bytes: impl AsRef<[u8]>) -> FsResult<()> {
+        let writer = self.writer.as_mut().ok_or(FsError::StreamClosed)?;
+        writer.write_all(bytes.as_ref()).await?;
+        Ok(())
+    }
+
+    /// Closes the stream and returns the file with its content updated to the written bytes.
+    ///
+    /// Fails with [`FsError::ReadOnlyFilesystem`] if the file tree was made read-only since the
+    /// stream was opened.
+    pub async fn close(mut self) -> FsResult<File<T>> {
+        if let Some(mut writer) = self.writer.take() {
+            writer.shutdown().await?;
+        }
+
+        let task = self.task.take().ok_or(FsError::StreamClosed)?;
+        let content = task.await.map_err(FsError::custom)??;
+        if self.handle.root().is_read_only() {
+            return Err(FsError::ReadOnlyFilesystem(self.handle.path()));
+        }
+
+        let mut file = self.handle.entity().clone();
+        match content {
+            PipedContent::Bytes(cid) => file.set_content(Some(cid)),
+            PipedContent::Chunks(cid) => file.set_chunks(cid),
+        }
+
+        Ok(file)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Stores the content of `file` with the bytes coming through `reader` written over it from
+/// `offset`, or after it if `offset` is `None`, in `store`.
+///
+/// Bytes written within the content are spliced into it, keeping the existing content before
+/// `offset` and after the written bytes. Bytes written at or past the end are added as a new chunk
+/// after the existing ones, with the gap before them, if any, left as a hole. Either way the content
+/// is streamed through a pipe, so it is never held in memory as a whole.
+async fn pipe_to_store<U, V>(
+    store: U,
+    file: File<V>,
+    offset: Option<u64>,
+    mut reader: DuplexStream,
+) -> StoreResult<PipedContent>
+where
+    U: IpldStore + Sync,
+    V: IpldStore + Sync,
+{
+    let len = file.get_size().await.map_err(StoreError::custom)?.len;
+    let offset = offset.unwrap_or(len);
+    if len == 0 && offset == 0 {
+        return Ok(PipedContent::Bytes(store.put_bytes(reader).await?));
+    }
+
+    let capacity = store
+        .get_raw_block_max_size()
+        .map(|size| size as usize)
+        .unwrap_or(DEFAULT_OUTPUT_PIPE_CAPACITY);
+    let (mut writer, content) = io::duplex(capacity);
+
+    if offset >= len {
+        let mut node =
+            ContentChunks::from_content(file.get_store(), file.get_content(), file.is_chunked())
+                .await
+                .map_err(StoreError::custom)?;
+        node.push_hole(offset - len);
+
+        let copy = async move {
+            let written = io::copy(&mut reader, &mut writer).await?;
+            writer.shutdown().await?;
+            Ok::<_, io::Error>(written)
+        };
+
+        let (cid, written) = tokio::join!(store.put_bytes(content), copy);
+        let written = written.map_err(StoreError::custom)?;
+        if written > 0 {
+            node.push_chunk(cid?, written);
+        }
+
+        return Ok(PipedContent::Chunks(store.put_node(&node).await?));
+    }
+
+    let splice = async move {
+        let mut existing = file.get_content_reader().await.map_err(io::Error::other)?;
+
+        // The existing content up to the offset.
+        io::copy(&mut (&mut existing).take(offset), &mut writer).await?;
+
+        // The written bytes, over as much of the existing content, and whatever remains after.
+        let written = io::copy(&mut reader, &mut writer).await?;
+        io::copy(&mut (&mut existing).take(written), &mut io::sink()).await?;
+        io::copy(&mut existing, &mut writer).await?;
+
+        writer.shutdown().await
+    };
+
+    let (cid, spliced) = tokio::join!(store.put_bytes(content), splice);
+    spliced.map_err(StoreError::custom)?;
+    Ok(PipedContent::Bytes(cid?))
 }
 
 //--------------------------------------------------------------------------------------------------
@@ -137,6 +371,51 @@ where
     }
 }
 
+impl AsyncRead for ContentReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let filled = buf.filled().len();
+        ready!(Pin::new(&mut self.reader).poll_read(cx, buf))?;
+        if buf.filled().len() > filled || buf.remaining() == 0 {
+            return Poll::Ready(Ok(()));
+        }
+
+        // The pipe is drained, so the task is done writing. Report how it went.
+        let Some(task) = self.task.as_mut() else {
+            return Poll::Ready(Ok(()));
+        };
+
+        let result = ready!(Pin::new(task).poll(cx));
+        self.task = None;
+
+        Poll::Ready(result.map_err(io::Error::other).and_then(|result| result))
+    }
+}
+
+impl Drop for ContentReader {
+    fn drop(&mut self) {
+        if let Some(task) = self.task.take() {
+            task.abort();
+        }
+    }
+}
+
+impl<S, T> Drop for FileOutputStream<S, T>
+where
+    S: IpldStore,
+    T: IpldStore,
+{
+    fn drop(&mut self) {
+        // A stream dropped without being closed, say by a cancelled request, stops storing.
+        if let Some(task) = self.task.take() {
+            task.abort();
+        }
+    }
+}
+
 impl<S, T> InputStream for FileInputStream<S, T>
 where
     S: IpldStore + Send + Sync + 'static,
@@ -159,7 +438,7 @@ where
 mod tests {
     use zeroutils_store::MemoryStore;
 
-    use crate::filesystem::{DescriptorFlags, RootDir};
+    use crate::filesystem::{MemoryBufferStore, RootDir};
 
     use super::*;
 
@@ -169,12 +448,141 @@ mod tests {
         let data = fixtures::sample_data();
         let cid = store.put_bytes(&data[..]).await?;
 
-        // Get root handle.
         let root = RootDir::new(store.clone());
-        let root = root.make_handle(DescriptorFlags::READ | DescriptorFlags::MUTATE_DIR);
+        let mut file = File::new(MemoryBufferStore::new(store.clone()));
+        file.set_content(Some(cid));
+
+        let handle = FileHandle::from(
+            file,
+            Some("file".parse()?),
+            DescriptorFlags::READ,
+            root,
+            vec![],
+        );
+
+        let mut input = FileInputStream::from(handle);
+        let mut content = Vec::new();
+        loop {
+            input.wait().await;
+            let bytes = input.read(u64::MAX)?;
+            if bytes.is_empty() {
+                break;
+            }
+
+            content.extend_from_slice(&bytes);
+        }
+
+        assert_eq!(content, data);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_file_output_stream_buffered_write() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let root = RootDir::new(store.clone());
+        let handle = FileHandle::from(
+            File::new(MemoryBufferStore::new(store.clone())),
+            Some("file".parse()?),
+            DescriptorFlags::READ | DescriptorFlags::WRITE,
+            root,
+            vec![],
+        );
+
+        let mut output = FileOutputStream::from(handle, 0);
+        output.write(fixtures::sample_data()).await?;
+        let file = output.close().await?;
+
+        let cid = file.get_content().unwrap();
+        assert!(!store.has(cid).await);
+
+        let mut content = Vec::new();
+        file.get_store()
+            .get_bytes(cid)
+            .await?
+            .read_to_end(&mut content)
+            .await?;
+
+        assert_eq!(content, fixtures::sample_data());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_file_output_stream_direct_write() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let root = RootDir::new(store.clone());
+        let handle = FileHandle::from(
+            File::new(MemoryBufferStore::new(store.clone())),
+            Some("file".parse()?),
+            DescriptorFlags::READ | DescriptorFlags::WRITE | DescriptorFlags::DIRECT_WRITE,
+            root,
+            vec![],
+        );
+
+        let mut output = FileOutputStream::from(handle, 0);
+        output.write(fixtures::sample_data()).await?;
+        let file = output.close().await?;
+
+        let cid = file.get_content().unwrap();
+        assert!(store.has(cid).await);
+
+        let mut content = Vec::new();
+        store
+            .get_bytes(cid)
+            .await?
+            .read_to_end(&mut content)
+            .await?;
+
+        assert_eq!(content, fixtures::sample_data());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_file_output_stream_write_at_offset() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let root = RootDir::new(store.clone());
+        let mut file = File::new(MemoryBufferStore::new(store.clone()));
+        file.set_content(Some(store.put_bytes(&b"0123456789"[..]).await?));
+
+        let write_at = |file: File<_>, offset, bytes: &'static [u8]| {
+            let root = root.clone();
+            async move {
+                let handle = FileHandle::from(
+                    file,
+                    Some("file".parse()?),
+                    DescriptorFlags::READ | DescriptorFlags::WRITE,
+                    root,
+                    vec![],
+                );
+                let mut output = FileOutputStream::from(handle, offset);
+                output.write(bytes).await?;
+                let file = output.close().await?;
+
+                let mut content = Vec::new();
+                file.get_content_reader()
+                    .await?
+                    .read_to_end(&mut content)
+                    .await?;
+                anyhow::Ok((file, content))
+            }
+        };
+
+        // Bytes within the content replace as many bytes and keep the rest.
+        let (file, content) = write_at(file, 2, b"ab").await?;
+        assert_eq!(content, b"01ab456789");
+
+        // Bytes running past the end extend the content.
+        let (file, content) = write_at(file, 8, b"xyz").await?;
+        assert_eq!(content, b"01ab4567xyz");
+
+        // Bytes past the end leave a hole that reads as zeros.
+        let (file, content) = write_at(file, 13, b"!").await?;
+        assert_eq!(content, b"01ab4567xyz\0\0!");
 
-        // // Create file.
-        // let file = todo!();
+        let size = file.get_size().await?;
+        assert_eq!((size.len, size.allocated), (14, 12));
 
         Ok(())
     }
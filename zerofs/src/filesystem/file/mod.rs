@@ -1,14 +1,21 @@
+mod chunker;
 mod file;
 #[cfg(feature = "wasi_api")]
 mod io;
 #[cfg(feature = "wasi_api")]
+mod op_clone_to;
+#[cfg(feature = "wasi_api")]
 mod op_read_via_stream;
 #[cfg(feature = "wasi_api")]
 mod op_write_via_stream;
+mod open;
 
 //--------------------------------------------------------------------------------------------------
 // Exports
 //--------------------------------------------------------------------------------------------------
 
+pub use chunker::*;
 pub use file::*;
+#[cfg(feature = "wasi_api")]
 pub use io::*;
+pub use open::*;
@@ -0,0 +1,114 @@
+use std::convert::TryInto;
+
+use zeroutils_key::GetPublicKey;
+use zeroutils_store::{ipld::cid::Cid, IpldStore, Storable};
+use zeroutils_ucan::UcanAuth;
+
+use crate::filesystem::{FileHandle, FsError, FsResult, Path, TraceResult};
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl<S, T> FileHandle<S, T>
+where
+    S: IpldStore + Send + Sync + 'static,
+    T: IpldStore + Send + Sync + 'static,
+{
+    /// Creates a new file at `path`, relative to the root directory, that shares the content of
+    /// this file rather than copying it, and returns its [`Cid`].
+    ///
+    /// This takes the same time whatever the size of the file, since only a new file node is
+    /// stored. The two files are [reflinks][crate::filesystem::File::reflink] of each other, so
+    /// writes to either of them afterwards do not show in the other. The content is the one the
+    /// handle was opened with, so writes through streams that are still open are not part of it.
+    ///
+    /// Fails with [`FsError::PathExists`] if there is already an entity at `path`.
+    pub async fn clone_to<U, K>(
+        &self,
+        path: impl TryInto<Path, Error: Into<FsError>>,
+        _ucan: UcanAuth<'_, U, K>,
+    ) -> FsResult<Cid>
+    where
+        U: IpldStore,
+        K: GetPublicKey,
+    {
+        let path = path.try_into().map_err(Into::into)?;
+        let root = self.root();
+        if root.is_read_only() {
+            return Err(FsError::ReadOnlyFilesystem(path));
+        }
+
+        if let TraceResult::Found { .. } = root.fork().trace_entity(&path).await? {
+            return Err(FsError::PathExists(path));
+        }
+
+        // TODO: Check if user has capabilities to create a file at the path.
+
+        let cid = self
+            .entity()
+            .reflink()
+            .use_store(root.get_store())
+            .store()
+            .await?;
+        root.link_at(&path, cid).await?;
+
+        Ok(cid)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::AsyncReadExt;
+    use zeroutils_key::{Ed25519KeyPair, KeyPairGenerate};
+    use zeroutils_store::{MemoryStore, PlaceholderStore};
+
+    use crate::{
+        filesystem::{Chunker, DescriptorFlags, File, RootDir},
+        utils::fixture,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_clone_to_shares_content() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let iss_key = Ed25519KeyPair::generate(&mut rand::thread_rng())?;
+        let root = RootDir::new(store.clone());
+
+        let mut file = File::new(store.clone());
+        file.put_content(&b"disk image"[..], &Chunker::Store)
+            .await?;
+        let handle = FileHandle::from(
+            file.clone(),
+            Some("image".parse()?),
+            DescriptorFlags::READ,
+            root.clone(),
+            vec![],
+        );
+
+        let auth = || fixture::mock_ucan_auth(&iss_key, PlaceholderStore);
+        let cid = handle.clone_to("backup/image", auth()?).await?;
+        let clone = File::load(&cid, store).await?;
+        assert_eq!(clone.get_content(), file.get_content());
+
+        let mut content = String::new();
+        clone
+            .get_content_reader()
+            .await?
+            .read_to_string(&mut content)
+            .await?;
+        assert_eq!(content, "disk image");
+
+        assert!(matches!(
+            handle.clone_to("backup/image", auth()?).await,
+            Err(FsError::PathExists(_))
+        ));
+
+        Ok(())
+    }
+}
@@ -24,7 +24,7 @@ where
         K: GetPublicKey,
     {
         // TODO: Check if user has capabilities to read the file.
-        // Ok(FileInputStream::from(self.clone()).await)
+        // Ok(FileInputStream::from(self.clone()))
         todo!()
     }
 }
@@ -2,7 +2,9 @@ use zeroutils_key::GetPublicKey;
 use zeroutils_store::IpldStore;
 use zeroutils_ucan::UcanAuth;
 
-use crate::filesystem::{DescriptorFlags, FileHandle, FileOutputStream, FsResult};
+use crate::filesystem::{
+    DescriptorFlags, EntityAttributes, FileHandle, FileOutputStream, FsError, FsResult,
+};
 
 //--------------------------------------------------------------------------------------------------
 // Methods
@@ -10,13 +12,19 @@ use crate::filesystem::{DescriptorFlags, FileHandle, FileOutputStream, FsResult}
 
 impl<S, T> FileHandle<S, T>
 where
-    S: IpldStore,
-    T: IpldStore,
+    S: IpldStore + Send + Sync + 'static,
+    T: IpldStore + Send + Sync + 'static,
 {
     /// Returns a stream to write to the file.
+    ///
+    /// If the handle has the [`DescriptorFlags::DIRECT_WRITE`] flag set, the content is written
+    /// straight to the backing store instead of the buffer store.
+    ///
+    /// Fails with [`FsError::Immutable`] if the file is immutable. Writes to an append-only file
+    /// always go after its existing content, whatever `offset` is.
     pub fn write_via_stream<U, K>(
         &self,
-        _offset: u64,
+        offset: u64,
         _ucan: UcanAuth<U, K>,
     ) -> FsResult<FileOutputStream<S, T>>
     where
@@ -24,15 +32,82 @@ where
         K: GetPublicKey,
     {
         if !self.flags().contains(DescriptorFlags::WRITE) {
-            todo!()
+            return Err(FsError::WrongFileDescriptorFlags(
+                self.path(),
+                *self.flags(),
+            ));
+        }
+
+        if self.root().is_read_only() {
+            return Err(FsError::ReadOnlyFilesystem(self.path()));
+        }
+
+        let attributes = self.entity().get_metadata().attributes;
+        if attributes.contains(EntityAttributes::IMMUTABLE) {
+            return Err(FsError::Immutable(self.path()));
         }
 
         // TODO: Check if user has capabilities to write to the file.
 
-        todo!()
+        if attributes.contains(EntityAttributes::APPEND_ONLY) {
+            return Ok(FileOutputStream::append(self.clone()));
+        }
+
+        Ok(FileOutputStream::from(self.clone(), offset))
     }
 }
 
 //--------------------------------------------------------------------------------------------------
 // Tests
 //--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::AsyncReadExt;
+    use zeroutils_key::{Ed25519KeyPair, KeyPairGenerate};
+    use zeroutils_store::{MemoryStore, PlaceholderStore};
+
+    use crate::{
+        filesystem::{Chunker, File, MemoryBufferStore, RootDir},
+        utils::fixture,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_write_via_stream_appends_to_append_only_file() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let iss_key = Ed25519KeyPair::generate(&mut rand::thread_rng())?;
+
+        let mut file = File::new(MemoryBufferStore::new(store.clone()));
+        file.put_content(&b"first line\n"[..], &Chunker::Store)
+            .await?;
+        file.set_attributes(EntityAttributes::APPEND_ONLY);
+
+        let handle = FileHandle::from(
+            file,
+            Some("log".parse()?),
+            DescriptorFlags::READ | DescriptorFlags::WRITE,
+            RootDir::new(store),
+            vec![],
+        );
+
+        // The offset is ignored, so the existing content is kept.
+        let mut output =
+            handle.write_via_stream(0, fixture::mock_ucan_auth(&iss_key, PlaceholderStore)?)?;
+        output.write(b"second line\n").await?;
+        let file = output.close().await?;
+
+        let mut content = String::new();
+        file.get_content_reader()
+            .await?
+            .read_to_string(&mut content)
+            .await?;
+        assert_eq!(content, "first line\nsecond line\n");
+
+        let size = file.get_size().await?;
+        assert_eq!((size.len, size.allocated), (23, 23));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,429 @@
+use std::{collections::BTreeMap, io::SeekFrom};
+
+use bytes::Bytes;
+use tokio::io::AsyncReadExt;
+use zeroutils_store::{ipld::cid::Cid, IpldStore};
+
+use crate::filesystem::{
+    current_time, ContentChunks, ContentPiece, DescriptorFlags, EntityAttributes, File, FileHandle,
+    FsError, FsResult,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// An open file description: a [`FileHandle`] along with a position in its content that reads and
+/// writes start from and move past, like a POSIX file offset.
+///
+/// Writes go to a copy of the file that the description keeps, which is returned by
+/// [`close`][Self::close]. A write within the content splices the written bytes into it, so the
+/// bytes after them are kept. A write at or past the end adds the written bytes as a new chunk
+/// after the existing ones, and leaves the gap before them, if any, as a hole that reads as zeros
+/// without being stored.
+#[derive(Debug)]
+pub struct OpenFile<S, T>
+where
+    S: IpldStore,
+    T: IpldStore,
+{
+    /// The file handle.
+    handle: FileHandle<S, T>,
+
+    /// The file with the writes made so far.
+    file: File<T>,
+
+    /// The position reads and writes start from.
+    position: u64,
+
+    /// The length of the content of the file.
+    len: u64,
+}
+
+/// The open file descriptions of a remote session, by the IDs they were given when they were
+/// opened.
+///
+/// IDs are never reused, so a stale ID sent by a client does not reach a file opened later.
+#[derive(Debug)]
+pub struct OpenFileTable<S, T>
+where
+    S: IpldStore,
+    T: IpldStore,
+{
+    /// The ID of the next file opened.
+    next_id: u64,
+
+    /// The open file descriptions by ID.
+    files: BTreeMap<u64, OpenFile<S, T>>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl<S, T> OpenFile<S, T>
+where
+    S: IpldStore + Send + Sync + 'static,
+    T: IpldStore + Send + Sync + 'static,
+{
+    /// Opens the file of `handle` with the position at its start.
+    pub async fn open(handle: FileHandle<S, T>) -> FsResult<Self> {
+        let file = handle.entity().clone();
+        let len = file.get_size().await?.len;
+
+        Ok(Self {
+            handle,
+            file,
+            position: 0,
+            len,
+        })
+    }
+
+    /// Returns the file handle.
+    pub fn handle(&self) -> &FileHandle<S, T> {
+        &self.handle
+    }
+
+    /// Returns the file with the writes made so far.
+    pub fn file(&self) -> &File<T> {
+        &self.file
+    }
+
+    /// Returns the position reads and writes start from.
+    pub fn get_position(&self) -> u64 {
+        self.position
+    }
+
+    /// Returns the length of the content of the file.
+    pub fn get_len(&self) -> u64 {
+        self.len
+    }
+
+    /// Moves the position and returns the new one.
+    ///
+    /// The position can be moved past the end of the file, in which case the next write leaves a
+    /// hole before it. Fails with [`FsError::InvalidSeek`] if it would move before the start.
+    pub fn seek(&mut self, pos: SeekFrom) -> FsResult<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.len.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+
+        self.position = position.ok_or_else(|| FsError::InvalidSeek(self.handle.path()))?;
+
+        Ok(self.position)
+    }
+
+    /// Reads up to `len` bytes from the position and moves it past them.
+    ///
+    /// Returns fewer bytes than `len`, or none, if the end of the file comes first.
+    pub async fn read(&mut self, len: u64) -> FsResult<Bytes> {
+        if !self.handle.flags().contains(DescriptorFlags::READ) {
+            return Err(FsError::WrongFileDescriptorFlags(
+                self.handle.path(),
+                *self.handle.flags(),
+            ));
+        }
+
+        let reader = self.file.get_content_reader_from(self.position).await?;
+
+        let mut bytes = Vec::new();
+        reader.take(len).read_to_end(&mut bytes).await?;
+        self.position += bytes.len() as u64;
+
+        Ok(bytes.into())
+    }
+
+    /// Writes `bytes` at the position, over the bytes already there, and moves the position past
+    /// them. Returns the number of bytes written.
+    ///
+    /// Fails with [`FsError::Immutable`] if the file is immutable, and with [`FsError::InvalidSeek`]
+    /// if the bytes would end past the largest position. Writes to an append-only file always go
+    /// after its existing content, wherever the position is.
+    pub async fn write(&mut self, bytes: impl AsRef<[u8]>) -> FsResult<u64> {
+        if !self.handle.flags().contains(DescriptorFlags::WRITE) {
+            return Err(FsError::WrongFileDescriptorFlags(
+                self.handle.path(),
+                *self.handle.flags(),
+            ));
+        }
+
+        if self.handle.root().is_read_only() {
+            return Err(FsError::ReadOnlyFilesystem(self.handle.path()));
+        }
+
+        let attributes = self.file.get_metadata().attributes;
+        if attributes.contains(EntityAttributes::IMMUTABLE) {
+            return Err(FsError::Immutable(self.handle.path()));
+        }
+
+        if attributes.contains(EntityAttributes::APPEND_ONLY) {
+            self.position = self.len;
+        }
+
+        let bytes = bytes.as_ref();
+        let end = self
+            .position
+            .checked_add(bytes.len() as u64)
+            .ok_or_else(|| FsError::InvalidSeek(self.handle.path()))?;
+
+        let direct = self.handle.flags().contains(DescriptorFlags::DIRECT_WRITE);
+        if self.position >= self.len {
+            let gap = self.position - self.len;
+            let cid = if direct {
+                let store = self.handle.root().get_store();
+                append(&store, &self.file, gap, bytes).await?
+            } else {
+                append(self.file.get_store(), &self.file, gap, bytes).await?
+            };
+
+            self.file.set_chunks(cid);
+        } else {
+            let cid = if direct {
+                let store = self.handle.root().get_store();
+                splice(&store, &self.file, self.position, bytes).await?
+            } else {
+                splice(self.file.get_store(), &self.file, self.position, bytes).await?
+            };
+
+            self.file.set_chunks(cid);
+        }
+
+        self.file.set_modified_at(current_time());
+        self.position = end;
+        self.len = self.len.max(end);
+
+        Ok(bytes.len() as u64)
+    }
+
+    /// Closes the file and returns it with the writes made to it.
+    pub fn close(self) -> File<T> {
+        self.file
+    }
+}
+
+impl<S, T> OpenFileTable<S, T>
+where
+    S: IpldStore,
+    T: IpldStore,
+{
+    /// Creates an empty table.
+    pub fn new() -> Self {
+        Self {
+            next_id: 0,
+            files: BTreeMap::new(),
+        }
+    }
+
+    /// Adds `file` to the table and returns the ID it was given.
+    pub fn insert(&mut self, file: OpenFile<S, T>) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.files.insert(id, file);
+        id
+    }
+
+    /// Returns the open file with the ID `id`.
+    pub fn get(&self, id: u64) -> Option<&OpenFile<S, T>> {
+        self.files.get(&id)
+    }
+
+    /// Returns the open file with the ID `id` to read, write or seek.
+    pub fn get_mut(&mut self, id: u64) -> Option<&mut OpenFile<S, T>> {
+        self.files.get_mut(&id)
+    }
+
+    /// Removes the open file with the ID `id` from the table and returns it.
+    pub fn remove(&mut self, id: u64) -> Option<OpenFile<S, T>> {
+        self.files.remove(&id)
+    }
+
+    /// Returns the number of open files.
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    /// Returns `true` if there are no open files.
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Stores the content of `file` with `bytes` written at `position`, within the content, in
+/// `store`, and returns the [`Cid`] of the [`ContentChunks`] node listing it.
+///
+/// The written bytes are stored as a new chunk. Only the chunks they overlap are read, to keep the
+/// bytes of them outside the write, and the other chunks and holes are kept as they are.
+async fn splice<U, V>(store: &U, file: &File<V>, position: u64, bytes: &[u8]) -> FsResult<Cid>
+where
+    U: IpldStore + Sync,
+    V: IpldStore + Sync,
+{
+    let content =
+        ContentChunks::from_content(file.get_store(), file.get_content(), file.is_chunked())
+            .await?;
+
+    let end = position + bytes.len() as u64;
+    let mut node = ContentChunks::default();
+    let mut start = 0;
+    for piece in content.pieces() {
+        let len = piece.get_len();
+        if start < position {
+            push_range(store, file, &mut node, piece, 0, len.min(position - start)).await?;
+        }
+
+        if start <= position && position < start + len && !bytes.is_empty() {
+            node.push_chunk(store.put_bytes(bytes).await?, bytes.len() as u64);
+        }
+
+        if start + len > end {
+            push_range(store, file, &mut node, piece, end.max(start) - start, len).await?;
+        }
+
+        start += len;
+    }
+
+    Ok(store.put_node(&node).await?)
+}
+
+/// Adds the bytes from `from` to `to` of `piece` of the content of `file` to `node`, storing them
+/// in `store` as a new chunk if they are only part of a chunk.
+async fn push_range<U, V>(
+    store: &U,
+    file: &File<V>,
+    node: &mut ContentChunks,
+    piece: ContentPiece,
+    from: u64,
+    to: u64,
+) -> FsResult<()>
+where
+    U: IpldStore + Sync,
+    V: IpldStore + Sync,
+{
+    match piece {
+        ContentPiece::Hole(_) => node.push_hole(to - from),
+        ContentPiece::Chunk(cid, _) if from == 0 && to == piece.get_len() => {
+            node.push_chunk(cid, to)
+        }
+        ContentPiece::Chunk(cid, _) => {
+            let mut bytes = Vec::new();
+            file.get_store()
+                .get_bytes(&cid)
+                .await?
+                .read_to_end(&mut bytes)
+                .await?;
+
+            let part = &bytes[from as usize..to as usize];
+            node.push_chunk(store.put_bytes(part).await?, part.len() as u64);
+        }
+    }
+
+    Ok(())
+}
+
+/// Stores the content of `file` followed by a hole of `gap` bytes and then `bytes` in `store`, and
+/// returns the [`Cid`] of the [`ContentChunks`] node listing it.
+///
+/// The existing content is kept as it is, so holes in it are not filled.
+async fn append<U, V>(store: &U, file: &File<V>, gap: u64, bytes: &[u8]) -> FsResult<Cid>
+where
+    U: IpldStore + Sync,
+    V: IpldStore + Sync,
+{
+    let mut node =
+        ContentChunks::from_content(file.get_store(), file.get_content(), file.is_chunked())
+            .await?;
+
+    node.push_hole(gap);
+    if !bytes.is_empty() {
+        node.push_chunk(store.put_bytes(bytes).await?, bytes.len() as u64);
+    }
+
+    Ok(store.put_node(&node).await?)
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl<S, T> Default for OpenFileTable<S, T>
+where
+    S: IpldStore,
+    T: IpldStore,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use zeroutils_store::MemoryStore;
+
+    use crate::filesystem::{MemoryBufferStore, RootDir};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_open_file_seek_read_write() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let mut file = File::new(MemoryBufferStore::new(store.clone()));
+        file.put_content(&b"hello world"[..], &Default::default())
+            .await?;
+
+        let handle = FileHandle::from(
+            file,
+            Some("file".parse()?),
+            DescriptorFlags::READ | DescriptorFlags::WRITE,
+            RootDir::new(store),
+            vec![],
+        );
+
+        let mut table = OpenFileTable::new();
+        let id = table.insert(OpenFile::open(handle).await?);
+        let open = table.get_mut(id).unwrap();
+
+        assert_eq!(open.seek(SeekFrom::Start(6))?, 6);
+        assert_eq!(open.read(3).await?, &b"wor"[..]);
+        assert_eq!(open.seek(SeekFrom::Current(-3))?, 6);
+        open.write(b"there").await?;
+        assert_eq!(open.seek(SeekFrom::End(2))?, 13);
+        open.write(b"!").await?;
+        assert!(open.seek(SeekFrom::Current(-20)).is_err());
+
+        // Writes that would end past the largest position are refused.
+        open.seek(SeekFrom::Start(u64::MAX))?;
+        assert!(matches!(
+            open.write(b"!").await,
+            Err(FsError::InvalidSeek(_))
+        ));
+
+        open.seek(SeekFrom::Start(0))?;
+        assert_eq!(open.read(u64::MAX).await?, &b"hello there\0\0!"[..]);
+        assert_eq!(open.get_len(), 14);
+
+        // The gap is a hole rather than stored zeros.
+        let size = open.file().get_size().await?;
+        assert_eq!((size.len, size.allocated), (14, 12));
+
+        let file = table.remove(id).unwrap().close();
+        let mut content = Vec::new();
+        file.get_content_reader()
+            .await?
+            .read_to_end(&mut content)
+            .await?;
+        assert_eq!(content, b"hello there\0\0!");
+        assert!(table.is_empty());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,138 @@
+use std::convert::TryInto;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use zeroutils_store::{ipld::cid::Cid, IpldStore};
+
+use super::{Entity, EntityType, FsResult};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The status of an entity in the shape of the WASI `filestat` structure, so that the WASI and
+/// FUSE frontends present the same results for the same entity.
+///
+/// Entities are identified by their content rather than by an inode, so the device and inode
+/// numbers are surrogates derived from the CID of the entity: the device is the code of its hash
+/// function and the inode is the start of its digest. Entities with the same content, which are
+/// stored once, get the same numbers, like hard links of the same file would. Timestamps are in
+/// nanoseconds since the Unix epoch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileStat {
+    /// The device the entity is on.
+    pub dev: u64,
+
+    /// The inode number of the entity.
+    pub ino: u64,
+
+    /// The type of the entity.
+    pub filetype: EntityType,
+
+    /// The number of links to the entity. `zerofs` does not support hard links, so this is `1`.
+    pub nlink: u64,
+
+    /// The size of the entity in bytes: the length of the content of a file, holes included, or
+    /// the length of the target of a symbolic link. Directories have a size of `0`.
+    pub size: u64,
+
+    /// The time of the last access, or of the last modification if no access time was set.
+    pub atim: u64,
+
+    /// The time of the last modification of the content.
+    pub mtim: u64,
+
+    /// The time of the last change to the content or to the status.
+    pub ctim: u64,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl FileStat {
+    /// Returns the status of `entity`, whose CID is `cid`.
+    pub async fn new<S>(entity: &Entity<S>, cid: &Cid) -> FsResult<Self>
+    where
+        S: IpldStore + Send + Sync,
+    {
+        let metadata = entity.get_metadata();
+        let size = match entity {
+            Entity::File(file) => file.get_size().await?.len,
+            Entity::Dir(_) => 0,
+            Entity::Symlink(symlink) => symlink.get_path().to_string().len() as u64,
+        };
+
+        let digest = cid.hash().digest();
+        let mut ino = [0; 8];
+        let len = digest.len().min(ino.len());
+        ino[..len].copy_from_slice(&digest[..len]);
+
+        Ok(Self {
+            dev: cid.hash().code(),
+            ino: u64::from_le_bytes(ino),
+            filetype: metadata.entity_type,
+            nlink: 1,
+            size,
+            atim: to_nanos(metadata.accessed_at.unwrap_or(metadata.modified_at)),
+            mtim: to_nanos(metadata.modified_at),
+            ctim: to_nanos(metadata.get_changed_at()),
+        })
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Returns `time` in nanoseconds since the Unix epoch, clamped to what fits in a `u64`.
+fn to_nanos(time: DateTime<Utc>) -> u64 {
+    time.timestamp_nanos_opt()
+        .and_then(|nanos| nanos.try_into().ok())
+        .unwrap_or(if time.timestamp() < 0 { 0 } else { u64::MAX })
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use chrono::TimeZone;
+    use zeroutils_store::{MemoryStore, Storable};
+
+    use crate::filesystem::{with_clock, Acl, Chunker, File, FixedClock};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_filestat() -> anyhow::Result<()> {
+        let clock = Arc::new(FixedClock::new(Utc.timestamp_opt(10, 0).unwrap()));
+        with_clock(clock.clone(), async {
+            let mut file = File::new(MemoryStore::default());
+            file.put_content(&b"hello"[..], &Chunker::Store).await?;
+
+            // A status change moves the change time but not the modification time.
+            clock.set(Utc.timestamp_opt(20, 0).unwrap());
+            file.set_acl(Acl::new());
+            let cid = file.store().await?;
+
+            let stat = FileStat::new(&Entity::File(file.clone()), &cid).await?;
+            assert_eq!(stat.filetype, EntityType::File);
+            assert_eq!(stat.size, 5);
+            assert_eq!(stat.nlink, 1);
+            assert_eq!(stat.atim, stat.mtim);
+            assert_eq!(stat.mtim, 10_000_000_000);
+            assert_eq!(stat.ctim, 20_000_000_000);
+
+            // The same content gets the same inode.
+            let again = FileStat::new(&Entity::File(file), &cid).await?;
+            assert_eq!((again.dev, again.ino), (stat.dev, stat.ino));
+
+            anyhow::Ok(())
+        })
+        .await
+    }
+}
@@ -23,6 +23,13 @@ bitflags! {
         /// This can only be used with directories and it means that the directory and its contents
         /// can be modified.
         const MUTATE_DIR = 0b0000_0100;
+
+        /// This can only be used with files and it means that writes bypass the ephemeral buffer
+        /// store and are chunked straight into the backing store of the root directory.
+        ///
+        /// Useful for large sequential writes where buffering the whole content in memory is not
+        /// an option.
+        const DIRECT_WRITE = 0b0000_1000;
     }
 
     /// Flags to determine how to open a path.
@@ -52,4 +59,39 @@ bitflags! {
         /// Truncate the file to zero size if it exists.
         const TRUNCATE = 0b0000_1000;
     }
+
+    /// Attributes that protect an entity from changes, whatever the flags of the descriptors it is
+    /// opened with.
+    ///
+    /// They are stored in the [`Metadata`][super::Metadata] of the entity and only apply to files.
+    #[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct EntityAttributes: u8 {
+        /// The file cannot be written to, truncated, replaced or removed.
+        const IMMUTABLE = 0b0000_0001;
+
+        /// The file can only be written to after its existing content, and cannot be truncated,
+        /// replaced or removed.
+        const APPEND_ONLY = 0b0000_0010;
+    }
+}
+
+/// How the content of a file is going to be read, declared on its handle so that reads can prefetch
+/// or leave out of the block cache accordingly.
+///
+/// This corresponds to `advice` in the WASI preview 2. Its `will-need` advice is
+/// [`File::will_need`][super::File::will_need], which prefetches a range of the file right away.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AccessPattern {
+    /// No particular pattern. Chunks are read one at a time and cached.
+    #[default]
+    Normal,
+
+    /// The content is read from start to end. The chunks after the one being read are fetched
+    /// ahead of it and cached.
+    Sequential,
+
+    /// The content is read in no particular order, and is unlikely to be read again soon. Chunks
+    /// are read one at a time and not cached, so they do not evict blocks that are.
+    Random,
 }
@@ -0,0 +1,178 @@
+use std::{fmt::Display, str::FromStr};
+
+use super::{FsError, FsResult, PATH_SEPARATOR};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// A pattern that matches paths, like `src/**/*test?`.
+///
+/// The pattern is made of segments separated by `/`. Within a segment, `*` matches any run of
+/// characters and `?` matches a single character. A segment that is exactly `**` matches any number
+/// of segments, including none. Like paths, patterns are case-insensitive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GlobPattern {
+    /// The segments of the pattern.
+    segments: Vec<GlobSegment>,
+}
+
+/// A segment of a [`GlobPattern`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GlobSegment {
+    /// `**`, which matches any number of segments.
+    AnyDepth,
+
+    /// A pattern that matches a single segment, lowercased.
+    Name(String),
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl GlobPattern {
+    /// Returns the segments of the pattern.
+    pub fn get_segments(&self) -> &[GlobSegment] {
+        &self.segments
+    }
+}
+
+impl GlobSegment {
+    /// Returns the name the segment matches if it has no wildcards, so it can be looked up rather
+    /// than matched against every entry of a directory.
+    pub fn as_literal(&self) -> Option<&str> {
+        match self {
+            GlobSegment::Name(pattern) if !pattern.contains(['*', '?']) => Some(pattern),
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if the segment matches `name`. [`GlobSegment::AnyDepth`] matches any name.
+    pub fn matches(&self, name: &str) -> bool {
+        match self {
+            GlobSegment::AnyDepth => true,
+            GlobSegment::Name(pattern) => {
+                let pattern = pattern.chars().collect::<Vec<_>>();
+                let name = name.to_lowercase().chars().collect::<Vec<_>>();
+                matches_wildcards(&pattern, &name)
+            }
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Returns `true` if `name` matches `pattern`, where `*` matches any run of characters and `?`
+/// matches a single one.
+fn matches_wildcards(pattern: &[char], name: &[char]) -> bool {
+    // The position in the pattern after the last `*`, and the position in the name it was
+    // matched up to, to backtrack to when the rest does not match.
+    let mut star = None;
+    let (mut p, mut n) = (0, 0);
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p + 1, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((star_p, star_n)) => {
+                    star = Some((star_p, star_n + 1));
+                    p = star_p;
+                    n = star_n + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl FromStr for GlobPattern {
+    type Err = FsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let segments = s
+            .split(PATH_SEPARATOR)
+            .filter(|segment| !segment.is_empty())
+            .map(|segment| {
+                if segment == "**" {
+                    return Ok(GlobSegment::AnyDepth);
+                }
+
+                if !segment
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '*' || c == '?')
+                {
+                    return Err(FsError::InvalidGlobPattern(s.to_owned()));
+                }
+
+                Ok(GlobSegment::Name(segment.to_lowercase()))
+            })
+            .collect::<FsResult<Vec<_>>>()?;
+
+        if segments.is_empty() {
+            return Err(FsError::InvalidGlobPattern(s.to_owned()));
+        }
+
+        Ok(Self { segments })
+    }
+}
+
+impl Display for GlobPattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let segments = self
+            .segments
+            .iter()
+            .map(|segment| match segment {
+                GlobSegment::AnyDepth => "**",
+                GlobSegment::Name(pattern) => pattern,
+            })
+            .collect::<Vec<_>>();
+
+        write!(f, "/{}", segments.join("/"))
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_pattern() -> anyhow::Result<()> {
+        let pattern: GlobPattern = "src/**/*Test?".parse()?;
+        assert_eq!(pattern.to_string(), "/src/**/*test?");
+
+        let segments = pattern.get_segments();
+        assert_eq!(segments[0].as_literal(), Some("src"));
+        assert_eq!(segments[1], GlobSegment::AnyDepth);
+        assert!(segments[2].matches("unitTests"));
+        assert!(segments[2].matches("tests"));
+        assert!(!segments[2].matches("test"));
+        assert!(!segments[2].matches("testsx"));
+
+        assert!(GlobSegment::Name("a*b*c".to_owned()).matches("aXbYbZc"));
+        assert!(!GlobSegment::Name("a*b*c".to_owned()).matches("aXcYb"));
+
+        assert!("".parse::<GlobPattern>().is_err());
+        assert!("src/*.rs".parse::<GlobPattern>().is_err());
+
+        Ok(())
+    }
+}
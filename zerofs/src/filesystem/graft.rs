@@ -0,0 +1,281 @@
+use futures::future::{BoxFuture, FutureExt};
+use zeroutils_store::{ipld::cid::Cid, IpldStore, Storable};
+
+use super::{
+    current_time, Dir, Entity, EntityType, EntryHint, FsError, FsResult, Path, TraceResult,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Determines what happens when a tree merged with [`merge_subtree_at`] has an entity where the
+/// tree it is merged into already has a different one.
+///
+/// Two directories are never a collision; their entries are merged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MergeStrategy {
+    /// The existing entity is kept and the merged one is left out.
+    Skip,
+
+    /// The existing entity is replaced by the merged one.
+    Overwrite,
+
+    /// Merging fails with [`FsError::PathExists`], leaving the tree as it was.
+    #[default]
+    Fail,
+}
+
+/// What merging a tree with [`merge_subtree_at`] did.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct MergeReport {
+    /// The paths entities of the merged tree were linked at, in the order they were merged. The
+    /// entries of a directory that was linked whole are not listed.
+    pub linked: Vec<Path>,
+
+    /// The paths where the existing entity was kept because of a collision.
+    pub skipped: Vec<Path>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Merges the tree whose root is the directory at `src` into the tree under `root`, at `dest`, and
+/// returns the updated root directory along with what was merged.
+///
+/// The tree at `src` must be in the store of `root`, like a snapshot or the root of another tenant
+/// on the same node. Entities that are not at `dest` yet are linked as they are, so they share
+/// their blocks with the merged tree, and so are the ones that are the same in both trees.
+/// Directories in both trees are merged entry by entry, and other entities in both trees are
+/// resolved according to `strategy`. Missing directories along `dest` are created. Overwriting
+/// fails if the entity overwritten is or holds an immutable or append-only file.
+///
+/// Like [`Dir::link_at`], the directories along the path are rewritten and stored but the root
+/// directory itself is not. Fails with [`FsError::NotADirectory`] if `src` is not a directory.
+pub async fn merge_subtree_at<S>(
+    root: &Dir<S>,
+    dest: &Path,
+    src: &Cid,
+    strategy: MergeStrategy,
+) -> FsResult<(Dir<S>, MergeReport)>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    let store = root.get_store().clone();
+    if !matches!(Entity::load(src, store.clone()).await?, Entity::Dir(_)) {
+        return Err(FsError::NotADirectory(None));
+    }
+
+    let existing = if dest.is_empty() {
+        Some(root.store().await?)
+    } else {
+        match root.trace_entity(dest).await? {
+            TraceResult::Found { entity, .. } => Some(entity.store().await?),
+            TraceResult::Incomplete { .. } => None,
+            TraceResult::NotADir { depth, .. } => {
+                return Err(FsError::NotADirectory(Some(dest.slice(..depth).to_owned())))
+            }
+        }
+    };
+
+    let mut report = MergeReport::default();
+    let merged = graft(
+        store.clone(),
+        existing,
+        *src,
+        dest.clone(),
+        strategy,
+        &mut report,
+    )
+    .await?;
+
+    let root = match merged {
+        None => root.clone(),
+        Some(cid) if dest.is_empty() => Dir::load(&cid, store).await?,
+        Some(cid) => root.link_at(dest, cid).await?,
+    };
+
+    Ok((root, report))
+}
+
+/// Merges the entity at `src` into the entity at `dest`, both at `path`, and returns the [`Cid`] to
+/// link at `path`, or `None` if the entity there does not change.
+fn graft<'a, S>(
+    store: S,
+    dest: Option<Cid>,
+    src: Cid,
+    path: Path,
+    strategy: MergeStrategy,
+    report: &'a mut MergeReport,
+) -> BoxFuture<'a, FsResult<Option<Cid>>>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    async move {
+        let dest = match dest {
+            None => {
+                report.linked.push(path);
+                return Ok(Some(src));
+            }
+            Some(dest) if dest == src => return Ok(None),
+            Some(dest) => dest,
+        };
+
+        let dest_entity = Entity::load(&dest, store.clone()).await?;
+        let src_entity = Entity::load(&src, store.clone()).await?;
+        let (dest_dir, src_dir) = match (dest_entity, src_entity) {
+            (Entity::Dir(dest_dir), Entity::Dir(src_dir)) => (dest_dir, src_dir),
+            (dest_entity, _) => {
+                return match strategy {
+                    MergeStrategy::Skip => {
+                        report.skipped.push(path);
+                        Ok(None)
+                    }
+                    MergeStrategy::Overwrite => {
+                        dest_entity.check_replaceable(&path).await?;
+                        report.linked.push(path);
+                        Ok(Some(src))
+                    }
+                    MergeStrategy::Fail => Err(FsError::PathExists(path)),
+                };
+            }
+        };
+
+        let mut merged = dest_dir.clone();
+        let mut changed = false;
+        for (name, link) in src_dir.get_entries() {
+            let mut child_path = path.clone();
+            child_path.push(name.clone());
+
+            let existing = dest_dir.get(name).map(|link| *link.get_cid());
+            let src_cid = *link.get_cid();
+            let Some(cid) = graft(
+                store.clone(),
+                existing,
+                src_cid,
+                child_path,
+                strategy,
+                report,
+            )
+            .await?
+            else {
+                continue;
+            };
+
+            // An entry taken as is from the merged tree keeps its hint, a merged one is a directory.
+            let hint = match cid == src_cid {
+                true => src_dir.get_hint(name).copied(),
+                false => Some(EntryHint {
+                    entity_type: EntityType::Dir,
+                    size: None,
+                }),
+            };
+
+            match hint {
+                Some(hint) => merged.put_with_hint(name.clone(), cid, hint)?,
+                None => merged.put(name.clone(), cid)?,
+            }
+
+            changed = true;
+        }
+
+        if !changed {
+            return Ok(None);
+        }
+
+        merged.set_times(None, Some(current_time()));
+        Ok(Some(merged.store().await?))
+    }
+    .boxed()
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use zeroutils_store::MemoryStore;
+
+    use crate::filesystem::{EntityAttributes, File};
+
+    use super::*;
+
+    async fn file_with(store: &MemoryStore, content: &[u8]) -> anyhow::Result<Cid> {
+        let mut file = File::new(store.clone());
+        file.set_content(Some(store.put_raw_block(content.to_vec()).await?));
+        Ok(file.store().await?)
+    }
+
+    #[tokio::test]
+    async fn test_merge_subtree_at() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let shared = file_with(&store, b"shared").await?;
+        let src = Dir::new(store.clone())
+            .link_at(&"a".parse()?, shared)
+            .await?
+            .link_at(&"sub/b".parse()?, file_with(&store, b"theirs").await?)
+            .await?
+            .link_at(&"sub/c".parse()?, file_with(&store, b"new").await?)
+            .await?;
+        let src_cid = src.store().await?;
+
+        let ours = file_with(&store, b"ours").await?;
+        let root = Dir::new(store.clone())
+            .link_at(&"restore/a".parse()?, shared)
+            .await?
+            .link_at(&"restore/sub/b".parse()?, ours)
+            .await?;
+
+        // The same file is left alone, the new one is linked and the collision is kept as is.
+        let (merged, report) =
+            merge_subtree_at(&root, &"restore".parse()?, &src_cid, MergeStrategy::Skip).await?;
+        assert_eq!(report.linked, ["restore/sub/c".parse::<Path>()?]);
+        assert_eq!(report.skipped, ["restore/sub/b".parse::<Path>()?]);
+        assert_eq!(
+            merged.content_cid_at(&"restore/sub/b".parse()?).await?,
+            root.content_cid_at(&"restore/sub/b".parse()?).await?
+        );
+
+        let (merged, _) = merge_subtree_at(
+            &root,
+            &"restore".parse()?,
+            &src_cid,
+            MergeStrategy::Overwrite,
+        )
+        .await?;
+        assert_eq!(
+            merged.content_cid_at(&"restore/sub/b".parse()?).await?,
+            src.content_cid_at(&"sub/b".parse()?).await?
+        );
+
+        assert!(matches!(
+            merge_subtree_at(&root, &"restore".parse()?, &src_cid, MergeStrategy::Fail).await,
+            Err(FsError::PathExists(_))
+        ));
+
+        // Protected files are not overwritten.
+        let protected = root
+            .set_attributes_at(&"restore/sub/b".parse()?, EntityAttributes::IMMUTABLE)
+            .await?;
+        assert!(matches!(
+            merge_subtree_at(
+                &protected,
+                &"restore".parse()?,
+                &src_cid,
+                MergeStrategy::Overwrite
+            )
+            .await,
+            Err(FsError::Immutable(_))
+        ));
+
+        // Merging into a path with nothing at it links the whole tree.
+        let (merged, report) =
+            merge_subtree_at(&root, &"copy".parse()?, &src_cid, MergeStrategy::Fail).await?;
+        assert_eq!(report.linked, ["copy".parse::<Path>()?]);
+        assert!(merged.get(&"copy".parse()?).is_some());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,93 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use serde_with::serde_as;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The content key of an entity, wrapped to the public key of the DID it was granted to.
+///
+/// Keys are wrapped and unwrapped by clients only. The server stores the wrapped key as is and
+/// never sees the content key in the clear, so a grant lets the recipient decrypt the content
+/// without the server being able to.
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyGrant {
+    /// The content key, encrypted to the public key of the recipient.
+    #[serde_as(as = "serde_with::Bytes")]
+    pub wrapped_key: Vec<u8>,
+
+    /// The name of the scheme the key was wrapped with, for the recipient to unwrap it with, for
+    /// example `x25519-xchacha20poly1305`.
+    pub algorithm: String,
+
+    /// The DID that made the grant, if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub granted_by: Option<String>,
+}
+
+/// The [`KeyGrant`]s stored in the [`Metadata`][super::Metadata] of an entity, by the DID of
+/// their recipient.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct KeyGrants {
+    /// The grants by the DID of their recipient.
+    grants: BTreeMap<String, KeyGrant>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl KeyGrant {
+    /// Creates a grant of `wrapped_key`, wrapped with `algorithm`.
+    pub fn new(wrapped_key: Vec<u8>, algorithm: impl Into<String>) -> Self {
+        Self {
+            wrapped_key,
+            algorithm: algorithm.into(),
+            granted_by: None,
+        }
+    }
+
+    /// Records `did` as the DID that made the grant.
+    pub fn with_granted_by(self, did: impl Into<String>) -> Self {
+        Self {
+            granted_by: Some(did.into()),
+            ..self
+        }
+    }
+}
+
+impl KeyGrants {
+    /// Creates an empty set of grants.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Grants the key to `did`, in place of any grant it had.
+    pub fn grant(&mut self, did: impl Into<String>, grant: KeyGrant) {
+        self.grants.insert(did.into(), grant);
+    }
+
+    /// Removes the grant to `did`.
+    pub fn revoke(&mut self, did: &str) -> Option<KeyGrant> {
+        self.grants.remove(did)
+    }
+
+    /// Returns the grant to `did`.
+    pub fn get(&self, did: &str) -> Option<&KeyGrant> {
+        self.grants.get(did)
+    }
+
+    /// Returns an iterator over the recipients and their grants.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &KeyGrant)> {
+        self.grants.iter().map(|(did, grant)| (did.as_str(), grant))
+    }
+
+    /// Returns `true` if there are no grants.
+    pub fn is_empty(&self) -> bool {
+        self.grants.is_empty()
+    }
+}
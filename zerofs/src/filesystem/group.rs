@@ -0,0 +1,231 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::Cursor,
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncReadExt;
+use zeroutils_store::{IpldStore, Storable};
+
+use super::{Chunker, Dir, Entity, File, FsCapabilities, FsError, FsResult, Path, TraceResult};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The well-known path of the file that stores group memberships, relative to the root.
+pub const GROUPS_PATH: &str = "system/groups";
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The group memberships of a file tree.
+///
+/// A group is identified by a DID that capabilities can be delegated to like any other DID. The
+/// members of a group are listed in a TOML document stored at [`GROUPS_PATH`] in the tree itself,
+/// so granting a team access takes a single delegation and changing who is on the team only takes
+/// a write to that file.
+///
+/// ```toml
+/// [groups."did:wk:z6MkTeam..."]
+/// members = ["did:wk:z6MkAlice...", "did:wk:z6MkBob..."]
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Groups {
+    /// The groups by their DID.
+    #[serde(default)]
+    groups: BTreeMap<String, Group>,
+}
+
+/// A group of DIDs.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Group {
+    /// The DIDs of the members of the group.
+    #[serde(default)]
+    pub members: BTreeSet<String>,
+}
+
+/// Capabilities delegated to an audience, which can be a member DID or a group DID.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FsDelegation {
+    /// The DID the capabilities are delegated to.
+    pub audience: String,
+
+    /// The delegated capabilities.
+    pub capabilities: FsCapabilities,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl Groups {
+    /// Creates an empty set of groups.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads the groups stored at [`GROUPS_PATH`] under `root`.
+    ///
+    /// A tree without a groups file has no groups.
+    pub async fn load<S>(root: &Dir<S>) -> FsResult<Self>
+    where
+        S: IpldStore + Send + Sync,
+    {
+        let path: Path = GROUPS_PATH.parse()?;
+        let file = match root.trace_entity(&path).await? {
+            TraceResult::Found {
+                entity: Entity::File(file),
+                ..
+            } => file,
+            TraceResult::Found { .. } => return Err(FsError::NotAFile(Some(path))),
+            _ => return Ok(Self::new()),
+        };
+
+        let mut content = String::new();
+        file.get_content_reader()
+            .await?
+            .read_to_string(&mut content)
+            .await?;
+
+        toml::from_str(&content).map_err(|e| FsError::InvalidGroupDocument(e.to_string()))
+    }
+
+    /// Stores the groups at [`GROUPS_PATH`] under `root`, replacing any existing groups file, and
+    /// returns the updated root.
+    pub async fn store_at<S>(&self, root: &Dir<S>) -> FsResult<Dir<S>>
+    where
+        S: IpldStore + Send + Sync + 'static,
+    {
+        let content =
+            toml::to_string(self).map_err(|e| FsError::InvalidGroupDocument(e.to_string()))?;
+
+        let mut file = File::new(root.get_store().clone());
+        file.put_content(Cursor::new(content.into_bytes()), &Chunker::Store)
+            .await?;
+
+        let path: Path = GROUPS_PATH.parse()?;
+        root.link_at(&path, file.store().await?).await
+    }
+
+    /// Returns the group with the given DID.
+    pub fn get_group(&self, did: &str) -> Option<&Group> {
+        self.groups.get(did)
+    }
+
+    /// Sets the group with the given DID, replacing any existing group.
+    pub fn set_group(&mut self, did: impl Into<String>, group: Group) {
+        self.groups.insert(did.into(), group);
+    }
+
+    /// Removes the group with the given DID.
+    pub fn remove_group(&mut self, did: &str) -> Option<Group> {
+        self.groups.remove(did)
+    }
+
+    /// Returns `true` if `member` is a member of the group with the DID `group`.
+    pub fn is_member(&self, group: &str, member: &str) -> bool {
+        self.groups
+            .get(group)
+            .is_some_and(|group| group.members.contains(member))
+    }
+
+    /// Returns the DIDs of the groups `member` belongs to.
+    pub fn groups_of<'a>(&'a self, member: &'a str) -> impl Iterator<Item = &'a str> {
+        self.groups
+            .iter()
+            .filter(move |(_, group)| group.members.contains(member))
+            .map(|(did, _)| did.as_str())
+    }
+
+    /// Collects the capabilities that `invoker` holds through `delegations`, either directly or as
+    /// a member of a group the capabilities were delegated to.
+    ///
+    /// The returned set records `invoker` as its holder.
+    pub fn resolve_capabilities<'a>(
+        &self,
+        invoker: &str,
+        delegations: impl IntoIterator<Item = &'a FsDelegation>,
+    ) -> FsCapabilities {
+        delegations
+            .into_iter()
+            .filter(|delegation| {
+                delegation.audience == invoker || self.is_member(&delegation.audience, invoker)
+            })
+            .flat_map(|delegation| delegation.capabilities.iter().cloned())
+            .collect::<FsCapabilities>()
+            .with_invoker(invoker)
+    }
+}
+
+impl Group {
+    /// Creates a group with the given members.
+    pub fn new(members: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self {
+            members: members.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl FsDelegation {
+    /// Creates a new delegation of `capabilities` to `audience`.
+    pub fn new(audience: impl Into<String>, capabilities: FsCapabilities) -> Self {
+        Self {
+            audience: audience.into(),
+            capabilities,
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use zeroutils_store::MemoryStore;
+
+    use crate::filesystem::{FsAction, FsCapability};
+
+    use super::*;
+
+    const ROOT: &str = "did:wk:m7QFAoSJPFzmaqQiTkLrWQ6pbYrmI6L07Fkdg8SCRpjP1Ig";
+    const TEAM: &str = "did:wk:z6MkhjKAZ8a3bzDRE95wWERcVL2Jvo6yY58enNduuWbUYGvG";
+    const ALICE: &str = "did:wk:z6MkoVs2h6TnfyY8fx2ZqpREWSLS8rBDQmGpyXgFpg63CSUb";
+    const BOB: &str = "did:wk:z6MknLif7jhwt6jUfn14EuDnxWoSHkkajyDi28QMMH5eS1DL";
+
+    #[tokio::test]
+    async fn test_groups_resolve_member_capabilities() -> anyhow::Result<()> {
+        let root = Dir::new(MemoryStore::default());
+        assert_eq!(Groups::load(&root).await?, Groups::new());
+
+        let mut groups = Groups::new();
+        groups.set_group(TEAM, Group::new([ALICE]));
+
+        let root = groups.store_at(&root).await?;
+        let groups = Groups::load(&root).await?;
+        assert!(groups.is_member(TEAM, ALICE));
+        assert!(!groups.is_member(TEAM, BOB));
+        assert_eq!(groups.groups_of(ALICE).collect::<Vec<_>>(), vec![TEAM]);
+
+        let delegation = FsDelegation::new(
+            TEAM,
+            vec![FsCapability::new(
+                format!("zerofs://{ROOT}/projects").parse()?,
+                FsAction::Read,
+            )]
+            .into_iter()
+            .collect(),
+        );
+
+        let path = "projects/zerofs".parse()?;
+        let alice = groups.resolve_capabilities(ALICE, [&delegation]);
+        assert!(alice.permits(ROOT, &path, FsAction::Read));
+
+        let bob = groups.resolve_capabilities(BOB, [&delegation]);
+        assert!(!bob.permits(ROOT, &path, FsAction::Read));
+
+        Ok(())
+    }
+}
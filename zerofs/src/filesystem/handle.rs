@@ -2,7 +2,7 @@ use std::{ops::Deref, sync::Arc};
 
 use zeroutils_store::IpldStore;
 
-use super::{DescriptorFlags, Dir, PathDirs, PathSegment, RootDir};
+use super::{AccessPattern, DescriptorFlags, Dir, Path, PathDirs, PathSegment, RootDir};
 
 //--------------------------------------------------------------------------------------------------
 // Types
@@ -47,6 +47,9 @@ where
 
     /// The directories along the path to the entity.
     pub(crate) pathdirs: PathDirs<T>,
+
+    /// How the content of the entity is going to be read.
+    pub(crate) pattern: AccessPattern,
 }
 
 //--------------------------------------------------------------------------------------------------
@@ -83,6 +86,7 @@ where
                 flags,
                 root,
                 pathdirs: pathdirs.into_iter().collect(),
+                pattern: AccessPattern::Normal,
             }),
         }
     }
@@ -111,6 +115,31 @@ where
     pub fn pathdirs(&self) -> &PathDirs<T> {
         &self.inner.pathdirs
     }
+
+    /// Returns how the content of the entity is going to be read.
+    pub fn get_access_pattern(&self) -> AccessPattern {
+        self.inner.pattern
+    }
+
+    /// Declares how the content of the entity is going to be read, so that the streams opened from
+    /// the handle from now on prefetch or skip caching accordingly.
+    pub fn advise(&mut self, pattern: AccessPattern)
+    where
+        E: Clone,
+    {
+        let inner = Arc::make_mut(&mut self.inner);
+        inner.pattern = pattern;
+    }
+
+    /// Returns the path to the entity, made up of the names in its pathdirs and its own name.
+    pub fn path(&self) -> Path {
+        self.inner
+            .pathdirs
+            .iter()
+            .map(|(_, segment)| segment.clone())
+            .chain(self.inner.name.clone())
+            .collect()
+    }
 }
 
 //--------------------------------------------------------------------------------------------------
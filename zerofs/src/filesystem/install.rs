@@ -0,0 +1,263 @@
+use std::{
+    path::{Path as LocalPath, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use serde::{Deserialize, Serialize};
+use serde_with::serde_as;
+use typed_builder::TypedBuilder;
+use zeroutils_store::{ipld::cid::Cid, IpldStore};
+
+use super::{
+    migrate_store, FsResult, MigrateOptions, MigrateStats, ProgressCallback, Subscription,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The default number of blocks between two checkpoints of the progress of an install.
+pub const DEFAULT_CHECKPOINT_INTERVAL: u64 = 256;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Options for [`install_snapshot`].
+#[derive(Clone, TypedBuilder)]
+pub struct InstallOptions {
+    /// The local file the progress of the install is kept in, so it can be resumed after a
+    /// restart.
+    #[builder(setter(into))]
+    pub progress_path: PathBuf,
+
+    /// The number of blocks copied or skipped between two checkpoints of the progress.
+    #[builder(default = DEFAULT_CHECKPOINT_INTERVAL)]
+    pub checkpoint_interval: u64,
+
+    /// Called after each block is copied or skipped, with the progress over all attempts.
+    #[builder(default, setter(strip_option))]
+    pub on_progress: Option<ProgressCallback<InstallProgress>>,
+
+    /// Only installs the subscribed subtrees, see [`MigrateOptions::subscription`].
+    #[builder(default, setter(strip_option))]
+    pub subscription: Option<Subscription>,
+}
+
+/// The progress of installing a snapshot, as kept on the node installing it.
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InstallProgress {
+    /// The root of the snapshot being installed.
+    #[serde_as(as = "serde_with::DisplayFromStr")]
+    pub root: Cid,
+
+    /// The number of times the install was started, the first time included.
+    pub attempts: u32,
+
+    /// The blocks copied and skipped, summed over the attempts.
+    #[serde(flatten)]
+    pub stats: MigrateStats,
+
+    /// Whether the whole snapshot is installed.
+    pub done: bool,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl InstallProgress {
+    /// Creates the progress of an install of `root` that has not started yet.
+    fn new(root: Cid) -> Self {
+        Self {
+            root,
+            attempts: 0,
+            stats: MigrateStats::default(),
+            done: false,
+        }
+    }
+
+    /// Returns this progress with the blocks of an attempt added.
+    fn add(&self, stats: MigrateStats) -> Self {
+        Self {
+            stats: MigrateStats {
+                blocks_copied: self.stats.blocks_copied + stats.blocks_copied,
+                blocks_skipped: self.stats.blocks_skipped + stats.blocks_skipped,
+                bytes_copied: self.stats.bytes_copied + stats.bytes_copied,
+            },
+            ..self.clone()
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Installs the snapshot with the root `root` from `src` into `dst`, like a follower catching up
+/// with its leader, resuming an install of the same snapshot that was interrupted.
+///
+/// The blocks are copied with [`migrate_store`], each after every block it references, so the
+/// subtrees a previous attempt copied in full are found in `dst` and skipped without being fetched
+/// again. The progress is checkpointed to [`progress_path`][InstallOptions::progress_path] every
+/// [`checkpoint_interval`][InstallOptions::checkpoint_interval] blocks, so it carries over a
+/// restart, and an install already done returns right away. Starting the install of another root
+/// starts the progress over, though the blocks it shares with the previous one are still skipped.
+///
+/// Transfers are not rate limited here. To keep an install from taking over a slow link, `src` is
+/// a [`ScheduledStore`][super::ScheduledStore] at
+/// [`TransferPriority::Background`][super::TransferPriority::Background], so that reads someone is
+/// waiting on go first and the install stays under the bandwidth caps.
+pub async fn install_snapshot<S, T>(
+    src: S,
+    dst: T,
+    root: Cid,
+    options: InstallOptions,
+) -> FsResult<InstallProgress>
+where
+    S: IpldStore + Send + Sync,
+    T: IpldStore + Send + Sync,
+{
+    let path = options.progress_path.clone();
+    let mut progress = match load_progress(&path).await {
+        Some(progress) if progress.root == root => progress,
+        _ => InstallProgress::new(root),
+    };
+
+    if progress.done {
+        return Ok(progress);
+    }
+
+    progress.attempts += 1;
+    save_progress(&path, &progress)?;
+
+    let previous = progress.clone();
+    let checkpointed = Arc::new(AtomicU64::new(0));
+    let on_progress: ProgressCallback<MigrateStats> = Arc::new({
+        let previous = previous.clone();
+        let path = path.clone();
+        let interval = options.checkpoint_interval.max(1);
+        let on_progress = options.on_progress.clone();
+        move |stats| {
+            let progress = previous.add(stats);
+            let blocks = stats.blocks_copied + stats.blocks_skipped;
+            if blocks - checkpointed.load(Ordering::Relaxed) >= interval {
+                checkpointed.store(blocks, Ordering::Relaxed);
+                if let Err(e) = save_progress(&path, &progress) {
+                    tracing::warn!("checkpointing install of {} failed: {}", root, e);
+                }
+            }
+
+            if let Some(on_progress) = &on_progress {
+                on_progress(progress);
+            }
+        }
+    });
+
+    let migrate_options = MigrateOptions {
+        on_progress: Some(on_progress),
+        subscription: options.subscription,
+    };
+
+    let stats = migrate_store(src, dst, &[root], migrate_options).await?;
+    let mut progress = previous.add(stats);
+    progress.done = true;
+    save_progress(&path, &progress)?;
+
+    Ok(progress)
+}
+
+/// Reads the progress kept at `path`, or returns `None` if there is none. Progress that cannot be
+/// read is ignored, as an install started over skips what is already copied anyway.
+async fn load_progress(path: &LocalPath) -> Option<InstallProgress> {
+    let content = tokio::fs::read_to_string(path).await.ok()?;
+    match toml::from_str(&content) {
+        Ok(progress) => Some(progress),
+        Err(e) => {
+            tracing::warn!("ignoring unreadable install progress {:?}: {}", path, e);
+            None
+        }
+    }
+}
+
+/// Writes `progress` to `path`, through a temporary file so that a crash never leaves it half
+/// written.
+///
+/// It is written synchronously, as it is called from progress callbacks. The file is small and is
+/// only written every so many blocks.
+fn save_progress(path: &LocalPath, progress: &InstallProgress) -> FsResult<()> {
+    let content = toml::to_string(progress).map_err(super::FsError::custom)?;
+    let temp = path.with_extension("tmp");
+    std::fs::write(&temp, content)?;
+    std::fs::rename(&temp, path)?;
+    Ok(())
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use zeroutils_store::{MemoryStore, Storable};
+
+    use crate::filesystem::{verify, Chunker, Dir, File};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_install_snapshot_resumes() -> anyhow::Result<()> {
+        let src = MemoryStore::default();
+        let mut root = Dir::new(src.clone());
+        for name in ["a/one", "a/two", "b/three"] {
+            let mut file = File::new(src.clone());
+            file.put_content(name.as_bytes(), &Chunker::Store).await?;
+            root = root.link_at(&name.parse()?, file.store().await?).await?;
+        }
+        let root_cid = root.store().await?;
+        let a = *root.get(&"a".parse()?).unwrap().get_cid();
+
+        let base_dir = std::env::temp_dir().join(format!("zerofs{}", rand::random::<u64>()));
+        std::fs::create_dir_all(&base_dir)?;
+        let progress_path = base_dir.join("install.toml");
+
+        // An attempt that was cut short after copying `a` and checkpointing.
+        let dst = MemoryStore::default();
+        let copied = migrate_store(src.clone(), dst.clone(), &[a], Default::default()).await?;
+        let mut interrupted = InstallProgress::new(root_cid).add(copied);
+        interrupted.attempts = 1;
+        save_progress(&progress_path, &interrupted)?;
+
+        // Resuming skips `a` as a whole and counts on from the checkpoint.
+        let options = InstallOptions::builder()
+            .progress_path(&progress_path)
+            .checkpoint_interval(1)
+            .build();
+        let progress =
+            install_snapshot(src.clone(), dst.clone(), root_cid, options.clone()).await?;
+        assert!(progress.done);
+        assert_eq!(progress.attempts, 2);
+        assert_eq!(progress.stats.blocks_skipped, 1);
+        assert_eq!(
+            progress.stats.blocks_copied,
+            copied.blocks_copied + 4,
+            "b, three and its content, and the root"
+        );
+        assert_eq!(load_progress(&progress_path).await, Some(progress.clone()));
+        assert!(verify(&Dir::load(&root_cid, dst.clone()).await?)
+            .await?
+            .is_ok());
+
+        // An install that is done is not run again.
+        let again = install_snapshot(src, dst, root_cid, options).await?;
+        assert_eq!(again, progress);
+
+        std::fs::remove_dir_all(base_dir)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,404 @@
+use serde::{Deserialize, Serialize};
+use serde_with::serde_as;
+use zeroutils_store::{ipld::cid::Cid, IpldStore, Storable};
+
+use super::{diff, Change, Dir, Entity, FsResult, Path, TraceResult};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The operations made on a file tree while it was disconnected from the cluster, to be replayed
+/// once it is connected again.
+///
+/// The journal starts from the root the tree had when it went offline and records the root after
+/// each operation, so the changes an operation made are the differences between its root and the
+/// one before. Every root recorded, along with the blocks under it, stays in the local store, so a
+/// journal can be persisted and replayed after a restart.
+///
+/// See [`replay`][Self::replay] for how the journal is merged with the tree of the cluster.
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Journal {
+    /// The root of the tree when it went offline.
+    #[serde_as(as = "serde_with::DisplayFromStr")]
+    base: Cid,
+
+    /// The operations in the order they were made.
+    #[serde(default)]
+    entries: Vec<JournalEntry>,
+}
+
+/// An operation recorded in a [`Journal`].
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    /// The name of the operation, like `link_at`.
+    pub operation: String,
+
+    /// The path the operation was made on.
+    pub path: Path,
+
+    /// The root of the tree after the operation.
+    #[serde_as(as = "serde_with::DisplayFromStr")]
+    pub root: Cid,
+}
+
+/// The outcome of replaying a [`Journal`].
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ConflictReport {
+    /// The number of changes applied to the tree of the cluster.
+    pub applied: usize,
+
+    /// The changes that were not applied because the tree of the cluster changed the same entity.
+    pub conflicts: Vec<Conflict>,
+}
+
+/// A local change that was not applied during a replay because the entity it changed was also
+/// changed in the tree of the cluster.
+///
+/// The change is left out of the merged tree, but the local version of the entity is still in the
+/// store under [`local`][Self::local], so it can be linked back in once the conflict is resolved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    /// The kind of conflict.
+    pub kind: ConflictKind,
+
+    /// The operation that made the change.
+    pub operation: String,
+
+    /// The path of the entity.
+    pub path: Path,
+
+    /// The CID of the entity before the change, or `None` if the change added it.
+    pub base: Option<Cid>,
+
+    /// The CID of the entity after the change, or `None` if the change removed it.
+    pub local: Option<Cid>,
+
+    /// The CID of the entity in the tree of the cluster, or `None` if there is nothing there.
+    pub remote: Option<Cid>,
+}
+
+/// The kind of a [`Conflict`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConflictKind {
+    /// Both sides added a different entity at the path.
+    BothAdded,
+
+    /// Both sides replaced the entity differently.
+    BothModified,
+
+    /// The entity was replaced locally but removed from the tree of the cluster.
+    RemovedRemotely,
+
+    /// The entity was removed locally but replaced in the tree of the cluster.
+    ModifiedRemotely,
+
+    /// Something other than a directory is now on the way to the path in the tree of the cluster.
+    PathBlocked,
+}
+
+/// What is at a path of a tree.
+enum Slot {
+    Entity(Cid),
+    Empty,
+
+    /// A parent of the path is not a directory.
+    Blocked,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl Journal {
+    /// Starts a journal for a tree whose root is `base`.
+    pub fn new(base: Cid) -> Self {
+        Self {
+            base,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Records that `operation` on `path` left the tree with the root `root`.
+    pub fn record(&mut self, operation: impl Into<String>, path: Path, root: Cid) {
+        self.entries.push(JournalEntry {
+            operation: operation.into(),
+            path,
+            root,
+        });
+    }
+
+    /// Returns the root of the tree when it went offline.
+    pub fn get_base(&self) -> &Cid {
+        &self.base
+    }
+
+    /// Returns the root after the last operation, or the base if there were none.
+    pub fn get_head(&self) -> &Cid {
+        self.entries.last().map_or(&self.base, |entry| &entry.root)
+    }
+
+    /// Returns the recorded operations in the order they were made.
+    pub fn get_entries(&self) -> &[JournalEntry] {
+        &self.entries
+    }
+
+    /// Returns `true` if no operations were recorded.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Replays the recorded operations on `remote`, the tree of the cluster, and returns the merged
+    /// tree along with a report of the changes that could not be applied.
+    ///
+    /// Operations are replayed in order, each as the [`diff`] between its root and the one before.
+    /// A change is applied only if the entity it changed is the same in the merged tree as it was
+    /// before the change, or if the merged tree already has the result of the change. Otherwise the
+    /// cluster changed the entity as well and the change is reported as a [`Conflict`] instead, so
+    /// neither side's version is lost. Changes to different entities never conflict, even in the
+    /// same directory.
+    ///
+    /// The directories along the path of an applied change are rewritten and stored, but the merged
+    /// root itself is not.
+    pub async fn replay<S>(&self, remote: &Dir<S>) -> FsResult<(Dir<S>, ConflictReport)>
+    where
+        S: IpldStore + Send + Sync + 'static,
+    {
+        let store = remote.get_store().clone();
+        let mut merged = remote.clone();
+        let mut report = ConflictReport::default();
+
+        let mut before = Dir::load(&self.base, store.clone()).await?;
+        for entry in &self.entries {
+            let after = Dir::load(&entry.root, store.clone()).await?;
+
+            let mut updates = Vec::new();
+            for change in diff(&before, &after).await? {
+                match change {
+                    Change::Added { path, cid } | Change::Modified { path, cid } => {
+                        updates.push((path, Some(cid)))
+                    }
+                    Change::Removed { path } => updates.push((path, None)),
+                    Change::Renamed { from, to, cid } => {
+                        updates.push((from, None));
+                        updates.push((to, Some(cid)));
+                    }
+                }
+            }
+
+            for (path, local) in updates {
+                let base = match get_slot(&before, &path).await? {
+                    Slot::Entity(cid) => Some(cid),
+                    _ => None,
+                };
+
+                let remote = match get_slot(&merged, &path).await? {
+                    Slot::Entity(cid) => Some(cid),
+                    Slot::Empty => None,
+                    Slot::Blocked => {
+                        report.conflicts.push(Conflict {
+                            kind: ConflictKind::PathBlocked,
+                            operation: entry.operation.clone(),
+                            path,
+                            base,
+                            local,
+                            remote: None,
+                        });
+                        continue;
+                    }
+                };
+
+                if remote == local {
+                    continue;
+                }
+
+                if remote != base {
+                    let kind = match (base, local, remote) {
+                        (None, _, _) => ConflictKind::BothAdded,
+                        (Some(_), Some(_), None) => ConflictKind::RemovedRemotely,
+                        (Some(_), None, _) => ConflictKind::ModifiedRemotely,
+                        (Some(_), Some(_), Some(_)) => ConflictKind::BothModified,
+                    };
+
+                    report.conflicts.push(Conflict {
+                        kind,
+                        operation: entry.operation.clone(),
+                        path,
+                        base,
+                        local,
+                        remote,
+                    });
+                    continue;
+                }
+
+                merged = match local {
+                    Some(cid) => merged.link_at(&path, cid).await?,
+                    None => merged.unlink_at(&path).await?.0,
+                };
+                report.applied += 1;
+            }
+
+            before = after;
+        }
+
+        Ok((merged, report))
+    }
+}
+
+impl ConflictReport {
+    /// Returns `true` if every change was applied.
+    pub fn is_clean(&self) -> bool {
+        self.conflicts.is_empty()
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Merges the changes made from `base` to `theirs` into `ours`, and returns the merged tree along
+/// with a report of the changes that could not be applied.
+///
+/// This is a three-way merge of trees that forked from `base`: the changes on the side of `theirs`
+/// are replayed on `ours` as a single journaled operation, see [`Journal::replay`]. In the report,
+/// `local` is the side of `theirs` and `remote` the side of `ours`.
+pub async fn merge<S>(base: &Cid, ours: &Dir<S>, theirs: &Cid) -> FsResult<(Dir<S>, ConflictReport)>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    let mut journal = Journal::new(*base);
+    journal.record("merge", Path::default(), *theirs);
+    journal.replay(ours).await
+}
+
+/// Returns what is at `path` in the tree under `root`.
+async fn get_slot<S>(root: &Dir<S>, path: &Path) -> FsResult<Slot>
+where
+    S: IpldStore + Send + Sync,
+{
+    let mut parent_path = path.clone();
+    let Some(name) = parent_path.pop() else {
+        return Ok(Slot::Entity(root.store().await?));
+    };
+
+    let parent = if parent_path.is_empty() {
+        root.clone()
+    } else {
+        match root.trace_entity(&parent_path).await? {
+            TraceResult::Found {
+                entity: Entity::Dir(dir),
+                ..
+            } => dir,
+            TraceResult::Found { .. } | TraceResult::NotADir { .. } => return Ok(Slot::Blocked),
+            TraceResult::Incomplete { .. } => return Ok(Slot::Empty),
+        }
+    };
+
+    Ok(match parent.get(&name) {
+        Some(link) => Slot::Entity(*link.get_cid()),
+        None => Slot::Empty,
+    })
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use zeroutils_store::MemoryStore;
+
+    use crate::filesystem::{Chunker, File};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_journal_replay_reports_conflicts() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let file_with = |content: &'static [u8]| {
+            let store = store.clone();
+            async move {
+                let mut file = File::new(store);
+                file.put_content(content, &Chunker::Store).await?;
+                anyhow::Ok(file.store().await?)
+            }
+        };
+
+        let base_c = file_with(b"c").await?;
+        let base = Dir::new(store.clone())
+            .link_at(&"docs/a".parse()?, file_with(b"a").await?)
+            .await?
+            .link_at(&"docs/b".parse()?, file_with(b"b").await?)
+            .await?
+            .link_at(&"docs/c".parse()?, base_c)
+            .await?;
+        let mut journal = Journal::new(base.store().await?);
+
+        // Offline, a is replaced, b removed, c replaced and notes added.
+        let local_a = file_with(b"local a").await?;
+        let local_c = file_with(b"local c").await?;
+        let notes = file_with(b"notes").await?;
+        let mut local = base.clone();
+        for (operation, path, cid) in [
+            ("link_at", "docs/a", Some(local_a)),
+            ("remove_at", "docs/b", None),
+            ("link_at", "docs/c", Some(local_c)),
+            ("link_at", "notes", Some(notes)),
+        ] {
+            let path: Path = path.parse()?;
+            local = match cid {
+                Some(cid) => local.link_at(&path, cid).await?,
+                None => local.unlink_at(&path).await?.0,
+            };
+            journal.record(operation, path, local.store().await?);
+        }
+
+        // Meanwhile, the cluster replaced c as well and added e.
+        let remote_c = file_with(b"remote c").await?;
+        let remote_e = file_with(b"e").await?;
+        let remote = base
+            .link_at(&"docs/c".parse()?, remote_c)
+            .await?
+            .link_at(&"docs/e".parse()?, remote_e)
+            .await?;
+
+        let (merged, report) = journal.replay(&remote).await?;
+        assert_eq!(report.applied, 3);
+        assert_eq!(
+            report.conflicts,
+            [Conflict {
+                kind: ConflictKind::BothModified,
+                operation: "link_at".to_owned(),
+                path: "docs/c".parse()?,
+                base: Some(base_c),
+                local: Some(local_c),
+                remote: Some(remote_c),
+            }]
+        );
+        assert!(!report.is_clean());
+
+        for (path, expected) in [
+            ("docs/a", Some(local_a)),
+            ("docs/b", None),
+            ("docs/c", Some(remote_c)),
+            ("docs/e", Some(remote_e)),
+            ("notes", Some(notes)),
+        ] {
+            let cid = match get_slot(&merged, &path.parse()?).await? {
+                Slot::Entity(cid) => Some(cid),
+                _ => None,
+            };
+            assert_eq!(cid, expected, "{path}");
+        }
+
+        // Replaying again changes nothing, since the merged tree has every change already.
+        let (_, report) = journal.replay(&merged).await?;
+        assert_eq!(report.applied, 0);
+        assert_eq!(report.conflicts.len(), 1);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,117 @@
+use std::fmt::{self, Debug};
+
+use super::{FsError, FsResult, Path, PathSegment};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The context the keys of children are derived in, so that they cannot collide with other uses
+/// of the same keys.
+const KEY_DERIVATION_CONTEXT: &[u8] = b"zerofs 2024 content key v1";
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// A 256-bit symmetric key that the content of an entity is encrypted with.
+///
+/// Keys form a hierarchy that mirrors the tree: the key of an entity is derived from the key of
+/// the directory it is in, its name and its [key epoch][super::Metadata::key_epoch]. So holding the
+/// key of a directory is enough to derive the key of everything beneath it, and sharing a
+/// directory takes a single [`KeyGrant`][super::KeyGrant] rather than one per file.
+///
+/// Keys are only ever derived by clients. The server stores key epochs and wrapped keys, but not
+/// the keys themselves.
+#[derive(Clone, PartialEq, Eq)]
+pub struct ContentKey([u8; 32]);
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl ContentKey {
+    /// Creates a key from its bytes.
+    pub fn from_bytes(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+
+    /// Returns the bytes of the key.
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    /// Derives the key of the child named `name` with key epoch `epoch` from this key.
+    ///
+    /// Names are case-insensitive, so the key is derived from the canonical form of `name`. Bumping
+    /// the epoch of an entity gives it, and so its whole subtree, keys unrelated to the old ones.
+    pub fn derive_child(&self, name: &PathSegment, epoch: u32) -> ContentKey {
+        let mut hasher = blake3::Hasher::new_keyed(&self.0);
+        hasher.update(KEY_DERIVATION_CONTEXT);
+        hasher.update(&epoch.to_le_bytes());
+        hasher.update(name.canonicalize().as_str().as_bytes());
+
+        ContentKey(*hasher.finalize().as_bytes())
+    }
+
+    /// Derives the key of the entity at `path` from this key, as the key of the directory `path` is
+    /// relative to, given the key epochs of the entities along `path`.
+    pub fn derive_at(&self, path: &Path, epochs: &[u32]) -> FsResult<ContentKey> {
+        if path.len() != epochs.len() {
+            return Err(FsError::custom(anyhow::anyhow!(
+                "expected {} key epochs for {}, got {}",
+                path.len(),
+                path,
+                epochs.len()
+            )));
+        }
+
+        Ok(path
+            .iter()
+            .zip(epochs)
+            .fold(self.clone(), |key, (name, epoch)| {
+                key.derive_child(name, *epoch)
+            }))
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl Debug for ContentKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ContentKey(..)")
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_content_key_hierarchy() -> anyhow::Result<()> {
+        let root = ContentKey::from_bytes([7; 32]);
+
+        // The key of a directory is enough to derive the keys beneath it.
+        let docs = root.derive_child(&"docs".parse()?, 0);
+        let notes = root.derive_at(&"docs/notes".parse()?, &[0, 0])?;
+        assert_eq!(docs.derive_child(&"notes".parse()?, 0), notes);
+
+        // Names are case-insensitive.
+        assert_eq!(root.derive_at(&"Docs/NOTES".parse()?, &[0, 0])?, notes);
+
+        // Rotating a directory changes the keys of its subtree only.
+        assert_ne!(root.derive_at(&"docs/notes".parse()?, &[1, 0])?, notes);
+        assert_ne!(root.derive_child(&"docs".parse()?, 1), docs);
+        assert_eq!(root.derive_child(&"docs".parse()?, 0), docs);
+
+        assert!(root.derive_at(&"docs/notes".parse()?, &[0]).is_err());
+
+        Ok(())
+    }
+}
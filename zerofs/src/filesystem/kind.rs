@@ -10,7 +10,7 @@ use serde::{Deserialize, Serialize};
 ///
 /// This corresponds to `descriptor-type` in the WASI. `zerofs` does not support all the types that WASI
 /// supports.
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum EntityType {
     /// The entity is a regular file.
     File,
@@ -0,0 +1,162 @@
+use zeroutils_store::{ipld::cid::Cid, IpldStore, Storable};
+
+use crate::config::ZerofsLimitsConfig;
+
+use super::{Dir, Entity, EntityType, FsError, FsResult, Path};
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Fails if linking the entity stored at `cid` at `path` under `root` would take the tree past
+/// `limits`.
+///
+/// Only what the link changes is checked: the segments of `path`, the directory along it that would
+/// get a new entry, if any, and, if the entity is a directory, everything under it. Trees that were
+/// already past the limits before they were configured keep working.
+///
+/// Fails with [`FsError::PathTooDeep`], [`FsError::NameTooLong`] or [`FsError::TooManyEntries`],
+/// naming the path that is past the limit.
+pub async fn check_limits<S>(
+    root: &Dir<S>,
+    path: &Path,
+    cid: &Cid,
+    limits: &ZerofsLimitsConfig,
+) -> FsResult<()>
+where
+    S: IpldStore + Send + Sync,
+{
+    let mut prefix = Path::default();
+    for segment in path.iter() {
+        prefix.push(segment.clone());
+        check_entry(&prefix, limits)?;
+    }
+
+    // Only the first missing segment adds an entry to an existing directory. The directories
+    // created for the rest of the path have a single entry each.
+    let mut dir = root.clone();
+    let mut dir_path = Path::default();
+    for segment in path.iter() {
+        match dir.get_entity(segment).await? {
+            Some(Entity::Dir(child)) => {
+                let child = child.clone();
+                dir_path.push(segment.clone());
+                dir = child;
+            }
+            Some(_) => break,
+            None => {
+                if dir.len() >= limits.max_dir_entries {
+                    return Err(FsError::TooManyEntries(dir_path, limits.max_dir_entries));
+                }
+
+                break;
+            }
+        }
+    }
+
+    let Entity::Dir(dir) = Entity::load(cid, root.get_store().clone()).await? else {
+        return Ok(());
+    };
+
+    let mut pending = vec![(dir, path.clone())];
+    while let Some((dir, dir_path)) = pending.pop() {
+        if dir.len() > limits.max_dir_entries {
+            return Err(FsError::TooManyEntries(dir_path, limits.max_dir_entries));
+        }
+
+        for (name, _) in dir.get_entries() {
+            let mut entry_path = dir_path.clone();
+            entry_path.push(name.clone());
+            check_entry(&entry_path, limits)?;
+
+            if dir.get_entity_type(name).await? != Some(EntityType::Dir) {
+                continue;
+            }
+
+            if let Some(Entity::Dir(child)) = dir.get_entity(name).await? {
+                pending.push((child.clone(), entry_path));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Fails if the depth of `path` or the length of its last segment is past `limits`.
+fn check_entry(path: &Path, limits: &ZerofsLimitsConfig) -> FsResult<()> {
+    if path.len() > limits.max_path_depth {
+        return Err(FsError::PathTooDeep(path.clone(), limits.max_path_depth));
+    }
+
+    if path
+        .last()
+        .is_some_and(|name| name.as_str().len() > limits.max_name_length)
+    {
+        return Err(FsError::NameTooLong(path.clone(), limits.max_name_length));
+    }
+
+    Ok(())
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use zeroutils_store::MemoryStore;
+
+    use crate::filesystem::{Chunker, File};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_check_limits() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let limits = ZerofsLimitsConfig::builder()
+            .max_dir_entries(2)
+            .max_path_depth(3)
+            .max_name_length(8)
+            .build();
+
+        let mut file = File::new(store.clone());
+        file.put_content(&b"data"[..], &Chunker::Store).await?;
+        let file = file.store().await?;
+
+        let root = Dir::new(store.clone())
+            .link_at(&"a".parse()?, file)
+            .await?
+            .link_at(&"b".parse()?, file)
+            .await?;
+
+        // Replacing an entry of a full directory does not add one.
+        check_limits(&root, &"a".parse()?, &file, &limits).await?;
+        assert!(matches!(
+            check_limits(&root, &"c/d".parse()?, &file, &limits).await,
+            Err(FsError::TooManyEntries(path, 2)) if path.is_empty()
+        ));
+
+        assert!(matches!(
+            check_limits(&root, &"a/b/c/d".parse()?, &file, &limits).await,
+            Err(FsError::PathTooDeep(_, 3))
+        ));
+        assert!(matches!(
+            check_limits(&root, &"a/verylongname".parse()?, &file, &limits).await,
+            Err(FsError::NameTooLong(_, 8))
+        ));
+
+        // The entries of a linked directory are checked too.
+        let dir = Dir::new(store.clone())
+            .link_at(&"x/y".parse()?, file)
+            .await?
+            .store()
+            .await?;
+        check_limits(&root, &"a".parse()?, &dir, &limits).await?;
+        assert!(matches!(
+            check_limits(&root, &"a/b".parse()?, &dir, &limits).await,
+            Err(FsError::PathTooDeep(path, 3)) if path.to_string() == "/a/b/x/y"
+        ));
+
+        Ok(())
+    }
+}
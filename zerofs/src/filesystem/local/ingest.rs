@@ -0,0 +1,373 @@
+use std::{
+    convert::TryFrom,
+    fmt::{self, Debug},
+    fs::Metadata,
+    path::{Component, Path as LocalPath, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use futures::{
+    future::{BoxFuture, FutureExt},
+    stream::{self, StreamExt, TryStreamExt},
+};
+use tokio::{fs, sync::Semaphore};
+use typed_builder::TypedBuilder;
+use zeroutils_store::{ipld::cid::Cid, IpldStore, Storable};
+
+use crate::filesystem::{
+    Chunker, Dir, EntityType, EntryHint, File, FsError, FsResult, Path, PathSegment,
+    ProgressCallback, Symlink,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The default number of local files read concurrently during an ingest.
+pub const DEFAULT_INGEST_CONCURRENCY: usize = 8;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Determines what happens to symbolic links found on the local file system.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SymlinkPolicy {
+    /// Symbolic links are left out.
+    #[default]
+    Skip,
+
+    /// Symbolic links are stored as [`Symlink`]s with the same target path.
+    ///
+    /// Targets must be relative paths made up of valid path segments.
+    Preserve,
+
+    /// Symbolic links are followed and whatever they point to is used in their place.
+    Follow,
+}
+
+/// The progress of an ingest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct IngestProgress {
+    /// The number of files stored so far.
+    pub files_done: u64,
+
+    /// The number of bytes stored so far.
+    pub bytes_done: u64,
+}
+
+/// Options for ingesting a local file or directory.
+#[derive(Clone, TypedBuilder)]
+pub struct IngestOptions {
+    /// The maximum number of local files and directories read at the same time, which is also how
+    /// many entries of a directory are ingested at once.
+    #[builder(default = DEFAULT_INGEST_CONCURRENCY)]
+    pub concurrency: usize,
+
+    /// What to do with symbolic links.
+    #[builder(default)]
+    pub symlinks: SymlinkPolicy,
+
+    /// How file contents are split into blocks. `None` uses the default of the file system, which
+    /// is [`Chunker::Store`] outside of a service.
+    #[builder(default, setter(strip_option))]
+    pub chunker: Option<Chunker>,
+
+    /// Called after each file is stored.
+    #[builder(default, setter(strip_option))]
+    pub on_progress: Option<ProgressCallback<IngestProgress>>,
+}
+
+/// Shared state of a running ingest.
+struct Ingester<S>
+where
+    S: IpldStore,
+{
+    store: S,
+    options: IngestOptions,
+    permits: Semaphore,
+    files_done: AtomicU64,
+    bytes_done: AtomicU64,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl<S> Ingester<S>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    fn new(store: S, options: IngestOptions) -> Self {
+        Self {
+            store,
+            permits: Semaphore::new(options.concurrency.max(1)),
+            options,
+            files_done: AtomicU64::new(0),
+            bytes_done: AtomicU64::new(0),
+        }
+    }
+
+    /// Stores the local entry at `local` and returns its [`Cid`] and a hint about it, or `None` if
+    /// the entry is left out.
+    ///
+    /// `ancestors` holds the canonical paths of the local directories being ingested along the way,
+    /// which is used to detect loops when following symbolic links.
+    fn ingest_entry(
+        &self,
+        local: PathBuf,
+        ancestors: Vec<PathBuf>,
+    ) -> BoxFuture<'_, FsResult<Option<(Cid, EntryHint)>>> {
+        async move {
+            let mut metadata = fs::symlink_metadata(&local).await?;
+            if metadata.file_type().is_symlink() {
+                match self.options.symlinks {
+                    SymlinkPolicy::Skip => return Ok(None),
+                    SymlinkPolicy::Preserve => {
+                        let target = local_to_path(&fs::read_link(&local).await?)?;
+                        let symlink = Symlink::new(self.store.clone(), target);
+                        let hint = EntryHint {
+                            entity_type: EntityType::Symlink,
+                            size: None,
+                        };
+
+                        return Ok(Some((symlink.store().await?, hint)));
+                    }
+                    SymlinkPolicy::Follow => metadata = fs::metadata(&local).await?,
+                }
+            }
+
+            if metadata.is_dir() {
+                let dir = self.ingest_dir(local, ancestors).await?;
+                let hint = EntryHint {
+                    entity_type: EntityType::Dir,
+                    size: None,
+                };
+
+                return Ok(Some((dir.store().await?, hint)));
+            }
+
+            if metadata.is_file() {
+                let hint = EntryHint {
+                    entity_type: EntityType::File,
+                    size: Some(metadata.len()),
+                };
+
+                return Ok(Some((self.ingest_file(&local, &metadata).await?, hint)));
+            }
+
+            // Sockets, fifos, devices and the like have no zerofs counterpart.
+            Ok(None)
+        }
+        .boxed()
+    }
+
+    /// Stores the entries of the local directory at `local` and returns the resulting directory.
+    async fn ingest_dir(&self, local: PathBuf, mut ancestors: Vec<PathBuf>) -> FsResult<Dir<S>> {
+        let canonical = fs::canonicalize(&local).await?;
+        if ancestors.contains(&canonical) {
+            return Err(FsError::LocalSymlinkLoop(local));
+        }
+
+        ancestors.push(canonical);
+
+        // The directory is read under a permit like a file, so no more local directories than
+        // files are open at the same time. The permit is released before the entries are ingested.
+        let mut names = vec![];
+        let mut paths = vec![];
+        {
+            let _permit = self.permits.acquire().await.map_err(FsError::custom)?;
+            let mut entries = fs::read_dir(&local).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                let name = entry.file_name();
+                let name = name.to_str().ok_or_else(|| {
+                    FsError::InvalidPathSegment(name.to_string_lossy().into_owned())
+                })?;
+
+                names.push(PathSegment::try_from(name)?);
+                paths.push(entry.path());
+            }
+        }
+
+        let children = stream::iter(paths)
+            .map(|path| self.ingest_entry(path, ancestors.clone()))
+            .buffered(self.options.concurrency.max(1))
+            .try_collect::<Vec<_>>()
+            .await?;
+
+        let mut dir = Dir::new(self.store.clone());
+        for (name, child) in names.into_iter().zip(children) {
+            if let Some((cid, hint)) = child {
+                dir.put_with_hint(name, cid, hint)?;
+            }
+        }
+
+        Ok(dir)
+    }
+
+    /// Stores the content of the local file at `local` and returns the [`Cid`] of the file.
+    ///
+    /// The file keeps the modification time of the local file.
+    async fn ingest_file(&self, local: &LocalPath, metadata: &Metadata) -> FsResult<Cid> {
+        let _permit = self.permits.acquire().await.map_err(FsError::custom)?;
+
+        let len = metadata.len();
+        let mut file = File::new(self.store.clone());
+        file.set_modified_at(metadata.modified()?.into());
+        if len > 0 {
+            let chunker = self.options.chunker.unwrap_or_default();
+            file.put_content(fs::File::open(local).await?, &chunker)
+                .await?;
+        }
+
+        let cid = file.store().await?;
+
+        let progress = IngestProgress {
+            files_done: self.files_done.fetch_add(1, Ordering::SeqCst) + 1,
+            bytes_done: self.bytes_done.fetch_add(len, Ordering::SeqCst) + len,
+        };
+
+        if let Some(on_progress) = &self.options.on_progress {
+            on_progress(progress);
+        }
+
+        Ok(cid)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Walks the local file or directory at `local` and stores it in `store`, returning the [`Cid`] of
+/// the resulting entity.
+///
+/// File contents are chunked into the store as they are read. At most
+/// [`concurrency`][IngestOptions::concurrency] local files and directories are read at the same
+/// time, and each directory ingests at most as many of its entries at once.
+pub async fn ingest_local<S>(
+    store: S,
+    local: impl AsRef<LocalPath>,
+    options: IngestOptions,
+) -> FsResult<Cid>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    let ingester = Ingester::new(store, options);
+    ingester
+        .ingest_entry(local.as_ref().to_owned(), vec![])
+        .await?
+        .map(|(cid, _)| cid)
+        .ok_or_else(|| FsError::NotAFileOrDir(None))
+}
+
+/// Converts a relative local path to a [`Path`].
+pub(crate) fn local_to_path(local: &LocalPath) -> FsResult<Path> {
+    local
+        .components()
+        .map(|component| match component {
+            Component::Normal(segment) => segment
+                .to_str()
+                .ok_or_else(|| FsError::InvalidPathSegment(segment.to_string_lossy().into_owned()))
+                .and_then(PathSegment::try_from),
+            Component::CurDir => Ok(PathSegment::CurrentDir),
+            Component::ParentDir => Ok(PathSegment::ParentDir),
+            Component::RootDir | Component::Prefix(_) => Err(FsError::InvalidPathSegment(
+                component.as_os_str().to_string_lossy().into_owned(),
+            )),
+        })
+        .collect()
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl Default for IngestOptions {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+impl Debug for IngestOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IngestOptions")
+            .field("concurrency", &self.concurrency)
+            .field("symlinks", &self.symlinks)
+            .field("chunker", &self.chunker)
+            .finish()
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use zeroutils_store::MemoryStore;
+
+    use crate::filesystem::Entity;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_ingest_local_dir() -> anyhow::Result<()> {
+        let local = std::env::temp_dir().join(format!("zerofs{}", rand::random::<u64>()));
+        fs::create_dir_all(local.join("photos")).await?;
+        fs::write(local.join("readme"), b"hello").await?;
+        fs::write(local.join("photos").join("cat"), b"meow meow").await?;
+        fs::write(local.join("photos").join("empty"), b"").await?;
+
+        let updates = Arc::new(Mutex::new(vec![]));
+        let options = IngestOptions::builder()
+            .on_progress({
+                let updates = Arc::clone(&updates);
+                Arc::new(move |progress| updates.lock().unwrap().push(progress))
+            })
+            .build();
+
+        let store = MemoryStore::default();
+        let cid = ingest_local(store.clone(), &local, options).await?;
+
+        // Nested directories are ingested one entry at a time with a single permit.
+        let options = IngestOptions::builder().concurrency(1).build();
+        let again = ingest_local(store.clone(), &local, options).await?;
+        let again = Dir::load(&again, store.clone()).await?;
+        assert_eq!(again.get_entries().count(), 2);
+        fs::remove_dir_all(&local).await?;
+
+        let dir = Dir::load(&cid, store.clone()).await?;
+        assert!(matches!(
+            dir.get_entity(&"readme".parse()?).await?,
+            Some(Entity::File(_))
+        ));
+
+        let photos = match dir.get_entity(&"photos".parse()?).await? {
+            Some(Entity::Dir(photos)) => photos.clone(),
+            _ => panic!("expected a directory"),
+        };
+
+        assert!(photos.get(&"cat".parse()?).is_some());
+        assert!(photos.get(&"empty".parse()?).is_some());
+
+        let last = *updates.lock().unwrap().last().unwrap();
+        assert_eq!(last.files_done, 3);
+        assert_eq!(last.bytes_done, 14);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_local_to_path() -> anyhow::Result<()> {
+        assert_eq!(
+            local_to_path(LocalPath::new("../photos/cat"))?,
+            Path::try_from_iter(vec!["..", "photos", "cat"])?
+        );
+        assert!(local_to_path(LocalPath::new("/etc/passwd")).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,321 @@
+use std::{
+    fmt::{self, Debug},
+    path::{Path as LocalPath, PathBuf},
+    time::SystemTime,
+};
+
+use futures::future::{BoxFuture, FutureExt};
+use tokio::{fs, io};
+use typed_builder::TypedBuilder;
+use zeroutils_store::IpldStore;
+
+use crate::filesystem::{Entity, FsError, FsResult, Path, PathSegment, ProgressCallback};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Determines what happens when something already exists where an entity is being materialized.
+///
+/// Existing local directories are never a collision for directories being materialized; their
+/// contents are merged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CollisionPolicy {
+    /// The existing local entry is removed and replaced.
+    Overwrite,
+
+    /// The existing local entry is kept and the entity is left out.
+    Skip,
+
+    /// Materializing fails with [`FsError::LocalEntryExists`].
+    #[default]
+    Fail,
+}
+
+/// Options for materializing an entity on the local file system.
+#[derive(Clone, TypedBuilder)]
+pub struct MaterializeOptions {
+    /// What to do when something already exists at a local path.
+    #[builder(default)]
+    pub collisions: CollisionPolicy,
+
+    /// Whether to recreate symlinks. If `false`, symlinks are skipped.
+    #[builder(default)]
+    pub symlinks: bool,
+
+    /// Whether to only report what would be written without touching the local file system.
+    #[builder(default)]
+    pub dry_run: bool,
+
+    /// Called with the local path of each entry after it is written.
+    #[builder(default, setter(strip_option))]
+    pub on_progress: Option<ProgressCallback<PathBuf>>,
+}
+
+/// What materializing an entity did, or would do in a dry run.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct MaterializeReport {
+    /// Local paths of the files, directories and symlinks written.
+    pub written: Vec<PathBuf>,
+
+    /// Local paths left untouched because of a collision or an unsupported symlink.
+    pub skipped: Vec<PathBuf>,
+}
+
+struct Materializer {
+    options: MaterializeOptions,
+    report: MaterializeReport,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl Materializer {
+    /// Writes `entity` to `local`.
+    fn materialize_entity<'a, S>(
+        &'a mut self,
+        entity: Entity<S>,
+        local: PathBuf,
+    ) -> BoxFuture<'a, FsResult<()>>
+    where
+        S: IpldStore + Send + Sync + 'static,
+    {
+        async move {
+            if !self.resolve_collision(&entity, &local).await? {
+                self.report.skipped.push(local);
+                return Ok(());
+            }
+
+            match entity {
+                Entity::File(file) => {
+                    if !self.options.dry_run {
+                        let mut output = fs::File::create(&local).await?;
+                        io::copy(&mut file.get_content_reader().await?, &mut output).await?;
+
+                        set_modified(&local, file.get_metadata().modified_at.into()).await?;
+                    }
+                }
+                Entity::Dir(dir) => {
+                    if !self.options.dry_run {
+                        fs::create_dir_all(&local).await?;
+                    }
+
+                    for (name, _) in dir.get_entries() {
+                        let child = match dir.get_entity(name).await? {
+                            Some(child) => child.clone(),
+                            None => continue,
+                        };
+
+                        self.materialize_entity(child, local_child(&local, name)?)
+                            .await?;
+                    }
+
+                    if !self.options.dry_run {
+                        set_modified(&local, dir.get_metadata().modified_at.into()).await?;
+                    }
+                }
+                Entity::Symlink(symlink) => {
+                    if !self.options.symlinks || !cfg!(unix) {
+                        self.report.skipped.push(local);
+                        return Ok(());
+                    }
+
+                    #[cfg(unix)]
+                    if !self.options.dry_run {
+                        fs::symlink(path_to_local(symlink.get_path()), &local).await?;
+                    }
+                }
+            }
+
+            if let Some(on_progress) = &self.options.on_progress {
+                on_progress(local.clone());
+            }
+
+            self.report.written.push(local);
+
+            Ok(())
+        }
+        .boxed()
+    }
+
+    /// Applies the collision policy to the local entry at `local`, returning `false` if the entity
+    /// should be skipped.
+    async fn resolve_collision<S>(&self, entity: &Entity<S>, local: &LocalPath) -> FsResult<bool>
+    where
+        S: IpldStore,
+    {
+        let metadata = match fs::symlink_metadata(local).await {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(true),
+            Err(e) => return Err(e.into()),
+        };
+
+        if entity.is_dir() && metadata.is_dir() {
+            return Ok(true);
+        }
+
+        match self.options.collisions {
+            CollisionPolicy::Fail => Err(FsError::LocalEntryExists(local.to_owned())),
+            CollisionPolicy::Skip => Ok(false),
+            CollisionPolicy::Overwrite => {
+                if !self.options.dry_run {
+                    if metadata.is_dir() {
+                        fs::remove_dir_all(local).await?;
+                    } else {
+                        fs::remove_file(local).await?;
+                    }
+                }
+
+                Ok(true)
+            }
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Writes `entity` and everything under it to `local` on the local file system, restoring file
+/// contents, directory structure and modification times.
+pub async fn materialize_local<S>(
+    entity: Entity<S>,
+    local: impl AsRef<LocalPath>,
+    options: MaterializeOptions,
+) -> FsResult<MaterializeReport>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    let mut materializer = Materializer {
+        options,
+        report: MaterializeReport::default(),
+    };
+
+    materializer
+        .materialize_entity(entity, local.as_ref().to_owned())
+        .await?;
+
+    Ok(materializer.report)
+}
+
+/// Converts a [`Path`] to a relative local path.
+pub(crate) fn path_to_local(path: &Path) -> PathBuf {
+    path.iter().map(|segment| segment.as_str()).collect()
+}
+
+/// Returns the local path of the entry `name` in the local directory `local`.
+///
+/// Entry names come from the DAG, which may not be trusted. `.`, `..` and names with a path
+/// separator are refused, so that an entry can never be written outside of `local`.
+pub(crate) fn local_child(local: &LocalPath, name: &PathSegment) -> FsResult<PathBuf> {
+    match name {
+        PathSegment::Named(name) if !name.is_empty() && !name.contains(std::path::is_separator) => {
+            Ok(local.join(name))
+        }
+        _ => Err(FsError::InvalidPathSegment(name.as_str().to_owned())),
+    }
+}
+
+/// Sets the modification time of the local file or directory at `local`.
+async fn set_modified(local: &LocalPath, modified: SystemTime) -> FsResult<()> {
+    let file = fs::File::open(local).await?.into_std().await;
+    file.set_modified(modified)?;
+    Ok(())
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl Default for MaterializeOptions {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+impl Debug for MaterializeOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MaterializeOptions")
+            .field("collisions", &self.collisions)
+            .field("symlinks", &self.symlinks)
+            .field("dry_run", &self.dry_run)
+            .finish()
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use zeroutils_store::{MemoryStore, Storable};
+
+    use crate::filesystem::{ingest_local, Dir, File, IngestOptions};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_materialize_local_roundtrip() -> anyhow::Result<()> {
+        let source = std::env::temp_dir().join(format!("zerofs{}", rand::random::<u64>()));
+        let dest = std::env::temp_dir().join(format!("zerofs{}", rand::random::<u64>()));
+        fs::create_dir_all(source.join("photos")).await?;
+        fs::write(source.join("readme"), b"hello").await?;
+        fs::write(source.join("photos").join("cat"), b"meow meow").await?;
+
+        let store = MemoryStore::default();
+        let cid = ingest_local(store.clone(), &source, IngestOptions::default()).await?;
+        let entity = Entity::load(&cid, store).await?;
+
+        // A dry run does not touch the local file system.
+        let options = MaterializeOptions::builder().dry_run(true).build();
+        let report = materialize_local(entity.clone(), &dest, options).await?;
+        assert_eq!(report.written.len(), 4);
+        assert!(fs::metadata(&dest).await.is_err());
+
+        let report = materialize_local(entity.clone(), &dest, Default::default()).await?;
+        assert_eq!(report.written.len(), 4);
+        assert_eq!(fs::read(dest.join("readme")).await?, b"hello");
+        assert_eq!(
+            fs::read(dest.join("photos").join("cat")).await?,
+            b"meow meow"
+        );
+
+        // Existing files collide.
+        let result = materialize_local(entity.clone(), &dest, Default::default()).await;
+        assert!(matches!(result, Err(FsError::LocalEntryExists(_))));
+
+        let options = MaterializeOptions::builder()
+            .collisions(CollisionPolicy::Skip)
+            .build();
+        let report = materialize_local(entity, &dest, options).await?;
+        assert_eq!(report.skipped.len(), 2);
+
+        fs::remove_dir_all(&source).await?;
+        fs::remove_dir_all(&dest).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_materialize_local_refuses_parent_dir_entries() -> anyhow::Result<()> {
+        let base = std::env::temp_dir().join(format!("zerofs{}", rand::random::<u64>()));
+        let dest = base.join("dest");
+
+        // A crafted DAG whose `..` entry would put `evil` next to `dest`.
+        let store = MemoryStore::default();
+        let mut parent = Dir::new(store.clone());
+        parent.put("evil", File::new(store.clone()).store().await?)?;
+        let mut dir = Dir::new(store.clone());
+        dir.put(PathSegment::ParentDir, parent.store().await?)?;
+
+        let result = materialize_local(Entity::Dir(dir), &dest, Default::default()).await;
+        assert!(matches!(result, Err(FsError::InvalidPathSegment(_))));
+        assert!(!fs::try_exists(base.join("evil")).await?);
+
+        fs::remove_dir_all(&base).await?;
+
+        Ok(())
+    }
+}
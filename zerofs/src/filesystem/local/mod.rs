@@ -0,0 +1,11 @@
+mod ingest;
+mod materialize;
+mod sync;
+
+//--------------------------------------------------------------------------------------------------
+// Exports
+//--------------------------------------------------------------------------------------------------
+
+pub use ingest::*;
+pub use materialize::*;
+pub use sync::*;
@@ -0,0 +1,453 @@
+use std::{
+    collections::HashSet,
+    convert::TryFrom,
+    fs::Metadata,
+    path::{Path as LocalPath, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use futures::future::{BoxFuture, FutureExt};
+use tokio::{
+    fs,
+    io::{self, AsyncBufReadExt, BufReader},
+};
+use zeroutils_store::{IpldStore, Storable};
+
+use crate::filesystem::{
+    ingest_local, local_child, materialize_local, CollisionPolicy, Dir, Entity, EntityType,
+    EntryHint, File, FsError, FsResult, IngestOptions, MaterializeOptions, Path, PathSegment,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The direction in which differences are applied during a sync.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncDirection {
+    /// The local directory is copied into the `zerofs` directory.
+    Push,
+
+    /// The `zerofs` directory is copied into the local directory.
+    Pull,
+}
+
+/// What a sync changed.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SyncReport {
+    /// Local paths of the entries that were added or updated on the receiving side.
+    pub updated: Vec<PathBuf>,
+
+    /// Local paths of the entries that were deleted from the receiving side.
+    pub deleted: Vec<PathBuf>,
+}
+
+struct Syncer {
+    delete_extraneous: bool,
+    options: IngestOptions,
+    report: SyncReport,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl Syncer {
+    /// Applies the differences between the local directory at `local` and `dir`, which is at
+    /// `dir_path` under the synced directory, to `dir`.
+    ///
+    /// Fails without replacing or deleting anything protected by its attributes.
+    fn push_dir<'a, S>(
+        &'a mut self,
+        mut dir: Dir<S>,
+        dir_path: Path,
+        local: PathBuf,
+    ) -> BoxFuture<'a, FsResult<Dir<S>>>
+    where
+        S: IpldStore + Send + Sync + 'static,
+    {
+        async move {
+            let store = dir.get_store().clone();
+            let mut seen = HashSet::new();
+            let mut entries = fs::read_dir(&local).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                let path = entry.path();
+                let name = entry.file_name();
+                let name = name.to_str().ok_or_else(|| {
+                    FsError::InvalidPathSegment(name.to_string_lossy().into_owned())
+                })?;
+                let name = PathSegment::try_from(name)?;
+                let mut entry_path = dir_path.clone();
+                entry_path.push(name.clone());
+
+                let metadata = entry.metadata().await?;
+                let current = dir.get_entity(&name).await?.cloned();
+                seen.insert(name.clone());
+
+                if metadata.is_dir() {
+                    let cid = match current {
+                        Some(Entity::Dir(current)) => {
+                            self.push_dir(current, entry_path, path)
+                                .await?
+                                .store()
+                                .await?
+                        }
+                        current => {
+                            if let Some(current) = current {
+                                current.check_replaceable(&entry_path).await?;
+                            }
+
+                            let cid =
+                                ingest_local(store.clone(), &path, self.options.clone()).await?;
+                            self.report.updated.push(path);
+                            cid
+                        }
+                    };
+
+                    let hint = EntryHint {
+                        entity_type: EntityType::Dir,
+                        size: None,
+                    };
+
+                    dir.put_with_hint(name, cid, hint)?;
+                } else if metadata.is_file() {
+                    if let Some(Entity::File(current)) = &current {
+                        let size = dir.get_hint(&name).and_then(|hint| hint.size);
+                        if is_unchanged(current, size, &path, &metadata).await? {
+                            continue;
+                        }
+                    }
+
+                    if let Some(current) = &current {
+                        current.check_replaceable(&entry_path).await?;
+                    }
+
+                    let cid = ingest_local(store.clone(), &path, self.options.clone()).await?;
+                    let hint = EntryHint {
+                        entity_type: EntityType::File,
+                        size: Some(metadata.len()),
+                    };
+
+                    dir.put_with_hint(name, cid, hint)?;
+                    self.report.updated.push(path);
+                }
+
+                // Symbolic links and special files are not synced.
+            }
+
+            if self.delete_extraneous {
+                let extraneous = dir
+                    .get_entries()
+                    .map(|(name, _)| name.clone())
+                    .filter(|name| !seen.contains(name))
+                    .collect::<Vec<_>>();
+
+                for name in extraneous {
+                    if let Some(entity) = dir.get_entity(&name).await? {
+                        let mut entry_path = dir_path.clone();
+                        entry_path.push(name.clone());
+                        entity.check_replaceable(&entry_path).await?;
+                    }
+
+                    dir.remove(&name);
+                    self.report.deleted.push(local.join(name.as_str()));
+                }
+            }
+
+            Ok(dir)
+        }
+        .boxed()
+    }
+
+    /// Applies the differences between `dir` and the local directory at `local` to `local`.
+    fn pull_dir<'a, S>(&'a mut self, dir: Dir<S>, local: PathBuf) -> BoxFuture<'a, FsResult<()>>
+    where
+        S: IpldStore + Send + Sync + 'static,
+    {
+        async move {
+            let mut seen = HashSet::new();
+            for (name, _) in dir.get_entries() {
+                let entity = match dir.get_entity(name).await? {
+                    Some(entity) => entity.clone(),
+                    None => continue,
+                };
+
+                let path = local_child(&local, name)?;
+                let size = dir.get_hint(name).and_then(|hint| hint.size);
+                seen.insert(name.clone());
+
+                let metadata = match fs::symlink_metadata(&path).await {
+                    Ok(metadata) => Some(metadata),
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+                    Err(e) => return Err(e.into()),
+                };
+
+                match (entity, metadata) {
+                    (Entity::Dir(child), Some(metadata)) if metadata.is_dir() => {
+                        self.pull_dir(child, path).await?;
+                    }
+                    (Entity::File(file), Some(metadata))
+                        if metadata.is_file()
+                            && is_unchanged(&file, size, &path, &metadata).await? => {}
+                    // Symbolic links are not synced.
+                    (Entity::Symlink(_), _) => {}
+                    (entity, _) => {
+                        let options = MaterializeOptions::builder()
+                            .collisions(CollisionPolicy::Overwrite)
+                            .build();
+
+                        let report = materialize_local(entity, &path, options).await?;
+                        self.report.updated.extend(report.written);
+                    }
+                }
+            }
+
+            if self.delete_extraneous {
+                let mut entries = fs::read_dir(&local).await?;
+                while let Some(entry) = entries.next_entry().await? {
+                    let known = entry
+                        .file_name()
+                        .to_str()
+                        .and_then(|name| PathSegment::try_from(name).ok())
+                        .is_some_and(|name| seen.contains(&name));
+
+                    if known {
+                        continue;
+                    }
+
+                    let path = entry.path();
+                    if entry.file_type().await?.is_dir() {
+                        fs::remove_dir_all(&path).await?;
+                    } else {
+                        fs::remove_file(&path).await?;
+                    }
+
+                    self.report.deleted.push(path);
+                }
+            }
+
+            Ok(())
+        }
+        .boxed()
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Compares the local directory at `local` with `dir` and applies only the differences in the
+/// given `direction`, returning the resulting `zerofs` directory.
+///
+/// Files are considered unchanged if their sizes and modification times match. Otherwise their
+/// contents are compared. If `delete_extraneous` is `true`, entries that only exist on the
+/// receiving side are deleted.
+///
+/// When pushing, the local files and directories that changed are ingested with `options`.
+/// Immutable and append-only files in `dir`, and directories holding them, are never replaced or
+/// deleted. The sync fails instead, naming the path of the file under `dir`.
+///
+/// Symbolic links are not synced.
+pub async fn sync_local<S>(
+    local: impl AsRef<LocalPath>,
+    dir: Dir<S>,
+    direction: SyncDirection,
+    delete_extraneous: bool,
+    options: IngestOptions,
+) -> FsResult<(Dir<S>, SyncReport)>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    let local = local.as_ref().to_owned();
+    let mut syncer = Syncer {
+        delete_extraneous,
+        options,
+        report: SyncReport::default(),
+    };
+
+    let dir = match direction {
+        SyncDirection::Push => syncer.push_dir(dir, Path::default(), local).await?,
+        SyncDirection::Pull => {
+            fs::create_dir_all(&local).await?;
+            syncer.pull_dir(dir.clone(), local).await?;
+            dir
+        }
+    };
+
+    Ok((dir, syncer.report))
+}
+
+/// Returns `true` if the local file at `local` has the same content as `file`.
+///
+/// Like the quick check of `rsync`, files of different sizes are changed and files of the same
+/// size and modification time are not. Otherwise the contents are compared. The `size` hinted by
+/// the parent directory is used when there is one, so the content is not read to measure it.
+async fn is_unchanged<S>(
+    file: &File<S>,
+    size: Option<u64>,
+    local: &LocalPath,
+    metadata: &Metadata,
+) -> FsResult<bool>
+where
+    S: IpldStore + Send + Sync,
+{
+    let size = match size {
+        Some(size) => size,
+        None => file.get_size().await?.len,
+    };
+
+    if size != metadata.len() {
+        return Ok(false);
+    }
+
+    if DateTime::<Utc>::from(metadata.modified()?) == file.get_metadata().modified_at {
+        return Ok(true);
+    }
+
+    let mut content = BufReader::new(file.get_content_reader().await?);
+    let mut local = BufReader::new(fs::File::open(local).await?);
+    loop {
+        let expected = content.fill_buf().await?;
+        let actual = local.fill_buf().await?;
+        if expected.is_empty() || actual.is_empty() {
+            return Ok(expected.is_empty() && actual.is_empty());
+        }
+
+        let len = expected.len().min(actual.len());
+        if expected[..len] != actual[..len] {
+            return Ok(false);
+        }
+
+        content.consume(len);
+        local.consume(len);
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    };
+
+    use zeroutils_store::MemoryStore;
+
+    use crate::filesystem::EntityAttributes;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_sync_local_push_and_pull() -> anyhow::Result<()> {
+        let source = std::env::temp_dir().join(format!("zerofs{}", rand::random::<u64>()));
+        let dest = std::env::temp_dir().join(format!("zerofs{}", rand::random::<u64>()));
+        fs::create_dir_all(source.join("photos")).await?;
+        fs::write(source.join("readme"), b"hello").await?;
+        fs::write(source.join("photos").join("cat"), b"meow meow").await?;
+
+        let store = MemoryStore::default();
+        let (dir, report) = sync_local(
+            &source,
+            Dir::new(store),
+            SyncDirection::Push,
+            false,
+            IngestOptions::default(),
+        )
+        .await?;
+        assert_eq!(report.updated.len(), 2);
+
+        // Nothing changed since the last push.
+        let (dir, report) = sync_local(
+            &source,
+            dir,
+            SyncDirection::Push,
+            false,
+            IngestOptions::default(),
+        )
+        .await?;
+        assert!(report.updated.is_empty());
+
+        // Only the changed file is ingested, with the given options.
+        fs::write(source.join("readme"), b"hello again").await?;
+        fs::remove_dir_all(source.join("photos")).await?;
+        let ingested = Arc::new(AtomicU64::new(0));
+        let options = IngestOptions::builder()
+            .on_progress({
+                let ingested = ingested.clone();
+                Arc::new(move |_| {
+                    ingested.fetch_add(1, Ordering::SeqCst);
+                })
+            })
+            .build();
+        let (dir, report) = sync_local(&source, dir, SyncDirection::Push, true, options).await?;
+        assert_eq!(report.updated, vec![source.join("readme")]);
+        assert_eq!(report.deleted, vec![source.join("photos")]);
+        assert_eq!(ingested.load(Ordering::SeqCst), 1);
+
+        fs::create_dir_all(&dest).await?;
+        fs::write(dest.join("stale"), b"stale").await?;
+        let (_, report) = sync_local(
+            &dest,
+            dir,
+            SyncDirection::Pull,
+            true,
+            IngestOptions::default(),
+        )
+        .await?;
+        assert_eq!(report.updated, vec![dest.join("readme")]);
+        assert_eq!(report.deleted, vec![dest.join("stale")]);
+        assert_eq!(fs::read(dest.join("readme")).await?, b"hello again");
+
+        fs::remove_dir_all(&source).await?;
+        fs::remove_dir_all(&dest).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_sync_local_push_keeps_protected_files() -> anyhow::Result<()> {
+        let source = std::env::temp_dir().join(format!("zerofs{}", rand::random::<u64>()));
+        fs::create_dir_all(source.join("logs")).await?;
+        fs::write(source.join("logs").join("app"), b"started").await?;
+
+        let store = MemoryStore::default();
+        let (dir, _) = sync_local(
+            &source,
+            Dir::new(store),
+            SyncDirection::Push,
+            false,
+            IngestOptions::default(),
+        )
+        .await?;
+        let dir = dir
+            .set_attributes_at(&"logs/app".parse()?, EntityAttributes::APPEND_ONLY)
+            .await?;
+
+        // Neither changing nor deleting the protected file goes through.
+        fs::write(source.join("logs").join("app"), b"rewritten").await?;
+        assert!(matches!(
+            sync_local(&source, dir.clone(), SyncDirection::Push, false, IngestOptions::default()).await,
+            Err(FsError::AppendOnly(path)) if path == "logs/app".parse::<Path>()?
+        ));
+
+        fs::remove_dir_all(source.join("logs")).await?;
+        assert!(matches!(
+            sync_local(
+                &source,
+                dir,
+                SyncDirection::Push,
+                true,
+                IngestOptions::default()
+            )
+            .await,
+            Err(FsError::AppendOnly(_))
+        ));
+
+        fs::remove_dir_all(&source).await?;
+
+        Ok(())
+    }
+}
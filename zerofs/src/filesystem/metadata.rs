@@ -1,7 +1,7 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
-use super::EntityType;
+use super::{current_time, Acl, EntityAttributes, EntityType, KeyGrants};
 
 //--------------------------------------------------------------------------------------------------
 // Types
@@ -11,7 +11,7 @@ use super::EntityType;
 ///
 /// This mostly corresponds to the `descriptor-stat` structure in the WASI. `zerofs` does not support
 /// hard links, so there is no `link-count` field. Also `size` is not stored here, but rather
-/// requested when needed.
+/// requested when needed. See [`FileStat`][super::FileStat] for the full structure.
 ///
 // TODO: Need to to know precisely what the DateTimes serialize to.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -24,6 +24,33 @@ pub struct Metadata {
 
     /// The time of the last modification of the entity.
     pub modified_at: DateTime<Utc>,
+
+    /// The time the entity was last accessed, if it was ever set. Reads do not update it; only
+    /// tools that restore it, like archive extractors, do.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub accessed_at: Option<DateTime<Utc>>,
+
+    /// The time of the last change to the status of the entity, like its access control list,
+    /// attributes or times, if there was one. See [`get_changed_at`][Self::get_changed_at].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub changed_at: Option<DateTime<Utc>>,
+
+    /// The access control list of the entity.
+    #[serde(default, skip_serializing_if = "Acl::is_empty")]
+    pub acl: Acl,
+
+    /// The attributes protecting the entity from changes.
+    #[serde(default, skip_serializing_if = "EntityAttributes::is_empty")]
+    pub attributes: EntityAttributes,
+
+    /// The content key of the entity, wrapped to each of the DIDs it is shared with.
+    #[serde(default, skip_serializing_if = "KeyGrants::is_empty")]
+    pub grants: KeyGrants,
+
+    /// The number of times the content key of the entity was rotated. It goes into the derivation
+    /// of the key, see [`ContentKey::derive_child`][super::ContentKey::derive_child].
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub key_epoch: u32,
 }
 
 //--------------------------------------------------------------------------------------------------
@@ -31,14 +58,65 @@ pub struct Metadata {
 //--------------------------------------------------------------------------------------------------
 
 impl Metadata {
-    /// Creates a new metadata object.
+    /// Creates a new metadata object timestamped with the [`current_time`].
     pub fn new(entity_type: EntityType) -> Self {
-        let now = Utc::now();
+        Self::new_at(entity_type, current_time())
+    }
 
+    /// Creates a new metadata object timestamped with `now`.
+    pub fn new_at(entity_type: EntityType, now: DateTime<Utc>) -> Self {
         Self {
             entity_type,
             created_at: now,
             modified_at: now,
+            accessed_at: None,
+            changed_at: None,
+            acl: Acl::new(),
+            attributes: EntityAttributes::empty(),
+            grants: KeyGrants::new(),
+            key_epoch: 0,
+        }
+    }
+
+    /// Sets the access time and the modification time to the ones given, leaving the others as
+    /// they are.
+    pub fn set_times(
+        &mut self,
+        accessed_at: Option<DateTime<Utc>>,
+        modified_at: Option<DateTime<Utc>>,
+    ) {
+        if accessed_at.is_some() {
+            self.accessed_at = accessed_at;
+        }
+
+        if let Some(modified_at) = modified_at {
+            self.modified_at = modified_at;
         }
+
+        self.mark_changed();
+    }
+
+    /// Returns the time of the last change to the entity, of its status or of its content,
+    /// like `ctime` in POSIX.
+    ///
+    /// Modifying the content also changes the entity, so this is never before the
+    /// [modification time][Self::modified_at], even if that was set back explicitly.
+    pub fn get_changed_at(&self) -> DateTime<Utc> {
+        self.changed_at.map_or(self.modified_at, |changed_at| {
+            changed_at.max(self.modified_at)
+        })
+    }
+
+    /// Records a change to the status of the entity at the [`current_time`].
+    pub fn mark_changed(&mut self) {
+        self.changed_at = Some(current_time());
     }
 }
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+fn is_zero(value: &u32) -> bool {
+    *value == 0
+}
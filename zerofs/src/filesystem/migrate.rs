@@ -0,0 +1,340 @@
+use std::{io::Cursor, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncReadExt;
+use typed_builder::TypedBuilder;
+use zeroutils_store::{ipld::cid::Cid, IpldReferences, IpldStore, Storable};
+
+use super::{ContentChunks, Entity, FsError, FsResult, Path, Subscription};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// A callback that gets called with the current progress of a long-running operation.
+pub type ProgressCallback<P> = Arc<dyn Fn(P) + Send + Sync>;
+
+/// Options for [`migrate_store`].
+#[derive(Clone, Default, TypedBuilder)]
+pub struct MigrateOptions {
+    /// Called after each block is copied or skipped.
+    #[builder(default, setter(strip_option))]
+    pub on_progress: Option<ProgressCallback<MigrateStats>>,
+
+    /// Only copies the blocks of the subscribed subtrees, along with the directories on the way to
+    /// them. `None` copies everything.
+    #[builder(default, setter(strip_option))]
+    pub subscription: Option<Subscription>,
+}
+
+/// The progress of a migration, and its outcome once it is done.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct MigrateStats {
+    /// The number of blocks copied to the destination.
+    pub blocks_copied: u64,
+
+    /// The number of blocks found in the destination already, along with everything under them.
+    pub blocks_skipped: u64,
+
+    /// The size in bytes of the content copied.
+    pub bytes_copied: u64,
+}
+
+/// A step of the walk over the blocks to migrate.
+enum Step<S>
+where
+    S: IpldStore,
+{
+    /// Visits the entity at the CID and path, scheduling the blocks under it before the entity
+    /// itself.
+    Visit(Cid, Path),
+
+    /// Copies the node of an entity whose children have been copied.
+    CopyEntity(Cid, Entity<S>),
+
+    /// Copies the content of a file.
+    CopyContent { cid: Cid, chunked: bool },
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Copies every block reachable from `roots` from `src` to `dst`, so a file system can move to
+/// another store, like from a [`MemoryStore`][zeroutils_store::MemoryStore] to a
+/// [`DiskStore`][super::DiskStore], while `src` keeps serving it.
+///
+/// The roots are the CIDs of entities, usually the current root along with the roots of its
+/// snapshots, which are not reachable from it.
+///
+/// Every block is written to `dst` and the CID `dst` gives it must be the one it has in `src`, so a
+/// migration also verifies that `dst` hashes and encodes blocks the same way. A mismatch fails the
+/// migration with [`FsError::MigrationMismatch`].
+///
+/// Blocks are copied after every block they reference, so a block found in `dst` has its whole
+/// subtree there as well and is skipped along with it. A migration that was interrupted picks up
+/// where it left off when run again, and running one again after `src` changed only copies what is
+/// new.
+///
+/// With a [`Subscription`], only the subscribed subtrees are copied, which is how a node fetches
+/// the parts of the tree it replicates. The roots are taken to be roots of the file tree. A
+/// directory copied on the way to a subscribed subtree does not have its whole subtree in `dst`, so
+/// the walk goes through blocks found in `dst` instead of skipping them, and a subscription can
+/// grow between runs.
+pub async fn migrate_store<S, T>(
+    src: S,
+    dst: T,
+    roots: &[Cid],
+    options: MigrateOptions,
+) -> FsResult<MigrateStats>
+where
+    S: IpldStore + Send + Sync,
+    T: IpldStore + Send + Sync,
+{
+    let mut stats = MigrateStats::default();
+    let mut pending = roots
+        .iter()
+        .rev()
+        .map(|cid| Step::Visit(*cid, Path::default()))
+        .collect::<Vec<_>>();
+    while let Some(step) = pending.pop() {
+        let copied = match step {
+            Step::Visit(cid, _) if options.subscription.is_none() && dst.has(&cid).await => {
+                stats.blocks_skipped += 1;
+                report(&options, stats);
+                continue;
+            }
+            Step::Visit(cid, path) => {
+                let entity = Entity::load(&cid, src.clone()).await?;
+                let children = match &entity {
+                    Entity::Dir(dir) => dir
+                        .get_entries()
+                        .filter_map(|(name, link)| {
+                            let mut child_path = path.clone();
+                            child_path.push(name.clone());
+                            is_replicated(&options, &child_path)
+                                .then(|| Step::Visit(*link.get_cid(), child_path))
+                        })
+                        .collect(),
+                    Entity::File(file) if is_covered(&options, &path) => file
+                        .get_content()
+                        .map(|content| Step::CopyContent {
+                            cid: *content,
+                            chunked: file.is_chunked(),
+                        })
+                        .into_iter()
+                        .collect(),
+                    Entity::File(_) | Entity::Symlink(_) => vec![],
+                };
+
+                pending.push(Step::CopyEntity(cid, entity));
+                pending.extend(children);
+                continue;
+            }
+            Step::CopyEntity(cid, _) if dst.has(&cid).await => {
+                stats.blocks_skipped += 1;
+                report(&options, stats);
+                continue;
+            }
+            Step::CopyEntity(cid, entity) => {
+                let copied = match &entity {
+                    Entity::Dir(dir) => dst.put_node(dir).await?,
+                    Entity::File(file) => dst.put_node(file).await?,
+                    Entity::Symlink(symlink) => dst.put_node(symlink).await?,
+                };
+
+                check_cid(cid, copied)?
+            }
+            Step::CopyContent { cid, .. } if dst.has(&cid).await => {
+                stats.blocks_skipped += 1;
+                report(&options, stats);
+                continue;
+            }
+            Step::CopyContent { cid, chunked: true } => {
+                let chunks: ContentChunks = src.get_node(&cid).await?;
+                for chunk in chunks.references() {
+                    if dst.has(chunk).await {
+                        stats.blocks_skipped += 1;
+                    } else {
+                        stats.bytes_copied += copy_bytes(&src, &dst, chunk).await?;
+                        stats.blocks_copied += 1;
+                    }
+
+                    report(&options, stats);
+                }
+
+                check_cid(cid, dst.put_node(&chunks).await?)?
+            }
+            Step::CopyContent {
+                cid,
+                chunked: false,
+            } => {
+                stats.bytes_copied += copy_bytes(&src, &dst, &cid).await?;
+                1
+            }
+        };
+
+        stats.blocks_copied += copied;
+        report(&options, stats);
+    }
+
+    Ok(stats)
+}
+
+/// Copies content stored with [`IpldStore::put_bytes`] and returns its size.
+async fn copy_bytes<S, T>(src: &S, dst: &T, cid: &Cid) -> FsResult<u64>
+where
+    S: IpldStore + Send + Sync,
+    T: IpldStore + Send + Sync,
+{
+    let mut bytes = Vec::new();
+    src.get_bytes(cid).await?.read_to_end(&mut bytes).await?;
+    let size = bytes.len() as u64;
+
+    check_cid(*cid, dst.put_bytes(Cursor::new(bytes)).await?)?;
+
+    Ok(size)
+}
+
+/// Checks that a block got the same CID in the destination as in the source, and returns the
+/// number of blocks copied.
+fn check_cid(expected: Cid, actual: Cid) -> FsResult<u64> {
+    if expected != actual {
+        return Err(FsError::MigrationMismatch(expected));
+    }
+
+    Ok(1)
+}
+
+/// Returns `true` if the entity at `path` is copied.
+fn is_replicated(options: &MigrateOptions, path: &Path) -> bool {
+    options
+        .subscription
+        .as_ref()
+        .is_none_or(|subscription| subscription.includes(path))
+}
+
+/// Returns `true` if everything under the entity at `path` is copied.
+fn is_covered(options: &MigrateOptions, path: &Path) -> bool {
+    options
+        .subscription
+        .as_ref()
+        .is_none_or(|subscription| subscription.covers(path))
+}
+
+fn report(options: &MigrateOptions, stats: MigrateStats) {
+    if let Some(on_progress) = &options.on_progress {
+        on_progress(stats);
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use zeroutils_store::MemoryStore;
+
+    use crate::filesystem::{verify, Chunker, Dir, FastCdc, File};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_migrate_store_copies_reachable_blocks() -> anyhow::Result<()> {
+        let src = MemoryStore::default();
+        let data = (0..100_000u32)
+            .map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8)
+            .collect::<Vec<_>>();
+        let chunker = Chunker::FastCdc(
+            FastCdc::builder()
+                .min_size(1024)
+                .avg_size(4096)
+                .max_size(16 * 1024)
+                .build()?,
+        );
+
+        let mut chunked = File::new(src.clone());
+        chunked.put_content(&data[..], &chunker).await?;
+        let mut small = File::new(src.clone());
+        small.put_content(&b"hello"[..], &Chunker::Store).await?;
+
+        let root = Dir::new(src.clone())
+            .link_at(&"public/data".parse()?, chunked.store().await?)
+            .await?
+            .link_at(&"public/hello".parse()?, small.store().await?)
+            .await?;
+        let root_cid = root.store().await?;
+
+        let dst = MemoryStore::default();
+        let updates = Arc::new(Mutex::new(0));
+        let options = MigrateOptions::builder()
+            .on_progress({
+                let updates = updates.clone();
+                Arc::new(move |_| *updates.lock().unwrap() += 1)
+            })
+            .build();
+
+        let stats = migrate_store(src.clone(), dst.clone(), &[root_cid], options).await?;
+        assert_eq!(stats.blocks_skipped, 0);
+        assert!(stats.bytes_copied >= data.len() as u64);
+        assert_eq!(*updates.lock().unwrap() as u64, stats.blocks_copied);
+
+        let migrated = Dir::load(&root_cid, dst.clone()).await?;
+        assert!(verify(&migrated).await?.is_ok());
+
+        // Running it again finds the root in the destination and copies nothing.
+        let stats = migrate_store(src, dst, &[root_cid], MigrateOptions::default()).await?;
+        assert_eq!(stats.blocks_copied, 0);
+        assert_eq!(stats.blocks_skipped, 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_migrate_store_copies_subscribed_subtrees() -> anyhow::Result<()> {
+        let src = MemoryStore::default();
+        let file_with = |content: &'static [u8]| {
+            let src = src.clone();
+            async move {
+                let mut file = File::new(src);
+                file.put_content(content, &Chunker::Store).await?;
+                anyhow::Ok(file.store().await?)
+            }
+        };
+
+        let root = Dir::new(src.clone())
+            .link_at(&"public/photos/cat".parse()?, file_with(b"meow").await?)
+            .await?
+            .link_at(&"public/music/song".parse()?, file_with(b"la la").await?)
+            .await?;
+        let root_cid = root.store().await?;
+
+        let dst = MemoryStore::default();
+        let options = MigrateOptions::builder()
+            .subscription(Subscription::new(["public/photos".parse()?])?)
+            .build();
+        migrate_store(src.clone(), dst.clone(), &[root_cid], options).await?;
+
+        let replica = Dir::load(&root_cid, dst.clone()).await?;
+        replica.get_acl_at(&"public/photos/cat".parse()?).await?;
+        assert!(matches!(
+            replica.get_acl_at(&"public/music/song".parse()?).await,
+            Err(FsError::NotReplicatedLocally(path)) if path == "public/music".parse()?
+        ));
+
+        // Growing the subscription fetches the rest, even though the root is already there.
+        let options = MigrateOptions::builder()
+            .subscription(Subscription::new(["public".parse()?])?)
+            .build();
+        let stats = migrate_store(src, dst.clone(), &[root_cid], options).await?;
+        assert_eq!(stats.blocks_copied, 3);
+
+        let replica = Dir::load(&root_cid, dst).await?;
+        assert!(verify(&replica).await?.is_ok());
+
+        Ok(())
+    }
+}
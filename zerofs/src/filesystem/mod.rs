@@ -1,35 +1,99 @@
 //! The file system module.
 
+mod acl;
+mod branch;
 mod capabilities;
+mod changefeed;
+mod clock;
+mod derived;
+mod diff;
 mod dir;
 mod entity;
 mod error;
+mod extract;
 mod file;
+mod filestat;
 mod flag;
+mod glob;
+mod graft;
+mod grant;
+mod group;
 mod handle;
+#[cfg(feature = "native")]
+mod install;
+mod journal;
+mod keys;
 mod kind;
+mod limits;
 mod link;
+#[cfg(feature = "native")]
+mod local;
 mod metadata;
+mod migrate;
 mod path;
 mod pathdirs;
+mod proof;
+mod refcount;
+mod refs;
+mod remove;
+mod roots;
+mod search;
+mod snapshot;
 mod stores;
+mod subscription;
 mod symlink;
+mod timeout;
+mod transfer;
+mod trash;
+mod verify;
 
 //--------------------------------------------------------------------------------------------------
 // Exports
 //--------------------------------------------------------------------------------------------------
 
+pub use acl::*;
+pub use branch::*;
 pub use capabilities::*;
+pub use changefeed::*;
+pub use clock::*;
+pub use derived::*;
+pub use diff::*;
 pub use dir::*;
 pub use entity::*;
 pub use error::*;
+pub use extract::*;
 pub use file::*;
+pub use filestat::*;
 pub use flag::*;
+pub use glob::*;
+pub use graft::*;
+pub use grant::*;
+pub use group::*;
 pub use handle::*;
+#[cfg(feature = "native")]
+pub use install::*;
+pub use journal::*;
+pub use keys::*;
 pub use kind::*;
+pub use limits::*;
 pub use link::*;
+#[cfg(feature = "native")]
+pub use local::*;
 pub use metadata::*;
+pub use migrate::*;
 pub use path::*;
 pub use pathdirs::*;
+pub use proof::*;
+pub use refcount::*;
+pub use refs::*;
+pub use remove::*;
+pub use roots::*;
+pub use search::*;
+pub use snapshot::*;
 pub use stores::*;
+pub use subscription::*;
 pub use symlink::*;
+pub use timeout::*;
+pub use transfer::*;
+pub use trash::*;
+pub use verify::*;
@@ -3,6 +3,7 @@ use std::{
     convert::{TryFrom, TryInto},
     fmt::Display,
     hash::{Hash, Hasher},
+    iter::FromIterator,
     slice::SliceIndex,
     str::FromStr,
 };
@@ -31,7 +32,7 @@ pub const PATH_SEPARATOR: char = '/';
 /// ## Important
 ///
 /// Paths are case-insensitive, which affects their equality and hash implementations.
-#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct Path {
     /// The segments composing the path.
     segments: Vec<PathSegment>,
@@ -290,6 +291,14 @@ impl TryFrom<String> for Path {
     }
 }
 
+impl FromIterator<PathSegment> for Path {
+    fn from_iter<T: IntoIterator<Item = PathSegment>>(iter: T) -> Self {
+        Self {
+            segments: iter.into_iter().collect(),
+        }
+    }
+}
+
 impl Extend<PathSegment> for Path {
     fn extend<T: IntoIterator<Item = PathSegment>>(&mut self, iter: T) {
         self.segments.extend(iter);
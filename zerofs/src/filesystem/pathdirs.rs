@@ -4,9 +4,9 @@ use std::{
     ops::{Deref, DerefMut},
 };
 
-use zeroutils_store::IpldStore;
+use zeroutils_store::{ipld::cid::Cid, IpldStore, Storable};
 
-use super::{Dir, PathSegment};
+use super::{current_time, Dir, EntityType, EntryHint, FsResult, PathSegment};
 
 //--------------------------------------------------------------------------------------------------
 // Types
@@ -52,6 +52,55 @@ where
     }
 }
 
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Links `cid` as `name` in the last directory of `pathdirs`, or in `root` if there is none, and
+/// rewrites the directories above it up to `root` to link to the new ones. Returns the new root
+/// along with the [`Cid`] it is stored under.
+///
+/// This is the commit step every change to the tree ends with: `pathdirs` are the directories
+/// from the root to the parent of the changed entity, as traced when the entity was looked up, so
+/// the tree is not traced again. Each rewritten directory is stored, and the root and the
+/// directories in `pathdirs` are left as they are. Like [`Dir::link_at`], the parent has its
+/// modification time set to the [`current_time`] only if nothing was named `name` in it.
+pub async fn commit_spine<S>(
+    root: &Dir<S>,
+    pathdirs: &PathDirs<S>,
+    name: &PathSegment,
+    cid: Cid,
+) -> FsResult<(Dir<S>, Cid)>
+where
+    S: IpldStore + Send + Sync,
+{
+    let hint = EntryHint {
+        entity_type: EntityType::Dir,
+        size: None,
+    };
+
+    let mut dir = pathdirs.last().map_or(root, |(dir, _)| dir).clone();
+    if dir.get(name).is_none() {
+        dir.set_times(None, Some(current_time()));
+    }
+
+    dir.put(name.clone(), cid)?;
+    let mut cid = dir.store().await?;
+
+    // Each directory is linked under its name in the one before it, the first one in the root.
+    for (depth, (_, segment)) in pathdirs.path.iter().enumerate().rev() {
+        dir = match depth {
+            0 => root.clone(),
+            _ => pathdirs[depth - 1].0.clone(),
+        };
+
+        dir.put_with_hint(segment.clone(), cid, hint)?;
+        cid = dir.store().await?;
+    }
+
+    Ok((dir, cid))
+}
+
 //--------------------------------------------------------------------------------------------------
 // Trait Implementations
 //--------------------------------------------------------------------------------------------------
@@ -125,3 +174,55 @@ where
         f.debug_list().entries(self.path.iter()).finish()
     }
 }
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use zeroutils_store::MemoryStore;
+
+    use crate::filesystem::{Entity, File, Path, TraceResult};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_commit_spine() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let old = File::new(store.clone()).store().await?;
+        let root = Dir::new(store.clone())
+            .link_at(&"docs/notes/a".parse()?, old)
+            .await?;
+
+        let path: Path = "docs/notes/a".parse()?;
+        let (_, name, pathdirs) = root.get_or_create_entity(&path, true).await?;
+        assert_eq!(pathdirs.len(), 2);
+
+        let mut file = File::new(store.clone());
+        let file_content = store.put_raw_block(b"hello".to_vec()).await?;
+        file.set_content(Some(file_content));
+        let new = file.store().await?;
+        let (committed, cid) = commit_spine(&root, &pathdirs, &name.unwrap(), new).await?;
+
+        // The new root is stored and links to the new file, and the old root is left as it was.
+        let loaded = Dir::load(&cid, store.clone()).await?;
+        assert_eq!(loaded, committed);
+        assert!(matches!(
+            committed.trace_entity(&path).await?,
+            TraceResult::Found { entity: Entity::File(f), .. } if f.get_content() == Some(&file_content)
+        ));
+        assert!(matches!(
+            root.trace_entity(&path).await?,
+            TraceResult::Found { entity: Entity::File(f), .. } if f.get_content().is_none()
+        ));
+        assert_eq!(
+            committed
+                .get_hint(&"docs".parse()?)
+                .map(|hint| hint.entity_type),
+            Some(EntityType::Dir)
+        );
+
+        Ok(())
+    }
+}
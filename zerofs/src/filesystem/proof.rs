@@ -0,0 +1,234 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use serde_with::serde_as;
+use sha2::{Digest, Sha256};
+use zeroutils_store::{
+    ipld::cid::{multihash::Multihash, Cid},
+    IpldStore, Storable, StoreError, StoreResult,
+};
+
+use crate::config::{HashFunction, NodeCodec};
+
+use super::{Dir, Entity, FsError, FsResult, Path};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// A proof that the entity at a path is included under a root, made of the blocks of the
+/// directories along the path.
+///
+/// The proof is small, one directory node per path segment, and can be checked with
+/// [`verify_inclusion`] by anyone who trusts the root CID, for example from a signed root
+/// announcement, without trusting whoever served the proof or fetching the rest of the tree.
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InclusionProof {
+    /// The CID of the root the entity is included under.
+    #[serde_as(as = "serde_with::DisplayFromStr")]
+    pub root: Cid,
+
+    /// The path to the entity from the root.
+    #[serde_as(as = "serde_with::DisplayFromStr")]
+    pub path: Path,
+
+    /// The CID of the entity.
+    #[serde_as(as = "serde_with::DisplayFromStr")]
+    pub target: Cid,
+
+    /// The blocks of the directories along the path, from the root down to the parent of the
+    /// entity.
+    #[serde_as(as = "Vec<serde_with::Bytes>")]
+    pub blocks: Vec<Vec<u8>>,
+}
+
+/// The part of a directory node that a proof needs.
+#[derive(Deserialize)]
+struct ProofDirNode {
+    entries: BTreeMap<String, Cid>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl<S> Dir<S>
+where
+    S: IpldStore,
+{
+    /// Returns a proof that the entity at `path` is included under this directory, as it is
+    /// stored.
+    pub async fn prove_at(&self, path: &Path) -> FsResult<InclusionProof>
+    where
+        S: Send + Sync,
+    {
+        let path = path.canonicalize()?;
+        if path.is_empty() {
+            return Err(FsError::EmptyPath);
+        }
+
+        let store = self.get_store();
+        let root = self.store().await?;
+
+        let mut blocks = Vec::with_capacity(path.len());
+        let mut dir = self;
+        let mut cid = root;
+        for (depth, segment) in path.iter().enumerate() {
+            blocks.push(store.get_raw_block(&cid).await?.to_vec());
+
+            cid = *dir
+                .get(segment)
+                .ok_or_else(|| FsError::NotFound(path.clone()))?
+                .get_cid();
+
+            if depth + 1 < path.len() {
+                match dir.get_entity(segment).await? {
+                    Some(Entity::Dir(d)) => dir = d,
+                    _ => return Err(FsError::NotADirectory(Some(path.clone()))),
+                }
+            }
+        }
+
+        Ok(InclusionProof {
+            root,
+            path,
+            target: cid,
+            blocks,
+        })
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Checks that `proof` shows its target to be at its path under `root`.
+///
+/// Each block must hash to the CID the block before it links to, starting from `root`, so a
+/// proof cannot be forged without breaking the hash function. Only DAG-CBOR directory nodes are
+/// supported.
+pub fn verify_inclusion(proof: &InclusionProof, root: &Cid) -> FsResult<()> {
+    let invalid = |reason: String| FsError::InvalidProof(proof.path.clone(), reason);
+
+    if proof.root != *root {
+        return Err(invalid(format!(
+            "proof is for root {}, expected {}",
+            proof.root, root
+        )));
+    }
+
+    if proof.path.is_empty() || proof.blocks.len() != proof.path.len() {
+        return Err(invalid(format!(
+            "expected {} blocks, got {}",
+            proof.path.len(),
+            proof.blocks.len()
+        )));
+    }
+
+    let mut expected = *root;
+    for (segment, block) in proof.path.iter().zip(&proof.blocks) {
+        if expected.codec() != NodeCodec::DagCbor.code() {
+            return Err(invalid(format!("unsupported codec of block {}", expected)));
+        }
+
+        if !block_matches(&expected, block) {
+            return Err(invalid(format!("block does not match {}", expected)));
+        }
+
+        let node: ProofDirNode = serde_ipld_dagcbor::from_slice(block)
+            .map_err(|e| invalid(format!("block {} is not a directory: {}", expected, e)))?;
+
+        let name = segment.canonicalize();
+        expected = node
+            .entries
+            .iter()
+            .find(|(entry, _)| entry.to_lowercase() == name.as_str())
+            .map(|(_, cid)| *cid)
+            .ok_or_else(|| invalid(format!("no entry {} in block {}", segment, expected)))?;
+    }
+
+    if expected != proof.target {
+        return Err(invalid(format!(
+            "path leads to {}, not {}",
+            expected, proof.target
+        )));
+    }
+
+    Ok(())
+}
+
+/// Returns `true` if `bytes` hash to the digest of `cid`, with the hash function of `cid`.
+pub(crate) fn block_matches(cid: &Cid, bytes: &[u8]) -> bool {
+    let hash = cid.hash();
+    if hash.code() == HashFunction::Blake3.code() {
+        hash.digest() == blake3::hash(bytes).as_bytes()
+    } else if hash.code() == HashFunction::Sha2_256.code() {
+        hash.digest() == Sha256::digest(bytes).as_slice()
+    } else {
+        false
+    }
+}
+
+/// Returns the CID of a block with the given codec, hashed with `hash_function`, as the stores of
+/// this crate create them.
+pub(crate) fn make_cid(codec: u64, hash_function: HashFunction, bytes: &[u8]) -> StoreResult<Cid> {
+    let digest = match hash_function {
+        HashFunction::Blake3 => blake3::hash(bytes).as_bytes().to_vec(),
+        HashFunction::Sha2_256 => Sha256::digest(bytes).to_vec(),
+    };
+    let hash = Multihash::wrap(hash_function.code(), &digest).map_err(StoreError::custom)?;
+
+    Ok(Cid::new_v1(codec, hash))
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use zeroutils_store::MemoryStore;
+
+    use crate::{config::RAW_CODEC_CODE, filesystem::File};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_inclusion_proof() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let file_cid = File::new(store.clone()).store().await?;
+        let root = Dir::new(store)
+            .link_at(&"public/Notes".parse()?, file_cid)
+            .await?;
+        let root_cid = root.store().await?;
+
+        let proof = root.prove_at(&"public/notes".parse()?).await?;
+        assert_eq!(proof.target, file_cid);
+        assert_eq!(proof.blocks.len(), 2);
+        verify_inclusion(&proof, &root_cid)?;
+
+        // A proof does not hold for another root or with a tampered block.
+        let other = Dir::new(MemoryStore::default()).store().await?;
+        assert!(verify_inclusion(&proof, &other).is_err());
+
+        let mut tampered = proof.clone();
+        tampered.blocks[1][0] ^= 1;
+        assert!(verify_inclusion(&tampered, &root_cid).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_make_cid() -> anyhow::Result<()> {
+        for hash_function in [HashFunction::Blake3, HashFunction::Sha2_256] {
+            let cid = make_cid(RAW_CODEC_CODE, hash_function, b"hello")?;
+            assert_eq!(cid.codec(), RAW_CODEC_CODE);
+            assert_eq!(cid.hash().code(), hash_function.code());
+            assert!(block_matches(&cid, b"hello"));
+            assert!(!block_matches(&cid, b"world"));
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,267 @@
+use std::collections::{BTreeSet, HashMap};
+
+use zeroutils_store::{ipld::cid::Cid, IpldStore, Storable};
+
+use crate::config::RAW_CODEC_CODE;
+
+use super::{ContentChunks, Entity, FsResult};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The number of references to each block of the trees under a set of roots.
+///
+/// A block is referenced once by each node that links to it, and once more if it is one of the
+/// roots. Moving the roots with [`set_roots`][Self::set_roots] only visits the blocks whose count
+/// changes from or to zero: a subtree shared by the old and new roots gains a reference from the
+/// new parent and loses one from the old, and is not walked. So after a commit the blocks that
+/// became unreferenced are known right away, without walking the whole tree as mark-and-sweep
+/// collection does.
+///
+/// The walk follows the same links as [`verify`][super::verify]: the entries of directories, the
+/// content of files and the chunks of chunked content.
+#[derive(Debug, Clone, Default)]
+pub struct RefCountIndex {
+    /// The number of references to each referenced block.
+    counts: HashMap<Cid, u64>,
+
+    /// The roots the blocks are counted under.
+    roots: BTreeSet<Cid>,
+}
+
+/// How [`RefCountIndex::set_roots`] changed the counts.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RefCountDelta {
+    /// The blocks that were not referenced before and now are.
+    pub referenced: Vec<Cid>,
+
+    /// The blocks that were referenced before and now are not, which can be removed from the
+    /// store.
+    pub unreferenced: Vec<Cid>,
+}
+
+/// What a block is, which tells which blocks it links to.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum BlockKind {
+    /// An entity node.
+    Entity,
+
+    /// The content of a file, with whether it is a [`ContentChunks`] node.
+    Content { chunked: bool },
+
+    /// A chunk of content.
+    Chunk,
+}
+
+/// The counts changed by an update in progress, over the counts of the index.
+struct Update<'a> {
+    base: &'a HashMap<Cid, u64>,
+    changed: HashMap<Cid, u64>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl RefCountIndex {
+    /// Creates an index counting no roots.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of references to the block of `cid`.
+    pub fn get_count(&self, cid: &Cid) -> u64 {
+        self.counts.get(cid).copied().unwrap_or_default()
+    }
+
+    /// Returns the roots the blocks are counted under.
+    pub fn get_roots(&self) -> &BTreeSet<Cid> {
+        &self.roots
+    }
+
+    /// Returns the number of referenced blocks.
+    pub fn len(&self) -> usize {
+        self.counts.len()
+    }
+
+    /// Returns `true` if no block is referenced.
+    pub fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
+
+    /// Counts the blocks under `roots` instead of the current roots, and returns the blocks that
+    /// became referenced or unreferenced.
+    ///
+    /// The roots of the trees that must be kept, like the current root of a file system along with
+    /// those of its snapshots, are all passed together. The update is all or nothing: if a block
+    /// cannot be read, the index is left as it was.
+    pub async fn set_roots<S>(&mut self, roots: BTreeSet<Cid>, store: &S) -> FsResult<RefCountDelta>
+    where
+        S: IpldStore + Clone + Send + Sync,
+    {
+        let mut update = Update {
+            base: &self.counts,
+            changed: HashMap::new(),
+        };
+
+        // References are added before they are removed, so that blocks that stay referenced never
+        // drop to zero and are not walked.
+        let added = roots.difference(&self.roots).copied().collect::<Vec<_>>();
+        let mut pending = added
+            .into_iter()
+            .map(|cid| (cid, BlockKind::Entity))
+            .collect::<Vec<_>>();
+        while let Some((cid, kind)) = pending.pop() {
+            if update.increment(&cid) == 1 {
+                pending.extend(links(&cid, kind, store).await?);
+            }
+        }
+
+        let removed = self.roots.difference(&roots).copied().collect::<Vec<_>>();
+        let mut pending = removed
+            .into_iter()
+            .map(|cid| (cid, BlockKind::Entity))
+            .collect::<Vec<_>>();
+        while let Some((cid, kind)) = pending.pop() {
+            if update.decrement(&cid) == 0 {
+                pending.extend(links(&cid, kind, store).await?);
+            }
+        }
+
+        let mut delta = RefCountDelta::default();
+        for (cid, count) in update.changed {
+            match (self.get_count(&cid), count) {
+                (0, 0) => {}
+                (0, _) => delta.referenced.push(cid),
+                (_, 0) => delta.unreferenced.push(cid),
+                _ => {}
+            }
+
+            if count == 0 {
+                self.counts.remove(&cid);
+            } else {
+                self.counts.insert(cid, count);
+            }
+        }
+
+        self.roots = roots;
+        Ok(delta)
+    }
+}
+
+impl Update<'_> {
+    /// Adds a reference to `cid` and returns its new count.
+    fn increment(&mut self, cid: &Cid) -> u64 {
+        let count = self.get(cid) + 1;
+        self.changed.insert(*cid, count);
+        count
+    }
+
+    /// Removes a reference to `cid` and returns its new count.
+    fn decrement(&mut self, cid: &Cid) -> u64 {
+        let count = self.get(cid).saturating_sub(1);
+        self.changed.insert(*cid, count);
+        count
+    }
+
+    /// Returns the count of `cid` as of the update.
+    fn get(&self, cid: &Cid) -> u64 {
+        self.changed
+            .get(cid)
+            .or_else(|| self.base.get(cid))
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Returns the blocks the block of `cid` links to.
+pub(crate) async fn links<S>(
+    cid: &Cid,
+    kind: BlockKind,
+    store: &S,
+) -> FsResult<Vec<(Cid, BlockKind)>>
+where
+    S: IpldStore + Clone + Send + Sync,
+{
+    let links = match kind {
+        BlockKind::Entity if cid.codec() == RAW_CODEC_CODE => vec![],
+        BlockKind::Entity => match Entity::load(cid, store.clone()).await? {
+            Entity::Dir(dir) => dir
+                .get_entries()
+                .map(|(_, link)| (*link.get_cid(), BlockKind::Entity))
+                .collect(),
+            Entity::File(file) => file
+                .get_content()
+                .map(|content| {
+                    let chunked = file.is_chunked();
+                    (*content, BlockKind::Content { chunked })
+                })
+                .into_iter()
+                .collect(),
+            Entity::Symlink(_) => vec![],
+        },
+        BlockKind::Content { chunked: true } => ContentChunks::get_chunks(store, cid)
+            .await?
+            .into_iter()
+            .map(|chunk| (chunk, BlockKind::Chunk))
+            .collect(),
+        BlockKind::Content { chunked: false } | BlockKind::Chunk => vec![],
+    };
+
+    Ok(links)
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use zeroutils_store::MemoryStore;
+
+    use crate::filesystem::{Dir, File};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_refcounts_follow_commits() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let mut file = File::new(store.clone());
+        file.set_content(Some(store.put_raw_block(b"hello".to_vec()).await?));
+        let file_cid = file.store().await?;
+
+        let v1 = Dir::new(store.clone())
+            .link_at(&"public/hello".parse()?, file_cid)
+            .await?;
+        let v1_cid = v1.store().await?;
+
+        let mut index = RefCountIndex::new();
+        let delta = index.set_roots([v1_cid].into(), &store).await?;
+        assert_eq!(delta.referenced.len(), 4);
+        assert!(delta.unreferenced.is_empty());
+
+        // Replacing the file frees it and its content, and the directories above it.
+        let other = File::new(store.clone()).store().await?;
+        let v2 = v1.link_at(&"public/hello".parse()?, other).await?;
+        let v2_cid = v2.store().await?;
+
+        let delta = index.set_roots([v2_cid].into(), &store).await?;
+        assert_eq!(delta.unreferenced.len(), 4);
+        assert!(delta.unreferenced.contains(&file_cid));
+        assert!(delta.unreferenced.contains(&v1_cid));
+        assert_eq!(index.get_count(&file_cid), 0);
+        assert_eq!(index.get_count(&other), 1);
+
+        // Keeping the old root as well, say for a snapshot, keeps its blocks referenced.
+        let delta = index.set_roots([v1_cid, v2_cid].into(), &store).await?;
+        assert!(delta.referenced.contains(&file_cid));
+        assert!(delta.unreferenced.is_empty());
+
+        Ok(())
+    }
+}
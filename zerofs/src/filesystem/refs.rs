@@ -0,0 +1,292 @@
+use std::{collections::BTreeMap, io::Cursor};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_with::serde_as;
+use tokio::io::AsyncReadExt;
+use zeroutils_store::{ipld::cid::Cid, IpldStore, Storable};
+
+use super::{current_time, Chunker, Dir, Entity, File, FsError, FsResult, Path, TraceResult};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The well-known path of the file that records named refs, relative to the root.
+pub const REFS_PATH: &str = "system/refs";
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The named refs of a file tree, like branches and tags.
+///
+/// A ref maps a human-readable name, like `main` or `release/2024`, to the [`Cid`] of a root. The
+/// index is a TOML document stored at [`REFS_PATH`] that maps the name of each ref to the [`Ref`]
+/// describing it. Like snapshots, the CIDs are recorded as text rather than links.
+///
+/// Names are made of segments separated by `/`, each of ASCII letters, digits, `-`, `_` and `.`,
+/// other than `.` and `..`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RefIndex {
+    /// The refs by their name.
+    #[serde(default)]
+    refs: BTreeMap<String, Ref>,
+}
+
+/// Describes a named ref of a file tree.
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Ref {
+    /// The CID of the root the ref points at.
+    #[serde_as(as = "serde_with::DisplayFromStr")]
+    pub root: Cid,
+
+    /// The root the ref was forked from, for branches.
+    #[serde_as(as = "Option<serde_with::DisplayFromStr>")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base: Option<Cid>,
+
+    /// The time the ref was last updated.
+    pub updated_at: DateTime<Utc>,
+}
+
+/// What a ref must point at for an update to go through, so that concurrent updates of the same
+/// ref do not silently overwrite each other.
+#[serde_as]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RefPrecondition {
+    /// The ref may point at anything, or not exist.
+    #[default]
+    Any,
+
+    /// The ref must not exist.
+    Absent,
+
+    /// The ref must point at the root.
+    At(#[serde_as(as = "serde_with::DisplayFromStr")] Cid),
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl RefIndex {
+    /// Loads the ref index of the tree under `root`.
+    ///
+    /// A tree without a ref index has no refs.
+    pub async fn load<S>(root: &Dir<S>) -> FsResult<Self>
+    where
+        S: IpldStore + Send + Sync,
+    {
+        let path: Path = REFS_PATH.parse()?;
+        let file = match root.trace_entity(&path).await? {
+            TraceResult::Found {
+                entity: Entity::File(file),
+                ..
+            } => file,
+            TraceResult::Found { .. } => return Err(FsError::NotAFile(Some(path))),
+            _ => return Ok(Self::default()),
+        };
+
+        let mut content = String::new();
+        file.get_content_reader()
+            .await?
+            .read_to_string(&mut content)
+            .await?;
+
+        toml::from_str(&content).map_err(|e| FsError::InvalidRefIndex(e.to_string()))
+    }
+
+    /// Stores the index at [`REFS_PATH`] under `root` and returns the updated root.
+    async fn store_at<S>(&self, root: &Dir<S>) -> FsResult<Dir<S>>
+    where
+        S: IpldStore + Send + Sync + 'static,
+    {
+        let content = toml::to_string(self).map_err(|e| FsError::InvalidRefIndex(e.to_string()))?;
+
+        let mut file = File::new(root.get_store().clone());
+        file.put_content(Cursor::new(content.into_bytes()), &Chunker::Store)
+            .await?;
+
+        root.link_at(&REFS_PATH.parse()?, file.store().await?).await
+    }
+
+    /// Returns the ref with the given name.
+    pub fn get_ref(&self, name: &str) -> Option<&Ref> {
+        self.refs.get(name)
+    }
+
+    /// Returns an iterator over the names and refs, sorted by name.
+    pub fn get_refs(&self) -> impl Iterator<Item = (&str, &Ref)> {
+        self.refs.iter().map(|(name, entry)| (name.as_str(), entry))
+    }
+
+    /// Returns the number of refs.
+    pub fn len(&self) -> usize {
+        self.refs.len()
+    }
+
+    /// Returns `true` if there are no refs.
+    pub fn is_empty(&self) -> bool {
+        self.refs.is_empty()
+    }
+
+    /// Fails with [`FsError::RefConflict`] unless the ref `name` meets `precondition`.
+    fn check(&self, name: &str, precondition: &RefPrecondition) -> FsResult<()> {
+        let current = self.refs.get(name).map(|entry| &entry.root);
+        let met = match precondition {
+            RefPrecondition::Any => true,
+            RefPrecondition::Absent => current.is_none(),
+            RefPrecondition::At(expected) => current == Some(expected),
+        };
+
+        if !met {
+            return Err(FsError::RefConflict(name.to_owned()));
+        }
+
+        Ok(())
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Points the ref `name` of the tree under `root` at `target`, creating it if it does not exist,
+/// and returns the updated root.
+///
+/// The update only goes through if the ref meets `precondition`, otherwise it fails with
+/// [`FsError::RefConflict`] and nothing changes.
+pub async fn update_ref<S>(
+    root: &Dir<S>,
+    name: &str,
+    target: Cid,
+    precondition: &RefPrecondition,
+) -> FsResult<Dir<S>>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    put_ref(root, name, precondition, |current| Ref {
+        root: target,
+        base: current.and_then(|entry| entry.base),
+        updated_at: current_time(),
+    })
+    .await
+}
+
+/// Drops the ref `name` of the tree under `root` and returns the updated root.
+///
+/// The ref is only dropped if it meets `precondition`, otherwise this fails with
+/// [`FsError::RefConflict`]. Fails with [`FsError::RefNotFound`] if there is no such ref.
+pub async fn delete_ref<S>(
+    root: &Dir<S>,
+    name: &str,
+    precondition: &RefPrecondition,
+) -> FsResult<Dir<S>>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    let mut index = RefIndex::load(root).await?;
+    if !index.refs.contains_key(name) {
+        return Err(FsError::RefNotFound(name.to_owned()));
+    }
+
+    index.check(name, precondition)?;
+    index.refs.remove(name);
+
+    index.store_at(root).await
+}
+
+/// Replaces the ref `name` of the tree under `root` with the one `make` returns from the current
+/// one, if the ref meets `precondition`, and returns the updated root.
+pub(super) async fn put_ref<S>(
+    root: &Dir<S>,
+    name: &str,
+    precondition: &RefPrecondition,
+    make: impl FnOnce(Option<&Ref>) -> Ref,
+) -> FsResult<Dir<S>>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    validate_ref_name(name)?;
+
+    let mut index = RefIndex::load(root).await?;
+    index.check(name, precondition)?;
+    let entry = make(index.refs.get(name));
+    index.refs.insert(name.to_owned(), entry);
+
+    index.store_at(root).await
+}
+
+/// Fails with [`FsError::InvalidRefName`] unless `name` is a valid ref name.
+pub fn validate_ref_name(name: &str) -> FsResult<()> {
+    let valid = name.split('/').all(|segment| {
+        !matches!(segment, "" | "." | "..")
+            && segment
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    });
+
+    if !valid {
+        return Err(FsError::InvalidRefName(name.to_owned()));
+    }
+
+    Ok(())
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use zeroutils_store::MemoryStore;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_refs_update_and_delete() -> anyhow::Result<()> {
+        let root = Dir::new(MemoryStore::default());
+        let first = root.store().await?;
+
+        let root = update_ref(&root, "main", first, &RefPrecondition::Absent).await?;
+        let second = root.store().await?;
+        let root = update_ref(&root, "release/2024", first, &RefPrecondition::Any).await?;
+
+        // Only the update that saw the current target goes through.
+        let root = update_ref(&root, "main", second, &RefPrecondition::At(first)).await?;
+        assert!(matches!(
+            update_ref(&root, "main", first, &RefPrecondition::At(first)).await,
+            Err(FsError::RefConflict(_))
+        ));
+        assert!(matches!(
+            update_ref(&root, "main", first, &RefPrecondition::Absent).await,
+            Err(FsError::RefConflict(_))
+        ));
+
+        let index = RefIndex::load(&root).await?;
+        assert_eq!(
+            index.get_refs().map(|(name, _)| name).collect::<Vec<_>>(),
+            ["main", "release/2024"]
+        );
+        assert_eq!(index.get_ref("main").unwrap().root, second);
+
+        for name in ["", "/main", "main/", "a/../b", "main branch"] {
+            assert!(matches!(
+                update_ref(&root, name, first, &RefPrecondition::Any).await,
+                Err(FsError::InvalidRefName(_))
+            ));
+        }
+
+        let root = delete_ref(&root, "release/2024", &RefPrecondition::At(first)).await?;
+        assert_eq!(RefIndex::load(&root).await?.len(), 1);
+        assert!(matches!(
+            delete_ref(&root, "release/2024", &RefPrecondition::Any).await,
+            Err(FsError::RefNotFound(_))
+        ));
+
+        Ok(())
+    }
+}
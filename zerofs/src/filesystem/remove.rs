@@ -0,0 +1,203 @@
+use typed_builder::TypedBuilder;
+use zeroutils_store::{ipld::cid::Cid, IpldStore};
+
+use super::{
+    Dir, Entity, EntityAttributes, FsError, FsResult, Path, ProgressCallback, TraceResult,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The progress of a recursive remove.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RemoveProgress {
+    /// The number of entries under the removed directory checked so far.
+    pub entries_done: u64,
+}
+
+/// Options for removing an entity.
+#[derive(Clone, TypedBuilder)]
+pub struct RemoveOptions {
+    /// Whether a directory that is not empty is removed along with everything under it.
+    #[builder(default)]
+    pub recursive: bool,
+
+    /// The CID the entity is expected to have. If it has another one, it was changed since it was
+    /// last read and is not removed.
+    #[builder(default, setter(strip_option))]
+    pub expected: Option<Cid>,
+
+    /// Called after each entry under a directory removed recursively is checked.
+    #[builder(default, setter(strip_option))]
+    pub on_progress: Option<ProgressCallback<RemoveProgress>>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Fails if the entity at `path` under `root` cannot be removed with `options`.
+///
+/// A directory that is not empty can only be removed recursively. Everything under it is then
+/// walked, reporting progress along the way, and the remove fails if any file under it is
+/// immutable or append-only. Since the tree is never changed in place, unlinking the directory
+/// afterwards removes its whole subtree at once, so readers never see it half removed.
+///
+/// Fails with [`FsError::EntityChanged`] if the entity does not have the expected CID,
+/// [`FsError::DirNotEmpty`] if it is a directory that is not empty and the remove is not recursive,
+/// and [`FsError::Immutable`] or [`FsError::AppendOnly`] if a file under it is protected.
+pub async fn check_removable<S>(root: &Dir<S>, path: &Path, options: &RemoveOptions) -> FsResult<()>
+where
+    S: IpldStore + Send + Sync,
+{
+    let mut parent_path = path.clone();
+    let Some(name) = parent_path.pop() else {
+        return Err(FsError::EmptyPath);
+    };
+
+    let parent = if parent_path.is_empty() {
+        root.clone()
+    } else {
+        match root.trace_entity(&parent_path).await? {
+            TraceResult::Found {
+                entity: Entity::Dir(dir),
+                ..
+            } => dir,
+            TraceResult::Found { .. } => return Err(FsError::NotADirectory(Some(parent_path))),
+            _ => return Err(FsError::NotFound(path.clone())),
+        }
+    };
+
+    let Some(link) = parent.get(&name) else {
+        return Err(FsError::NotFound(path.clone()));
+    };
+
+    let cid = *link.get_cid();
+    if options.expected.is_some_and(|expected| expected != cid) {
+        return Err(FsError::EntityChanged(path.clone(), cid));
+    }
+
+    let Some(Entity::Dir(dir)) = parent.get_entity(&name).await? else {
+        return Ok(());
+    };
+
+    if dir.is_empty() {
+        return Ok(());
+    }
+
+    if !options.recursive {
+        return Err(FsError::DirNotEmpty(path.clone()));
+    }
+
+    let mut progress = RemoveProgress::default();
+    let mut pending = vec![(dir.clone(), path.clone())];
+    while let Some((dir, dir_path)) = pending.pop() {
+        for (name, _) in dir.get_entries() {
+            let mut entry_path = dir_path.clone();
+            entry_path.push(name.clone());
+
+            match dir.get_entity(name).await? {
+                Some(Entity::Dir(child)) => pending.push((child.clone(), entry_path)),
+                Some(entity) => {
+                    let attributes = entity.get_metadata().attributes;
+                    if attributes.contains(EntityAttributes::IMMUTABLE) {
+                        return Err(FsError::Immutable(entry_path));
+                    }
+
+                    if attributes.contains(EntityAttributes::APPEND_ONLY) {
+                        return Err(FsError::AppendOnly(entry_path));
+                    }
+                }
+                None => (),
+            }
+
+            progress.entries_done += 1;
+            if let Some(on_progress) = &options.on_progress {
+                on_progress(progress);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl Default for RemoveOptions {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use zeroutils_store::{MemoryStore, Storable};
+
+    use crate::filesystem::{Chunker, File};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_check_removable() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let mut file = File::new(store.clone());
+        file.put_content(&b"data"[..], &Chunker::Store).await?;
+        let file = file.store().await?;
+
+        let root = Dir::new(store.clone())
+            .link_at(&"docs/a".parse()?, file)
+            .await?
+            .link_at(&"docs/sub/b".parse()?, file)
+            .await?;
+        let docs: Path = "docs".parse()?;
+
+        assert!(matches!(
+            check_removable(&root, &docs, &RemoveOptions::default()).await,
+            Err(FsError::DirNotEmpty(_))
+        ));
+
+        // A stale CID means the directory changed since it was read.
+        let options = RemoveOptions::builder()
+            .recursive(true)
+            .expected(file)
+            .build();
+        assert!(matches!(
+            check_removable(&root, &docs, &options).await,
+            Err(FsError::EntityChanged(..))
+        ));
+
+        let updates = Arc::new(Mutex::new(Vec::new()));
+        let docs_cid = *root.get(&"docs".parse()?).unwrap().get_cid();
+        let options = RemoveOptions::builder()
+            .recursive(true)
+            .expected(docs_cid)
+            .on_progress({
+                let updates = updates.clone();
+                Arc::new(move |progress| updates.lock().unwrap().push(progress))
+            })
+            .build();
+        check_removable(&root, &docs, &options).await?;
+        assert_eq!(updates.lock().unwrap().len(), 3);
+
+        // Protected files deep in the tree stop a recursive remove.
+        let root = root
+            .set_attributes_at(&"docs/sub/b".parse()?, EntityAttributes::IMMUTABLE)
+            .await?;
+        let options = RemoveOptions::builder().recursive(true).build();
+        assert!(matches!(
+            check_removable(&root, &docs, &options).await,
+            Err(FsError::Immutable(path)) if path.to_string() == "/docs/sub/b"
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,201 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Debug,
+    sync::Arc,
+};
+
+use futures::future::BoxFuture;
+use zeroutils_store::{ipld::cid::Cid, IpldStore, Storable};
+
+use super::{DerivedIndex, Dir, FsResult, RefIndex, SnapshotIndex};
+
+//--------------------------------------------------------------------------------------------------
+// Traits
+//--------------------------------------------------------------------------------------------------
+
+/// Lists roots whose trees must be kept along with the current tree, because they are recorded
+/// somewhere garbage collection does not follow, like the CIDs recorded as text by snapshots.
+pub trait RootSource<S>: Debug + Send + Sync
+where
+    S: IpldStore,
+{
+    /// Returns the ID of the source.
+    fn id(&self) -> &str;
+
+    /// Returns the roots to keep for the tree under `root`.
+    fn roots<'a>(&'a self, root: &'a Dir<S>) -> BoxFuture<'a, FsResult<Vec<Cid>>>;
+}
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The roots garbage collection keeps the trees of.
+///
+/// Those are the current root, the roots of its snapshots and refs and its derived blobs, which
+/// are always kept, along with the roots listed by each registered [`RootSource`] and the roots
+/// pinned by name. Sources are for roots that can be found in the tree, and are asked again every
+/// time the roots are collected. Pins are for roots held outside of it, like by a client reading
+/// an old version, and last until they are unpinned.
+#[derive(Debug)]
+pub struct RootRegistry<S>
+where
+    S: IpldStore,
+{
+    /// The sources of roots by their ID.
+    sources: BTreeMap<String, Arc<dyn RootSource<S>>>,
+
+    /// The pinned roots by the name of their owner.
+    pins: BTreeMap<String, BTreeSet<Cid>>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl<S> RootRegistry<S>
+where
+    S: IpldStore,
+{
+    /// Creates a registry with no sources or pins.
+    pub fn new() -> Self {
+        Self {
+            sources: BTreeMap::new(),
+            pins: BTreeMap::new(),
+        }
+    }
+
+    /// Registers `source`, in place of any source with the same ID.
+    pub fn register_source(&mut self, source: Arc<dyn RootSource<S>>) {
+        self.sources.insert(source.id().to_owned(), source);
+    }
+
+    /// Unregisters the source with ID `id`, returning it if it was registered.
+    pub fn unregister_source(&mut self, id: &str) -> Option<Arc<dyn RootSource<S>>> {
+        self.sources.remove(id)
+    }
+
+    /// Returns the IDs of the registered sources.
+    pub fn get_source_ids(&self) -> impl Iterator<Item = &str> {
+        self.sources.keys().map(String::as_str)
+    }
+
+    /// Pins `cid` for `owner`, so its tree is kept until `owner` unpins it.
+    pub fn pin(&mut self, owner: impl Into<String>, cid: Cid) {
+        self.pins.entry(owner.into()).or_default().insert(cid);
+    }
+
+    /// Unpins `cid` for `owner`. Returns `false` if `owner` had not pinned it.
+    pub fn unpin(&mut self, owner: &str, cid: &Cid) -> bool {
+        let Some(pins) = self.pins.get_mut(owner) else {
+            return false;
+        };
+
+        let unpinned = pins.remove(cid);
+        if pins.is_empty() {
+            self.pins.remove(owner);
+        }
+
+        unpinned
+    }
+
+    /// Returns the roots pinned by `owner`.
+    pub fn get_pins(&self, owner: &str) -> Option<&BTreeSet<Cid>> {
+        self.pins.get(owner)
+    }
+
+    /// Returns every root to keep for the tree under `root`, `root` included.
+    pub async fn collect(&self, root: &Dir<S>) -> FsResult<BTreeSet<Cid>>
+    where
+        S: Send + Sync,
+    {
+        let mut roots = BTreeSet::from([root.store().await?]);
+        let snapshots = SnapshotIndex::load(root).await?;
+        roots.extend(snapshots.get_snapshots().map(|(_, snapshot)| snapshot.root));
+        let refs = RefIndex::load(root).await?;
+        roots.extend(refs.get_refs().map(|(_, r)| r.root));
+        let derived = DerivedIndex::load(root).await?;
+        roots.extend(derived.get_blobs().map(|blob| blob.blob));
+
+        for source in self.sources.values() {
+            roots.extend(source.roots(root).await?);
+        }
+
+        roots.extend(self.pins.values().flatten().copied());
+        Ok(roots)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl<S> Default for RootRegistry<S>
+where
+    S: IpldStore,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use zeroutils_store::MemoryStore;
+
+    use crate::filesystem::{create_snapshot, File};
+
+    use super::*;
+
+    #[derive(Debug)]
+    struct FixedSource(Vec<Cid>);
+
+    impl RootSource<MemoryStore> for FixedSource {
+        fn id(&self) -> &str {
+            "fixed"
+        }
+
+        fn roots<'a>(&'a self, _: &'a Dir<MemoryStore>) -> BoxFuture<'a, FsResult<Vec<Cid>>> {
+            Box::pin(async move { Ok(self.0.clone()) })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_root_registry_collects_all_roots() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let file_cid = File::new(store.clone()).store().await?;
+        let root = Dir::new(store.clone())
+            .link_at(&"public/a".parse()?, file_cid)
+            .await?;
+        let old_root = root.store().await?;
+        let (root, _) = create_snapshot(&root, None).await?;
+
+        let pinned = store.put_raw_block(b"pinned".to_vec()).await?;
+        let sourced = store.put_raw_block(b"sourced".to_vec()).await?;
+
+        let mut registry = RootRegistry::new();
+        registry.pin("reader", pinned);
+        registry.register_source(Arc::new(FixedSource(vec![sourced])));
+
+        let roots = registry.collect(&root).await?;
+        assert_eq!(
+            roots,
+            BTreeSet::from([root.store().await?, old_root, pinned, sourced])
+        );
+
+        // Unpinning and unregistering drop their roots.
+        assert!(registry.unpin("reader", &pinned));
+        assert!(!registry.unpin("reader", &pinned));
+        assert!(registry.unregister_source("fixed").is_some());
+        assert_eq!(
+            registry.collect(&root).await?,
+            BTreeSet::from([root.store().await?, old_root])
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,555 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::{Deserialize, Serialize};
+use zeroutils_store::{ipld::cid::Cid, IpldReferences, IpldStore, Storable};
+
+use super::{
+    diff, Change, ContentQueue, Dir, Entity, EntityAttributes, EntityType, FsResult, Path,
+    TraceResult,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The name of the directory at the root that holds the files the file system keeps for itself,
+/// which are not indexed.
+const SYSTEM_DIR_NAME: &str = "system";
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// An index of the paths of a file tree by the tokens of their names and their attributes, so
+/// that files can be found without walking the directories.
+///
+/// The names of entities are split into lowercase tokens where the case changes from lower to
+/// upper and between letters and digits, so `AnnualReport2024` is found by `annual`, `report` and
+/// `2024`. Entities are also indexed by their type, as `type:file`, `type:dir` or `type:symlink`,
+/// and by their attributes, as `attr:immutable` and `attr:append-only`. The files the file system
+/// keeps for itself under `system` are not indexed.
+///
+/// With a [`ContentQueue`] set, files are indexed by the tokens of their text as well. Their
+/// content is handed to the queue as they are indexed and the terms extracted in the background
+/// are picked up by the next [`update`][Self::update], so the text of a file is found some time
+/// after its name.
+///
+/// The index is brought up to date with [`update`][Self::update], which only looks at what
+/// changed since the root it was last updated with, and can be persisted in the store as a node
+/// with [`store`][Self::store].
+#[derive(Debug, Clone)]
+pub struct SearchIndex<S>
+where
+    S: IpldStore,
+{
+    store: S,
+
+    /// The root the index is up to date with, or `None` if nothing was indexed yet.
+    root: Option<Cid>,
+
+    /// The terms of each indexed path.
+    paths: BTreeMap<Path, BTreeSet<String>>,
+
+    /// The paths with each term.
+    terms: BTreeMap<String, BTreeSet<Path>>,
+
+    /// Where the content of files is handed to be extracted, if it is indexed.
+    content: Option<ContentQueue>,
+
+    /// The CIDs of the files whose content is waiting to be extracted.
+    pending: BTreeMap<Path, Cid>,
+}
+
+/// A [`SearchIndex`] as it is stored.
+#[derive(Debug, Serialize, Deserialize)]
+struct SearchIndexNode {
+    root: Option<Cid>,
+    paths: BTreeMap<String, BTreeSet<String>>,
+    #[serde(default)]
+    pending: BTreeMap<String, Cid>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl<S> SearchIndex<S>
+where
+    S: IpldStore,
+{
+    /// Creates an empty index that is stored in `store`.
+    pub fn new(store: S) -> Self {
+        Self {
+            store,
+            root: None,
+            paths: BTreeMap::new(),
+            terms: BTreeMap::new(),
+            content: None,
+            pending: BTreeMap::new(),
+        }
+    }
+
+    /// Loads the index stored at `cid`.
+    pub async fn load(cid: &Cid, store: S) -> FsResult<Self>
+    where
+        S: Send + Sync + 'static,
+    {
+        let node: SearchIndexNode = store.get_node(cid).await?;
+
+        let mut index = Self::new(store);
+        index.root = node.root;
+        for (path, terms) in node.paths {
+            index.insert(path.parse()?, terms);
+        }
+
+        for (path, cid) in node.pending {
+            index.pending.insert(path.parse()?, cid);
+        }
+
+        Ok(index)
+    }
+
+    /// Stores the index as a node and returns its CID.
+    pub async fn store(&self) -> FsResult<Cid>
+    where
+        S: Send + Sync + 'static,
+    {
+        let node = SearchIndexNode {
+            root: self.root,
+            paths: self
+                .paths
+                .iter()
+                .map(|(path, terms)| (path.to_string(), terms.clone()))
+                .collect(),
+            pending: self
+                .pending
+                .iter()
+                .map(|(path, cid)| (path.to_string(), *cid))
+                .collect(),
+        };
+
+        Ok(self.store.put_node(&node).await?)
+    }
+
+    /// Returns the root the index is up to date with, or `None` if nothing was indexed yet.
+    pub fn get_root(&self) -> Option<&Cid> {
+        self.root.as_ref()
+    }
+
+    /// Returns the number of indexed paths.
+    pub fn len(&self) -> usize {
+        self.paths.len()
+    }
+
+    /// Returns `true` if no paths are indexed.
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+    }
+
+    /// Returns the queue the content of files is handed to, or `None` if it is not indexed.
+    pub fn get_content_queue(&self) -> Option<&ContentQueue> {
+        self.content.as_ref()
+    }
+
+    /// Indexes the content of files from now on, handing it to `queue` to be extracted, along with
+    /// the content of the files that were still waiting when the index was stored.
+    pub fn set_content_queue(&mut self, queue: ContentQueue) {
+        for (path, cid) in &self.pending {
+            queue.enqueue(path.clone(), *cid);
+        }
+
+        self.content = Some(queue);
+    }
+
+    /// Returns the number of files whose content is waiting to be extracted.
+    pub fn get_pending_count(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Brings the index up to date with the tree under `root`.
+    ///
+    /// Only the entities that changed since the root the index was last updated with are
+    /// reindexed, so the cost grows with what changed rather than with the size of the tree. The
+    /// terms extracted from the content of files since the last update are picked up as well.
+    pub async fn update(&mut self, root: &Dir<S>) -> FsResult<()>
+    where
+        S: Send + Sync + 'static,
+    {
+        self.collect_content();
+
+        let cid = root.store().await?;
+        if self.root == Some(cid) {
+            return Ok(());
+        }
+
+        let changes = match &self.root {
+            Some(old) => diff(&Dir::load(old, self.store.clone()).await?, root).await?,
+            None => root
+                .get_entries()
+                .map(|(name, link)| {
+                    let mut path = Path::default();
+                    path.push(name.clone());
+                    Change::Added {
+                        path,
+                        cid: *link.get_cid(),
+                    }
+                })
+                .collect(),
+        };
+
+        // Everything that changed is dropped first, so that entities moved between paths that
+        // changed as well are indexed at their new path.
+        let mut reindex = Vec::new();
+        for change in changes {
+            match change {
+                Change::Removed { path } => self.remove_under(&path),
+                Change::Added { path, cid } | Change::Modified { path, cid } => {
+                    self.remove_under(&path);
+                    reindex.push((path, cid));
+                }
+                Change::Renamed { from, to, cid } => {
+                    self.remove_under(&from);
+                    self.remove_under(&to);
+                    reindex.push((to, cid));
+                }
+            }
+        }
+
+        for (path, cid) in reindex {
+            if is_system(&path) {
+                continue;
+            }
+
+            if let TraceResult::Found { entity, .. } = root.trace_entity(&path).await? {
+                self.index_under(path, cid, entity).await?;
+            }
+        }
+
+        self.root = Some(cid);
+
+        Ok(())
+    }
+
+    /// Returns the paths that match every term of `query`, sorted.
+    ///
+    /// Terms with a `:`, like `type:file`, must match exactly. Other terms are split into tokens
+    /// like names are, and each token matches the names, or the text of files if their content is
+    /// indexed, with a token it is a prefix of, so `rep` finds `AnnualReport`. An empty query
+    /// matches nothing.
+    pub fn search(&self, query: &str) -> Vec<&Path> {
+        let mut matches: Option<BTreeSet<&Path>> = None;
+        for word in query.split_whitespace() {
+            let found = if word.contains(':') {
+                self.terms
+                    .get(&word.to_lowercase())
+                    .into_iter()
+                    .flatten()
+                    .collect()
+            } else {
+                tokenize(word)
+                    .map(|token| {
+                        self.terms
+                            .range(token.clone()..)
+                            .take_while(|(term, _)| term.starts_with(&token))
+                            .filter(|(term, _)| !term.contains(':'))
+                            .flat_map(|(_, paths)| paths)
+                            .collect::<BTreeSet<_>>()
+                    })
+                    .reduce(|a, b| a.intersection(&b).copied().collect())
+                    .unwrap_or_default()
+            };
+
+            matches = Some(match matches {
+                Some(matches) => matches.intersection(&found).copied().collect(),
+                None => found,
+            });
+        }
+
+        matches.unwrap_or_default().into_iter().collect()
+    }
+
+    /// Indexes `entity`, with the CID `cid`, at `path` along with everything under it.
+    async fn index_under(&mut self, path: Path, cid: Cid, entity: Entity<S>) -> FsResult<()>
+    where
+        S: Send + Sync + 'static,
+    {
+        let mut pending = vec![(path, cid, entity)];
+        while let Some((path, cid, entity)) = pending.pop() {
+            match &entity {
+                Entity::Dir(dir) => {
+                    for (name, link) in dir.get_entries() {
+                        if let Some(child) = dir.get_entity(name).await? {
+                            let mut child_path = path.clone();
+                            child_path.push(name.clone());
+                            pending.push((child_path, *link.get_cid(), child.clone()));
+                        }
+                    }
+                }
+                Entity::File(_) => {
+                    if let Some(content) = &self.content {
+                        content.enqueue(path.clone(), cid);
+                        self.pending.insert(path.clone(), cid);
+                    }
+                }
+                _ => {}
+            }
+
+            let terms = get_terms(&path, &entity);
+            self.insert(path, terms);
+        }
+
+        Ok(())
+    }
+
+    /// Adds the terms extracted from the content of files to the terms of their paths, unless the
+    /// files changed since they were queued.
+    fn collect_content(&mut self) {
+        let Some(content) = &self.content else {
+            return;
+        };
+
+        for extracted in content.take_extracted() {
+            if self.pending.get(&extracted.path) != Some(&extracted.cid) {
+                continue;
+            }
+
+            self.pending.remove(&extracted.path);
+            let mut terms = self.paths.remove(&extracted.path).unwrap_or_default();
+            terms.extend(extracted.terms);
+            self.insert(extracted.path, terms);
+        }
+    }
+
+    /// Drops `path` and everything under it from the index.
+    fn remove_under(&mut self, path: &Path) {
+        let under = self
+            .paths
+            .range(path.clone()..)
+            .map(|(indexed, _)| indexed)
+            .take_while(|indexed| indexed.get_segments().starts_with(path.get_segments()))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        self.pending
+            .retain(|waiting, _| !waiting.get_segments().starts_with(path.get_segments()));
+
+        for indexed in under {
+            let terms = self.paths.remove(&indexed).unwrap_or_default();
+            for term in terms {
+                if let Some(paths) = self.terms.get_mut(&term) {
+                    paths.remove(&indexed);
+                    if paths.is_empty() {
+                        self.terms.remove(&term);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Indexes `path` under `terms`.
+    fn insert(&mut self, path: Path, terms: BTreeSet<String>) {
+        for term in &terms {
+            self.terms
+                .entry(term.clone())
+                .or_default()
+                .insert(path.clone());
+        }
+
+        self.paths.insert(path, terms);
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Returns the terms `entity` at `path` is indexed under.
+fn get_terms<S>(path: &Path, entity: &Entity<S>) -> BTreeSet<String>
+where
+    S: IpldStore,
+{
+    let metadata = entity.get_metadata();
+
+    let mut terms: BTreeSet<String> = path
+        .last()
+        .map(|name| tokenize(name.as_str()).collect())
+        .unwrap_or_default();
+
+    terms.insert(
+        match metadata.entity_type {
+            EntityType::File => "type:file",
+            EntityType::Dir => "type:dir",
+            EntityType::Symlink => "type:symlink",
+        }
+        .to_owned(),
+    );
+
+    if metadata.attributes.contains(EntityAttributes::IMMUTABLE) {
+        terms.insert("attr:immutable".to_owned());
+    }
+
+    if metadata.attributes.contains(EntityAttributes::APPEND_ONLY) {
+        terms.insert("attr:append-only".to_owned());
+    }
+
+    terms
+}
+
+/// Splits `text` into lowercase tokens where the case changes from lower to upper, between
+/// letters and digits, and at anything else.
+pub(super) fn tokenize(text: &str) -> impl Iterator<Item = String> {
+    let mut tokens = Vec::new();
+    let mut token = String::new();
+    let mut previous: Option<char> = None;
+    for c in text.chars() {
+        let boundary = match previous {
+            Some(previous) => {
+                !c.is_alphanumeric()
+                    || (previous.is_lowercase() && c.is_uppercase())
+                    || previous.is_alphabetic() != c.is_alphabetic()
+            }
+            None => true,
+        };
+
+        if boundary && !token.is_empty() {
+            tokens.push(std::mem::take(&mut token));
+        }
+
+        if c.is_alphanumeric() {
+            token.extend(c.to_lowercase());
+            previous = Some(c);
+        } else {
+            previous = None;
+        }
+    }
+
+    if !token.is_empty() {
+        tokens.push(token);
+    }
+
+    tokens.into_iter()
+}
+
+/// Returns `true` if `path` is under the directory the file system keeps its own files in.
+fn is_system(path: &Path) -> bool {
+    path.first()
+        .is_some_and(|name| name.as_str() == SYSTEM_DIR_NAME)
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl IpldReferences for SearchIndexNode {
+    fn references<'a>(&'a self) -> Box<dyn Iterator<Item = &'a Cid> + Send + 'a> {
+        Box::new(self.root.iter().chain(self.pending.values()))
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use zeroutils_store::MemoryStore;
+
+    use crate::filesystem::{content_queue, Chunker, File};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_search_index_follows_changes() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let file = File::new(store.clone()).store().await?;
+        let mut immutable = File::new(store.clone());
+        immutable.set_attributes(EntityAttributes::IMMUTABLE);
+        let immutable = immutable.store().await?;
+
+        let root = Dir::new(store.clone())
+            .link_at(&"docs/AnnualReport2024".parse()?, file)
+            .await?
+            .link_at(&"docs/notes".parse()?, immutable)
+            .await?
+            .link_at(&"system/trash/report".parse()?, file)
+            .await?;
+
+        let mut index = SearchIndex::new(store.clone());
+        index.update(&root).await?;
+
+        let search = |index: &SearchIndex<_>, query| {
+            index
+                .search(query)
+                .into_iter()
+                .map(|path| path.to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(search(&index, "rep 2024"), ["/docs/AnnualReport2024"]);
+        assert_eq!(search(&index, "AnnualRep"), ["/docs/AnnualReport2024"]);
+        assert_eq!(search(&index, "type:dir"), ["/docs"]);
+        assert_eq!(search(&index, "attr:immutable"), ["/docs/notes"]);
+        assert!(search(&index, "").is_empty());
+
+        // Moving the directory reindexes everything under it from the diff.
+        let (root, docs) = root.unlink_at(&"docs".parse()?).await?;
+        let root = root.link_at(&"archive".parse()?, docs).await?;
+        index.update(&root).await?;
+        assert_eq!(
+            search(&index, "report type:file"),
+            ["/archive/AnnualReport2024"]
+        );
+
+        let index = SearchIndex::load(&index.store().await?, store).await?;
+        assert_eq!(index.len(), 3);
+        assert_eq!(search(&index, "notes"), ["/archive/notes"]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_search_index_extracts_content() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let file_with = |content: &'static [u8]| {
+            let store = store.clone();
+            async move {
+                let mut file = File::new(store);
+                file.put_content(content, &Chunker::Store).await?;
+                anyhow::Ok(file.store().await?)
+            }
+        };
+
+        let (queue, mut worker) = content_queue(store.clone(), 1024);
+        let mut index = SearchIndex::new(store.clone());
+        index.set_content_queue(queue);
+
+        let root = Dir::new(store.clone())
+            .link_at(&"minutes".parse()?, file_with(b"# Budget\nAgreed.").await?)
+            .await?
+            .link_at(&"blob".parse()?, file_with(&[0x00, 0xff]).await?)
+            .await?;
+        index.update(&root).await?;
+
+        // The text is only found once the worker extracted it.
+        assert!(index.search("budget").is_empty());
+        assert_eq!(worker.run_pending().await?, 2);
+        index.update(&root).await?;
+        assert_eq!(index.get_pending_count(), 0);
+        assert_eq!(index.search("agreed")[0].to_string(), "/minutes");
+
+        // Terms extracted from a file that changed since it was queued are dropped.
+        let root = root
+            .link_at(&"minutes".parse()?, file_with(b"Postponed.").await?)
+            .await?;
+        index.update(&root).await?;
+        let root = root
+            .link_at(&"minutes".parse()?, file_with(b"Cancelled.").await?)
+            .await?;
+        index.update(&root).await?;
+        assert_eq!(worker.run_pending().await?, 2);
+        index.update(&root).await?;
+        assert!(index.search("postponed").is_empty());
+        assert_eq!(index.search("cancelled")[0].to_string(), "/minutes");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,206 @@
+use std::{collections::BTreeMap, io::Cursor};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_with::serde_as;
+use tokio::io::AsyncReadExt;
+use zeroutils_store::{ipld::cid::Cid, IpldStore, Storable};
+
+use super::{current_time, Chunker, Dir, Entity, File, FsError, FsResult, Path, TraceResult};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The well-known path of the file that records snapshots, relative to the root.
+pub const SNAPSHOTS_PATH: &str = "system/snapshots";
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The record of the snapshots taken of a file tree.
+///
+/// A snapshot is the [`Cid`] of the root at some point in time. The index is a TOML document stored
+/// at [`SNAPSHOTS_PATH`] that maps the ID of each snapshot to the [`Snapshot`] describing it. The
+/// CIDs are recorded as text rather than links, so a snapshot does not keep the snapshots taken
+/// before it reachable from the root.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotIndex {
+    /// The snapshots by their ID.
+    #[serde(default)]
+    snapshots: BTreeMap<String, Snapshot>,
+}
+
+/// Describes a snapshot of a file tree.
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// The CID of the root when the snapshot was taken.
+    #[serde_as(as = "serde_with::DisplayFromStr")]
+    pub root: Cid,
+
+    /// The time the snapshot was taken.
+    pub created_at: DateTime<Utc>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl SnapshotIndex {
+    /// Loads the snapshot index of the tree under `root`.
+    ///
+    /// A tree without a snapshot index has no snapshots.
+    pub async fn load<S>(root: &Dir<S>) -> FsResult<Self>
+    where
+        S: IpldStore + Send + Sync,
+    {
+        let path: Path = SNAPSHOTS_PATH.parse()?;
+        let file = match root.trace_entity(&path).await? {
+            TraceResult::Found {
+                entity: Entity::File(file),
+                ..
+            } => file,
+            TraceResult::Found { .. } => return Err(FsError::NotAFile(Some(path))),
+            _ => return Ok(Self::default()),
+        };
+
+        let mut content = String::new();
+        file.get_content_reader()
+            .await?
+            .read_to_string(&mut content)
+            .await?;
+
+        toml::from_str(&content).map_err(|e| FsError::InvalidSnapshotIndex(e.to_string()))
+    }
+
+    /// Stores the index at [`SNAPSHOTS_PATH`] under `root` and returns the updated root.
+    async fn store_at<S>(&self, root: &Dir<S>) -> FsResult<Dir<S>>
+    where
+        S: IpldStore + Send + Sync + 'static,
+    {
+        let content =
+            toml::to_string(self).map_err(|e| FsError::InvalidSnapshotIndex(e.to_string()))?;
+
+        let mut file = File::new(root.get_store().clone());
+        file.put_content(Cursor::new(content.into_bytes()), &Chunker::Store)
+            .await?;
+
+        root.link_at(&SNAPSHOTS_PATH.parse()?, file.store().await?)
+            .await
+    }
+
+    /// Returns the snapshot with the given ID.
+    pub fn get_snapshot(&self, id: &str) -> Option<&Snapshot> {
+        self.snapshots.get(id)
+    }
+
+    /// Returns an iterator over the IDs and snapshots, oldest first.
+    pub fn get_snapshots(&self) -> impl Iterator<Item = (&str, &Snapshot)> {
+        self.snapshots
+            .iter()
+            .map(|(id, snapshot)| (id.as_str(), snapshot))
+    }
+
+    /// Returns the number of snapshots.
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    /// Returns `true` if there are no snapshots.
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Stores the tree under `root` and records it as a snapshot, returning the updated root along
+/// with the ID of the snapshot.
+///
+/// If `keep` is set, the oldest snapshots are dropped from the index so that at most `keep` remain.
+pub async fn create_snapshot<S>(root: &Dir<S>, keep: Option<usize>) -> FsResult<(Dir<S>, String)>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    let mut index = SnapshotIndex::load(root).await?;
+    let created_at = current_time();
+    let snapshot = Snapshot {
+        root: root.store().await?,
+        created_at,
+    };
+
+    // IDs sort in creation order.
+    let base = created_at.format("%Y%m%d%H%M%S%f").to_string();
+    let id = (0..)
+        .map(|n| format!("{base}{n}"))
+        .find(|id| !index.snapshots.contains_key(id))
+        .unwrap();
+
+    index.snapshots.insert(id.clone(), snapshot);
+    if let Some(keep) = keep {
+        while index.snapshots.len() > keep {
+            index.snapshots.pop_first();
+        }
+    }
+
+    Ok((index.store_at(root).await?, id))
+}
+
+/// Drops the snapshot with the given ID from the index of the tree under `root` and returns the
+/// updated root.
+pub async fn delete_snapshot<S>(root: &Dir<S>, id: &str) -> FsResult<Dir<S>>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    let mut index = SnapshotIndex::load(root).await?;
+    if index.snapshots.remove(id).is_none() {
+        return Err(FsError::SnapshotNotFound(id.to_owned()));
+    }
+
+    index.store_at(root).await
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use zeroutils_store::MemoryStore;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_snapshots_create_and_delete() -> anyhow::Result<()> {
+        let root = Dir::new(MemoryStore::default());
+
+        let (root, first) = create_snapshot(&root, None).await?;
+        let (root, second) = create_snapshot(&root, None).await?;
+        let (root, third) = create_snapshot(&root, Some(2)).await?;
+
+        let index = SnapshotIndex::load(&root).await?;
+        assert_eq!(
+            index.get_snapshots().map(|(id, _)| id).collect::<Vec<_>>(),
+            vec![second.as_str(), third.as_str()]
+        );
+        assert!(index.get_snapshot(&first).is_none());
+
+        // The snapshot can be loaded back as a root.
+        let snapshot = Dir::load(
+            &index.get_snapshot(&second).unwrap().root,
+            root.get_store().clone(),
+        )
+        .await?;
+        assert_eq!(SnapshotIndex::load(&snapshot).await?.len(), 1);
+
+        let root = delete_snapshot(&root, &second).await?;
+        assert_eq!(SnapshotIndex::load(&root).await?.len(), 1);
+        assert!(delete_snapshot(&root, &second).await.is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,368 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    future::Future,
+    io::Cursor,
+    pin::Pin,
+    sync::{Arc, Mutex},
+};
+
+use bytes::Bytes;
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::io::AsyncRead;
+use zeroutils_store::{ipld::cid::Cid, Codec, IpldReferences, IpldStore, StoreResult};
+
+use crate::config::RAW_CODEC_CODE;
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The default total size of the blocks a [`CachedStore`] keeps in memory, in bytes.
+pub const DEFAULT_BLOCK_CACHE_CAPACITY: usize = 64 * 1024 * 1024;
+
+tokio::task_local! {
+    static SKIP_CACHE: ();
+}
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// An [`IpldStore`] that keeps the raw blocks read from another store in memory, evicting the least
+/// recently used blocks once their total size goes over the capacity.
+///
+/// Cached blocks are handed out as reference-counted [`Bytes`], so reads served from the cache,
+/// like those of [`File::get_content_stream`][crate::filesystem::File::get_content_stream], share
+/// the cached copy of each block instead of copying it.
+///
+/// Blocks read inside [`without_caching`] are still served from the cache if they are in it, but
+/// are not added to it when they are not.
+#[derive(Debug, Clone)]
+pub struct CachedStore<S>
+where
+    S: IpldStore,
+{
+    inner: S,
+    cache: Arc<Mutex<BlockCache>>,
+}
+
+/// Statistics about the reads of raw blocks from a [`CachedStore`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BlockCacheStats {
+    /// The number of reads served from the cache.
+    pub hits: u64,
+
+    /// The number of reads that went to the underlying store.
+    pub misses: u64,
+
+    /// The number of blocks in the cache.
+    pub blocks: usize,
+
+    /// The total size of the blocks in the cache, in bytes.
+    pub size: usize,
+}
+
+#[derive(Debug)]
+struct BlockCache {
+    /// The maximum total size of the cached blocks.
+    capacity: usize,
+
+    /// The cached blocks along with the tick they were last used at.
+    blocks: HashMap<Cid, (Bytes, u64)>,
+
+    /// The cached blocks by the tick they were last used at, least recently used first.
+    recency: BTreeMap<u64, Cid>,
+
+    /// Incremented on every use of a block.
+    tick: u64,
+
+    /// Statistics about the reads.
+    stats: BlockCacheStats,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl<S> CachedStore<S>
+where
+    S: IpldStore,
+{
+    /// Creates a store caching the raw blocks read from `inner` with the
+    /// [default capacity][DEFAULT_BLOCK_CACHE_CAPACITY].
+    pub fn new(inner: S) -> Self {
+        Self::with_capacity(inner, DEFAULT_BLOCK_CACHE_CAPACITY)
+    }
+
+    /// Creates a store caching up to `capacity` bytes of the raw blocks read from `inner`.
+    pub fn with_capacity(inner: S, capacity: usize) -> Self {
+        Self {
+            inner,
+            cache: Arc::new(Mutex::new(BlockCache {
+                capacity,
+                blocks: HashMap::new(),
+                recency: BTreeMap::new(),
+                tick: 0,
+                stats: BlockCacheStats::default(),
+            })),
+        }
+    }
+
+    /// Returns the underlying store.
+    pub fn get_inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns statistics about the reads of raw blocks from the store.
+    pub fn get_stats(&self) -> BlockCacheStats {
+        self.cache.lock().unwrap().stats
+    }
+
+    /// Returns the raw block with the given [`Cid`] from the cache, or reads it from the underlying
+    /// store and caches it.
+    async fn get_cached_raw_block(&self, cid: &Cid) -> StoreResult<Bytes> {
+        if let Some(bytes) = self.cache.lock().unwrap().get(cid) {
+            return Ok(bytes);
+        }
+
+        let bytes = self.inner.get_raw_block(cid).await?;
+        if SKIP_CACHE.try_with(|_| ()).is_err() {
+            self.cache.lock().unwrap().insert(*cid, bytes.clone());
+        }
+
+        Ok(bytes)
+    }
+}
+
+impl BlockCache {
+    fn get(&mut self, cid: &Cid) -> Option<Bytes> {
+        self.tick += 1;
+        let tick = self.tick;
+
+        let Some((bytes, used_at)) = self.blocks.get_mut(cid) else {
+            self.stats.misses += 1;
+            return None;
+        };
+
+        self.recency.remove(used_at);
+        self.recency.insert(tick, *cid);
+        *used_at = tick;
+        self.stats.hits += 1;
+
+        Some(bytes.clone())
+    }
+
+    fn insert(&mut self, cid: Cid, bytes: Bytes) {
+        if bytes.len() > self.capacity || self.blocks.contains_key(&cid) {
+            return;
+        }
+
+        while self.stats.size + bytes.len() > self.capacity {
+            let Some((_, evicted)) = self.recency.pop_first() else {
+                break;
+            };
+
+            if let Some((bytes, _)) = self.blocks.remove(&evicted) {
+                self.stats.size -= bytes.len();
+                self.stats.blocks -= 1;
+            }
+        }
+
+        self.tick += 1;
+        self.stats.size += bytes.len();
+        self.stats.blocks += 1;
+        self.recency.insert(self.tick, cid);
+        self.blocks.insert(cid, (bytes, self.tick));
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Runs `future` without adding the blocks it reads from a [`CachedStore`] to its cache.
+pub async fn without_caching<F>(future: F) -> F::Output
+where
+    F: Future,
+{
+    SKIP_CACHE.scope((), future).await
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl<S> IpldStore for CachedStore<S>
+where
+    S: IpldStore + Sync,
+{
+    async fn put_node<T>(&self, data: &T) -> StoreResult<Cid>
+    where
+        T: Serialize + IpldReferences + Sync,
+    {
+        self.inner.put_node(data).await
+    }
+
+    async fn put_bytes<'a>(
+        &'a self,
+        reader: impl AsyncRead + Send + Sync + 'a,
+    ) -> StoreResult<Cid> {
+        self.inner.put_bytes(reader).await
+    }
+
+    async fn put_raw_block(&self, bytes: impl Into<Bytes> + Send) -> StoreResult<Cid> {
+        self.inner.put_raw_block(bytes).await
+    }
+
+    async fn get_node<T>(&self, cid: &Cid) -> StoreResult<T>
+    where
+        T: DeserializeOwned + Send,
+    {
+        self.inner.get_node(cid).await
+    }
+
+    async fn get_bytes<'a>(
+        &'a self,
+        cid: &'a Cid,
+    ) -> StoreResult<Pin<Box<dyn AsyncRead + Send + Sync + 'a>>> {
+        if cid.codec() == RAW_CODEC_CODE {
+            let bytes = self.get_cached_raw_block(cid).await?;
+            return Ok(Box::pin(Cursor::new(bytes)));
+        }
+
+        self.inner.get_bytes(cid).await
+    }
+
+    async fn get_raw_block(&self, cid: &Cid) -> StoreResult<Bytes> {
+        self.get_cached_raw_block(cid).await
+    }
+
+    #[inline]
+    async fn has(&self, cid: &Cid) -> bool {
+        self.cache.lock().unwrap().blocks.contains_key(cid) || self.inner.has(cid).await
+    }
+
+    fn get_supported_codecs(&self) -> HashSet<Codec> {
+        self.inner.get_supported_codecs()
+    }
+
+    #[inline]
+    fn get_node_block_max_size(&self) -> Option<u64> {
+        self.inner.get_node_block_max_size()
+    }
+
+    #[inline]
+    fn get_raw_block_max_size(&self) -> Option<u64> {
+        self.inner.get_raw_block_max_size()
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use futures::TryStreamExt;
+    use zeroutils_store::MemoryStore;
+
+    use crate::filesystem::{AccessPattern, Chunker, FastCdc, File};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_cached_store_evicts_least_recently_used() -> anyhow::Result<()> {
+        let store = CachedStore::with_capacity(MemoryStore::default(), 8);
+        let a = store.put_raw_block(&b"aaaa"[..]).await?;
+        let b = store.put_raw_block(&b"bbbb"[..]).await?;
+        let c = store.put_raw_block(&b"cccc"[..]).await?;
+
+        let first = store.get_raw_block(&a).await?;
+        let again = store.get_raw_block(&a).await?;
+        assert_eq!(first.as_ptr(), again.as_ptr());
+
+        store.get_raw_block(&b).await?;
+        store.get_raw_block(&a).await?;
+
+        // `b` is the least recently used block, so it makes room for `c`.
+        store.get_raw_block(&c).await?;
+        let stats = store.get_stats();
+        assert_eq!(stats.blocks, 2);
+        assert_eq!(stats.size, 8);
+
+        store.get_raw_block(&a).await?;
+        store.get_raw_block(&b).await?;
+        let stats = store.get_stats();
+        assert_eq!((stats.hits, stats.misses), (3, 4));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_content_stream_shares_cached_blocks() -> anyhow::Result<()> {
+        let store = CachedStore::new(MemoryStore::default());
+        let data = (0..100_000u32)
+            .map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8)
+            .collect::<Vec<_>>();
+        let chunker = Chunker::FastCdc(
+            FastCdc::builder()
+                .min_size(1024)
+                .avg_size(4096)
+                .max_size(16 * 1024)
+                .build()?,
+        );
+
+        let mut file = File::new(store.clone());
+        file.put_content(&data[..], &chunker).await?;
+
+        let first = file.get_content_stream().try_collect::<Vec<_>>().await?;
+        let second = file.get_content_stream().try_collect::<Vec<_>>().await?;
+        assert_eq!(first.concat(), data);
+        assert!(first.len() > 1);
+        assert!(first
+            .iter()
+            .zip(&second)
+            .all(|(a, b)| a.as_ptr() == b.as_ptr()));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_access_patterns_drive_caching() -> anyhow::Result<()> {
+        let store = CachedStore::new(MemoryStore::default());
+        let data = (0..100_000u32)
+            .map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8)
+            .collect::<Vec<_>>();
+        let chunker = Chunker::FastCdc(
+            FastCdc::builder()
+                .min_size(1024)
+                .avg_size(4096)
+                .max_size(16 * 1024)
+                .build()?,
+        );
+
+        let mut file = File::new(store.clone());
+        file.put_content(&data[..], &chunker).await?;
+
+        let random = file
+            .get_content_stream_with(AccessPattern::Random)
+            .try_collect::<Vec<_>>()
+            .await?;
+        assert_eq!(random.concat(), data);
+        assert_eq!(store.get_stats().blocks, 0);
+
+        // Only the chunks holding the range are cached.
+        file.will_need(50_000, 10).await?;
+        let blocks = store.get_stats().blocks;
+        assert!((1..=2).contains(&blocks));
+
+        let sequential = file
+            .get_content_stream_with(AccessPattern::Sequential)
+            .try_collect::<Vec<_>>()
+            .await?;
+        assert_eq!(sequential.concat(), data);
+        assert_eq!(store.get_stats().blocks, sequential.len());
+
+        Ok(())
+    }
+}
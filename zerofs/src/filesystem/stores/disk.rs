@@ -0,0 +1,1801 @@
+use std::{
+    collections::HashSet,
+    fmt::Debug,
+    io::{Cursor, ErrorKind, Read},
+    path::{Path, PathBuf},
+    pin::Pin,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, Weak,
+    },
+    time::Duration,
+};
+
+use bytes::Bytes;
+use futures::future::BoxFuture;
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::{
+    fs,
+    io::{AsyncRead, AsyncReadExt, AsyncWriteExt},
+    sync::{watch, RwLock},
+};
+use typed_builder::TypedBuilder;
+use zeroutils_store::{ipld::cid::Cid, Codec, IpldReferences, IpldStore, StoreError, StoreResult};
+
+use crate::{
+    config::{HashFunction, NodeCodec, ZerofsStoreConfig, RAW_CODEC_CODE},
+    filesystem::{
+        block_matches, make_cid, ContentChunks, ContentPiecesReader, DEFAULT_FASTCDC_MAX_SIZE,
+        FASTCDC_MAX_SIZE_BOUNDS,
+    },
+};
+
+#[cfg(all(target_os = "linux", feature = "uring"))]
+use super::uring::UringDriver;
+
+use super::ExistenceFilter;
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The number of shard directories blocks are spread across.
+pub const DISK_STORE_SHARD_COUNT: usize = 256;
+
+/// The directory under the base directory where the shard directories live.
+const BLOCKS_DIR: &str = "blocks";
+
+/// The extension of blocks that are still being written.
+const TEMP_EXTENSION: &str = "tmp";
+
+/// The default fraction of a volume's capacity above which new blocks are written to other volumes.
+pub const DEFAULT_VOLUME_HIGH_WATERMARK: f64 = 0.9;
+
+/// The default fraction of the space of a disk in use above which a [`DiskStore`] warns that it is
+/// filling up.
+pub const DEFAULT_DISK_SPACE_WARN_WATERMARK: f64 = 0.8;
+
+/// The default fraction of the space of a disk in use above which a [`DiskStore`] refuses new
+/// blocks.
+pub const DEFAULT_DISK_SPACE_HIGH_WATERMARK: f64 = 0.95;
+
+/// The default interval at which a [`DiskStore`] checks the free space of its disks.
+pub const DEFAULT_DISK_SPACE_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The default maximum size in bytes of a raw block, which is the size of the largest default
+/// FastCDC chunk, so that chunks of files split by a
+/// [`Chunker::FastCdc`][crate::filesystem::Chunker::FastCdc] are kept whole.
+pub const DEFAULT_DISK_RAW_BLOCK_MAX_SIZE: usize = DEFAULT_FASTCDC_MAX_SIZE;
+
+/// The default maximum size in bytes of a block, which fits the largest chunk a
+/// [`Chunker::FastCdc`][crate::filesystem::Chunker::FastCdc] can be set to produce.
+pub const DEFAULT_DISK_MAX_BLOCK_SIZE: usize = *FASTCDC_MAX_SIZE_BOUNDS.end();
+
+/// The default zstd compression level.
+pub const DEFAULT_COMPRESSION_LEVEL: i32 = 3;
+
+/// The default size in bytes above which blocks are compressed.
+pub const DEFAULT_COMPRESSION_THRESHOLD: usize = 1024;
+
+/// The header byte of blocks stored as is.
+const UNCOMPRESSED_HEADER: u8 = 0;
+
+/// The header byte of blocks stored zstd-compressed.
+const ZSTD_HEADER: u8 = 1;
+
+/// The default number of entries in the submission queue of an io_uring backend.
+pub const DEFAULT_URING_ENTRIES: u32 = 256;
+
+/// The default number of buffers an io_uring backend registers with the kernel.
+pub const DEFAULT_URING_BUFFER_COUNT: usize = 64;
+
+/// The default size of the buffers an io_uring backend registers, big enough for a block of the
+/// largest default FastCDC chunk along with its header byte.
+pub const DEFAULT_URING_BUFFER_SIZE: usize = DEFAULT_FASTCDC_MAX_SIZE + 1;
+
+//--------------------------------------------------------------------------------------------------
+// Traits
+//--------------------------------------------------------------------------------------------------
+
+/// Somewhere blocks can be fetched from by CID, like the peers of a cluster, that a [`DiskStore`]
+/// repairs its corrupt blocks from.
+///
+/// Every [`IpldStore`] is one.
+pub trait BlockSource: Debug + Send + Sync {
+    /// Fetches the block of `cid`.
+    fn fetch_block<'a>(&'a self, cid: &'a Cid) -> BoxFuture<'a, StoreResult<Bytes>>;
+}
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Determines when written blocks are flushed to disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SyncMode {
+    /// Every block is flushed to disk before its write returns.
+    #[default]
+    EveryWrite,
+
+    /// The content of every block is flushed to disk before its write returns, and the directory
+    /// entries naming the blocks are flushed at the given interval and on [`DiskStore::sync`].
+    ///
+    /// A crash can lose the blocks written since the last sync, but never leaves a block with
+    /// incomplete content.
+    Periodic(Duration),
+}
+
+/// Configuration for a [`DiskStore`].
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct DiskStoreConfig {
+    /// The hash function the CIDs of nodes and content put through [`IpldStore`] are created with.
+    #[builder(default)]
+    pub hash_function: HashFunction,
+
+    /// The codec nodes put through [`IpldStore`] are encoded with. Nodes are decoded with the codec
+    /// of their CID, so nodes stored with either codec can be read.
+    #[builder(default)]
+    pub codec: NodeCodec,
+
+    /// The maximum size in bytes of a raw block put through [`IpldStore`]. Larger content is
+    /// split into raw blocks of this size listed in a chunks node.
+    #[builder(default = DEFAULT_DISK_RAW_BLOCK_MAX_SIZE)]
+    pub raw_block_max_size: usize,
+
+    /// The maximum size in bytes of any block, before compression. Larger blocks are refused, and
+    /// a compressed block is never decompressed past it.
+    #[builder(default = DEFAULT_DISK_MAX_BLOCK_SIZE)]
+    pub max_block_size: usize,
+
+    /// When written blocks are flushed to disk.
+    #[builder(default)]
+    pub sync_mode: SyncMode,
+
+    /// How blocks are compressed. `None` stores all blocks as is.
+    #[builder(default, setter(strip_option))]
+    pub compression: Option<CompressionConfig>,
+
+    /// How block files are read and written.
+    #[builder(default)]
+    pub io_backend: IoBackend,
+
+    /// Whether every block read is hashed again and checked against its CID before it is returned.
+    ///
+    /// Blocks that do not match are counted in [`DiskStoreStats::corrupt_blocks`] and replaced
+    /// with a good copy from `repair_source` if there is one, or fail to read otherwise. Blocks
+    /// hashed with a function other than those of [`HashFunction`] cannot be checked and are read as
+    /// is.
+    #[builder(default)]
+    pub verify_on_read: bool,
+
+    /// Where corrupt blocks found by `verify_on_read` are fetched again from, typically the peers
+    /// of the cluster when the node is part of one.
+    #[builder(default, setter(strip_option))]
+    pub repair_source: Option<Arc<dyn BlockSource>>,
+
+    /// How the free space of the disks the volumes are on is monitored. `None` leaves it
+    /// unmonitored, so writes only fail once a disk is actually full.
+    #[builder(default, setter(strip_option))]
+    pub disk_space: Option<DiskSpaceConfig>,
+}
+
+/// Configuration for monitoring the free space of the disks of a [`DiskStore`].
+///
+/// The watermarks are fractions of the whole space of a disk, counting what other programs use.
+#[derive(Debug, Clone, PartialEq, TypedBuilder)]
+pub struct DiskSpaceConfig {
+    /// The fraction in use above which the store warns that a disk is filling up.
+    #[builder(default = DEFAULT_DISK_SPACE_WARN_WATERMARK)]
+    pub warn_watermark: f64,
+
+    /// The fraction in use above which no new blocks are written to a disk.
+    #[builder(default = DEFAULT_DISK_SPACE_HIGH_WATERMARK)]
+    pub high_watermark: f64,
+
+    /// The interval at which the free space is checked.
+    #[builder(default = DEFAULT_DISK_SPACE_CHECK_INTERVAL)]
+    pub check_interval: Duration,
+}
+
+/// How close a disk, or a whole [`DiskStore`], is to running out of space.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum DiskSpaceLevel {
+    /// Below the warn watermark.
+    #[default]
+    Ok,
+
+    /// Above the warn watermark, but still taking new blocks.
+    Low,
+
+    /// Above the high watermark, refusing new blocks.
+    Full,
+}
+
+/// The free space of the disk a [`DiskVolume`] is on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiskVolumeSpace {
+    /// The directory of the volume.
+    pub path: PathBuf,
+
+    /// The size in bytes of the disk.
+    pub total: u64,
+
+    /// The size in bytes of the space left on the disk.
+    pub available: u64,
+
+    /// How close the disk is to running out of space.
+    pub level: DiskSpaceLevel,
+}
+
+/// The free space of the disks of a [`DiskStore`], as of the last check.
+///
+/// The store is as full as its emptiest disk, since blocks that do not fit on one volume are
+/// written to another.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct DiskSpaceStatus {
+    /// How close the store is to running out of space.
+    pub level: DiskSpaceLevel,
+
+    /// The free space of the disk of each volume, empty until the first check.
+    pub volumes: Vec<DiskVolumeSpace>,
+}
+
+/// Determines how a [`DiskStore`] reads and writes its block files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IoBackend {
+    /// Block files are read and written through tokio's blocking thread pool.
+    Standard,
+
+    /// Block files are read and written through io_uring, with block-sized buffers registered with
+    /// the kernel.
+    ///
+    /// This needs Linux and the `uring` feature. Elsewhere, or if the kernel refuses to set up the
+    /// ring, the store falls back to [`IoBackend::Standard`]. It is the default when available.
+    Uring(UringConfig),
+}
+
+/// Configuration for the io_uring backend of a [`DiskStore`].
+#[derive(Debug, Clone, PartialEq, Eq, TypedBuilder)]
+pub struct UringConfig {
+    /// The number of entries in the submission queue, which is also the number of block reads and
+    /// writes in flight at once.
+    #[builder(default = DEFAULT_URING_ENTRIES)]
+    pub entries: u32,
+
+    /// The number of buffers registered with the kernel.
+    #[builder(default = DEFAULT_URING_BUFFER_COUNT)]
+    pub buffer_count: usize,
+
+    /// The size in bytes of each registered buffer. Block files that do not fit, and reads and
+    /// writes made while all buffers are taken, go through regular buffers instead.
+    #[builder(default = DEFAULT_URING_BUFFER_SIZE)]
+    pub buffer_size: usize,
+}
+
+/// A directory a [`DiskStore`] keeps blocks in, typically on a drive of its own.
+#[derive(Debug, Clone, PartialEq, TypedBuilder)]
+pub struct DiskVolume {
+    /// The directory the blocks are stored under.
+    #[builder(setter(into))]
+    pub path: PathBuf,
+
+    /// The size in bytes of the blocks the volume may hold. `None` leaves it unbounded.
+    #[builder(default, setter(strip_option))]
+    pub capacity: Option<u64>,
+
+    /// The fraction of the capacity above which new blocks are written to other volumes instead.
+    #[builder(default = DEFAULT_VOLUME_HIGH_WATERMARK)]
+    pub high_watermark: f64,
+}
+
+/// How much of a [`DiskVolume`] is in use.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiskVolumeUsage {
+    /// The directory of the volume.
+    pub path: PathBuf,
+
+    /// The size in bytes of the blocks on the volume, as stored on disk.
+    pub used: u64,
+
+    /// The capacity of the volume, if it has one.
+    pub capacity: Option<u64>,
+}
+
+/// The outcome of [rebalancing][DiskStore::rebalance] a [`DiskStore`] across its volumes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DiskStoreRebalance {
+    /// The number of blocks moved to another volume.
+    pub moved: u64,
+
+    /// The size in bytes of the blocks moved, as stored on disk.
+    pub bytes_moved: u64,
+}
+
+/// Configuration for compressing the blocks of a [`DiskStore`] with zstd.
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct CompressionConfig {
+    /// The zstd compression level.
+    #[builder(default = DEFAULT_COMPRESSION_LEVEL)]
+    pub level: i32,
+
+    /// The size in bytes above which blocks are compressed.
+    #[builder(default = DEFAULT_COMPRESSION_THRESHOLD)]
+    pub threshold: usize,
+
+    /// The multicodec codes of blocks that are never compressed, like raw blocks of media that is
+    /// compressed already.
+    #[builder(default)]
+    pub skip_codecs: HashSet<u64>,
+}
+
+/// Statistics about the blocks written to and read from a [`DiskStore`] since it was created.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DiskStoreStats {
+    /// The number of blocks written.
+    pub blocks_written: u64,
+
+    /// The number of blocks written compressed.
+    pub blocks_compressed: u64,
+
+    /// The size in bytes of the blocks written, before compression.
+    pub bytes_in: u64,
+
+    /// The size in bytes of the blocks written, as stored on disk.
+    pub bytes_stored: u64,
+
+    /// The number of blocks read that did not match their CID, with
+    /// [`verify_on_read`][DiskStoreConfig::verify_on_read] on.
+    pub corrupt_blocks: u64,
+
+    /// The number of corrupt blocks replaced with a good copy from the
+    /// [`repair_source`][DiskStoreConfig::repair_source].
+    pub blocks_repaired: u64,
+
+    /// The number of blocks refused because every volume was over its watermark.
+    pub blocks_refused: u64,
+}
+
+/// A store that keeps its blocks on disk.
+///
+/// Blocks are stored as one file per block, named after the block's [`Cid`] and spread across
+/// [`DISK_STORE_SHARD_COUNT`] shard directories by the last byte of the CID's digest. Each shard
+/// has its own lock, so writes to different shards do not wait on each other.
+///
+/// Each block file starts with a header byte telling whether the rest is stored as is or
+/// zstd-compressed, so compression can be turned on and off without rewriting existing blocks.
+///
+/// Blocks are written to a temporary file first and then renamed, so a block file is either
+/// complete or missing. Temporary files left behind by a crash are removed by
+/// [`recover`][DiskStore::recover].
+///
+/// A store can spread its blocks across several [volumes][DiskVolume], say one per drive. Each
+/// block has its own order of preference over the volumes, derived from its CID with rendezvous
+/// hashing, and is written to the first volume in that order that is below its high watermark.
+/// Adding a volume only changes the preferred volume of the blocks that now prefer the new one,
+/// which [`rebalance`][DiskStore::rebalance] moves over.
+///
+/// With [`disk_space`][DiskStoreConfig::disk_space] set, the store checks the free space of its
+/// disks in the background. Volumes whose disk is over the high watermark take no new blocks, and
+/// once every volume is, writes fail while reads keep being served. Changes of level are logged and
+/// sent to the receivers of [`subscribe_disk_space`][DiskStore::subscribe_disk_space], so operators
+/// are warned before a disk fills up.
+///
+/// As an [`IpldStore`], the store encodes nodes as DAG-CBOR and gives them and raw blocks CIDs
+/// hashed with the configured [`HashFunction`].
+/// Content put with [`put_bytes`][IpldStore::put_bytes] is kept as a single raw block up to
+/// [`raw_block_max_size`][DiskStoreConfig::raw_block_max_size], and split into raw blocks listed
+/// in a chunks node otherwise, without ever holding more than two of them in memory. The chunks
+/// node is the layout chunked files use in this crate, so split content does not get the CIDs
+/// other IPFS implementations would give it.
+///
+/// Once [recovered][DiskStore::recover], the store keeps an [`ExistenceFilter`] of its blocks in
+/// memory, so that checking for a block it does not have rarely touches the disk. The filter is
+/// rebuilt from the scan of every recovery, and kept up to date as blocks are written and removed.
+#[derive(Debug, Clone)]
+pub struct DiskStore {
+    inner: Arc<DiskStoreInner>,
+}
+
+#[derive(Debug)]
+struct DiskStoreInner {
+    /// The volumes the blocks are spread across.
+    volumes: Vec<VolumeState>,
+
+    /// The configuration of the store.
+    config: DiskStoreConfig,
+
+    /// One lock per shard directory.
+    shards: Vec<RwLock<()>>,
+
+    /// The shard directories with blocks whose names are not flushed to disk yet in
+    /// [`SyncMode::Periodic`].
+    unsynced: Mutex<HashSet<PathBuf>>,
+
+    /// The number of blocks in the store.
+    block_count: AtomicU64,
+
+    /// Statistics about the blocks written.
+    stats: Mutex<DiskStoreStats>,
+
+    /// The filter of the blocks in the store, once it is recovered. Until then the blocks already
+    /// on disk are unknown, so every check goes to the disk.
+    existence: Mutex<Option<ExistenceFilter>>,
+
+    /// The free space of the disks as of the last check.
+    disk_space: watch::Sender<DiskSpaceStatus>,
+
+    /// The io_uring the block files are read and written through, if the store uses one.
+    #[cfg(all(target_os = "linux", feature = "uring"))]
+    uring: Option<UringDriver>,
+}
+
+#[derive(Debug)]
+struct VolumeState {
+    volume: DiskVolume,
+
+    /// The size in bytes of the blocks on the volume, as stored on disk.
+    used: AtomicU64,
+}
+
+/// The outcome of scanning a [`DiskStore`] on startup.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DiskStoreRecovery {
+    /// The number of blocks found.
+    pub blocks: u64,
+
+    /// The number of incomplete blocks removed.
+    pub removed: u64,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl DiskStore {
+    /// Creates a new `DiskStore` with the given base directory.
+    pub fn new(base_dir: impl Into<PathBuf>) -> Self {
+        Self::with_config(base_dir, DiskStoreConfig::default())
+    }
+
+    /// Creates a new `DiskStore` with the given base directory and configuration.
+    ///
+    /// Nothing is read from or written to disk until the store is used. See [`open`][Self::open]
+    /// for a store that is recovered and kept in sync.
+    pub fn with_config(base_dir: impl Into<PathBuf>, config: DiskStoreConfig) -> Self {
+        let volume = DiskVolume::builder().path(base_dir).build();
+        Self::with_volumes(vec![volume], config)
+    }
+
+    /// Creates a new `DiskStore` spreading its blocks across the given volumes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `volumes` is empty.
+    pub fn with_volumes(volumes: Vec<DiskVolume>, config: DiskStoreConfig) -> Self {
+        assert!(
+            !volumes.is_empty(),
+            "a disk store needs at least one volume"
+        );
+
+        #[cfg(all(target_os = "linux", feature = "uring"))]
+        let uring = match &config.io_backend {
+            IoBackend::Uring(uring_config) => match UringDriver::start(uring_config) {
+                Ok(driver) => Some(driver),
+                Err(e) => {
+                    tracing::warn!(
+                        "Failed to set up io_uring, falling back to standard IO: {}",
+                        e
+                    );
+                    None
+                }
+            },
+            IoBackend::Standard => None,
+        };
+
+        Self {
+            inner: Arc::new(DiskStoreInner {
+                volumes: volumes
+                    .into_iter()
+                    .map(|volume| VolumeState {
+                        volume,
+                        used: AtomicU64::new(0),
+                    })
+                    .collect(),
+                config,
+                shards: (0..DISK_STORE_SHARD_COUNT)
+                    .map(|_| RwLock::new(()))
+                    .collect(),
+                unsynced: Mutex::new(HashSet::new()),
+                block_count: AtomicU64::new(0),
+                stats: Mutex::new(DiskStoreStats::default()),
+                existence: Mutex::new(None),
+                disk_space: watch::Sender::new(DiskSpaceStatus::default()),
+                #[cfg(all(target_os = "linux", feature = "uring"))]
+                uring,
+            }),
+        }
+    }
+
+    /// Opens the `DiskStore` at the given base directory, recovering it from any interrupted
+    /// writes.
+    ///
+    /// In [`SyncMode::Periodic`], a background task flushes the names of written blocks to disk
+    /// until the last clone of the store is dropped.
+    pub async fn open(base_dir: impl Into<PathBuf>, config: DiskStoreConfig) -> StoreResult<Self> {
+        let volume = DiskVolume::builder().path(base_dir).build();
+        Self::open_volumes(vec![volume], config).await
+    }
+
+    /// Opens the `DiskStore` spreading its blocks across the given volumes, recovering them from
+    /// any interrupted writes.
+    ///
+    /// After adding a volume, call [`rebalance`][Self::rebalance] to move the blocks that now
+    /// prefer it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `volumes` is empty.
+    pub async fn open_volumes(
+        volumes: Vec<DiskVolume>,
+        config: DiskStoreConfig,
+    ) -> StoreResult<Self> {
+        let store = Self::with_volumes(volumes, config);
+        store.recover().await?;
+
+        if let SyncMode::Periodic(interval) = store.inner.config.sync_mode {
+            tokio::spawn(sync_periodically(Arc::downgrade(&store.inner), interval));
+        }
+
+        if let Some(disk_space) = &store.inner.config.disk_space {
+            store.check_disk_space().await?;
+            let interval = disk_space.check_interval;
+            tokio::spawn(monitor_disk_space(Arc::downgrade(&store.inner), interval));
+        }
+
+        Ok(store)
+    }
+
+    /// Returns the base directory of the store, the directory of its first volume.
+    pub fn get_base_dir(&self) -> &Path {
+        &self.inner.volumes[0].volume.path
+    }
+
+    /// Returns how much of each volume is in use.
+    pub fn get_volume_usage(&self) -> Vec<DiskVolumeUsage> {
+        self.inner
+            .volumes
+            .iter()
+            .map(|state| DiskVolumeUsage {
+                path: state.volume.path.clone(),
+                used: state.used.load(Ordering::SeqCst),
+                capacity: state.volume.capacity,
+            })
+            .collect()
+    }
+
+    /// Returns the number of blocks in the store.
+    pub fn get_block_count(&self) -> u64 {
+        self.inner.block_count.load(Ordering::SeqCst)
+    }
+
+    /// Returns `true` if block files are read and written through io_uring.
+    pub fn is_using_uring(&self) -> bool {
+        #[cfg(all(target_os = "linux", feature = "uring"))]
+        return self.inner.uring.is_some();
+
+        #[cfg(not(all(target_os = "linux", feature = "uring")))]
+        false
+    }
+
+    /// Returns the free space of the disks as of the last check.
+    pub fn get_disk_space(&self) -> DiskSpaceStatus {
+        self.inner.disk_space.borrow().clone()
+    }
+
+    /// Returns a receiver that sees the free space of the disks after every check that changes the
+    /// level of one of them.
+    pub fn subscribe_disk_space(&self) -> watch::Receiver<DiskSpaceStatus> {
+        self.inner.disk_space.subscribe()
+    }
+
+    /// Checks the free space of the disks the volumes are on against the watermarks of
+    /// [`disk_space`][DiskStoreConfig::disk_space], and returns it. Does nothing if the free space
+    /// is not monitored.
+    pub async fn check_disk_space(&self) -> StoreResult<DiskSpaceStatus> {
+        let Some(config) = &self.inner.config.disk_space else {
+            return Ok(self.get_disk_space());
+        };
+
+        let mut volumes = Vec::with_capacity(self.inner.volumes.len());
+        for state in &self.inner.volumes {
+            let path = state.volume.path.clone();
+            fs::create_dir_all(&path)
+                .await
+                .map_err(StoreError::custom)?;
+
+            let (total, available) = tokio::task::spawn_blocking({
+                let path = path.clone();
+                move || {
+                    Ok::<_, std::io::Error>((
+                        fs2::total_space(&path)?,
+                        fs2::available_space(&path)?,
+                    ))
+                }
+            })
+            .await
+            .map_err(StoreError::custom)?
+            .map_err(StoreError::custom)?;
+
+            let used = 1.0 - available as f64 / total.max(1) as f64;
+            let level = if used > config.high_watermark {
+                DiskSpaceLevel::Full
+            } else if used > config.warn_watermark {
+                DiskSpaceLevel::Low
+            } else {
+                DiskSpaceLevel::Ok
+            };
+
+            volumes.push(DiskVolumeSpace {
+                path,
+                total,
+                available,
+                level,
+            });
+        }
+
+        let status = DiskSpaceStatus {
+            level: volumes
+                .iter()
+                .map(|volume| volume.level)
+                .min()
+                .unwrap_or_default(),
+            volumes,
+        };
+
+        self.inner.disk_space.send_if_modified(|current| {
+            let changed = current.volumes.len() != status.volumes.len()
+                || current
+                    .volumes
+                    .iter()
+                    .zip(&status.volumes)
+                    .any(|(a, b)| a.level != b.level);
+            if changed {
+                log_disk_space(&status);
+            }
+
+            *current = status.clone();
+            changed
+        });
+
+        Ok(status)
+    }
+
+    /// Returns statistics about the blocks written and read since the store was created.
+    pub fn get_stats(&self) -> DiskStoreStats {
+        *self.inner.stats.lock().unwrap()
+    }
+
+    /// Writes `bytes` as a block with the given codec and returns its CID.
+    async fn put(&self, codec: u64, bytes: &[u8]) -> StoreResult<Cid> {
+        let cid = make_cid(codec, self.inner.config.hash_function, bytes)?;
+        self.put_block(&cid, bytes).await?;
+
+        Ok(cid)
+    }
+
+    /// Writes a block to the store. Writing a block that is already stored does nothing.
+    ///
+    /// Fails if every volume is over its high watermark.
+    pub async fn put_block(&self, cid: &Cid, bytes: &[u8]) -> StoreResult<()> {
+        if bytes.len() > self.inner.config.max_block_size {
+            return Err(StoreError::custom(anyhow::anyhow!(
+                "block of {} bytes is larger than the maximum of {} bytes",
+                bytes.len(),
+                self.inner.config.max_block_size
+            )));
+        }
+
+        let _guard = self.inner.shards[shard_index(cid)].write().await;
+
+        if self.find_block(cid).await?.is_some() {
+            return Ok(());
+        }
+
+        let encoded = self.encode_block(cid, bytes)?;
+        let volume = self
+            .volume_order(cid)
+            .into_iter()
+            .find(|&index| self.has_room_for(index, encoded.len() as u64));
+        let Some(volume) = volume else {
+            self.inner.stats.lock().unwrap().blocks_refused += 1;
+            return Err(StoreError::custom(anyhow::anyhow!(
+                "All disk store volumes are over their high watermark"
+            )));
+        };
+
+        let path = self.block_path(volume, cid);
+        let shard_dir = path.parent().expect("block paths have a shard directory");
+        fs::create_dir_all(shard_dir)
+            .await
+            .map_err(StoreError::custom)?;
+
+        let compressed = encoded[0] == ZSTD_HEADER;
+        let stored = encoded.len();
+
+        // The content must be on disk before the block gets its name, or a crash could leave a
+        // block file that looks complete but is not.
+        let temp_path = path.with_extension(TEMP_EXTENSION);
+        self.write_file(&temp_path, encoded, true).await?;
+        fs::rename(&temp_path, &path)
+            .await
+            .map_err(StoreError::custom)?;
+
+        match self.inner.config.sync_mode {
+            SyncMode::EveryWrite => sync_dir(shard_dir).await?,
+            SyncMode::Periodic(_) => {
+                self.inner
+                    .unsynced
+                    .lock()
+                    .unwrap()
+                    .insert(shard_dir.to_owned());
+            }
+        }
+
+        self.inner.block_count.fetch_add(1, Ordering::SeqCst);
+        if let Some(filter) = self.inner.existence.lock().unwrap().as_mut() {
+            filter.insert(cid);
+        }
+
+        self.inner.volumes[volume]
+            .used
+            .fetch_add(stored as u64, Ordering::SeqCst);
+
+        let mut stats = self.inner.stats.lock().unwrap();
+        stats.blocks_written += 1;
+        stats.blocks_compressed += compressed as u64;
+        stats.bytes_in += bytes.len() as u64;
+        stats.bytes_stored += stored as u64;
+
+        Ok(())
+    }
+
+    /// Reads a block from the store.
+    ///
+    /// With [`verify_on_read`][DiskStoreConfig::verify_on_read] on, the block is checked against
+    /// its CID and repaired if it does not match.
+    pub async fn get_block(&self, cid: &Cid) -> StoreResult<Bytes> {
+        let guard = self.inner.shards[shard_index(cid)].read().await;
+        let path = self.find_block(cid).await?.ok_or_else(|| {
+            StoreError::custom(anyhow::anyhow!("Block {} is not in the disk store", cid))
+        })?;
+        let encoded = self.read_file(&path).await?;
+
+        if !self.inner.config.verify_on_read || !is_verifiable(cid) {
+            return decode_block(&encoded, self.inner.config.max_block_size);
+        }
+
+        if let Ok(bytes) = decode_block(&encoded, self.inner.config.max_block_size) {
+            if block_matches(cid, &bytes) {
+                return Ok(bytes);
+            }
+        }
+
+        drop(guard);
+        self.inner.stats.lock().unwrap().corrupt_blocks += 1;
+        tracing::error!("Block {} in the disk store does not match its CID", cid);
+
+        self.repair_block(cid, &path, encoded.len() as u64).await
+    }
+
+    /// Returns `true` if the block is in the store.
+    pub async fn has_block(&self, cid: &Cid) -> bool {
+        let _guard = self.inner.shards[shard_index(cid)].read().await;
+        if !self.may_contain(cid) {
+            return false;
+        }
+
+        matches!(self.find_block(cid).await, Ok(Some(_)))
+    }
+
+    /// Removes a block from the store. Returns `false` if the block was not in the store.
+    pub async fn remove_block(&self, cid: &Cid) -> StoreResult<bool> {
+        let _guard = self.inner.shards[shard_index(cid)].write().await;
+        if !self.may_contain(cid) {
+            return Ok(false);
+        }
+
+        // A block can be on two volumes if a rebalance was interrupted.
+        let mut removed = false;
+        for volume in self.volume_order(cid) {
+            let path = self.block_path(volume, cid);
+            let size = match fs::metadata(&path).await {
+                Ok(metadata) => metadata.len(),
+                Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                Err(e) => return Err(StoreError::custom(e)),
+            };
+
+            fs::remove_file(&path).await.map_err(StoreError::custom)?;
+            self.inner.volumes[volume]
+                .used
+                .fetch_sub(size, Ordering::SeqCst);
+            removed = true;
+        }
+
+        if removed {
+            self.inner.block_count.fetch_sub(1, Ordering::SeqCst);
+            if let Some(filter) = self.inner.existence.lock().unwrap().as_mut() {
+                filter.remove(cid);
+            }
+        }
+
+        Ok(removed)
+    }
+
+    /// Flushes the names of the blocks written since the last sync to disk.
+    pub async fn sync(&self) -> StoreResult<()> {
+        let unsynced = std::mem::take(&mut *self.inner.unsynced.lock().unwrap());
+        for shard_dir in unsynced {
+            match sync_dir(&shard_dir).await {
+                Ok(()) => {}
+                // The directory is only gone if the whole store was removed.
+                Err(e) if !fs::try_exists(&shard_dir).await.unwrap_or(true) => {
+                    tracing::debug!("Skipping sync of removed {}: {}", shard_dir.display(), e)
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Scans the store, removing blocks left incomplete by interrupted writes, counting the rest
+    /// and rebuilding the existence filter from them.
+    pub async fn recover(&self) -> StoreResult<DiskStoreRecovery> {
+        let mut recovery = DiskStoreRecovery::default();
+        let mut cids = HashSet::new();
+        for (index, state) in self.inner.volumes.iter().enumerate() {
+            let (blocks, removed) = self.scan_volume(index, true).await?;
+            recovery.removed += removed;
+
+            let used = blocks.iter().map(|(_, _, size)| size).sum();
+            state.used.store(used, Ordering::SeqCst);
+
+            // A block can be on two volumes if a rebalance was interrupted.
+            cids.extend(blocks.into_iter().map(|(cid, _, _)| cid));
+        }
+
+        recovery.blocks = cids.len() as u64;
+        self.inner
+            .block_count
+            .store(recovery.blocks, Ordering::SeqCst);
+
+        // Leave room for the store to double before the filter gets less selective.
+        let mut filter = ExistenceFilter::with_capacity(cids.len() * 2);
+        for cid in &cids {
+            filter.insert(cid);
+        }
+        *self.inner.existence.lock().unwrap() = Some(filter);
+
+        Ok(recovery)
+    }
+
+    /// Moves every block that is not on the volume it would be written to now over to that volume.
+    ///
+    /// Run this after adding a volume, or after raising the capacity of one, so the blocks spread
+    /// across the volumes the same way new blocks do. Blocks stay readable throughout, and a
+    /// rebalance that is interrupted can just be run again.
+    pub async fn rebalance(&self) -> StoreResult<DiskStoreRebalance> {
+        let mut rebalance = DiskStoreRebalance::default();
+        for source in 0..self.inner.volumes.len() {
+            let (blocks, _) = self.scan_volume(source, true).await?;
+            for (cid, path, size) in blocks {
+                let _guard = self.inner.shards[shard_index(&cid)].write().await;
+
+                let Some(target) = self
+                    .volume_order(&cid)
+                    .into_iter()
+                    .find(|&index| index == source || self.has_room_for(index, size))
+                else {
+                    continue;
+                };
+
+                if target == source {
+                    continue;
+                }
+
+                let target_path = self.block_path(target, &cid);
+                if !fs::try_exists(&target_path)
+                    .await
+                    .map_err(StoreError::custom)?
+                {
+                    let encoded = self.read_file(&path).await?;
+                    let shard_dir = target_path
+                        .parent()
+                        .expect("block paths have a shard directory");
+                    fs::create_dir_all(shard_dir)
+                        .await
+                        .map_err(StoreError::custom)?;
+
+                    // The copy must be on disk before the original goes away.
+                    let temp_path = target_path.with_extension(TEMP_EXTENSION);
+                    self.write_file(&temp_path, encoded, true).await?;
+                    fs::rename(&temp_path, &target_path)
+                        .await
+                        .map_err(StoreError::custom)?;
+                    sync_dir(shard_dir).await?;
+
+                    self.inner.volumes[target]
+                        .used
+                        .fetch_add(size, Ordering::SeqCst);
+                    rebalance.moved += 1;
+                    rebalance.bytes_moved += size;
+                }
+
+                fs::remove_file(&path).await.map_err(StoreError::custom)?;
+                self.inner.volumes[source]
+                    .used
+                    .fetch_sub(size, Ordering::SeqCst);
+            }
+        }
+
+        Ok(rebalance)
+    }
+
+    /// Replaces the corrupt block of `cid`, stored in `size` bytes at `path`, with a good copy
+    /// fetched from the repair source, and returns it.
+    async fn repair_block(&self, cid: &Cid, path: &Path, size: u64) -> StoreResult<Bytes> {
+        let Some(source) = &self.inner.config.repair_source else {
+            return Err(StoreError::custom(anyhow::anyhow!(
+                "Block {} in the disk store is corrupt",
+                cid
+            )));
+        };
+
+        let bytes = source.fetch_block(cid).await?;
+        if !block_matches(cid, &bytes) {
+            return Err(StoreError::custom(anyhow::anyhow!(
+                "Block {} in the disk store is corrupt, and the copy fetched to repair it does not \
+                 match either",
+                cid
+            )));
+        }
+
+        let _guard = self.inner.shards[shard_index(cid)].write().await;
+
+        // The block may have been removed or moved while it was fetched.
+        let volume = self
+            .volume_order(cid)
+            .into_iter()
+            .find(|&volume| self.block_path(volume, cid) == path);
+        let Some(volume) = volume else {
+            return Ok(bytes);
+        };
+        if !fs::try_exists(path).await.map_err(StoreError::custom)? {
+            return Ok(bytes);
+        }
+
+        let encoded = self.encode_block(cid, &bytes)?;
+        let stored = encoded.len() as u64;
+        let temp_path = path.with_extension(TEMP_EXTENSION);
+        self.write_file(&temp_path, encoded, true).await?;
+        fs::rename(&temp_path, path)
+            .await
+            .map_err(StoreError::custom)?;
+        sync_dir(path.parent().expect("block paths have a shard directory")).await?;
+
+        let used = &self.inner.volumes[volume].used;
+        used.fetch_add(stored, Ordering::SeqCst);
+        used.fetch_sub(size, Ordering::SeqCst);
+        self.inner.stats.lock().unwrap().blocks_repaired += 1;
+        tracing::info!("Repaired block {} of the disk store", cid);
+
+        Ok(bytes)
+    }
+
+    /// Lists the CIDs of the blocks in the store, for checking it against a tree with
+    /// [`check_consistency`][crate::filesystem::check_consistency].
+    pub async fn list_blocks(&self) -> StoreResult<HashSet<Cid>> {
+        let mut cids = HashSet::new();
+        for index in 0..self.inner.volumes.len() {
+            let (blocks, _) = self.scan_volume(index, false).await?;
+            cids.extend(blocks.into_iter().map(|(cid, _, _)| cid));
+        }
+
+        Ok(cids)
+    }
+
+    /// Lists the blocks on a volume along with their size on disk, removing the blocks left
+    /// incomplete by interrupted writes if `remove_incomplete` is set, which must only be done while
+    /// no block is being written. Returns the blocks and the number of files removed.
+    async fn scan_volume(
+        &self,
+        volume: usize,
+        remove_incomplete: bool,
+    ) -> StoreResult<(Vec<(Cid, PathBuf, u64)>, u64)> {
+        let blocks_dir = self.inner.volumes[volume].volume.path.join(BLOCKS_DIR);
+        fs::create_dir_all(&blocks_dir)
+            .await
+            .map_err(StoreError::custom)?;
+
+        let mut blocks = Vec::new();
+        let mut removed = 0;
+        let mut shard_dirs = fs::read_dir(&blocks_dir)
+            .await
+            .map_err(StoreError::custom)?;
+        while let Some(shard_dir) = shard_dirs.next_entry().await.map_err(StoreError::custom)? {
+            if !shard_dir
+                .file_type()
+                .await
+                .map_err(StoreError::custom)?
+                .is_dir()
+            {
+                continue;
+            }
+
+            let mut entries = fs::read_dir(shard_dir.path())
+                .await
+                .map_err(StoreError::custom)?;
+            while let Some(entry) = entries.next_entry().await.map_err(StoreError::custom)? {
+                let path = entry.path();
+                if path.extension().is_some_and(|ext| ext == TEMP_EXTENSION) {
+                    if remove_incomplete {
+                        fs::remove_file(&path).await.map_err(StoreError::custom)?;
+                        removed += 1;
+                    }
+                    continue;
+                }
+
+                let cid = path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .and_then(|name| Cid::from_str(name).ok())
+                    .filter(|cid| self.block_path(volume, cid) == path);
+
+                if let Some(cid) = cid {
+                    let size = entry.metadata().await.map_err(StoreError::custom)?.len();
+                    blocks.push((cid, path, size));
+                }
+            }
+        }
+
+        Ok((blocks, removed))
+    }
+
+    /// Prefixes a block with its header byte, compressing it if the configuration asks for it and
+    /// it actually gets smaller.
+    fn encode_block(&self, cid: &Cid, bytes: &[u8]) -> StoreResult<Vec<u8>> {
+        if let Some(compression) = &self.inner.config.compression {
+            if bytes.len() > compression.threshold
+                && !compression.skip_codecs.contains(&cid.codec())
+            {
+                let compressed =
+                    zstd::bulk::compress(bytes, compression.level).map_err(StoreError::custom)?;
+
+                if compressed.len() < bytes.len() {
+                    let mut encoded = Vec::with_capacity(compressed.len() + 1);
+                    encoded.push(ZSTD_HEADER);
+                    encoded.extend_from_slice(&compressed);
+                    return Ok(encoded);
+                }
+            }
+        }
+
+        let mut encoded = Vec::with_capacity(bytes.len() + 1);
+        encoded.push(UNCOMPRESSED_HEADER);
+        encoded.extend_from_slice(bytes);
+        Ok(encoded)
+    }
+
+    /// Reads a whole block file.
+    async fn read_file(&self, path: &Path) -> StoreResult<Vec<u8>> {
+        #[cfg(all(target_os = "linux", feature = "uring"))]
+        if let Some(uring) = &self.inner.uring {
+            return uring
+                .read(path.to_owned())
+                .await
+                .map_err(StoreError::custom);
+        }
+
+        fs::read(path).await.map_err(StoreError::custom)
+    }
+
+    /// Creates a block file holding `bytes`, flushing it to disk if `sync` is set.
+    async fn write_file(&self, path: &Path, bytes: Vec<u8>, sync: bool) -> StoreResult<()> {
+        #[cfg(all(target_os = "linux", feature = "uring"))]
+        if let Some(uring) = &self.inner.uring {
+            return uring
+                .write(path.to_owned(), bytes, sync)
+                .await
+                .map_err(StoreError::custom);
+        }
+
+        let mut file = fs::File::create(path).await.map_err(StoreError::custom)?;
+        file.write_all(&bytes).await.map_err(StoreError::custom)?;
+        if sync {
+            file.sync_all().await.map_err(StoreError::custom)?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the path of the file a block is stored in on the given volume.
+    fn block_path(&self, volume: usize, cid: &Cid) -> PathBuf {
+        self.inner.volumes[volume]
+            .volume
+            .path
+            .join(BLOCKS_DIR)
+            .join(format!("{:02x}", shard_index(cid)))
+            .join(cid.to_string())
+    }
+
+    /// Returns the path of the file a block is stored in, looking through the volumes in the
+    /// block's order of preference.
+    async fn find_block(&self, cid: &Cid) -> StoreResult<Option<PathBuf>> {
+        for volume in self.volume_order(cid) {
+            let path = self.block_path(volume, cid);
+            if fs::try_exists(&path).await.map_err(StoreError::custom)? {
+                return Ok(Some(path));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Returns `true` if `size` more bytes can be written to the volume at `index`, which must be
+    /// below its own high watermark and on a disk below the high watermark of the free space.
+    fn has_room_for(&self, index: usize, size: u64) -> bool {
+        let disk_full = self
+            .inner
+            .disk_space
+            .borrow()
+            .volumes
+            .get(index)
+            .is_some_and(|space| space.level == DiskSpaceLevel::Full);
+
+        !disk_full && self.inner.volumes[index].has_room_for(size)
+    }
+
+    /// Returns `false` if the existence filter rules the block out.
+    fn may_contain(&self, cid: &Cid) -> bool {
+        self.inner
+            .existence
+            .lock()
+            .unwrap()
+            .as_ref()
+            .is_none_or(|filter| filter.may_contain(cid))
+    }
+
+    /// Returns the indices of the volumes in the order a block prefers them.
+    ///
+    /// Each volume gets a score from hashing the block's digest along with the volume's path, and
+    /// the volumes are sorted by decreasing score.
+    fn volume_order(&self, cid: &Cid) -> Vec<usize> {
+        let mut scores = self
+            .inner
+            .volumes
+            .iter()
+            .enumerate()
+            .map(|(index, state)| {
+                let path = state.volume.path.as_os_str().as_encoded_bytes();
+                (fnv1a([cid.hash().digest(), path]), index)
+            })
+            .collect::<Vec<_>>();
+
+        scores.sort_unstable_by(|a, b| b.cmp(a));
+        scores.into_iter().map(|(_, index)| index).collect()
+    }
+}
+
+impl VolumeState {
+    /// Returns `true` if writing `size` more bytes keeps the volume at or below its high
+    /// watermark.
+    fn has_room_for(&self, size: u64) -> bool {
+        let Some(capacity) = self.volume.capacity else {
+            return true;
+        };
+
+        let used = self.used.load(Ordering::SeqCst) + size;
+        used as f64 <= capacity as f64 * self.volume.high_watermark
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods: DiskStoreStats
+//--------------------------------------------------------------------------------------------------
+
+impl DiskStoreStats {
+    /// Returns the size of the blocks written before compression divided by their size on disk.
+    /// Returns `1.0` if nothing was written.
+    pub fn compression_ratio(&self) -> f64 {
+        if self.bytes_stored == 0 {
+            return 1.0;
+        }
+
+        self.bytes_in as f64 / self.bytes_stored as f64
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Reads up to `max_size` bytes from `reader`, less only at the end of it.
+async fn read_chunk<R>(reader: Pin<&mut R>, max_size: usize) -> StoreResult<Vec<u8>>
+where
+    R: AsyncRead + ?Sized,
+{
+    let mut chunk = Vec::with_capacity(max_size);
+    reader
+        .take(max_size as u64)
+        .read_to_end(&mut chunk)
+        .await
+        .map_err(StoreError::custom)?;
+
+    Ok(chunk)
+}
+
+/// Strips the header byte of a stored block, decompressing it if needed.
+///
+/// A compressed block that would decompress to more than `max_size` bytes fails, so a corrupt or
+/// crafted block cannot exhaust memory.
+fn decode_block(encoded: &[u8], max_size: usize) -> StoreResult<Bytes> {
+    match encoded.split_first() {
+        Some((&UNCOMPRESSED_HEADER, bytes)) => Ok(Bytes::copy_from_slice(bytes)),
+        Some((&ZSTD_HEADER, compressed)) => {
+            let mut decoded = Vec::new();
+            zstd::stream::read::Decoder::with_buffer(compressed)
+                .map_err(StoreError::custom)?
+                .take(max_size as u64 + 1)
+                .read_to_end(&mut decoded)
+                .map_err(StoreError::custom)?;
+            if decoded.len() > max_size {
+                return Err(StoreError::custom(anyhow::anyhow!(
+                    "Compressed block in disk store decompresses to more than {} bytes",
+                    max_size
+                )));
+            }
+
+            Ok(Bytes::from(decoded))
+        }
+        _ => Err(StoreError::custom(anyhow::anyhow!(
+            "Invalid block header in disk store"
+        ))),
+    }
+}
+
+/// Logs the free space of the disks after the level of one of them changed.
+fn log_disk_space(status: &DiskSpaceStatus) {
+    for volume in &status.volumes {
+        let used = 100.0 * (1.0 - volume.available as f64 / volume.total.max(1) as f64);
+        match volume.level {
+            DiskSpaceLevel::Full => tracing::error!(
+                "Disk of volume {} is {:.1}% full, refusing new blocks",
+                volume.path.display(),
+                used
+            ),
+            DiskSpaceLevel::Low => tracing::warn!(
+                "Disk of volume {} is {:.1}% full",
+                volume.path.display(),
+                used
+            ),
+            DiskSpaceLevel::Ok => {}
+        }
+    }
+
+    if status.level == DiskSpaceLevel::Full {
+        tracing::error!("Every disk store volume is full, new blocks are refused");
+    }
+}
+
+/// Returns `true` if the block of `cid` can be checked against it, which needs its hash function
+/// to be one of [`HashFunction`].
+fn is_verifiable(cid: &Cid) -> bool {
+    let code = cid.hash().code();
+    code == HashFunction::Blake3.code() || code == HashFunction::Sha2_256.code()
+}
+
+/// Hashes the concatenation of the given byte strings with 64-bit FNV-1a, which is stable across
+/// platforms and releases, unlike the hashers of the standard library.
+fn fnv1a<'a>(parts: impl IntoIterator<Item = &'a [u8]>) -> u64 {
+    parts
+        .into_iter()
+        .flatten()
+        .fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+            (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+        })
+}
+
+/// Returns the shard a block belongs to, based on the last byte of its digest.
+fn shard_index(cid: &Cid) -> usize {
+    cid.hash().digest().last().copied().unwrap_or_default() as usize % DISK_STORE_SHARD_COUNT
+}
+
+/// Flushes a directory to disk so renames and new entries in it survive a crash.
+async fn sync_dir(dir: &Path) -> StoreResult<()> {
+    fs::File::open(dir)
+        .await
+        .map_err(StoreError::custom)?
+        .sync_all()
+        .await
+        .map_err(StoreError::custom)
+}
+
+/// Syncs the store at the given interval until it is dropped.
+async fn sync_periodically(inner: Weak<DiskStoreInner>, interval: Duration) {
+    let mut interval = tokio::time::interval(interval);
+    loop {
+        interval.tick().await;
+
+        let store = match inner.upgrade() {
+            Some(inner) => DiskStore { inner },
+            None => break,
+        };
+
+        if let Err(e) = store.sync().await {
+            tracing::error!("Failed to sync disk store: {}", e);
+        }
+    }
+}
+
+/// Checks the free space of the disks of the store at the given interval until it is dropped.
+async fn monitor_disk_space(inner: Weak<DiskStoreInner>, interval: Duration) {
+    let mut interval = tokio::time::interval(interval);
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+
+        let store = match inner.upgrade() {
+            Some(inner) => DiskStore { inner },
+            None => break,
+        };
+
+        if let Err(e) = store.check_disk_space().await {
+            tracing::error!(
+                "Failed to check the free space of disk store volumes: {}",
+                e
+            );
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl<S> BlockSource for S
+where
+    S: IpldStore + Debug + Send + Sync,
+{
+    fn fetch_block<'a>(&'a self, cid: &'a Cid) -> BoxFuture<'a, StoreResult<Bytes>> {
+        Box::pin(self.get_raw_block(cid))
+    }
+}
+
+impl IpldStore for DiskStore {
+    async fn put_node<T>(&self, data: &T) -> StoreResult<Cid>
+    where
+        T: Serialize + IpldReferences + Sync,
+    {
+        let codec = self.inner.config.codec;
+        let bytes = match codec {
+            NodeCodec::DagCbor => serde_ipld_dagcbor::to_vec(data).map_err(StoreError::custom)?,
+            NodeCodec::DagJson => serde_ipld_dagjson::to_vec(data).map_err(StoreError::custom)?,
+        };
+
+        self.put(codec.code(), &bytes).await
+    }
+
+    async fn put_bytes<'a>(
+        &'a self,
+        reader: impl AsyncRead + Send + Sync + 'a,
+    ) -> StoreResult<Cid> {
+        let max_size = self.inner.config.raw_block_max_size.max(1);
+        let mut reader = Box::pin(reader);
+
+        // A full chunk is only known to be the whole content once the next read comes back empty.
+        let mut chunks = ContentChunks::default();
+        let mut split = false;
+        let mut chunk = read_chunk(reader.as_mut(), max_size).await?;
+        loop {
+            let next = if chunk.len() == max_size {
+                read_chunk(reader.as_mut(), max_size).await?
+            } else {
+                Vec::new()
+            };
+
+            if !split && next.is_empty() {
+                return self.put(RAW_CODEC_CODE, &chunk).await;
+            }
+
+            split = true;
+            let cid = self.put(RAW_CODEC_CODE, &chunk).await?;
+            chunks.push_chunk(cid, chunk.len() as u64);
+
+            if next.is_empty() {
+                break;
+            }
+
+            chunk = next;
+        }
+
+        self.put_node(&chunks).await
+    }
+
+    async fn put_raw_block(&self, bytes: impl Into<Bytes> + Send) -> StoreResult<Cid> {
+        let bytes = bytes.into();
+        if bytes.len() > self.inner.config.raw_block_max_size {
+            return Err(StoreError::custom(anyhow::anyhow!(
+                "raw block of {} bytes is larger than the maximum of {} bytes",
+                bytes.len(),
+                self.inner.config.raw_block_max_size
+            )));
+        }
+
+        self.put(RAW_CODEC_CODE, &bytes).await
+    }
+
+    async fn get_node<T>(&self, cid: &Cid) -> StoreResult<T>
+    where
+        T: DeserializeOwned + Send,
+    {
+        let bytes = self.get_block(cid).await?;
+        if cid.codec() == NodeCodec::DagJson.code() {
+            return serde_ipld_dagjson::from_slice(&bytes).map_err(StoreError::custom);
+        }
+
+        serde_ipld_dagcbor::from_slice(&bytes).map_err(StoreError::custom)
+    }
+
+    async fn get_bytes<'a>(
+        &'a self,
+        cid: &'a Cid,
+    ) -> StoreResult<Pin<Box<dyn AsyncRead + Send + Sync + 'a>>> {
+        if cid.codec() == RAW_CODEC_CODE {
+            let bytes = self.get_block(cid).await?;
+            return Ok(Box::pin(Cursor::new(bytes)));
+        }
+
+        // Only content split by `put_bytes` has a chunks node here, and its chunks are raw blocks.
+        let node: ContentChunks = self.get_node(cid).await?;
+        let reader = ContentPiecesReader::new(node.pieces(), move |chunk| {
+            Box::pin(async move { self.get_block(&chunk).await })
+        });
+
+        Ok(Box::pin(reader))
+    }
+
+    async fn get_raw_block(&self, cid: &Cid) -> StoreResult<Bytes> {
+        self.get_block(cid).await
+    }
+
+    #[inline]
+    async fn has(&self, cid: &Cid) -> bool {
+        self.has_block(cid).await
+    }
+
+    fn get_supported_codecs(&self) -> HashSet<Codec> {
+        vec![Codec::Raw, Codec::DagCbor, Codec::DagJson]
+            .into_iter()
+            .collect()
+    }
+
+    #[inline]
+    fn get_node_block_max_size(&self) -> Option<u64> {
+        None
+    }
+
+    #[inline]
+    fn get_raw_block_max_size(&self) -> Option<u64> {
+        Some(self.inner.config.raw_block_max_size as u64)
+    }
+}
+
+impl Default for DiskStoreConfig {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+impl From<&ZerofsStoreConfig> for DiskStoreConfig {
+    fn from(config: &ZerofsStoreConfig) -> Self {
+        Self::builder()
+            .hash_function(config.hash)
+            .codec(config.codec)
+            .build()
+    }
+}
+
+impl Default for IoBackend {
+    fn default() -> Self {
+        if cfg!(all(target_os = "linux", feature = "uring")) {
+            IoBackend::Uring(UringConfig::builder().build())
+        } else {
+            IoBackend::Standard
+        }
+    }
+}
+
+impl Default for UringConfig {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use zeroutils_store::{IpldStore, MemoryStore};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_disk_store_put_get_and_recover() -> anyhow::Result<()> {
+        let base_dir = std::env::temp_dir().join(format!("zerofs{}", rand::random::<u64>()));
+        let store = DiskStore::open(&base_dir, DiskStoreConfig::default()).await?;
+
+        let cid: Cid = "bafkr4igxion64jdxhpf7uliks6kh5y3ce6yq2ebcwgsvqr7jfclfxnv73y".parse()?;
+        store.put_block(&cid, b"hello").await?;
+        store.put_block(&cid, b"hello").await?;
+
+        assert!(store.has_block(&cid).await);
+        assert_eq!(store.get_block(&cid).await?, Bytes::from_static(b"hello"));
+        assert_eq!(store.get_block_count(), 1);
+
+        // Simulate a write interrupted by a crash.
+        let temp_path = store.block_path(0, &cid).with_extension(TEMP_EXTENSION);
+        fs::write(&temp_path, b"hel").await?;
+
+        let config = DiskStoreConfig::builder()
+            .sync_mode(SyncMode::Periodic(Duration::from_secs(60)))
+            .build();
+        let store = DiskStore::open(&base_dir, config).await?;
+        assert_eq!(store.get_block_count(), 1);
+        assert!(!fs::try_exists(&temp_path).await?);
+
+        // The existence filter is rebuilt on recovery and follows removals.
+        assert!(store.has_block(&cid).await);
+        assert!(store.remove_block(&cid).await?);
+        assert!(!store.has_block(&cid).await);
+        assert!(!store.remove_block(&cid).await?);
+        assert_eq!(store.get_block_count(), 0);
+
+        fs::remove_dir_all(&base_dir).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_disk_store_ipld_store() -> anyhow::Result<()> {
+        let base_dir = std::env::temp_dir().join(format!("zerofs{}", rand::random::<u64>()));
+        let store_config = ZerofsStoreConfig::builder()
+            .hash(HashFunction::Sha2_256)
+            .build();
+        let config = DiskStoreConfig {
+            raw_block_max_size: 1024,
+            ..DiskStoreConfig::from(&store_config)
+        };
+        let store = DiskStore::open(&base_dir, config).await?;
+
+        let small = store.put_bytes(&b"hello"[..]).await?;
+        assert_eq!(small.codec(), RAW_CODEC_CODE);
+        assert!(store_config.is_consistent_with(&small));
+        assert_eq!(
+            store.get_raw_block(&small).await?,
+            Bytes::from_static(b"hello")
+        );
+
+        // Content of exactly one block stays a single raw block, larger content is split.
+        let full = store.put_bytes(&[7; 1024][..]).await?;
+        assert_eq!(full.codec(), RAW_CODEC_CODE);
+
+        let data = (0..5000u32).map(|i| i as u8).collect::<Vec<_>>();
+        let large = store.put_bytes(&data[..]).await?;
+        assert_eq!(large.codec(), NodeCodec::DagCbor.code());
+
+        let mut read = Vec::new();
+        store
+            .get_bytes(&large)
+            .await?
+            .read_to_end(&mut read)
+            .await?;
+        assert_eq!(read, data);
+        assert!(store.put_raw_block(data).await.is_err());
+
+        let mut chunks = ContentChunks::default();
+        chunks.push_chunk(small, 5);
+        let node = store.put_node(&chunks).await?;
+        assert_eq!(store.get_node::<ContentChunks>(&node).await?, chunks);
+        assert!(store.has(&node).await);
+
+        // Nodes are stored with the configured codec, and the ones stored before still read.
+        let store_config = ZerofsStoreConfig::builder()
+            .hash(HashFunction::Sha2_256)
+            .codec(NodeCodec::DagJson)
+            .build();
+        drop(store);
+        let store = DiskStore::open(&base_dir, DiskStoreConfig::from(&store_config)).await?;
+        let json_node = store.put_node(&chunks).await?;
+        assert_eq!(json_node.codec(), NodeCodec::DagJson.code());
+        assert!(store_config.is_consistent_with(&json_node));
+        assert_eq!(store.get_node::<ContentChunks>(&json_node).await?, chunks);
+        assert_eq!(store.get_node::<ContentChunks>(&node).await?, chunks);
+        assert!(store.get_supported_codecs().contains(&Codec::DagJson));
+
+        fs::remove_dir_all(&base_dir).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_disk_store_compression() -> anyhow::Result<()> {
+        let base_dir = std::env::temp_dir().join(format!("zerofs{}", rand::random::<u64>()));
+        let config = DiskStoreConfig::builder()
+            .compression(CompressionConfig::builder().build())
+            .build();
+        let store = DiskStore::open(&base_dir, config).await?;
+
+        // raw + blake3
+        let text_cid: Cid =
+            "bafkr4igxion64jdxhpf7uliks6kh5y3ce6yq2ebcwgsvqr7jfclfxnv73y".parse()?;
+        // dag-cbor + blake3
+        let small_cid: Cid =
+            "bafyr4icul2stqrqqapx5zdebyjcfggyah5xsnt6m63aaoozshh6635euiy".parse()?;
+
+        let text = "all work and no play makes jack a dull boy ".repeat(100);
+        store.put_block(&text_cid, text.as_bytes()).await?;
+        store.put_block(&small_cid, b"tiny").await?;
+
+        assert_eq!(store.get_block(&text_cid).await?, text.as_bytes());
+        assert_eq!(store.get_block(&small_cid).await?, &b"tiny"[..]);
+
+        let stats = store.get_stats();
+        assert_eq!(stats.blocks_written, 2);
+        assert_eq!(stats.blocks_compressed, 1);
+        assert!(stats.compression_ratio() > 10.0);
+
+        // Blocks past the maximum size are refused, and never decompressed past it.
+        let big = vec![0; store.inner.config.max_block_size + 1];
+        assert!(store.put_block(&text_cid, &big).await.is_err());
+
+        let mut bomb = vec![ZSTD_HEADER];
+        bomb.extend(zstd::bulk::compress(&big, DEFAULT_COMPRESSION_LEVEL)?);
+        assert!(decode_block(&bomb, big.len() - 1).is_err());
+        assert_eq!(decode_block(&bomb, big.len())?.len(), big.len());
+
+        fs::remove_dir_all(&base_dir).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_disk_store_volumes_and_rebalance() -> anyhow::Result<()> {
+        let root = std::env::temp_dir().join(format!("zerofs{}", rand::random::<u64>()));
+        let volume = |name: &str| DiskVolume::builder().path(root.join(name)).build();
+
+        let mut blocks = Vec::new();
+        let memory = MemoryStore::default();
+        for i in 0..32u32 {
+            let bytes = format!("block {i}").into_bytes();
+            blocks.push((memory.put_raw_block(bytes.clone()).await?, bytes));
+        }
+
+        let store = DiskStore::open_volumes(vec![volume("a")], DiskStoreConfig::default()).await?;
+        for (cid, bytes) in blocks.iter() {
+            store.put_block(cid, bytes).await?;
+        }
+
+        // Adding a volume moves over the blocks that prefer it, and only those.
+        let store =
+            DiskStore::open_volumes(vec![volume("a"), volume("b")], DiskStoreConfig::default())
+                .await?;
+        let rebalance = store.rebalance().await?;
+        let preferring_b = blocks
+            .iter()
+            .filter(|(cid, _)| store.volume_order(cid)[0] == 1)
+            .count() as u64;
+        assert!(preferring_b > 0);
+        assert_eq!(rebalance.moved, preferring_b);
+        assert_eq!(store.rebalance().await?, DiskStoreRebalance::default());
+
+        for (cid, bytes) in blocks.iter() {
+            assert_eq!(store.get_block(cid).await?, &bytes[..]);
+        }
+
+        let usage = store.get_volume_usage();
+        assert_eq!(usage[1].used, rebalance.bytes_moved);
+        assert_eq!(store.get_block_count(), blocks.len() as u64);
+
+        // A volume over its high watermark takes no new blocks.
+        let full = DiskVolume::builder()
+            .path(root.join("c"))
+            .capacity(0)
+            .build();
+        let store = DiskStore::open_volumes(vec![full], DiskStoreConfig::default()).await?;
+        assert!(store.put_block(&blocks[0].0, &blocks[0].1).await.is_err());
+
+        fs::remove_dir_all(&root).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_disk_store_verify_on_read() -> anyhow::Result<()> {
+        let base_dir = std::env::temp_dir().join(format!("zerofs{}", rand::random::<u64>()));
+        let peer = MemoryStore::default();
+        let cid = peer.put_raw_block(b"hello".to_vec()).await?;
+
+        let store = DiskStore::open(&base_dir, DiskStoreConfig::default()).await?;
+        store.put_block(&cid, b"hello").await?;
+
+        // Flip a byte of the stored block. Without verification it is read as is.
+        let path = store.block_path(0, &cid);
+        fs::write(&path, [&[UNCOMPRESSED_HEADER][..], b"jello"].concat()).await?;
+        assert_eq!(store.get_block(&cid).await?, &b"jello"[..]);
+
+        let config = DiskStoreConfig::builder().verify_on_read(true).build();
+        let store = DiskStore::open(&base_dir, config).await?;
+        assert!(store.get_block(&cid).await.is_err());
+        assert_eq!(store.get_stats().corrupt_blocks, 1);
+
+        // With a repair source, the block is fetched again and rewritten.
+        let config = DiskStoreConfig::builder()
+            .verify_on_read(true)
+            .repair_source(Arc::new(peer))
+            .build();
+        let store = DiskStore::open(&base_dir, config).await?;
+        assert_eq!(store.get_block(&cid).await?, &b"hello"[..]);
+        assert_eq!(store.get_block(&cid).await?, &b"hello"[..]);
+
+        let stats = store.get_stats();
+        assert_eq!(stats.corrupt_blocks, 1);
+        assert_eq!(stats.blocks_repaired, 1);
+        assert_eq!(fs::read(&path).await?[1..], b"hello"[..]);
+
+        fs::remove_dir_all(&base_dir).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_disk_store_disk_space_watermarks() -> anyhow::Result<()> {
+        let base_dir = std::env::temp_dir().join(format!("zerofs{}", rand::random::<u64>()));
+        let memory = MemoryStore::default();
+        let first = memory.put_raw_block(b"first".to_vec()).await?;
+        let second = memory.put_raw_block(b"second".to_vec()).await?;
+
+        // A disk above the warn watermark still takes blocks.
+        let config = DiskStoreConfig::builder()
+            .disk_space(
+                DiskSpaceConfig::builder()
+                    .warn_watermark(0.0)
+                    .high_watermark(1.0)
+                    .build(),
+            )
+            .build();
+        let store = DiskStore::open(&base_dir, config).await?;
+        assert_eq!(store.get_disk_space().level, DiskSpaceLevel::Low);
+        store.put_block(&first, b"first").await?;
+
+        // Above the high watermark, new blocks are refused but the others are still read.
+        let config = DiskStoreConfig::builder()
+            .disk_space(DiskSpaceConfig::builder().high_watermark(0.0).build())
+            .build();
+        let store = DiskStore::open(&base_dir, config).await?;
+        let status = store.subscribe_disk_space().borrow().clone();
+        assert_eq!(status.level, DiskSpaceLevel::Full);
+        assert_eq!(status.volumes.len(), 1);
+        assert!(status.volumes[0].available <= status.volumes[0].total);
+
+        assert!(store.put_block(&second, b"second").await.is_err());
+        assert_eq!(store.get_stats().blocks_refused, 1);
+        assert_eq!(store.get_block(&first).await?, &b"first"[..]);
+
+        fs::remove_dir_all(&base_dir).await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,470 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io::Cursor,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use bytes::Bytes;
+use futures::future;
+use reed_solomon_erasure::galois_8::ReedSolomon;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use typed_builder::TypedBuilder;
+use zeroutils_store::{ipld::cid::Cid, Codec, IpldReferences, IpldStore, StoreError, StoreResult};
+
+use crate::config::RAW_CODEC_CODE;
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The default number of data shards a block is split into.
+pub const DEFAULT_DATA_SHARDS: usize = 4;
+
+/// The default number of parity shards computed for a block.
+pub const DEFAULT_PARITY_SHARDS: usize = 2;
+
+/// The default size in bytes from which content is erasure-coded.
+pub const DEFAULT_ERASURE_MIN_SIZE: usize = 64 * 1024;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// An [`IpldStore`] that erasure-codes large content across the stores of a set of peers instead
+/// of keeping a full copy of it.
+///
+/// Content put with [`put_bytes`][IpldStore::put_bytes] that is at least
+/// [`min_size`][ErasureConfig::min_size] bytes long is split into `k` data shards, from which `m`
+/// parity shards are computed, and each of the `k + m` shards is stored with a different peer. The
+/// placement of the shards is recorded in a [`ShardManifest`] node in the local store, and the
+/// [`Cid`] of the manifest is what identifies the content. Reading it back fetches the shards from
+/// the peers and reconstructs the content from any `k` of them, so up to `m` peers can be offline.
+///
+/// Everything else, including nodes, raw blocks and smaller content, is kept in the local store.
+/// Chunks of files split by a [`Chunker::FastCdc`][crate::filesystem::Chunker::FastCdc] are put
+/// with `put_bytes` one by one, so the large ones are erasure-coded individually.
+#[derive(Clone)]
+pub struct ErasureStore<S, P>
+where
+    S: IpldStore,
+    P: IpldStore,
+{
+    inner: Arc<ErasureStoreInner<S, P>>,
+}
+
+struct ErasureStoreInner<S, P>
+where
+    S: IpldStore,
+    P: IpldStore,
+{
+    /// The store keeping the manifests along with everything that is not erasure-coded.
+    local: S,
+
+    /// The peers shards are stored with.
+    peers: Vec<ErasurePeer<P>>,
+
+    /// The positions of the peers in `peers` by ID.
+    peer_index: HashMap<String, usize>,
+
+    /// The Reed-Solomon codec for the configured numbers of shards.
+    codec: ReedSolomon,
+
+    /// The configuration of the store.
+    config: ErasureConfig,
+
+    /// The peer the shards of the next block start at, so shards are spread evenly.
+    next_peer: AtomicUsize,
+}
+
+/// A peer of an [`ErasureStore`].
+#[derive(Debug, Clone)]
+pub struct ErasurePeer<P> {
+    /// The ID of the peer, recorded in the manifests of the shards it stores.
+    pub id: String,
+
+    /// The store of the peer.
+    pub store: P,
+}
+
+/// Configuration for an [`ErasureStore`].
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct ErasureConfig {
+    /// The number of data shards content is split into.
+    #[builder(default = DEFAULT_DATA_SHARDS)]
+    pub data_shards: usize,
+
+    /// The number of parity shards computed for content, which is how many shards can be lost.
+    #[builder(default = DEFAULT_PARITY_SHARDS)]
+    pub parity_shards: usize,
+
+    /// The size in bytes from which content is erasure-coded.
+    #[builder(default = DEFAULT_ERASURE_MIN_SIZE)]
+    pub min_size: usize,
+}
+
+/// A node recording where the shards of erasure-coded content are stored.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShardManifest {
+    /// The size of the content in bytes.
+    pub size: u64,
+
+    /// The number of data shards the content was split into.
+    pub data_shards: usize,
+
+    /// The number of parity shards computed for the content.
+    pub parity_shards: usize,
+
+    /// The shards, data shards first, along with the peers they are stored with.
+    pub shards: Vec<ShardPlacement>,
+}
+
+/// Where a shard of erasure-coded content is stored.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShardPlacement {
+    /// The ID of the peer storing the shard.
+    pub peer: String,
+
+    /// The [`Cid`] of the shard in the store of the peer.
+    pub cid: Cid,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl<S, P> ErasureStore<S, P>
+where
+    S: IpldStore,
+    P: IpldStore,
+{
+    /// Creates a store keeping manifests in `local` and erasure-coding content across `peers`.
+    ///
+    /// Fails if there are fewer peers than shards per block, since every shard of a block goes to
+    /// a different peer.
+    pub fn new(local: S, peers: Vec<ErasurePeer<P>>, config: ErasureConfig) -> StoreResult<Self> {
+        let shards = config.data_shards + config.parity_shards;
+        if peers.len() < shards {
+            return Err(StoreError::custom(anyhow::anyhow!(
+                "erasure coding into {shards} shards needs at least {shards} peers, got {}",
+                peers.len()
+            )));
+        }
+
+        let codec = ReedSolomon::new(config.data_shards, config.parity_shards)
+            .map_err(StoreError::custom)?;
+        let peer_index = peers
+            .iter()
+            .enumerate()
+            .map(|(index, peer)| (peer.id.clone(), index))
+            .collect();
+
+        Ok(Self {
+            inner: Arc::new(ErasureStoreInner {
+                local,
+                peers,
+                peer_index,
+                codec,
+                config,
+                next_peer: AtomicUsize::new(0),
+            }),
+        })
+    }
+
+    /// Returns the store keeping the manifests.
+    pub fn get_local(&self) -> &S {
+        &self.inner.local
+    }
+
+    /// Returns the manifest at `cid`, or `None` if the node at `cid` is not a manifest.
+    pub async fn get_manifest(&self, cid: &Cid) -> Option<ShardManifest>
+    where
+        S: Send + Sync,
+    {
+        if cid.codec() == RAW_CODEC_CODE {
+            return None;
+        }
+
+        self.inner.local.get_node(cid).await.ok()
+    }
+
+    /// Splits `bytes` into shards, stores each with a different peer and returns the [`Cid`] of
+    /// the manifest recording where they are.
+    async fn put_shards(&self, bytes: &[u8]) -> StoreResult<Cid>
+    where
+        S: Send + Sync,
+        P: Send + Sync,
+    {
+        let inner = &self.inner;
+        let data_shards = inner.config.data_shards;
+        let shard_size = bytes.len().div_ceil(data_shards);
+
+        let mut shards = bytes
+            .chunks(shard_size)
+            .map(|chunk| {
+                let mut shard = chunk.to_vec();
+                shard.resize(shard_size, 0);
+                shard
+            })
+            .collect::<Vec<_>>();
+        shards.resize(
+            data_shards + inner.config.parity_shards,
+            vec![0; shard_size],
+        );
+        inner
+            .codec
+            .encode(&mut shards)
+            .map_err(StoreError::custom)?;
+
+        let first = inner.next_peer.fetch_add(1, Ordering::Relaxed);
+        let peers = (0..shards.len())
+            .map(|i| &inner.peers[(first + i) % inner.peers.len()])
+            .collect::<Vec<_>>();
+        let cids = future::try_join_all(
+            shards
+                .into_iter()
+                .zip(&peers)
+                .map(|(shard, peer)| peer.store.put_raw_block(shard)),
+        )
+        .await?;
+
+        let manifest = ShardManifest {
+            size: bytes.len() as u64,
+            data_shards,
+            parity_shards: inner.config.parity_shards,
+            shards: peers
+                .iter()
+                .zip(cids)
+                .map(|(peer, cid)| ShardPlacement {
+                    peer: peer.id.clone(),
+                    cid,
+                })
+                .collect(),
+        };
+
+        inner.local.put_node(&manifest).await
+    }
+
+    /// Fetches the shards listed in `manifest` and reconstructs the content from them.
+    ///
+    /// Shards that cannot be fetched, because their peer is offline or unknown, are reconstructed
+    /// from the others as long as no more than the number of parity shards are missing.
+    async fn get_shards(&self, manifest: &ShardManifest) -> StoreResult<Bytes>
+    where
+        P: Send + Sync,
+    {
+        let inner = &self.inner;
+        let mut shards = future::join_all(manifest.shards.iter().map(|placement| async move {
+            let index = *inner.peer_index.get(&placement.peer)?;
+            let shard = inner.peers[index]
+                .store
+                .get_raw_block(&placement.cid)
+                .await
+                .ok()?;
+
+            Some(shard.to_vec())
+        }))
+        .await;
+
+        let available = shards.iter().filter(|shard| shard.is_some()).count();
+        if available < manifest.data_shards {
+            return Err(StoreError::custom(anyhow::anyhow!(
+                "only {available} of the {} shards needed to reconstruct the content are available",
+                manifest.data_shards
+            )));
+        }
+
+        if available < shards.len() {
+            ReedSolomon::new(manifest.data_shards, manifest.parity_shards)
+                .and_then(|codec| codec.reconstruct_data(&mut shards))
+                .map_err(StoreError::custom)?;
+        }
+
+        let mut bytes = shards
+            .into_iter()
+            .take(manifest.data_shards)
+            .flatten()
+            .flatten()
+            .collect::<Vec<_>>();
+        bytes.truncate(manifest.size as usize);
+
+        Ok(bytes.into())
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl<S, P> IpldStore for ErasureStore<S, P>
+where
+    S: IpldStore + Send + Sync,
+    P: IpldStore + Send + Sync,
+{
+    async fn put_node<T>(&self, data: &T) -> StoreResult<Cid>
+    where
+        T: Serialize + IpldReferences + Sync,
+    {
+        self.inner.local.put_node(data).await
+    }
+
+    async fn put_bytes<'a>(
+        &'a self,
+        reader: impl AsyncRead + Send + Sync + 'a,
+    ) -> StoreResult<Cid> {
+        let mut bytes = Vec::new();
+        Box::pin(reader)
+            .read_to_end(&mut bytes)
+            .await
+            .map_err(StoreError::custom)?;
+
+        if bytes.len() < self.inner.config.min_size {
+            return self.inner.local.put_bytes(Cursor::new(bytes)).await;
+        }
+
+        self.put_shards(&bytes).await
+    }
+
+    async fn put_raw_block(&self, bytes: impl Into<Bytes> + Send) -> StoreResult<Cid> {
+        self.inner.local.put_raw_block(bytes).await
+    }
+
+    async fn get_node<T>(&self, cid: &Cid) -> StoreResult<T>
+    where
+        T: DeserializeOwned + Send,
+    {
+        self.inner.local.get_node(cid).await
+    }
+
+    async fn get_bytes<'a>(
+        &'a self,
+        cid: &'a Cid,
+    ) -> StoreResult<Pin<Box<dyn AsyncRead + Send + Sync + 'a>>> {
+        match self.get_manifest(cid).await {
+            Some(manifest) => Ok(Box::pin(Cursor::new(self.get_shards(&manifest).await?))),
+            None => self.inner.local.get_bytes(cid).await,
+        }
+    }
+
+    async fn get_raw_block(&self, cid: &Cid) -> StoreResult<Bytes> {
+        self.inner.local.get_raw_block(cid).await
+    }
+
+    #[inline]
+    async fn has(&self, cid: &Cid) -> bool {
+        self.inner.local.has(cid).await
+    }
+
+    fn get_supported_codecs(&self) -> HashSet<Codec> {
+        self.inner.local.get_supported_codecs()
+    }
+
+    #[inline]
+    fn get_node_block_max_size(&self) -> Option<u64> {
+        self.inner.local.get_node_block_max_size()
+    }
+
+    #[inline]
+    fn get_raw_block_max_size(&self) -> Option<u64> {
+        self.inner.local.get_raw_block_max_size()
+    }
+}
+
+impl IpldReferences for ShardManifest {
+    fn references<'a>(&'a self) -> Box<dyn Iterator<Item = &'a Cid> + Send + 'a> {
+        // The shards are in the stores of the peers, not in the store of the manifest.
+        Box::new(std::iter::empty())
+    }
+}
+
+impl Default for ErasureConfig {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use zeroutils_store::MemoryStore;
+
+    use super::*;
+
+    fn peers(count: usize) -> Vec<ErasurePeer<MemoryStore>> {
+        (0..count)
+            .map(|i| ErasurePeer {
+                id: format!("peer{i}"),
+                store: MemoryStore::default(),
+            })
+            .collect()
+    }
+
+    async fn read_all(
+        store: &ErasureStore<MemoryStore, MemoryStore>,
+        cid: &Cid,
+    ) -> StoreResult<Vec<u8>> {
+        let mut bytes = Vec::new();
+        store
+            .get_bytes(cid)
+            .await?
+            .read_to_end(&mut bytes)
+            .await
+            .map_err(StoreError::custom)?;
+
+        Ok(bytes)
+    }
+
+    #[tokio::test]
+    async fn test_erasure_store_reconstructs_with_offline_peers() -> anyhow::Result<()> {
+        let local = MemoryStore::default();
+        let peers = peers(6);
+        let config = ErasureConfig::builder().min_size(1024).build();
+        let store = ErasureStore::new(local.clone(), peers.clone(), config.clone())?;
+
+        let data = (0..100_001u32)
+            .map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8)
+            .collect::<Vec<_>>();
+        let cid = store.put_bytes(&data[..]).await?;
+        let small = store.put_bytes(&b"hello"[..]).await?;
+
+        let manifest = store.get_manifest(&cid).await.unwrap();
+        assert_eq!(manifest.shards.len(), 6);
+        assert_eq!(
+            manifest
+                .shards
+                .iter()
+                .map(|shard| &shard.peer)
+                .collect::<HashSet<_>>()
+                .len(),
+            6
+        );
+        assert!(store.get_manifest(&small).await.is_none());
+        assert_eq!(read_all(&store, &cid).await?, data);
+        assert_eq!(read_all(&store, &small).await?, b"hello");
+
+        // Two peers going offline is what the parity shards make up for.
+        let mut online = peers.clone();
+        online[1].store = MemoryStore::default();
+        online.remove(4);
+        online.push(ErasurePeer {
+            id: "peer6".to_owned(),
+            store: MemoryStore::default(),
+        });
+        let degraded = ErasureStore::new(local.clone(), online.clone(), config.clone())?;
+        assert_eq!(read_all(&degraded, &cid).await?, data);
+
+        // A third one is too many.
+        online[2].store = MemoryStore::default();
+        let broken = ErasureStore::new(local, online, config)?;
+        assert!(read_all(&broken, &cid).await.is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,134 @@
+use std::convert::TryInto;
+
+use zeroutils_store::ipld::cid::Cid;
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The smallest number of blocks an [`ExistenceFilter`] is sized for.
+pub const MIN_EXISTENCE_FILTER_CAPACITY: usize = 64 * 1024;
+
+/// The number of counters an [`ExistenceFilter`] keeps per block it is sized for, which with
+/// [`EXISTENCE_FILTER_HASHES`] hashes gives about 1% false positives at capacity.
+const COUNTERS_PER_BLOCK: usize = 10;
+
+/// The number of counters each block sets in an [`ExistenceFilter`].
+const EXISTENCE_FILTER_HASHES: u64 = 7;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// A counting bloom filter over the CIDs of the blocks in a store.
+///
+/// It answers most checks for blocks that are not in the store without touching the disk:
+/// [`may_contain`][Self::may_contain] never returns `false` for a block that was inserted and not
+/// removed since, and only rarely returns `true` for one that was not. Each CID bumps a few
+/// byte-sized counters rather than setting bits, so blocks can be removed again. A counter that
+/// saturates stays saturated, which can only make the filter answer `true` more often.
+#[derive(Debug, Clone)]
+pub struct ExistenceFilter {
+    counters: Vec<u8>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl ExistenceFilter {
+    /// Creates an empty filter sized for `capacity` blocks, or [`MIN_EXISTENCE_FILTER_CAPACITY`]
+    /// if that is more. Past its capacity the filter stays correct, but answers `true` for more
+    /// blocks that are not in the store.
+    pub fn with_capacity(capacity: usize) -> Self {
+        let capacity = capacity.max(MIN_EXISTENCE_FILTER_CAPACITY);
+        Self {
+            counters: vec![0; capacity * COUNTERS_PER_BLOCK],
+        }
+    }
+
+    /// Records that the block of `cid` is in the store.
+    pub fn insert(&mut self, cid: &Cid) {
+        for index in self.indices(cid) {
+            let counter = &mut self.counters[index];
+            *counter = counter.saturating_add(1);
+        }
+    }
+
+    /// Records that the block of `cid`, which was inserted, is not in the store anymore.
+    pub fn remove(&mut self, cid: &Cid) {
+        for index in self.indices(cid) {
+            let counter = &mut self.counters[index];
+            if *counter != u8::MAX {
+                *counter = counter.saturating_sub(1);
+            }
+        }
+    }
+
+    /// Returns `false` if the block of `cid` is certainly not in the store.
+    pub fn may_contain(&self, cid: &Cid) -> bool {
+        self.indices(cid).all(|index| self.counters[index] > 0)
+    }
+
+    /// Returns the counters the block of `cid` sets, derived from two hashes of the CID by double
+    /// hashing.
+    fn indices(&self, cid: &Cid) -> impl Iterator<Item = usize> {
+        let hash = blake3::hash(&cid.to_bytes());
+        let bytes = hash.as_bytes();
+        let h1 = u64::from_le_bytes(bytes[..8].try_into().unwrap());
+        let h2 = u64::from_le_bytes(bytes[8..16].try_into().unwrap()) | 1;
+        let len = self.counters.len() as u64;
+
+        (0..EXISTENCE_FILTER_HASHES)
+            .map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % len) as usize)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use zeroutils_store::{IpldStore, MemoryStore};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_existence_filter() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let mut filter = ExistenceFilter::with_capacity(0);
+
+        let mut cids = Vec::new();
+        for i in 0..1000u32 {
+            cids.push(store.put_raw_block(i.to_le_bytes().to_vec()).await?);
+        }
+
+        for cid in &cids[..500] {
+            filter.insert(cid);
+        }
+
+        assert!(cids[..500].iter().all(|cid| filter.may_contain(cid)));
+        let false_positives = cids[500..]
+            .iter()
+            .filter(|cid| filter.may_contain(cid))
+            .count();
+        assert!(false_positives < 10);
+
+        // Removed blocks are not reported anymore, and removing leaves the others alone.
+        for cid in &cids[..250] {
+            filter.remove(cid);
+        }
+
+        assert!(cids[250..500].iter().all(|cid| filter.may_contain(cid)));
+        assert!(
+            cids[..250]
+                .iter()
+                .filter(|cid| filter.may_contain(cid))
+                .count()
+                < 10
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,405 @@
+use std::{collections::HashSet, io::Cursor, pin::Pin, sync::Arc};
+
+use bytes::Bytes;
+use js_sys::{Array, Promise, Uint8Array};
+use send_wrapper::SendWrapper;
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use typed_builder::TypedBuilder;
+use wasm_bindgen::{closure::Closure, JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{
+    IdbDatabase, IdbFactory, IdbObjectStore, IdbRequest, IdbTransaction, IdbTransactionMode,
+};
+use zeroutils_store::{ipld::cid::Cid, Codec, IpldReferences, IpldStore, StoreError, StoreResult};
+
+use crate::{
+    config::{HashFunction, NodeCodec, ZerofsStoreConfig, RAW_CODEC_CODE},
+    filesystem::{make_cid, ContentChunks, ContentPiecesReader, DEFAULT_FASTCDC_MAX_SIZE},
+};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The default maximum size in bytes of a raw block, which is the size of the largest default
+/// FastCDC chunk, so that chunks of files split by a
+/// [`Chunker::FastCdc`][crate::filesystem::Chunker::FastCdc] are kept whole.
+pub const DEFAULT_IDB_RAW_BLOCK_MAX_SIZE: usize = DEFAULT_FASTCDC_MAX_SIZE;
+
+/// The version of the layout of the database.
+const IDB_VERSION: u32 = 1;
+
+/// The object store the blocks are kept in, keyed by the text form of their CID.
+const BLOCKS_STORE: &str = "blocks";
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// An [`IpldStore`] keeping blocks in an IndexedDB database, for browser clients to keep the
+/// tree across page loads and work with it offline.
+///
+/// Nodes are encoded as DAG-CBOR and, like raw blocks, get CIDs hashed with the configured
+/// [`HashFunction`], so they get the same CIDs as in the stores of a node configured alike. Content
+/// put with [`put_bytes`][IpldStore::put_bytes] is kept as a single raw block up to
+/// [`raw_block_max_size`][IdbStoreConfig::raw_block_max_size], and split into raw blocks listed
+/// in a chunks node otherwise.
+///
+/// Blocks written offline reach a node once the client is back online by copying them with
+/// [`migrate_store`][crate::filesystem::migrate_store] from the roots that changed, which skips
+/// the blocks the node already has.
+///
+/// IndexedDB objects cannot leave the thread that made them, so the store can only be used on
+/// the thread that opened it, which is the only one in a browser page.
+#[derive(Debug, Clone)]
+pub struct IdbStore {
+    inner: Arc<IdbStoreInner>,
+}
+
+#[derive(Debug)]
+struct IdbStoreInner {
+    /// The name of the database.
+    name: String,
+
+    /// The open database.
+    db: SendWrapper<IdbDatabase>,
+
+    /// The configuration of the store.
+    config: IdbStoreConfig,
+}
+
+/// Configuration for an [`IdbStore`].
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct IdbStoreConfig {
+    /// The hash function CIDs are created with.
+    #[builder(default)]
+    pub hash_function: HashFunction,
+
+    /// The maximum size in bytes of a raw block.
+    #[builder(default = DEFAULT_IDB_RAW_BLOCK_MAX_SIZE)]
+    pub raw_block_max_size: usize,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl IdbStore {
+    /// Opens the IndexedDB database named `name`, creating it if it does not exist yet.
+    ///
+    /// Works in windows and workers alike. Fails where IndexedDB is not available, like in
+    /// browsers that disable it for private windows.
+    pub async fn open(name: impl Into<String>, config: IdbStoreConfig) -> StoreResult<Self> {
+        let name = name.into();
+        let db = open_database(&name).await?;
+
+        Ok(Self {
+            inner: Arc::new(IdbStoreInner {
+                name,
+                db: SendWrapper::new(db),
+                config,
+            }),
+        })
+    }
+
+    /// Returns the name of the database.
+    pub fn get_name(&self) -> &str {
+        &self.inner.name
+    }
+
+    /// Returns the configuration of the store.
+    pub fn get_config(&self) -> &IdbStoreConfig {
+        &self.inner.config
+    }
+
+    /// Writes a block to the store, replacing it if it is already there.
+    ///
+    /// Returns once the transaction writing it is committed.
+    pub async fn put_block(&self, cid: &Cid, bytes: &[u8]) -> StoreResult<()> {
+        SendWrapper::new(async move {
+            let (transaction, store) = self.open_blocks(IdbTransactionMode::Readwrite)?;
+            store
+                .put_with_key(&Uint8Array::from(bytes), &cid_key(cid))
+                .map_err(js_error)?;
+
+            complete_transaction(&transaction).await
+        })
+        .await
+    }
+
+    /// Reads a block from the store.
+    pub async fn get_block(&self, cid: &Cid) -> StoreResult<Bytes> {
+        SendWrapper::new(async move {
+            let (_, store) = self.open_blocks(IdbTransactionMode::Readonly)?;
+            let value = complete_request(&store.get(&cid_key(cid)).map_err(js_error)?).await?;
+            if value.is_undefined() {
+                return Err(StoreError::custom(anyhow::anyhow!(
+                    "Block {} is not in the IndexedDB store",
+                    cid
+                )));
+            }
+
+            Ok(Bytes::from(Uint8Array::new(&value).to_vec()))
+        })
+        .await
+    }
+
+    /// Returns `true` if the block is in the store.
+    pub async fn has_block(&self, cid: &Cid) -> bool {
+        SendWrapper::new(async move {
+            let (_, store) = self.open_blocks(IdbTransactionMode::Readonly)?;
+            let request = store.count_with_key(&cid_key(cid)).map_err(js_error)?;
+            let count = complete_request(&request).await?;
+
+            StoreResult::Ok(count.as_f64().unwrap_or_default() > 0.0)
+        })
+        .await
+        .unwrap_or(false)
+    }
+
+    /// Removes a block from the store. Returns `false` if the block was not in the store.
+    pub async fn remove_block(&self, cid: &Cid) -> StoreResult<bool> {
+        if !self.has_block(cid).await {
+            return Ok(false);
+        }
+
+        SendWrapper::new(async move {
+            let (transaction, store) = self.open_blocks(IdbTransactionMode::Readwrite)?;
+            store.delete(&cid_key(cid)).map_err(js_error)?;
+            complete_transaction(&transaction).await?;
+
+            Ok(true)
+        })
+        .await
+    }
+
+    /// Returns the CIDs of all the blocks in the store.
+    pub async fn list_blocks(&self) -> StoreResult<HashSet<Cid>> {
+        SendWrapper::new(async move {
+            let (_, store) = self.open_blocks(IdbTransactionMode::Readonly)?;
+            let keys = complete_request(&store.get_all_keys().map_err(js_error)?).await?;
+
+            keys.unchecked_into::<Array>()
+                .iter()
+                .map(|key| {
+                    let key = key.as_string().unwrap_or_default();
+                    key.parse::<Cid>().map_err(|e| {
+                        StoreError::custom(anyhow::anyhow!(
+                            "invalid block key {} in the IndexedDB store: {}",
+                            key,
+                            e
+                        ))
+                    })
+                })
+                .collect()
+        })
+        .await
+    }
+
+    /// Starts a transaction over the blocks and returns it along with the object store of the
+    /// blocks.
+    fn open_blocks(
+        &self,
+        mode: IdbTransactionMode,
+    ) -> StoreResult<(IdbTransaction, IdbObjectStore)> {
+        let transaction = self
+            .inner
+            .db
+            .transaction_with_str_and_mode(BLOCKS_STORE, mode)
+            .map_err(js_error)?;
+        let store = transaction.object_store(BLOCKS_STORE).map_err(js_error)?;
+
+        Ok((transaction, store))
+    }
+
+    /// Writes `bytes` as a block with the given codec and returns its CID.
+    async fn put(&self, codec: u64, bytes: &[u8]) -> StoreResult<Cid> {
+        let cid = make_cid(codec, self.inner.config.hash_function, bytes)?;
+        if !self.has_block(&cid).await {
+            self.put_block(&cid, bytes).await?;
+        }
+
+        Ok(cid)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Opens the database named `name`, creating the object store of the blocks on first use.
+async fn open_database(name: &str) -> StoreResult<IdbDatabase> {
+    // `indexedDB` is on the global object of windows and workers alike.
+    let factory = js_sys::Reflect::get(&js_sys::global(), &JsValue::from_str("indexedDB"))
+        .ok()
+        .and_then(|factory| factory.dyn_into::<IdbFactory>().ok())
+        .ok_or_else(|| StoreError::custom(anyhow::anyhow!("IndexedDB is not available")))?;
+
+    let request = factory.open_with_u32(name, IDB_VERSION).map_err(js_error)?;
+    let on_upgrade = Closure::<dyn FnMut()>::new({
+        let request = request.clone();
+        move || {
+            if let Some(db) = request
+                .result()
+                .ok()
+                .and_then(|db| db.dyn_into::<IdbDatabase>().ok())
+            {
+                if !db.object_store_names().contains(BLOCKS_STORE) {
+                    let _ = db.create_object_store(BLOCKS_STORE);
+                }
+            }
+        }
+    });
+    request.set_onupgradeneeded(Some(on_upgrade.as_ref().unchecked_ref()));
+
+    let db = complete_request(request.unchecked_ref::<IdbRequest>()).await;
+    request.set_onupgradeneeded(None);
+
+    db?.dyn_into::<IdbDatabase>().map_err(js_error)
+}
+
+/// Waits for `request` to succeed and returns its result.
+async fn complete_request(request: &IdbRequest) -> StoreResult<JsValue> {
+    let promise = Promise::new(&mut |resolve, reject| {
+        request.set_onsuccess(Some(&resolve));
+        request.set_onerror(Some(&reject));
+    });
+    JsFuture::from(promise).await.map_err(js_error)?;
+
+    request.result().map_err(js_error)
+}
+
+/// Waits for `transaction` to be committed.
+async fn complete_transaction(transaction: &IdbTransaction) -> StoreResult<()> {
+    let promise = Promise::new(&mut |resolve, reject| {
+        transaction.set_oncomplete(Some(&resolve));
+        transaction.set_onerror(Some(&reject));
+        transaction.set_onabort(Some(&reject));
+    });
+    JsFuture::from(promise).await.map_err(js_error)?;
+
+    Ok(())
+}
+
+/// Returns the key of the block of `cid`.
+fn cid_key(cid: &Cid) -> JsValue {
+    JsValue::from_str(&cid.to_string())
+}
+
+fn js_error(error: JsValue) -> StoreError {
+    StoreError::custom(anyhow::anyhow!("IndexedDB error: {:?}", error))
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl IpldStore for IdbStore {
+    async fn put_node<T>(&self, data: &T) -> StoreResult<Cid>
+    where
+        T: Serialize + IpldReferences + Sync,
+    {
+        let bytes = serde_ipld_dagcbor::to_vec(data).map_err(StoreError::custom)?;
+        self.put(NodeCodec::DagCbor.code(), &bytes).await
+    }
+
+    async fn put_bytes<'a>(
+        &'a self,
+        reader: impl AsyncRead + Send + Sync + 'a,
+    ) -> StoreResult<Cid> {
+        let mut bytes = Vec::new();
+        Box::pin(reader)
+            .read_to_end(&mut bytes)
+            .await
+            .map_err(StoreError::custom)?;
+
+        let max_size = self.inner.config.raw_block_max_size.max(1);
+        if bytes.len() <= max_size {
+            return self.put(RAW_CODEC_CODE, &bytes).await;
+        }
+
+        let mut chunks = ContentChunks::default();
+        for chunk in bytes.chunks(max_size) {
+            let cid = self.put(RAW_CODEC_CODE, chunk).await?;
+            chunks.push_chunk(cid, chunk.len() as u64);
+        }
+
+        self.put_node(&chunks).await
+    }
+
+    async fn put_raw_block(&self, bytes: impl Into<Bytes> + Send) -> StoreResult<Cid> {
+        let bytes = bytes.into();
+        if bytes.len() > self.inner.config.raw_block_max_size {
+            return Err(StoreError::custom(anyhow::anyhow!(
+                "raw block of {} bytes is larger than the maximum of {} bytes",
+                bytes.len(),
+                self.inner.config.raw_block_max_size
+            )));
+        }
+
+        self.put(RAW_CODEC_CODE, &bytes).await
+    }
+
+    async fn get_node<T>(&self, cid: &Cid) -> StoreResult<T>
+    where
+        T: DeserializeOwned + Send,
+    {
+        let bytes = self.get_block(cid).await?;
+        serde_ipld_dagcbor::from_slice(&bytes).map_err(StoreError::custom)
+    }
+
+    async fn get_bytes<'a>(
+        &'a self,
+        cid: &'a Cid,
+    ) -> StoreResult<Pin<Box<dyn AsyncRead + Send + Sync + 'a>>> {
+        if cid.codec() == RAW_CODEC_CODE {
+            let bytes = self.get_block(cid).await?;
+            return Ok(Box::pin(Cursor::new(bytes)));
+        }
+
+        // Only content split by `put_bytes` has a chunks node here, and its chunks are raw blocks.
+        let node: ContentChunks = serde_ipld_dagcbor::from_slice(&self.get_block(cid).await?)
+            .map_err(StoreError::custom)?;
+        let reader = ContentPiecesReader::new(node.pieces(), move |chunk| {
+            Box::pin(async move { self.get_block(&chunk).await })
+        });
+
+        Ok(Box::pin(reader))
+    }
+
+    async fn get_raw_block(&self, cid: &Cid) -> StoreResult<Bytes> {
+        self.get_block(cid).await
+    }
+
+    #[inline]
+    async fn has(&self, cid: &Cid) -> bool {
+        self.has_block(cid).await
+    }
+
+    fn get_supported_codecs(&self) -> HashSet<Codec> {
+        vec![Codec::Raw, Codec::DagCbor].into_iter().collect()
+    }
+
+    #[inline]
+    fn get_node_block_max_size(&self) -> Option<u64> {
+        None
+    }
+
+    #[inline]
+    fn get_raw_block_max_size(&self) -> Option<u64> {
+        Some(self.inner.config.raw_block_max_size as u64)
+    }
+}
+
+impl Default for IdbStoreConfig {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+impl From<&ZerofsStoreConfig> for IdbStoreConfig {
+    fn from(config: &ZerofsStoreConfig) -> Self {
+        Self::builder().hash_function(config.hash).build()
+    }
+}
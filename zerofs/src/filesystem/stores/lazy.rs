@@ -0,0 +1,208 @@
+use std::{collections::HashSet, io::Cursor, pin::Pin};
+
+use bytes::Bytes;
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::io::AsyncRead;
+use zeroutils_store::{ipld::cid::Cid, Codec, IpldReferences, IpldStore, StoreError, StoreResult};
+
+use crate::{
+    config::{NodeCodec, RAW_CODEC_CODE},
+    filesystem::block_matches,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// An [`IpldStore`] that reads the blocks it does not have locally from a remote store, as they
+/// are asked for.
+///
+/// It lets a read replica serve a tree as soon as it follows its root, without copying the tree
+/// first. Every block fetched from the remote store is checked against the hash in its CID, so a
+/// remote store cannot serve anything but the tree under a root the replica has verified. Fetched
+/// raw blocks are kept in the local store. Nodes are decoded from their verified bytes but not kept,
+/// since they can only be stored by encoding them again; wrap the store in a
+/// [`CachedStore`][super::CachedStore] to avoid fetching them repeatedly.
+///
+/// Writes only go to the local store.
+#[derive(Debug, Clone)]
+pub struct LazyStore<L, R>
+where
+    L: IpldStore,
+    R: IpldStore,
+{
+    local: L,
+    remote: R,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl<L, R> LazyStore<L, R>
+where
+    L: IpldStore,
+    R: IpldStore,
+{
+    /// Creates a store that reads from `local` and falls back to `remote`.
+    pub fn new(local: L, remote: R) -> Self {
+        Self { local, remote }
+    }
+
+    /// Returns the local store.
+    pub fn get_local(&self) -> &L {
+        &self.local
+    }
+
+    /// Returns the remote store.
+    pub fn get_remote(&self) -> &R {
+        &self.remote
+    }
+
+    /// Fetches the block of `cid` from the remote store and checks it against `cid`.
+    async fn fetch(&self, cid: &Cid) -> StoreResult<Bytes> {
+        let bytes = self.remote.get_raw_block(cid).await?;
+        if !block_matches(cid, &bytes) {
+            return Err(StoreError::custom(anyhow::anyhow!(
+                "fetched block does not match {}",
+                cid
+            )));
+        }
+
+        Ok(bytes)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl<L, R> IpldStore for LazyStore<L, R>
+where
+    L: IpldStore + Sync,
+    R: IpldStore + Sync,
+{
+    async fn put_node<T>(&self, data: &T) -> StoreResult<Cid>
+    where
+        T: Serialize + IpldReferences + Sync,
+    {
+        self.local.put_node(data).await
+    }
+
+    async fn put_bytes<'a>(
+        &'a self,
+        reader: impl AsyncRead + Send + Sync + 'a,
+    ) -> StoreResult<Cid> {
+        self.local.put_bytes(reader).await
+    }
+
+    async fn put_raw_block(&self, bytes: impl Into<Bytes> + Send) -> StoreResult<Cid> {
+        self.local.put_raw_block(bytes).await
+    }
+
+    async fn get_node<T>(&self, cid: &Cid) -> StoreResult<T>
+    where
+        T: DeserializeOwned + Send,
+    {
+        if self.local.has(cid).await {
+            return self.local.get_node(cid).await;
+        }
+
+        if cid.codec() != NodeCodec::DagCbor.code() {
+            return Err(StoreError::custom(anyhow::anyhow!(
+                "cannot verify the codec of {}",
+                cid
+            )));
+        }
+
+        let bytes = self.fetch(cid).await?;
+        serde_ipld_dagcbor::from_slice(&bytes).map_err(StoreError::custom)
+    }
+
+    async fn get_bytes<'a>(
+        &'a self,
+        cid: &'a Cid,
+    ) -> StoreResult<Pin<Box<dyn AsyncRead + Send + Sync + 'a>>> {
+        if cid.codec() == RAW_CODEC_CODE {
+            let bytes = self.get_raw_block(cid).await?;
+            return Ok(Box::pin(Cursor::new(bytes)));
+        }
+
+        if self.local.has(cid).await {
+            return self.local.get_bytes(cid).await;
+        }
+
+        // The chunks of content that is not local are read by the remote store itself, so unlike
+        // raw blocks and nodes they are not checked against their CID.
+        self.remote.get_bytes(cid).await
+    }
+
+    async fn get_raw_block(&self, cid: &Cid) -> StoreResult<Bytes> {
+        if self.local.has(cid).await {
+            return self.local.get_raw_block(cid).await;
+        }
+
+        let bytes = self.fetch(cid).await?;
+        if cid.codec() == RAW_CODEC_CODE {
+            self.local.put_raw_block(bytes.clone()).await?;
+        }
+
+        Ok(bytes)
+    }
+
+    #[inline]
+    async fn has(&self, cid: &Cid) -> bool {
+        self.local.has(cid).await || self.remote.has(cid).await
+    }
+
+    fn get_supported_codecs(&self) -> HashSet<Codec> {
+        self.local.get_supported_codecs()
+    }
+
+    #[inline]
+    fn get_node_block_max_size(&self) -> Option<u64> {
+        self.local.get_node_block_max_size()
+    }
+
+    #[inline]
+    fn get_raw_block_max_size(&self) -> Option<u64> {
+        self.local.get_raw_block_max_size()
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use zeroutils_store::{MemoryStore, Storable};
+
+    use crate::filesystem::{Dir, File};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_lazy_store_fetches_missing_blocks() -> anyhow::Result<()> {
+        let remote = MemoryStore::default();
+        let raw = remote.put_raw_block(&b"hello"[..]).await?;
+        let file = File::new(remote.clone()).store().await?;
+        let root = Dir::new(remote.clone())
+            .link_at(&"public/notes".parse()?, file)
+            .await?
+            .store()
+            .await?;
+
+        let local = MemoryStore::default();
+        let store = LazyStore::new(local.clone(), remote);
+
+        let dir = Dir::load(&root, store.clone()).await?;
+        assert!(dir.get_entity(&"public".parse()?).await?.is_some());
+
+        assert!(!local.has(&raw).await);
+        assert_eq!(&store.get_raw_block(&raw).await?[..], b"hello");
+        assert!(local.has(&raw).await);
+
+        Ok(())
+    }
+}
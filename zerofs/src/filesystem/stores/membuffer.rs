@@ -1,15 +1,15 @@
-use std::{collections::HashSet, path::PathBuf, pin::Pin, sync::Arc};
+use std::{collections::HashSet, pin::Pin};
 
 use bytes::Bytes;
 use serde::{de::DeserializeOwned, Serialize};
-use tokio::{io::AsyncRead, sync::RwLock};
+use tokio::io::AsyncRead;
 use zeroutils_store::{
     ipld::cid::Cid, Codec, DualStore, DualStoreConfig, IpldReferences, IpldStore, MemoryStore,
     StoreResult,
 };
 
 //--------------------------------------------------------------------------------------------------
-// Types: MemoryBufferStore
+// Types
 //--------------------------------------------------------------------------------------------------
 
 /// An [`IpldStore`][zeroutils_store::IpldStore] with two underlying stores: an ephemeral in-memory
@@ -25,25 +25,7 @@ where
 }
 
 //--------------------------------------------------------------------------------------------------
-// Types: DiskStore
-//--------------------------------------------------------------------------------------------------
-
-/// An [`IpldStore`][zeroutils_store::IpldStore] that stores its blocks on disk.
-#[derive(Debug, Clone)]
-pub struct DiskStore {
-    _inner: Arc<RwLock<DiskStoreInner>>,
-}
-
-#[derive(Debug)]
-struct DiskStoreInner {
-    /// The base directory where the blocks are stored.
-    ///
-    /// Default is set to `~/.zerofs`.
-    _base_dir: PathBuf,
-}
-
-//--------------------------------------------------------------------------------------------------
-// Methods: MemoryBufferStore
+// Methods
 //--------------------------------------------------------------------------------------------------
 
 impl<S> MemoryBufferStore<S>
@@ -62,21 +44,6 @@ where
     }
 }
 
-//--------------------------------------------------------------------------------------------------
-// Methods: DiskStore
-//--------------------------------------------------------------------------------------------------
-
-impl DiskStore {
-    /// Creates a new `DiskStore` with the given base directory.
-    pub fn new(base_dir: impl Into<PathBuf>) -> Self {
-        Self {
-            _inner: Arc::new(RwLock::new(DiskStoreInner {
-                _base_dir: base_dir.into(),
-            })),
-        }
-    }
-}
-
 //--------------------------------------------------------------------------------------------------
 // Trait Implementations
 //--------------------------------------------------------------------------------------------------
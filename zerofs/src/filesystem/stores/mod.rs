@@ -0,0 +1,29 @@
+mod cached;
+#[cfg(feature = "native")]
+mod disk;
+#[cfg(feature = "erasure")]
+mod erasure;
+mod existence;
+#[cfg(feature = "idb")]
+mod idb;
+mod lazy;
+mod membuffer;
+mod scheduled;
+#[cfg(all(target_os = "linux", feature = "uring"))]
+mod uring;
+
+//--------------------------------------------------------------------------------------------------
+// Exports
+//--------------------------------------------------------------------------------------------------
+
+pub use cached::*;
+#[cfg(feature = "native")]
+pub use disk::*;
+#[cfg(feature = "erasure")]
+pub use erasure::*;
+pub use existence::*;
+#[cfg(feature = "idb")]
+pub use idb::*;
+pub use lazy::*;
+pub use membuffer::*;
+pub use scheduled::*;
@@ -0,0 +1,234 @@
+use std::{collections::HashSet, io::Cursor, pin::Pin, sync::Arc};
+
+use bytes::Bytes;
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use zeroutils_store::{ipld::cid::Cid, Codec, IpldReferences, IpldStore, StoreError, StoreResult};
+
+use crate::filesystem::{TransferDirection, TransferPermit, TransferPriority, TransferScheduler};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// An [`IpldStore`] for the blocks of a peer that puts every transfer through a
+/// [`TransferScheduler`].
+///
+/// Puts are uploads to the peer and gets are downloads from it, each waiting for its turn at the
+/// priority of the store. Content and raw blocks are charged for their size. Nodes are counted as
+/// blocks but not charged, since their encoded size is not known to the store, and they are small
+/// next to the content they reference.
+///
+/// Stores for the same peer can share a scheduler at different priorities, like an interactive one
+/// for reads someone is waiting on and a background one to pass to
+/// [`migrate_store`][crate::filesystem::migrate_store].
+#[derive(Debug, Clone)]
+pub struct ScheduledStore<S>
+where
+    S: IpldStore,
+{
+    inner: S,
+    scheduler: Arc<TransferScheduler>,
+    peer: String,
+    priority: TransferPriority,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl<S> ScheduledStore<S>
+where
+    S: IpldStore,
+{
+    /// Creates a store scheduling the transfers with the blocks of `peer` in `inner` at `priority`.
+    pub fn new(
+        inner: S,
+        scheduler: Arc<TransferScheduler>,
+        peer: impl Into<String>,
+        priority: TransferPriority,
+    ) -> Self {
+        Self {
+            inner,
+            scheduler,
+            peer: peer.into(),
+            priority,
+        }
+    }
+
+    /// Returns a store for the same peer and scheduler at another priority.
+    pub fn with_priority(&self, priority: TransferPriority) -> Self {
+        Self {
+            priority,
+            ..self.clone()
+        }
+    }
+
+    /// Returns the underlying store.
+    pub fn get_inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns the ID of the peer.
+    pub fn get_peer(&self) -> &str {
+        &self.peer
+    }
+
+    /// Returns the priority transfers are scheduled at.
+    pub fn get_priority(&self) -> TransferPriority {
+        self.priority
+    }
+
+    /// Waits for the turn of a transfer in `direction`.
+    async fn acquire(&self, direction: TransferDirection) -> TransferPermit<'_> {
+        self.scheduler
+            .acquire(direction, &self.peer, self.priority)
+            .await
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl<S> IpldStore for ScheduledStore<S>
+where
+    S: IpldStore + Sync,
+{
+    async fn put_node<T>(&self, data: &T) -> StoreResult<Cid>
+    where
+        T: Serialize + IpldReferences + Sync,
+    {
+        let permit = self.acquire(TransferDirection::Upload).await;
+        let cid = self.inner.put_node(data).await?;
+        permit.complete(0);
+
+        Ok(cid)
+    }
+
+    async fn put_bytes<'a>(
+        &'a self,
+        reader: impl AsyncRead + Send + Sync + 'a,
+    ) -> StoreResult<Cid> {
+        let mut bytes = Vec::new();
+        Box::pin(reader)
+            .read_to_end(&mut bytes)
+            .await
+            .map_err(StoreError::custom)?;
+
+        let size = bytes.len() as u64;
+        let permit = self.acquire(TransferDirection::Upload).await;
+        let cid = self.inner.put_bytes(Cursor::new(bytes)).await?;
+        permit.complete(size);
+
+        Ok(cid)
+    }
+
+    async fn put_raw_block(&self, bytes: impl Into<Bytes> + Send) -> StoreResult<Cid> {
+        let bytes = bytes.into();
+        let size = bytes.len() as u64;
+        let permit = self.acquire(TransferDirection::Upload).await;
+        let cid = self.inner.put_raw_block(bytes).await?;
+        permit.complete(size);
+
+        Ok(cid)
+    }
+
+    async fn get_node<T>(&self, cid: &Cid) -> StoreResult<T>
+    where
+        T: DeserializeOwned + Send,
+    {
+        let permit = self.acquire(TransferDirection::Download).await;
+        let node = self.inner.get_node(cid).await?;
+        permit.complete(0);
+
+        Ok(node)
+    }
+
+    async fn get_bytes<'a>(
+        &'a self,
+        cid: &'a Cid,
+    ) -> StoreResult<Pin<Box<dyn AsyncRead + Send + Sync + 'a>>> {
+        let permit = self.acquire(TransferDirection::Download).await;
+        let mut bytes = Vec::new();
+        self.inner
+            .get_bytes(cid)
+            .await?
+            .read_to_end(&mut bytes)
+            .await
+            .map_err(StoreError::custom)?;
+        permit.complete(bytes.len() as u64);
+
+        Ok(Box::pin(Cursor::new(bytes)))
+    }
+
+    async fn get_raw_block(&self, cid: &Cid) -> StoreResult<Bytes> {
+        let permit = self.acquire(TransferDirection::Download).await;
+        let bytes = self.inner.get_raw_block(cid).await?;
+        permit.complete(bytes.len() as u64);
+
+        Ok(bytes)
+    }
+
+    #[inline]
+    async fn has(&self, cid: &Cid) -> bool {
+        self.inner.has(cid).await
+    }
+
+    fn get_supported_codecs(&self) -> HashSet<Codec> {
+        self.inner.get_supported_codecs()
+    }
+
+    #[inline]
+    fn get_node_block_max_size(&self) -> Option<u64> {
+        self.inner.get_node_block_max_size()
+    }
+
+    #[inline]
+    fn get_raw_block_max_size(&self) -> Option<u64> {
+        self.inner.get_raw_block_max_size()
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use zeroutils_store::{MemoryStore, Storable};
+
+    use crate::{config::ZerofsTransferConfig, filesystem::File};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_scheduled_store_charges_transfers() -> anyhow::Result<()> {
+        let scheduler = Arc::new(TransferScheduler::new(&ZerofsTransferConfig::default()));
+        let store = ScheduledStore::new(
+            MemoryStore::default(),
+            scheduler.clone(),
+            "peer",
+            TransferPriority::Background,
+        );
+
+        let cid = store.put_bytes(&b"hello world"[..]).await?;
+        let interactive = store.with_priority(TransferPriority::Interactive);
+        let mut bytes = Vec::new();
+        interactive
+            .get_bytes(&cid)
+            .await?
+            .read_to_end(&mut bytes)
+            .await?;
+        assert_eq!(bytes, b"hello world");
+        File::new(store.clone()).store().await?;
+
+        let stats = scheduler.get_stats();
+        assert_eq!(stats.upload.bytes, 11);
+        assert_eq!(stats.upload.blocks, 2);
+        assert_eq!(stats.download.bytes, 11);
+        assert_eq!(stats.peers["peer"].bytes_downloaded, 11);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,453 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::File,
+    io::{self, ErrorKind},
+    os::fd::AsRawFd,
+    path::PathBuf,
+    sync::mpsc::{self, Receiver, Sender, TryRecvError},
+    thread,
+};
+
+use io_uring::{opcode, types::Fd, IoUring};
+use tokio::sync::oneshot;
+
+use super::UringConfig;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Reads and writes whole files through an io_uring owned by a dedicated thread.
+///
+/// Requests are handed to the thread over a channel. The thread submits every request it has
+/// pending at once, so reads and writes coming from many tasks share `io_uring_enter` calls. Files
+/// that fit in one of the buffers registered with the kernel are read and written through it,
+/// which saves the kernel from mapping the pages of the buffer on every operation.
+///
+/// The thread stops once the driver is dropped and the requests in flight are done.
+#[derive(Debug)]
+pub(crate) struct UringDriver {
+    requests: Sender<Request>,
+}
+
+/// A request to the ring thread.
+struct Request {
+    kind: RequestKind,
+    reply: oneshot::Sender<io::Result<Vec<u8>>>,
+}
+
+enum RequestKind {
+    /// Reads the whole file at the path.
+    Read(PathBuf),
+
+    /// Creates the file at the path with the given content, flushing it to disk if `sync` is set.
+    Write {
+        path: PathBuf,
+        bytes: Vec<u8>,
+        sync: bool,
+    },
+}
+
+/// The state of the ring thread.
+struct Ring {
+    ring: IoUring,
+
+    /// The buffers registered with the kernel.
+    buffers: Vec<Box<[u8]>>,
+
+    /// The indices of the registered buffers not in use.
+    free_buffers: Vec<u16>,
+
+    /// The operations submitted and not completed yet, by their user data.
+    in_flight: HashMap<u64, Operation>,
+
+    /// The user data of the next operation.
+    next_id: u64,
+}
+
+/// A read or write in flight.
+struct Operation {
+    file: File,
+    buffer: Buffer,
+
+    /// The number of bytes to read or write.
+    len: usize,
+
+    /// The number of bytes read or written so far.
+    done: usize,
+
+    /// What the operation is doing.
+    stage: Stage,
+
+    reply: oneshot::Sender<io::Result<Vec<u8>>>,
+}
+
+enum Buffer {
+    /// One of the registered buffers.
+    Registered(u16),
+
+    /// A regular buffer, for files that do not fit in a registered one.
+    Regular(Vec<u8>),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Stage {
+    Reading,
+    Writing { sync: bool },
+    Syncing,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl UringDriver {
+    /// Sets up an io_uring with the given configuration and starts the thread that drives it.
+    pub(crate) fn start(config: &UringConfig) -> io::Result<Self> {
+        let ring = Ring::new(config)?;
+        let (requests, receiver) = mpsc::channel();
+
+        thread::Builder::new()
+            .name("zerofs-uring".into())
+            .spawn(move || ring.run(receiver))?;
+
+        Ok(Self { requests })
+    }
+
+    /// Reads the whole file at `path`.
+    pub(crate) async fn read(&self, path: PathBuf) -> io::Result<Vec<u8>> {
+        self.send(RequestKind::Read(path)).await
+    }
+
+    /// Creates the file at `path` with `bytes` as its content, flushing it to disk if `sync` is
+    /// set.
+    pub(crate) async fn write(&self, path: PathBuf, bytes: Vec<u8>, sync: bool) -> io::Result<()> {
+        self.send(RequestKind::Write { path, bytes, sync })
+            .await
+            .map(drop)
+    }
+
+    async fn send(&self, kind: RequestKind) -> io::Result<Vec<u8>> {
+        let (reply, response) = oneshot::channel();
+        self.requests
+            .send(Request { kind, reply })
+            .map_err(|_| io::Error::new(ErrorKind::BrokenPipe, "io_uring thread stopped"))?;
+
+        response
+            .await
+            .map_err(|_| io::Error::new(ErrorKind::BrokenPipe, "io_uring thread stopped"))?
+    }
+}
+
+impl Ring {
+    fn new(config: &UringConfig) -> io::Result<Self> {
+        let ring = IoUring::new(config.entries)?;
+        let mut buffers = (0..config.buffer_count.min(u16::MAX as usize))
+            .map(|_| vec![0; config.buffer_size].into_boxed_slice())
+            .collect::<Vec<_>>();
+
+        let iovecs = buffers
+            .iter_mut()
+            .map(|buffer| libc::iovec {
+                iov_base: buffer.as_mut_ptr().cast(),
+                iov_len: buffer.len(),
+            })
+            .collect::<Vec<_>>();
+
+        // SAFETY: The buffers live as long as the ring and are never reallocated.
+        if let Err(e) = unsafe { ring.submitter().register_buffers(&iovecs) } {
+            tracing::warn!("Failed to register io_uring buffers: {}", e);
+            buffers.clear();
+        }
+
+        Ok(Self {
+            ring,
+            free_buffers: (0..buffers.len() as u16).rev().collect(),
+            buffers,
+            in_flight: HashMap::new(),
+            next_id: 0,
+        })
+    }
+
+    /// Serves requests until the driver is dropped.
+    fn run(mut self, requests: Receiver<Request>) {
+        let capacity = self.ring.params().sq_entries() as usize;
+        let mut pending = VecDeque::new();
+        let mut connected = true;
+
+        loop {
+            // Only block for new requests when there is nothing in flight to wait on.
+            if self.in_flight.is_empty() && pending.is_empty() {
+                match requests.recv() {
+                    Ok(request) => pending.push_back(request),
+                    Err(_) => break,
+                }
+            }
+
+            while connected {
+                match requests.try_recv() {
+                    Ok(request) => pending.push_back(request),
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => connected = false,
+                }
+            }
+
+            // Each operation has at most one entry in the submission queue, so it never overflows.
+            while self.in_flight.len() < capacity {
+                let Some(request) = pending.pop_front() else {
+                    break;
+                };
+
+                self.start(request);
+            }
+
+            if self.in_flight.is_empty() {
+                if !connected && pending.is_empty() {
+                    break;
+                }
+
+                continue;
+            }
+
+            if let Err(e) = self.ring.submit_and_wait(1) {
+                // The completions already there still need reaping when the wait is cut short.
+                if !matches!(
+                    e.raw_os_error(),
+                    Some(libc::EINTR | libc::EAGAIN | libc::EBUSY)
+                ) {
+                    tracing::error!("io_uring submission failed, stopping: {}", e);
+                    break;
+                }
+            }
+
+            let completions = self
+                .ring
+                .completion()
+                .map(|entry| (entry.user_data(), entry.result()))
+                .collect::<Vec<_>>();
+
+            for (id, result) in completions {
+                self.complete(id, result);
+            }
+        }
+    }
+
+    /// Opens the file of a request and submits its first operation.
+    fn start(&mut self, request: Request) {
+        let Request { kind, reply } = request;
+        let opened = match kind {
+            RequestKind::Read(path) => File::open(path).and_then(|file| {
+                let len = file.metadata()?.len() as usize;
+                let buffer = self.take_buffer(len, None);
+                Ok((file, buffer, len, Stage::Reading))
+            }),
+            RequestKind::Write { path, bytes, sync } => File::create(path).map(|file| {
+                let len = bytes.len();
+                let buffer = self.take_buffer(len, Some(bytes));
+                (file, buffer, len, Stage::Writing { sync })
+            }),
+        };
+
+        let (file, buffer, len, stage) = match opened {
+            Ok(opened) => opened,
+            Err(e) => {
+                let _ = reply.send(Err(e));
+                return;
+            }
+        };
+
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        self.in_flight.insert(
+            id,
+            Operation {
+                file,
+                buffer,
+                len,
+                done: 0,
+                stage,
+                reply,
+            },
+        );
+
+        self.advance(id);
+    }
+
+    /// Handles the completion of an operation, submitting the next step or replying.
+    fn complete(&mut self, id: u64, result: i32) {
+        let Some(operation) = self.in_flight.get_mut(&id) else {
+            return;
+        };
+
+        if result < 0 {
+            let error = io::Error::from_raw_os_error(-result);
+            self.finish(id, Err(error));
+            return;
+        }
+
+        match operation.stage {
+            Stage::Reading | Stage::Writing { .. }
+                if result == 0 && operation.done < operation.len =>
+            {
+                let error = io::Error::new(ErrorKind::UnexpectedEof, "file changed during io");
+                self.finish(id, Err(error));
+                return;
+            }
+            Stage::Reading | Stage::Writing { .. } => operation.done += result as usize,
+            Stage::Syncing => {}
+        }
+
+        match operation.stage {
+            Stage::Writing { sync: true } if operation.done == operation.len => {
+                operation.stage = Stage::Syncing;
+                self.advance(id);
+            }
+            Stage::Reading | Stage::Writing { .. } if operation.done < operation.len => {
+                self.advance(id);
+            }
+            _ => {
+                let bytes = self.take_bytes(id);
+                self.finish(id, Ok(bytes));
+            }
+        }
+    }
+
+    /// Pushes the next step of an operation to the submission queue.
+    fn advance(&mut self, id: u64) {
+        let operation = self.in_flight.get_mut(&id).unwrap();
+        let fd = Fd(operation.file.as_raw_fd());
+        let done = operation.done;
+        let remaining = (operation.len - done).min(u32::MAX as usize) as u32;
+
+        // A file that is already complete still gets a zero-length operation, which keeps the
+        // flow the same for empty files.
+        let entry = match (&mut operation.buffer, operation.stage) {
+            (_, Stage::Syncing) => opcode::Fsync::new(fd).build(),
+            (Buffer::Registered(index), Stage::Reading) => {
+                let buffer = &mut self.buffers[*index as usize][done..];
+                opcode::ReadFixed::new(fd, buffer.as_mut_ptr(), remaining, *index)
+                    .offset(done as u64)
+                    .build()
+            }
+            (Buffer::Registered(index), Stage::Writing { .. }) => {
+                let buffer = &self.buffers[*index as usize][done..];
+                opcode::WriteFixed::new(fd, buffer.as_ptr(), remaining, *index)
+                    .offset(done as u64)
+                    .build()
+            }
+            (Buffer::Regular(buffer), Stage::Reading) => {
+                opcode::Read::new(fd, buffer[done..].as_mut_ptr(), remaining)
+                    .offset(done as u64)
+                    .build()
+            }
+            (Buffer::Regular(buffer), Stage::Writing { .. }) => {
+                opcode::Write::new(fd, buffer[done..].as_ptr(), remaining)
+                    .offset(done as u64)
+                    .build()
+            }
+        };
+
+        // SAFETY: The buffer and the file of the operation stay alive and in place until its
+        // completion is reaped, and the queue has room since each operation holds at most one
+        // entry.
+        unsafe {
+            self.ring
+                .submission()
+                .push(&entry.user_data(id))
+                .expect("submission queue has room for every operation in flight");
+        }
+    }
+
+    /// Returns a buffer for a file of `len` bytes, holding `bytes` if it is being written.
+    fn take_buffer(&mut self, len: usize, bytes: Option<Vec<u8>>) -> Buffer {
+        if len <= self.buffers.first().map_or(0, |buffer| buffer.len()) {
+            if let Some(index) = self.free_buffers.pop() {
+                if let Some(bytes) = bytes {
+                    self.buffers[index as usize][..len].copy_from_slice(&bytes);
+                }
+
+                return Buffer::Registered(index);
+            }
+        }
+
+        Buffer::Regular(bytes.unwrap_or_else(|| vec![0; len]))
+    }
+
+    /// Returns the bytes read by an operation, or nothing if it wrote.
+    fn take_bytes(&mut self, id: u64) -> Vec<u8> {
+        let operation = self.in_flight.get_mut(&id).unwrap();
+        if operation.stage != Stage::Reading {
+            return Vec::new();
+        }
+
+        match &mut operation.buffer {
+            Buffer::Registered(index) => self.buffers[*index as usize][..operation.len].to_vec(),
+            Buffer::Regular(buffer) => std::mem::take(buffer),
+        }
+    }
+
+    /// Removes an operation, releasing its buffer, and sends its result.
+    fn finish(&mut self, id: u64, result: io::Result<Vec<u8>>) {
+        if let Some(operation) = self.in_flight.remove(&id) {
+            if let Buffer::Registered(index) = operation.buffer {
+                self.free_buffers.push(index);
+            }
+
+            let _ = operation.reply.send(result);
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_uring_driver_reads_and_writes_files() -> anyhow::Result<()> {
+        let config = UringConfig::builder()
+            .buffer_count(1)
+            .buffer_size(16)
+            .build();
+        let driver = match UringDriver::start(&config) {
+            Ok(driver) => driver,
+            // io_uring is not available in every environment the tests run in.
+            Err(e) => {
+                eprintln!("skipping io_uring test: {e}");
+                return Ok(());
+            }
+        };
+
+        let dir = std::env::temp_dir().join(format!("zerofs{}", rand::random::<u64>()));
+        tokio::fs::create_dir_all(&dir).await?;
+
+        // One file fits the registered buffer, the other does not, and one is empty.
+        let files = [
+            (dir.join("small"), b"hello".to_vec()),
+            (dir.join("large"), b"all work and no play ".repeat(1000)),
+            (dir.join("empty"), Vec::new()),
+        ];
+
+        for (path, content) in files.iter() {
+            driver.write(path.clone(), content.clone(), true).await?;
+            assert_eq!(tokio::fs::read(path).await?, *content);
+        }
+
+        let reads =
+            futures::future::try_join_all(files.iter().map(|(path, _)| driver.read(path.clone())))
+                .await?;
+        for ((_, content), read) in files.iter().zip(reads) {
+            assert_eq!(read, *content);
+        }
+
+        let missing = driver.read(dir.join("missing")).await;
+        assert_eq!(missing.unwrap_err().kind(), ErrorKind::NotFound);
+
+        tokio::fs::remove_dir_all(&dir).await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,116 @@
+use std::convert::TryFrom;
+
+use serde::{Deserialize, Serialize};
+
+use super::{FsError, FsResult, Path};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The subtrees of the file tree a node replicates, given by the paths of their roots.
+///
+/// A node with a subscription only keeps the blocks of the subscribed subtrees, along with the
+/// directories on the way to them. Everything else in the tree is left to other nodes, and tracing
+/// a path into it fails with [`FsError::NotReplicatedLocally`].
+///
+/// A subscription serializes as the list of its paths.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "Vec<String>", into = "Vec<String>")]
+pub struct Subscription {
+    /// The roots of the subscribed subtrees.
+    prefixes: Vec<Path>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl Subscription {
+    /// Creates a subscription to the subtrees at `prefixes`.
+    pub fn new(prefixes: impl IntoIterator<Item = Path>) -> FsResult<Self> {
+        let prefixes = prefixes
+            .into_iter()
+            .map(|prefix| prefix.canonicalize())
+            .collect::<FsResult<_>>()?;
+
+        Ok(Self { prefixes })
+    }
+
+    /// Returns the roots of the subscribed subtrees.
+    pub fn get_prefixes(&self) -> &[Path] {
+        &self.prefixes
+    }
+
+    /// Returns `true` if `path` is in one of the subscribed subtrees, so everything under it is
+    /// replicated.
+    pub fn covers(&self, path: &Path) -> bool {
+        self.prefixes
+            .iter()
+            .any(|prefix| path.get_segments().starts_with(prefix.get_segments()))
+    }
+
+    /// Returns `true` if the entity at `path` is replicated, either because it is in a subscribed
+    /// subtree or because it is a directory on the way to one.
+    pub fn includes(&self, path: &Path) -> bool {
+        self.prefixes.iter().any(|prefix| {
+            path.get_segments().starts_with(prefix.get_segments())
+                || prefix.get_segments().starts_with(path.get_segments())
+        })
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl TryFrom<Vec<String>> for Subscription {
+    type Error = FsError;
+
+    fn try_from(prefixes: Vec<String>) -> Result<Self, Self::Error> {
+        Self::new(
+            prefixes
+                .into_iter()
+                .map(Path::try_from)
+                .collect::<FsResult<Vec<_>>>()?,
+        )
+    }
+}
+
+impl From<Subscription> for Vec<String> {
+    fn from(subscription: Subscription) -> Self {
+        subscription
+            .prefixes
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_subscription_covers_and_includes() -> anyhow::Result<()> {
+        let subscription = Subscription::new(["public/photos".parse()?, "shared".parse()?])?;
+
+        assert!(subscription.covers(&"public/photos".parse()?));
+        assert!(subscription.covers(&"PUBLIC/photos/cat".parse()?));
+        assert!(subscription.covers(&"shared/notes".parse()?));
+        assert!(!subscription.covers(&"public".parse()?));
+        assert!(!subscription.covers(&"public/music".parse()?));
+
+        assert!(subscription.includes(&Path::default()));
+        assert!(subscription.includes(&"public".parse()?));
+        assert!(subscription.includes(&"public/photos/cat".parse()?));
+        assert!(!subscription.includes(&"public/music".parse()?));
+        assert!(!subscription.includes(&"private".parse()?));
+
+        Ok(())
+    }
+}
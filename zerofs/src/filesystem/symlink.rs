@@ -3,6 +3,7 @@ use std::{
     sync::Arc,
 };
 
+use chrono::{DateTime, Utc};
 use serde::{
     de::{self, DeserializeSeed},
     Deserialize, Deserializer, Serialize, Serializer,
@@ -11,7 +12,9 @@ use zeroutils_store::{
     ipld::cid::Cid, IpldReferences, IpldStore, Storable, StoreError, StoreResult,
 };
 
-use super::{EntityPathLink, EntityType, FsError, FsResult, Metadata, Path, PathLink};
+use super::{
+    Acl, EntityPathLink, EntityType, FsError, FsResult, KeyGrants, Metadata, Path, PathLink,
+};
 
 //--------------------------------------------------------------------------------------------------
 // Types
@@ -86,6 +89,37 @@ where
         &self.inner.metadata
     }
 
+    /// Sets the access control list of the symlink.
+    pub fn set_acl(&mut self, acl: Acl) {
+        let inner = Arc::make_mut(&mut self.inner);
+        inner.metadata.acl = acl;
+        inner.metadata.mark_changed();
+    }
+
+    /// Sets the grants of the content key of the symlink.
+    pub fn set_key_grants(&mut self, grants: KeyGrants) {
+        let inner = Arc::make_mut(&mut self.inner);
+        inner.metadata.grants = grants;
+        inner.metadata.mark_changed();
+    }
+
+    /// Sets the number of times the content key of the symlink was rotated.
+    pub fn set_key_epoch(&mut self, epoch: u32) {
+        let inner = Arc::make_mut(&mut self.inner);
+        inner.metadata.key_epoch = epoch;
+        inner.metadata.mark_changed();
+    }
+
+    /// Sets the access and modification times of the symlink, see [`Metadata::set_times`].
+    pub fn set_times(
+        &mut self,
+        accessed_at: Option<DateTime<Utc>>,
+        modified_at: Option<DateTime<Utc>>,
+    ) {
+        let inner = Arc::make_mut(&mut self.inner);
+        inner.metadata.set_times(accessed_at, modified_at);
+    }
+
     /// Gets the target path of the symlink.
     pub fn get_path(&self) -> &Path {
         self.inner.link.get_path()
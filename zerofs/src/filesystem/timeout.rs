@@ -0,0 +1,79 @@
+use std::{future::Future, time::Duration};
+
+use super::FsError;
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Runs `future`, the operation named `operation`, failing with [`FsError::Timeout`] if it does not
+/// complete within `timeout`. A `timeout` of `None` lets it run for as long as it takes.
+///
+/// When the time runs out the future is dropped, which cancels the store reads and writes it was
+/// waiting on. Content piped to or from the store by tasks of its own is cancelled with it, as
+/// [`ContentReader`][super::ContentReader] and [`FileOutputStream`][super::FileOutputStream] abort
+/// their task when dropped.
+pub async fn with_timeout<F, T, E>(
+    operation: &str,
+    timeout: Option<Duration>,
+    future: F,
+) -> Result<T, E>
+where
+    F: Future<Output = Result<T, E>>,
+    E: From<FsError>,
+{
+    let Some(timeout) = timeout else {
+        return future.await;
+    };
+
+    match tokio::time::timeout(timeout, future).await {
+        Ok(result) => result,
+        Err(_) => Err(FsError::Timeout(operation.to_owned(), timeout).into()),
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    };
+
+    use super::*;
+
+    /// Sets a flag when it is dropped.
+    struct DropFlag(Arc<AtomicBool>);
+
+    impl Drop for DropFlag {
+        fn drop(&mut self) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_with_timeout_cancels_operation() -> anyhow::Result<()> {
+        let value =
+            with_timeout::<_, _, FsError>("fast", Some(Duration::from_secs(5)), async { Ok(7) })
+                .await?;
+        assert_eq!(value, 7);
+
+        // A slow operation fails with a timeout, and is dropped rather than left running.
+        let dropped = Arc::new(AtomicBool::new(false));
+        let flag = DropFlag(Arc::clone(&dropped));
+        let result = with_timeout("slow", Some(Duration::from_millis(10)), async move {
+            let _flag = flag;
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            Ok::<_, FsError>(())
+        })
+        .await;
+
+        assert!(matches!(result, Err(FsError::Timeout(ref op, _)) if op == "slow"));
+        assert!(dropped.load(Ordering::SeqCst));
+
+        Ok(())
+    }
+}
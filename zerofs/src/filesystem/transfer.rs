@@ -0,0 +1,421 @@
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+
+use crate::config::ZerofsTransferConfig;
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The shortest time a transfer waits for the bandwidth budget to refill before checking again.
+const MIN_WAIT: Duration = Duration::from_millis(1);
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Schedules the block transfers between this node and its peers.
+///
+/// Uploads and downloads each have a bandwidth budget that refills continuously at the configured
+/// cap and can hold up to one second's worth. A transfer waits in line until the budget is not
+/// exhausted and it is its turn, and is charged for its size once it is done, so a large block can
+/// overdraw the budget and hold back the transfers after it until the budget has refilled.
+///
+/// Transfers of a higher [`TransferPriority`] always go first. Within a priority class, the peer
+/// that was served least recently goes next, so a peer with many blocks in line does not starve
+/// the others, and the transfers of a peer go in the order they were queued in.
+///
+/// Without caps, transfers go right away and are only counted.
+#[derive(Debug)]
+pub struct TransferScheduler {
+    state: Mutex<SchedulerState>,
+
+    /// Wakes up the queued transfers when the head of a line may have changed.
+    notify: Notify,
+}
+
+/// A transfer the scheduler has let through, to be charged for its size once it is done.
+///
+/// Dropping the permit without completing it, like when the transfer fails, charges nothing.
+#[derive(Debug)]
+#[must_use]
+pub struct TransferPermit<'a> {
+    scheduler: &'a TransferScheduler,
+    direction: TransferDirection,
+    peer: String,
+}
+
+/// The priority class of a transfer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TransferPriority {
+    /// Background replication, which gets what interactive transfers leave of the bandwidth.
+    Background,
+
+    /// Reads someone is waiting on.
+    Interactive,
+}
+
+/// The direction of a transfer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TransferDirection {
+    /// Blocks sent to a peer.
+    Upload,
+
+    /// Blocks fetched from a peer.
+    Download,
+}
+
+/// The transfers of a [`TransferScheduler`], as reported by the admin API.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct TransferStats {
+    /// The blocks sent to peers.
+    pub upload: TransferLaneStats,
+
+    /// The blocks fetched from peers.
+    pub download: TransferLaneStats,
+
+    /// The bytes transferred with each peer.
+    pub peers: BTreeMap<String, PeerTransferStats>,
+}
+
+/// The transfers in one direction.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct TransferLaneStats {
+    /// The bandwidth cap in bytes per second, if there is one.
+    pub bytes_per_second: Option<u64>,
+
+    /// The number of bytes transferred.
+    pub bytes: u64,
+
+    /// The number of blocks transferred.
+    pub blocks: u64,
+
+    /// The number of transfers waiting in line, by priority class.
+    pub queued: BTreeMap<TransferPriority, usize>,
+}
+
+/// The bytes transferred with a peer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct PeerTransferStats {
+    /// The number of bytes sent to the peer.
+    pub bytes_uploaded: u64,
+
+    /// The number of bytes fetched from the peer.
+    pub bytes_downloaded: u64,
+}
+
+#[derive(Debug)]
+struct SchedulerState {
+    upload: Lane,
+    download: Lane,
+    peers: BTreeMap<String, PeerTransferStats>,
+
+    /// The ticket of the next queued transfer.
+    next_ticket: u64,
+}
+
+/// The line of transfers in one direction along with its bandwidth budget.
+#[derive(Debug)]
+struct Lane {
+    /// The bandwidth cap in bytes per second.
+    rate: Option<f64>,
+
+    /// The remaining bandwidth budget in bytes, which goes negative when overdrawn.
+    budget: f64,
+    updated_at: Instant,
+
+    /// The queued transfers, in the order they were queued in.
+    waiting: Vec<Waiter>,
+
+    /// The number of transfers let through so far.
+    granted: u64,
+
+    /// The number of the last transfer let through for each peer.
+    last_served: HashMap<String, u64>,
+
+    stats: TransferLaneStats,
+}
+
+#[derive(Debug)]
+struct Waiter {
+    ticket: u64,
+    peer: String,
+    priority: TransferPriority,
+}
+
+/// Takes a queued transfer out of line if it is dropped before it is let through.
+struct Queued<'a> {
+    scheduler: &'a TransferScheduler,
+    direction: TransferDirection,
+    ticket: u64,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl TransferScheduler {
+    /// Creates a scheduler enforcing the bandwidth caps of `config`.
+    pub fn new(config: &ZerofsTransferConfig) -> Self {
+        Self {
+            state: Mutex::new(SchedulerState {
+                upload: Lane::new(config.upload_bytes_per_second),
+                download: Lane::new(config.download_bytes_per_second),
+                peers: BTreeMap::new(),
+                next_ticket: 0,
+            }),
+            notify: Notify::new(),
+        }
+    }
+
+    /// Waits until a transfer with `peer` in `direction` can go, and returns the permit to charge it
+    /// with once it is done.
+    pub async fn acquire(
+        &self,
+        direction: TransferDirection,
+        peer: &str,
+        priority: TransferPriority,
+    ) -> TransferPermit<'_> {
+        let ticket = {
+            let mut state = self.state.lock().unwrap();
+            let ticket = state.next_ticket;
+            state.next_ticket += 1;
+            state.lane_mut(direction).waiting.push(Waiter {
+                ticket,
+                peer: peer.to_owned(),
+                priority,
+            });
+            ticket
+        };
+
+        let _queued = Queued {
+            scheduler: self,
+            direction,
+            ticket,
+        };
+
+        loop {
+            // Created before checking, so a notification sent in between is not missed.
+            let notified = self.notify.notified();
+            let wait = {
+                let mut state = self.state.lock().unwrap();
+                match state.lane_mut(direction).try_grant(ticket, Instant::now()) {
+                    Ok(()) => break,
+                    Err(wait) => wait,
+                }
+            };
+
+            match wait {
+                Some(wait) => {
+                    tokio::select! {
+                        _ = notified => {}
+                        _ = tokio::time::sleep(wait) => {}
+                    }
+                }
+                None => notified.await,
+            }
+        }
+
+        // The transfer left the line, so another one may be at its head now.
+        self.notify.notify_waiters();
+
+        TransferPermit {
+            scheduler: self,
+            direction,
+            peer: peer.to_owned(),
+        }
+    }
+
+    /// Returns the transfers so far, along with those waiting in line.
+    pub fn get_stats(&self) -> TransferStats {
+        let state = self.state.lock().unwrap();
+        TransferStats {
+            upload: state.upload.get_stats(),
+            download: state.download.get_stats(),
+            peers: state.peers.clone(),
+        }
+    }
+}
+
+impl TransferPermit<'_> {
+    /// Charges the transfer for the `bytes` it moved.
+    pub fn complete(self, bytes: u64) {
+        let mut state = self.scheduler.state.lock().unwrap();
+        let lane = state.lane_mut(self.direction);
+        lane.budget -= bytes as f64;
+        lane.stats.bytes += bytes;
+        lane.stats.blocks += 1;
+
+        let peer = state.peers.entry(self.peer).or_default();
+        match self.direction {
+            TransferDirection::Upload => peer.bytes_uploaded += bytes,
+            TransferDirection::Download => peer.bytes_downloaded += bytes,
+        }
+    }
+}
+
+impl SchedulerState {
+    fn lane_mut(&mut self, direction: TransferDirection) -> &mut Lane {
+        match direction {
+            TransferDirection::Upload => &mut self.upload,
+            TransferDirection::Download => &mut self.download,
+        }
+    }
+}
+
+impl Lane {
+    fn new(bytes_per_second: Option<u64>) -> Self {
+        // A cap of zero would never refill, so the budget refills at a byte per second at least.
+        let rate = bytes_per_second.map(|rate| rate.max(1) as f64);
+        Self {
+            rate,
+            budget: rate.unwrap_or_default(),
+            updated_at: Instant::now(),
+            waiting: Vec::new(),
+            granted: 0,
+            last_served: HashMap::new(),
+            stats: TransferLaneStats {
+                bytes_per_second,
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Lets the transfer with `ticket` through if it is at the head of the line and the budget is
+    /// not exhausted. Otherwise returns how long to wait for the budget to refill, or `None` if the
+    /// transfer has to wait for its turn.
+    fn try_grant(&mut self, ticket: u64, now: Instant) -> Result<(), Option<Duration>> {
+        let head = self
+            .waiting
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, waiter)| {
+                (
+                    Reverse(waiter.priority),
+                    self.last_served.get(&waiter.peer).copied(),
+                    waiter.ticket,
+                )
+            })
+            .map(|(index, waiter)| (index, waiter.ticket));
+
+        let index = match head {
+            Some((index, head)) if head == ticket => index,
+            _ => return Err(None),
+        };
+
+        if let Some(rate) = self.rate {
+            let elapsed = now.duration_since(self.updated_at).as_secs_f64();
+            self.budget = (self.budget + elapsed * rate).min(rate);
+            self.updated_at = now;
+
+            if self.budget <= 0.0 {
+                return Err(Some(
+                    Duration::from_secs_f64(-self.budget / rate).max(MIN_WAIT),
+                ));
+            }
+        }
+
+        let waiter = self.waiting.remove(index);
+        self.granted += 1;
+        self.last_served.insert(waiter.peer, self.granted);
+
+        Ok(())
+    }
+
+    fn get_stats(&self) -> TransferLaneStats {
+        let mut stats = self.stats.clone();
+        for waiter in &self.waiting {
+            *stats.queued.entry(waiter.priority).or_default() += 1;
+        }
+
+        stats
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl Drop for Queued<'_> {
+    fn drop(&mut self) {
+        let mut state = self.scheduler.state.lock().unwrap();
+        let waiting = &mut state.lane_mut(self.direction).waiting;
+        let queued = waiting.len();
+        waiting.retain(|waiter| waiter.ticket != self.ticket);
+
+        if waiting.len() < queued {
+            drop(state);
+            self.scheduler.notify.notify_waiters();
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use futures::future;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_transfer_scheduler_orders_by_priority_and_peer() {
+        let scheduler = TransferScheduler::new(
+            &ZerofsTransferConfig::builder()
+                .upload_bytes_per_second(1000)
+                .build(),
+        );
+
+        // Overdraw the budget so the transfers below have to queue.
+        scheduler
+            .acquire(TransferDirection::Upload, "x", TransferPriority::Background)
+            .await
+            .complete(1100);
+
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let transfer = |peer: &'static str, priority| {
+            let scheduler = &scheduler;
+            let order = order.clone();
+            async move {
+                let permit = scheduler
+                    .acquire(TransferDirection::Upload, peer, priority)
+                    .await;
+                order.lock().unwrap().push(peer);
+                permit.complete(100);
+            }
+        };
+
+        future::join_all([
+            transfer("a", TransferPriority::Background),
+            transfer("a", TransferPriority::Background),
+            transfer("b", TransferPriority::Background),
+            transfer("c", TransferPriority::Interactive),
+        ])
+        .await;
+
+        // The interactive transfer goes first, then the peers take turns.
+        assert_eq!(*order.lock().unwrap(), ["c", "a", "b", "a"]);
+
+        let stats = scheduler.get_stats();
+        assert_eq!(stats.upload.bytes_per_second, Some(1000));
+        assert_eq!(stats.upload.bytes, 1500);
+        assert_eq!(stats.upload.blocks, 5);
+        assert!(stats.upload.queued.is_empty());
+        assert_eq!(stats.download, TransferLaneStats::default());
+        assert_eq!(stats.peers["a"].bytes_uploaded, 200);
+        assert_eq!(stats.peers["c"].bytes_downloaded, 0);
+    }
+}
@@ -0,0 +1,273 @@
+use std::{collections::BTreeMap, io::Cursor};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_with::serde_as;
+use tokio::io::AsyncReadExt;
+use zeroutils_store::{IpldStore, Storable};
+
+use super::{
+    current_time, Chunker, Dir, Entity, File, FsError, FsResult, Path, PathSegment, TraceResult,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The well-known path of the trash, relative to the root.
+pub const TRASH_PATH: &str = "system/trash";
+
+/// The name of the directory in the trash that holds the removed entities.
+const TRASH_ITEMS_NAME: &str = "items";
+
+/// The name of the file in the trash that records where the removed entities came from.
+const TRASH_INDEX_NAME: &str = "index";
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The record of the entities in the trash of a file tree.
+///
+/// Removed entities are kept under [`TRASH_PATH`] in the tree itself until they are restored or
+/// purged, so they stay reachable from the root and are not collected. The index is a TOML document
+/// that maps the ID of each removed entity to the [`TrashEntry`] describing it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrashIndex {
+    /// The entries in the trash by their ID.
+    #[serde(default)]
+    entries: BTreeMap<String, TrashEntry>,
+}
+
+/// Describes an entity in the trash.
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrashEntry {
+    /// The path the entity was removed from.
+    #[serde_as(as = "serde_with::DisplayFromStr")]
+    pub path: Path,
+
+    /// The time the entity was removed.
+    pub deleted_at: DateTime<Utc>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl TrashIndex {
+    /// Loads the trash index of the tree under `root`.
+    ///
+    /// A tree without a trash has an empty index.
+    pub async fn load<S>(root: &Dir<S>) -> FsResult<Self>
+    where
+        S: IpldStore + Send + Sync,
+    {
+        let path = index_path()?;
+        let file = match root.trace_entity(&path).await? {
+            TraceResult::Found {
+                entity: Entity::File(file),
+                ..
+            } => file,
+            TraceResult::Found { .. } => return Err(FsError::NotAFile(Some(path))),
+            _ => return Ok(Self::default()),
+        };
+
+        let mut content = String::new();
+        file.get_content_reader()
+            .await?
+            .read_to_string(&mut content)
+            .await?;
+
+        toml::from_str(&content).map_err(|e| FsError::InvalidTrashIndex(e.to_string()))
+    }
+
+    /// Stores the index in the trash under `root` and returns the updated root.
+    async fn store_at<S>(&self, root: &Dir<S>) -> FsResult<Dir<S>>
+    where
+        S: IpldStore + Send + Sync + 'static,
+    {
+        let content =
+            toml::to_string(self).map_err(|e| FsError::InvalidTrashIndex(e.to_string()))?;
+
+        let mut file = File::new(root.get_store().clone());
+        file.put_content(Cursor::new(content.into_bytes()), &Chunker::Store)
+            .await?;
+
+        root.link_at(&index_path()?, file.store().await?).await
+    }
+
+    /// Returns an iterator over the IDs and entries in the trash.
+    pub fn get_entries(&self) -> impl Iterator<Item = (&str, &TrashEntry)> {
+        self.entries.iter().map(|(id, entry)| (id.as_str(), entry))
+    }
+
+    /// Returns `true` if the trash is empty.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns a new ID for an entity removed at `deleted_at`.
+    fn next_id(&self, deleted_at: &DateTime<Utc>) -> String {
+        let base = deleted_at.format("%Y%m%d%H%M%S%f").to_string();
+        (0..)
+            .map(|n| format!("{base}{n}"))
+            .find(|id| !self.entries.contains_key(id))
+            .unwrap()
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Moves the entity at `path` into the trash of the tree under `root` and returns the updated root
+/// along with the ID of the entity in the trash.
+pub async fn trash_at<S>(root: &Dir<S>, path: &Path) -> FsResult<(Dir<S>, String)>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    if is_in_trash(path)? {
+        return Err(FsError::PathInTrash(path.clone()));
+    }
+
+    let (root, cid) = root.unlink_at(path).await?;
+
+    let mut index = TrashIndex::load(&root).await?;
+    let deleted_at = current_time();
+    let id = index.next_id(&deleted_at);
+
+    let root = root.link_at(&item_path(&id)?, cid).await?;
+    index.entries.insert(
+        id.clone(),
+        TrashEntry {
+            path: path.clone(),
+            deleted_at,
+        },
+    );
+
+    Ok((index.store_at(&root).await?, id))
+}
+
+/// Moves the entity most recently removed from `path` out of the trash of the tree under `root`
+/// and back to `path`, returning the updated root.
+///
+/// Fails if nothing removed from `path` is in the trash or if something else now exists at `path`.
+pub async fn restore_from_trash<S>(root: &Dir<S>, path: &Path) -> FsResult<Dir<S>>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    let mut index = TrashIndex::load(root).await?;
+    let id = index
+        .entries
+        .iter()
+        .filter(|(_, entry)| &entry.path == path)
+        .max_by_key(|(_, entry)| entry.deleted_at)
+        .map(|(id, _)| id.clone())
+        .ok_or_else(|| FsError::NotFound(path.clone()))?;
+
+    if let TraceResult::Found { .. } = root.trace_entity(path).await? {
+        return Err(FsError::PathExists(path.clone()));
+    }
+
+    let (root, cid) = root.unlink_at(&item_path(&id)?).await?;
+    let root = root.link_at(path, cid).await?;
+    index.entries.remove(&id);
+
+    index.store_at(&root).await
+}
+
+/// Permanently removes the entities that were moved into the trash of the tree under `root` before
+/// `older_than`, returning the updated root and the number of entities removed.
+pub async fn purge_trash<S>(root: &Dir<S>, older_than: DateTime<Utc>) -> FsResult<(Dir<S>, usize)>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    let mut index = TrashIndex::load(root).await?;
+    let expired = index
+        .entries
+        .iter()
+        .filter(|(_, entry)| entry.deleted_at < older_than)
+        .map(|(id, _)| id.clone())
+        .collect::<Vec<_>>();
+
+    if expired.is_empty() {
+        return Ok((root.clone(), 0));
+    }
+
+    let mut root = root.clone();
+    for id in expired.iter() {
+        root = root.unlink_at(&item_path(id)?).await?.0;
+        index.entries.remove(id);
+    }
+
+    Ok((index.store_at(&root).await?, expired.len()))
+}
+
+/// Returns `true` if `path` is inside the trash.
+fn is_in_trash(path: &Path) -> FsResult<bool> {
+    let trash: Path = TRASH_PATH.parse()?;
+    Ok(path.len() >= trash.len() && path.iter().zip(trash.iter()).all(|(a, b)| a == b))
+}
+
+/// Returns the path of the trash index.
+fn index_path() -> FsResult<Path> {
+    let mut path: Path = TRASH_PATH.parse()?;
+    path.push(PathSegment::Named(TRASH_INDEX_NAME.to_owned()));
+    Ok(path)
+}
+
+/// Returns the path of the removed entity with the given ID.
+fn item_path(id: &str) -> FsResult<Path> {
+    let mut path: Path = TRASH_PATH.parse()?;
+    path.push(PathSegment::Named(TRASH_ITEMS_NAME.to_owned()));
+    path.push(id.parse()?);
+    Ok(path)
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use zeroutils_store::MemoryStore;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_trash_restore_and_purge() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let file_cid = File::new(store.clone()).store().await?;
+        let path: Path = "public/notes".parse()?;
+        let root = Dir::new(store).link_at(&path, file_cid).await?;
+
+        // Removing moves the entity into the trash.
+        let (root, id) = trash_at(&root, &path).await?;
+        assert!(matches!(
+            root.trace_entity(&path).await?,
+            TraceResult::Incomplete { .. }
+        ));
+        let index = TrashIndex::load(&root).await?;
+        assert_eq!(index.get_entries().count(), 1);
+        assert_eq!(index.get_entries().next().unwrap().0, id);
+        assert!(trash_at(&root, &item_path(&id)?).await.is_err());
+
+        // Restoring brings it back.
+        let root = restore_from_trash(&root, &path).await?;
+        assert!(matches!(
+            root.trace_entity(&path).await?,
+            TraceResult::Found { .. }
+        ));
+        assert!(TrashIndex::load(&root).await?.is_empty());
+
+        // Purging removes it for good.
+        let (root, _) = trash_at(&root, &path).await?;
+        let (root, purged) = purge_trash(&root, Utc::now()).await?;
+        assert_eq!(purged, 1);
+        assert!(TrashIndex::load(&root).await?.is_empty());
+        assert!(restore_from_trash(&root, &path).await.is_err());
+
+        Ok(())
+    }
+}
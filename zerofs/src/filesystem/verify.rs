@@ -0,0 +1,274 @@
+use std::collections::{BTreeSet, HashSet};
+
+use serde::{Deserialize, Serialize};
+use serde_with::serde_as;
+use zeroutils_store::{ipld::cid::Cid, IpldStore, Storable};
+
+use super::{links, BlockKind, ContentChunks, Dir, Entity, FsResult, Path, RootRegistry};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The result of verifying a file tree with [`verify`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VerifyReport {
+    /// The number of entities that were checked.
+    pub entities: usize,
+
+    /// The number of blocks that were checked.
+    pub blocks: usize,
+
+    /// The blocks that are missing from the store, with the path of the entity that references
+    /// them.
+    pub missing: Vec<(Path, Cid)>,
+}
+
+/// The result of checking a store against the trees under a root with [`check_consistency`].
+#[serde_as]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConsistencyReport {
+    /// The CID of the root.
+    #[serde_as(as = "Option<serde_with::DisplayFromStr>")]
+    pub root: Option<Cid>,
+
+    /// Whether the root could be loaded as a directory.
+    pub root_readable: bool,
+
+    /// The number of blocks reachable from the root and the roots it keeps, missing ones included.
+    pub reachable: usize,
+
+    /// The number of blocks in the store, if it can list them.
+    pub stored: Option<usize>,
+
+    /// The reachable blocks that are missing from the store or cannot be read.
+    #[serde_as(as = "Vec<serde_with::DisplayFromStr>")]
+    pub missing: Vec<Cid>,
+
+    /// The blocks in the store that are not reachable, if it can list them.
+    #[serde_as(as = "Option<Vec<serde_with::DisplayFromStr>>")]
+    pub orphans: Option<Vec<Cid>>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl VerifyReport {
+    /// Returns `true` if no blocks are missing.
+    pub fn is_ok(&self) -> bool {
+        self.missing.is_empty()
+    }
+}
+
+impl ConsistencyReport {
+    /// Returns `true` if the root is readable and no reachable block is missing.
+    pub fn is_ok(&self) -> bool {
+        self.root_readable && self.missing.is_empty()
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Walks the tree under `dir` and checks that every block it references is in the store.
+///
+/// Missing blocks are collected in the report rather than failing the walk, so a single report
+/// lists everything that is broken. Entities under a missing block cannot be reached and are not
+/// checked.
+pub async fn verify<S>(dir: &Dir<S>) -> FsResult<VerifyReport>
+where
+    S: IpldStore + Send + Sync,
+{
+    let store = dir.get_store();
+    let mut report = VerifyReport {
+        entities: 1,
+        ..Default::default()
+    };
+
+    let mut pending = vec![(Path::default(), dir.clone())];
+    while let Some((path, dir)) = pending.pop() {
+        for (name, link) in dir.get_entries() {
+            let mut entity_path = path.clone();
+            entity_path.push(name.clone());
+
+            report.blocks += 1;
+            if !store.has(link.get_cid()).await {
+                report.missing.push((entity_path, *link.get_cid()));
+                continue;
+            }
+
+            report.entities += 1;
+            match dir.get_entity(name).await? {
+                Some(Entity::Dir(child)) => pending.push((entity_path, child.clone())),
+                Some(Entity::File(file)) => {
+                    let Some(content) = file.get_content() else {
+                        continue;
+                    };
+
+                    report.blocks += 1;
+                    if !store.has(content).await {
+                        report.missing.push((entity_path, *content));
+                        continue;
+                    }
+
+                    if file.is_chunked() {
+                        for chunk in ContentChunks::get_chunks(store, content).await? {
+                            report.blocks += 1;
+                            if !store.has(&chunk).await {
+                                report.missing.push((entity_path.clone(), chunk));
+                            }
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+/// Checks the store against the trees under `root`: whether the root is readable, which blocks
+/// are reachable from it and the roots it keeps, like those of its snapshots, and which of those
+/// are missing.
+///
+/// With `stored`, the blocks in the store, the blocks that are not reachable are reported as
+/// orphans. Unlike [`verify`], each block is only visited once, so trees shared between snapshots
+/// are not walked again, and missing blocks are not traced back to a path.
+pub async fn check_consistency<S>(
+    root: &Cid,
+    store: &S,
+    stored: Option<&HashSet<Cid>>,
+) -> FsResult<ConsistencyReport>
+where
+    S: IpldStore + Clone + Send + Sync,
+{
+    let mut report = ConsistencyReport {
+        root: Some(*root),
+        stored: stored.map(HashSet::len),
+        ..Default::default()
+    };
+
+    let roots = match Dir::load(root, store.clone()).await {
+        Ok(dir) => {
+            report.root_readable = true;
+            match RootRegistry::new().collect(&dir).await {
+                Ok(roots) => roots,
+                Err(e) => {
+                    tracing::warn!("Failed to collect the roots kept by {}: {}", root, e);
+                    BTreeSet::from([*root])
+                }
+            }
+        }
+        Err(_) => BTreeSet::from([*root]),
+    };
+
+    let mut seen = HashSet::new();
+    let mut pending = roots
+        .into_iter()
+        .map(|cid| (cid, BlockKind::Entity))
+        .collect::<Vec<_>>();
+    while let Some((cid, kind)) = pending.pop() {
+        if !seen.insert(cid) {
+            continue;
+        }
+
+        if !store.has(&cid).await {
+            report.missing.push(cid);
+            continue;
+        }
+
+        match links(&cid, kind, store).await {
+            Ok(links) => pending.extend(links),
+            Err(_) => report.missing.push(cid),
+        }
+    }
+
+    report.reachable = seen.len();
+    report.missing.sort();
+    report.orphans = stored.map(|stored| {
+        let mut orphans = stored.difference(&seen).copied().collect::<Vec<_>>();
+        orphans.sort();
+        orphans
+    });
+
+    Ok(report)
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use zeroutils_store::{MemoryStore, Storable};
+
+    use crate::filesystem::File;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_verify_reports_missing_blocks() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let mut file = File::new(store.clone());
+        file.set_content(Some(store.put_raw_block(b"hello".to_vec()).await?));
+        let file_cid = file.store().await?;
+
+        let root = Dir::new(store.clone())
+            .link_at(&"public/hello".parse()?, file_cid)
+            .await?;
+
+        let report = verify(&root).await?;
+        assert!(report.is_ok());
+        assert_eq!(report.entities, 3);
+        assert_eq!(report.blocks, 3);
+
+        // A file whose content was never stored.
+        let missing: Cid = "bafkreidgvpkjawlxz6sffxzwgooowe5yt7i6wsyg236mfoks77nywkptdq".parse()?;
+        let mut file = File::new(store.clone());
+        file.set_content(Some(missing));
+        let root = root
+            .link_at(&"public/broken".parse()?, file.store().await?)
+            .await?;
+
+        let report = verify(&root).await?;
+        assert_eq!(report.missing, vec![("public/broken".parse()?, missing)]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_check_consistency() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let content = store.put_raw_block(b"hello".to_vec()).await?;
+        let mut file = File::new(store.clone());
+        file.set_content(Some(content));
+        let file_cid = file.store().await?;
+
+        let root = Dir::new(store.clone())
+            .link_at(&"public/hello".parse()?, file_cid)
+            .await?;
+        let root_cid = root.store().await?;
+        let orphan = store.put_raw_block(b"orphan".to_vec()).await?;
+
+        // The tree has the root, `public`, the file and its content.
+        let stored = HashSet::from([root_cid, content, orphan]);
+        let report = check_consistency(&root_cid, &store, Some(&stored)).await?;
+        assert!(report.is_ok());
+        assert_eq!(report.reachable, 4);
+        assert_eq!(report.stored, Some(3));
+        assert_eq!(report.orphans, Some(vec![orphan]));
+
+        // A root that is not in the store is unreadable.
+        let missing: Cid = "bafyr4icul2stqrqqapx5zdebyjcfggyah5xsnt6m63aaoozshh6635euiy".parse()?;
+        let report = check_consistency(&missing, &store, None).await?;
+        assert!(!report.is_ok());
+        assert!(!report.root_readable);
+        assert_eq!(report.missing, vec![missing]);
+        assert_eq!(report.orphans, None);
+
+        Ok(())
+    }
+}
@@ -6,8 +6,16 @@
 // Exports
 //--------------------------------------------------------------------------------------------------
 
+#[cfg(feature = "blocking")]
+pub mod blocking;
+#[cfg(feature = "client")]
+pub mod client;
 pub mod config;
+pub mod embed;
 pub mod filesystem;
+#[cfg(feature = "native")]
 pub mod service;
+#[cfg(feature = "testing")]
+pub mod testing;
 #[cfg(test)]
 pub mod utils;
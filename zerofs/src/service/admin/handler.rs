@@ -0,0 +1,476 @@
+use std::{collections::BTreeMap, net::SocketAddr};
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_with::serde_as;
+use zeroutils_store::{ipld::cid::Cid, IpldStore, Storable};
+
+use crate::{
+    filesystem::{
+        self, ConsistencyReport, FsError, Ref, RefIndex, RefPrecondition, SnapshotIndex,
+        TransferStats, REFS_PATH,
+    },
+    service::{
+        FsService, Job, JobStatus, MaintenanceOutcome, MaintenanceStatus, MaintenanceTask,
+        SharedService,
+    },
+};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The status of a node as reported by the admin API.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NodeStatus {
+    /// The DID of the node.
+    pub id: String,
+
+    /// The name of the node.
+    pub name: String,
+
+    /// The CID of the root of the file tree.
+    pub root: String,
+
+    /// The number of snapshots of the file tree.
+    pub snapshots: usize,
+
+    /// Whether the file tree is read-only.
+    #[serde(default)]
+    pub read_only: bool,
+}
+
+/// A peer of a node as reported by the admin API.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerInfo {
+    /// The DID of the peer.
+    pub id: String,
+
+    /// The address of the peer.
+    pub address: SocketAddr,
+}
+
+/// The consensus state of a node as reported by the admin API.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RaftState {
+    /// The consensus term mutations are committed in.
+    pub term: u64,
+
+    /// The number of mutations committed through the service.
+    pub commit_index: u64,
+
+    /// The index of the first entry of the consensus log, if the node keeps one and it is not
+    /// empty.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log_first_index: Option<u64>,
+
+    /// The index of the last entry of the consensus log, if the node keeps one and it is not empty.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log_last_index: Option<u64>,
+}
+
+/// Whether the file tree is read-only, as reported and set through the admin API.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReadOnlyMode {
+    /// Whether the file tree is read-only.
+    pub read_only: bool,
+}
+
+/// The response to creating a snapshot.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotCreated {
+    /// The ID of the new snapshot.
+    pub id: String,
+}
+
+/// The request to point a ref at a root.
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RefUpdate {
+    /// The CID of the root to point the ref at.
+    #[serde_as(as = "serde_with::DisplayFromStr")]
+    pub root: Cid,
+
+    /// What the ref must point at for the update to go through.
+    #[serde(default)]
+    pub precondition: RefPrecondition,
+}
+
+/// The query parameters of a request to list the background jobs.
+#[derive(Debug, Deserialize)]
+pub(crate) struct JobsQuery {
+    /// Only lists the jobs with this status.
+    #[serde(default)]
+    status: Option<JobStatus>,
+}
+
+/// The query parameters of a request to drop a ref.
+#[serde_as]
+#[derive(Debug, Deserialize)]
+pub(crate) struct RefDeleteQuery {
+    /// The CID of the root the ref must point at for it to be dropped.
+    #[serde_as(as = "Option<serde_with::DisplayFromStr>")]
+    #[serde(default)]
+    expected: Option<Cid>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// This endpoint handler returns the status of the node.
+pub(crate) async fn get_status<S>(
+    State(service): State<SharedService<S>>,
+) -> Result<Json<NodeStatus>, StatusCode>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    let service = service.lock().await;
+    let root = service
+        .root_dir
+        .store()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let snapshots = SnapshotIndex::load(&service.root_dir)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(NodeStatus {
+        id: service.config.network.id.to_string(),
+        name: service.config.network.name.clone(),
+        root: root.to_string(),
+        snapshots: snapshots.len(),
+        read_only: service.is_read_only(),
+    }))
+}
+
+/// This endpoint handler returns the peers the node knows about.
+pub(crate) async fn get_peers<S>(State(service): State<SharedService<S>>) -> Json<Vec<PeerInfo>>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    let config = service.lock().await.config.clone();
+    let mut peers = config
+        .network
+        .seeds
+        .iter()
+        .map(|(id, address)| PeerInfo {
+            id: id.to_string(),
+            address: *address,
+        })
+        .collect::<Vec<_>>();
+
+    peers.sort_by(|a, b| a.id.cmp(&b.id));
+
+    Json(peers)
+}
+
+/// This endpoint handler returns the Raft state of the node: the position of the last committed
+/// mutation and the bounds of the consensus log.
+pub(crate) async fn get_raft<S>(State(service): State<SharedService<S>>) -> Json<RaftState>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    let service = service.lock().await;
+    let (term, commit_index) = service.get_commit_position();
+    let log = service.get_consensus_log();
+
+    Json(RaftState {
+        term,
+        commit_index,
+        log_first_index: log.and_then(|log| log.first_index()),
+        log_last_index: log.and_then(|log| log.last_index()),
+    })
+}
+
+/// This endpoint handler returns the configuration of the node.
+pub(crate) async fn get_config<S>(State(service): State<SharedService<S>>) -> Response
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    let config = service.lock().await.config.clone();
+    Json(&*config).into_response()
+}
+
+/// This endpoint handler returns whether the file tree is read-only.
+pub(crate) async fn get_read_only<S>(State(service): State<SharedService<S>>) -> Json<ReadOnlyMode>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    Json(ReadOnlyMode {
+        read_only: service.lock().await.is_read_only(),
+    })
+}
+
+/// This endpoint handler makes the file tree read-only, or writable again.
+pub(crate) async fn set_read_only<S>(
+    State(service): State<SharedService<S>>,
+    Json(mode): Json<ReadOnlyMode>,
+) -> Json<ReadOnlyMode>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    service.lock().await.set_read_only(mode.read_only);
+    Json(mode)
+}
+
+/// This endpoint handler returns the block transfers with peers, along with those waiting in line.
+pub(crate) async fn get_transfers<S>(State(service): State<SharedService<S>>) -> Json<TransferStats>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    Json(service.lock().await.get_transfer_scheduler().get_stats())
+}
+
+/// This endpoint handler returns the result of checking the store when the service was opened.
+pub(crate) async fn get_consistency<S>(
+    State(service): State<SharedService<S>>,
+) -> Result<Json<ConsistencyReport>, StatusCode>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    service
+        .lock()
+        .await
+        .get_consistency_report()
+        .cloned()
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// This endpoint handler returns the run history of the maintenance tasks.
+pub(crate) async fn get_maintenance<S>(
+    State(service): State<SharedService<S>>,
+) -> Json<BTreeMap<MaintenanceTask, MaintenanceStatus>>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    Json(service.lock().await.get_maintenance_status().clone())
+}
+
+/// This endpoint handler runs a maintenance task right away.
+pub(crate) async fn run_maintenance<S>(
+    State(service): State<SharedService<S>>,
+    Path(task): Path<MaintenanceTask>,
+) -> Json<MaintenanceOutcome>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    Json(service.lock().await.run_maintenance_task(task).await)
+}
+
+/// This endpoint handler returns the background jobs waiting to run, running or failed, oldest
+/// first.
+pub(crate) async fn get_jobs<S>(
+    State(service): State<SharedService<S>>,
+    Query(query): Query<JobsQuery>,
+) -> Result<Json<Vec<Job>>, StatusCode>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    let index = service
+        .lock()
+        .await
+        .get_jobs()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(
+        index
+            .get_jobs()
+            .filter(|job| query.status.is_none_or(|status| job.status == status))
+            .cloned()
+            .collect(),
+    ))
+}
+
+/// This endpoint handler returns the snapshots of the file tree.
+pub(crate) async fn get_snapshots<S>(
+    State(service): State<SharedService<S>>,
+) -> Result<Json<SnapshotIndex>, StatusCode>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    let service = service.lock().await;
+    let index = SnapshotIndex::load(&service.root_dir)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(index))
+}
+
+/// This endpoint handler takes a snapshot of the file tree.
+pub(crate) async fn create_snapshot<S>(
+    State(service): State<SharedService<S>>,
+) -> Result<(StatusCode, Json<SnapshotCreated>), StatusCode>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    let mut service = service.lock().await;
+    if service.is_read_only() {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let keep = service.config.maintenance.keep_snapshots;
+    let (root_dir, id) = filesystem::create_snapshot(&service.root_dir, keep)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    service.root_dir = root_dir;
+
+    Ok((StatusCode::CREATED, Json(SnapshotCreated { id })))
+}
+
+/// This endpoint handler drops a snapshot of the file tree.
+pub(crate) async fn delete_snapshot<S>(
+    State(service): State<SharedService<S>>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, StatusCode>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    let mut service = service.lock().await;
+    if service.is_read_only() {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    service.root_dir = filesystem::delete_snapshot(&service.root_dir, &id)
+        .await
+        .map_err(|e| match e {
+            FsError::SnapshotNotFound(_) => StatusCode::NOT_FOUND,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        })?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// This endpoint handler returns the named refs of the file tree.
+pub(crate) async fn get_refs<S>(
+    State(service): State<SharedService<S>>,
+) -> Result<Json<RefIndex>, StatusCode>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    let service = service.lock().await;
+    let index = RefIndex::load(&service.root_dir)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(index))
+}
+
+/// This endpoint handler returns a named ref of the file tree.
+pub(crate) async fn get_ref<S>(
+    State(service): State<SharedService<S>>,
+    Path(name): Path<String>,
+) -> Result<Json<Ref>, StatusCode>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    let service = service.lock().await;
+    let index = RefIndex::load(&service.root_dir)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    index
+        .get_ref(&name)
+        .cloned()
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// This endpoint handler points a named ref of the file tree at a root, creating it if needed.
+///
+/// The root must be in the store and match its configuration, otherwise the update is rejected
+/// with `400 Bad Request`. An update whose precondition does not hold, because the ref was changed
+/// since the client last saw it, is rejected with `412 Precondition Failed`.
+pub(crate) async fn update_ref<S>(
+    State(service): State<SharedService<S>>,
+    Path(name): Path<String>,
+    Json(update): Json<RefUpdate>,
+) -> Result<Json<Ref>, StatusCode>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    let mut service = service.lock().await;
+    if service.is_read_only() {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    if !service.config.store.is_consistent_with(&update.root)
+        || !service.root_dir.get_store().has(&update.root).await
+    {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    service.root_dir =
+        filesystem::update_ref(&service.root_dir, &name, update.root, &update.precondition)
+            .await
+            .map_err(|e| match e {
+                FsError::InvalidRefName(_) => StatusCode::BAD_REQUEST,
+                FsError::RefConflict(_) => StatusCode::PRECONDITION_FAILED,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            })?;
+    record_refs_update(&mut service).await?;
+
+    let index = RefIndex::load(&service.root_dir)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    index
+        .get_ref(&name)
+        .cloned()
+        .map(Json)
+        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+/// This endpoint handler drops a named ref of the file tree.
+///
+/// If the `expected` query parameter is set, the ref is only dropped while it points at that root,
+/// and is otherwise rejected with `412 Precondition Failed`.
+pub(crate) async fn delete_ref<S>(
+    State(service): State<SharedService<S>>,
+    Path(name): Path<String>,
+    Query(query): Query<RefDeleteQuery>,
+) -> Result<StatusCode, StatusCode>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    let mut service = service.lock().await;
+    if service.is_read_only() {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let precondition = query
+        .expected
+        .map_or(RefPrecondition::Any, RefPrecondition::At);
+    service.root_dir = filesystem::delete_ref(&service.root_dir, &name, &precondition)
+        .await
+        .map_err(|e| match e {
+            FsError::RefNotFound(_) => StatusCode::NOT_FOUND,
+            FsError::RefConflict(_) => StatusCode::PRECONDITION_FAILED,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        })?;
+    record_refs_update(&mut service).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Records the update of the refs in the change feed of the service.
+async fn record_refs_update<S>(service: &mut FsService<S>) -> Result<(), StatusCode>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    let path = REFS_PATH
+        .parse()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    service
+        .record_operation("update_ref", &path)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
@@ -0,0 +1,52 @@
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{header, Response, StatusCode},
+    middleware::Next,
+};
+
+use crate::service::SharedConfig;
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+const BEARER_PREFIX: &str = "Bearer ";
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Rejects admin requests that do not present the configured admin token as a bearer token.
+///
+/// Admin requests are not authenticated with capabilities, so without a configured token every
+/// request is rejected.
+pub(crate) async fn authorize(
+    State(config): State<SharedConfig>,
+    request: Request,
+    next: Next,
+) -> Result<Response<Body>, StatusCode> {
+    let expected = config.admin.token.as_deref().ok_or(StatusCode::FORBIDDEN)?;
+
+    let token = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix(BEARER_PREFIX))
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+
+    if !tokens_match(token, expected) {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
+    Ok(next.run(request).await)
+}
+
+/// Compares two tokens in time that only depends on their length.
+fn tokens_match(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |acc, (x, y)| acc | (x ^ y))
+            == 0
+}
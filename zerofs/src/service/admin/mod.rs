@@ -0,0 +1,11 @@
+mod handler;
+mod middleware;
+mod router;
+mod server;
+
+//--------------------------------------------------------------------------------------------------
+// Exports
+//--------------------------------------------------------------------------------------------------
+
+pub use handler::{NodeStatus, PeerInfo, RaftState, ReadOnlyMode, RefUpdate, SnapshotCreated};
+pub use server::*;
@@ -0,0 +1,53 @@
+use axum::{routing, Router};
+use zeroutils_store::IpldStore;
+
+use crate::service::{SharedConfig, SharedService};
+
+use super::{handler, middleware};
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+pub(crate) fn router<S>(service: SharedService<S>, config: SharedConfig) -> Router
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    Router::new()
+        .route("/status", routing::get(handler::get_status::<S>))
+        .route("/peers", routing::get(handler::get_peers::<S>))
+        .route("/raft", routing::get(handler::get_raft::<S>))
+        .route("/config", routing::get(handler::get_config::<S>))
+        .route(
+            "/read-only",
+            routing::get(handler::get_read_only::<S>).put(handler::set_read_only::<S>),
+        )
+        .route("/transfers", routing::get(handler::get_transfers::<S>))
+        .route("/consistency", routing::get(handler::get_consistency::<S>))
+        .route("/maintenance", routing::get(handler::get_maintenance::<S>))
+        .route(
+            "/maintenance/:task",
+            routing::post(handler::run_maintenance::<S>),
+        )
+        .route("/jobs", routing::get(handler::get_jobs::<S>))
+        .route(
+            "/snapshots",
+            routing::get(handler::get_snapshots::<S>).post(handler::create_snapshot::<S>),
+        )
+        .route(
+            "/snapshots/:id",
+            routing::delete(handler::delete_snapshot::<S>),
+        )
+        .route("/refs", routing::get(handler::get_refs::<S>))
+        .route(
+            "/refs/*name",
+            routing::get(handler::get_ref::<S>)
+                .put(handler::update_ref::<S>)
+                .delete(handler::delete_ref::<S>),
+        )
+        .layer(axum::middleware::from_fn_with_state(
+            config,
+            middleware::authorize,
+        ))
+        .with_state(service)
+}
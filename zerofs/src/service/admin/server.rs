@@ -0,0 +1,53 @@
+use tokio::net::TcpListener;
+use zeroutils_store::IpldStore;
+
+use crate::service::{ServiceResult, SharedService};
+
+use super::router;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// An HTTP server for operating a `zerofs` node.
+///
+/// The admin API is kept apart from the user API. It listens on its own port and is authenticated
+/// with the bearer token in the [admin configuration][crate::config::ZerofsAdminConfig] instead of
+/// capabilities. It exposes the status, peers and configuration of the node, the run history of
+/// the maintenance tasks along with a way to trigger them, and snapshot management.
+pub struct FsAdminServer<S>
+where
+    S: IpldStore,
+{
+    /// The file system service to operate.
+    service: SharedService<S>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl<S> FsAdminServer<S>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    /// Creates a new admin server for the given file system service.
+    pub fn new(service: SharedService<S>) -> Self {
+        Self { service }
+    }
+
+    /// Starts the admin server.
+    pub async fn start(&self) -> ServiceResult<()> {
+        let config = self.service.lock().await.config.clone();
+        let address = config.get_admin_address();
+
+        let router = router::router(self.service.clone(), config);
+        let listener = TcpListener::bind(address).await?;
+
+        tracing::info!("Admin server started at {}", address);
+
+        axum::serve(listener, router).await?;
+
+        Ok(())
+    }
+}
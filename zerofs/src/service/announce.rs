@@ -0,0 +1,379 @@
+use std::collections::BTreeSet;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_with::serde_as;
+use zeroutils_key::{JwsAlgName, Sign, Verify};
+use zeroutils_store::{ipld::cid::Cid, IpldStore, Storable};
+
+use crate::filesystem::current_time;
+
+use super::{FsService, ServiceError, ServiceResult};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The prefix of the bytes a root announcement is signed as, so that its signatures cannot be
+/// mistaken for signatures of anything else.
+const ANNOUNCEMENT_DOMAIN: &str = "zerofs root announcement v1";
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// A statement that a root was committed by the cluster.
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RootAnnouncement {
+    /// The CID of the committed root.
+    #[serde_as(as = "serde_with::DisplayFromStr")]
+    pub root: Cid,
+
+    /// The sequence number of the commit of the root in the change feed, so that mirrors can tell
+    /// a newer announcement from a replayed older one.
+    pub sequence: u64,
+
+    /// The CID of the root announced before this one, if any, so that mirrors can check that the
+    /// announcements they get follow on from each other.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[serde_as(as = "Option<serde_with::DisplayFromStr>")]
+    pub previous: Option<Cid>,
+
+    /// The CID of the [idempotency index][super::IdempotencyIndex] of the announcer, if a key was
+    /// ever used, so that the node taking over from it keeps replaying the recorded responses.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[serde_as(as = "Option<serde_with::DisplayFromStr>")]
+    pub idempotency: Option<Cid>,
+
+    /// The time the root was announced.
+    pub announced_at: DateTime<Utc>,
+}
+
+/// A signature of a [`RootAnnouncement`] by a cluster member.
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RootSignature {
+    /// The DID of the signer.
+    pub did: String,
+
+    /// The JWS name of the signature algorithm.
+    pub alg: String,
+
+    /// The signature of the [signed bytes][RootAnnouncement::to_signed_bytes] of the announcement.
+    #[serde_as(as = "serde_with::Bytes")]
+    pub signature: Vec<u8>,
+}
+
+/// A [`RootAnnouncement`] signed by the node that announced it and optionally countersigned by
+/// other members of the cluster.
+///
+/// Anyone who knows the DIDs of the cluster can check it with [`verify`][Self::verify] and then
+/// trust the root, and through [inclusion proofs][crate::filesystem::InclusionProof] anything under
+/// it, without trusting whoever served it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedRoot {
+    /// The announcement.
+    pub announcement: RootAnnouncement,
+
+    /// The signatures, the first one by the node that announced the root.
+    pub signatures: Vec<RootSignature>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl RootAnnouncement {
+    /// Creates an announcement of `root`, committed as `sequence` and following the announcement
+    /// of `previous`, at `announced_at`.
+    pub fn new(
+        root: Cid,
+        sequence: u64,
+        previous: Option<Cid>,
+        announced_at: DateTime<Utc>,
+    ) -> Self {
+        Self {
+            root,
+            sequence,
+            previous,
+            idempotency: None,
+            announced_at,
+        }
+    }
+
+    /// Returns the bytes the announcement is signed as.
+    ///
+    /// The idempotency index is only signed when there is one, so announcements without one are
+    /// signed as they were before it was announced.
+    pub fn to_signed_bytes(&self) -> Vec<u8> {
+        let previous = self
+            .previous
+            .as_ref()
+            .map_or_else(String::new, ToString::to_string);
+
+        let mut signed = format!(
+            "{}\n{}\n{}\n{}\n{}",
+            ANNOUNCEMENT_DOMAIN,
+            self.root,
+            self.sequence,
+            previous,
+            self.announced_at.timestamp_micros()
+        );
+        if let Some(idempotency) = &self.idempotency {
+            signed.push_str(&format!("\n{}", idempotency));
+        }
+
+        signed.into_bytes()
+    }
+}
+
+impl SignedRoot {
+    /// Signs `announcement` as the node with DID `did` and key `key`.
+    pub fn sign<K>(
+        announcement: RootAnnouncement,
+        did: impl Into<String>,
+        key: &K,
+    ) -> ServiceResult<Self>
+    where
+        K: Sign + JwsAlgName,
+    {
+        let signature = RootSignature {
+            did: did.into(),
+            alg: key.alg_name().to_owned(),
+            signature: key.sign(&announcement.to_signed_bytes())?,
+        };
+
+        Ok(Self {
+            announcement,
+            signatures: vec![signature],
+        })
+    }
+
+    /// Returns the signature of the announcement by `did` and `key`, for the node that announced it
+    /// to add with [`add_signature`][Self::add_signature].
+    pub fn countersign<K>(&self, did: impl Into<String>, key: &K) -> ServiceResult<RootSignature>
+    where
+        K: Sign + JwsAlgName,
+    {
+        Ok(RootSignature {
+            did: did.into(),
+            alg: key.alg_name().to_owned(),
+            signature: key.sign(&self.announcement.to_signed_bytes())?,
+        })
+    }
+
+    /// Adds a countersignature, in place of any earlier signature by the same DID. The signature
+    /// of the node that announced the root cannot be replaced.
+    pub fn add_signature(&mut self, signature: RootSignature) -> ServiceResult<()> {
+        match self.signatures.iter().position(|s| s.did == signature.did) {
+            Some(0) => {
+                return Err(ServiceError::InvalidRootSignature(format!(
+                    "{} announced the root and cannot countersign it",
+                    signature.did
+                )))
+            }
+            Some(i) => self.signatures[i] = signature,
+            None => self.signatures.push(signature),
+        }
+
+        Ok(())
+    }
+
+    /// Returns the DID of the node that announced the root.
+    pub fn get_announcer(&self) -> Option<&str> {
+        self.signatures.first().map(|s| s.did.as_str())
+    }
+
+    /// Checks that the announcement is signed by at least `quorum` distinct DIDs out of `members`,
+    /// the announcer included, and that every signature is valid.
+    ///
+    /// `resolve` returns the public key of a DID to check its signatures with. A signature by a DID
+    /// that is not a member, that `resolve` has no key for, or whose algorithm is not the one of the
+    /// key, fails the check.
+    pub fn verify<V>(
+        &self,
+        members: &[String],
+        quorum: usize,
+        resolve: impl Fn(&str) -> Option<V>,
+    ) -> ServiceResult<()>
+    where
+        V: Verify + JwsAlgName,
+    {
+        let invalid = |reason: String| Err(ServiceError::InvalidRootSignature(reason));
+        if self.signatures.is_empty() {
+            return invalid("the announcement is not signed".into());
+        }
+
+        let bytes = self.announcement.to_signed_bytes();
+        let mut signers = BTreeSet::new();
+        for signature in &self.signatures {
+            if !members.contains(&signature.did) {
+                return invalid(format!("{} is not a member of the cluster", signature.did));
+            }
+
+            let Some(key) = resolve(&signature.did) else {
+                return invalid(format!("no public key for {}", signature.did));
+            };
+
+            if signature.alg != key.alg_name() {
+                return invalid(format!(
+                    "{} signed with {} but has a {} key",
+                    signature.did,
+                    signature.alg,
+                    key.alg_name()
+                ));
+            }
+
+            if key.verify(&bytes, &signature.signature).is_err() {
+                return invalid(format!("bad signature by {}", signature.did));
+            }
+
+            signers.insert(signature.did.as_str());
+        }
+
+        if signers.len() < quorum {
+            return invalid(format!(
+                "signed by {} members, {} needed",
+                signers.len(),
+                quorum
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl<S> FsService<S>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    /// Signs the current root with `key`, the key of this node, and makes it the latest signed
+    /// root. The current announcement is returned as is if neither the root nor the idempotency
+    /// index has changed since.
+    ///
+    /// The sequence number is the cursor of the change feed, which is persisted along with it, so
+    /// it keeps growing across restarts and failovers. It is moved past the one of the previous
+    /// announcement if the feed has not moved since.
+    pub async fn announce_root<K>(&mut self, key: &K) -> ServiceResult<&SignedRoot>
+    where
+        K: Sign + JwsAlgName,
+    {
+        let root = self.root_dir.store().await?;
+        let cursor = self.changes.get_cursor();
+        let (sequence, previous) = match &self.signed_root {
+            Some(signed)
+                if signed.announcement.root == root
+                    && signed.announcement.idempotency == self.idempotency =>
+            {
+                return Ok(self.signed_root.as_ref().unwrap())
+            }
+            Some(signed) => (
+                cursor.max(signed.announcement.sequence + 1),
+                Some(signed.announcement.root),
+            ),
+            None => (cursor, None),
+        };
+
+        let mut announcement = RootAnnouncement::new(root, sequence, previous, current_time());
+        announcement.idempotency = self.idempotency;
+        let did = self.config.network.id.to_string();
+
+        Ok(self
+            .signed_root
+            .insert(SignedRoot::sign(announcement, did, key)?))
+    }
+
+    /// Adds the countersignature of another member of the cluster to the latest signed root.
+    pub fn countersign_root(&mut self, signature: RootSignature) -> ServiceResult<()> {
+        if !self.get_cluster_members().contains(&signature.did) {
+            return Err(ServiceError::InvalidRootSignature(format!(
+                "{} is not a member of the cluster",
+                signature.did
+            )));
+        }
+
+        self.signed_root
+            .as_mut()
+            .ok_or_else(|| ServiceError::InvalidRootSignature("no root was announced".into()))?
+            .add_signature(signature)
+    }
+
+    /// Returns the latest signed root, if a root was announced.
+    pub fn get_signed_root(&self) -> Option<&SignedRoot> {
+        self.signed_root.as_ref()
+    }
+
+    /// Returns the DIDs of the members of the cluster: this node, its seed peers and the nodes it
+    /// admitted.
+    ///
+    /// Discovered peers are not members, since anyone on the network can advertise themselves, see
+    /// [`get_discovered_peers`][Self::get_discovered_peers].
+    pub fn get_cluster_members(&self) -> Vec<String> {
+        let mut members = vec![self.config.network.id.to_string()];
+        members.extend(self.config.network.seeds.keys().map(ToString::to_string));
+        members.extend(self.admitted.keys().cloned());
+        members
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use zeroutils_did_wk::{Base, WrappedDidWebKey};
+    use zeroutils_key::{Ed25519KeyPair, GetPublicKey, IntoOwned, KeyPairGenerate};
+
+    use super::*;
+
+    #[test]
+    fn test_signed_root() -> anyhow::Result<()> {
+        let node_key = Ed25519KeyPair::generate(&mut rand::thread_rng())?;
+        let peer_key = Ed25519KeyPair::generate(&mut rand::thread_rng())?;
+        let node = WrappedDidWebKey::from_key(&node_key, Base::Base58Btc)?.to_string();
+        let peer = WrappedDidWebKey::from_key(&peer_key, Base::Base58Btc)?.to_string();
+
+        let members = vec![node.clone(), peer.clone()];
+        let resolve = |did: &str| {
+            if did == node {
+                Some(node_key.public_key().into_owned())
+            } else if did == peer {
+                Some(peer_key.public_key().into_owned())
+            } else {
+                None
+            }
+        };
+
+        let root = "bafkreihdwdcefgh4dqkjv67uzcmw7ojee6xedzdetojuzjevtenxquvyku".parse()?;
+        let mut signed = SignedRoot::sign(
+            RootAnnouncement::new(root, 0, None, Utc::now()),
+            node.clone(),
+            &node_key,
+        )?;
+        signed.verify(&members, 1, resolve)?;
+        assert!(signed.verify(&members, 2, resolve).is_err());
+
+        // A countersignature brings the announcement to quorum.
+        let countersignature = signed.countersign(peer.clone(), &peer_key)?;
+        signed.add_signature(countersignature)?;
+        signed.verify(&members, 2, resolve)?;
+
+        // Signatures by outsiders, or that do not match, fail the check.
+        assert!(signed.verify(&members[..1], 1, resolve).is_err());
+        let mut replayed = signed.clone();
+        replayed.announcement.sequence += 1;
+        assert!(replayed.verify(&members, 1, resolve).is_err());
+
+        let mut unlinked = signed.clone();
+        unlinked.announcement.previous = Some(root);
+        assert!(unlinked.verify(&members, 1, resolve).is_err());
+
+        // Signatures that claim another algorithm than the one of the key fail the check.
+        signed.signatures[1].alg = "ES256".into();
+        assert!(signed.verify(&members, 2, resolve).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,349 @@
+use std::{
+    fmt::{self, Debug},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use futures::future::BoxFuture;
+use tokio::{
+    sync::{mpsc, oneshot, Mutex},
+    time::Instant,
+};
+use typed_builder::TypedBuilder;
+use zeroutils_store::IpldStore;
+
+use crate::filesystem::{current_time, Dir, FsError, FsResult};
+
+use super::FsStateMachine;
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The default time an [`ApplyBatcher`] waits after the first operation of a batch for more to
+/// join it.
+pub const DEFAULT_BATCH_WINDOW: Duration = Duration::from_millis(2);
+
+/// The default largest number of operations in a batch.
+pub const DEFAULT_MAX_BATCH_SIZE: usize = 64;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// An operation on the file tree, which takes the current root and returns the updated one, like
+/// those given to [`FsStateMachine::apply`].
+///
+/// It may be run more than once, as operations failing with retryable errors are tried again.
+pub type BoxedOperation<S> =
+    Box<dyn Fn(Dir<S>) -> BoxFuture<'static, FsResult<Dir<S>>> + Send + Sync>;
+
+/// Packs concurrent mutations into batches, each applied to a [`FsStateMachine`] as one entry.
+///
+/// Applying each mutation as its own entry pays the cost of an entry, a proposal and a round of
+/// agreement in a cluster, for every operation. Instead, the operations submitted within
+/// [`window`][BatchOptions::window] of the first one of a batch join it, up to
+/// [`max_size`][BatchOptions::max_size] of them, and share the timestamp of the entry. They are
+/// applied in the order they were submitted, and each submitter gets the result of its own
+/// operation: one failing does not fail the others.
+///
+/// Batches never wait past the deadline of any of their operations, so latency-sensitive callers
+/// are not held back by the window. Operations whose submitter went away before the batch is
+/// applied, like one whose request timed out, are left out.
+pub struct ApplyBatcher<S>
+where
+    S: IpldStore,
+{
+    /// The state machine the batches are applied to.
+    machine: Arc<Mutex<FsStateMachine<S>>>,
+
+    /// Where operations are submitted to the task applying the batches.
+    proposals: mpsc::UnboundedSender<Proposal<S>>,
+
+    /// The counters of the batches applied.
+    counters: Arc<BatchCounters>,
+}
+
+/// Options for an [`ApplyBatcher`].
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct BatchOptions {
+    /// How long a batch waits after its first operation for more to join it.
+    #[builder(default = DEFAULT_BATCH_WINDOW)]
+    pub window: Duration,
+
+    /// The largest number of operations in a batch. A full batch is applied right away.
+    #[builder(default = DEFAULT_MAX_BATCH_SIZE)]
+    pub max_size: usize,
+}
+
+/// The metrics of the batches applied by an [`ApplyBatcher`].
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct BatchStats {
+    /// The number of batches applied.
+    pub batches: u64,
+
+    /// The number of operations applied, successfully or not.
+    pub operations: u64,
+
+    /// The number of operations left out because their submitter went away.
+    pub abandoned: u64,
+
+    /// The number of operations in the largest batch.
+    pub largest_batch: u64,
+
+    /// The total time spent applying batches.
+    pub apply_time: Duration,
+}
+
+/// An operation waiting to be applied.
+struct Proposal<S>
+where
+    S: IpldStore,
+{
+    operation: BoxedOperation<S>,
+    deadline: Option<Instant>,
+    result: oneshot::Sender<FsResult<()>>,
+}
+
+#[derive(Debug, Default)]
+struct BatchCounters {
+    batches: AtomicU64,
+    operations: AtomicU64,
+    abandoned: AtomicU64,
+    largest_batch: AtomicU64,
+    apply_micros: AtomicU64,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl<S> ApplyBatcher<S>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    /// Creates a batcher applying batches to `machine`, and spawns the task applying them. The
+    /// task stops once the batcher is dropped and the last batch is applied.
+    pub fn new(machine: FsStateMachine<S>, options: BatchOptions) -> Self {
+        let machine = Arc::new(Mutex::new(machine));
+        let counters = Arc::new(BatchCounters::default());
+        let (proposals, receiver) = mpsc::unbounded_channel();
+
+        tokio::spawn(run_batches(
+            Arc::clone(&machine),
+            receiver,
+            options,
+            Arc::clone(&counters),
+        ));
+
+        Self {
+            machine,
+            proposals,
+            counters,
+        }
+    }
+
+    /// Submits `operation` and waits for the batch it joins to be applied, returning its result.
+    ///
+    /// The batch is applied by `deadline` at the latest, if one is given, rather than at the end of
+    /// the window. Dropping the returned future before the batch is applied leaves the operation
+    /// out of it.
+    pub async fn submit(
+        &self,
+        operation: BoxedOperation<S>,
+        deadline: Option<Instant>,
+    ) -> FsResult<()> {
+        let (result, receiver) = oneshot::channel();
+        self.proposals
+            .send(Proposal {
+                operation,
+                deadline,
+                result,
+            })
+            .map_err(|_| FsError::custom(anyhow::anyhow!("apply batcher stopped")))?;
+
+        receiver.await.map_err(FsError::custom)?
+    }
+
+    /// Returns the current root of the file tree.
+    pub async fn get_root(&self) -> Dir<S> {
+        self.machine.lock().await.get_root().clone()
+    }
+
+    /// Returns the metrics of the batches applied so far.
+    pub fn get_stats(&self) -> BatchStats {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        BatchStats {
+            batches: load(&self.counters.batches),
+            operations: load(&self.counters.operations),
+            abandoned: load(&self.counters.abandoned),
+            largest_batch: load(&self.counters.largest_batch),
+            apply_time: Duration::from_micros(load(&self.counters.apply_micros)),
+        }
+    }
+}
+
+impl BatchCounters {
+    fn record(&self, operations: usize, abandoned: usize, apply_time: Duration) {
+        self.batches.fetch_add(1, Ordering::Relaxed);
+        self.operations
+            .fetch_add(operations as u64, Ordering::Relaxed);
+        self.abandoned
+            .fetch_add(abandoned as u64, Ordering::Relaxed);
+        self.largest_batch
+            .fetch_max((operations + abandoned) as u64, Ordering::Relaxed);
+        self.apply_micros
+            .fetch_add(apply_time.as_micros() as u64, Ordering::Relaxed);
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Gathers the submitted operations into batches and applies them, until the batcher is dropped.
+async fn run_batches<S>(
+    machine: Arc<Mutex<FsStateMachine<S>>>,
+    mut receiver: mpsc::UnboundedReceiver<Proposal<S>>,
+    options: BatchOptions,
+    counters: Arc<BatchCounters>,
+) where
+    S: IpldStore + Send + Sync + 'static,
+{
+    while let Some(first) = receiver.recv().await {
+        let mut flush_at = Instant::now() + options.window;
+        flush_at = first
+            .deadline
+            .map_or(flush_at, |deadline| flush_at.min(deadline));
+
+        let mut batch = vec![first];
+        while batch.len() < options.max_size.max(1) {
+            match tokio::time::timeout_at(flush_at, receiver.recv()).await {
+                Ok(Some(proposal)) => {
+                    if let Some(deadline) = proposal.deadline {
+                        flush_at = flush_at.min(deadline);
+                    }
+
+                    batch.push(proposal);
+                }
+                Ok(None) | Err(_) => break,
+            }
+        }
+
+        let (batch, abandoned): (Vec<_>, Vec<_>) = batch
+            .into_iter()
+            .partition(|proposal| !proposal.result.is_closed());
+
+        let started = Instant::now();
+        let mut machine = machine.lock().await;
+        let proposed_at = current_time();
+        let operations = batch.len();
+        for proposal in batch {
+            let result = machine
+                .apply_retrying(proposed_at, |root| (proposal.operation)(root))
+                .await;
+            let _ = proposal.result.send(result);
+        }
+        drop(machine);
+
+        let apply_time = started.elapsed();
+        counters.record(operations, abandoned.len(), apply_time);
+        tracing::debug!(
+            "applied a batch of {} operations in {:?}",
+            operations,
+            apply_time
+        );
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl<S> Debug for ApplyBatcher<S>
+where
+    S: IpldStore,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ApplyBatcher")
+            .field("counters", &self.counters)
+            .finish()
+    }
+}
+
+impl Default for BatchOptions {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use futures::{future, FutureExt};
+    use zeroutils_store::{MemoryStore, Storable};
+
+    use crate::filesystem::{File, Path};
+
+    use super::*;
+
+    /// An operation linking an empty file at `path`.
+    fn link_file(path: &str) -> BoxedOperation<MemoryStore> {
+        let path: Path = path.parse().unwrap();
+        Box::new(move |root: Dir<MemoryStore>| {
+            let path = path.clone();
+            async move {
+                let file = File::new(root.get_store().clone()).store().await?;
+                root.link_at(&path, file).await
+            }
+            .boxed()
+        })
+    }
+
+    #[tokio::test]
+    async fn test_apply_batcher_packs_concurrent_operations() -> anyhow::Result<()> {
+        let options = BatchOptions::builder()
+            .window(Duration::from_millis(50))
+            .max_size(3)
+            .build();
+        let machine = FsStateMachine::new(Dir::new(MemoryStore::default()));
+        let batcher = ApplyBatcher::new(machine, options);
+
+        // Concurrent operations share batches, and each gets its own result.
+        let failing: BoxedOperation<MemoryStore> =
+            Box::new(|_| async { Err(FsError::EmptyPath) }.boxed());
+        let results = future::join_all([
+            batcher.submit(link_file("a"), None),
+            batcher.submit(failing, None),
+            batcher.submit(link_file("b"), None),
+            batcher.submit(link_file("c"), None),
+        ])
+        .await;
+        assert!(results[0].is_ok() && results[2].is_ok() && results[3].is_ok());
+        assert!(matches!(results[1], Err(FsError::EmptyPath)));
+
+        let root = batcher.get_root().await;
+        for name in ["a", "b", "c"] {
+            assert!(root.get(&name.parse()?).is_some());
+        }
+
+        let stats = batcher.get_stats();
+        assert_eq!(stats.operations, 4);
+        assert_eq!(stats.batches, 2);
+        assert_eq!(stats.largest_batch, 3);
+
+        // A deadline cuts the window short.
+        let started = Instant::now();
+        let deadline = started + Duration::from_millis(5);
+        batcher.submit(link_file("d"), Some(deadline)).await?;
+        assert!(started.elapsed() < Duration::from_millis(50));
+
+        Ok(())
+    }
+}
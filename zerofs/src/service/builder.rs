@@ -1,22 +1,55 @@
-use std::sync::Arc;
+use std::{
+    collections::BTreeSet,
+    net::SocketAddr,
+    path::{Path as LocalPath, PathBuf},
+    sync::Arc,
+};
 
-use zeroutils_config::{network::NetworkConfig, MainConfig};
+use zeroutils_config::MainConfig;
 use zeroutils_did_wk::{Base, WrappedDidWebKey};
 use zeroutils_key::GetPublicKey;
-use zeroutils_store::IpldStore;
+use zeroutils_store::{ipld::cid::Cid, IpldStore};
 
-use crate::{config::ZerofsConfig, filesystem::Dir};
+use crate::{
+    config::{
+        ConfigLoader, ZerofsAdminConfig, ZerofsClusterConfig, ZerofsConfig, ZerofsDiscoveryConfig,
+        ZerofsIdempotencyConfig, ZerofsJobsConfig, ZerofsLimitsConfig, ZerofsLogConfig,
+        ZerofsMaintenanceConfig, ZerofsRateLimitConfig, ZerofsReplicaConfig,
+        ZerofsReplicationConfig, ZerofsSearchConfig, ZerofsStartupConfig, ZerofsStoreConfig,
+        ZerofsTimeoutsConfig, ZerofsTransferConfig, ZerofsTrashConfig, ZerofsUploadConfig,
+        ZerofsWasiConfig,
+    },
+    filesystem::Dir,
+};
 
-use super::{FsService, ServiceResult};
+use super::{DiskLog, FsService, ServiceError, ServiceResult};
 
 //--------------------------------------------------------------------------------------------------
 // Types
 //--------------------------------------------------------------------------------------------------
 
+/// A change to the configuration of the service, applied on top of the configuration file.
+pub type ConfigOverride = Box<dyn FnOnce(&mut ZerofsConfig) + Send>;
+
 /// A builder for the file system service.
+///
+/// The block store and the key of the node are required, and [`build`][Self::build] is only
+/// available once both are set, as their types are part of the type of the builder.
+///
+/// The configuration starts from the [configuration file][Self::config_path] if there is one, or
+/// from the defaults, and the [overrides][Self::config_override] and the other setters are applied
+/// on top of it in the order they are called. It is only checked when the service is built, which
+/// reports every problem found in it at once with [`ServiceError::InvalidConfig`].
 pub struct FsServiceBuilder<'a, S = (), K = ()> {
     store: S,
     key: &'a K,
+    config_path: Option<PathBuf>,
+    overrides: Vec<ConfigOverride>,
+    root: Option<Cid>,
+    consensus_log_dir: Option<PathBuf>,
+    user_address: Option<SocketAddr>,
+    peer_address: Option<SocketAddr>,
+    admin_address: Option<SocketAddr>,
 }
 
 //--------------------------------------------------------------------------------------------------
@@ -24,7 +57,7 @@ pub struct FsServiceBuilder<'a, S = (), K = ()> {
 //--------------------------------------------------------------------------------------------------
 
 impl<'a, S, K> FsServiceBuilder<'a, S, K> {
-    /// Sets the block store for the file system service
+    /// Sets the block store the file tree is kept in.
     pub fn store<T>(self, store: T) -> FsServiceBuilder<'a, T, K>
     where
         T: IpldStore,
@@ -32,6 +65,13 @@ impl<'a, S, K> FsServiceBuilder<'a, S, K> {
         FsServiceBuilder {
             store,
             key: self.key,
+            config_path: self.config_path,
+            overrides: self.overrides,
+            root: self.root,
+            consensus_log_dir: self.consensus_log_dir,
+            user_address: self.user_address,
+            peer_address: self.peer_address,
+            admin_address: self.admin_address,
         }
     }
 
@@ -40,35 +80,331 @@ impl<'a, S, K> FsServiceBuilder<'a, S, K> {
         FsServiceBuilder {
             store: self.store,
             key,
+            config_path: self.config_path,
+            overrides: self.overrides,
+            root: self.root,
+            consensus_log_dir: self.consensus_log_dir,
+            user_address: self.user_address,
+            peer_address: self.peer_address,
+            admin_address: self.admin_address,
+        }
+    }
+
+    /// Sets the TOML file the configuration is read from before the overrides are applied.
+    pub fn config_path(self, config_path: impl Into<PathBuf>) -> Self {
+        FsServiceBuilder {
+            config_path: Some(config_path.into()),
+            ..self
+        }
+    }
+
+    /// Adds a change to the configuration, applied after the configuration file and the changes
+    /// added before it.
+    pub fn config_override(
+        mut self,
+        config_override: impl FnOnce(&mut ZerofsConfig) + Send + 'static,
+    ) -> Self {
+        self.overrides.push(Box::new(config_override));
+        self
+    }
+
+    /// Sets the root the service is opened on, see [`FsService::open`]. Without one, the service
+    /// starts on an empty tree.
+    pub fn root(self, root: Cid) -> Self {
+        FsServiceBuilder {
+            root: Some(root),
+            ..self
+        }
+    }
+
+    /// Sets the directory the consensus layer keeps its log in, apart from the blocks of the tree
+    /// in the block store. The log is opened with the configured [`ZerofsLogConfig`].
+    pub fn consensus_log_dir(self, consensus_log_dir: impl Into<PathBuf>) -> Self {
+        FsServiceBuilder {
+            consensus_log_dir: Some(consensus_log_dir.into()),
+            ..self
         }
     }
+
+    /// Sets the address the user API listens on.
+    pub fn user_address(self, user_address: SocketAddr) -> Self {
+        FsServiceBuilder {
+            user_address: Some(user_address),
+            ..self
+        }
+    }
+
+    /// Sets the address the node listens on for its peers.
+    pub fn peer_address(self, peer_address: SocketAddr) -> Self {
+        FsServiceBuilder {
+            peer_address: Some(peer_address),
+            ..self
+        }
+    }
+
+    /// Sets the address the admin API listens on.
+    pub fn admin_address(self, admin_address: SocketAddr) -> Self {
+        FsServiceBuilder {
+            admin_address: Some(admin_address),
+            ..self
+        }
+    }
+
+    /// Sets the hash function and node codec the block store is expected to use.
+    pub fn store_config(self, store_config: ZerofsStoreConfig) -> Self {
+        self.config_override(move |config| config.store = store_config)
+    }
+
+    /// Sets whether and for how long removed entities are kept in the trash.
+    pub fn trash_config(self, trash_config: ZerofsTrashConfig) -> Self {
+        self.config_override(move |config| config.trash = trash_config)
+    }
+
+    /// Sets which maintenance tasks run in the background and how often.
+    pub fn maintenance_config(self, maintenance_config: ZerofsMaintenanceConfig) -> Self {
+        self.config_override(move |config| config.maintenance = maintenance_config)
+    }
+
+    /// Sets the port and token of the admin API.
+    pub fn admin_config(self, admin_config: ZerofsAdminConfig) -> Self {
+        self.config_override(move |config| config.admin = admin_config)
+    }
+
+    /// Sets the request and bandwidth quotas of the user API.
+    pub fn rate_limit_config(self, rate_limit_config: ZerofsRateLimitConfig) -> Self {
+        self.config_override(move |config| config.rate_limit = rate_limit_config)
+    }
+
+    /// Sets the size limits of uploads to the user API.
+    pub fn upload_config(self, upload_config: ZerofsUploadConfig) -> Self {
+        self.config_override(move |config| config.upload = upload_config)
+    }
+
+    /// Sets how long the responses to idempotent requests are replayed.
+    pub fn idempotency_config(self, idempotency_config: ZerofsIdempotencyConfig) -> Self {
+        self.config_override(move |config| config.idempotency = idempotency_config)
+    }
+
+    /// Sets which subtrees of the file tree the node replicates.
+    pub fn replication_config(self, replication_config: ZerofsReplicationConfig) -> Self {
+        self.config_override(move |config| config.replication = replication_config)
+    }
+
+    /// Sets the bandwidth caps of block transfers with peers.
+    pub fn transfer_config(self, transfer_config: ZerofsTransferConfig) -> Self {
+        self.config_override(move |config| config.transfer = transfer_config)
+    }
+
+    /// Sets whether the search index is maintained.
+    pub fn search_config(self, search_config: ZerofsSearchConfig) -> Self {
+        self.config_override(move |config| config.search = search_config)
+    }
+
+    /// Sets how background jobs run and are retried.
+    pub fn jobs_config(self, jobs_config: ZerofsJobsConfig) -> Self {
+        self.config_override(move |config| config.jobs = jobs_config)
+    }
+
+    /// Sets the limits on the shape of the file tree.
+    pub fn limits_config(self, limits_config: ZerofsLimitsConfig) -> Self {
+        self.config_override(move |config| config.limits = limits_config)
+    }
+
+    /// Sets whether the service is a read replica, and of which cluster.
+    pub fn replica_config(self, replica_config: ZerofsReplicaConfig) -> Self {
+        self.config_override(move |config| config.replica = replica_config)
+    }
+
+    /// Sets how the service checks the store when it is opened on a stored root.
+    pub fn startup_config(self, startup_config: ZerofsStartupConfig) -> Self {
+        self.config_override(move |config| config.startup = startup_config)
+    }
+
+    /// Sets the timeouts of the operations of the service.
+    pub fn timeouts_config(self, timeouts_config: ZerofsTimeoutsConfig) -> Self {
+        self.config_override(move |config| config.timeouts = timeouts_config)
+    }
+
+    /// Sets how the log of the consensus layer is kept on disk.
+    pub fn log_config(self, log_config: ZerofsLogConfig) -> Self {
+        self.config_override(move |config| config.log = log_config)
+    }
+
+    /// Sets the cluster the node is a member of.
+    pub fn cluster_config(self, cluster_config: ZerofsClusterConfig) -> Self {
+        self.config_override(move |config| config.cluster = cluster_config)
+    }
+
+    /// Sets how the node discovers the peers of its cluster.
+    pub fn discovery_config(self, discovery_config: ZerofsDiscoveryConfig) -> Self {
+        self.config_override(move |config| config.discovery = discovery_config)
+    }
+
+    /// Sets the directories preopened for WASI guests.
+    pub fn wasi_config(self, wasi_config: ZerofsWasiConfig) -> Self {
+        self.config_override(move |config| config.wasi = wasi_config)
+    }
+
+    /// Sets whether the file tree starts out read-only.
+    pub fn read_only(self, read_only: bool) -> Self {
+        self.config_override(move |config| config.read_only = read_only)
+    }
 }
 
 impl<'a, S, K> FsServiceBuilder<'a, S, K>
 where
-    S: IpldStore,
+    S: IpldStore + Send + Sync + 'static,
     K: GetPublicKey,
 {
     /// Builds the file system service.
-    pub fn build(self) -> ServiceResult<FsService<S>> {
-        let did = WrappedDidWebKey::from_key(self.key, Base::Base58Btc)?;
-
-        let config = ZerofsConfig {
-            network: NetworkConfig::builder().id(did).build(),
-            // interface: InterfaceConfig::builder().build(),
+    ///
+    /// Fails with [`ServiceError::InvalidConfig`], listing every problem found, if the
+    /// configuration file cannot be read, or if the configuration is invalid or does not fit the
+    /// store, the root or the listener addresses.
+    pub async fn build(self) -> ServiceResult<FsService<S>> {
+        let mut config = match &self.config_path {
+            Some(path) => read_config(path)?,
+            None => ZerofsConfig::default(),
         };
 
-        config.validate()?;
+        config.network.id = WrappedDidWebKey::from_key(self.key, Base::Base58Btc)?;
+        for config_override in self.overrides {
+            config_override(&mut config);
+        }
+
+        let mut problems = Vec::new();
+        set_listeners(
+            &mut config,
+            [self.user_address, self.peer_address, self.admin_address],
+            &mut problems,
+        );
+        check_config(&config, &mut problems);
+
+        // The store must be able to encode nodes with the configured codec.
+        if !self
+            .store
+            .get_supported_codecs()
+            .contains(&config.store.codec.into())
+        {
+            problems.push(format!(
+                "the store does not support the {:?} node codec",
+                config.store.codec
+            ));
+        }
 
-        let service = FsService {
-            root_dir: Dir::new(self.store),
-            config: Arc::new(config),
+        if let Some(root) = &self.root {
+            if !config.store.is_consistent_with(root) {
+                problems.push(format!(
+                    "root {} does not match the hash function and codec of the store",
+                    root
+                ));
+            }
+        }
+
+        if self.consensus_log_dir.is_some() && config.replica.enabled {
+            problems.push("a read replica does not keep a consensus log".to_owned());
+        }
+
+        if !problems.is_empty() {
+            return Err(ServiceError::InvalidConfig(problems));
+        }
+
+        let config = Arc::new(config);
+        let mut service = match &self.root {
+            Some(root) => FsService::open(root, self.store, config.clone(), None).await?,
+            None => FsService::new(Dir::new(self.store), config.clone()),
         };
 
+        if let Some(dir) = self.consensus_log_dir {
+            service.consensus_log = Some(DiskLog::open(dir, config.log.clone()).await?);
+        }
+
         Ok(service)
     }
 }
 
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Reads the configuration from the TOML file at `path`.
+fn read_config(path: &LocalPath) -> ServiceResult<ZerofsConfig> {
+    ConfigLoader::new()
+        .file(path)
+        .load()
+        .map_err(|e| ServiceError::InvalidConfig(vec![e.to_string()]))
+}
+
+/// Sets the host and ports of the configuration to the listener addresses that are set, which must
+/// share a host.
+fn set_listeners(
+    config: &mut ZerofsConfig,
+    [user, peer, admin]: [Option<SocketAddr>; 3],
+    problems: &mut Vec<String>,
+) {
+    let hosts = [user, peer, admin]
+        .iter()
+        .flatten()
+        .map(SocketAddr::ip)
+        .collect::<BTreeSet<_>>();
+    if hosts.len() > 1 {
+        problems.push(format!(
+            "the listener addresses must share a host, got {}",
+            hosts
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+        return;
+    }
+
+    if let Some(host) = hosts.into_iter().next() {
+        config.network.host = host;
+    }
+
+    if let Some(user) = user {
+        config.network.user_port = user.port();
+    }
+
+    if let Some(peer) = peer {
+        config.network.peer_port = peer.port();
+    }
+
+    if let Some(admin) = admin {
+        config.admin.port = admin.port();
+    }
+}
+
+/// Checks the configuration on its own, and adds the problems found to `problems`.
+fn check_config(config: &ZerofsConfig, problems: &mut Vec<String>) {
+    if let Err(e) = config.validate() {
+        problems.push(e.to_string());
+    }
+
+    let listeners = [
+        ("user API", config.network.user_port),
+        ("peer", config.network.peer_port),
+        ("admin API", config.admin.port),
+    ];
+    for (i, (name, port)) in listeners.iter().enumerate() {
+        // Port 0 lets the system pick a free port for each listener.
+        if let Some((other, _)) = listeners[..i]
+            .iter()
+            .find(|(_, other)| *port != 0 && other == port)
+        {
+            problems.push(format!(
+                "the {} and {} listeners both use port {}",
+                other, name, port
+            ));
+        }
+    }
+
+    if config.log.segment_size == 0 {
+        problems.push("the consensus log segment size must not be 0".to_owned());
+    }
+}
+
 //--------------------------------------------------------------------------------------------------
 // Trait Implementations
 //--------------------------------------------------------------------------------------------------
@@ -78,6 +414,13 @@ impl<'a> Default for FsServiceBuilder<'a> {
         FsServiceBuilder {
             store: (),
             key: &(),
+            config_path: None,
+            overrides: Vec::new(),
+            root: None,
+            consensus_log_dir: None,
+            user_address: None,
+            peer_address: None,
+            admin_address: None,
         }
     }
 }
@@ -93,18 +436,58 @@ mod tests {
 
     use super::*;
 
-    #[test]
-    fn test_fs_service_builder() -> anyhow::Result<()> {
+    #[tokio::test]
+    async fn test_fs_service_builder() -> anyhow::Result<()> {
         let keypair = Ed25519KeyPair::generate(&mut rand::thread_rng())?;
         let store = MemoryStore::default();
 
         let _fs_service = FsServiceBuilder::default()
             .store(store)
             .key(&keypair)
-            .build()?;
+            .build()
+            .await?;
 
         // TODO: Add tests for the file system service
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_fs_service_builder_validation() -> anyhow::Result<()> {
+        let keypair = Ed25519KeyPair::generate(&mut rand::thread_rng())?;
+        let builder = || {
+            FsServiceBuilder::default()
+                .store(MemoryStore::default())
+                .key(&keypair)
+        };
+
+        let service = builder()
+            .user_address("127.0.0.1:7600".parse()?)
+            .read_only(true)
+            .build()
+            .await?;
+        assert_eq!(service.config.network.user_port, 7600);
+        assert!(service.is_read_only());
+
+        // Every problem is reported at once.
+        let result = builder()
+            .user_address("127.0.0.1:7600".parse()?)
+            .admin_address("127.0.0.1:7600".parse()?)
+            .replica_config(ZerofsReplicaConfig::builder().enabled(true).build())
+            .consensus_log_dir(std::env::temp_dir().join("zerofs-unused-log"))
+            .build()
+            .await;
+        match result {
+            Err(ServiceError::InvalidConfig(problems)) => assert_eq!(problems.len(), 2),
+            _ => panic!("expected an invalid configuration"),
+        }
+
+        let result = builder()
+            .config_path(std::env::temp_dir().join("zerofs-missing-config.toml"))
+            .build()
+            .await;
+        assert!(matches!(result, Err(ServiceError::InvalidConfig(_))));
+
+        Ok(())
+    }
 }
@@ -0,0 +1,137 @@
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+use serde::{Deserialize, Serialize};
+use zeroutils_store::IpldStore;
+
+use super::{FsService, ServiceError, ServiceResult};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// How fresh the data a read is served from must be, chosen per request to trade freshness for
+/// latency.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ReadConsistency {
+    /// The read sees every write acknowledged before it started, wherever it was made.
+    Linearizable,
+
+    /// The read is served by the node holding the leader lease from its own root, without
+    /// confirming with the rest of the cluster. It only misses writes if clocks drift past the
+    /// lease.
+    LeaderLease,
+
+    /// The read is served from whatever root the node has, which may be behind the leader, like
+    /// that of a read replica.
+    Eventual,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl<S> FsService<S>
+where
+    S: IpldStore,
+{
+    /// Checks that reads with the `consistency` can be served by this node.
+    ///
+    /// The service does not run a Raft node yet, so a node that is not a read replica is the only
+    /// one taking writes, and its root is always the latest one: it serves every level. A read
+    /// replica follows roots announced by the cluster, so it only serves
+    /// [`ReadConsistency::Eventual`] reads, and fails with [`ServiceError::ConsistencyUnavailable`]
+    /// otherwise, for the client to go to the cluster instead.
+    pub fn check_consistency(&self, consistency: ReadConsistency) -> ServiceResult<()> {
+        match consistency {
+            ReadConsistency::Eventual => Ok(()),
+            _ if self.config.replica.enabled => {
+                Err(ServiceError::ConsistencyUnavailable(consistency))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl FromStr for ReadConsistency {
+    type Err = ServiceError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "linearizable" => Ok(ReadConsistency::Linearizable),
+            "leader-lease" => Ok(ReadConsistency::LeaderLease),
+            "eventual" => Ok(ReadConsistency::Eventual),
+            _ => Err(ServiceError::InvalidConsistency(s.to_owned())),
+        }
+    }
+}
+
+impl Display for ReadConsistency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReadConsistency::Linearizable => write!(f, "linearizable"),
+            ReadConsistency::LeaderLease => write!(f, "leader-lease"),
+            ReadConsistency::Eventual => write!(f, "eventual"),
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use zeroutils_store::MemoryStore;
+
+    use crate::{
+        config::{ZerofsConfig, ZerofsReplicaConfig},
+        filesystem::Dir,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_check_consistency() -> anyhow::Result<()> {
+        let levels = ["linearizable", "leader-lease", "eventual"]
+            .iter()
+            .map(|level| level.parse())
+            .collect::<Result<Vec<ReadConsistency>, _>>()?;
+        assert_eq!(levels[1].to_string(), "leader-lease");
+        assert!("strong".parse::<ReadConsistency>().is_err());
+
+        // A node that is not a replica has the latest root and serves every level.
+        let store = MemoryStore::default();
+        let primary = FsService::new(Dir::new(store.clone()), Arc::new(ZerofsConfig::default()));
+        for level in &levels {
+            primary.check_consistency(*level)?;
+        }
+
+        // A replica only serves eventual reads.
+        let config = ZerofsConfig::builder()
+            .replica(ZerofsReplicaConfig::builder().enabled(true).build())
+            .build();
+        let replica = FsService::new(Dir::new(store), Arc::new(config));
+        replica.check_consistency(ReadConsistency::Eventual)?;
+        assert!(matches!(
+            replica.check_consistency(ReadConsistency::Linearizable),
+            Err(ServiceError::ConsistencyUnavailable(
+                ReadConsistency::Linearizable
+            ))
+        ));
+        assert!(replica
+            .check_consistency(ReadConsistency::LeaderLease)
+            .is_err());
+
+        anyhow::Ok(())
+    }
+}
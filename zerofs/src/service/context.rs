@@ -0,0 +1,140 @@
+use std::fmt::{self, Display};
+
+use serde::{Deserialize, Serialize};
+use serde_with::serde_as;
+
+use crate::filesystem::{DescriptorFlags, Path};
+
+use super::{ServiceError, ServiceResult};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// What an error happened in: the operation that failed, the path it was asked for and the flags
+/// of the handle it went through.
+///
+/// Contexts are attached to errors as they cross the service boundary with
+/// [`ServiceResultExt::context`], and are kept in the [`Problem`][super::Problem] the error is
+/// returned as over HTTP.
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ErrorContext {
+    /// The name of the operation, like `write_at`.
+    pub operation: String,
+
+    /// The full path the operation was asked for, as requested.
+    #[serde_as(as = "Option<serde_with::DisplayFromStr>")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<Path>,
+
+    /// The flags of the handle the operation went through.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub flags: Option<DescriptorFlags>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Traits
+//--------------------------------------------------------------------------------------------------
+
+/// Attaches an [`ErrorContext`] to the error of a result.
+pub trait ServiceResultExt<T> {
+    /// Attaches the context returned by `context` to the error, if there is one.
+    ///
+    /// Contexts attached to an error that already has some are added as breadcrumbs, so an error
+    /// from a nested operation says which operations it went through.
+    fn context(self, context: impl FnOnce() -> ErrorContext) -> ServiceResult<T>;
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl ErrorContext {
+    /// Creates the context of the operation named `operation`.
+    pub fn new(operation: impl Into<String>) -> Self {
+        Self {
+            operation: operation.into(),
+            path: None,
+            flags: None,
+        }
+    }
+
+    /// Sets the path the operation was asked for.
+    pub fn path(mut self, path: &Path) -> Self {
+        self.path = Some(path.clone());
+        self
+    }
+
+    /// Sets the flags of the handle the operation went through.
+    pub fn flags(mut self, flags: DescriptorFlags) -> Self {
+        self.flags = Some(flags);
+        self
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl<T, E> ServiceResultExt<T> for Result<T, E>
+where
+    E: Into<ServiceError>,
+{
+    fn context(self, context: impl FnOnce() -> ErrorContext) -> ServiceResult<T> {
+        self.map_err(|e| e.into().with_context(context()))
+    }
+}
+
+impl Display for ErrorContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.operation)?;
+        if let Some(path) = &self.path {
+            write!(f, " at {}", path)?;
+        }
+
+        if let Some(flags) = &self.flags {
+            write!(f, " with {:?}", flags)?;
+        }
+
+        Ok(())
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::filesystem::{ErrorCode, FsError};
+
+    use super::*;
+
+    #[test]
+    fn test_error_context_breadcrumbs() -> anyhow::Result<()> {
+        let path: Path = "public/notes".parse()?;
+        let result: Result<(), _> = Err(FsError::NotFound(path.clone()));
+        let error = result
+            .context(|| ErrorContext::new("resolve_handle").flags(DescriptorFlags::READ))
+            .context(|| ErrorContext::new("read_at").path(&path))
+            .unwrap_err();
+
+        // The error keeps its code, and says what it went through from the innermost operation out.
+        assert_eq!(error.code(), ErrorCode::NotFound);
+        assert!(matches!(
+            error.root_cause(),
+            ServiceError::FsError(FsError::NotFound(_))
+        ));
+
+        let breadcrumbs = error.get_breadcrumbs();
+        assert_eq!(breadcrumbs.len(), 2);
+        assert_eq!(breadcrumbs[0].flags, Some(DescriptorFlags::READ));
+        assert!(error
+            .to_string()
+            .starts_with(&format!("read_at at {}: resolve_handle with", path)));
+        assert_eq!(breadcrumbs[1].path, Some(path));
+
+        anyhow::Ok(())
+    }
+}
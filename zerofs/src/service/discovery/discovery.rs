@@ -0,0 +1,223 @@
+use std::{collections::BTreeMap, net::SocketAddr, time::Duration};
+
+use tokio::net::TcpStream;
+use zeroutils_key::Verify;
+use zeroutils_store::IpldStore;
+
+use crate::{
+    config::ZerofsDiscoveryConfig,
+    service::{
+        handshake, verify_peer_identity, FsService, PeerHello, PeerSession, ServiceError,
+        ServiceResult, SharedService,
+    },
+};
+
+use super::{DnsDiscovery, MdnsAdvertiser, MdnsDiscovery};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// A peer found by a discovery provider, not verified yet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiscoveredPeer {
+    /// The DID the peer was advertised with.
+    pub did: String,
+
+    /// The address the peer takes peer connections on.
+    pub addr: SocketAddr,
+
+    /// The provider that found the peer.
+    pub source: DiscoverySource,
+}
+
+/// A provider of [`DiscoveredPeer`]s.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiscoverySource {
+    /// The SRV records of a DNS name.
+    Dns,
+
+    /// mDNS on the local network.
+    Mdns,
+}
+
+/// The discovery providers [configured][ZerofsDiscoveryConfig] for a node.
+#[derive(Debug, Clone, Default)]
+pub struct PeerDiscovery {
+    /// The DNS provider, if it is enabled.
+    dns: Option<DnsDiscovery>,
+
+    /// The mDNS provider, if it is enabled.
+    mdns: Option<MdnsDiscovery>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl PeerDiscovery {
+    /// Creates the providers enabled in `config`.
+    pub fn from_config(config: &ZerofsDiscoveryConfig) -> ServiceResult<Self> {
+        let timeout = Duration::from_millis(config.timeout);
+        let dns = match &config.dns {
+            Some(name) => Some(DnsDiscovery::new(name, config.dns_server, timeout)?),
+            None => None,
+        };
+
+        let mdns = config
+            .mdns
+            .then(|| MdnsDiscovery::new(&config.mdns_service, timeout));
+
+        Ok(Self { dns, mdns })
+    }
+
+    /// Returns `true` if any provider is enabled.
+    pub fn is_enabled(&self) -> bool {
+        self.dns.is_some() || self.mdns.is_some()
+    }
+
+    /// Returns the peers found by all the providers. A provider that fails is logged and skipped,
+    /// so that the others still count.
+    pub async fn discover(&self) -> Vec<DiscoveredPeer> {
+        let mut peers = Vec::new();
+        if let Some(dns) = &self.dns {
+            match dns.discover().await {
+                Ok(found) => peers.extend(found),
+                Err(e) => tracing::warn!("DNS peer discovery failed: {}", e),
+            }
+        }
+
+        if let Some(mdns) = &self.mdns {
+            match mdns.discover().await {
+                Ok(found) => peers.extend(found),
+                Err(e) => tracing::warn!("mDNS peer discovery failed: {}", e),
+            }
+        }
+
+        peers
+    }
+}
+
+impl<S> FsService<S>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    /// Returns the peer addresses of the discovered peers that proved their DID, by DID.
+    ///
+    /// They are candidates to connect to, not members of the cluster: DNS and mDNS answers are not
+    /// authenticated, so anyone on the network can advertise a key of their own. Peers become
+    /// members as seeds or by being [admitted][Self::admit_member].
+    pub fn get_discovered_peers(&self) -> &BTreeMap<String, SocketAddr> {
+        &self.discovered
+    }
+
+    /// Returns `true` if `peer` is a member of the cluster, or was discovered, at its address.
+    pub fn is_known_peer(&self, peer: &DiscoveredPeer) -> bool {
+        self.get_member_addresses().get(&peer.did) == Some(&peer.addr)
+            || self.discovered.get(&peer.did) == Some(&peer.addr)
+    }
+
+    /// Adds a discovered peer to the candidates to connect to, and returns `true` if it was not
+    /// known at that address already.
+    ///
+    /// The peer must have proven its DID first, see [`connect_peer`]. It does not become a member of
+    /// the cluster, see [`get_discovered_peers`][Self::get_discovered_peers].
+    pub fn add_discovered_peer(&mut self, peer: &DiscoveredPeer) -> bool {
+        if self.is_known_peer(peer) {
+            return false;
+        }
+
+        tracing::info!(
+            "adding {} at {}, discovered with {:?}",
+            peer.did,
+            peer.addr,
+            peer.source
+        );
+        self.discovered.insert(peer.did.clone(), peer.addr);
+        true
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Connects to a discovered peer, runs the [handshake] with `hello` and checks that the peer holds
+/// the key of the DID it was advertised with, see [`verify_peer_identity`].
+///
+/// The peer is expected to answer the challenge with
+/// [`prove_identity`][crate::service::prove_identity] right after the handshake.
+pub async fn connect_peer<V>(
+    peer: &DiscoveredPeer,
+    hello: &PeerHello,
+    resolve: impl Fn(&str) -> Option<V>,
+) -> ServiceResult<(TcpStream, PeerSession)>
+where
+    V: Verify,
+{
+    let mut stream = TcpStream::connect(peer.addr).await?;
+    let session = handshake(&mut stream, hello).await?;
+    verify_peer_identity(&mut stream, &peer.did, resolve).await?;
+
+    Ok((stream, session))
+}
+
+/// Runs the [configured][ZerofsDiscoveryConfig] discovery of the peers of the node of `service`
+/// until an error occurs, adding the peers that prove their DID to its candidates to connect to.
+///
+/// When mDNS is enabled, the node is also advertised on the local network. `resolve` returns the
+/// public key of a DID. Peers that cannot be reached or do not prove their DID are skipped until
+/// the next round.
+pub async fn run_discovery<S, V>(
+    service: SharedService<S>,
+    hello: PeerHello,
+    resolve: impl Fn(&str) -> Option<V>,
+) -> ServiceResult<()>
+where
+    S: IpldStore + Send + Sync + 'static,
+    V: Verify,
+{
+    let config = service.lock().await.config.clone();
+    let discovery = PeerDiscovery::from_config(&config.discovery)?;
+    if !discovery.is_enabled() {
+        return Ok(());
+    }
+
+    let did = config.network.id.to_string();
+    if config.discovery.mdns {
+        let addr = SocketAddr::new(config.network.host, config.network.peer_port);
+        let advertiser = MdnsAdvertiser::new(&config.discovery.mdns_service, did.clone(), addr);
+        tokio::spawn(async move {
+            if let Err(e) = advertiser.run().await {
+                tracing::warn!("mDNS advertising stopped: {}", e);
+            }
+        });
+    }
+
+    let timeout = Duration::from_millis(config.discovery.timeout);
+    let mut interval = tokio::time::interval(Duration::from_secs(config.discovery.interval.max(1)));
+    loop {
+        interval.tick().await;
+        for peer in discovery.discover().await {
+            if peer.did == did || service.lock().await.is_known_peer(&peer) {
+                continue;
+            }
+
+            let connected = tokio::time::timeout(timeout, connect_peer(&peer, &hello, &resolve))
+                .await
+                .unwrap_or_else(|_| {
+                    Err(ServiceError::DiscoveryFailed(format!(
+                        "timed out connecting to {}",
+                        peer.addr
+                    )))
+                });
+
+            match connected {
+                Ok(_) => {
+                    service.lock().await.add_discovered_peer(&peer);
+                }
+                Err(e) => tracing::warn!("skipping discovered peer {}: {}", peer.did, e),
+            }
+        }
+    }
+}
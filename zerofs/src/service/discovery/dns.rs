@@ -0,0 +1,570 @@
+use std::{
+    convert::TryInto,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    time::Duration,
+};
+
+use tokio::net::UdpSocket;
+
+use crate::service::{ServiceError, ServiceResult};
+
+use super::{DiscoveredPeer, DiscoverySource};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The type of A records.
+pub(crate) const TYPE_A: u16 = 1;
+
+/// The type of PTR records.
+pub(crate) const TYPE_PTR: u16 = 12;
+
+/// The type of TXT records.
+pub(crate) const TYPE_TXT: u16 = 16;
+
+/// The type of AAAA records.
+pub(crate) const TYPE_AAAA: u16 = 28;
+
+/// The type of SRV records.
+pub(crate) const TYPE_SRV: u16 = 33;
+
+/// The internet class.
+pub(crate) const CLASS_IN: u16 = 1;
+
+/// The bit of the class of an mDNS question asking for the answer to be sent to the sender.
+const UNICAST_RESPONSE: u16 = 0x8000;
+
+/// The largest DNS message read, in bytes.
+pub(crate) const MAX_MESSAGE_SIZE: usize = 9000;
+
+/// The number of times a unicast lookup is sent before giving up.
+const DNS_ATTEMPTS: u32 = 2;
+
+/// The largest number of compression pointers followed while reading a name, so that a message
+/// pointing in circles is rejected.
+const MAX_NAME_JUMPS: usize = 16;
+
+/// The TXT attribute holding the DID of a peer.
+pub(crate) const DID_ATTRIBUTE: &str = "did";
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Discovers peers through the SRV records of a DNS name, for clusters spread over the internet.
+///
+/// Each SRV record of the name points at a peer, whose address is found in the A and AAAA records
+/// of its target and whose DID is in the `did=<did>` TXT record of its target.
+#[derive(Debug, Clone)]
+pub struct DnsDiscovery {
+    /// The DNS name whose SRV records point at the peers.
+    name: String,
+
+    /// The DNS server the lookups are sent to.
+    server: SocketAddr,
+
+    /// How long a lookup waits for an answer.
+    timeout: Duration,
+}
+
+/// A DNS message, with only what discovery needs.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct DnsMessage {
+    /// The ID matching answers to queries.
+    pub id: u16,
+
+    /// Whether the message is an answer.
+    pub response: bool,
+
+    /// Whether the questions ask mDNS responders to answer to the sender rather than to the group.
+    pub unicast: bool,
+
+    /// The questions, as names and types.
+    pub questions: Vec<(String, u16)>,
+
+    /// The records of all sections.
+    pub records: Vec<DnsRecord>,
+}
+
+/// A resource record of a [`DnsMessage`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct DnsRecord {
+    /// The name the record is about.
+    pub name: String,
+
+    /// How long the record can be cached, in seconds.
+    pub ttl: u32,
+
+    /// The data of the record.
+    pub data: RecordData,
+}
+
+/// The data of a [`DnsRecord`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum RecordData {
+    /// An IPv4 address.
+    A(Ipv4Addr),
+
+    /// An IPv6 address.
+    Aaaa(Ipv6Addr),
+
+    /// A pointer to another name, like an instance of a service.
+    Ptr(String),
+
+    /// Text attributes.
+    Txt(Vec<String>),
+
+    /// The host and port of a service.
+    Srv {
+        priority: u16,
+        weight: u16,
+        port: u16,
+        target: String,
+    },
+
+    /// A record of a type discovery does not use.
+    Other(u16),
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl DnsDiscovery {
+    /// Creates a discovery of the peers behind the SRV records of `name`, sending lookups to
+    /// `server`, or to the first name server of the system if `None`.
+    pub fn new(
+        name: impl Into<String>,
+        server: Option<SocketAddr>,
+        timeout: Duration,
+    ) -> ServiceResult<Self> {
+        let server = match server {
+            Some(server) => server,
+            None => system_name_server()?,
+        };
+
+        Ok(Self {
+            name: name.into(),
+            server,
+            timeout,
+        })
+    }
+
+    /// Looks up the peers, in the order of the priorities of their SRV records.
+    ///
+    /// Targets without an address or without a DID are skipped.
+    pub async fn discover(&self) -> ServiceResult<Vec<DiscoveredPeer>> {
+        let answer = self.query(&self.name, TYPE_SRV).await?;
+        let mut targets = answer
+            .records
+            .iter()
+            .filter(|record| names_match(&record.name, &self.name))
+            .filter_map(|record| match &record.data {
+                RecordData::Srv {
+                    priority,
+                    weight,
+                    port,
+                    target,
+                } => Some((*priority, u16::MAX - *weight, target.clone(), *port)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        targets.sort();
+
+        let mut peers = Vec::new();
+        for (_, _, target, port) in targets {
+            // Servers often send the records of the targets along with the SRV records.
+            let mut records = answer.records.clone();
+            if find_did(&records, &target).is_none() {
+                records.extend(self.query(&target, TYPE_TXT).await?.records);
+            }
+
+            if find_ip(&records, &target).is_none() && target.parse::<IpAddr>().is_err() {
+                records.extend(self.query(&target, TYPE_A).await?.records);
+                records.extend(self.query(&target, TYPE_AAAA).await?.records);
+            }
+
+            let ip = target.parse::<IpAddr>().ok().or(find_ip(&records, &target));
+            match (find_did(&records, &target), ip) {
+                (Some(did), Some(ip)) => peers.push(DiscoveredPeer {
+                    did,
+                    addr: SocketAddr::new(ip, port),
+                    source: DiscoverySource::Dns,
+                }),
+                _ => tracing::debug!("skipping {} without a DID or an address", target),
+            }
+        }
+
+        Ok(peers)
+    }
+
+    /// Sends a query for the records of `name` of type `kind` and returns the answer.
+    async fn query(&self, name: &str, kind: u16) -> ServiceResult<DnsMessage> {
+        let bind: SocketAddr = match self.server {
+            SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+            SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+        };
+        let socket = UdpSocket::bind(bind).await?;
+        socket.connect(self.server).await?;
+
+        let query = DnsMessage::query(rand::random(), name, kind, false);
+        let mut buf = vec![0; MAX_MESSAGE_SIZE];
+        for _ in 0..DNS_ATTEMPTS {
+            socket.send(&query.encode()).await?;
+            let deadline = tokio::time::Instant::now() + self.timeout;
+            while let Ok(received) = tokio::time::timeout_at(deadline, socket.recv(&mut buf)).await
+            {
+                match DnsMessage::decode(&buf[..received?]) {
+                    Some(answer) if answer.response && answer.id == query.id => return Ok(answer),
+                    _ => continue,
+                }
+            }
+        }
+
+        Err(ServiceError::DiscoveryFailed(format!(
+            "no answer from {} for {}",
+            self.server, name
+        )))
+    }
+}
+
+impl DnsMessage {
+    /// Creates a query for the records of `name` of type `kind`. `unicast` asks mDNS responders to
+    /// answer to the sender rather than to the group.
+    pub fn query(id: u16, name: &str, kind: u16, unicast: bool) -> Self {
+        Self {
+            id,
+            response: false,
+            unicast,
+            questions: vec![(name.to_owned(), kind)],
+            records: Vec::new(),
+        }
+    }
+
+    /// Encodes the message, with the records as answers. Names are not compressed.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        let flags: u16 = if self.response { 0x8400 } else { 0x0100 };
+        for value in [
+            self.id,
+            flags,
+            self.questions.len() as u16,
+            self.records.len() as u16,
+            0,
+            0,
+        ] {
+            out.extend_from_slice(&value.to_be_bytes());
+        }
+
+        let class = if self.unicast {
+            CLASS_IN | UNICAST_RESPONSE
+        } else {
+            CLASS_IN
+        };
+        for (name, kind) in &self.questions {
+            encode_name(name, &mut out);
+            out.extend_from_slice(&kind.to_be_bytes());
+            out.extend_from_slice(&class.to_be_bytes());
+        }
+
+        for record in &self.records {
+            encode_name(&record.name, &mut out);
+            let mut data = Vec::new();
+            let kind = match &record.data {
+                RecordData::A(ip) => {
+                    data.extend_from_slice(&ip.octets());
+                    TYPE_A
+                }
+                RecordData::Aaaa(ip) => {
+                    data.extend_from_slice(&ip.octets());
+                    TYPE_AAAA
+                }
+                RecordData::Ptr(name) => {
+                    encode_name(name, &mut data);
+                    TYPE_PTR
+                }
+                RecordData::Txt(strings) => {
+                    for string in strings {
+                        let bytes = &string.as_bytes()[..string.len().min(255)];
+                        data.push(bytes.len() as u8);
+                        data.extend_from_slice(bytes);
+                    }
+                    TYPE_TXT
+                }
+                RecordData::Srv {
+                    priority,
+                    weight,
+                    port,
+                    target,
+                } => {
+                    data.extend_from_slice(&priority.to_be_bytes());
+                    data.extend_from_slice(&weight.to_be_bytes());
+                    data.extend_from_slice(&port.to_be_bytes());
+                    encode_name(target, &mut data);
+                    TYPE_SRV
+                }
+                RecordData::Other(kind) => *kind,
+            };
+
+            out.extend_from_slice(&kind.to_be_bytes());
+            out.extend_from_slice(&CLASS_IN.to_be_bytes());
+            out.extend_from_slice(&record.ttl.to_be_bytes());
+            out.extend_from_slice(&(data.len() as u16).to_be_bytes());
+            out.extend_from_slice(&data);
+        }
+
+        out
+    }
+
+    /// Decodes a message, or returns `None` if it is malformed.
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        let read_u16 = |at: usize| -> Option<u16> {
+            Some(u16::from_be_bytes(bytes.get(at..at + 2)?.try_into().ok()?))
+        };
+
+        let id = read_u16(0)?;
+        let flags = read_u16(2)?;
+        let counts = [read_u16(4)?, read_u16(6)?, read_u16(8)?, read_u16(10)?];
+
+        let mut at = 12;
+        let mut questions = Vec::new();
+        let mut unicast = false;
+        for _ in 0..counts[0] {
+            let (name, next) = decode_name(bytes, at)?;
+            questions.push((name, read_u16(next)?));
+            unicast |= read_u16(next + 2)? & UNICAST_RESPONSE != 0;
+            at = next + 4;
+        }
+
+        let mut records = Vec::new();
+        for _ in 0..counts[1] as usize + counts[2] as usize + counts[3] as usize {
+            let (name, next) = decode_name(bytes, at)?;
+            let kind = read_u16(next)?;
+            let ttl = u32::from_be_bytes(bytes.get(next + 4..next + 8)?.try_into().ok()?);
+            let len = read_u16(next + 8)? as usize;
+            let start = next + 10;
+            let data = bytes.get(start..start + len)?;
+
+            let data = match kind {
+                TYPE_A => {
+                    let octets: [u8; 4] = data.try_into().ok()?;
+                    RecordData::A(octets.into())
+                }
+                TYPE_AAAA => {
+                    let octets: [u8; 16] = data.try_into().ok()?;
+                    RecordData::Aaaa(octets.into())
+                }
+                TYPE_PTR => RecordData::Ptr(decode_name(bytes, start)?.0),
+                TYPE_TXT => {
+                    let mut strings = Vec::new();
+                    let mut i = 0;
+                    while i < data.len() {
+                        let len = data[i] as usize;
+                        let string = data.get(i + 1..i + 1 + len)?;
+                        strings.push(String::from_utf8_lossy(string).into_owned());
+                        i += 1 + len;
+                    }
+                    RecordData::Txt(strings)
+                }
+                TYPE_SRV => RecordData::Srv {
+                    priority: read_u16(start)?,
+                    weight: read_u16(start + 2)?,
+                    port: read_u16(start + 4)?,
+                    target: decode_name(bytes, start + 6)?.0,
+                },
+                kind => RecordData::Other(kind),
+            };
+
+            records.push(DnsRecord { name, ttl, data });
+            at = start + len;
+        }
+
+        Some(Self {
+            id,
+            response: flags & 0x8000 != 0,
+            unicast,
+            questions,
+            records,
+        })
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Returns the first name server of the system, from `/etc/resolv.conf`.
+fn system_name_server() -> ServiceResult<SocketAddr> {
+    let content = std::fs::read_to_string("/etc/resolv.conf")?;
+    content
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("nameserver"))
+        .find_map(|server| server.trim().parse::<IpAddr>().ok())
+        .map(|ip| SocketAddr::new(ip, 53))
+        .ok_or_else(|| ServiceError::DiscoveryFailed("no name server configured".into()))
+}
+
+/// Returns `true` if `a` and `b` are the same DNS name, which are not case sensitive.
+pub(crate) fn names_match(a: &str, b: &str) -> bool {
+    a.trim_end_matches('.')
+        .eq_ignore_ascii_case(b.trim_end_matches('.'))
+}
+
+/// Returns the DID in the TXT records of `name` among `records`.
+pub(crate) fn find_did(records: &[DnsRecord], name: &str) -> Option<String> {
+    records
+        .iter()
+        .filter(|record| names_match(&record.name, name))
+        .find_map(|record| match &record.data {
+            RecordData::Txt(strings) => strings.iter().find_map(|string| {
+                let (key, value) = string.split_once('=')?;
+                (key == DID_ATTRIBUTE).then(|| value.to_owned())
+            }),
+            _ => None,
+        })
+}
+
+/// Returns the address in the A or AAAA records of `name` among `records`, preferring IPv4.
+pub(crate) fn find_ip(records: &[DnsRecord], name: &str) -> Option<IpAddr> {
+    let mut ips = records
+        .iter()
+        .filter(|record| names_match(&record.name, name))
+        .filter_map(|record| match record.data {
+            RecordData::A(ip) => Some(IpAddr::V4(ip)),
+            RecordData::Aaaa(ip) => Some(IpAddr::V6(ip)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    ips.sort_by_key(|ip| ip.is_ipv6());
+    ips.first().copied()
+}
+
+/// Appends `name` to `out` as a sequence of labels. Labels longer than DNS allows are cut.
+fn encode_name(name: &str, out: &mut Vec<u8>) {
+    for label in name.trim_end_matches('.').split('.') {
+        if label.is_empty() {
+            continue;
+        }
+
+        let bytes = &label.as_bytes()[..label.len().min(63)];
+        out.push(bytes.len() as u8);
+        out.extend_from_slice(bytes);
+    }
+
+    out.push(0);
+}
+
+/// Reads the name at `at` in `bytes`, following compression pointers, and returns it with the
+/// offset right after it.
+fn decode_name(bytes: &[u8], mut at: usize) -> Option<(String, usize)> {
+    let mut labels = Vec::new();
+    let mut end = None;
+    let mut jumps = 0;
+    loop {
+        let len = *bytes.get(at)? as usize;
+        match len {
+            0 => {
+                end.get_or_insert(at + 1);
+                break;
+            }
+            len if len & 0xC0 == 0xC0 => {
+                jumps += 1;
+                if jumps > MAX_NAME_JUMPS {
+                    return None;
+                }
+
+                end.get_or_insert(at + 2);
+                at = ((len & 0x3F) << 8) | *bytes.get(at + 1)? as usize;
+            }
+            len => {
+                let label = bytes.get(at + 1..at + 1 + len)?;
+                labels.push(String::from_utf8_lossy(label).into_owned());
+                at += 1 + len;
+            }
+        }
+    }
+
+    Some((labels.join("."), end?))
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dns_message_roundtrip() -> anyhow::Result<()> {
+        let name = "_zerofs._tcp.example.com";
+        let answer = DnsMessage {
+            id: 7,
+            response: true,
+            unicast: false,
+            questions: vec![(name.into(), TYPE_SRV)],
+            records: vec![
+                DnsRecord {
+                    name: name.into(),
+                    ttl: 60,
+                    data: RecordData::Srv {
+                        priority: 10,
+                        weight: 5,
+                        port: 6611,
+                        target: "node1.example.com".into(),
+                    },
+                },
+                DnsRecord {
+                    name: "node1.example.com".into(),
+                    ttl: 60,
+                    data: RecordData::Txt(vec!["v=1".into(), "did=did:wk:abc".into()]),
+                },
+                DnsRecord {
+                    name: "node1.example.com".into(),
+                    ttl: 60,
+                    data: RecordData::Aaaa(Ipv6Addr::LOCALHOST),
+                },
+                DnsRecord {
+                    name: "node1.example.com".into(),
+                    ttl: 60,
+                    data: RecordData::A(Ipv4Addr::new(10, 0, 0, 1)),
+                },
+            ],
+        };
+
+        let decoded = DnsMessage::decode(&answer.encode()).unwrap();
+        assert_eq!(decoded, answer);
+        assert_eq!(
+            find_did(&decoded.records, "NODE1.example.com."),
+            Some("did:wk:abc".into())
+        );
+        assert_eq!(
+            find_ip(&decoded.records, "node1.example.com"),
+            Some(Ipv4Addr::new(10, 0, 0, 1).into())
+        );
+
+        // Compressed names are followed, but pointers in circles are refused.
+        let mut compressed = DnsMessage::query(1, "a.example.com", TYPE_PTR, false).encode();
+        compressed[7] = 1;
+        compressed.extend_from_slice(&[0xC0, 12]);
+        compressed.extend_from_slice(&TYPE_PTR.to_be_bytes());
+        compressed.extend_from_slice(&CLASS_IN.to_be_bytes());
+        compressed.extend_from_slice(&60u32.to_be_bytes());
+        compressed.extend_from_slice(&4u16.to_be_bytes());
+        compressed.extend_from_slice(&[1, b'b', 0xC0, 12]);
+        let decoded = DnsMessage::decode(&compressed).unwrap();
+        assert_eq!(decoded.records[0].name, "a.example.com");
+        assert_eq!(
+            decoded.records[0].data,
+            RecordData::Ptr("b.a.example.com".into())
+        );
+
+        let mut circular = compressed.clone();
+        let at = circular.len() - 2;
+        circular[at + 1] = at as u8;
+        assert!(DnsMessage::decode(&circular).is_none());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,309 @@
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4},
+    time::Duration,
+};
+
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::UdpSocket;
+
+use crate::service::ServiceResult;
+
+use super::{
+    dns::{
+        find_did, find_ip, names_match, DnsMessage, DnsRecord, RecordData, DID_ATTRIBUTE,
+        MAX_MESSAGE_SIZE, TYPE_PTR,
+    },
+    DiscoveredPeer, DiscoverySource,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The multicast group of mDNS.
+pub const MDNS_GROUP: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+
+/// The port of mDNS.
+pub const MDNS_PORT: u16 = 5353;
+
+/// How long the records advertised by an [`MdnsAdvertiser`] can be cached, in seconds.
+const MDNS_TTL: u32 = 120;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Discovers the peers advertised with mDNS on the local network, for development clusters.
+///
+/// Peers are advertised as instances of a DNS-SD service, with the DID of the peer in the
+/// `did=<did>` TXT record of the instance, by an [`MdnsAdvertiser`].
+#[derive(Debug, Clone)]
+pub struct MdnsDiscovery {
+    /// The service the peers are advertised as, like `_zerofs._tcp.local`.
+    service: String,
+
+    /// How long a lookup collects answers for.
+    window: Duration,
+}
+
+/// Advertises this node on the local network, answering the mDNS queries for its service.
+#[derive(Debug, Clone)]
+pub struct MdnsAdvertiser {
+    /// The service the node is advertised as.
+    service: String,
+
+    /// The DID of the node.
+    did: String,
+
+    /// The address the node takes peer connections on.
+    addr: SocketAddr,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl MdnsDiscovery {
+    /// Creates a discovery of the instances of `service`, collecting the answers for `window`.
+    pub fn new(service: impl Into<String>, window: Duration) -> Self {
+        Self {
+            service: service.into(),
+            window,
+        }
+    }
+
+    /// Asks the local network for the instances of the service and returns the peers that
+    /// answered within the window with a DID and an address.
+    pub async fn discover(&self) -> ServiceResult<Vec<DiscoveredPeer>> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+        let query = DnsMessage::query(0, &self.service, TYPE_PTR, true);
+        socket
+            .send_to(&query.encode(), (MDNS_GROUP, MDNS_PORT))
+            .await?;
+
+        let mut records = Vec::new();
+        let mut buf = vec![0; MAX_MESSAGE_SIZE];
+        let deadline = tokio::time::Instant::now() + self.window;
+        while let Ok(received) = tokio::time::timeout_at(deadline, socket.recv_from(&mut buf)).await
+        {
+            let (len, _) = received?;
+            if let Some(answer) = DnsMessage::decode(&buf[..len]).filter(|m| m.response) {
+                records.extend(answer.records);
+            }
+        }
+
+        Ok(peers_from_records(&self.service, &records))
+    }
+}
+
+impl MdnsAdvertiser {
+    /// Creates an advertiser of the node with DID `did`, taking peer connections on `addr`, as an
+    /// instance of `service`.
+    pub fn new(service: impl Into<String>, did: impl Into<String>, addr: SocketAddr) -> Self {
+        Self {
+            service: service.into(),
+            did: did.into(),
+            addr,
+        }
+    }
+
+    /// Answers the mDNS queries for the service, its instance or its host until an error occurs.
+    ///
+    /// The socket is shared with other mDNS responders on the machine, like other nodes of a
+    /// development cluster.
+    pub async fn run(self) -> ServiceResult<()> {
+        let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+        socket.set_reuse_address(true)?;
+        #[cfg(unix)]
+        socket.set_reuse_port(true)?;
+        socket.set_nonblocking(true)?;
+        socket.bind(&SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, MDNS_PORT).into())?;
+        socket.join_multicast_v4(&MDNS_GROUP, &Ipv4Addr::UNSPECIFIED)?;
+        let socket = UdpSocket::from_std(socket.into())?;
+
+        let mut buf = vec![0; MAX_MESSAGE_SIZE];
+        loop {
+            let (len, from) = socket.recv_from(&mut buf).await?;
+            let Some(query) = DnsMessage::decode(&buf[..len]).filter(|m| !m.response) else {
+                continue;
+            };
+
+            let Some(answer) = self.answer(&query) else {
+                continue;
+            };
+
+            // Legacy resolvers query from other ports and only read answers sent to them.
+            let to: SocketAddr = if query.unicast || from.port() != MDNS_PORT {
+                from
+            } else {
+                (MDNS_GROUP, MDNS_PORT).into()
+            };
+
+            if let Err(e) = socket.send_to(&answer.encode(), to).await {
+                tracing::debug!("failed to answer mDNS query from {}: {}", from, e);
+            }
+        }
+    }
+
+    /// Returns the answer to `query`, or `None` if it is not about this node.
+    fn answer(&self, query: &DnsMessage) -> Option<DnsMessage> {
+        let instance = self.get_instance();
+        let host = self.get_host();
+        let asked = query.questions.iter().any(|(name, _)| {
+            names_match(name, &self.service)
+                || names_match(name, &instance)
+                || names_match(name, &host)
+        });
+
+        asked.then(|| DnsMessage {
+            id: query.id,
+            response: true,
+            unicast: false,
+            questions: Vec::new(),
+            records: self.get_records(),
+        })
+    }
+
+    /// Returns the records advertising the node: the instance of the service, where it is, and
+    /// its DID.
+    fn get_records(&self) -> Vec<DnsRecord> {
+        let instance = self.get_instance();
+        let host = self.get_host();
+        let record = |name: &str, data| DnsRecord {
+            name: name.to_owned(),
+            ttl: MDNS_TTL,
+            data,
+        };
+
+        let mut records = vec![
+            record(&self.service, RecordData::Ptr(instance.clone())),
+            record(
+                &instance,
+                RecordData::Srv {
+                    priority: 0,
+                    weight: 0,
+                    port: self.addr.port(),
+                    target: host.clone(),
+                },
+            ),
+            record(
+                &instance,
+                RecordData::Txt(vec![format!("{}={}", DID_ATTRIBUTE, self.did)]),
+            ),
+        ];
+
+        match self.addr.ip() {
+            IpAddr::V4(ip) => records.push(record(&host, RecordData::A(ip))),
+            IpAddr::V6(ip) => records.push(record(&host, RecordData::Aaaa(ip))),
+        }
+
+        records
+    }
+
+    /// Returns the name of the instance of the service advertising the node.
+    fn get_instance(&self) -> String {
+        format!("{}.{}", self.get_label(), self.service)
+    }
+
+    /// Returns the host name of the node on the local network.
+    fn get_host(&self) -> String {
+        format!("{}.local", self.get_label())
+    }
+
+    /// Returns a DNS label telling the node apart from the others: the end of its DID.
+    fn get_label(&self) -> String {
+        let id = self.did.rsplit(':').next().unwrap_or(&self.did);
+        let start = id.len().saturating_sub(32);
+        format!("zerofs-{}", &id[start..])
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Returns the peers advertised as instances of `service` in `records`.
+fn peers_from_records(service: &str, records: &[DnsRecord]) -> Vec<DiscoveredPeer> {
+    let mut peers = Vec::new();
+    for record in records {
+        let RecordData::Ptr(instance) = &record.data else {
+            continue;
+        };
+
+        if !names_match(&record.name, service) {
+            continue;
+        }
+
+        let srv = records.iter().find_map(|record| match &record.data {
+            RecordData::Srv { port, target, .. } if names_match(&record.name, instance) => {
+                Some((*port, target))
+            }
+            _ => None,
+        });
+
+        let Some((port, target)) = srv else {
+            continue;
+        };
+
+        if let (Some(did), Some(ip)) = (find_did(records, instance), find_ip(records, target)) {
+            let peer = DiscoveredPeer {
+                did,
+                addr: SocketAddr::new(ip, port),
+                source: DiscoverySource::Mdns,
+            };
+
+            if !peers.contains(&peer) {
+                peers.push(peer);
+            }
+        }
+    }
+
+    peers
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        super::dns::{TYPE_A, TYPE_SRV},
+        *,
+    };
+
+    #[test]
+    fn test_mdns_advertiser_answers() -> anyhow::Result<()> {
+        let service = "_zerofs._tcp.local";
+        let did = "did:wk:z6MknLif7jhwt6jUfn14EuDnxWoSHkkajyDi28QMMH5eS1DL";
+        let addr: SocketAddr = "192.168.1.20:6611".parse()?;
+        let advertiser = MdnsAdvertiser::new(service, did, addr);
+
+        // Queries for other services are not answered.
+        let other = DnsMessage::query(0, "_http._tcp.local", TYPE_PTR, true);
+        assert!(advertiser.answer(&other).is_none());
+
+        // The answer to a query for the service is enough to find the peer.
+        let query = DnsMessage::query(0, service, TYPE_PTR, true);
+        let answer = advertiser.answer(&query).unwrap();
+        let answer = DnsMessage::decode(&answer.encode()).unwrap();
+        assert!(answer.response);
+        assert_eq!(
+            peers_from_records(service, &answer.records),
+            vec![DiscoveredPeer {
+                did: did.into(),
+                addr,
+                source: DiscoverySource::Mdns,
+            }]
+        );
+
+        // As is the answer to a query for the instance or the host of the node.
+        let instance = DnsMessage::query(0, &advertiser.get_instance(), TYPE_SRV, false);
+        assert!(advertiser.answer(&instance).is_some());
+        let host = DnsMessage::query(0, &advertiser.get_host(), TYPE_A, false);
+        assert!(advertiser.answer(&host).is_some());
+        assert!(advertiser.get_label().len() <= 63);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,13 @@
+//! The discovery module finds the peers of a cluster beyond its static seeds.
+
+mod discovery;
+mod dns;
+mod mdns;
+
+//--------------------------------------------------------------------------------------------------
+// Exports
+//--------------------------------------------------------------------------------------------------
+
+pub use discovery::*;
+pub use dns::*;
+pub use mdns::*;
@@ -0,0 +1,506 @@
+use std::{
+    convert::TryInto,
+    io::SeekFrom,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use bytes::Bytes;
+use tokio::{
+    fs::{self, OpenOptions},
+    io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
+};
+
+use crate::config::ZerofsLogConfig;
+
+use super::{ServiceError, ServiceResult};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The directory the consensus log is kept in, relative to the base directory of the node.
+pub const LOG_DIR: &str = "raft";
+
+/// The extension of the files of the segments of the log.
+const SEGMENT_EXTENSION: &str = "log";
+
+/// The size of the header of a record: the length of the data, its checksum, the term and the
+/// index.
+const RECORD_HEADER_SIZE: usize = 4 + 4 + 8 + 8;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// An entry of the consensus log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogEntry {
+    /// The position of the entry in the log.
+    pub index: u64,
+
+    /// The term of the leader that created the entry.
+    pub term: u64,
+
+    /// The command of the entry, opaque to the log.
+    pub data: Bytes,
+}
+
+/// A durable log for the consensus layer, kept under the base directory of the node so that its
+/// state survives a restart.
+///
+/// The log is a sequence of append-only segment files, each named after the index of its first
+/// entry. An entry is written as a record with the length of its data and a CRC-32 of the rest, so
+/// a record torn by a crash is found when the log is opened. A torn record at the end of the last
+/// segment, along with anything after it, is cut off, as it was never acknowledged. Anywhere else
+/// it fails the open with [`ServiceError::CorruptLog`].
+///
+/// Appends go to the last segment until it reaches the [configured][ZerofsLogConfig] size, and
+/// then to a new one. Once a snapshot covers the entries up to some index,
+/// [`compact`][Self::compact] deletes the segments that hold nothing after it.
+///
+/// The offsets of the entries are kept in memory, so reads take a single seek.
+#[derive(Debug)]
+pub struct DiskLog {
+    /// The directory the segments are in.
+    dir: PathBuf,
+
+    /// The configuration of the log.
+    config: ZerofsLogConfig,
+
+    /// The segments, oldest first.
+    segments: Vec<Segment>,
+}
+
+/// A segment file of a [`DiskLog`].
+#[derive(Debug)]
+struct Segment {
+    /// The path of the file.
+    path: PathBuf,
+
+    /// The index of the first entry of the segment.
+    first_index: u64,
+
+    /// The offsets of the records of the entries in the file.
+    offsets: Vec<u64>,
+
+    /// The size of the file.
+    size: u64,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl DiskLog {
+    /// Opens the log in [`LOG_DIR`] under the base directory of the node, creating it if needed.
+    pub async fn open_in(
+        base_dir: impl AsRef<Path>,
+        config: ZerofsLogConfig,
+    ) -> ServiceResult<Self> {
+        Self::open(base_dir.as_ref().join(LOG_DIR), config).await
+    }
+
+    /// Opens the log in `dir`, creating it if needed, and checks its records.
+    pub async fn open(dir: impl Into<PathBuf>, config: ZerofsLogConfig) -> ServiceResult<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir).await?;
+
+        let mut files = Vec::new();
+        let mut entries = fs::read_dir(&dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some(SEGMENT_EXTENSION) {
+                continue;
+            }
+
+            let first_index = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| stem.parse::<u64>().ok())
+                .ok_or_else(|| {
+                    ServiceError::CorruptLog(format!("unexpected segment file {:?}", path))
+                })?;
+            files.push((first_index, path));
+        }
+
+        files.sort();
+        let count = files.len();
+        let mut segments = Vec::<Segment>::with_capacity(count);
+        for (i, (first_index, path)) in files.into_iter().enumerate() {
+            if let Some(previous) = segments.last() {
+                if previous.next_index() != first_index {
+                    return Err(ServiceError::CorruptLog(format!(
+                        "segment {:?} does not follow on from the one before",
+                        path
+                    )));
+                }
+            }
+
+            segments.push(Segment::load(path, first_index, i + 1 == count).await?);
+        }
+
+        Ok(Self {
+            dir,
+            config,
+            segments,
+        })
+    }
+
+    /// Returns the index of the first entry in the log, or `None` if it is empty.
+    pub fn first_index(&self) -> Option<u64> {
+        self.segments
+            .iter()
+            .find(|segment| !segment.offsets.is_empty())
+            .map(|segment| segment.first_index)
+    }
+
+    /// Returns the index of the last entry in the log, or `None` if it is empty.
+    pub fn last_index(&self) -> Option<u64> {
+        self.segments
+            .iter()
+            .rev()
+            .find(|segment| !segment.offsets.is_empty())
+            .map(|segment| segment.next_index() - 1)
+    }
+
+    /// Appends `entries` to the log, flushing them to disk first if the log is configured to.
+    ///
+    /// The entries must follow on from the last one in the log, with no gaps. The first entry of
+    /// an empty log can have any index, as a log that was compacted away starts after its snapshot.
+    pub async fn append(&mut self, entries: &[LogEntry]) -> ServiceResult<()> {
+        let mut records = Vec::new();
+        for entry in entries {
+            if let Some(last) = self.last_index() {
+                if entry.index != last + 1 {
+                    return Err(ServiceError::InvalidLogEntry(format!(
+                        "entry {} does not follow entry {}",
+                        entry.index, last
+                    )));
+                }
+            }
+
+            let full = match self.segments.last() {
+                Some(segment) => {
+                    segment.size + records.len() as u64 >= self.config.segment_size
+                        && !segment.offsets.is_empty()
+                }
+                None => true,
+            };
+
+            if full {
+                self.flush(&mut records).await?;
+                self.create_segment(entry.index).await?;
+            }
+
+            let segment = self
+                .segments
+                .last_mut()
+                .expect("a segment was just created");
+            segment.offsets.push(segment.size + records.len() as u64);
+            encode_record(entry, &mut records);
+        }
+
+        self.flush(&mut records).await
+    }
+
+    /// Returns the entry at `index`, or `None` if it is not in the log.
+    pub async fn get(&self, index: u64) -> ServiceResult<Option<LogEntry>> {
+        let Some(segment) = self.find_segment(index) else {
+            return Ok(None);
+        };
+
+        let Some(offset) = segment.offsets.get((index - segment.first_index) as usize) else {
+            return Ok(None);
+        };
+
+        let mut file = fs::File::open(&segment.path).await?;
+        file.seek(SeekFrom::Start(*offset)).await?;
+
+        let mut header = [0; RECORD_HEADER_SIZE];
+        file.read_exact(&mut header).await?;
+        let len = u32::from_le_bytes(header[0..4].try_into().unwrap()) as usize;
+        let mut record = header.to_vec();
+        record.resize(RECORD_HEADER_SIZE + len, 0);
+        file.read_exact(&mut record[RECORD_HEADER_SIZE..]).await?;
+
+        match decode_record(&record) {
+            Some((entry, _)) if entry.index == index => Ok(Some(entry)),
+            _ => Err(ServiceError::CorruptLog(format!(
+                "entry {} in {:?} does not match its checksum",
+                index, segment.path
+            ))),
+        }
+    }
+
+    /// Returns the entries in `range` that are in the log, in order.
+    pub async fn get_range(&self, range: Range<u64>) -> ServiceResult<Vec<LogEntry>> {
+        let mut entries = Vec::new();
+        for index in range {
+            match self.get(index).await? {
+                Some(entry) => entries.push(entry),
+                None if entries.is_empty() => continue,
+                None => break,
+            }
+        }
+
+        Ok(entries)
+    }
+
+    /// Removes the entries after `index`, like the entries of a follower that conflict with those
+    /// of a new leader.
+    pub async fn truncate_after(&mut self, index: u64) -> ServiceResult<()> {
+        while let Some(segment) = self.segments.last_mut() {
+            if segment.first_index > index {
+                fs::remove_file(&segment.path).await?;
+                self.segments.pop();
+                continue;
+            }
+
+            let keep = (index - segment.first_index + 1) as usize;
+            if let Some(&offset) = segment.offsets.get(keep) {
+                let file = OpenOptions::new().write(true).open(&segment.path).await?;
+                file.set_len(offset).await?;
+                file.sync_all().await?;
+                segment.offsets.truncate(keep);
+                segment.size = offset;
+            }
+
+            break;
+        }
+
+        Ok(())
+    }
+
+    /// Deletes the segments whose entries all come at or before `index`, once a snapshot covering
+    /// them was taken, and returns the number of segments deleted.
+    ///
+    /// The last segment is kept even if the snapshot covers it, so the log still knows its last
+    /// index and where to append next.
+    pub async fn compact(&mut self, index: u64) -> ServiceResult<usize> {
+        let covered = self
+            .segments
+            .iter()
+            .take(self.segments.len().saturating_sub(1))
+            .take_while(|segment| segment.next_index() <= index + 1)
+            .count();
+
+        for segment in self.segments.drain(..covered) {
+            fs::remove_file(&segment.path).await?;
+        }
+
+        Ok(covered)
+    }
+
+    /// Appends `records` to the last segment and clears them.
+    async fn flush(&mut self, records: &mut Vec<u8>) -> ServiceResult<()> {
+        let Some(segment) = self.segments.last_mut() else {
+            return Ok(());
+        };
+
+        if records.is_empty() {
+            return Ok(());
+        }
+
+        let mut file = OpenOptions::new().append(true).open(&segment.path).await?;
+        file.write_all(records).await?;
+        if self.config.sync {
+            file.sync_data().await?;
+        }
+
+        segment.size += records.len() as u64;
+        records.clear();
+
+        Ok(())
+    }
+
+    /// Starts a new segment whose first entry is at `first_index`.
+    async fn create_segment(&mut self, first_index: u64) -> ServiceResult<()> {
+        let path = self
+            .dir
+            .join(format!("{:020}.{}", first_index, SEGMENT_EXTENSION));
+        fs::File::create(&path).await?;
+        if self.config.sync {
+            fs::File::open(&self.dir).await?.sync_all().await?;
+        }
+
+        // An empty segment left by a crash is replaced rather than followed.
+        if let Some(empty) = self.segments.pop_if(|segment| segment.offsets.is_empty()) {
+            if empty.path != path {
+                fs::remove_file(&empty.path).await?;
+            }
+        }
+
+        self.segments.push(Segment {
+            path,
+            first_index,
+            offsets: Vec::new(),
+            size: 0,
+        });
+
+        Ok(())
+    }
+
+    /// Returns the segment that holds `index` if it is in the log.
+    fn find_segment(&self, index: u64) -> Option<&Segment> {
+        let position = self
+            .segments
+            .partition_point(|segment| segment.first_index <= index);
+        self.segments[..position].last()
+    }
+}
+
+impl Segment {
+    /// Reads the segment at `path` and indexes its records. A torn record at the end of the last
+    /// segment is cut off.
+    async fn load(path: PathBuf, first_index: u64, last: bool) -> ServiceResult<Self> {
+        let content = fs::read(&path).await?;
+        let mut offsets = Vec::new();
+        let mut offset = 0;
+        while offset < content.len() {
+            match decode_record(&content[offset..]) {
+                Some((entry, len)) if entry.index == first_index + offsets.len() as u64 => {
+                    offsets.push(offset as u64);
+                    offset += len;
+                }
+                _ if last => {
+                    tracing::warn!(
+                        "cutting off a torn record at offset {} of {:?}",
+                        offset,
+                        path
+                    );
+                    let file = OpenOptions::new().write(true).open(&path).await?;
+                    file.set_len(offset as u64).await?;
+                    file.sync_all().await?;
+                    break;
+                }
+                _ => {
+                    return Err(ServiceError::CorruptLog(format!(
+                        "invalid record at offset {} of {:?}",
+                        offset, path
+                    )))
+                }
+            }
+        }
+
+        Ok(Self {
+            path,
+            first_index,
+            offsets,
+            size: offset as u64,
+        })
+    }
+
+    /// Returns the index the entry after the last one of the segment gets.
+    fn next_index(&self) -> u64 {
+        self.first_index + self.offsets.len() as u64
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Appends the record of `entry` to `out`.
+fn encode_record(entry: &LogEntry, out: &mut Vec<u8>) {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(&entry.term.to_le_bytes());
+    hasher.update(&entry.index.to_le_bytes());
+    hasher.update(&entry.data);
+
+    out.extend_from_slice(&(entry.data.len() as u32).to_le_bytes());
+    out.extend_from_slice(&hasher.finalize().to_le_bytes());
+    out.extend_from_slice(&entry.term.to_le_bytes());
+    out.extend_from_slice(&entry.index.to_le_bytes());
+    out.extend_from_slice(&entry.data);
+}
+
+/// Decodes the record at the start of `bytes`, returning the entry and the size of the record, or
+/// `None` if it is incomplete or does not match its checksum.
+fn decode_record(bytes: &[u8]) -> Option<(LogEntry, usize)> {
+    let header = bytes.get(..RECORD_HEADER_SIZE)?;
+    let len = u32::from_le_bytes(header[0..4].try_into().ok()?) as usize;
+    let crc = u32::from_le_bytes(header[4..8].try_into().ok()?);
+    let data = bytes.get(RECORD_HEADER_SIZE..RECORD_HEADER_SIZE + len)?;
+
+    if crc32fast::hash(&bytes[8..RECORD_HEADER_SIZE + len]) != crc {
+        return None;
+    }
+
+    let entry = LogEntry {
+        term: u64::from_le_bytes(header[8..16].try_into().ok()?),
+        index: u64::from_le_bytes(header[16..24].try_into().ok()?),
+        data: Bytes::copy_from_slice(data),
+    };
+
+    Some((entry, RECORD_HEADER_SIZE + len))
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(range: Range<u64>, term: u64) -> Vec<LogEntry> {
+        range
+            .map(|index| LogEntry {
+                index,
+                term,
+                data: Bytes::from(format!("command {}", index)),
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_disk_log_survives_restart() -> anyhow::Result<()> {
+        let base_dir = std::env::temp_dir().join(format!("zerofs{}", rand::random::<u64>()));
+        let config = ZerofsLogConfig::builder().segment_size(100).build();
+
+        let mut log = DiskLog::open_in(&base_dir, config.clone()).await?;
+        assert_eq!(log.last_index(), None);
+        log.append(&entries(1..11, 1)).await?;
+        assert!(log.segments.len() > 1);
+        assert!(matches!(
+            log.append(&entries(12..13, 1)).await,
+            Err(ServiceError::InvalidLogEntry(_))
+        ));
+
+        // A conflicting suffix is replaced by the entries of the new leader.
+        log.truncate_after(7).await?;
+        log.append(&entries(8..12, 2)).await?;
+        drop(log);
+
+        // A torn write at the end of the last segment is cut off on open.
+        let mut log = DiskLog::open_in(&base_dir, config.clone()).await?;
+        let last = log.segments.last().unwrap().path.clone();
+        let mut file = OpenOptions::new().append(true).open(&last).await?;
+        file.write_all(&[7; 10]).await?;
+        drop(file);
+
+        let log_dir = base_dir.join(LOG_DIR);
+        drop(log);
+        log = DiskLog::open(&log_dir, config.clone()).await?;
+        assert_eq!(log.first_index(), Some(1));
+        assert_eq!(log.last_index(), Some(11));
+        assert_eq!(log.get(3).await?, Some(entries(3..4, 1).remove(0)));
+        assert_eq!(log.get_range(7..20).await?.len(), 5);
+        assert_eq!(log.get(9).await?.unwrap().term, 2);
+
+        // Compaction drops the segments a snapshot covers, but keeps the last one.
+        let deleted = log.compact(8).await?;
+        assert!(deleted > 0);
+        let first = log.first_index().unwrap();
+        assert!(first > 1 && first <= 9);
+        assert_eq!(log.get(1).await?, None);
+        log.compact(100).await?;
+        assert_eq!(log.segments.len(), 1);
+        assert_eq!(log.last_index(), Some(11));
+
+        fs::remove_dir_all(&base_dir).await?;
+
+        Ok(())
+    }
+}
@@ -1,4 +1,12 @@
 use thiserror::Error;
+use zeroutils_store::ipld::cid::Cid;
+
+use crate::{
+    config::NodeCodec,
+    filesystem::{self, ErrorCode},
+};
+
+use super::{ErrorContext, ReadConsistency};
 
 //--------------------------------------------------------------------------------------------------
 // Types
@@ -22,15 +30,258 @@ pub enum ServiceError {
     #[error("Config error: {0}")]
     ConfigError(#[from] zeroutils_config::ConfigError),
 
+    /// Config load error.
+    #[error("Config load error: {0}")]
+    ConfigLoadError(#[from] crate::config::ConfigLoadError),
+
     /// Did error.
     #[error("Did error: {0}")]
     DidError(#[from] zeroutils_did_wk::DidError),
+
+    /// File system error.
+    #[error("File system error: {0}")]
+    FsError(#[from] crate::filesystem::FsError),
+
+    /// Store error.
+    #[error("Store error: {0}")]
+    StoreError(#[from] zeroutils_store::StoreError),
+
+    /// The store does not support the configured node codec.
+    #[error("Store does not support the configured node codec: {0:?}")]
+    UnsupportedNodeCodec(NodeCodec),
+
+    /// The CID was not produced with the configured hash function and codec.
+    #[error("CID does not match the store configuration: {0}")]
+    StoreConfigMismatch(Cid),
+
+    /// The handle index could not be parsed or serialized.
+    #[error("Invalid handle index: {0}")]
+    InvalidHandleIndex(String),
+
+    /// No handle with the ID is open in the session.
+    #[error("Unknown handle: {0}")]
+    UnknownHandle(Cid),
+
+    /// The job index could not be parsed or serialized.
+    #[error("Invalid job index: {0}")]
+    InvalidJobIndex(String),
+
+    /// No transformer is registered for the transform.
+    #[error("Unknown transform: {0}")]
+    UnknownTransform(String),
+
+    /// The search index is not enabled in the configuration.
+    #[error("Search index is disabled")]
+    SearchDisabled,
+
+    /// A frame of the operation or peer protocol could not be encoded or decoded.
+    #[error("Invalid frame: {0}")]
+    InvalidFrame(String),
+
+    /// A frame is larger than the largest frame that is read.
+    #[error("Frame of {0} bytes is too large")]
+    FrameTooLarge(u64),
+
+    /// An operation was encoded with a newer wire version than this one.
+    #[error("Unsupported wire version: {0}")]
+    UnsupportedWireVersion(u32),
+
+    /// A peer cannot be talked to, as it does not share a protocol version, hash function or codec
+    /// with this node.
+    #[error("Incompatible peer: {0}")]
+    IncompatiblePeer(String),
+
+    /// A signed root announcement does not verify against the members of the cluster.
+    #[error("Invalid root signature: {0}")]
+    InvalidRootSignature(String),
+
+    /// A replica operation was asked of a node that is not configured as a read replica.
+    #[error("Not a read replica")]
+    NotAReplica,
+
+    /// A read asked for a consistency this node cannot serve, like a linearizable read of a read
+    /// replica.
+    #[error("Read consistency unavailable on this node: {0}")]
+    ConsistencyUnavailable(ReadConsistency),
+
+    /// A read consistency is not one of the known levels.
+    #[error("Invalid read consistency: {0:?}")]
+    InvalidConsistency(String),
+
+    /// A join token was refused, or a node could not be admitted to the cluster.
+    #[error("Invalid join token: {0}")]
+    InvalidJoinToken(String),
+
+    /// Peers could not be discovered.
+    #[error("Peer discovery failed: {0}")]
+    DiscoveryFailed(String),
+
+    /// A peer did not prove it holds the key of the DID it claims.
+    #[error("Invalid peer identity: {0}")]
+    InvalidPeerIdentity(String),
+
+    /// A peer could not be reached, or its connection broke before it answered.
+    #[error("Peer unavailable: {0}")]
+    PeerUnavailable(String),
+
+    /// A directory preopened for WASI guests is misconfigured.
+    #[error("Invalid preopen: {0}")]
+    InvalidPreopen(String),
+
+    /// A commit receipt does not verify, or does not match an inclusion proof.
+    #[error("Invalid receipt: {0}")]
+    InvalidReceipt(String),
+
+    /// A peer answered a request with an error.
+    #[error("Remote error: {0}")]
+    RemoteError(String),
+
+    /// The consensus log on disk is damaged somewhere other than its last record.
+    #[error("Corrupt log: {0}")]
+    CorruptLog(String),
+
+    /// An entry appended to the consensus log does not follow on from the last one.
+    #[error("Invalid log entry: {0}")]
+    InvalidLogEntry(String),
+
+    /// The root the service was opened on cannot be read from the store.
+    #[error("Root cannot be read: {0}")]
+    UnreadableRoot(Cid),
+
+    /// The service was built with an invalid configuration, with every problem found in it.
+    #[error("Invalid service configuration: {}", .0.join("; "))]
+    InvalidConfig(Vec<String>),
+
+    /// The TLS certificate or key could not be loaded.
+    #[error("Invalid TLS certificate: {0}")]
+    InvalidTlsCertificate(String),
+
+    /// An error with the operations it happened in, see [`ServiceResultExt`][super::ServiceResultExt].
+    #[error("{}: {source}", display_breadcrumbs(.breadcrumbs))]
+    WithContext {
+        /// The contexts of the operations, from the innermost one out.
+        breadcrumbs: Vec<ErrorContext>,
+
+        /// The error.
+        source: Box<ServiceError>,
+    },
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl ServiceError {
+    /// Returns the stable code of the kind of the error.
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            ServiceError::WithContext { source, .. } => source.code(),
+            ServiceError::FsError(e) => e.code(),
+            ServiceError::IoError(_)
+            | ServiceError::KeyError(_)
+            | ServiceError::ConfigError(_)
+            | ServiceError::DidError(_) => ErrorCode::Internal,
+            ServiceError::StoreError(_) => ErrorCode::StoreError,
+            ServiceError::StoreConfigMismatch(_)
+            | ServiceError::InvalidFrame(_)
+            | ServiceError::InvalidConsistency(_)
+            | ServiceError::InvalidLogEntry(_)
+            | ServiceError::InvalidPreopen(_)
+            | ServiceError::InvalidConfig(_)
+            | ServiceError::InvalidTlsCertificate(_)
+            | ServiceError::ConfigLoadError(_) => ErrorCode::InvalidArgument,
+            ServiceError::InvalidHandleIndex(_)
+            | ServiceError::InvalidJobIndex(_)
+            | ServiceError::CorruptLog(_) => ErrorCode::CorruptData,
+            ServiceError::UnknownHandle(_) | ServiceError::UnknownTransform(_) => {
+                ErrorCode::NotFound
+            }
+            ServiceError::UnsupportedNodeCodec(_)
+            | ServiceError::SearchDisabled
+            | ServiceError::UnsupportedWireVersion(_)
+            | ServiceError::IncompatiblePeer(_)
+            | ServiceError::NotAReplica => ErrorCode::NotSupported,
+            ServiceError::FrameTooLarge(_) => ErrorCode::PayloadTooLarge,
+            ServiceError::InvalidRootSignature(_) | ServiceError::InvalidReceipt(_) => {
+                ErrorCode::InvalidSignature
+            }
+            ServiceError::InvalidJoinToken(_) => ErrorCode::PermissionDenied,
+            ServiceError::InvalidPeerIdentity(_) => ErrorCode::Unauthenticated,
+            ServiceError::DiscoveryFailed(_) | ServiceError::PeerUnavailable(_) => {
+                ErrorCode::Unavailable
+            }
+            ServiceError::RemoteError(_) => ErrorCode::Internal,
+            ServiceError::UnreadableRoot(_) | ServiceError::ConsistencyUnavailable(_) => {
+                ErrorCode::Unavailable
+            }
+        }
+    }
+
+    /// Returns `true` if the error is transient, like a store timeout or a peer that is not
+    /// reachable, so that the operation may succeed if it is tried again as is. Errors from
+    /// validating the operation are never retryable.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            ServiceError::WithContext { source, .. } => source.is_retryable(),
+            ServiceError::FsError(e) => e.is_retryable(),
+            ServiceError::IoError(e) => filesystem::is_transient_io_error(e),
+            // The node will not serve the read however many times it is asked.
+            ServiceError::ConsistencyUnavailable(_) => false,
+            e => e.code().is_retryable(),
+        }
+    }
+
+    /// Adds `context` to the breadcrumbs of the error, as the outermost one.
+    pub fn with_context(self, context: ErrorContext) -> Self {
+        match self {
+            ServiceError::WithContext {
+                mut breadcrumbs,
+                source,
+            } => {
+                breadcrumbs.push(context);
+                ServiceError::WithContext {
+                    breadcrumbs,
+                    source,
+                }
+            }
+            error => ServiceError::WithContext {
+                breadcrumbs: vec![context],
+                source: Box::new(error),
+            },
+        }
+    }
+
+    /// Returns the contexts of the operations the error happened in, from the innermost one out.
+    pub fn get_breadcrumbs(&self) -> &[ErrorContext] {
+        match self {
+            ServiceError::WithContext { breadcrumbs, .. } => breadcrumbs,
+            _ => &[],
+        }
+    }
+
+    /// Returns the error without its contexts.
+    pub fn root_cause(&self) -> &ServiceError {
+        match self {
+            ServiceError::WithContext { source, .. } => source.root_cause(),
+            error => error,
+        }
+    }
 }
 
 //--------------------------------------------------------------------------------------------------
 // Functions
 //--------------------------------------------------------------------------------------------------
 
+/// Formats breadcrumbs from the outermost one in.
+fn display_breadcrumbs(breadcrumbs: &[ErrorContext]) -> String {
+    breadcrumbs
+        .iter()
+        .rev()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(": ")
+}
+
 /// Creates an `Ok` `FsResult` d.
 #[allow(non_snake_case)]
 pub fn Ok<T>(value: T) -> ServiceResult<T> {
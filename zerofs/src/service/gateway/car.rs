@@ -0,0 +1,395 @@
+use std::{
+    collections::HashSet,
+    convert::TryFrom,
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+use bytes::Bytes;
+use futures::{future, stream, Stream, StreamExt};
+use zeroutils_store::{ipld::cid::Cid, IpldStore, StoreResult};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The multicodec code for raw blocks.
+pub const RAW_CODEC: u64 = 0x55;
+
+/// The multicodec code for DAG-CBOR blocks.
+pub const DAG_CBOR_CODEC: u64 = 0x71;
+
+/// The CBOR tag for CIDs in DAG-CBOR.
+const CID_TAG: u64 = 42;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The part of a DAG to include in a CAR file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DagScope {
+    /// Only the root block.
+    Block,
+
+    /// The root block and every block reachable from it.
+    All,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Streams the DAG rooted at `root` as a [CARv1][car] file, with blocks in depth-first order and
+/// without duplicates.
+///
+/// Each block is read from the store as the stream gets to it, so only the block being sent and
+/// the CIDs seen so far are held in memory. Links are only followed in DAG-CBOR blocks.
+///
+/// [car]: https://ipld.io/specs/transport/car/carv1/
+pub fn stream_car<S>(
+    store: S,
+    root: Cid,
+    scope: DagScope,
+) -> impl Stream<Item = StoreResult<Bytes>> + Send + 'static
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    let mut header = vec![];
+    write_car_header(&mut header, &root);
+
+    let blocks = stream::try_unfold(
+        (store, vec![root], HashSet::new()),
+        move |(store, mut stack, mut seen)| async move {
+            while let Some(cid) = stack.pop() {
+                if !seen.insert(cid) {
+                    continue;
+                }
+
+                let block = store.get_raw_block(&cid).await?;
+                if scope == DagScope::All && cid.codec() == DAG_CBOR_CODEC {
+                    let mut links = vec![];
+                    // Blocks that cannot be scanned are still sent, just without their links.
+                    if scan_cbor_links(&mut &block[..], &mut links).is_some() {
+                        stack.extend(links.into_iter().rev());
+                    }
+                }
+
+                let cid = cid.to_bytes();
+                let mut section = Vec::with_capacity(cid.len() + block.len() + 10);
+                write_varint(&mut section, (cid.len() + block.len()) as u64);
+                section.extend_from_slice(&cid);
+                section.extend_from_slice(&block);
+
+                return Ok(Some((Bytes::from(section), (store, stack, seen))));
+            }
+
+            Ok(None)
+        },
+    );
+
+    stream::once(future::ready(Ok(Bytes::from(header)))).chain(blocks)
+}
+
+/// Returns the CIDs of the blocks in the DAG rooted at `root`, following links the way
+/// [`stream_car`] does.
+///
+/// Blocks missing from the store are left out, along with what only they link to.
+pub async fn collect_dag<S>(store: &S, root: Cid) -> HashSet<Cid>
+where
+    S: IpldStore + Sync,
+{
+    let mut stack = vec![root];
+    let mut seen = HashSet::new();
+    while let Some(cid) = stack.pop() {
+        if seen.contains(&cid) {
+            continue;
+        }
+
+        let Ok(block) = store.get_raw_block(&cid).await else {
+            continue;
+        };
+
+        seen.insert(cid);
+        if cid.codec() == DAG_CBOR_CODEC {
+            let mut links = vec![];
+            if scan_cbor_links(&mut &block[..], &mut links).is_some() {
+                stack.extend(links);
+            }
+        }
+    }
+
+    seen
+}
+
+/// Writes the length-prefixed DAG-CBOR header `{"roots": [root], "version": 1}`.
+fn write_car_header(car: &mut Vec<u8>, root: &Cid) {
+    let mut header = vec![0xa2];
+    write_cbor_head(&mut header, 3, 5);
+    header.extend_from_slice(b"roots");
+    write_cbor_head(&mut header, 4, 1);
+    write_cbor_head(&mut header, 6, CID_TAG);
+
+    // CIDs in DAG-CBOR are prefixed with the multibase identity prefix.
+    let cid = root.to_bytes();
+    write_cbor_head(&mut header, 2, cid.len() as u64 + 1);
+    header.push(0x00);
+    header.extend_from_slice(&cid);
+
+    write_cbor_head(&mut header, 3, 7);
+    header.extend_from_slice(b"version");
+    write_cbor_head(&mut header, 0, 1);
+
+    write_varint(car, header.len() as u64);
+    car.extend_from_slice(&header);
+}
+
+/// Writes the head of a CBOR data item with the given major type and argument.
+fn write_cbor_head(buf: &mut Vec<u8>, major: u8, arg: u64) {
+    let major = major << 5;
+    match arg {
+        0..=23 => buf.push(major | arg as u8),
+        24..=0xff => buf.extend_from_slice(&[major | 24, arg as u8]),
+        0x100..=0xffff => {
+            buf.push(major | 25);
+            buf.extend_from_slice(&(arg as u16).to_be_bytes());
+        }
+        0x1_0000..=0xffff_ffff => {
+            buf.push(major | 26);
+            buf.extend_from_slice(&(arg as u32).to_be_bytes());
+        }
+        _ => {
+            buf.push(major | 27);
+            buf.extend_from_slice(&arg.to_be_bytes());
+        }
+    }
+}
+
+/// Writes an unsigned LEB128 varint.
+fn write_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+
+    buf.push(value as u8);
+}
+
+/// Scans one CBOR data item from `data`, collecting the CIDs it links to. Returns `None` if the
+/// data is not valid DAG-CBOR.
+///
+/// Nested items are counted rather than recursed into, so a deeply nested block cannot exhaust the
+/// stack.
+fn scan_cbor_links(data: &mut &[u8], links: &mut Vec<Cid>) -> Option<()> {
+    let mut pending: u64 = 1;
+    while pending > 0 {
+        pending -= 1;
+
+        let (major, arg) = read_cbor_head(data)?;
+        let nested = match major {
+            0 | 1 | 7 => 0,
+            2 | 3 => {
+                let len = usize::try_from(arg).ok()?;
+                *data = data.get(len..)?;
+                0
+            }
+            4 => arg,
+            5 => arg.checked_mul(2)?,
+            _ if arg == CID_TAG => {
+                let (major, len) = read_cbor_head(data)?;
+                let len = usize::try_from(len).ok()?;
+                let bytes = data.get(..len)?;
+                if major != 2 || bytes.first() != Some(&0x00) {
+                    return None;
+                }
+
+                links.push(Cid::try_from(&bytes[1..]).ok()?);
+                *data = &data[len..];
+                0
+            }
+            _ => 1,
+        };
+
+        // Every item takes at least a byte, so more items than bytes left cannot be valid.
+        pending = pending.checked_add(nested)?;
+        if pending > data.len() as u64 {
+            return None;
+        }
+    }
+
+    Some(())
+}
+
+/// Reads the head of a CBOR data item, returning its major type and argument.
+///
+/// Indefinite lengths are not allowed in DAG-CBOR.
+fn read_cbor_head(data: &mut &[u8]) -> Option<(u8, u64)> {
+    let (&initial, rest) = data.split_first()?;
+    let len = match initial & 0x1f {
+        info @ 0..=23 => {
+            *data = rest;
+            return Some((initial >> 5, info as u64));
+        }
+        24 => 1,
+        25 => 2,
+        26 => 4,
+        27 => 8,
+        _ => return None,
+    };
+
+    let bytes = rest.get(..len)?;
+    *data = &rest[len..];
+
+    let arg = bytes.iter().fold(0, |arg, byte| arg << 8 | *byte as u64);
+    Some((initial >> 5, arg))
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl FromStr for DagScope {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "block" => Ok(DagScope::Block),
+            "all" => Ok(DagScope::All),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Display for DagScope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DagScope::Block => write!(f, "block"),
+            DagScope::All => write!(f, "all"),
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use zeroutils_store::{MemoryStore, Storable};
+
+    use crate::filesystem::{Dir, File};
+
+    use super::*;
+
+    #[test]
+    fn test_varint() {
+        let mut buf = vec![];
+        write_varint(&mut buf, 1);
+        write_varint(&mut buf, 300);
+        assert_eq!(buf, vec![0x01, 0xac, 0x02]);
+    }
+
+    #[test]
+    fn test_scan_cbor_links() -> anyhow::Result<()> {
+        let cid: Cid = "bafkr4igxion64jdxhpf7uliks6kh5y3ce6yq2ebcwgsvqr7jfclfxnv73y".parse()?;
+
+        // {"content": cid, "size": 300}
+        let mut block = vec![0xa2];
+        write_cbor_head(&mut block, 3, 7);
+        block.extend_from_slice(b"content");
+        write_cbor_head(&mut block, 6, CID_TAG);
+        write_cbor_head(&mut block, 2, cid.to_bytes().len() as u64 + 1);
+        block.push(0x00);
+        block.extend_from_slice(&cid.to_bytes());
+        write_cbor_head(&mut block, 3, 4);
+        block.extend_from_slice(b"size");
+        write_cbor_head(&mut block, 0, 300);
+
+        let mut links = vec![];
+        assert!(scan_cbor_links(&mut &block[..], &mut links).is_some());
+        assert_eq!(links, vec![cid]);
+
+        // Truncated blocks are rejected.
+        assert!(scan_cbor_links(&mut &block[..10], &mut vec![]).is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_scan_deeply_nested_cbor() {
+        // A million nested single-item arrays around an integer.
+        let mut block = vec![0x81; 1_000_000];
+        block.push(0x01);
+
+        let mut links = vec![];
+        assert!(scan_cbor_links(&mut &block[..], &mut links).is_some());
+        assert!(links.is_empty());
+
+        // An array claiming more items than there are bytes left is rejected early.
+        let mut block = vec![];
+        write_cbor_head(&mut block, 4, u64::MAX);
+        assert!(scan_cbor_links(&mut &block[..], &mut vec![]).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_stream_car() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let cid = store.put_raw_block(Bytes::from_static(b"hello")).await?;
+
+        let car = stream_car(store, cid, DagScope::All)
+            .map(|bytes| bytes.map(|bytes| bytes.to_vec()))
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect::<StoreResult<Vec<_>>>()?;
+        assert_eq!(car.len(), 2);
+
+        let mut header = vec![];
+        write_car_header(&mut header, &cid);
+        assert_eq!(car[0], header);
+
+        let section = &car[1];
+        let cid_bytes = cid.to_bytes();
+        assert_eq!(section[0] as usize, cid_bytes.len() + 5);
+        assert_eq!(&section[1..1 + cid_bytes.len()], &cid_bytes[..]);
+        assert_eq!(&section[1 + cid_bytes.len()..], b"hello");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_collect_dag() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let file = File::new(store.clone()).store().await?;
+        let root = Dir::new(store.clone())
+            .link_at(&"docs/notes".parse()?, file)
+            .await?
+            .store()
+            .await?;
+        let other = store.put_raw_block(Bytes::from_static(b"hello")).await?;
+
+        let dag = collect_dag(&store, root).await;
+        assert!(dag.contains(&root));
+        assert!(dag.contains(&file));
+        assert!(!dag.contains(&other));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_car_header() -> anyhow::Result<()> {
+        let cid: Cid = "bafkr4igxion64jdxhpf7uliks6kh5y3ce6yq2ebcwgsvqr7jfclfxnv73y".parse()?;
+
+        let mut car = vec![];
+        write_car_header(&mut car, &cid);
+
+        let mut header = &car[1..];
+        let mut links = vec![];
+        assert_eq!(car[0] as usize, header.len());
+        assert!(scan_cbor_links(&mut header, &mut links).is_some());
+        assert!(header.is_empty());
+        assert_eq!(links, vec![cid]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,412 @@
+use std::{
+    collections::{HashMap, HashSet},
+    future::Future,
+    io,
+    str::FromStr,
+    sync::Arc,
+};
+
+use axum::{
+    body::Body,
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use futures::{future, stream, StreamExt};
+use serde::Deserialize;
+use tokio::{
+    io::DuplexStream,
+    sync::{oneshot, Mutex},
+};
+use tokio_util::io::ReaderStream;
+use zeroutils_store::{ipld::cid::Cid, IpldStore, Storable};
+
+use crate::{
+    filesystem::{Entity, File, Path as FsPath},
+    service::{ServiceError, SharedService},
+};
+
+use super::{collect_dag, stream_car, DagScope, RAW_CODEC};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+const RAW_CONTENT_TYPE: &str = "application/vnd.ipld.raw";
+const CAR_CONTENT_TYPE: &str = "application/vnd.ipld.car";
+const IMMUTABLE_CACHE_CONTROL: &str = "public, max-age=29030400, immutable";
+
+/// The size of the pipe file content is streamed to response bodies through.
+const STREAM_BUFFER_SIZE: usize = 64 * 1024;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The state shared by the gateway handlers.
+pub(crate) struct GatewayState<S>
+where
+    S: IpldStore,
+{
+    /// The store to serve blocks from.
+    pub(crate) store: S,
+
+    /// The service that derives and caches blobs from files, if any.
+    pub(crate) service: Option<SharedService<S>>,
+
+    /// The roots of the DAGs served.
+    pub(crate) published_roots: Arc<[Cid]>,
+
+    /// The paths in the tree of the service whose DAGs are served.
+    pub(crate) published_paths: Arc<[FsPath]>,
+
+    /// The CIDs reachable from each published root, kept until the root is no longer published.
+    pub(crate) reachable: Arc<Mutex<HashMap<Cid, Arc<HashSet<Cid>>>>>,
+}
+
+/// The query parameters of a gateway request.
+#[derive(Debug, Deserialize)]
+pub(crate) struct GatewayQuery {
+    /// The response format, `raw` or `car`.
+    format: Option<String>,
+
+    /// The part of the DAG to include in a CAR response.
+    #[serde(rename = "dag-scope")]
+    dag_scope: Option<String>,
+
+    /// The transform to derive a blob from the file with, like `thumbnail`.
+    derive: Option<String>,
+}
+
+/// The format of a gateway response.
+enum ResponseFormat {
+    Raw,
+    Car,
+    Deserialized,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// This endpoint handler returns the block, CAR file or file content identified by a CID.
+pub(crate) async fn get_ipfs<S>(
+    State(state): State<GatewayState<S>>,
+    Path(cid): Path<String>,
+    Query(query): Query<GatewayQuery>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    let cid = Cid::from_str(&cid).map_err(|_| StatusCode::BAD_REQUEST)?;
+    if !is_published(&state, &cid).await {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let store = state.store;
+    if !store.has(&cid).await {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    if let Some(transform) = &query.derive {
+        let service = state.service.ok_or(StatusCode::NOT_IMPLEMENTED)?;
+        return get_derived(service, store, cid, transform).await;
+    }
+
+    let etag = |suffix: &str| HeaderValue::from_str(&format!("\"{}{}\"", cid, suffix));
+
+    match response_format(&query, &headers)? {
+        ResponseFormat::Raw => {
+            let block = store
+                .get_raw_block(&cid)
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+            Ok((
+                [
+                    (
+                        header::CONTENT_TYPE,
+                        HeaderValue::from_static(RAW_CONTENT_TYPE),
+                    ),
+                    (
+                        header::CACHE_CONTROL,
+                        HeaderValue::from_static(IMMUTABLE_CACHE_CONTROL),
+                    ),
+                    (
+                        header::X_CONTENT_TYPE_OPTIONS,
+                        HeaderValue::from_static("nosniff"),
+                    ),
+                    (
+                        header::ETAG,
+                        etag(".raw").map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+                    ),
+                ],
+                block,
+            )
+                .into_response())
+        }
+        ResponseFormat::Car => {
+            let scope = match query.dag_scope.as_deref() {
+                None => DagScope::All,
+                Some(scope) => DagScope::from_str(scope).map_err(|_| StatusCode::BAD_REQUEST)?,
+            };
+
+            // Once the body has started, a block that fails to read can only cut the response
+            // short, which the client sees as a truncated CAR file.
+            let car = Body::from_stream(stream_car(store, cid, scope));
+
+            Ok((
+                [
+                    (
+                        header::CONTENT_TYPE,
+                        HeaderValue::from_static(
+                            "application/vnd.ipld.car; version=1; order=dfs; dups=n",
+                        ),
+                    ),
+                    (
+                        header::CACHE_CONTROL,
+                        HeaderValue::from_static(IMMUTABLE_CACHE_CONTROL),
+                    ),
+                    (
+                        header::X_CONTENT_TYPE_OPTIONS,
+                        HeaderValue::from_static("nosniff"),
+                    ),
+                    (
+                        header::ETAG,
+                        etag(&format!(".car.{}", scope))
+                            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+                    ),
+                ],
+                car,
+            )
+                .into_response())
+        }
+        ResponseFormat::Deserialized => {
+            let body = if cid.codec() == RAW_CODEC {
+                pipe_body(move |mut pipe| async move {
+                    let mut bytes = store.get_bytes(&cid).await?;
+                    tokio::io::copy(&mut bytes, &mut pipe).await?;
+                    Ok(())
+                })
+            } else {
+                let file = match Entity::load(&cid, store).await {
+                    Ok(Entity::File(file)) => file,
+                    // Directory listings and symlinks have no deserialized representation yet.
+                    Ok(_) => return Err(StatusCode::NOT_IMPLEMENTED),
+                    Err(_) => return Err(StatusCode::NOT_ACCEPTABLE),
+                };
+
+                content_body(file)
+            };
+
+            Ok((
+                [
+                    (
+                        header::CONTENT_TYPE,
+                        HeaderValue::from_static("application/octet-stream"),
+                    ),
+                    (
+                        header::CACHE_CONTROL,
+                        HeaderValue::from_static(IMMUTABLE_CACHE_CONTROL),
+                    ),
+                    (
+                        header::ETAG,
+                        etag("").map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+                    ),
+                ],
+                body,
+            )
+                .into_response())
+        }
+    }
+}
+
+/// Returns the blob derived from the file with the CID `cid` by the transform `transform`, if the
+/// service has derived it before.
+///
+/// Blobs are only derived by authorized users of the service, so anonymous requests cannot make
+/// the gateway run transforms or change the tree.
+async fn get_derived<S>(
+    service: SharedService<S>,
+    store: S,
+    cid: Cid,
+    transform: &str,
+) -> Result<Response, StatusCode>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    let cached = service
+        .lock()
+        .await
+        .get_cached_derived(&cid, transform)
+        .await;
+    let blob = match cached {
+        Ok(Some(blob)) => blob,
+        Ok(None) => return Err(StatusCode::NOT_FOUND),
+        Err(ServiceError::UnknownTransform(_)) => return Err(StatusCode::BAD_REQUEST),
+        Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
+    };
+
+    let file = File::load(&blob.blob, store)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok((
+        [
+            (
+                header::CONTENT_TYPE,
+                HeaderValue::from_str(&blob.content_type)
+                    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+            ),
+            (
+                header::CACHE_CONTROL,
+                HeaderValue::from_static(IMMUTABLE_CACHE_CONTROL),
+            ),
+            (
+                header::ETAG,
+                HeaderValue::from_str(&format!("\"{}.{}\"", cid, transform))
+                    .map_err(|_| StatusCode::BAD_REQUEST)?,
+            ),
+        ],
+        content_body(file),
+    )
+        .into_response())
+}
+
+/// Returns whether `cid` is reachable from one of the published roots or paths, so the gateway
+/// does not serve the rest of the store to anyone who learns a CID, like that of the root.
+///
+/// Paths are resolved against the current tree of the service on every request, so the DAG they
+/// publish follows the changes made under them.
+async fn is_published<S>(state: &GatewayState<S>, cid: &Cid) -> bool
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    let mut roots = state.published_roots.to_vec();
+    if let (Some(service), false) = (&state.service, state.published_paths.is_empty()) {
+        let root_dir = service.lock().await.root_dir.clone();
+        for path in state.published_paths.iter() {
+            // A path that does not resolve, like one removed since, publishes nothing.
+            if let Ok((_, root)) = root_dir.realpath_at(path).await {
+                roots.push(root);
+            }
+        }
+    }
+
+    state
+        .reachable
+        .lock()
+        .await
+        .retain(|root, _| roots.contains(root));
+
+    for root in roots {
+        let cached = state.reachable.lock().await.get(&root).cloned();
+        let reachable = match cached {
+            Some(reachable) => reachable,
+            None => {
+                let reachable = Arc::new(collect_dag(&state.store, root).await);
+                state
+                    .reachable
+                    .lock()
+                    .await
+                    .insert(root, Arc::clone(&reachable));
+                reachable
+            }
+        };
+
+        if reachable.contains(cid) {
+            return true;
+        }
+    }
+
+    false
+}
+
+/// Returns a body streaming the content of `file`.
+fn content_body<S>(file: File<S>) -> Body
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    pipe_body(move |mut pipe| async move {
+        let mut content = file.get_content_reader().await?;
+        tokio::io::copy(&mut content, &mut pipe).await?;
+        Ok(())
+    })
+}
+
+/// Returns a body streaming what `copy` writes to the pipe it is given.
+///
+/// The copy runs in its own task, which owns whatever its reader borrows from, and the pipe holds
+/// at most [`STREAM_BUFFER_SIZE`] bytes, so content is never buffered whole. If the copy fails,
+/// the body ends with an error, which the client sees as a truncated response.
+fn pipe_body<F, Fut>(copy: F) -> Body
+where
+    F: FnOnce(DuplexStream) -> Fut,
+    Fut: Future<Output = anyhow::Result<()>> + Send + 'static,
+{
+    let (pipe, reader) = tokio::io::duplex(STREAM_BUFFER_SIZE);
+    let (done_tx, done_rx) = oneshot::channel();
+    let copied = copy(pipe);
+    tokio::spawn(async move {
+        let _ = done_tx.send(copied.await);
+    });
+
+    let failed = stream::once(done_rx).filter_map(|copied| {
+        future::ready(match copied {
+            Ok(Err(e)) => {
+                tracing::warn!("failed to stream content: {}", e);
+                Some(Err(io::Error::new(io::ErrorKind::Other, e)))
+            }
+            _ => None,
+        })
+    });
+
+    Body::from_stream(ReaderStream::new(reader).chain(failed))
+}
+
+/// Picks the response format from the `format` query parameter, falling back to the `Accept`
+/// header.
+fn response_format(
+    query: &GatewayQuery,
+    headers: &HeaderMap,
+) -> Result<ResponseFormat, StatusCode> {
+    match query.format.as_deref() {
+        Some("raw") => return Ok(ResponseFormat::Raw),
+        Some("car") => return Ok(ResponseFormat::Car),
+        Some(_) => return Err(StatusCode::BAD_REQUEST),
+        None => {}
+    }
+
+    let accept = headers
+        .get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .unwrap_or_default();
+
+    if accept.contains(RAW_CONTENT_TYPE) {
+        Ok(ResponseFormat::Raw)
+    } else if accept.contains(CAR_CONTENT_TYPE) {
+        Ok(ResponseFormat::Car)
+    } else {
+        Ok(ResponseFormat::Deserialized)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl<S> Clone for GatewayState<S>
+where
+    S: IpldStore,
+{
+    fn clone(&self) -> Self {
+        Self {
+            store: self.store.clone(),
+            service: self.service.clone(),
+            published_roots: Arc::clone(&self.published_roots),
+            published_paths: Arc::clone(&self.published_paths),
+            reachable: Arc::clone(&self.reachable),
+        }
+    }
+}
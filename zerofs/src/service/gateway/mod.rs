@@ -0,0 +1,11 @@
+mod car;
+mod handler;
+mod router;
+mod server;
+
+//--------------------------------------------------------------------------------------------------
+// Exports
+//--------------------------------------------------------------------------------------------------
+
+pub use car::*;
+pub use server::*;
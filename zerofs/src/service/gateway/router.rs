@@ -0,0 +1,32 @@
+use std::sync::Arc;
+
+use axum::{routing, Router};
+use zeroutils_store::{ipld::cid::Cid, IpldStore};
+
+use crate::{filesystem::Path, service::SharedService};
+
+use super::handler::{self, GatewayState};
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+pub(crate) fn router<S>(
+    store: S,
+    service: Option<SharedService<S>>,
+    published_roots: Vec<Cid>,
+    published_paths: Vec<Path>,
+) -> Router
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    Router::new()
+        .route("/ipfs/:cid", routing::get(handler::get_ipfs::<S>))
+        .with_state(GatewayState {
+            store,
+            service,
+            published_roots: published_roots.into(),
+            published_paths: published_paths.into(),
+            reachable: Arc::default(),
+        })
+}
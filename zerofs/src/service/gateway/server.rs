@@ -0,0 +1,104 @@
+use std::net::SocketAddr;
+
+use tokio::net::TcpListener;
+use zeroutils_store::{ipld::cid::Cid, IpldStore};
+
+use crate::{
+    filesystem::Path,
+    service::{ServiceResult, SharedService},
+};
+
+use super::router;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// A read-only HTTP gateway that serves the blocks of a store at `/ipfs/{cid}`.
+///
+/// Responses follow the [IPFS trustless gateway spec][spec], so existing IPFS tooling can fetch and
+/// verify data published by a `zerofs` node. Blocks are returned with `?format=raw` and CAR files
+/// with `?format=car`, or the equivalent `Accept` headers. Without either, the content of files is
+/// returned as is, or a blob derived from it with `?derive={transform}`, like
+/// `?derive=thumbnail`, if the gateway is backed by a service that has derived it before.
+///
+/// Only the DAGs of the [published roots][Self::with_published_root] and
+/// [paths][Self::with_published_path] are served, anything else in the store being answered with
+/// `404 Not Found`, so a gateway publishes nothing until told what to publish.
+///
+/// [spec]: https://specs.ipfs.tech/http-gateways/trustless-gateway/
+pub struct FsGatewayServer<S>
+where
+    S: IpldStore,
+{
+    /// The store to serve blocks from.
+    store: S,
+
+    /// The address to listen on.
+    address: SocketAddr,
+
+    /// The service that derives and caches blobs from files, if any.
+    service: Option<SharedService<S>>,
+
+    /// The roots of the DAGs served.
+    published_roots: Vec<Cid>,
+
+    /// The paths in the tree of the service whose DAGs are served.
+    published_paths: Vec<Path>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl<S> FsGatewayServer<S>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    /// Creates a new gateway server for the given store.
+    pub fn new(store: S, address: SocketAddr) -> Self {
+        Self {
+            store,
+            address,
+            service: None,
+            published_roots: Vec::new(),
+            published_paths: Vec::new(),
+        }
+    }
+
+    /// Serves the blobs derived from files by `service` and cached in its tree.
+    pub fn with_service(mut self, service: SharedService<S>) -> Self {
+        self.service = Some(service);
+        self
+    }
+
+    /// Serves the DAG rooted at `root`.
+    pub fn with_published_root(mut self, root: Cid) -> Self {
+        self.published_roots.push(root);
+        self
+    }
+
+    /// Serves the DAG at `path` in the tree of the service, as it is at the time of each request.
+    /// Paths are only served if the gateway is [backed by a service][Self::with_service].
+    pub fn with_published_path(mut self, path: Path) -> Self {
+        self.published_paths.push(path);
+        self
+    }
+
+    /// Starts the gateway server.
+    pub async fn start(&self) -> ServiceResult<()> {
+        let router = router::router(
+            self.store.clone(),
+            self.service.clone(),
+            self.published_roots.clone(),
+            self.published_paths.clone(),
+        );
+        let listener = TcpListener::bind(self.address).await?;
+
+        tracing::info!("Gateway server started at {}", self.address);
+
+        axum::serve(listener, router).await?;
+
+        Ok(())
+    }
+}
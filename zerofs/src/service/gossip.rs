@@ -0,0 +1,348 @@
+use std::{collections::BTreeMap, net::SocketAddr, sync::Arc, time::Duration};
+
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+use zeroutils_key::{JwsAlgName, Verify};
+use zeroutils_store::IpldStore;
+
+use crate::filesystem::current_time;
+
+use super::{
+    Envelope, FsService, PeerPool, ServiceError, ServiceResult, SharedService, SignedRoot,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The most membership hints a node keeps and sends in a [`GossipMessage`].
+pub const MAX_GOSSIP_HINTS: usize = 32;
+
+/// The number of rounds of gossip a membership hint is kept for without being refreshed.
+const HINT_TTL_ROUNDS: u32 = 20;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// What two read replicas exchange in a round of gossip: the latest signed root each follows, and
+/// the other replicas each knows of.
+///
+/// Each replica gossips with a few random others each round, so that a new root reaches all of
+/// them in a number of rounds that grows with the logarithm of their number, without them all
+/// polling the cluster.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GossipMessage {
+    /// The latest root the sender follows, if any.
+    pub signed_root: Option<SignedRoot>,
+
+    /// The replicas the sender knows of, itself included.
+    pub hints: Vec<MembershipHint>,
+}
+
+/// A replica known to gossip at an address.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MembershipHint {
+    /// The DID of the replica.
+    pub did: String,
+
+    /// The address the replica takes peer connections on.
+    pub addr: SocketAddr,
+
+    /// The last time the replica was heard of, directly or through another replica.
+    pub seen_at: DateTime<Utc>,
+}
+
+/// The replicas a node knows of, by DID, forgotten when they are not heard of for a while.
+#[derive(Debug, Clone, Default)]
+pub struct GossipView {
+    /// The known replicas, by DID.
+    hints: BTreeMap<String, MembershipHint>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl GossipView {
+    /// Merges `hints` into the view at `now`, skipping `own`, the DID of this node.
+    ///
+    /// Hints older than `ttl` are dropped, and the oldest ones past [`MAX_GOSSIP_HINTS`]. A hint
+    /// cannot be seen later than `now`, so that a peer cannot keep a replica in the views of the
+    /// others forever.
+    pub fn merge(
+        &mut self,
+        hints: impl IntoIterator<Item = MembershipHint>,
+        own: &str,
+        now: DateTime<Utc>,
+        ttl: chrono::Duration,
+    ) {
+        for mut hint in hints {
+            if hint.did == own {
+                continue;
+            }
+
+            hint.seen_at = hint.seen_at.min(now);
+            match self.hints.get(&hint.did) {
+                Some(known) if known.seen_at >= hint.seen_at => {}
+                _ => {
+                    self.hints.insert(hint.did.clone(), hint);
+                }
+            }
+        }
+
+        self.hints.retain(|_, hint| now - hint.seen_at <= ttl);
+        while self.hints.len() > MAX_GOSSIP_HINTS {
+            let oldest = self
+                .hints
+                .values()
+                .min_by_key(|hint| hint.seen_at)
+                .map(|hint| hint.did.clone());
+            if let Some(did) = oldest {
+                self.hints.remove(&did);
+            }
+        }
+    }
+
+    /// Returns up to `count` known replicas, chosen at random.
+    pub fn sample(&self, count: usize) -> Vec<MembershipHint> {
+        let hints = self.hints.values().collect::<Vec<_>>();
+        hints
+            .choose_multiple(&mut rand::thread_rng(), count)
+            .map(|hint| (*hint).clone())
+            .collect()
+    }
+
+    /// Returns the known replicas.
+    pub fn get_hints(&self) -> impl Iterator<Item = &MembershipHint> {
+        self.hints.values()
+    }
+
+    /// Returns `true` if no replica is known.
+    pub fn is_empty(&self) -> bool {
+        self.hints.is_empty()
+    }
+}
+
+impl<S> FsService<S>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    /// Returns the message this node gossips: the root it follows and the replicas it knows of.
+    /// A read replica also advertises itself.
+    pub fn get_gossip(&self) -> GossipMessage {
+        let mut hints = self.gossip.get_hints().cloned().collect::<Vec<_>>();
+        if self.config.replica.enabled {
+            let network = &self.config.network;
+            hints.truncate(MAX_GOSSIP_HINTS - 1);
+            hints.push(MembershipHint {
+                did: network.id.to_string(),
+                addr: SocketAddr::new(network.host, network.peer_port),
+                seen_at: current_time(),
+            });
+        }
+
+        GossipMessage {
+            signed_root: self.signed_root.clone(),
+            hints,
+        }
+    }
+
+    /// Takes in a message gossiped by a peer: remembers the replicas it knows of and, on a read
+    /// replica, follows its root if it is newer and its signatures verify, see
+    /// [`follow_root`][Self::follow_root].
+    ///
+    /// Returns `true` if a newer root was followed.
+    pub async fn receive_gossip<V>(
+        &mut self,
+        message: GossipMessage,
+        resolve: impl Fn(&str) -> Option<V>,
+    ) -> ServiceResult<bool>
+    where
+        V: Verify + JwsAlgName,
+    {
+        let own = self.config.network.id.to_string();
+        let ttl = self.get_hint_ttl();
+        self.gossip.merge(message.hints, &own, current_time(), ttl);
+
+        match message.signed_root {
+            Some(signed) if self.config.replica.enabled => self.follow_root(signed, resolve).await,
+            _ => Ok(false),
+        }
+    }
+
+    /// Returns how long a membership hint is kept without being refreshed.
+    fn get_hint_ttl(&self) -> chrono::Duration {
+        let interval = self.config.replica.gossip_interval.max(1) as i64;
+        chrono::Duration::seconds(interval * HINT_TTL_ROUNDS as i64)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Runs the gossip of the read replica of `service` until the task is dropped.
+///
+/// Each [round][crate::config::ZerofsReplicaConfig::gossip_interval], the replica exchanges
+/// [`GossipMessage`]s with a few random replicas it knows of over `pool`, or with its
+/// [seeds][crate::config::ZerofsReplicaConfig::gossip_seeds] while it knows of none. `resolve`
+/// returns the public key of a member DID, to check the gossiped roots with. Peers that cannot be
+/// reached or gossip roots that do not verify are logged and skipped.
+pub async fn run_gossip<S, V>(
+    service: SharedService<S>,
+    pool: Arc<PeerPool>,
+    resolve: impl Fn(&str) -> Option<V>,
+) -> ServiceResult<()>
+where
+    S: IpldStore + Send + Sync + 'static,
+    V: Verify + JwsAlgName,
+{
+    let config = service.lock().await.config.clone();
+    let replica = &config.replica;
+    if !replica.enabled {
+        return Err(ServiceError::NotAReplica);
+    }
+
+    if replica.gossip_seeds.is_empty() {
+        return Ok(());
+    }
+
+    let mut interval = tokio::time::interval(Duration::from_secs(replica.gossip_interval.max(1)));
+    loop {
+        interval.tick().await;
+        let (message, mut targets) = {
+            let service = service.lock().await;
+            let targets = service
+                .gossip
+                .sample(replica.gossip_fanout)
+                .into_iter()
+                .map(|hint| (hint.did, hint.addr))
+                .collect::<Vec<_>>();
+            (service.get_gossip(), targets)
+        };
+
+        if targets.is_empty() {
+            targets.extend(replica.gossip_seeds.clone());
+        }
+
+        for (did, addr) in targets {
+            pool.add_peer(did.clone(), addr);
+            let answer = match pool.call::<_, GossipMessage>(&did, &message).await {
+                Ok(answer) => answer,
+                Err(e) => {
+                    tracing::debug!("failed to gossip with {}: {}", did, e);
+                    continue;
+                }
+            };
+
+            match service.lock().await.receive_gossip(answer, &resolve).await {
+                Ok(true) => tracing::info!("followed a newer root gossiped by {}", did),
+                Ok(false) => {}
+                Err(e) => tracing::warn!("ignoring root gossiped by {}: {}", did, e),
+            }
+        }
+    }
+}
+
+/// Answers a round of gossip started by a peer with `body`, a [`GossipMessage`] in an
+/// [`Envelope`] as sent by [`PeerPool::call`], with the message of this node.
+///
+/// The message of this node is taken before the one of the peer, so that the peer does not get
+/// its own hints back as news. Cluster members answer too, handing the replicas that reach them
+/// the latest root without having to poll for it.
+pub async fn serve_gossip<S, V>(
+    service: &SharedService<S>,
+    body: Bytes,
+    resolve: impl Fn(&str) -> Option<V>,
+) -> ServiceResult<Bytes>
+where
+    S: IpldStore + Send + Sync + 'static,
+    V: Verify + JwsAlgName,
+{
+    let message = serde_ipld_dagcbor::from_slice::<Envelope<GossipMessage>>(&body)
+        .map_err(|e| ServiceError::InvalidFrame(e.to_string()))?
+        .into_body()?;
+
+    let mut service = service.lock().await;
+    let answer = service.get_gossip();
+    if let Err(e) = service.receive_gossip(message, resolve).await {
+        tracing::warn!("ignoring gossiped root: {}", e);
+    }
+
+    let body = serde_ipld_dagcbor::to_vec(&Envelope::new(&answer))
+        .map_err(|e| ServiceError::InvalidFrame(e.to_string()))?;
+
+    Ok(body.into())
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use zeroutils_store::MemoryStore;
+
+    use crate::{
+        config::{ZerofsConfig, ZerofsReplicaConfig},
+        filesystem::Dir,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_gossip_spreads_membership_hints() -> anyhow::Result<()> {
+        let config = ZerofsConfig::builder()
+            .replica(ZerofsReplicaConfig::builder().enabled(true).build())
+            .build();
+        let own = config.network.id.to_string();
+        let mut service = FsService::new(Dir::new(MemoryStore::default()), Arc::new(config));
+
+        let now = current_time();
+        let hint = |did: &str, seconds_ago: i64| MembershipHint {
+            did: did.into(),
+            addr: "10.0.0.1:6611".parse().unwrap(),
+            seen_at: now - chrono::Duration::seconds(seconds_ago),
+        };
+
+        // The replica advertises itself, and nothing else yet.
+        let message = service.get_gossip();
+        assert_eq!(message.hints.len(), 1);
+        assert_eq!(message.hints[0].did, own);
+
+        // Hints about itself and hints too old to keep are skipped.
+        let ttl = service.get_hint_ttl();
+        let received = vec![hint("a", 1), hint("b", 3600), hint(&own, 0)];
+        service.gossip.merge(received, &own, now, ttl);
+        let dids = service
+            .gossip
+            .get_hints()
+            .map(|h| h.did.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(dids, ["a"]);
+
+        // An older hint does not replace a newer one, and hints from the future are clamped.
+        let mut view = GossipView::default();
+        let ttl = chrono::Duration::seconds(60);
+        view.merge([hint("a", 5), hint("a", 10)], &own, now, ttl);
+        assert_eq!(
+            view.get_hints().next().unwrap().seen_at,
+            hint("a", 5).seen_at
+        );
+        view.merge([hint("a", -600)], &own, now, ttl);
+        assert_eq!(view.get_hints().next().unwrap().seen_at, now);
+
+        // The view keeps the most recently seen replicas.
+        let many = (0..MAX_GOSSIP_HINTS as i64 + 8).map(|i| hint(&format!("r{}", i), i));
+        view.merge(many, &own, now, ttl);
+        assert_eq!(view.get_hints().count(), MAX_GOSSIP_HINTS);
+        assert!(view.get_hints().all(|h| now - h.seen_at < ttl));
+        assert_eq!(view.sample(3).len(), 3);
+        assert_eq!(view.sample(100).len(), MAX_GOSSIP_HINTS);
+
+        Ok(())
+    }
+}
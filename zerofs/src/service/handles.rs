@@ -0,0 +1,312 @@
+use std::{collections::BTreeMap, convert::TryInto, io::Cursor};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_with::serde_as;
+use tokio::io::AsyncReadExt;
+use zeroutils_store::{ipld::cid::Cid, IpldStore, Storable};
+
+use crate::filesystem::{
+    current_time, Chunker, DescriptorFlags, Dir, Entity, File, FsAction, FsCapabilities, FsError,
+    Path, TraceResult,
+};
+
+use super::{ErrorContext, FsService, ServiceError, ServiceResult, ServiceResultExt};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The well-known path of the file that records the open handles, relative to the root.
+pub const HANDLES_PATH: &str = "system/handles";
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The handles opened by clients, by their ID.
+///
+/// The index is a TOML document stored at [`HANDLES_PATH`] in the tree itself, so it is replicated
+/// along with the root and a client can keep using its handles against a new leader after a
+/// failover.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HandleIndex {
+    /// The open handles by their ID.
+    #[serde(default)]
+    handles: BTreeMap<String, HandleRecord>,
+}
+
+/// An open handle, as recorded in the [`HandleIndex`].
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HandleRecord {
+    /// The CID the entity had when the handle was opened.
+    #[serde_as(as = "serde_with::DisplayFromStr")]
+    pub entity: Cid,
+
+    /// The path to the entity from the root.
+    #[serde_as(as = "serde_with::DisplayFromStr")]
+    pub path: Path,
+
+    /// The flags the handle was opened with.
+    pub flags: DescriptorFlags,
+
+    /// The client session the handle belongs to.
+    pub session: String,
+
+    /// The time the handle was opened.
+    pub opened_at: DateTime<Utc>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl HandleIndex {
+    /// Loads the handle index of the tree under `root`.
+    ///
+    /// A tree without a handle index has no open handles.
+    pub async fn load<S>(root: &Dir<S>) -> ServiceResult<Self>
+    where
+        S: IpldStore + Send + Sync,
+    {
+        let path: Path = HANDLES_PATH.parse()?;
+        let file = match root.trace_entity(&path).await? {
+            TraceResult::Found {
+                entity: Entity::File(file),
+                ..
+            } => file,
+            TraceResult::Found { .. } => return Err(FsError::NotAFile(Some(path)).into()),
+            _ => return Ok(Self::default()),
+        };
+
+        let mut content = String::new();
+        file.get_content_reader()
+            .await?
+            .read_to_string(&mut content)
+            .await?;
+
+        toml::from_str(&content).map_err(|e| ServiceError::InvalidHandleIndex(e.to_string()))
+    }
+
+    /// Stores the index at [`HANDLES_PATH`] under `root` and returns the updated root.
+    async fn store_at<S>(&self, root: &Dir<S>) -> ServiceResult<Dir<S>>
+    where
+        S: IpldStore + Send + Sync + 'static,
+    {
+        let content =
+            toml::to_string(self).map_err(|e| ServiceError::InvalidHandleIndex(e.to_string()))?;
+
+        let mut file = File::new(root.get_store().clone());
+        file.put_content(Cursor::new(content.into_bytes()), &Chunker::Store)
+            .await?;
+
+        Ok(root
+            .link_at(&HANDLES_PATH.parse()?, file.store().await?)
+            .await?)
+    }
+
+    /// Returns the handle with ID `id`.
+    pub fn get(&self, id: &Cid) -> Option<&HandleRecord> {
+        self.handles.get(&id.to_string())
+    }
+
+    /// Returns the number of open handles.
+    pub fn len(&self) -> usize {
+        self.handles.len()
+    }
+
+    /// Returns `true` if there are no open handles.
+    pub fn is_empty(&self) -> bool {
+        self.handles.is_empty()
+    }
+}
+
+impl HandleRecord {
+    /// Returns the ID of the handle.
+    ///
+    /// The ID is the CID of a raw block made of the entity CID, path, flags and session of the
+    /// handle, so it does not depend on which node opened the handle: every node of the cluster
+    /// derives the same ID for it, and opening the same unchanged entity again in the same session
+    /// gives back the same handle. The CID of the entity is used rather than that of the root, as
+    /// the root changes with every change to the tree, including the recording of the handle.
+    pub async fn id<S>(&self, store: &S) -> ServiceResult<Cid>
+    where
+        S: IpldStore,
+    {
+        let key = format!(
+            "{}\n{}\n{}\n{}",
+            self.entity,
+            self.path,
+            self.flags.bits(),
+            self.session
+        );
+
+        Ok(store.put_raw_block(key.into_bytes()).await?)
+    }
+}
+
+impl<S> FsService<S>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    /// Opens a handle to the entity at `path` with `flags` for the client session `session` and
+    /// returns its ID.
+    ///
+    /// Requires [`FsAction::Read`] on `path` if `flags` allow reading, and [`FsAction::Write`] if
+    /// they allow writing. Errors carry the path and flags in their [`ErrorContext`].
+    pub async fn open_handle(
+        &mut self,
+        capabilities: &FsCapabilities,
+        path: impl TryInto<Path, Error: Into<FsError>>,
+        flags: DescriptorFlags,
+        session: &str,
+    ) -> ServiceResult<Cid> {
+        let path = path.try_into().map_err(Into::into)?.canonicalize()?;
+        let context = || ErrorContext::new("open_handle").path(&path).flags(flags);
+        self.authorize_handle(capabilities, &path, flags)
+            .await
+            .context(context)?;
+
+        let entity = match self.root_dir.trace_entity(&path).await.context(context)? {
+            TraceResult::Found { entity, .. } => entity.store().await?,
+            _ => return Err(FsError::NotFound(path.clone())).context(context),
+        };
+
+        let record = HandleRecord {
+            entity,
+            path,
+            flags,
+            session: session.to_owned(),
+            opened_at: current_time(),
+        };
+        let id = record.id(self.root_dir.get_store()).await?;
+
+        let mut index = HandleIndex::load(&self.root_dir).await?;
+        if index.get(&id).is_none() {
+            index.handles.insert(id.to_string(), record);
+            self.root_dir = index.store_at(&self.root_dir).await?;
+        }
+
+        Ok(id)
+    }
+
+    /// Returns the handle with ID `id` opened in the client session `session`.
+    ///
+    /// The handle is looked up in the replicated [`HandleIndex`], so it resolves on any node, and
+    /// `capabilities` are checked again as they may have changed since it was opened.
+    pub async fn resolve_handle(
+        &self,
+        capabilities: &FsCapabilities,
+        id: &Cid,
+        session: &str,
+    ) -> ServiceResult<HandleRecord> {
+        let index = HandleIndex::load(&self.root_dir).await?;
+        let record = index
+            .get(id)
+            .filter(|record| record.session == session)
+            .cloned()
+            .ok_or(ServiceError::UnknownHandle(*id))?;
+
+        self.authorize_handle(capabilities, &record.path, record.flags)
+            .await
+            .context(|| {
+                ErrorContext::new("resolve_handle")
+                    .path(&record.path)
+                    .flags(record.flags)
+            })?;
+
+        Ok(record)
+    }
+
+    /// Closes the handle with ID `id` opened in the client session `session`.
+    pub async fn close_handle(&mut self, id: &Cid, session: &str) -> ServiceResult<()> {
+        let mut index = HandleIndex::load(&self.root_dir).await?;
+        let key = id.to_string();
+        match index.handles.get(&key) {
+            Some(record) if record.session == session => {}
+            _ => return Err(ServiceError::UnknownHandle(*id)),
+        }
+
+        index.handles.remove(&key);
+        self.root_dir = index.store_at(&self.root_dir).await?;
+
+        Ok(())
+    }
+
+    /// Checks that `capabilities` allow opening a handle to `path` with `flags`.
+    async fn authorize_handle(
+        &self,
+        capabilities: &FsCapabilities,
+        path: &Path,
+        flags: DescriptorFlags,
+    ) -> ServiceResult<()> {
+        if flags.contains(DescriptorFlags::READ) {
+            self.authorize(capabilities, path, FsAction::Read).await?;
+        }
+
+        if flags.contains(DescriptorFlags::WRITE) {
+            self.authorize(capabilities, path, FsAction::Write).await?;
+        }
+
+        Ok(())
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use zeroutils_store::MemoryStore;
+
+    use crate::{config::ZerofsConfig, filesystem::FsCapability};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_handles_survive_failover() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let config = Arc::new(ZerofsConfig::default());
+        let capabilities: FsCapabilities = [FsCapability {
+            resource: "zerofs://*".parse()?,
+            action: FsAction::Manage,
+        }]
+        .iter()
+        .cloned()
+        .collect();
+
+        let file = File::new(store.clone());
+        let root = Dir::new(store.clone())
+            .link_at(&"public/notes".parse()?, file.store().await?)
+            .await?;
+
+        let mut leader = FsService::new(root, config.clone());
+        let id = leader
+            .open_handle(&capabilities, "public/notes", DescriptorFlags::READ, "s1")
+            .await?;
+
+        // Opening the same path again in the same session gives back the same handle.
+        let again = leader
+            .open_handle(&capabilities, "public/notes", DescriptorFlags::READ, "s1")
+            .await?;
+        assert_eq!(id, again);
+
+        // A new leader with the replicated root resolves the handle, for its session only.
+        let follower = FsService::new(leader.root_dir.clone(), config);
+        let record = follower.resolve_handle(&capabilities, &id, "s1").await?;
+        assert_eq!(record.path, "public/notes".parse()?);
+        assert!(matches!(
+            follower.resolve_handle(&capabilities, &id, "s2").await,
+            Err(ServiceError::UnknownHandle(_))
+        ));
+
+        leader.close_handle(&id, "s1").await?;
+        assert!(HandleIndex::load(&leader.root_dir).await?.is_empty());
+
+        Ok(())
+    }
+}
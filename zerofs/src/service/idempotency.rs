@@ -0,0 +1,394 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use zeroutils_store::{ipld::cid::Cid, IpldReferences, IpldStore};
+
+use crate::filesystem::current_time;
+
+use super::{FsService, ServiceResult};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The owner the head of the idempotency index is pinned for, so garbage collection keeps it.
+pub const IDEMPOTENCY_PIN_OWNER: &str = "idempotency";
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The responses recorded for requests made with an idempotency key.
+///
+/// The index is a node in the store, apart from the file tree, so users never see it and cannot
+/// change it. Like the [`ChangeFeed`][crate::filesystem::ChangeFeed], the [`Cid`] of the node is
+/// all it takes to pick it up again, on another node after a failover included, with
+/// [`load_idempotency_index`][FsService::load_idempotency_index]. The CID is announced along with
+/// the root, so the nodes that [follow][FsService::follow_root] the announcements pick it up as
+/// they go. Keys are scoped to the DID that made the request, so clients cannot replay each other's
+/// responses.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IdempotencyIndex {
+    /// The recorded responses by the DID that made the request and then by idempotency key.
+    #[serde(default)]
+    records: BTreeMap<String, BTreeMap<String, IdempotencyRecord>>,
+}
+
+/// What is recorded for a request made with an idempotency key.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IdempotencyRecord {
+    /// Identifies the request the key was first used with, so that reusing the key for a different
+    /// request can be detected.
+    pub fingerprint: String,
+
+    /// The response to the request, or `None` while the request is still running.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response: Option<IdempotentResponse>,
+
+    /// The time after which the record is discarded.
+    pub expires_at: DateTime<Utc>,
+}
+
+/// The response recorded for a request made with an idempotency key.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IdempotentResponse {
+    /// The status code of the response.
+    pub status: u16,
+
+    /// The content type of the response.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+
+    /// The body of the response.
+    pub body: String,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl IdempotencyIndex {
+    /// Loads the idempotency index stored at `cid`.
+    pub async fn load<S>(cid: &Cid, store: &S) -> ServiceResult<Self>
+    where
+        S: IpldStore,
+    {
+        Ok(store.get_node(cid).await?)
+    }
+
+    /// Returns the unexpired record for `key` used by `did`.
+    pub fn get_record(
+        &self,
+        did: &str,
+        key: &str,
+        now: DateTime<Utc>,
+    ) -> Option<&IdempotencyRecord> {
+        self.records
+            .get(did)
+            .and_then(|records| records.get(key))
+            .filter(|record| record.expires_at > now)
+    }
+
+    /// Drops the records that have expired by `now`.
+    fn prune(&mut self, now: DateTime<Utc>) {
+        for records in self.records.values_mut() {
+            records.retain(|_, record| record.expires_at > now);
+        }
+        self.records.retain(|_, records| !records.is_empty());
+    }
+}
+
+impl<S> FsService<S>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    /// Returns the [`Cid`] of the idempotency index, or `None` if no key was ever used.
+    pub fn get_idempotency_head(&self) -> Option<&Cid> {
+        self.idempotency.as_ref()
+    }
+
+    /// Picks up the idempotency index stored at `head`, like one recorded by the previous leader,
+    /// replacing the current one.
+    pub async fn load_idempotency_index(&mut self, head: &Cid) -> ServiceResult<()> {
+        IdempotencyIndex::load(head, self.root_dir.get_store()).await?;
+        self.set_idempotency_head(*head).await
+    }
+
+    /// Returns the record for `key` used by `did`, unless it has expired.
+    pub async fn get_idempotent_record(
+        &self,
+        did: &str,
+        key: &str,
+    ) -> ServiceResult<Option<IdempotencyRecord>> {
+        let index = self.load_idempotency().await?;
+        Ok(index.get_record(did, key, current_time()).cloned())
+    }
+
+    /// Reserves `key` for the request made by `did` that `fingerprint` identifies, so that retries
+    /// made while it runs are not run as well.
+    ///
+    /// Returns the unexpired record of an earlier use of the key instead, if there is one, without
+    /// reserving anything. Expired records are dropped along the way.
+    pub async fn reserve_idempotency_key(
+        &mut self,
+        did: &str,
+        key: &str,
+        fingerprint: impl Into<String>,
+    ) -> ServiceResult<Option<IdempotencyRecord>> {
+        let now = current_time();
+        let mut index = self.load_idempotency().await?;
+        if let Some(record) = index.get_record(did, key, now) {
+            return Ok(Some(record.clone()));
+        }
+
+        index.prune(now);
+        index.records.entry(did.to_owned()).or_default().insert(
+            key.to_owned(),
+            IdempotencyRecord {
+                fingerprint: fingerprint.into(),
+                response: None,
+                expires_at: now + Duration::seconds(self.config.idempotency.ttl as i64),
+            },
+        );
+
+        self.store_idempotency(&index).await?;
+
+        Ok(None)
+    }
+
+    /// Records the response to the request made by `did` with the reserved `key`, to be replayed
+    /// for retries until the configured time to live has passed.
+    pub async fn record_idempotent_response(
+        &mut self,
+        did: &str,
+        key: &str,
+        response: IdempotentResponse,
+    ) -> ServiceResult<()> {
+        let mut index = self.load_idempotency().await?;
+        let Some(record) = index
+            .records
+            .get_mut(did)
+            .and_then(|records| records.get_mut(key))
+        else {
+            return Ok(());
+        };
+
+        record.response = Some(response);
+        record.expires_at = current_time() + Duration::seconds(self.config.idempotency.ttl as i64);
+
+        self.store_idempotency(&index).await
+    }
+
+    /// Releases `key` reserved by `did` without recording a response, like when the request
+    /// failed, so it can be retried with the same key.
+    pub async fn release_idempotency_key(&mut self, did: &str, key: &str) -> ServiceResult<()> {
+        let mut index = self.load_idempotency().await?;
+        let Some(records) = index.records.get_mut(did) else {
+            return Ok(());
+        };
+
+        if records.remove(key).is_none() {
+            return Ok(());
+        }
+
+        if records.is_empty() {
+            index.records.remove(did);
+        }
+
+        self.store_idempotency(&index).await
+    }
+
+    /// Loads the current idempotency index, which is empty if no key was ever used.
+    async fn load_idempotency(&self) -> ServiceResult<IdempotencyIndex> {
+        match &self.idempotency {
+            Some(head) => IdempotencyIndex::load(head, self.root_dir.get_store()).await,
+            None => Ok(IdempotencyIndex::default()),
+        }
+    }
+
+    /// Stores `index` and makes it the current idempotency index.
+    async fn store_idempotency(&mut self, index: &IdempotencyIndex) -> ServiceResult<()> {
+        let head = self.root_dir.get_store().put_node(index).await?;
+        self.set_idempotency_head(head).await
+    }
+
+    /// Makes the index stored at `head` the current one, moving the pin that keeps it over to it.
+    async fn set_idempotency_head(&mut self, head: Cid) -> ServiceResult<()> {
+        if self.idempotency == Some(head) {
+            return Ok(());
+        }
+
+        self.roots.pin(IDEMPOTENCY_PIN_OWNER, head);
+        if let Some(previous) = self.idempotency.replace(head) {
+            self.roots.unpin(IDEMPOTENCY_PIN_OWNER, &previous);
+        }
+
+        self.update_refcounts().await
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl IpldReferences for IdempotencyIndex {
+    fn references<'a>(&'a self) -> Box<dyn Iterator<Item = &'a Cid> + Send + 'a> {
+        Box::new(std::iter::empty())
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use zeroutils_did_wk::{Base, WrappedDidWebKey};
+    use zeroutils_key::{Ed25519KeyPair, GetPublicKey, IntoOwned, KeyPairGenerate};
+    use zeroutils_store::{MemoryStore, Storable};
+
+    use crate::{
+        config::{ZerofsConfig, ZerofsIdempotencyConfig, ZerofsReplicaConfig},
+        filesystem::Dir,
+    };
+
+    use super::*;
+
+    const ALICE: &str = "did:wk:z6MkoVs2h6TnfyY8fx2ZqpREWSLS8rBDQmGpyXgFpg63CSUb";
+    const BOB: &str = "did:wk:z6MknLif7jhwt6jUfn14EuDnxWoSHkkajyDi28QMMH5eS1DL";
+
+    #[tokio::test]
+    async fn test_idempotent_responses() -> anyhow::Result<()> {
+        let config = ZerofsConfig::builder()
+            .idempotency(ZerofsIdempotencyConfig::builder().ttl(60).build())
+            .build();
+        let mut service = FsService::new(Dir::new(MemoryStore::default()), Arc::new(config));
+
+        assert!(service
+            .reserve_idempotency_key(ALICE, "a1", "POST /write_at")
+            .await?
+            .is_none());
+
+        // A retry made while the request runs finds the reservation.
+        let record = service
+            .reserve_idempotency_key(ALICE, "a1", "POST /write_at")
+            .await?
+            .unwrap();
+        assert_eq!(record.fingerprint, "POST /write_at");
+        assert!(record.response.is_none());
+
+        service
+            .record_idempotent_response(
+                ALICE,
+                "a1",
+                IdempotentResponse {
+                    status: 200,
+                    content_type: None,
+                    body: "{}".to_owned(),
+                },
+            )
+            .await?;
+
+        let record = service.get_idempotent_record(ALICE, "a1").await?.unwrap();
+        let response = record.response.unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body, "{}");
+
+        // Keys are scoped to the DID that used them.
+        assert!(service.get_idempotent_record(BOB, "a1").await?.is_none());
+
+        // Released keys can be used again.
+        service
+            .reserve_idempotency_key(BOB, "b1", "POST /write_at")
+            .await?;
+        service.release_idempotency_key(BOB, "b1").await?;
+        assert!(service.get_idempotent_record(BOB, "b1").await?.is_none());
+
+        // Expired records are not returned.
+        let head = *service.get_idempotency_head().unwrap();
+        let index = IdempotencyIndex::load(&head, service.root_dir.get_store()).await?;
+        assert!(index
+            .get_record(ALICE, "a1", Utc::now() + Duration::seconds(61))
+            .is_none());
+
+        // The records are kept out of the file tree, and the head is kept by garbage collection.
+        assert!(service
+            .root_dir
+            .get_entity_at(&"system".parse()?)
+            .await
+            .is_err());
+        assert!(service.get_gc_roots().await?.contains(&head));
+
+        Ok(())
+    }
+    #[tokio::test]
+    async fn test_idempotent_responses_survive_failover() -> anyhow::Result<()> {
+        let key = Ed25519KeyPair::generate(&mut rand::thread_rng())?;
+        let did = WrappedDidWebKey::from_key(&key, Base::Base58Btc)?.to_string();
+        let resolve = |_: &str| Some(key.public_key().into_owned());
+
+        let store = MemoryStore::default();
+        let config = ZerofsConfig::builder()
+            .idempotency(ZerofsIdempotencyConfig::builder().ttl(60).build())
+            .build();
+        let mut leader = FsService::new(Dir::new(store.clone()), Arc::new(config));
+        leader
+            .reserve_idempotency_key(ALICE, "a1", "POST /write_at")
+            .await?;
+        leader
+            .record_idempotent_response(
+                ALICE,
+                "a1",
+                IdempotentResponse {
+                    status: 201,
+                    content_type: None,
+                    body: "{}".to_owned(),
+                },
+            )
+            .await?;
+
+        // The announcement carries the index, and a new one is made when only the index changed.
+        let first = leader.announce_root(&key).await?.clone();
+        assert_eq!(
+            first.announcement.idempotency.as_ref(),
+            leader.get_idempotency_head()
+        );
+
+        leader
+            .reserve_idempotency_key(BOB, "b1", "POST /write_at")
+            .await?;
+        let signed = leader.announce_root(&key).await?.clone();
+        assert!(signed.announcement.sequence > first.announcement.sequence);
+        assert_eq!(
+            signed.announcement.idempotency.as_ref(),
+            leader.get_idempotency_head()
+        );
+
+        // A node reopened on the announced root picks the index up and replays the response.
+        let config = ZerofsConfig::builder()
+            .idempotency(ZerofsIdempotencyConfig::builder().ttl(60).build())
+            .replica(
+                ZerofsReplicaConfig::builder()
+                    .enabled(true)
+                    .members(vec![did])
+                    .build(),
+            )
+            .build();
+        let root_dir = Dir::load(&first.announcement.root, store).await?;
+        let mut follower = FsService::new(root_dir, Arc::new(config));
+        assert!(follower.get_idempotent_record(ALICE, "a1").await?.is_none());
+
+        assert!(follower.follow_root(first, resolve).await?);
+        let record = follower.get_idempotent_record(ALICE, "a1").await?.unwrap();
+        assert_eq!(record.response.unwrap().status, 201);
+        assert_eq!(
+            follower.root_dir.store().await?,
+            leader.root_dir.store().await?
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,519 @@
+use std::{collections::HashMap, io::Cursor, sync::Arc, time::Duration as StdDuration};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::{io::AsyncReadExt, task::JoinHandle};
+use zeroutils_store::{IpldStore, Storable};
+
+use crate::filesystem::{current_time, Chunker, Dir, Entity, File, FsError, Path, TraceResult};
+
+use super::{FsService, ServiceError, ServiceResult, SharedService};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The well-known path of the file that records the background jobs, relative to the root.
+pub const JOBS_PATH: &str = "system/jobs";
+
+/// How often the job runner checks for due jobs.
+pub const DEFAULT_JOB_TICK: StdDuration = StdDuration::from_secs(1);
+
+//--------------------------------------------------------------------------------------------------
+// Traits
+//--------------------------------------------------------------------------------------------------
+
+/// Runs the background jobs of one kind.
+///
+/// A handler gets the service rather than a lock on it, so it only holds the lock while it touches
+/// the tree and other jobs and operations go on in between.
+#[async_trait]
+pub trait JobHandler<S>: Send + Sync
+where
+    S: IpldStore,
+{
+    /// Runs a job with the given payload. An error fails the attempt, which is retried later.
+    async fn run(&self, service: SharedService<S>, payload: &str) -> anyhow::Result<()>;
+}
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The background jobs of a file tree, waiting to run, running or failed.
+///
+/// The index is a TOML document stored at [`JOBS_PATH`] in the tree itself, so pending jobs survive
+/// a restart. Jobs are dropped from the index once they succeed, and kept as
+/// [`Failed`][JobStatus::Failed] once they have failed too many times, until they are looked into.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JobIndex {
+    /// The ID the next job gets.
+    #[serde(default)]
+    next_id: u64,
+
+    /// The jobs, oldest first.
+    #[serde(default)]
+    jobs: Vec<Job>,
+}
+
+/// A background job.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Job {
+    /// The ID of the job.
+    pub id: u64,
+
+    /// The kind of the job, which picks the [`JobHandler`] that runs it.
+    pub kind: String,
+
+    /// What the job works on, in a format of its handler's choosing.
+    #[serde(default)]
+    pub payload: String,
+
+    /// Where the job is at.
+    pub status: JobStatus,
+
+    /// The number of attempts that failed.
+    #[serde(default)]
+    pub attempts: u32,
+
+    /// The time the job was queued.
+    pub created_at: DateTime<Utc>,
+
+    /// The time the job is due to run next.
+    pub run_at: DateTime<Utc>,
+
+    /// The error of the last failed attempt.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+/// Where a background job is at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum JobStatus {
+    /// The job waits to run at its due time.
+    Queued,
+
+    /// The job is running.
+    Running,
+
+    /// The job failed too many times and is not retried.
+    Failed,
+}
+
+/// Runs the background jobs of a service with the [`JobHandler`]s registered for their kinds.
+pub struct JobRunner<S>
+where
+    S: IpldStore,
+{
+    handlers: HashMap<String, Arc<dyn JobHandler<S>>>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl JobIndex {
+    /// Loads the job index of the tree under `root`.
+    ///
+    /// A tree without a job index has no jobs.
+    pub async fn load<S>(root: &Dir<S>) -> ServiceResult<Self>
+    where
+        S: IpldStore + Send + Sync,
+    {
+        let path: Path = JOBS_PATH.parse()?;
+        let file = match root.trace_entity(&path).await? {
+            TraceResult::Found {
+                entity: Entity::File(file),
+                ..
+            } => file,
+            TraceResult::Found { .. } => return Err(FsError::NotAFile(Some(path)).into()),
+            _ => return Ok(Self::default()),
+        };
+
+        let mut content = String::new();
+        file.get_content_reader()
+            .await?
+            .read_to_string(&mut content)
+            .await?;
+
+        toml::from_str(&content).map_err(|e| ServiceError::InvalidJobIndex(e.to_string()))
+    }
+
+    /// Stores the index at [`JOBS_PATH`] under `root` and returns the updated root.
+    async fn store_at<S>(&self, root: &Dir<S>) -> ServiceResult<Dir<S>>
+    where
+        S: IpldStore + Send + Sync + 'static,
+    {
+        let content =
+            toml::to_string(self).map_err(|e| ServiceError::InvalidJobIndex(e.to_string()))?;
+
+        let mut file = File::new(root.get_store().clone());
+        file.put_content(Cursor::new(content.into_bytes()), &Chunker::Store)
+            .await?;
+
+        Ok(root
+            .link_at(&JOBS_PATH.parse()?, file.store().await?)
+            .await?)
+    }
+
+    /// Returns the job with the given ID.
+    pub fn get_job(&self, id: u64) -> Option<&Job> {
+        self.jobs.iter().find(|job| job.id == id)
+    }
+
+    /// Returns an iterator over the jobs, oldest first.
+    pub fn get_jobs(&self) -> impl Iterator<Item = &Job> {
+        self.jobs.iter()
+    }
+
+    /// Returns the number of jobs.
+    pub fn len(&self) -> usize {
+        self.jobs.len()
+    }
+
+    /// Returns `true` if there are no jobs.
+    pub fn is_empty(&self) -> bool {
+        self.jobs.is_empty()
+    }
+}
+
+impl<S> JobRunner<S>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    /// Creates a runner with no handlers.
+    pub fn new() -> Self {
+        Self {
+            handlers: HashMap::new(),
+        }
+    }
+
+    /// Registers `handler` to run the jobs of `kind`.
+    pub fn with_handler(
+        mut self,
+        kind: impl Into<String>,
+        handler: Arc<dyn JobHandler<S>>,
+    ) -> Self {
+        self.handlers.insert(kind.into(), handler);
+        self
+    }
+
+    /// Runs the jobs of `service` that are due, one after the other, and returns how many ran.
+    pub async fn run_due(&self, service: &SharedService<S>) -> ServiceResult<usize> {
+        let jobs = service.lock().await.claim_due_jobs().await?;
+        let count = jobs.len();
+        for job in jobs {
+            let result = self.run(service.clone(), &job).await;
+            service.lock().await.finish_job(job.id, result).await?;
+        }
+
+        Ok(count)
+    }
+
+    /// Runs `job` with the handler of its kind.
+    async fn run(&self, service: SharedService<S>, job: &Job) -> Result<(), String> {
+        let handler = self
+            .handlers
+            .get(&job.kind)
+            .ok_or_else(|| format!("no handler for jobs of kind {}", job.kind))?;
+
+        handler
+            .run(service, &job.payload)
+            .await
+            .map_err(|e| e.to_string())
+    }
+}
+
+impl<S> FsService<S>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    /// Queues a job of `kind` with `payload` to run in the background as soon as possible, and
+    /// returns its ID.
+    pub async fn enqueue_job(
+        &mut self,
+        kind: impl Into<String>,
+        payload: impl Into<String>,
+    ) -> ServiceResult<u64> {
+        let now = current_time();
+        let mut index = JobIndex::load(&self.root_dir).await?;
+
+        let id = index.next_id;
+        index.next_id += 1;
+        index.jobs.push(Job {
+            id,
+            kind: kind.into(),
+            payload: payload.into(),
+            status: JobStatus::Queued,
+            attempts: 0,
+            created_at: now,
+            run_at: now,
+            last_error: None,
+        });
+
+        self.root_dir = index.store_at(&self.root_dir).await?;
+
+        Ok(id)
+    }
+
+    /// Returns the background jobs waiting to run, running or failed.
+    pub async fn get_jobs(&self) -> ServiceResult<JobIndex> {
+        JobIndex::load(&self.root_dir).await
+    }
+
+    /// Marks the queued jobs that are due as running and returns them, oldest first, leaving out
+    /// those that would take the number of running jobs past the configured concurrency.
+    pub async fn claim_due_jobs(&mut self) -> ServiceResult<Vec<Job>> {
+        let now = current_time();
+        let mut index = JobIndex::load(&self.root_dir).await?;
+
+        let running = index
+            .jobs
+            .iter()
+            .filter(|job| job.status == JobStatus::Running)
+            .count();
+        let available = self.config.jobs.concurrency.saturating_sub(running);
+
+        let mut claimed = Vec::new();
+        for job in &mut index.jobs {
+            if claimed.len() >= available {
+                break;
+            }
+
+            if job.status == JobStatus::Queued && job.run_at <= now {
+                job.status = JobStatus::Running;
+                claimed.push(job.clone());
+            }
+        }
+
+        if !claimed.is_empty() {
+            self.root_dir = index.store_at(&self.root_dir).await?;
+        }
+
+        Ok(claimed)
+    }
+
+    /// Records the outcome of an attempt of the job `id`.
+    ///
+    /// A job that succeeded is dropped. A job that failed is queued again after a backoff that
+    /// doubles with each attempt, or marked as failed once it has been attempted as many times as
+    /// configured.
+    pub async fn finish_job(&mut self, id: u64, result: Result<(), String>) -> ServiceResult<()> {
+        let now = current_time();
+        let config = &self.config.jobs;
+        let mut index = JobIndex::load(&self.root_dir).await?;
+
+        match result {
+            Ok(()) => index.jobs.retain(|job| job.id != id),
+            Err(e) => {
+                let Some(job) = index.jobs.iter_mut().find(|job| job.id == id) else {
+                    return Ok(());
+                };
+
+                tracing::warn!("job {} of kind {} failed: {}", id, job.kind, e);
+                job.attempts += 1;
+                job.last_error = Some(e);
+                if job.attempts >= config.max_attempts {
+                    job.status = JobStatus::Failed;
+                } else {
+                    let exponent = (job.attempts - 1).min(32);
+                    let backoff = config
+                        .base_backoff
+                        .saturating_mul(1 << exponent)
+                        .min(config.max_backoff);
+                    job.status = JobStatus::Queued;
+                    job.run_at = now + Duration::seconds(backoff as i64);
+                }
+            }
+        }
+
+        self.root_dir = index.store_at(&self.root_dir).await?;
+
+        Ok(())
+    }
+
+    /// Queues the jobs left running when the service last stopped again, to run right away.
+    pub async fn requeue_running_jobs(&mut self) -> ServiceResult<()> {
+        let now = current_time();
+        let mut index = JobIndex::load(&self.root_dir).await?;
+
+        let mut requeued = false;
+        for job in &mut index.jobs {
+            if job.status == JobStatus::Running {
+                job.status = JobStatus::Queued;
+                job.run_at = now;
+                requeued = true;
+            }
+        }
+
+        if requeued {
+            self.root_dir = index.store_at(&self.root_dir).await?;
+        }
+
+        Ok(())
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Spawns a task that runs the due jobs of `service` with `runner`, checking for them every
+/// `tick`.
+///
+/// The jobs left running when the service last stopped are queued again first. Each claimed job
+/// runs in a task of its own, so up to the configured concurrency of jobs run at the same time.
+pub fn spawn_jobs<S>(
+    service: SharedService<S>,
+    runner: JobRunner<S>,
+    tick: StdDuration,
+) -> JoinHandle<()>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    let runner = Arc::new(runner);
+    tokio::spawn(async move {
+        if let Err(e) = service.lock().await.requeue_running_jobs().await {
+            tracing::warn!("failed to requeue running jobs: {e}");
+        }
+
+        let mut interval = tokio::time::interval(tick);
+        loop {
+            interval.tick().await;
+            let jobs = match service.lock().await.claim_due_jobs().await {
+                Ok(jobs) => jobs,
+                Err(e) => {
+                    tracing::warn!("failed to claim due jobs: {e}");
+                    continue;
+                }
+            };
+
+            for job in jobs {
+                let service = service.clone();
+                let runner = runner.clone();
+                tokio::spawn(async move {
+                    let result = runner.run(service.clone(), &job).await;
+                    if let Err(e) = service.lock().await.finish_job(job.id, result).await {
+                        tracing::warn!("failed to record the outcome of job {}: {e}", job.id);
+                    }
+                });
+            }
+        }
+    })
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl<S> Default for JobRunner<S>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use tokio::sync::Mutex;
+    use zeroutils_store::MemoryStore;
+
+    use crate::{
+        config::{ZerofsConfig, ZerofsJobsConfig},
+        filesystem::{with_clock, FixedClock},
+    };
+
+    use super::*;
+
+    /// Fails until it has been called `failures` times.
+    struct Flaky {
+        calls: AtomicU32,
+        failures: u32,
+    }
+
+    #[async_trait]
+    impl JobHandler<MemoryStore> for Flaky {
+        async fn run(&self, _: SharedService<MemoryStore>, payload: &str) -> anyhow::Result<()> {
+            if self.calls.fetch_add(1, Ordering::SeqCst) < self.failures {
+                anyhow::bail!("{payload} is not ready");
+            }
+
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_jobs_retry_with_backoff() -> anyhow::Result<()> {
+        let clock = Arc::new(FixedClock::new(Utc::now()));
+        with_clock(clock.clone(), async {
+            let config = ZerofsConfig::builder()
+                .jobs(
+                    ZerofsJobsConfig::builder()
+                        .max_attempts(3)
+                        .base_backoff(10)
+                        .build(),
+                )
+                .build();
+            let service = Arc::new(Mutex::new(FsService::new(
+                Dir::new(MemoryStore::default()),
+                Arc::new(config),
+            )));
+            let runner = JobRunner::new()
+                .with_handler(
+                    "flaky",
+                    Arc::new(Flaky {
+                        calls: AtomicU32::new(0),
+                        failures: 1,
+                    }),
+                )
+                .with_handler(
+                    "broken",
+                    Arc::new(Flaky {
+                        calls: AtomicU32::new(0),
+                        failures: u32::MAX,
+                    }),
+                );
+
+            let flaky = service.lock().await.enqueue_job("flaky", "index").await?;
+            let broken = service.lock().await.enqueue_job("broken", "gc").await?;
+            let orphan = service.lock().await.enqueue_job("unknown", "").await?;
+            assert_eq!(runner.run_due(&service).await?, 3);
+
+            // Failed attempts wait out their backoff.
+            let jobs = service.lock().await.get_jobs().await?;
+            let job = jobs.get_job(flaky).unwrap();
+            assert_eq!((job.status, job.attempts), (JobStatus::Queued, 1));
+            assert_eq!(job.last_error.as_deref(), Some("index is not ready"));
+            assert_eq!(runner.run_due(&service).await?, 0);
+
+            clock.advance(Duration::seconds(10));
+            assert_eq!(runner.run_due(&service).await?, 3);
+            let jobs = service.lock().await.get_jobs().await?;
+            assert!(jobs.get_job(flaky).is_none());
+            assert_eq!(jobs.get_job(broken).unwrap().attempts, 2);
+
+            // The backoff doubles, and the last attempt marks the job as failed.
+            clock.advance(Duration::seconds(10));
+            assert_eq!(runner.run_due(&service).await?, 0);
+            clock.advance(Duration::seconds(10));
+            assert_eq!(runner.run_due(&service).await?, 2);
+            let jobs = service.lock().await.get_jobs().await?;
+            assert_eq!(jobs.len(), 2);
+            assert!(jobs.get_jobs().all(|job| job.status == JobStatus::Failed));
+            assert!(jobs.get_job(orphan).unwrap().last_error.is_some());
+
+            anyhow::Ok(())
+        })
+        .await
+    }
+}
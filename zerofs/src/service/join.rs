@@ -0,0 +1,477 @@
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display},
+    net::SocketAddr,
+    str::FromStr,
+};
+
+use chrono::{DateTime, Duration, Utc};
+use data_encoding::BASE64URL_NOPAD;
+use serde::{Deserialize, Serialize};
+use serde_with::serde_as;
+use zeroutils_did_wk::WrappedDidWebKey;
+use zeroutils_key::{JwsAlgName, Sign, Verify};
+use zeroutils_store::IpldStore;
+
+use crate::{
+    config::{ZerofsConfig, ZerofsLogConfig, ZerofsStoreConfig},
+    filesystem::current_time,
+};
+
+use super::{FsService, ServiceError, ServiceResult, SignedRoot};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The prefix of the bytes a join token is signed as, so that its signatures cannot be mistaken
+/// for signatures of anything else.
+const JOIN_TOKEN_DOMAIN: &str = "zerofs join token v1";
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// An invitation for a new node to join a cluster.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JoinToken {
+    /// The ID of the cluster to join.
+    pub cluster_id: String,
+
+    /// The DID of the member that minted the token.
+    pub issuer: String,
+
+    /// The peer addresses of the members of the cluster, by DID, for the new node to reach it at.
+    pub seeds: BTreeMap<String, SocketAddr>,
+
+    /// The time the token was minted.
+    pub issued_at: DateTime<Utc>,
+
+    /// The time the token stops being accepted.
+    pub expires_at: DateTime<Utc>,
+}
+
+/// A [`JoinToken`] signed by the member that minted it.
+///
+/// It is passed to the new node as the string it [displays][Display] as, and presented by the new
+/// node to any member of the cluster to be admitted. It is accepted any number of times until it
+/// expires, so it should be handed out like a password.
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedJoinToken {
+    /// The token.
+    pub token: JoinToken,
+
+    /// The JWS name of the signature algorithm.
+    pub alg: String,
+
+    /// The signature of the [signed bytes][JoinToken::to_signed_bytes] of the token by its issuer.
+    #[serde_as(as = "serde_with::Bytes")]
+    pub signature: Vec<u8>,
+}
+
+/// A request of a new node to join a cluster.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JoinRequest {
+    /// The join token the node was given.
+    pub token: SignedJoinToken,
+
+    /// The DID of the node.
+    pub did: String,
+
+    /// The address the node takes peer connections on.
+    pub addr: SocketAddr,
+}
+
+/// What a node admitted to a cluster gets to start as a member of it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JoinGrant {
+    /// The ID of the cluster.
+    pub cluster_id: String,
+
+    /// The peer addresses of the members of the cluster, by DID, the new node included.
+    pub members: BTreeMap<String, SocketAddr>,
+
+    /// The latest root announced by the member that admitted the node, if any, for the node to
+    /// start from.
+    pub signed_root: Option<SignedRoot>,
+
+    /// The store configuration of the cluster, which every member must share to accept each
+    /// other's blocks.
+    pub store: ZerofsStoreConfig,
+
+    /// The consensus log configuration of the cluster.
+    pub log: ZerofsLogConfig,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl JoinToken {
+    /// Returns the bytes the token is signed as.
+    pub fn to_signed_bytes(&self) -> Vec<u8> {
+        let seeds = self
+            .seeds
+            .iter()
+            .map(|(did, addr)| format!("{}={}", did, addr))
+            .collect::<Vec<_>>()
+            .join(",");
+
+        format!(
+            "{}\n{}\n{}\n{}\n{}\n{}",
+            JOIN_TOKEN_DOMAIN,
+            self.cluster_id,
+            self.issuer,
+            seeds,
+            self.issued_at.timestamp_micros(),
+            self.expires_at.timestamp_micros()
+        )
+        .into_bytes()
+    }
+}
+
+impl SignedJoinToken {
+    /// Signs `token` with `key`, the key of its issuer.
+    pub fn sign<K>(token: JoinToken, key: &K) -> ServiceResult<Self>
+    where
+        K: Sign + JwsAlgName,
+    {
+        Ok(Self {
+            alg: key.alg_name().to_owned(),
+            signature: key.sign(&token.to_signed_bytes())?,
+            token,
+        })
+    }
+
+    /// Checks that the token is for the cluster `cluster_id`, was minted by one of its `members`
+    /// with a valid signature and has not expired at `now`.
+    ///
+    /// `resolve` returns the public key of a DID to check the signature with.
+    pub fn verify<V>(
+        &self,
+        cluster_id: &str,
+        members: &[String],
+        now: DateTime<Utc>,
+        resolve: impl Fn(&str) -> Option<V>,
+    ) -> ServiceResult<()>
+    where
+        V: Verify,
+    {
+        let invalid = |reason: String| Err(ServiceError::InvalidJoinToken(reason));
+        let token = &self.token;
+        if token.cluster_id != cluster_id {
+            return invalid(format!("the token is for cluster {}", token.cluster_id));
+        }
+
+        if !members.contains(&token.issuer) {
+            return invalid(format!("{} is not a member of the cluster", token.issuer));
+        }
+
+        let Some(key) = resolve(&token.issuer) else {
+            return invalid(format!("no public key for {}", token.issuer));
+        };
+
+        if key
+            .verify(&token.to_signed_bytes(), &self.signature)
+            .is_err()
+        {
+            return invalid(format!("bad signature by {}", token.issuer));
+        }
+
+        if token.expires_at <= now {
+            return invalid(format!("the token expired at {}", token.expires_at));
+        }
+
+        Ok(())
+    }
+}
+
+impl JoinGrant {
+    /// Applies the grant to `config`, the configuration of the admitted node, keeping its own
+    /// identity and addresses.
+    ///
+    /// The other members of the cluster become its seeds, and the settings the members share are
+    /// replaced with those of the cluster.
+    pub fn apply_to(&self, config: &mut ZerofsConfig) -> ServiceResult<()> {
+        let did = config.network.id.to_string();
+        let mut seeds = std::collections::HashMap::new();
+        for (member, addr) in &self.members {
+            if *member != did {
+                seeds.insert(member.parse::<WrappedDidWebKey>()?, *addr);
+            }
+        }
+
+        config.network.seeds = seeds;
+        config.store = self.store.clone();
+        config.log = self.log.clone();
+        config.cluster.id = Some(self.cluster_id.clone());
+
+        Ok(())
+    }
+}
+
+impl<S> FsService<S>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    /// Returns the ID of the cluster this node is a member of.
+    ///
+    /// It is the [configured][crate::config::ZerofsClusterConfig::id] one, or the DID of this node
+    /// if there is none, as a node started on its own founds a cluster of its own.
+    pub fn get_cluster_id(&self) -> String {
+        self.config
+            .cluster
+            .id
+            .clone()
+            .unwrap_or_else(|| self.config.network.id.to_string())
+    }
+
+    /// Returns the peer addresses of the members of the cluster, by DID: this node, its seed peers
+    /// and the nodes it admitted.
+    pub fn get_member_addresses(&self) -> BTreeMap<String, SocketAddr> {
+        let network = &self.config.network;
+        let mut members = BTreeMap::new();
+        members.insert(
+            network.id.to_string(),
+            SocketAddr::new(network.host, network.peer_port),
+        );
+        members.extend(
+            network
+                .seeds
+                .iter()
+                .map(|(did, addr)| (did.to_string(), *addr)),
+        );
+        members.extend(self.admitted.clone());
+        members
+    }
+
+    /// Mints a join token for a new node, signed with `key`, the key of this node, and valid for
+    /// the [configured][crate::config::ZerofsClusterConfig::join_token_ttl] time.
+    ///
+    /// Read replicas are not members of the cluster and cannot mint tokens.
+    pub fn mint_join_token<K>(&self, key: &K) -> ServiceResult<SignedJoinToken>
+    where
+        K: Sign + JwsAlgName,
+    {
+        if self.config.replica.enabled {
+            return Err(ServiceError::InvalidJoinToken(
+                "a read replica cannot invite members".into(),
+            ));
+        }
+
+        let issued_at = current_time();
+        let token = JoinToken {
+            cluster_id: self.get_cluster_id(),
+            issuer: self.config.network.id.to_string(),
+            seeds: self.get_member_addresses(),
+            issued_at,
+            expires_at: issued_at + Duration::seconds(self.config.cluster.join_token_ttl as i64),
+        };
+
+        SignedJoinToken::sign(token, key)
+    }
+
+    /// Admits the node making `request` to the cluster if its token verifies, and returns what it
+    /// needs to start as a member.
+    ///
+    /// `peer` is the DID the node making the request proved it holds, and the request is refused
+    /// if it is for another DID. `resolve` returns the public key of a member DID. The node is
+    /// added to the members of the cluster, with the address it gave, and is included in the
+    /// tokens minted after. A node that joins again, like after moving to another address, is
+    /// admitted again with its new address, which no other node can do in its place.
+    ///
+    /// The service does not run a Raft node yet, so the membership change only takes effect on
+    /// this node. Other members learn of the new node when it connects to them.
+    pub fn admit_member<V>(
+        &mut self,
+        request: JoinRequest,
+        peer: &str,
+        resolve: impl Fn(&str) -> Option<V>,
+    ) -> ServiceResult<JoinGrant>
+    where
+        V: Verify,
+    {
+        if self.config.replica.enabled {
+            return Err(ServiceError::InvalidJoinToken(
+                "a read replica cannot admit members".into(),
+            ));
+        }
+
+        if request.did != peer {
+            return Err(ServiceError::InvalidPeerIdentity(format!(
+                "{} asked to join as {}",
+                peer, request.did
+            )));
+        }
+
+        request.token.verify(
+            &self.get_cluster_id(),
+            &self.get_cluster_members(),
+            current_time(),
+            resolve,
+        )?;
+
+        if request.did == self.config.network.id.to_string() {
+            return Err(ServiceError::InvalidJoinToken(
+                "a node cannot admit itself".into(),
+            ));
+        }
+
+        tracing::info!(
+            "admitting {} at {} to the cluster",
+            request.did,
+            request.addr
+        );
+        self.admitted.insert(request.did, request.addr);
+
+        Ok(JoinGrant {
+            cluster_id: self.get_cluster_id(),
+            members: self.get_member_addresses(),
+            signed_root: self.signed_root.clone(),
+            store: self.config.store.clone(),
+            log: self.config.log.clone(),
+        })
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl Display for SignedJoinToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let json = serde_json::to_vec(self).map_err(|_| fmt::Error)?;
+        write!(f, "{}", BASE64URL_NOPAD.encode(&json))
+    }
+}
+
+impl FromStr for SignedJoinToken {
+    type Err = ServiceError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let json = BASE64URL_NOPAD
+            .decode(s.trim().as_bytes())
+            .map_err(|e| ServiceError::InvalidJoinToken(e.to_string()))?;
+
+        serde_json::from_slice(&json).map_err(|e| ServiceError::InvalidJoinToken(e.to_string()))
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use zeroutils_did_wk::Base;
+    use zeroutils_key::{Ed25519KeyPair, GetPublicKey, IntoOwned, KeyPairGenerate};
+    use zeroutils_store::MemoryStore;
+
+    use crate::{
+        config::NodeCodec,
+        filesystem::Dir,
+        service::{DiscoveredPeer, DiscoverySource},
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_join_cluster_with_token() -> anyhow::Result<()> {
+        let key = Ed25519KeyPair::generate(&mut rand::thread_rng())?;
+        let did = WrappedDidWebKey::from_key(&key, Base::Base58Btc)?;
+        let resolve = |_: &str| Some(key.public_key().into_owned());
+
+        let mut config = ZerofsConfig::default();
+        config.network.id = did.clone();
+        config.store.codec = NodeCodec::DagJson;
+        let mut service = FsService::new(Dir::new(MemoryStore::default()), Arc::new(config));
+        service.announce_root(&key).await?;
+
+        // The token survives being passed around as a string.
+        let token: SignedJoinToken = service.mint_join_token(&key)?.to_string().parse()?;
+        assert_eq!(token.token.cluster_id, did.to_string());
+        assert!(token.token.seeds.contains_key(&did.to_string()));
+
+        let joiner = "did:wk:z6MknLif7jhwt6jUfn14EuDnxWoSHkkajyDi28QMMH5eS1DL";
+        let addr: SocketAddr = "127.0.0.1:7900".parse()?;
+        let grant = service.admit_member(
+            JoinRequest {
+                token: token.clone(),
+                did: joiner.into(),
+                addr,
+            },
+            joiner,
+            resolve,
+        )?;
+        assert_eq!(grant.members.get(joiner), Some(&addr));
+        assert!(grant.signed_root.is_some());
+        assert!(service.get_cluster_members().contains(&joiner.to_owned()));
+
+        // The new node takes on the settings of the cluster and knows the other members.
+        let mut joined = ZerofsConfig::default();
+        joined.network.id = joiner.parse()?;
+        grant.apply_to(&mut joined)?;
+        assert_eq!(joined.store.codec, NodeCodec::DagJson);
+        assert_eq!(joined.cluster.id, Some(did.to_string()));
+        assert_eq!(joined.network.seeds.len(), 1);
+
+        // Another node cannot join as the new member, or move it to another address.
+        let impostor = "did:wk:z6MkoVs2h6TnfyY8fx2ZqpREWSLS8rBDQmGpyXgFpg63CSUb";
+        let moved = service.admit_member(
+            JoinRequest {
+                token: token.clone(),
+                did: joiner.into(),
+                addr: "127.0.0.1:7901".parse()?,
+            },
+            impostor,
+            resolve,
+        );
+        assert!(matches!(moved, Err(ServiceError::InvalidPeerIdentity(_))));
+        assert_eq!(service.get_member_addresses().get(joiner), Some(&addr));
+
+        // Tampered and expired tokens are refused.
+        let mut tampered = token.clone();
+        tampered.token.seeds.clear();
+        let later = token.token.expires_at + Duration::seconds(1);
+        let members = service.get_cluster_members();
+        assert!(matches!(
+            tampered.verify(&did.to_string(), &members, current_time(), resolve),
+            Err(ServiceError::InvalidJoinToken(_))
+        ));
+        assert!(matches!(
+            token.verify(&did.to_string(), &members, later, resolve),
+            Err(ServiceError::InvalidJoinToken(_))
+        ));
+        assert!(token
+            .verify("another cluster", &members, current_time(), resolve)
+            .is_err());
+
+        // Discovered peers are only candidates to connect to, so their tokens are refused.
+        let discovered = DiscoveredPeer {
+            did: impostor.into(),
+            addr: "127.0.0.1:7902".parse()?,
+            source: DiscoverySource::Mdns,
+        };
+        assert!(service.add_discovered_peer(&discovered));
+        assert!(!service.add_discovered_peer(&discovered));
+        assert!(service.get_discovered_peers().contains_key(impostor));
+        assert!(!service.get_member_addresses().contains_key(impostor));
+
+        let mut forged = token.clone();
+        forged.token.issuer = impostor.into();
+        assert!(matches!(
+            forged.verify(
+                &did.to_string(),
+                &service.get_cluster_members(),
+                current_time(),
+                resolve
+            ),
+            Err(ServiceError::InvalidJoinToken(_))
+        ));
+
+        anyhow::Ok(())
+    }
+}
@@ -0,0 +1,362 @@
+use std::{
+    collections::BTreeMap,
+    sync::atomic::Ordering,
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinHandle;
+use zeroutils_store::IpldStore;
+
+pub use crate::config::MaintenanceTask;
+use crate::filesystem;
+
+use super::{FsService, ServiceResult, SharedService};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// How often the maintenance runner checks for due tasks.
+pub const DEFAULT_MAINTENANCE_TICK: Duration = Duration::from_secs(30);
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The outcome of a maintenance task run.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "message", rename_all = "snake_case")]
+pub enum MaintenanceOutcome {
+    /// The task ran to completion.
+    Completed(String),
+
+    /// The task had nothing to do or cannot run with the current setup.
+    Skipped(String),
+
+    /// The task failed.
+    Failed(String),
+}
+
+/// The run history of a maintenance task.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MaintenanceStatus {
+    /// The number of times the task has run.
+    pub runs: u64,
+
+    /// The number of runs that failed.
+    pub failures: u64,
+
+    /// The time the task last ran.
+    pub last_run_at: Option<DateTime<Utc>>,
+
+    /// How long the last run took, in milliseconds.
+    pub last_duration_ms: Option<u64>,
+
+    /// The outcome of the last run.
+    pub last_outcome: Option<MaintenanceOutcome>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl<S> FsService<S>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    /// Runs a maintenance task right away and records its outcome.
+    pub async fn run_maintenance_task(&mut self, task: MaintenanceTask) -> MaintenanceOutcome {
+        let started_at = Utc::now();
+        let timer = Instant::now();
+
+        let outcome = match self.run_task(task).await {
+            Ok(outcome) => outcome,
+            Err(e) => MaintenanceOutcome::Failed(e.to_string()),
+        };
+
+        let status = self.maintenance.entry(task).or_default();
+        status.runs += 1;
+        if let MaintenanceOutcome::Failed(e) = &outcome {
+            status.failures += 1;
+            tracing::warn!("maintenance task {task:?} failed: {e}");
+        }
+
+        status.last_run_at = Some(started_at);
+        status.last_duration_ms = Some(timer.elapsed().as_millis() as u64);
+        status.last_outcome = Some(outcome.clone());
+
+        outcome
+    }
+
+    /// Runs the maintenance tasks whose interval has elapsed, as long as the service has been
+    /// idle for the configured quiet period.
+    pub async fn run_due_maintenance(&mut self) -> Vec<(MaintenanceTask, MaintenanceOutcome)> {
+        let now = Utc::now();
+        let config = &self.config.maintenance;
+
+        let idle_ms = now.timestamp_millis() - self.last_activity.load(Ordering::Relaxed);
+        if idle_ms < (config.quiet_period * 1000) as i64 {
+            return Vec::new();
+        }
+
+        let due = config
+            .intervals
+            .iter()
+            .filter(|(task, interval)| {
+                let last_run_at = self
+                    .maintenance
+                    .get(task)
+                    .and_then(|status| status.last_run_at);
+
+                last_run_at.is_none_or(|last| (now - last).num_seconds() >= **interval as i64)
+            })
+            .map(|(task, _)| *task)
+            .collect::<Vec<_>>();
+
+        let mut outcomes = Vec::with_capacity(due.len());
+        for task in due {
+            outcomes.push((task, self.run_maintenance_task(task).await));
+        }
+
+        outcomes
+    }
+
+    /// Returns the run history of every maintenance task that has run.
+    pub fn get_maintenance_status(&self) -> &BTreeMap<MaintenanceTask, MaintenanceStatus> {
+        &self.maintenance
+    }
+
+    async fn run_task(&mut self, task: MaintenanceTask) -> ServiceResult<MaintenanceOutcome> {
+        if self.is_read_only()
+            && matches!(
+                task,
+                MaintenanceTask::GarbageCollect
+                    | MaintenanceTask::Snapshot
+                    | MaintenanceTask::PurgeTrash
+            )
+        {
+            return Ok(MaintenanceOutcome::Skipped(
+                "the file tree is read-only".to_owned(),
+            ));
+        }
+
+        let outcome = match task {
+            MaintenanceTask::GarbageCollect => {
+                // Blocks are not removed, but derived blobs of sources that are gone are dropped
+                // from the index so that nothing keeps them around.
+                let (root_dir, pruned) = filesystem::prune_derived(&self.root_dir).await?;
+                if pruned > 0 {
+                    self.root_dir = root_dir;
+                    self.record_operation("gc", &filesystem::DERIVED_PATH.parse()?)
+                        .await?;
+                }
+
+                // Roots can be dropped without an operation, like snapshots deleted by an
+                // admin, so the counts are brought up to date with the roots kept now.
+                self.update_refcounts().await?;
+                match &self.refcounts {
+                    Some(_) => MaintenanceOutcome::Completed(format!(
+                        "dropped {pruned} derived blobs, {} blocks are unreferenced",
+                        self.unreferenced.len()
+                    )),
+                    None if pruned > 0 => {
+                        MaintenanceOutcome::Completed(format!("dropped {pruned} derived blobs"))
+                    }
+                    None => {
+                        return Ok(MaintenanceOutcome::Skipped(
+                            "the store does not support removing blocks".to_owned(),
+                        ))
+                    }
+                }
+            }
+            MaintenanceTask::Snapshot => {
+                let keep = self.config.maintenance.keep_snapshots;
+                let (root_dir, id) = filesystem::create_snapshot(&self.root_dir, keep).await?;
+                self.root_dir = root_dir;
+                self.record_operation("snapshot", &filesystem::SNAPSHOTS_PATH.parse()?)
+                    .await?;
+                MaintenanceOutcome::Completed(format!("created snapshot {id}"))
+            }
+            MaintenanceTask::PurgeTrash => {
+                if !self.config.trash.enabled {
+                    return Ok(MaintenanceOutcome::Skipped(
+                        "the trash is disabled".to_owned(),
+                    ));
+                }
+
+                let Some(retention) = self.config.trash.retention else {
+                    return Ok(MaintenanceOutcome::Skipped(
+                        "no trash retention is configured".to_owned(),
+                    ));
+                };
+
+                let older_than = Utc::now() - chrono::Duration::seconds(retention as i64);
+                let (root_dir, purged) =
+                    filesystem::purge_trash(&self.root_dir, older_than).await?;
+                self.root_dir = root_dir;
+                self.record_operation("purge", &filesystem::TRASH_PATH.parse()?)
+                    .await?;
+                MaintenanceOutcome::Completed(format!("purged {purged} entities"))
+            }
+            MaintenanceTask::Verify => {
+                let report = filesystem::verify(&self.root_dir).await?;
+                if report.is_ok() {
+                    MaintenanceOutcome::Completed(format!(
+                        "checked {} entities and {} blocks",
+                        report.entities, report.blocks
+                    ))
+                } else {
+                    MaintenanceOutcome::Failed(format!(
+                        "{} of {} blocks are missing",
+                        report.missing.len(),
+                        report.blocks
+                    ))
+                }
+            }
+        };
+
+        Ok(outcome)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Spawns a task that runs due maintenance on `service` every `tick`.
+pub fn spawn_maintenance<S>(service: SharedService<S>, tick: Duration) -> JoinHandle<()>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(tick);
+        loop {
+            interval.tick().await;
+            service.lock().await.run_due_maintenance().await;
+        }
+    })
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use zeroutils_store::{MemoryStore, Storable};
+
+    use crate::{
+        config::{ZerofsConfig, ZerofsMaintenanceConfig},
+        filesystem::{Dir, File, SnapshotIndex},
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_maintenance_runs_due_tasks() -> anyhow::Result<()> {
+        let config = ZerofsConfig::builder()
+            .maintenance(
+                ZerofsMaintenanceConfig::builder()
+                    .intervals(BTreeMap::from([
+                        (MaintenanceTask::Snapshot, 3600),
+                        (MaintenanceTask::Verify, 3600),
+                    ]))
+                    .quiet_period(0)
+                    .build(),
+            )
+            .build();
+
+        let mut service = FsService::new(Dir::new(MemoryStore::default()), Arc::new(config));
+
+        let outcomes = service.run_due_maintenance().await;
+        assert_eq!(outcomes.len(), 2);
+        assert!(outcomes
+            .iter()
+            .all(|(_, outcome)| matches!(outcome, MaintenanceOutcome::Completed(_))));
+        assert_eq!(SnapshotIndex::load(&service.root_dir).await?.len(), 1);
+
+        // Nothing is due again until the interval has elapsed.
+        assert!(service.run_due_maintenance().await.is_empty());
+
+        let outcome = service
+            .run_maintenance_task(MaintenanceTask::GarbageCollect)
+            .await;
+        assert!(matches!(outcome, MaintenanceOutcome::Skipped(_)));
+
+        let status = service.get_maintenance_status();
+        assert_eq!(status[&MaintenanceTask::Snapshot].runs, 1);
+        assert_eq!(status[&MaintenanceTask::GarbageCollect].failures, 0);
+
+        // Tasks that change the file tree are skipped while it is read-only.
+        service.set_read_only(true);
+        let outcome = service
+            .run_maintenance_task(MaintenanceTask::Snapshot)
+            .await;
+        assert!(matches!(outcome, MaintenanceOutcome::Skipped(_)));
+        assert_eq!(SnapshotIndex::load(&service.root_dir).await?.len(), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_gc_keeps_blocks_until_snapshots_and_pins_are_dropped() -> anyhow::Result<()> {
+        let config = ZerofsConfig::builder()
+            .maintenance(
+                ZerofsMaintenanceConfig::builder()
+                    .refcount_index(true)
+                    .build(),
+            )
+            .build();
+
+        let store = MemoryStore::default();
+        let file_cid = File::new(store.clone()).store().await?;
+        let root_dir = Dir::new(store.clone())
+            .link_at(&"public/a".parse()?, file_cid)
+            .await?;
+        let mut service = FsService::new(root_dir, Arc::new(config));
+
+        let gc = MaintenanceTask::GarbageCollect;
+        assert!(matches!(
+            service.run_maintenance_task(gc).await,
+            MaintenanceOutcome::Completed(_)
+        ));
+        assert_eq!(service.get_refcount(&file_cid), Some(1));
+
+        // A snapshot keeps the file after it is replaced in the tree.
+        let (root_dir, id) = filesystem::create_snapshot(&service.root_dir, None).await?;
+        let other = store.put_raw_block(b"other".to_vec()).await?;
+        service.root_dir = root_dir.link_at(&"public/a".parse()?, other).await?;
+        service.run_maintenance_task(gc).await;
+        assert_eq!(service.get_refcount(&file_cid), Some(1));
+        assert!(!service
+            .take_unreferenced_blocks()
+            .unwrap()
+            .contains(&file_cid));
+
+        // A pin of the snapshotted root keeps it after the snapshot is deleted, until it is
+        // unpinned.
+        let snapshot = SnapshotIndex::load(&service.root_dir)
+            .await?
+            .get_snapshot(&id)
+            .unwrap()
+            .root;
+        service.pin_root("reader", snapshot).await?;
+        service.root_dir = filesystem::delete_snapshot(&service.root_dir, &id).await?;
+        service.run_maintenance_task(gc).await;
+        assert_eq!(service.get_refcount(&file_cid), Some(1));
+        assert!(service.get_gc_roots().await?.contains(&snapshot));
+
+        assert!(service.unpin_root("reader", &snapshot).await?);
+        service.run_maintenance_task(gc).await;
+        assert_eq!(service.get_refcount(&file_cid), Some(0));
+        let unreferenced = service.take_unreferenced_blocks().unwrap();
+        assert!(unreferenced.contains(&file_cid));
+        assert!(unreferenced.contains(&snapshot));
+
+        Ok(())
+    }
+}
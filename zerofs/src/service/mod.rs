@@ -1,21 +1,62 @@
 //! The service module provides the file system service.
 
+mod admin;
+mod announce;
+mod batch;
 mod builder;
+mod consistency;
+mod context;
+mod discovery;
+mod disklog;
 mod error;
+#[cfg(feature = "gateway")]
+mod gateway;
+mod gossip;
+mod handles;
+mod idempotency;
+mod jobs;
+mod join;
+mod maintenance;
 mod peer;
+#[cfg(feature = "wasi_api")]
+mod preopen;
+mod problem;
+mod receipt;
+mod replica;
 mod request;
 mod service;
 mod statemachine;
 mod user;
+mod wire;
 
 //--------------------------------------------------------------------------------------------------
 // Exports
 //--------------------------------------------------------------------------------------------------
 
+pub use admin::*;
+pub use announce::*;
+pub use batch::*;
 pub use builder::*;
+pub use consistency::*;
+pub use context::*;
+pub use discovery::*;
+pub use disklog::*;
 pub use error::*;
+#[cfg(feature = "gateway")]
+pub use gateway::*;
+pub use gossip::*;
+pub use handles::*;
+pub use idempotency::*;
+pub use jobs::*;
+pub use join::*;
+pub use maintenance::*;
 pub use peer::*;
+#[cfg(feature = "wasi_api")]
+pub use preopen::*;
+pub use problem::*;
+pub use receipt::*;
 pub use request::*;
 pub use service::*;
 pub use statemachine::*;
 pub use user::*;
+pub use wire::*;
@@ -0,0 +1,251 @@
+use bitflags::bitflags;
+use serde::{de::IgnoredAny, Deserialize, Deserializer, Serialize};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+
+use crate::{
+    config::{HashFunction, NodeCodec, ZerofsStoreConfig},
+    service::{read_frame, write_frame, Envelope, ServiceError, ServiceResult},
+};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The version of the peer protocol spoken by this version.
+pub const PEER_PROTOCOL_VERSION: u32 = 1;
+
+/// The oldest version of the peer protocol this version can still speak.
+///
+/// A node keeps speaking older versions for as long as nodes of those versions may be in the
+/// cluster, so that a cluster can be upgraded one node at a time.
+pub const MIN_PEER_PROTOCOL_VERSION: u32 = 1;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+bitflags! {
+    /// Optional features of the peer protocol.
+    ///
+    /// A feature is only used on a connection if both peers support it. Features a peer does not
+    /// know are dropped when its hello is read.
+    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+    pub struct PeerFeatures: u32 {
+        /// Blocks can be sent as erasure-coded shards.
+        const ERASURE = 0b0000_0001;
+
+        /// Blocks can be sent zstd-compressed.
+        const COMPRESSION = 0b0000_0010;
+    }
+}
+
+/// The first message each peer sends on a new connection, describing what it supports.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerHello {
+    /// The newest version of the protocol the peer speaks.
+    protocol_version: u32,
+
+    /// The oldest version of the protocol the peer speaks.
+    min_protocol_version: u32,
+
+    /// The hash functions of the CIDs the peer accepts.
+    #[serde(deserialize_with = "deserialize_known")]
+    hashes: Vec<HashFunction>,
+
+    /// The codecs of the nodes the peer accepts.
+    #[serde(deserialize_with = "deserialize_known")]
+    codecs: Vec<NodeCodec>,
+
+    /// The bits of the [`PeerFeatures`] the peer supports.
+    #[serde(default)]
+    features: u32,
+}
+
+/// What two peers agreed on in their handshake.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PeerSession {
+    /// The version of the protocol spoken on the connection.
+    pub protocol_version: u32,
+
+    /// The features used on the connection.
+    pub features: PeerFeatures,
+}
+
+/// A value that is either one this version knows or one it skips.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MaybeKnown<T> {
+    Known(T),
+    Unknown(IgnoredAny),
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl PeerFeatures {
+    /// Returns the features this build supports.
+    pub fn supported() -> Self {
+        let mut features = PeerFeatures::COMPRESSION;
+        if cfg!(feature = "erasure") {
+            features |= PeerFeatures::ERASURE;
+        }
+
+        features
+    }
+}
+
+impl PeerHello {
+    /// Creates the hello of a node with the given store configuration.
+    ///
+    /// A node only accepts CIDs made with its own hash function and codec, so those are the only
+    /// ones it advertises.
+    pub fn new(store: &ZerofsStoreConfig) -> Self {
+        Self {
+            protocol_version: PEER_PROTOCOL_VERSION,
+            min_protocol_version: MIN_PEER_PROTOCOL_VERSION,
+            hashes: vec![store.hash],
+            codecs: vec![store.codec],
+            features: PeerFeatures::supported().bits(),
+        }
+    }
+
+    /// Returns the features the peer supports that this version knows.
+    pub fn get_features(&self) -> PeerFeatures {
+        PeerFeatures::from_bits_truncate(self.features)
+    }
+
+    /// Agrees on a session between this node, with hello `self`, and a peer with hello `remote`.
+    ///
+    /// The newest protocol version both peers speak is used, along with the features both support.
+    /// It fails if there is no such version, or if the peers do not share a hash function and
+    /// codec, as they would refuse each other's blocks.
+    pub fn negotiate(&self, remote: &PeerHello) -> ServiceResult<PeerSession> {
+        let protocol_version = self.protocol_version.min(remote.protocol_version);
+        let min_protocol_version = self.min_protocol_version.max(remote.min_protocol_version);
+        if protocol_version < min_protocol_version {
+            return Err(ServiceError::IncompatiblePeer(format!(
+                "no common protocol version: local speaks {}..={}, peer speaks {}..={}",
+                self.min_protocol_version,
+                self.protocol_version,
+                remote.min_protocol_version,
+                remote.protocol_version
+            )));
+        }
+
+        if !self.hashes.iter().any(|hash| remote.hashes.contains(hash)) {
+            return Err(ServiceError::IncompatiblePeer(format!(
+                "no common hash function: local accepts {:?}, peer accepts {:?}",
+                self.hashes, remote.hashes
+            )));
+        }
+
+        if !self
+            .codecs
+            .iter()
+            .any(|codec| remote.codecs.contains(codec))
+        {
+            return Err(ServiceError::IncompatiblePeer(format!(
+                "no common codec: local accepts {:?}, peer accepts {:?}",
+                self.codecs, remote.codecs
+            )));
+        }
+
+        Ok(PeerSession {
+            protocol_version,
+            features: self.get_features() & remote.get_features(),
+        })
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Runs the handshake on a new peer connection: sends `local`, reads the peer's hello and agrees
+/// on a session.
+///
+/// Both peers send their hello first, so neither waits on the other.
+pub async fn handshake<S>(stream: &mut S, local: &PeerHello) -> ServiceResult<PeerSession>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    write_frame(stream, &Envelope::new(local)).await?;
+    stream.flush().await?;
+
+    let remote = read_frame::<_, Envelope<PeerHello>>(stream)
+        .await?
+        .ok_or_else(|| ServiceError::IncompatiblePeer("connection closed before hello".into()))?
+        .into_body()?;
+
+    local.negotiate(&remote)
+}
+
+/// Reads a list, skipping the values this version does not know.
+fn deserialize_known<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Ok(Vec::<MaybeKnown<T>>::deserialize(deserializer)?
+        .into_iter()
+        .filter_map(|value| match value {
+            MaybeKnown::Known(value) => Some(value),
+            MaybeKnown::Unknown(_) => None,
+        })
+        .collect())
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_peer_handshake() -> anyhow::Result<()> {
+        let local = PeerHello::new(&ZerofsStoreConfig::default());
+
+        // A newer peer that still speaks this version agrees on it and drops unknown features.
+        let newer = PeerHello {
+            protocol_version: PEER_PROTOCOL_VERSION + 1,
+            min_protocol_version: PEER_PROTOCOL_VERSION,
+            features: PeerFeatures::COMPRESSION.bits() | 0x8000,
+            ..local.clone()
+        };
+        let session = local.negotiate(&newer)?;
+        assert_eq!(session.protocol_version, PEER_PROTOCOL_VERSION);
+        assert_eq!(session.features, PeerFeatures::COMPRESSION);
+
+        // A peer that no longer speaks this version is refused.
+        let too_new = PeerHello {
+            protocol_version: PEER_PROTOCOL_VERSION + 2,
+            min_protocol_version: PEER_PROTOCOL_VERSION + 1,
+            ..local.clone()
+        };
+        assert!(matches!(
+            local.negotiate(&too_new),
+            Err(ServiceError::IncompatiblePeer(_))
+        ));
+
+        // So is a peer whose blocks this node would refuse.
+        let other_hash = PeerHello {
+            hashes: vec![HashFunction::Sha2_256],
+            ..local.clone()
+        };
+        assert!(matches!(
+            local.negotiate(&other_hash),
+            Err(ServiceError::IncompatiblePeer(_))
+        ));
+
+        // Over a connection, both sides agree on the same session.
+        let (mut a, mut b) = tokio::io::duplex(1024);
+        let (session_a, session_b) =
+            tokio::try_join!(handshake(&mut a, &local), handshake(&mut b, &newer))?;
+        assert_eq!(session_a, session_b);
+
+        Ok(())
+    }
+}
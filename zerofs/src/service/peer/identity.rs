@@ -0,0 +1,163 @@
+use serde::{Deserialize, Serialize};
+use serde_with::serde_as;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use zeroutils_key::{JwsAlgName, Sign, Verify};
+
+use crate::service::{read_frame, write_frame, Envelope, ServiceError, ServiceResult};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The prefix of the bytes a peer signs to prove its identity, so that its signatures cannot be
+/// mistaken for signatures of anything else.
+const IDENTITY_DOMAIN: &str = "zerofs peer identity v1";
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// A random challenge a peer signs to prove it holds the key of its DID.
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IdentityChallenge {
+    /// The random bytes to sign.
+    #[serde_as(as = "serde_with::Bytes")]
+    pub nonce: [u8; 32],
+}
+
+/// The answer of a peer to an [`IdentityChallenge`].
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IdentityProof {
+    /// The DID of the peer.
+    pub did: String,
+
+    /// The JWS name of the signature algorithm.
+    pub alg: String,
+
+    /// The signature of the challenge by the key of the DID.
+    #[serde_as(as = "serde_with::Bytes")]
+    pub signature: Vec<u8>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl IdentityChallenge {
+    /// Creates a challenge with a random nonce.
+    pub fn new() -> Self {
+        Self {
+            nonce: rand::random(),
+        }
+    }
+
+    /// Returns the bytes a peer signs to answer the challenge.
+    pub fn to_signed_bytes(&self) -> Vec<u8> {
+        let mut bytes = format!("{}\n", IDENTITY_DOMAIN).into_bytes();
+        bytes.extend_from_slice(&self.nonce);
+        bytes
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Checks that the peer at the other end of `stream` is `did`, by having it sign a random
+/// challenge with its key, after the [handshake][super::handshake].
+///
+/// `resolve` returns the public key of a DID to check the signature with. Fails with
+/// [`ServiceError::InvalidPeerIdentity`] if the peer answers as another DID or its signature does
+/// not verify, like a peer advertised with a DID it does not hold.
+pub async fn verify_peer_identity<T, V>(
+    stream: &mut T,
+    did: &str,
+    resolve: impl Fn(&str) -> Option<V>,
+) -> ServiceResult<()>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+    V: Verify,
+{
+    let proven = identify_peer(stream, resolve).await?;
+    if proven != did {
+        return Err(ServiceError::InvalidPeerIdentity(format!(
+            "expected {}, the peer is {}",
+            did, proven
+        )));
+    }
+
+    Ok(())
+}
+
+/// Returns the DID of the peer at the other end of `stream`, once it has proven it holds its key
+/// by signing a random challenge, after the [handshake][super::handshake].
+///
+/// This is for peers whose DID is not known beforehand, like a node asking to join the cluster.
+/// `resolve` returns the public key of a DID to check the signature with. Fails with
+/// [`ServiceError::InvalidPeerIdentity`] if the signature does not verify.
+pub async fn identify_peer<T, V>(
+    stream: &mut T,
+    resolve: impl Fn(&str) -> Option<V>,
+) -> ServiceResult<String>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+    V: Verify,
+{
+    let challenge = IdentityChallenge::new();
+    write_frame(stream, &Envelope::new(&challenge)).await?;
+    stream.flush().await?;
+
+    let proof = read_frame::<_, Envelope<IdentityProof>>(stream)
+        .await?
+        .ok_or_else(|| ServiceError::InvalidPeerIdentity("connection closed before proof".into()))?
+        .into_body()?;
+
+    let key = resolve(&proof.did).ok_or_else(|| {
+        ServiceError::InvalidPeerIdentity(format!("no public key for {}", proof.did))
+    })?;
+
+    key.verify(&challenge.to_signed_bytes(), &proof.signature)
+        .map_err(|_| {
+            ServiceError::InvalidPeerIdentity(format!("bad signature by {}", proof.did))
+        })?;
+
+    Ok(proof.did)
+}
+
+/// Answers the identity challenge of the peer at the other end of `stream` as `did`, signing it
+/// with `key`, the key of this node.
+pub async fn prove_identity<T, K>(stream: &mut T, did: &str, key: &K) -> ServiceResult<()>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+    K: Sign + JwsAlgName,
+{
+    let challenge = read_frame::<_, Envelope<IdentityChallenge>>(stream)
+        .await?
+        .ok_or_else(|| {
+            ServiceError::InvalidPeerIdentity("connection closed before challenge".into())
+        })?
+        .into_body()?;
+
+    let proof = IdentityProof {
+        did: did.to_owned(),
+        alg: key.alg_name().to_owned(),
+        signature: key.sign(&challenge.to_signed_bytes())?,
+    };
+
+    write_frame(stream, &Envelope::new(&proof)).await?;
+    stream.flush().await?;
+
+    Ok(())
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl Default for IdentityChallenge {
+    fn default() -> Self {
+        Self::new()
+    }
+}
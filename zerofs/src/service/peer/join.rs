@@ -0,0 +1,94 @@
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use zeroutils_key::Verify;
+use zeroutils_store::IpldStore;
+
+use crate::service::{
+    read_frame, write_frame, Envelope, JoinGrant, JoinRequest, ServiceError, ServiceResult,
+    SharedService,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The answer of a member to a [`JoinRequest`] sent over a peer connection.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum JoinResponse {
+    /// The node was admitted to the cluster.
+    Admitted(Box<JoinGrant>),
+
+    /// The node was not admitted, for the given reason.
+    Refused(String),
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Asks the member at the other end of `stream` to admit this node to its cluster, after the
+/// [handshake][super::handshake] and [proving][super::prove_identity] the DID of the request, and
+/// returns what it granted.
+///
+/// Fails with [`ServiceError::InvalidJoinToken`] if the member refused the request.
+pub async fn request_join<T>(stream: &mut T, request: &JoinRequest) -> ServiceResult<JoinGrant>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    write_frame(stream, &Envelope::new(request)).await?;
+    stream.flush().await?;
+
+    let response = read_frame::<_, Envelope<JoinResponse>>(stream)
+        .await?
+        .ok_or_else(|| {
+            ServiceError::IncompatiblePeer("connection closed before join response".into())
+        })?
+        .into_body()?;
+
+    match response {
+        JoinResponse::Admitted(grant) => Ok(*grant),
+        JoinResponse::Refused(reason) => Err(ServiceError::InvalidJoinToken(reason)),
+    }
+}
+
+/// Reads a [`JoinRequest`] from the node at the other end of `stream` and answers it, admitting
+/// the node to the cluster of `service` if its token verifies, see
+/// [`FsService::admit_member`][crate::service::FsService::admit_member].
+///
+/// `peer` is the DID the node proved it holds after the handshake, with
+/// [`identify_peer`][super::identify_peer]. A request for any other DID is refused, so a node
+/// cannot join as, or move the address of, a member it is not. A refused request is answered with
+/// the reason and is not an error of this function.
+pub async fn serve_join<S, T, V>(
+    stream: &mut T,
+    service: &SharedService<S>,
+    peer: &str,
+    resolve: impl Fn(&str) -> Option<V>,
+) -> ServiceResult<()>
+where
+    S: IpldStore + Send + Sync + 'static,
+    T: AsyncRead + AsyncWrite + Unpin,
+    V: Verify,
+{
+    let request = read_frame::<_, Envelope<JoinRequest>>(stream)
+        .await?
+        .ok_or_else(|| {
+            ServiceError::IncompatiblePeer("connection closed before join request".into())
+        })?
+        .into_body()?;
+
+    let did = request.did.clone();
+    let admitted = service.lock().await.admit_member(request, peer, resolve);
+    let response = match admitted {
+        Ok(grant) => JoinResponse::Admitted(Box::new(grant)),
+        Err(e) => {
+            tracing::warn!("refused to admit {} to the cluster: {}", did, e);
+            JoinResponse::Refused(e.to_string())
+        }
+    };
+
+    write_frame(stream, &Envelope::new(response)).await?;
+    stream.flush().await?;
+
+    Ok(())
+}
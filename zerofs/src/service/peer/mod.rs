@@ -1,9 +1,19 @@
 //! The service module provides the file system service.
 
+mod handshake;
+mod identity;
+mod join;
+mod mux;
+mod pool;
 mod server;
 
 //--------------------------------------------------------------------------------------------------
 // Exports
 //--------------------------------------------------------------------------------------------------
 
+pub use handshake::*;
+pub use identity::*;
+pub use join::*;
+pub use mux::*;
+pub use pool::*;
 pub use server::*;
@@ -0,0 +1,386 @@
+use std::{
+    collections::HashMap,
+    convert::TryInto,
+    future::Future,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex as StdMutex,
+    },
+};
+
+use bytes::Bytes;
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadHalf, WriteHalf},
+    sync::{oneshot, Mutex},
+    task::JoinHandle,
+};
+
+use crate::service::{Envelope, ServiceError, ServiceResult, MAX_FRAME_SIZE};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The size of the header of a multiplexed frame: the stream, the kind and the length of the body.
+const MUX_HEADER_SIZE: usize = 8 + 1 + 4;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// A stream a peer connection can run over, like a TCP stream after the
+/// [handshake][super::handshake].
+pub trait PeerStream: AsyncRead + AsyncWrite + Send + Unpin + 'static {}
+
+/// A persistent connection to a peer, carrying any number of concurrent requests.
+///
+/// Each request is sent as a frame tagged with a stream ID of its own, and the peer answers with a
+/// frame tagged with the same ID, in whatever order the answers are ready. A task reads the answers
+/// and hands each to the request waiting for it. Once the connection breaks, the requests still
+/// waiting fail with [`ServiceError::PeerUnavailable`] and the connection reports itself
+/// [closed][Self::is_closed].
+pub struct PeerConnection {
+    /// Where the frames are written to.
+    writer: Mutex<WriteHalf<Box<dyn PeerStream>>>,
+
+    /// The requests waiting for an answer, by stream ID.
+    pending: Arc<StdMutex<HashMap<u64, oneshot::Sender<ServiceResult<Bytes>>>>>,
+
+    /// The ID of the next stream.
+    next_id: AtomicU64,
+
+    /// Whether the connection broke.
+    closed: Arc<AtomicBool>,
+
+    /// The task reading the answers.
+    reader: JoinHandle<()>,
+}
+
+/// The kind of a multiplexed frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FrameKind {
+    /// A request, answered with a response or an error on the same stream.
+    Request = 0,
+
+    /// The answer to a request.
+    Response = 1,
+
+    /// A request that failed on the peer, with the error as the body.
+    Error = 2,
+
+    /// A health check, answered with a pong on the same stream.
+    Ping = 3,
+
+    /// The answer to a ping.
+    Pong = 4,
+}
+
+/// Runs a closure when dropped.
+struct RemoveOnDrop<F: FnMut()>(F);
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl PeerConnection {
+    /// Starts multiplexing requests over `stream`.
+    pub fn new(stream: impl PeerStream) -> Self {
+        let (reader, writer) = tokio::io::split(Box::new(stream) as Box<dyn PeerStream>);
+        let pending = Arc::new(StdMutex::new(HashMap::new()));
+        let closed = Arc::new(AtomicBool::new(false));
+        let reader = tokio::spawn(read_answers(
+            reader,
+            Arc::clone(&pending),
+            Arc::clone(&closed),
+        ));
+
+        Self {
+            writer: Mutex::new(writer),
+            pending,
+            next_id: AtomicU64::new(0),
+            closed,
+            reader,
+        }
+    }
+
+    /// Sends a request with `body` to the peer and returns the body of its answer.
+    ///
+    /// Fails with [`ServiceError::RemoteError`] if the request failed on the peer, and with
+    /// [`ServiceError::PeerUnavailable`] if the connection breaks before the answer.
+    pub async fn request(&self, body: Bytes) -> ServiceResult<Bytes> {
+        self.send(FrameKind::Request, body).await
+    }
+
+    /// Sends `request` to the peer, in an [`Envelope`] encoded as DAG-CBOR, and decodes its answer.
+    pub async fn call<Req, Resp>(&self, request: &Req) -> ServiceResult<Resp>
+    where
+        Req: Serialize,
+        Resp: DeserializeOwned,
+    {
+        let body = serde_ipld_dagcbor::to_vec(&Envelope::new(request))
+            .map_err(|e| ServiceError::InvalidFrame(e.to_string()))?;
+        let answer = self.request(body.into()).await?;
+
+        serde_ipld_dagcbor::from_slice::<Envelope<Resp>>(&answer)
+            .map_err(|e| ServiceError::InvalidFrame(e.to_string()))?
+            .into_body()
+    }
+
+    /// Checks that the peer still answers on the connection.
+    pub async fn ping(&self) -> ServiceResult<()> {
+        self.send(FrameKind::Ping, Bytes::new()).await.map(|_| ())
+    }
+
+    /// Returns `true` if the connection broke, and a new one is needed to reach the peer.
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
+
+    /// Returns the number of requests waiting for an answer.
+    pub fn get_pending_count(&self) -> usize {
+        self.pending.lock().unwrap().len()
+    }
+
+    /// Sends a frame of `kind` on a new stream and waits for the answer on it.
+    async fn send(&self, kind: FrameKind, body: Bytes) -> ServiceResult<Bytes> {
+        if self.is_closed() {
+            return Err(ServiceError::PeerUnavailable("connection closed".into()));
+        }
+
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = oneshot::channel();
+        self.pending.lock().unwrap().insert(id, sender);
+
+        // A request given up on, like one that timed out, leaves no answer to wait for.
+        let pending = Arc::clone(&self.pending);
+        let _guard = RemoveOnDrop(move || {
+            pending.lock().unwrap().remove(&id);
+        });
+
+        let written = {
+            let mut writer = self.writer.lock().await;
+            write_mux_frame(&mut *writer, id, kind, &body).await
+        };
+
+        if let Err(e) = written {
+            self.closed.store(true, Ordering::Release);
+            return Err(ServiceError::PeerUnavailable(e.to_string()));
+        }
+
+        receiver
+            .await
+            .unwrap_or_else(|_| Err(ServiceError::PeerUnavailable("connection closed".into())))
+    }
+}
+
+impl FrameKind {
+    fn from_u8(kind: u8) -> Option<Self> {
+        match kind {
+            0 => Some(FrameKind::Request),
+            1 => Some(FrameKind::Response),
+            2 => Some(FrameKind::Error),
+            3 => Some(FrameKind::Ping),
+            4 => Some(FrameKind::Pong),
+            _ => None,
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Serves the requests a peer multiplexes over `stream` with [`PeerConnection`], until the peer
+/// closes it.
+///
+/// Each request is handled in a task of its own by `handler`, so a slow request does not hold back
+/// the others, and is answered with what the handler returns or with the error it fails with.
+pub async fn serve_connection<T, H, F>(stream: T, handler: H) -> ServiceResult<()>
+where
+    T: PeerStream,
+    H: Fn(Bytes) -> F + Send + Sync + 'static,
+    F: Future<Output = ServiceResult<Bytes>> + Send + 'static,
+{
+    let (mut reader, writer) = tokio::io::split(stream);
+    let writer = Arc::new(Mutex::new(writer));
+    let handler = Arc::new(handler);
+    while let Some((id, kind, body)) = read_mux_frame(&mut reader).await? {
+        let writer = Arc::clone(&writer);
+        match kind {
+            FrameKind::Request => {
+                let answer = handler(body);
+                tokio::spawn(async move {
+                    let (kind, body) = match answer.await {
+                        Ok(body) => (FrameKind::Response, body),
+                        Err(e) => (FrameKind::Error, Bytes::from(e.to_string())),
+                    };
+
+                    let mut writer = writer.lock().await;
+                    if let Err(e) = write_mux_frame(&mut *writer, id, kind, &body).await {
+                        tracing::debug!("failed to answer peer request {}: {}", id, e);
+                    }
+                });
+            }
+            FrameKind::Ping => {
+                let mut writer = writer.lock().await;
+                write_mux_frame(&mut *writer, id, FrameKind::Pong, &[]).await?;
+            }
+            _ => tracing::debug!("ignoring unexpected {:?} frame from peer", kind),
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads the answers from `reader` and hands each to the request waiting for it, until the
+/// connection breaks.
+async fn read_answers(
+    mut reader: ReadHalf<Box<dyn PeerStream>>,
+    pending: Arc<StdMutex<HashMap<u64, oneshot::Sender<ServiceResult<Bytes>>>>>,
+    closed: Arc<AtomicBool>,
+) {
+    loop {
+        let (id, kind, body) = match read_mux_frame(&mut reader).await {
+            Ok(Some(frame)) => frame,
+            Ok(None) => break,
+            Err(e) => {
+                tracing::debug!("peer connection broke: {}", e);
+                break;
+            }
+        };
+
+        let answer = match kind {
+            FrameKind::Response | FrameKind::Pong => Ok(body),
+            FrameKind::Error => Err(ServiceError::RemoteError(
+                String::from_utf8_lossy(&body).into_owned(),
+            )),
+            _ => continue,
+        };
+
+        if let Some(sender) = pending.lock().unwrap().remove(&id) {
+            let _ = sender.send(answer);
+        }
+    }
+
+    closed.store(true, Ordering::Release);
+    for (_, sender) in pending.lock().unwrap().drain() {
+        let _ = sender.send(Err(ServiceError::PeerUnavailable(
+            "connection closed".into(),
+        )));
+    }
+}
+
+/// Writes a frame of `kind` on stream `id` with `body`.
+async fn write_mux_frame<W>(
+    writer: &mut W,
+    id: u64,
+    kind: FrameKind,
+    body: &[u8],
+) -> ServiceResult<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    let mut head = [0; MUX_HEADER_SIZE];
+    head[..8].copy_from_slice(&id.to_be_bytes());
+    head[8] = kind as u8;
+    head[9..].copy_from_slice(&(body.len() as u32).to_be_bytes());
+
+    writer.write_all(&head).await?;
+    writer.write_all(body).await?;
+    writer.flush().await?;
+
+    Ok(())
+}
+
+/// Reads a frame written by [`write_mux_frame`], or returns `None` if the stream ended before it.
+async fn read_mux_frame<R>(reader: &mut R) -> ServiceResult<Option<(u64, FrameKind, Bytes)>>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut head = [0; MUX_HEADER_SIZE];
+    match reader.read_exact(&mut head).await {
+        Ok(_) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+
+    let id = u64::from_be_bytes(head[..8].try_into().unwrap());
+    let kind = FrameKind::from_u8(head[8])
+        .ok_or_else(|| ServiceError::InvalidFrame(format!("unknown frame kind {}", head[8])))?;
+    let len = u32::from_be_bytes(head[9..].try_into().unwrap()) as u64;
+    if len > MAX_FRAME_SIZE {
+        return Err(ServiceError::FrameTooLarge(len));
+    }
+
+    let mut body = vec![0; len as usize];
+    reader.read_exact(&mut body).await?;
+
+    Ok(Some((id, kind, body.into())))
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl<T> PeerStream for T where T: AsyncRead + AsyncWrite + Send + Unpin + 'static {}
+
+impl Drop for PeerConnection {
+    fn drop(&mut self) {
+        self.reader.abort();
+    }
+}
+
+impl<F: FnMut()> Drop for RemoveOnDrop<F> {
+    fn drop(&mut self) {
+        (self.0)()
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_peer_connection_multiplexes_requests() -> anyhow::Result<()> {
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        let server = tokio::spawn(serve_connection(server, |body: Bytes| async move {
+            // Requests are answered out of order: the first ones take the longest.
+            let delay = 30 - body[0] as u64 * 10;
+            tokio::time::sleep(Duration::from_millis(delay)).await;
+            match body[0] {
+                2 => Err(ServiceError::PeerUnavailable("busy".into())),
+                _ => Ok(Bytes::from(vec![body[0] * 2])),
+            }
+        }));
+
+        let connection = PeerConnection::new(client);
+        let (a, b, c) = tokio::join!(
+            connection.request(Bytes::from_static(&[0])),
+            connection.request(Bytes::from_static(&[1])),
+            connection.request(Bytes::from_static(&[2])),
+        );
+        assert_eq!(&a?[..], &[0]);
+        assert_eq!(&b?[..], &[2]);
+        assert!(matches!(c, Err(ServiceError::RemoteError(_))));
+        connection.ping().await?;
+        assert_eq!(connection.get_pending_count(), 0);
+
+        // Requests waiting when the connection breaks fail, and so do later ones.
+        server.abort();
+        let _ = server.await;
+        assert!(matches!(
+            connection.request(Bytes::from_static(&[0])).await,
+            Err(ServiceError::PeerUnavailable(_))
+        ));
+        assert!(connection.is_closed());
+
+        Ok(())
+    }
+}
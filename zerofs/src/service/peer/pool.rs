@@ -0,0 +1,559 @@
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex as StdMutex},
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use futures::{future::BoxFuture, FutureExt};
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::{net::TcpStream, sync::Mutex};
+use typed_builder::TypedBuilder;
+
+use crate::service::{Envelope, ServiceError, ServiceResult};
+
+use super::{handshake, PeerConnection, PeerHello, PeerStream};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The default time a request to a peer is given before it fails.
+pub const DEFAULT_PEER_REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The default time before the first reconnection to a peer that could not be reached.
+pub const DEFAULT_RECONNECT_BASE_BACKOFF: Duration = Duration::from_millis(100);
+
+/// The default longest time between two reconnections to a peer.
+pub const DEFAULT_RECONNECT_MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// The default number of failures in a row that open the circuit breaker of a peer.
+pub const DEFAULT_BREAKER_THRESHOLD: u32 = 5;
+
+/// The default time the circuit breaker of a peer stays open.
+pub const DEFAULT_BREAKER_OPEN_DURATION: Duration = Duration::from_secs(30);
+
+/// The default time between two health checks of the connections.
+pub const DEFAULT_HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Opens a stream to the peer at an address, ready to multiplex requests over.
+pub type PeerConnector =
+    Arc<dyn Fn(SocketAddr) -> BoxFuture<'static, ServiceResult<Box<dyn PeerStream>>> + Send + Sync>;
+
+/// Keeps one persistent, multiplexed [`PeerConnection`] to each peer, so that requests to a peer
+/// share a connection rather than each opening one.
+///
+/// A connection is opened on the first request to its peer, and again on the next request once it
+/// breaks, waiting longer after each connection that fails, from
+/// [`base_backoff`][PoolOptions::base_backoff] up to [`max_backoff`][PoolOptions::max_backoff].
+/// [`check_health`][Self::check_health] pings the connections, so that broken ones are found
+/// before a request is sent over them.
+///
+/// Each peer has a circuit breaker: after [`breaker_threshold`][PoolOptions::breaker_threshold]
+/// failures in a row, requests to the peer fail right away with [`ServiceError::PeerUnavailable`]
+/// for [`breaker_open_duration`][PoolOptions::breaker_open_duration], rather than each waiting for
+/// the timeout. Then a single request is let through, and closes the breaker again if it succeeds.
+/// Requests that fail on the peer itself do not count as failures, as the peer answered them.
+pub struct PeerPool {
+    /// The options of the pool.
+    options: PoolOptions,
+
+    /// Opens the streams to the peers.
+    connector: PeerConnector,
+
+    /// The peers, by DID.
+    peers: StdMutex<HashMap<String, Arc<PeerSlot>>>,
+}
+
+/// Options for a [`PeerPool`].
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct PoolOptions {
+    /// The time a request to a peer is given before it fails, connecting included.
+    #[builder(default = DEFAULT_PEER_REQUEST_TIMEOUT)]
+    pub request_timeout: Duration,
+
+    /// The time before the first reconnection to a peer that could not be reached. It doubles with
+    /// each failed connection.
+    #[builder(default = DEFAULT_RECONNECT_BASE_BACKOFF)]
+    pub base_backoff: Duration,
+
+    /// The longest time between two reconnections to a peer.
+    #[builder(default = DEFAULT_RECONNECT_MAX_BACKOFF)]
+    pub max_backoff: Duration,
+
+    /// The number of failures in a row that open the circuit breaker of a peer.
+    #[builder(default = DEFAULT_BREAKER_THRESHOLD)]
+    pub breaker_threshold: u32,
+
+    /// The time the circuit breaker of a peer stays open before a request is let through again.
+    #[builder(default = DEFAULT_BREAKER_OPEN_DURATION)]
+    pub breaker_open_duration: Duration,
+
+    /// The time between two health checks of the connections, see [`run_health_checks`].
+    #[builder(default = DEFAULT_HEALTH_CHECK_INTERVAL)]
+    pub health_check_interval: Duration,
+}
+
+/// The state of the circuit breaker of a peer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreakerState {
+    /// Requests go through.
+    Closed,
+
+    /// Requests fail right away.
+    Open,
+
+    /// The breaker was open long enough and a single request is let through to try the peer.
+    HalfOpen,
+}
+
+/// The state of a peer of a [`PeerPool`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerStatus {
+    /// The DID of the peer.
+    pub did: String,
+
+    /// The address of the peer.
+    pub addr: SocketAddr,
+
+    /// Whether the pool has an open connection to the peer.
+    pub connected: bool,
+
+    /// The state of the circuit breaker of the peer.
+    pub breaker: BreakerState,
+
+    /// The number of failures in a row.
+    pub failures: u32,
+}
+
+/// A peer of a [`PeerPool`].
+struct PeerSlot {
+    /// The address of the peer.
+    addr: StdMutex<SocketAddr>,
+
+    /// The connection to the peer, if one is open. Locked while connecting, so that concurrent
+    /// requests wait for the same connection.
+    connection: Mutex<Option<Arc<PeerConnection>>>,
+
+    /// The health of the peer.
+    health: StdMutex<PeerHealth>,
+}
+
+/// The failures of a peer, driving its backoff and circuit breaker.
+#[derive(Debug, Default)]
+struct PeerHealth {
+    /// The number of failures in a row.
+    failures: u32,
+
+    /// The number of failed connections in a row.
+    connect_failures: u32,
+
+    /// The time before which no new connection is tried.
+    retry_at: Option<Instant>,
+
+    /// The time the breaker opened until, if it opened.
+    open_until: Option<Instant>,
+
+    /// Whether the request let through a half-open breaker is still running.
+    trial: bool,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl PeerPool {
+    /// Creates a pool opening streams to peers with `connector`.
+    pub fn new(options: PoolOptions, connector: PeerConnector) -> Self {
+        Self {
+            options,
+            connector,
+            peers: StdMutex::new(HashMap::new()),
+        }
+    }
+
+    /// Creates a pool connecting to peers over TCP and running the [handshake] with `hello`.
+    pub fn tcp(options: PoolOptions, hello: PeerHello) -> Self {
+        let connector: PeerConnector = Arc::new(move |addr| {
+            let hello = hello.clone();
+            async move {
+                let mut stream = TcpStream::connect(addr).await?;
+                stream.set_nodelay(true)?;
+                handshake(&mut stream, &hello).await?;
+                Ok(Box::new(stream) as Box<dyn PeerStream>)
+            }
+            .boxed()
+        });
+
+        Self::new(options, connector)
+    }
+
+    /// Adds the peer `did` at `addr`. A peer that moved to another address is reconnected to on
+    /// the next request.
+    pub fn add_peer(&self, did: impl Into<String>, addr: SocketAddr) {
+        let did = did.into();
+        let mut peers = self.peers.lock().unwrap();
+        match peers.get(&did) {
+            Some(slot) => {
+                let mut current = slot.addr.lock().unwrap();
+                if *current != addr {
+                    *current = addr;
+                    if let Ok(mut connection) = slot.connection.try_lock() {
+                        connection.take();
+                    }
+                }
+            }
+            None => {
+                peers.insert(
+                    did,
+                    Arc::new(PeerSlot {
+                        addr: StdMutex::new(addr),
+                        connection: Mutex::new(None),
+                        health: StdMutex::new(PeerHealth::default()),
+                    }),
+                );
+            }
+        }
+    }
+
+    /// Removes the peer `did`, closing its connection once the requests over it are done.
+    pub fn remove_peer(&self, did: &str) {
+        self.peers.lock().unwrap().remove(did);
+    }
+
+    /// Sends a request with `body` to the peer `did` over its connection and returns the body of
+    /// the answer, see [`PeerConnection::request`].
+    pub async fn request(&self, did: &str, body: Bytes) -> ServiceResult<Bytes> {
+        let slot = self.get_slot(did)?;
+        slot.health.lock().unwrap().admit(did, Instant::now())?;
+
+        let result = tokio::time::timeout(self.options.request_timeout, async {
+            let connection = self.connect(did, &slot).await?;
+            connection.request(body).await
+        })
+        .await
+        .unwrap_or_else(|_| {
+            Err(ServiceError::PeerUnavailable(format!(
+                "request to {} timed out",
+                did
+            )))
+        });
+
+        let mut health = slot.health.lock().unwrap();
+        match &result {
+            Err(ServiceError::PeerUnavailable(_)) => health.record_failure(&self.options),
+            _ => health.record_success(),
+        }
+
+        result
+    }
+
+    /// Sends `request` to the peer `did` and decodes the answer, see [`PeerConnection::call`].
+    pub async fn call<Req, Resp>(&self, did: &str, request: &Req) -> ServiceResult<Resp>
+    where
+        Req: Serialize,
+        Resp: DeserializeOwned,
+    {
+        let body = serde_ipld_dagcbor::to_vec(&Envelope::new(request))
+            .map_err(|e| ServiceError::InvalidFrame(e.to_string()))?;
+        let answer = self.request(did, body.into()).await?;
+
+        serde_ipld_dagcbor::from_slice::<Envelope<Resp>>(&answer)
+            .map_err(|e| ServiceError::InvalidFrame(e.to_string()))?
+            .into_body()
+    }
+
+    /// Pings the open connections, closing the ones that do not answer in time so that the next
+    /// request to their peer reconnects.
+    pub async fn check_health(&self) {
+        let slots = self
+            .peers
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(did, slot)| (did.clone(), Arc::clone(slot)))
+            .collect::<Vec<_>>();
+
+        for (did, slot) in slots {
+            let Some(connection) = slot.connection.lock().await.clone() else {
+                continue;
+            };
+
+            let ping = tokio::time::timeout(self.options.request_timeout, connection.ping()).await;
+            if !matches!(ping, Ok(Ok(()))) {
+                tracing::warn!("peer {} failed its health check", did);
+                slot.connection.lock().await.take();
+                slot.health.lock().unwrap().record_failure(&self.options);
+            }
+        }
+    }
+
+    /// Returns the state of each peer.
+    pub fn get_statuses(&self) -> Vec<PeerStatus> {
+        let now = Instant::now();
+        let mut statuses = self
+            .peers
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(did, slot)| {
+                let health = slot.health.lock().unwrap();
+                let connected = slot
+                    .connection
+                    .try_lock()
+                    .map(|connection| connection.as_ref().is_some_and(|c| !c.is_closed()))
+                    .unwrap_or(false);
+
+                PeerStatus {
+                    did: did.clone(),
+                    addr: *slot.addr.lock().unwrap(),
+                    connected,
+                    breaker: health.get_state(now),
+                    failures: health.failures,
+                }
+            })
+            .collect::<Vec<_>>();
+        statuses.sort_by(|a, b| a.did.cmp(&b.did));
+        statuses
+    }
+
+    /// Returns the options of the pool.
+    pub fn get_options(&self) -> &PoolOptions {
+        &self.options
+    }
+
+    /// Returns the slot of the peer `did`.
+    fn get_slot(&self, did: &str) -> ServiceResult<Arc<PeerSlot>> {
+        self.peers
+            .lock()
+            .unwrap()
+            .get(did)
+            .cloned()
+            .ok_or_else(|| ServiceError::PeerUnavailable(format!("unknown peer {}", did)))
+    }
+
+    /// Returns the open connection to the peer of `slot`, opening one if there is none and the
+    /// backoff allows it.
+    async fn connect(&self, did: &str, slot: &PeerSlot) -> ServiceResult<Arc<PeerConnection>> {
+        let mut connection = slot.connection.lock().await;
+        if let Some(open) = connection.as_ref().filter(|c| !c.is_closed()) {
+            return Ok(Arc::clone(open));
+        }
+
+        connection.take();
+        if let Some(retry_at) = slot.health.lock().unwrap().retry_at {
+            if retry_at > Instant::now() {
+                return Err(ServiceError::PeerUnavailable(format!(
+                    "backing off from reconnecting to {}",
+                    did
+                )));
+            }
+        }
+
+        let addr = *slot.addr.lock().unwrap();
+        match (self.connector)(addr).await {
+            Ok(stream) => {
+                tracing::debug!("connected to peer {} at {}", did, addr);
+                slot.health.lock().unwrap().record_connect(None);
+                Ok(Arc::clone(
+                    connection.insert(Arc::new(PeerConnection::new(stream))),
+                ))
+            }
+            Err(e) => {
+                slot.health
+                    .lock()
+                    .unwrap()
+                    .record_connect(Some(&self.options));
+                Err(ServiceError::PeerUnavailable(format!(
+                    "cannot connect to {} at {}: {}",
+                    did, addr, e
+                )))
+            }
+        }
+    }
+}
+
+impl PeerHealth {
+    /// Returns the state of the breaker at `now`.
+    fn get_state(&self, now: Instant) -> BreakerState {
+        match self.open_until {
+            Some(until) if until > now => BreakerState::Open,
+            Some(_) => BreakerState::HalfOpen,
+            None => BreakerState::Closed,
+        }
+    }
+
+    /// Checks that the breaker lets a request to the peer `did` through at `now`.
+    fn admit(&mut self, did: &str, now: Instant) -> ServiceResult<()> {
+        match self.get_state(now) {
+            BreakerState::Closed => Ok(()),
+            BreakerState::HalfOpen if !self.trial => {
+                self.trial = true;
+                Ok(())
+            }
+            _ => Err(ServiceError::PeerUnavailable(format!(
+                "circuit breaker of {} is open",
+                did
+            ))),
+        }
+    }
+
+    /// Records a request that reached the peer, closing the breaker.
+    fn record_success(&mut self) {
+        self.failures = 0;
+        self.open_until = None;
+        self.trial = false;
+    }
+
+    /// Records a request that did not reach the peer, opening the breaker after enough of them.
+    fn record_failure(&mut self, options: &PoolOptions) {
+        self.failures += 1;
+        if self.trial || self.failures >= options.breaker_threshold {
+            self.open_until = Some(Instant::now() + options.breaker_open_duration);
+        }
+
+        self.trial = false;
+    }
+
+    /// Records a connection that was opened, or failed to open with the backoff in `options`.
+    fn record_connect(&mut self, failed: Option<&PoolOptions>) {
+        match failed {
+            Some(options) => {
+                let exponent = self.connect_failures.min(16);
+                let backoff = options
+                    .base_backoff
+                    .saturating_mul(1 << exponent)
+                    .min(options.max_backoff);
+                self.connect_failures += 1;
+                self.retry_at = Some(Instant::now() + backoff);
+            }
+            None => {
+                self.connect_failures = 0;
+                self.retry_at = None;
+            }
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Runs the [health checks][PeerPool::check_health] of `pool` every
+/// [`health_check_interval`][PoolOptions::health_check_interval], for as long as the task runs.
+pub async fn run_health_checks(pool: Arc<PeerPool>) {
+    let mut interval = tokio::time::interval(pool.get_options().health_check_interval);
+    loop {
+        interval.tick().await;
+        pool.check_health().await;
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl Default for PoolOptions {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    use futures::future;
+
+    use super::{super::serve_connection, *};
+
+    #[tokio::test]
+    async fn test_peer_pool_reuses_and_breaks() -> anyhow::Result<()> {
+        let connects = Arc::new(AtomicUsize::new(0));
+        let reachable = Arc::new(AtomicBool::new(true));
+        let servers = Arc::new(StdMutex::new(Vec::new()));
+        let connector: PeerConnector = Arc::new({
+            let (connects, reachable, servers) =
+                (connects.clone(), reachable.clone(), servers.clone());
+            move |_| {
+                connects.fetch_add(1, Ordering::SeqCst);
+                let reachable = reachable.load(Ordering::SeqCst);
+                let (client, server) = tokio::io::duplex(64 * 1024);
+                servers.lock().unwrap().push(tokio::spawn(serve_connection(
+                    server,
+                    |body: Bytes| async move { Ok(body) },
+                )));
+
+                async move {
+                    match reachable {
+                        true => Ok(Box::new(client) as Box<dyn PeerStream>),
+                        false => Err(ServiceError::PeerUnavailable("refused".into())),
+                    }
+                }
+                .boxed()
+            }
+        });
+
+        let options = PoolOptions::builder()
+            .base_backoff(Duration::from_millis(1))
+            .max_backoff(Duration::from_millis(1))
+            .breaker_threshold(2)
+            .breaker_open_duration(Duration::from_millis(50))
+            .build();
+        let pool = PeerPool::new(options, connector);
+        pool.add_peer("a", "127.0.0.1:6611".parse()?);
+
+        // Concurrent requests share a single connection.
+        let answers = future::join_all((0..8u8).map(|i| pool.request("a", vec![i].into()))).await;
+        for (i, answer) in answers.into_iter().enumerate() {
+            assert_eq!(&answer?[..], &[i as u8]);
+        }
+        assert_eq!(connects.load(Ordering::SeqCst), 1);
+        assert!(pool.get_statuses()[0].connected);
+
+        // A broken connection is found by the health check and replaced on the next request.
+        for server in servers.lock().unwrap().drain(..) {
+            server.abort();
+        }
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        pool.check_health().await;
+        assert!(!pool.get_statuses()[0].connected);
+        pool.request("a", Bytes::from_static(b"again")).await?;
+        assert_eq!(connects.load(Ordering::SeqCst), 2);
+
+        // A peer that cannot be reached opens its breaker, which then fails requests right away.
+        for server in servers.lock().unwrap().drain(..) {
+            server.abort();
+        }
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        reachable.store(false, Ordering::SeqCst);
+        for _ in 0..2 {
+            assert!(pool.request("a", Bytes::new()).await.is_err());
+            tokio::time::sleep(Duration::from_millis(2)).await;
+        }
+        assert_eq!(pool.get_statuses()[0].breaker, BreakerState::Open);
+        let attempts = connects.load(Ordering::SeqCst);
+        assert!(pool.request("a", Bytes::new()).await.is_err());
+        assert_eq!(connects.load(Ordering::SeqCst), attempts);
+
+        // Once the peer is back, the request let through closes the breaker.
+        reachable.store(true, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert_eq!(pool.get_statuses()[0].breaker, BreakerState::HalfOpen);
+        pool.request("a", Bytes::from_static(b"back")).await?;
+        assert_eq!(pool.get_statuses()[0].breaker, BreakerState::Closed);
+
+        assert!(pool.request("unknown", Bytes::new()).await.is_err());
+
+        Ok(())
+    }
+}
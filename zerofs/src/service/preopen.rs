@@ -0,0 +1,217 @@
+use std::collections::BTreeSet;
+
+use zeroutils_store::IpldStore;
+
+use crate::{
+    config::WasiPreopen,
+    filesystem::{DescriptorFlags, DirHandle, MemoryBufferStore, RootDir},
+};
+
+use super::{FsService, ServiceError, ServiceResult};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The directories preopened for WASI guests, as [configured][crate::config::ZerofsWasiConfig],
+/// to hand to the WASI host.
+pub struct WasiPreopens<S>
+where
+    S: IpldStore + Sync,
+{
+    /// The handles to the preopened directories, with the guest path of each, in the configured
+    /// order.
+    preopens: Vec<(String, DirHandle<S, MemoryBufferStore<S>>)>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl<S> WasiPreopens<S>
+where
+    S: IpldStore + Sync,
+{
+    /// Returns the handle to the directory preopened at the guest path `guest`.
+    pub fn get(&self, guest: &str) -> Option<&DirHandle<S, MemoryBufferStore<S>>> {
+        self.preopens
+            .iter()
+            .find(|(path, _)| path == guest)
+            .map(|(_, handle)| handle)
+    }
+
+    /// Returns the guest paths and handles of the preopened directories, in the configured order,
+    /// as the WASI `get-directories` call lists them.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &DirHandle<S, MemoryBufferStore<S>>)> {
+        self.preopens
+            .iter()
+            .map(|(guest, handle)| (guest.as_str(), handle))
+    }
+
+    /// Returns the number of preopened directories.
+    pub fn len(&self) -> usize {
+        self.preopens.len()
+    }
+
+    /// Returns `true` if no directory is preopened.
+    pub fn is_empty(&self) -> bool {
+        self.preopens.is_empty()
+    }
+}
+
+impl<S> FsService<S>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    /// Opens the [configured][crate::config::ZerofsWasiConfig] preopened directories over the
+    /// current root, for the WASI host to hand to guests when the service starts.
+    ///
+    /// Each guest path must be absolute and used once. The flags must include
+    /// [`DescriptorFlags::READ`], and cannot include the flags that only apply to files. While the
+    /// file tree is [read-only][Self::set_read_only], so are the handles, including when it is made
+    /// read-only after they were opened.
+    pub async fn open_preopens(&self) -> ServiceResult<WasiPreopens<S>> {
+        let root =
+            RootDir::from_dir_sharing_read_only(self.root_dir.clone(), self.read_only.clone());
+
+        let mut guests = BTreeSet::new();
+        let mut preopens = Vec::new();
+        for preopen in &self.config.wasi.preopens {
+            check_preopen(preopen)?;
+            if !guests.insert(preopen.guest.as_str()) {
+                return Err(ServiceError::InvalidPreopen(format!(
+                    "{} is preopened more than once",
+                    preopen.guest
+                )));
+            }
+
+            let handle = root
+                .make_handle_at(&preopen.path, preopen.flags, preopen.create)
+                .await?;
+            preopens.push((preopen.guest.clone(), handle));
+        }
+
+        Ok(WasiPreopens { preopens })
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Checks the guest path and flags of `preopen`.
+fn check_preopen(preopen: &WasiPreopen) -> ServiceResult<()> {
+    if !preopen.guest.starts_with('/') {
+        return Err(ServiceError::InvalidPreopen(format!(
+            "guest path {} is not absolute",
+            preopen.guest
+        )));
+    }
+
+    if !preopen.flags.contains(DescriptorFlags::READ) {
+        return Err(ServiceError::InvalidPreopen(format!(
+            "{} is not preopened with the READ flag",
+            preopen.guest
+        )));
+    }
+
+    if preopen
+        .flags
+        .intersects(DescriptorFlags::WRITE | DescriptorFlags::DIRECT_WRITE)
+    {
+        return Err(ServiceError::InvalidPreopen(format!(
+            "{} is a directory and cannot be preopened with file flags",
+            preopen.guest
+        )));
+    }
+
+    Ok(())
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use zeroutils_store::MemoryStore;
+
+    use crate::{
+        config::{ZerofsConfig, ZerofsWasiConfig},
+        filesystem::Dir,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_open_preopens() -> anyhow::Result<()> {
+        let service = |preopens: Vec<WasiPreopen>| {
+            let config = ZerofsConfig::builder()
+                .wasi(ZerofsWasiConfig::builder().preopens(preopens).build())
+                .build();
+            FsService::new(Dir::new(MemoryStore::default()), Arc::new(config))
+        };
+
+        let data = WasiPreopen::builder()
+            .guest("/data")
+            .path("public/data".parse()?)
+            .flags(DescriptorFlags::READ | DescriptorFlags::MUTATE_DIR)
+            .create(true)
+            .build();
+        let root = WasiPreopen::builder()
+            .guest("/")
+            .path(Default::default())
+            .build();
+
+        let preopens = service(vec![data.clone(), root.clone()])
+            .open_preopens()
+            .await?;
+        assert_eq!(
+            preopens.iter().map(|(guest, _)| guest).collect::<Vec<_>>(),
+            ["/data", "/"]
+        );
+        let handle = preopens.get("/data").unwrap();
+        assert_eq!(handle.path(), "public/data".parse()?);
+        assert_eq!(
+            *handle.flags(),
+            DescriptorFlags::READ | DescriptorFlags::MUTATE_DIR
+        );
+        assert_eq!(*preopens.get("/").unwrap().flags(), DescriptorFlags::READ);
+
+        // Guest paths must be absolute and unique, and the flags must suit a directory.
+        let relative = WasiPreopen {
+            guest: "data".into(),
+            ..data.clone()
+        };
+        let write = WasiPreopen {
+            flags: DescriptorFlags::READ | DescriptorFlags::WRITE,
+            ..data.clone()
+        };
+        for preopens in [
+            vec![relative],
+            vec![write],
+            vec![data.clone(), data.clone()],
+        ] {
+            assert!(matches!(
+                service(preopens).open_preopens().await,
+                Err(ServiceError::InvalidPreopen(_))
+            ));
+        }
+
+        // Handles opened before the file tree is made read-only follow along.
+        let mut service = service(vec![data]);
+        let preopens = service.open_preopens().await?;
+        let handle = preopens.get("/data").unwrap();
+        assert!(!handle.root().is_read_only());
+
+        service.set_read_only(true);
+        assert!(handle.root().is_read_only());
+
+        service.set_read_only(false);
+        assert!(!handle.root().is_read_only());
+
+        Ok(())
+    }
+}
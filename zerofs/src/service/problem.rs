@@ -0,0 +1,204 @@
+use axum::{
+    http::{header, HeaderName, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::filesystem::{ErrorCode, FsError};
+
+use super::{ErrorContext, ServiceError};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The content type of a [`Problem`] body.
+pub const PROBLEM_CONTENT_TYPE: &str = "application/problem+json";
+
+/// The prefix of the `type` URI of a [`Problem`], which is followed by the name of its code.
+pub const PROBLEM_TYPE_PREFIX: &str = "urn:zerofs:error:";
+
+/// The header that says whether the request that failed may succeed if it is sent again as is,
+/// `true` or `false`.
+pub const RETRYABLE_HEADER: &str = "zerofs-retryable";
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// An error as returned by the HTTP API, in the problem details format of RFC 9457.
+///
+/// Along with the standard members, a problem has the [code][ErrorCode] of the error by name and
+/// by number, which clients should branch on rather than on the status or the detail.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Problem {
+    /// A URI naming the kind of the problem, made of [`PROBLEM_TYPE_PREFIX`] and the code.
+    #[serde(rename = "type")]
+    pub type_uri: String,
+
+    /// A short summary of the kind of the problem.
+    pub title: String,
+
+    /// The HTTP status of the response.
+    pub status: u16,
+
+    /// What went wrong, for people to read.
+    pub detail: String,
+
+    /// The code of the error.
+    pub code: ErrorCode,
+
+    /// The number of the code of the error.
+    pub code_number: u16,
+
+    /// Whether the request may succeed if it is sent again as is.
+    #[serde(default)]
+    pub retryable: bool,
+
+    /// The operations the error happened in, from the innermost one out.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub context: Vec<ErrorContext>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl Problem {
+    /// Creates a problem with the given code, and the status the code maps to.
+    pub fn new(code: ErrorCode, detail: impl Into<String>) -> Self {
+        let status = get_http_status(code);
+        Self {
+            type_uri: format!("{}{}", PROBLEM_TYPE_PREFIX, code),
+            title: status.canonical_reason().unwrap_or_default().to_owned(),
+            status: status.as_u16(),
+            detail: detail.into(),
+            code,
+            code_number: code.number(),
+            retryable: code.is_retryable(),
+            context: Vec::new(),
+        }
+    }
+
+    /// Creates the problem of a request that presented no capabilities.
+    pub fn unauthenticated() -> Self {
+        Problem::new(ErrorCode::Unauthenticated, "no capabilities were presented")
+    }
+
+    /// Returns the HTTP status of the problem.
+    pub fn get_status(&self) -> StatusCode {
+        StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Returns the HTTP status that errors with `code` are returned with.
+pub fn get_http_status(code: ErrorCode) -> StatusCode {
+    match code {
+        ErrorCode::InvalidArgument
+        | ErrorCode::InvalidPath
+        | ErrorCode::NotAFile
+        | ErrorCode::NotADirectory
+        | ErrorCode::InvalidSignature
+        | ErrorCode::LimitExceeded => StatusCode::BAD_REQUEST,
+        ErrorCode::Unauthenticated => StatusCode::UNAUTHORIZED,
+        ErrorCode::PermissionDenied => StatusCode::FORBIDDEN,
+        ErrorCode::NotFound => StatusCode::NOT_FOUND,
+        ErrorCode::AlreadyExists
+        | ErrorCode::DirNotEmpty
+        | ErrorCode::ConcurrentModification
+        | ErrorCode::ReadOnly
+        | ErrorCode::Immutable
+        | ErrorCode::AppendOnly => StatusCode::CONFLICT,
+        ErrorCode::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+        ErrorCode::NotSupported => StatusCode::NOT_IMPLEMENTED,
+        ErrorCode::Unavailable | ErrorCode::NotReplicated => StatusCode::SERVICE_UNAVAILABLE,
+        ErrorCode::StoreFull => StatusCode::INSUFFICIENT_STORAGE,
+        ErrorCode::Timeout => StatusCode::GATEWAY_TIMEOUT,
+        ErrorCode::Internal | ErrorCode::StoreError | ErrorCode::CorruptData => {
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl From<ServiceError> for Problem {
+    fn from(error: ServiceError) -> Self {
+        Problem {
+            retryable: error.is_retryable(),
+            context: error.get_breadcrumbs().to_vec(),
+            ..Problem::new(error.code(), error.root_cause().to_string())
+        }
+    }
+}
+
+impl From<FsError> for Problem {
+    fn from(error: FsError) -> Self {
+        Problem {
+            retryable: error.is_retryable(),
+            ..Problem::new(error.code(), error.to_string())
+        }
+    }
+}
+
+impl IntoResponse for Problem {
+    fn into_response(self) -> Response {
+        (
+            self.get_status(),
+            [
+                (header::CONTENT_TYPE, PROBLEM_CONTENT_TYPE),
+                (
+                    HeaderName::from_static(RETRYABLE_HEADER),
+                    if self.retryable { "true" } else { "false" },
+                ),
+            ],
+            Json(self),
+        )
+            .into_response()
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_problem_from_error() -> anyhow::Result<()> {
+        let path = "public/notes".parse()?;
+        let problem = Problem::from(ServiceError::FsError(FsError::NotFound(path)));
+        assert_eq!(problem.code, ErrorCode::NotFound);
+        assert_eq!(problem.code_number, 101);
+        assert_eq!(problem.type_uri, "urn:zerofs:error:not_found");
+        assert_eq!(problem.title, "Not Found");
+
+        let response = problem.into_response();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(
+            response.headers().get(header::CONTENT_TYPE).unwrap(),
+            PROBLEM_CONTENT_TYPE
+        );
+        assert_eq!(response.headers().get(RETRYABLE_HEADER).unwrap(), "false");
+
+        let problem = Problem::from(FsError::StoreFull("disk is 99% full".into()));
+        assert_eq!(problem.code, ErrorCode::StoreFull);
+        assert_eq!(problem.get_status(), StatusCode::INSUFFICIENT_STORAGE);
+        assert!(!problem.retryable);
+
+        let timeout = std::io::Error::from(std::io::ErrorKind::TimedOut);
+        let problem = Problem::from(ServiceError::IoError(timeout));
+        assert!(problem.retryable);
+
+        anyhow::Ok(())
+    }
+}
@@ -0,0 +1,287 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_with::serde_as;
+use zeroutils_key::{JwsAlgName, Sign, Verify};
+use zeroutils_store::{ipld::cid::Cid, IpldStore};
+
+use crate::filesystem::{current_time, verify_inclusion, InclusionProof, Path};
+
+use super::{FsService, ServiceError, ServiceResult};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The prefix of the bytes a commit receipt is signed as, so that its signatures cannot be
+/// mistaken for signatures of anything else.
+const RECEIPT_DOMAIN: &str = "zerofs commit receipt v1";
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// A statement by a node that a mutation was committed, and where.
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CommitReceipt {
+    /// The CID of the root right after the mutation.
+    #[serde_as(as = "serde_with::DisplayFromStr")]
+    pub root: Cid,
+
+    /// The consensus term the mutation was committed in.
+    pub term: u64,
+
+    /// The position of the mutation among all the mutations committed by the node, from 1.
+    pub index: u64,
+
+    /// The name of the mutation, like `link_at`.
+    pub operation: String,
+
+    /// The path the mutation was made at.
+    #[serde_as(as = "serde_with::DisplayFromStr")]
+    pub path: Path,
+
+    /// The time the mutation was committed.
+    pub committed_at: DateTime<Utc>,
+
+    /// The DID of the node that committed the mutation.
+    pub did: String,
+}
+
+/// A [`CommitReceipt`] signed by the node that committed the mutation.
+///
+/// Clients can store it as evidence of their write, check it with [`verify`][Self::verify], and
+/// later check that what they wrote is still in place under a newer root with
+/// [`check_inclusion`][Self::check_inclusion].
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedReceipt {
+    /// The receipt.
+    pub receipt: CommitReceipt,
+
+    /// The JWS name of the signature algorithm.
+    pub alg: String,
+
+    /// The signature of the [signed bytes][CommitReceipt::to_signed_bytes] of the receipt by the
+    /// key of the node.
+    #[serde_as(as = "serde_with::Bytes")]
+    pub signature: Vec<u8>,
+}
+
+/// Signs commit receipts for a [`FsService`], with the key of the node.
+pub trait ReceiptSigner: Send + Sync {
+    /// Returns the JWS name of the signature algorithm.
+    fn get_alg_name(&self) -> &str;
+
+    /// Signs `bytes`.
+    fn sign_bytes(&self, bytes: &[u8]) -> ServiceResult<Vec<u8>>;
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl CommitReceipt {
+    /// Returns the bytes the receipt is signed as.
+    pub fn to_signed_bytes(&self) -> Vec<u8> {
+        format!(
+            "{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}",
+            RECEIPT_DOMAIN,
+            self.did,
+            self.root,
+            self.term,
+            self.index,
+            self.operation,
+            self.path,
+            self.committed_at.timestamp_micros()
+        )
+        .into_bytes()
+    }
+
+    /// Signs the receipt with `signer`.
+    pub fn sign(self, signer: &dyn ReceiptSigner) -> ServiceResult<SignedReceipt> {
+        let signature = signer.sign_bytes(&self.to_signed_bytes())?;
+        Ok(SignedReceipt {
+            alg: signer.get_alg_name().to_owned(),
+            receipt: self,
+            signature,
+        })
+    }
+}
+
+impl SignedReceipt {
+    /// Checks that the receipt was signed by a node out of `members`.
+    ///
+    /// `resolve` returns the public key of a DID to check the signature with.
+    pub fn verify<V>(
+        &self,
+        members: &[String],
+        resolve: impl Fn(&str) -> Option<V>,
+    ) -> ServiceResult<()>
+    where
+        V: Verify,
+    {
+        let did = &self.receipt.did;
+        if !members.contains(did) {
+            return Err(ServiceError::InvalidReceipt(format!(
+                "{} is not a member of the cluster",
+                did
+            )));
+        }
+
+        let key = resolve(did)
+            .ok_or_else(|| ServiceError::InvalidReceipt(format!("no public key for {}", did)))?;
+
+        key.verify(&self.receipt.to_signed_bytes(), &self.signature)
+            .map_err(|_| ServiceError::InvalidReceipt(format!("bad signature by {}", did)))
+    }
+
+    /// Checks that `proof` shows `target`, the entity the mutation wrote, at the path of the
+    /// receipt under `root`, see [`verify_inclusion`].
+    ///
+    /// `root` is any root trusted by the client, like a [`SignedRoot`][super::SignedRoot]
+    /// announced after the receipt. The check fails once the entity is replaced or removed.
+    pub fn check_inclusion(
+        &self,
+        proof: &InclusionProof,
+        root: &Cid,
+        target: &Cid,
+    ) -> ServiceResult<()> {
+        if proof.path != self.receipt.path {
+            return Err(ServiceError::InvalidReceipt(format!(
+                "proof is for {}, the receipt is for {}",
+                proof.path, self.receipt.path
+            )));
+        }
+
+        if proof.target != *target {
+            return Err(ServiceError::InvalidReceipt(format!(
+                "{} is at {}, expected {}",
+                proof.target, proof.path, target
+            )));
+        }
+
+        Ok(verify_inclusion(proof, root)?)
+    }
+}
+
+impl<S> FsService<S>
+where
+    S: IpldStore,
+{
+    /// Sets the signer of the receipts of the mutations made through the service. Without one,
+    /// receipts are recorded but not handed out.
+    pub fn set_receipt_signer(&mut self, signer: Arc<dyn ReceiptSigner>) {
+        self.receipt_signer = Some(signer);
+    }
+
+    /// Sets the consensus term later mutations are committed in.
+    pub fn set_commit_term(&mut self, term: u64) {
+        self.commit_term = term;
+    }
+
+    /// Returns the term and index of the last committed mutation.
+    pub fn get_commit_position(&self) -> (u64, u64) {
+        (self.commit_term, self.commit_index)
+    }
+
+    /// Returns the receipt of the last committed mutation, if any, unsigned.
+    pub fn get_last_receipt(&self) -> Option<&CommitReceipt> {
+        self.last_receipt.as_ref()
+    }
+
+    /// Returns the receipt of the last committed mutation, signed, or `None` if there is no
+    /// mutation or no [signer][Self::set_receipt_signer].
+    pub fn sign_last_receipt(&self) -> ServiceResult<Option<SignedReceipt>> {
+        match (&self.last_receipt, &self.receipt_signer) {
+            (Some(receipt), Some(signer)) => Ok(Some(receipt.clone().sign(signer.as_ref())?)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Records the commit of the mutation `operation` at `path`, which led to `root`.
+    pub(crate) fn record_commit(&mut self, operation: &str, path: &Path, root: Cid) {
+        self.commit_index += 1;
+        self.last_receipt = Some(CommitReceipt {
+            root,
+            term: self.commit_term,
+            index: self.commit_index,
+            operation: operation.to_owned(),
+            path: path.clone(),
+            committed_at: current_time(),
+            did: self.config.network.id.to_string(),
+        });
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl<K> ReceiptSigner for K
+where
+    K: Sign + JwsAlgName + Send + Sync,
+{
+    fn get_alg_name(&self) -> &str {
+        self.alg_name()
+    }
+
+    fn sign_bytes(&self, bytes: &[u8]) -> ServiceResult<Vec<u8>> {
+        Ok(self.sign(bytes)?)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use zeroutils_store::{MemoryStore, Storable};
+
+    use crate::{
+        config::ZerofsConfig,
+        filesystem::{Dir, File, FsAction, FsCapabilities, FsCapability},
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_mutations_record_receipts() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let mut service =
+            FsService::new(Dir::new(store.clone()), Arc::new(ZerofsConfig::default()));
+        let capabilities: FsCapabilities = [FsCapability {
+            resource: "zerofs://*".parse()?,
+            action: FsAction::Create,
+        }]
+        .iter()
+        .cloned()
+        .collect();
+        assert!(service.get_last_receipt().is_none());
+
+        let file = File::new(store.clone()).store().await?;
+        service.set_commit_term(3);
+        let root = service.link_at(&capabilities, "docs/a", file).await?;
+        let receipt = service.get_last_receipt().unwrap().clone();
+        assert_eq!(receipt.root, root);
+        assert_eq!((receipt.term, receipt.index), (3, 1));
+        assert_eq!(receipt.operation, "link_at");
+        assert_eq!(receipt.path, "docs/a".parse()?);
+        assert_eq!(receipt.did, service.config.network.id.to_string());
+        assert_eq!(service.get_commit_position(), (3, 1));
+
+        // Receipts are only signed with a signer.
+        assert!(service.sign_last_receipt()?.is_none());
+
+        // Each mutation moves the index, and the signed bytes tell receipts apart.
+        service.link_at(&capabilities, "docs/b", file).await?;
+        let next = service.get_last_receipt().unwrap();
+        assert_eq!(next.index, 2);
+        assert_ne!(next.to_signed_bytes(), receipt.to_signed_bytes());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,149 @@
+use chrono::Duration;
+use zeroutils_key::{JwsAlgName, Verify};
+use zeroutils_store::{IpldStore, Storable};
+
+use crate::filesystem::{current_time, Dir};
+
+use super::{FsService, ServiceError, ServiceResult, SignedRoot};
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl<S> FsService<S>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    /// Follows a root announced by the cluster this node is a read replica of, if its signatures
+    /// verify against the [configured][crate::config::ZerofsReplicaConfig] members and quorum.
+    ///
+    /// `resolve` returns the public key of a member DID. The file tree is switched to the announced
+    /// root and stays read-only, and the announced idempotency index is picked up, so the replica
+    /// replays the recorded responses if it takes over. Announcements no newer than the followed
+    /// one are ignored, so an old root cannot be replayed, and `false` is returned for them.
+    pub async fn follow_root<V>(
+        &mut self,
+        signed: SignedRoot,
+        resolve: impl Fn(&str) -> Option<V>,
+    ) -> ServiceResult<bool>
+    where
+        V: Verify + JwsAlgName,
+    {
+        let replica = &self.config.replica;
+        if !replica.enabled {
+            return Err(ServiceError::NotAReplica);
+        }
+
+        signed.verify(&replica.members, replica.quorum, resolve)?;
+        if let Some(current) = &self.signed_root {
+            if signed.announcement.sequence <= current.announcement.sequence {
+                return Ok(false);
+            }
+        }
+
+        let store = self.root_dir.get_store().clone();
+        let root_dir = Dir::load(&signed.announcement.root, store).await?;
+        if let Some(head) = &signed.announcement.idempotency {
+            self.load_idempotency_index(head).await?;
+        }
+
+        self.root_dir = root_dir;
+        self.signed_root = Some(signed);
+        self.set_read_only(true);
+
+        Ok(true)
+    }
+
+    /// Returns how long ago the followed root was announced, or `None` if no root was followed
+    /// yet.
+    pub fn get_staleness(&self) -> Option<Duration> {
+        self.signed_root
+            .as_ref()
+            .map(|signed| current_time() - signed.announcement.announced_at)
+    }
+
+    /// Returns `true` if this is a read replica that should not serve requests, as it follows no
+    /// root yet or its root is older than the configured maximum staleness.
+    pub fn is_too_stale(&self) -> bool {
+        let replica = &self.config.replica;
+        if !replica.enabled {
+            return false;
+        }
+
+        match (self.get_staleness(), replica.max_staleness) {
+            (None, _) => true,
+            (Some(staleness), Some(max)) => staleness.num_seconds() > max as i64,
+            (Some(_), None) => false,
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use zeroutils_did_wk::{Base, WrappedDidWebKey};
+    use zeroutils_key::{Ed25519KeyPair, GetPublicKey, IntoOwned, KeyPairGenerate};
+    use zeroutils_store::{MemoryStore, Storable};
+
+    use crate::{
+        config::{ZerofsConfig, ZerofsReplicaConfig},
+        filesystem::{File, LazyStore},
+        service::RootAnnouncement,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_replica_follows_signed_roots() -> anyhow::Result<()> {
+        let key = Ed25519KeyPair::generate(&mut rand::thread_rng())?;
+        let did = WrappedDidWebKey::from_key(&key, Base::Base58Btc)?.to_string();
+        let resolve = |_: &str| Some(key.public_key().into_owned());
+
+        let primary = MemoryStore::default();
+        let file = File::new(primary.clone()).store().await?;
+        let root = Dir::new(primary.clone())
+            .link_at(&"public/notes".parse()?, file)
+            .await?
+            .store()
+            .await?;
+
+        let config = ZerofsConfig::builder()
+            .replica(
+                ZerofsReplicaConfig::builder()
+                    .enabled(true)
+                    .members(vec![did.clone()])
+                    .max_staleness(60)
+                    .build(),
+            )
+            .build();
+        let store = LazyStore::new(MemoryStore::default(), primary);
+        let mut replica = FsService::new(Dir::new(store), Arc::new(config));
+        assert!(replica.is_too_stale());
+
+        let signed = SignedRoot::sign(
+            RootAnnouncement::new(root, 1, None, current_time()),
+            did.clone(),
+            &key,
+        )?;
+        assert!(replica.follow_root(signed.clone(), resolve).await?);
+        assert_eq!(replica.root_dir.store().await?, root);
+        assert!(replica.is_read_only());
+        assert!(!replica.is_too_stale());
+
+        // A replayed or older announcement is ignored.
+        assert!(!replica.follow_root(signed, resolve).await?);
+
+        // Old roots are too stale to serve.
+        let old = current_time() - Duration::seconds(120);
+        let signed = SignedRoot::sign(RootAnnouncement::new(root, 2, Some(root), old), did, &key)?;
+        assert!(replica.follow_root(signed, resolve).await?);
+        assert!(replica.is_too_stale());
+
+        Ok(())
+    }
+}
@@ -1,8 +1,12 @@
-use serde::{Deserialize, Serialize};
+use serde::{de::IgnoredAny, Deserialize, Deserializer, Serialize};
 use serde_with::serde_as;
 use zeroutils_store::ipld::cid::Cid;
 
-use crate::filesystem::{DescriptorFlags, OpenFlags, Path};
+use crate::filesystem::{
+    Acl, DescriptorFlags, EntityStat, EntityType, OpenFlags, Path, PathSegment,
+};
+
+use super::SignedReceipt;
 
 //--------------------------------------------------------------------------------------------------
 // Types: Identifiers
@@ -31,6 +35,11 @@ pub struct EntityOperation {
     pub identifier: Option<EntityIdentifier>,
 
     /// The operation to perform on the entity.
+    ///
+    /// Operations this version does not know are read as [`EntityOperationKind::Unknown`] rather
+    /// than failing, so a batch from a newer peer can still be read and its unknown operations
+    /// rejected one by one.
+    #[serde(deserialize_with = "deserialize_operation_kind")]
     pub operation: EntityOperationKind,
 }
 
@@ -40,6 +49,55 @@ pub struct EntityOperation {
 pub enum EntityOperationKind {
     /// `Open` returns a handle to the entity that can be used to perform other operations on it.
     OpenAt(OpenAt),
+
+    /// `SetAclAt` replaces the access control list of the entity at a given path.
+    SetAclAt(SetAclAt),
+
+    /// `GetAclAt` returns the access control list of the entity at a given path.
+    GetAclAt(GetAclAt),
+
+    /// `ReadAt` reads part of the content of the file at a given path.
+    ReadAt(ReadAt),
+
+    /// `WriteAt` writes bytes into the content of the file at a given path.
+    WriteAt(WriteAt),
+
+    /// `CreateDirAt` creates a directory at a given path.
+    CreateDirAt(CreateDirAt),
+
+    /// `RemoveAt` removes the entity at a given path.
+    RemoveAt(RemoveAt),
+
+    /// `RenameAt` renames the entity at a given path within its directory.
+    RenameAt(RenameAt),
+
+    /// `StatAt` returns the status of the entity at a given path.
+    StatAt(StatAt),
+
+    /// `ReadDir` lists the entries of the directory at a given path.
+    ReadDir(ReadDir),
+
+    /// `CreateSnapshot` records a snapshot of the file tree.
+    CreateSnapshot(CreateSnapshot),
+
+    /// An operation this version does not know, with the type it was sent with. It is never sent.
+    #[serde(skip)]
+    Unknown(String),
+}
+
+/// How an [`EntityOperationKind`] is read: as one of the known operations if it is one, and
+/// otherwise by its type alone, ignoring its parameters.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum EntityOperationKindWire {
+    Known(EntityOperationKind),
+    Unknown {
+        #[serde(rename = "type")]
+        kind: String,
+        #[serde(default)]
+        #[allow(dead_code)]
+        params: IgnoredAny,
+    },
 }
 
 /// Represents an operation that opens an entity at a given path.
@@ -57,6 +115,368 @@ pub struct OpenAt {
     descriptor_flags: DescriptorFlags, // TODO: Should serialize to u8
 }
 
+/// Represents an operation that replaces the access control list of the entity at a given path.
+#[serde_as]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SetAclAt {
+    /// The path to the entity.
+    #[serde_as(as = "serde_with::DisplayFromStr")]
+    pub(crate) path: Path,
+
+    /// The new access control list of the entity.
+    pub(crate) acl: Acl,
+}
+
+/// Represents an operation that returns the access control list of the entity at a given path.
+#[serde_as]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GetAclAt {
+    /// The path to the entity.
+    #[serde_as(as = "serde_with::DisplayFromStr")]
+    pub(crate) path: Path,
+}
+
+/// The paths to stat or check with `/stat_many` or `/exists_many`.
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PathsRequest {
+    /// The paths, relative to the root.
+    #[serde_as(as = "Vec<serde_with::DisplayFromStr>")]
+    pub paths: Vec<Path>,
+}
+
+/// Represents an operation that reads up to `len` bytes of the content of the file at a given
+/// path, starting at `offset`, or all of it after `offset` if `len` is `None`.
+#[serde_as]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReadAt {
+    /// The path to the file.
+    #[serde_as(as = "serde_with::DisplayFromStr")]
+    path: Path,
+
+    /// The position of the first byte to read.
+    #[serde(default)]
+    offset: u64,
+
+    /// The largest number of bytes to read.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    len: Option<u64>,
+}
+
+/// Represents an operation that writes bytes into the content of the file at a given path,
+/// starting at `offset`.
+#[serde_as]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WriteAt {
+    /// The path to the file.
+    #[serde_as(as = "serde_with::DisplayFromStr")]
+    path: Path,
+
+    /// The position of the first byte written.
+    #[serde(default)]
+    offset: u64,
+
+    /// The bytes to write.
+    #[serde_as(as = "serde_with::Bytes")]
+    data: Vec<u8>,
+}
+
+/// Represents an operation that creates a directory at a given path.
+#[serde_as]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CreateDirAt {
+    /// The path to the directory.
+    #[serde_as(as = "serde_with::DisplayFromStr")]
+    path: Path,
+}
+
+/// Represents an operation that removes the entity at a given path.
+#[serde_as]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RemoveAt {
+    /// The path to the entity.
+    #[serde_as(as = "serde_with::DisplayFromStr")]
+    path: Path,
+
+    /// Whether a directory that is not empty is removed along with everything under it.
+    #[serde(default)]
+    recursive: bool,
+
+    /// The CID the entity is expected to have, if it is only to be removed if it did not change.
+    #[serde_as(as = "Option<serde_with::DisplayFromStr>")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expected: Option<Cid>,
+}
+
+/// Represents an operation that renames the entity at a given path within its directory.
+#[serde_as]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RenameAt {
+    /// The path to the entity.
+    #[serde_as(as = "serde_with::DisplayFromStr")]
+    path: Path,
+
+    /// The new name of the entity.
+    #[serde_as(as = "serde_with::DisplayFromStr")]
+    name: PathSegment,
+}
+
+/// Represents an operation that returns the status of the entity at a given path.
+#[serde_as]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StatAt {
+    /// The path to the entity.
+    #[serde_as(as = "serde_with::DisplayFromStr")]
+    path: Path,
+}
+
+/// Represents an operation that lists the entries of the directory at a given path, in name
+/// order, starting after the entry named `after`.
+#[serde_as]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReadDir {
+    /// The path to the directory.
+    #[serde_as(as = "serde_with::DisplayFromStr")]
+    path: Path,
+
+    /// The name of the last entry of the previous page, if any.
+    #[serde_as(as = "Option<serde_with::DisplayFromStr>")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    after: Option<PathSegment>,
+
+    /// The largest number of entries to return.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    limit: Option<usize>,
+}
+
+/// Represents an operation that records a snapshot of the file tree, keeping only the `keep` most
+/// recent snapshots if it is set.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CreateSnapshot {
+    /// The number of snapshots to keep.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    keep: Option<usize>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Types: Responses
+//--------------------------------------------------------------------------------------------------
+
+/// The response to writing a file with `/write_at`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WriteAtResponse {
+    /// The CID of the written file.
+    pub file: String,
+
+    /// The CID of the root after the file was linked.
+    pub root: String,
+
+    /// The signed receipt of the write, if the node signs receipts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub receipt: Option<SignedReceipt>,
+}
+
+/// The response to searching the file tree with `/search`.
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SearchResponse {
+    /// The matching paths, sorted.
+    #[serde_as(as = "Vec<serde_with::DisplayFromStr>")]
+    pub paths: Vec<Path>,
+}
+
+/// The response to matching paths with `/glob`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GlobResponse {
+    /// The matching paths the caller can read, sorted.
+    pub matches: Vec<GlobMatch>,
+}
+
+/// A path that matched a glob pattern.
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GlobMatch {
+    /// The matching path.
+    #[serde_as(as = "serde_with::DisplayFromStr")]
+    pub path: Path,
+
+    /// The CID of the entity at the path.
+    #[serde_as(as = "serde_with::DisplayFromStr")]
+    pub cid: Cid,
+}
+
+/// The response to stating paths with `/stat_many`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StatManyResponse {
+    /// The stat of the entity at each requested path, in the same order, or `None` if there is
+    /// none the caller can read.
+    pub stats: Vec<Option<EntityStat>>,
+}
+
+/// The response to checking paths with `/exists_many`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExistsManyResponse {
+    /// Whether there is an entity the caller can read at each requested path, in the same order.
+    pub exists: Vec<bool>,
+}
+
+/// The response to asking for the content CID of a file with `/content_cid_at`.
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContentCidResponse {
+    /// The CID of the content of the file, or `None` if the file is empty.
+    #[serde_as(as = "Option<serde_with::DisplayFromStr>")]
+    #[serde(default)]
+    pub content: Option<Cid>,
+}
+
+/// The response to getting or replacing the access control list of an entity with `/get_acl_at`
+/// or `/set_acl_at`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AclResponse {
+    /// The access control list of the entity.
+    pub acl: Acl,
+}
+
+/// The response to resolving a path with `/realpath_at`.
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RealpathResponse {
+    /// The canonical path, with no symbolic links and no `.` or `..`.
+    pub path: Path,
+
+    /// The CID of the entity at the canonical path.
+    #[serde_as(as = "serde_with::DisplayFromStr")]
+    pub cid: Cid,
+}
+
+/// An entry of a directory listed with `/read_dir`, which is streamed as one JSON object per line.
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReadDirEntry {
+    /// The name of the entry, which is also the cursor to resume the listing after it.
+    #[serde_as(as = "serde_with::DisplayFromStr")]
+    pub name: PathSegment,
+
+    /// The CID of the entity.
+    #[serde_as(as = "serde_with::DisplayFromStr")]
+    pub cid: Cid,
+
+    /// The type of the entity, if it could be found out.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entity_type: Option<EntityType>,
+
+    /// The size of the file content in bytes, if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl OpenAt {
+    /// Creates an operation that opens the entity at `path`.
+    pub fn new(path: Path, open_flags: OpenFlags, descriptor_flags: DescriptorFlags) -> Self {
+        Self {
+            path,
+            open_flags,
+            descriptor_flags,
+        }
+    }
+}
+
+impl SetAclAt {
+    /// Creates an operation that replaces the access control list of the entity at `path`.
+    pub fn new(path: Path, acl: Acl) -> Self {
+        Self { path, acl }
+    }
+}
+
+impl GetAclAt {
+    /// Creates an operation that returns the access control list of the entity at `path`.
+    pub fn new(path: Path) -> Self {
+        Self { path }
+    }
+}
+
+impl ReadAt {
+    /// Creates an operation that reads up to `len` bytes of the file at `path` from `offset`.
+    pub fn new(path: Path, offset: u64, len: Option<u64>) -> Self {
+        Self { path, offset, len }
+    }
+}
+
+impl WriteAt {
+    /// Creates an operation that writes `data` into the file at `path` from `offset`.
+    pub fn new(path: Path, offset: u64, data: Vec<u8>) -> Self {
+        Self { path, offset, data }
+    }
+}
+
+impl CreateDirAt {
+    /// Creates an operation that creates a directory at `path`.
+    pub fn new(path: Path) -> Self {
+        Self { path }
+    }
+}
+
+impl RemoveAt {
+    /// Creates an operation that removes the entity at `path`.
+    pub fn new(path: Path, recursive: bool, expected: Option<Cid>) -> Self {
+        Self {
+            path,
+            recursive,
+            expected,
+        }
+    }
+}
+
+impl RenameAt {
+    /// Creates an operation that renames the entity at `path` to `name`.
+    pub fn new(path: Path, name: PathSegment) -> Self {
+        Self { path, name }
+    }
+}
+
+impl StatAt {
+    /// Creates an operation that returns the status of the entity at `path`.
+    pub fn new(path: Path) -> Self {
+        Self { path }
+    }
+}
+
+impl ReadDir {
+    /// Creates an operation that lists up to `limit` entries of the directory at `path` after
+    /// `after`.
+    pub fn new(path: Path, after: Option<PathSegment>, limit: Option<usize>) -> Self {
+        Self { path, after, limit }
+    }
+}
+
+impl CreateSnapshot {
+    /// Creates an operation that records a snapshot, keeping the `keep` most recent ones.
+    pub fn new(keep: Option<usize>) -> Self {
+        Self { keep }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Reads an [`EntityOperationKind`], falling back to [`EntityOperationKind::Unknown`] for
+/// operations this version does not know.
+fn deserialize_operation_kind<'de, D>(deserializer: D) -> Result<EntityOperationKind, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(match EntityOperationKindWire::deserialize(deserializer)? {
+        EntityOperationKindWire::Known(kind) => kind,
+        EntityOperationKindWire::Unknown { kind, .. } => EntityOperationKind::Unknown(kind),
+    })
+}
+
 //--------------------------------------------------------------------------------------------------
 // Tests
 //--------------------------------------------------------------------------------------------------
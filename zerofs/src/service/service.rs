@@ -1,10 +1,35 @@
 use std::sync::Arc;
 
-use zeroutils_store::IpldStore;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    convert::TryInto,
+    net::SocketAddr,
+    path::Path as LocalPath,
+    sync::atomic::{AtomicBool, AtomicI64, Ordering},
+};
 
-use crate::{config::ZerofsConfig, filesystem::Dir};
+use chrono::{DateTime, Duration, Utc};
+use tokio::sync::{watch, Mutex};
+use zeroutils_store::{ipld::cid::Cid, IpldStore, Storable};
 
-use super::{FsServiceBuilder, ServiceResult};
+use crate::{
+    config::ZerofsConfig,
+    filesystem::{
+        self, content_queue, Acl, Branch, ChangeFeed, ChangeSet, ConflictReport, ConsistencyReport,
+        ContentWorker, DerivedBlob, DerivedIndex, Dir, DiskSpaceLevel, DiskSpaceStatus, Entity,
+        EntityAttributes, EntityStat, File, FsAction, FsCapabilities, FsDelegation, FsError,
+        FsResource, GlobPattern, Group, Groups, InclusionProof, IngestOptions, Journal, KeyGrant,
+        MaterializeOptions, MaterializeReport, MergeReport, MergeStrategy, Path, PathSegment,
+        PermissionError, RefCountIndex, RemoveOptions, RootRegistry, RootSource, SearchIndex,
+        SyncDirection, SyncReport, TraceResult, TransferScheduler, Transformer,
+        DEFAULT_GLOB_MAX_VISITED, DERIVED_PATH, GROUPS_PATH, REFS_PATH, TRASH_PATH,
+    },
+};
+
+use super::{
+    CommitReceipt, DiskLog, FsServiceBuilder, GossipView, MaintenanceStatus, MaintenanceTask,
+    ReceiptSigner, ServiceError, ServiceResult, SignedRoot,
+};
 
 //--------------------------------------------------------------------------------------------------
 // Types
@@ -13,9 +38,15 @@ use super::{FsServiceBuilder, ServiceResult};
 /// A shared configuration for the file system service.
 pub type SharedConfig = Arc<ZerofsConfig>;
 
+/// A file system service shared between the servers and background tasks that use it.
+pub type SharedService<S> = Arc<Mutex<FsService<S>>>;
+
 /// `FsService` is a service that provides a distributed file system functionality.
 ///
-/// This service uses a block store to store the file system data.
+/// This service uses a block store to store the file system data. Every operation takes the
+/// [`FsCapabilities`] of the caller and fails unless they, or the [`Acl`]s stored in the tree for
+/// the caller, allow the operation on the paths it touches in this service's tree, identified by
+/// the DID in the network configuration.
 pub struct FsService<S>
 where
     S: IpldStore,
@@ -25,10 +56,99 @@ where
 
     /// The configuration of the file system.
     pub config: SharedConfig,
+
+    /// The time of the last automatic trash purge.
+    last_trash_purge: Option<DateTime<Utc>>,
+
+    /// The run history of the maintenance tasks.
+    pub(crate) maintenance: BTreeMap<MaintenanceTask, MaintenanceStatus>,
+
+    /// The time of the last operation, in milliseconds since the Unix epoch.
+    pub(crate) last_activity: AtomicI64,
+
+    /// Whether operations that change the file tree are refused, shared with the roots handed out
+    /// by the service, like the one of the [preopened directories][Self::open_preopens].
+    pub(crate) read_only: Arc<AtomicBool>,
+
+    /// Schedules the block transfers with peers.
+    transfers: Arc<TransferScheduler>,
+
+    /// The operations made while disconnected from the cluster, if it is.
+    journal: Option<Journal>,
+
+    /// The history of the roots of the file tree.
+    changes: ChangeFeed<S>,
+
+    /// The [`Cid`] of the index of the responses to idempotent requests, if a key was ever used.
+    pub(crate) idempotency: Option<Cid>,
+
+    /// The index of the names and attributes of the entities in the tree, if it is enabled.
+    search: Option<SearchIndex<S>>,
+
+    /// The worker that extracts the content of files for the search index, until it is taken to
+    /// run in the background.
+    content_worker: Option<ContentWorker<S>>,
+
+    /// The transformers that derive blobs from files, by the ID of their transform.
+    transformers: BTreeMap<String, Arc<dyn Transformer>>,
+
+    /// The latest root announced by this node, with its signatures.
+    pub(crate) signed_root: Option<SignedRoot>,
+
+    /// The peer addresses of the nodes admitted to the cluster by this node, by DID.
+    pub(crate) admitted: BTreeMap<String, SocketAddr>,
+
+    /// The peer addresses of the discovered peers that proved their DID, by DID. They are not
+    /// members of the cluster.
+    pub(crate) discovered: BTreeMap<String, SocketAddr>,
+
+    /// The read replicas this node heard of through gossip.
+    pub(crate) gossip: GossipView,
+
+    /// The consensus term mutations are committed in.
+    pub(crate) commit_term: u64,
+
+    /// The number of mutations committed through the service.
+    pub(crate) commit_index: u64,
+
+    /// The receipt of the last committed mutation.
+    pub(crate) last_receipt: Option<CommitReceipt>,
+
+    /// Signs the receipts handed out to clients, if set.
+    pub(crate) receipt_signer: Option<Arc<dyn ReceiptSigner>>,
+
+    /// The roots whose trees are kept along with the current one.
+    pub(crate) roots: RootRegistry<S>,
+
+    /// The references to each block of the trees under the kept roots, if they are counted.
+    pub(crate) refcounts: Option<RefCountIndex>,
+
+    /// The blocks that became unreferenced and were not removed from the store yet.
+    pub(crate) unreferenced: BTreeSet<Cid>,
+
+    /// The free space of the disks of the store, if it is monitored.
+    disk_space: Option<watch::Receiver<DiskSpaceStatus>>,
+
+    /// The result of checking the store when the service was opened, if it was.
+    consistency: Option<ConsistencyReport>,
+
+    /// The log the consensus layer keeps its entries in, apart from the blocks of the tree, if the
+    /// service was given one.
+    pub(crate) consensus_log: Option<DiskLog>,
     // /// Raft node.
     // pub raft: RaftNode<FsStateMachine<DiskStore>, ...>,
 }
 
+/// Identifies an entity either by its path in the file tree or by its [`Cid`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathOrCid {
+    /// The path to the entity from the root directory.
+    Path(Path),
+
+    /// The CID of the entity.
+    Cid(Cid),
+}
+
 //--------------------------------------------------------------------------------------------------
 // Methods
 //--------------------------------------------------------------------------------------------------
@@ -39,7 +159,528 @@ where
 {
     /// Creates a new file system service with the given root directory and configuration.
     pub fn new(root_dir: Dir<S>, config: SharedConfig) -> Self {
-        Self { root_dir, config }
+        let store = root_dir.get_store().clone();
+        let mut search = None;
+        let mut content_worker = None;
+        if config.search.enabled {
+            let mut index = SearchIndex::new(store.clone());
+            if config.search.content {
+                let (queue, worker) = content_queue(store.clone(), config.search.max_content_size);
+                index.set_content_queue(queue);
+                content_worker = Some(worker);
+            }
+
+            search = Some(index);
+        }
+
+        #[allow(unused_mut)]
+        let mut transformers = BTreeMap::<String, Arc<dyn Transformer>>::new();
+        #[cfg(feature = "thumbnails")]
+        transformers.insert(
+            filesystem::THUMBNAIL_TRANSFORM.to_owned(),
+            Arc::new(filesystem::Thumbnailer),
+        );
+
+        Self {
+            changes: ChangeFeed::new(store),
+            idempotency: None,
+            search,
+            content_worker,
+            signed_root: None,
+            admitted: BTreeMap::new(),
+            discovered: BTreeMap::new(),
+            gossip: GossipView::default(),
+            commit_term: 0,
+            commit_index: 0,
+            last_receipt: None,
+            receipt_signer: None,
+            roots: RootRegistry::new(),
+            refcounts: config.maintenance.refcount_index.then(RefCountIndex::new),
+            unreferenced: BTreeSet::new(),
+            disk_space: None,
+            consistency: None,
+            consensus_log: None,
+            transformers,
+            root_dir,
+            last_trash_purge: None,
+            maintenance: BTreeMap::new(),
+            last_activity: AtomicI64::new(Utc::now().timestamp_millis()),
+            read_only: Arc::new(AtomicBool::new(config.read_only || config.replica.enabled)),
+            transfers: Arc::new(TransferScheduler::new(&config.transfer)),
+            journal: None,
+            config,
+        }
+    }
+
+    /// Opens a file system service on the tree stored at `root`.
+    ///
+    /// With [`consistency_check`][crate::config::ZerofsStartupConfig::consistency_check] on, the
+    /// store is checked against the tree first, and the report is logged and kept for
+    /// [`get_consistency_report`][Self::get_consistency_report]. `stored`, the blocks in the store
+    /// if it can list them, lets the check report orphaned blocks.
+    ///
+    /// Fails with [`ServiceError::UnreadableRoot`] if the root cannot be loaded, unless the
+    /// configuration asks to serve it anyway, in which case the service starts read-only on an
+    /// empty tree.
+    pub async fn open(
+        root: &Cid,
+        store: S,
+        config: SharedConfig,
+        stored: Option<HashSet<Cid>>,
+    ) -> ServiceResult<Self>
+    where
+        S: Send + Sync + 'static,
+    {
+        if !config.store.is_consistent_with(root) {
+            return Err(ServiceError::StoreConfigMismatch(*root));
+        }
+
+        let consistency = if config.startup.consistency_check {
+            let report = filesystem::check_consistency(root, &store, stored.as_ref()).await?;
+            if report.is_ok() {
+                tracing::info!(
+                    "Store is consistent with root {}: {} blocks reachable, {} orphaned",
+                    root,
+                    report.reachable,
+                    report.orphans.as_ref().map_or(0, Vec::len)
+                );
+            } else {
+                tracing::error!(
+                    "Store is inconsistent with root {}: {} of {} reachable blocks missing",
+                    root,
+                    report.missing.len(),
+                    report.reachable
+                );
+            }
+
+            Some(report)
+        } else {
+            None
+        };
+
+        let (root_dir, unreadable) = match Dir::load(root, store.clone()).await {
+            Ok(root_dir) => (root_dir, false),
+            Err(e) if config.startup.serve_unreadable_root => {
+                tracing::error!("Root {} cannot be read, serving an empty tree: {}", root, e);
+                (Dir::new(store), true)
+            }
+            Err(e) => {
+                tracing::error!("Root {} cannot be read: {}", root, e);
+                return Err(ServiceError::UnreadableRoot(*root));
+            }
+        };
+
+        let mut service = Self::new(root_dir, config);
+        service.consistency = consistency;
+        if unreadable {
+            service.set_read_only(true);
+        }
+
+        Ok(service)
+    }
+
+    /// Returns the result of checking the store when the service was opened, if it was.
+    pub fn get_consistency_report(&self) -> Option<&ConsistencyReport> {
+        self.consistency.as_ref()
+    }
+
+    /// Returns the log the consensus layer keeps its entries in, if the service was given one.
+    pub fn get_consensus_log(&self) -> Option<&DiskLog> {
+        self.consensus_log.as_ref()
+    }
+
+    /// Returns the log the consensus layer keeps its entries in, to append to it.
+    pub fn get_consensus_log_mut(&mut self) -> Option<&mut DiskLog> {
+        self.consensus_log.as_mut()
+    }
+
+    /// Makes the file tree read-only, or writable again.
+    ///
+    /// While the file tree is read-only, every operation that would change it fails with
+    /// [`FsError::ReadOnlyFilesystem`], which is useful during maintenance or a migration, or when
+    /// serving a published archive. The service starts out read-only if the configuration says so.
+    /// The handles opened from the roots the service handed out follow along.
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only.store(read_only, Ordering::SeqCst);
+    }
+
+    /// Returns `true` if the file tree is read-only.
+    pub fn is_read_only(&self) -> bool {
+        self.read_only.load(Ordering::SeqCst)
+    }
+
+    /// Returns the scheduler the block transfers with peers go through, to be shared with the
+    /// [`ScheduledStore`][filesystem::ScheduledStore]s of the peers.
+    pub fn get_transfer_scheduler(&self) -> &Arc<TransferScheduler> {
+        &self.transfers
+    }
+
+    /// Starts journaling the operations on the file tree while disconnected from the cluster.
+    ///
+    /// Writes keep being accepted locally, and each operation that changes the tree is recorded
+    /// in a [`Journal`] to be replayed with [`reconnect`][Self::reconnect]. Does nothing if the
+    /// service is offline already.
+    pub async fn go_offline(&mut self) -> ServiceResult<()>
+    where
+        S: Send + Sync,
+    {
+        if self.journal.is_none() {
+            self.journal = Some(Journal::new(self.root_dir.store().await?));
+        }
+
+        Ok(())
+    }
+
+    /// Returns `true` if operations are being journaled while disconnected from the cluster.
+    pub fn is_offline(&self) -> bool {
+        self.journal.is_some()
+    }
+
+    /// Returns the operations journaled while disconnected from the cluster.
+    pub fn get_journal(&self) -> Option<&Journal> {
+        self.journal.as_ref()
+    }
+
+    /// Replays the journaled operations on the tree of the cluster, whose root is `remote`, and
+    /// makes the merged tree the root directory.
+    ///
+    /// Changes the cluster made to the same entities are kept, and the local changes that conflict
+    /// with them are returned in the [`ConflictReport`] rather than applied. Without a journal, the
+    /// tree of the cluster simply replaces the root directory.
+    pub async fn reconnect(&mut self, remote: &Cid) -> ServiceResult<ConflictReport>
+    where
+        S: Send + Sync + 'static,
+    {
+        if !self.config.store.is_consistent_with(remote) {
+            return Err(ServiceError::StoreConfigMismatch(*remote));
+        }
+
+        // Changes made around the service methods, like streamed writes, are replayed as one more
+        // operation.
+        self.record_operation("offline", &Path::default()).await?;
+
+        let store = self.root_dir.get_store().clone();
+        let remote = Dir::load(remote, store).await?;
+        let (root_dir, report) = match self.journal.take() {
+            Some(journal) => journal.replay(&remote).await?,
+            None => (remote, ConflictReport::default()),
+        };
+
+        self.root_dir = root_dir;
+        self.record_operation("reconnect", &Path::default()).await?;
+
+        Ok(report)
+    }
+
+    /// Returns the history of the roots of the file tree.
+    pub fn get_change_feed(&self) -> &ChangeFeed<S> {
+        &self.changes
+    }
+
+    /// Picks up the change feed whose head commit is at `head`, like one persisted before a
+    /// restart, replacing the current one.
+    pub async fn load_change_feed(&mut self, head: &Cid) -> ServiceResult<()>
+    where
+        S: Send + Sync,
+    {
+        self.changes = ChangeFeed::load(head, self.root_dir.get_store().clone()).await?;
+
+        Ok(())
+    }
+
+    /// Returns what changed in the file tree since `cursor`, up to `limit` commits at a time.
+    ///
+    /// A client that was offline passes the cursor of the last [`ChangeSet`] it got to catch up,
+    /// without diffing the trees itself. Requires [`FsAction::Read`] on the whole tree, since the
+    /// changes can be anywhere in it.
+    pub async fn changes_since(
+        &self,
+        capabilities: &FsCapabilities,
+        cursor: u64,
+        limit: Option<usize>,
+    ) -> ServiceResult<ChangeSet>
+    where
+        S: Send + Sync + 'static,
+    {
+        self.authorize(capabilities, &Path::default(), FsAction::Read)
+            .await?;
+
+        Ok(self.changes.changes_since(cursor, limit).await?)
+    }
+
+    /// Returns the search index, or `None` if it is disabled.
+    pub fn get_search_index(&self) -> Option<&SearchIndex<S>> {
+        self.search.as_ref()
+    }
+
+    /// Picks up the search index stored at `cid`, like one persisted before a restart, so that
+    /// only what changed since is indexed again.
+    pub async fn load_search_index(&mut self, cid: &Cid) -> ServiceResult<()>
+    where
+        S: Send + Sync + 'static,
+    {
+        let queue = match &self.search {
+            Some(index) => index.get_content_queue().cloned(),
+            None => return Err(ServiceError::SearchDisabled),
+        };
+
+        let mut index = SearchIndex::load(cid, self.root_dir.get_store().clone()).await?;
+        if let Some(queue) = queue {
+            index.set_content_queue(queue);
+        }
+
+        self.search = Some(index);
+
+        Ok(())
+    }
+
+    /// Takes the worker that extracts the content of files for the search index, or `None` if
+    /// content indexing is disabled or the worker was taken already.
+    ///
+    /// The worker is meant to be [`spawn`][ContentWorker::spawn]ed, after adding any extractors,
+    /// so that the text of files is indexed in the background. Until it is, files wait to be
+    /// extracted and are only found by their names.
+    pub fn take_content_worker(&mut self) -> Option<ContentWorker<S>> {
+        self.content_worker.take()
+    }
+
+    /// Returns the paths whose names and attributes match `query`, up to `limit` of them, see
+    /// [`SearchIndex::search`] for the syntax.
+    ///
+    /// Only the paths `capabilities` allow [`FsAction::Read`] on are returned. Fails with
+    /// [`ServiceError::SearchDisabled`] unless the search index is enabled.
+    pub async fn search(
+        &mut self,
+        capabilities: &FsCapabilities,
+        query: &str,
+        limit: Option<usize>,
+    ) -> ServiceResult<Vec<Path>>
+    where
+        S: Send + Sync + 'static,
+    {
+        let index = self.search.as_mut().ok_or(ServiceError::SearchDisabled)?;
+
+        // Catches up with changes made around the service methods, like streamed writes.
+        index.update(&self.root_dir).await?;
+
+        let index = self.search.as_ref().ok_or(ServiceError::SearchDisabled)?;
+        let limit = limit.unwrap_or(usize::MAX);
+        let mut paths = Vec::new();
+        for path in index.search(query) {
+            if paths.len() >= limit {
+                break;
+            }
+
+            if self
+                .authorize(capabilities, path, FsAction::Read)
+                .await
+                .is_ok()
+            {
+                paths.push(path.clone());
+            }
+        }
+
+        Ok(paths)
+    }
+
+    /// Returns the [`EntityStat`] of the entity at each of `paths`, in the same order, see
+    /// [`Dir::stat_many`].
+    ///
+    /// Paths `capabilities` do not allow [`FsAction::Read`] on are reported as `None`, like paths
+    /// nothing is at, so a batch does not fail because of one of them.
+    pub async fn stat_many(
+        &self,
+        capabilities: &FsCapabilities,
+        paths: &[Path],
+    ) -> ServiceResult<Vec<Option<EntityStat>>>
+    where
+        S: Send + Sync,
+    {
+        let mut stats = self.root_dir.stat_many(paths).await?;
+        for (path, stat) in paths.iter().zip(&mut stats) {
+            if stat.is_some()
+                && self
+                    .authorize(capabilities, path, FsAction::Read)
+                    .await
+                    .is_err()
+            {
+                *stat = None;
+            }
+        }
+
+        Ok(stats)
+    }
+
+    /// Returns whether there is an entity at each of `paths`, in the same order, see
+    /// [`Dir::exists_many`].
+    ///
+    /// Paths `capabilities` do not allow [`FsAction::Read`] on are reported as missing.
+    pub async fn exists_many(
+        &self,
+        capabilities: &FsCapabilities,
+        paths: &[Path],
+    ) -> ServiceResult<Vec<bool>>
+    where
+        S: Send + Sync,
+    {
+        let stats = self.stat_many(capabilities, paths).await?;
+        Ok(stats.iter().map(Option::is_some).collect())
+    }
+
+    /// Returns the paths that match `pattern` with the CIDs of the entities at them, in path order,
+    /// see [`Dir::glob_at`].
+    ///
+    /// Only the paths `capabilities` allow [`FsAction::Read`] on are returned. The walk looks at no
+    /// more than [`DEFAULT_GLOB_MAX_VISITED`] entries.
+    pub async fn glob_at(
+        &self,
+        capabilities: &FsCapabilities,
+        pattern: &GlobPattern,
+    ) -> ServiceResult<Vec<(Path, Cid)>>
+    where
+        S: Send + Sync,
+    {
+        let mut matches = Vec::new();
+        for (path, cid) in self
+            .root_dir
+            .glob_at(pattern, DEFAULT_GLOB_MAX_VISITED)
+            .await?
+        {
+            if self
+                .authorize(capabilities, &path, FsAction::Read)
+                .await
+                .is_ok()
+            {
+                matches.push((path, cid));
+            }
+        }
+
+        Ok(matches)
+    }
+
+    /// Returns the directory at `path`, whose entries can then be listed without holding on to the
+    /// service.
+    ///
+    /// Requires [`FsAction::Read`] on `path`. Fails with [`FsError::NotADirectory`] if the entity
+    /// at `path` is not a directory.
+    pub async fn get_dir_at(
+        &self,
+        capabilities: &FsCapabilities,
+        path: &Path,
+    ) -> ServiceResult<Dir<S>>
+    where
+        S: Send + Sync,
+    {
+        self.authorize(capabilities, path, FsAction::Read).await?;
+
+        match self.get_entity(PathOrCid::Path(path.clone())).await? {
+            Entity::Dir(dir) => Ok(dir),
+            _ => Err(FsError::NotADirectory(Some(path.clone())).into()),
+        }
+    }
+
+    /// Returns the canonical path `path` resolves to once its symbolic links are followed, and the
+    /// [`Cid`] of the entity there, see [`Dir::realpath_at`].
+    ///
+    /// Requires [`FsAction::Read`] on both `path` and the canonical path, so links do not reveal
+    /// what the caller could not read otherwise.
+    pub async fn realpath_at(
+        &self,
+        capabilities: &FsCapabilities,
+        path: &Path,
+    ) -> ServiceResult<(Path, Cid)>
+    where
+        S: Send + Sync,
+    {
+        self.authorize(capabilities, path, FsAction::Read).await?;
+        let (resolved, cid) = self.root_dir.realpath_at(path).await?;
+        self.authorize(capabilities, &resolved, FsAction::Read)
+            .await?;
+
+        Ok((resolved, cid))
+    }
+
+    /// Returns the [`Cid`] of the content of the file at `path`, or `None` if the file is empty,
+    /// see [`Dir::content_cid_at`].
+    ///
+    /// Requires [`FsAction::Read`] on `path`.
+    pub async fn content_cid_at(
+        &self,
+        capabilities: &FsCapabilities,
+        path: &Path,
+    ) -> ServiceResult<Option<Cid>>
+    where
+        S: Send + Sync,
+    {
+        self.authorize(capabilities, path, FsAction::Read).await?;
+
+        Ok(self.root_dir.content_cid_at(path).await?)
+    }
+
+    /// Registers `transformer` to derive blobs from files, in place of any transformer with the
+    /// same transform ID.
+    pub fn register_transformer(&mut self, transformer: Arc<dyn Transformer>) {
+        self.transformers
+            .insert(transformer.id().to_owned(), transformer);
+    }
+
+    /// Returns the blob derived from the file with the CID `source` by the transform `transform`,
+    /// or `None` if the transform does not apply to the file, see [`filesystem::derive`].
+    ///
+    /// Derived blobs are cached in the [derived index][DerivedIndex] of the tree.
+    /// Requires [`FsAction::Read`] on the whole tree, since the source is not named by a path, and
+    /// [`FsAction::Write`] on [`DERIVED_PATH`]. Fails with [`ServiceError::UnknownTransform`] if no
+    /// transformer is registered for `transform`.
+    pub async fn derive(
+        &mut self,
+        capabilities: &FsCapabilities,
+        source: &Cid,
+        transform: &str,
+    ) -> ServiceResult<Option<DerivedBlob>>
+    where
+        S: Send + Sync + 'static,
+    {
+        let path: Path = DERIVED_PATH.parse()?;
+        self.authorize(capabilities, &Path::default(), FsAction::Read)
+            .await?;
+        self.authorize(capabilities, &path, FsAction::Write).await?;
+        self.check_writable(&path)?;
+
+        let transformer = self
+            .transformers
+            .get(transform)
+            .cloned()
+            .ok_or_else(|| ServiceError::UnknownTransform(transform.to_owned()))?;
+
+        let (root_dir, blob) = filesystem::derive(&self.root_dir, source, transformer).await?;
+        if root_dir.store().await? != self.root_dir.store().await? {
+            self.root_dir = root_dir;
+            self.record_operation("derive", &path).await?;
+        }
+
+        Ok(blob)
+    }
+
+    /// Returns the blob derived from the file with the CID `source` by the transform `transform`
+    /// if it is already cached, without deriving it otherwise.
+    ///
+    /// This does not authorize anything, and is meant for frontends like the gateway that check
+    /// the source is public themselves. Fails with [`ServiceError::UnknownTransform`] if no
+    /// transformer is registered for `transform`.
+    pub(crate) async fn get_cached_derived(
+        &self,
+        source: &Cid,
+        transform: &str,
+    ) -> ServiceResult<Option<DerivedBlob>>
+    where
+        S: Send + Sync,
+    {
+        if !self.transformers.contains_key(transform) {
+            return Err(ServiceError::UnknownTransform(transform.to_owned()));
+        }
+
+        let index = DerivedIndex::load(&self.root_dir).await?;
+        Ok(index.get_blob(source, transform).cloned())
     }
 
     /// Creates a file system builder.
@@ -47,8 +688,1061 @@ where
         FsServiceBuilder::default()
     }
 
+    /// Loads the root directory stored at the given CID, replacing the current root directory.
+    ///
+    /// The CID must match the hash function and codec of the store configuration, otherwise the
+    /// existing store was created with different settings. Replacing the root requires
+    /// [`FsAction::Manage`] on the whole tree.
+    pub async fn load_root(&mut self, capabilities: &FsCapabilities, cid: &Cid) -> ServiceResult<()>
+    where
+        S: Send + Sync + 'static,
+    {
+        self.authorize(capabilities, &Path::default(), FsAction::Manage)
+            .await?;
+        self.check_writable(&Path::default())?;
+
+        if !self.config.store.is_consistent_with(cid) {
+            return Err(ServiceError::StoreConfigMismatch(*cid));
+        }
+
+        let store = self.root_dir.get_store().clone();
+        self.root_dir = Dir::load(cid, store).await?;
+        self.record_operation("load_root", &Path::default()).await?;
+
+        Ok(())
+    }
+
+    /// Ingests the local file or directory at `local` and links it at `dest` in the file tree,
+    /// returning the new root [`Cid`].
+    ///
+    /// Missing intermediate directories in `dest` are created and an existing entity at `dest` is
+    /// replaced, unless it is or holds an immutable or append-only file. File contents are split
+    /// with the configured store chunker unless `options` asks for another one.
+    ///
+    /// Requires [`FsAction::Write`] on `dest` if something is already there and
+    /// [`FsAction::Create`] otherwise.
+    pub async fn ingest_local(
+        &mut self,
+        capabilities: &FsCapabilities,
+        local: impl AsRef<LocalPath>,
+        dest: impl TryInto<Path, Error: Into<FsError>>,
+        options: IngestOptions,
+    ) -> ServiceResult<Cid>
+    where
+        S: Send + Sync + 'static,
+    {
+        let dest = dest.try_into().map_err(Into::into)?;
+        let action = self.get_write_action(&dest).await;
+        self.authorize(capabilities, &dest, action).await?;
+        self.check_writable(&dest)?;
+        self.root_dir.check_replaceable(&dest).await?;
+
+        let store = self.root_dir.get_store().clone();
+        let options = IngestOptions {
+            chunker: options.chunker.or(Some(self.config.store.chunker)),
+            ..options
+        };
+
+        let cid = filesystem::ingest_local(store, local, options).await?;
+        filesystem::check_limits(&self.root_dir, &dest, &cid, &self.config.limits).await?;
+
+        self.root_dir = self.root_dir.link_at(&dest, cid).await?;
+        self.record_operation("ingest_local", &dest).await?;
+
+        Ok(self.root_dir.store().await?)
+    }
+
+    /// Writes the entity identified by `source` and everything under it to `local` on the local
+    /// file system.
+    ///
+    /// Requires [`FsAction::Read`] on the source path. A CID can point anywhere, so materializing
+    /// one requires [`FsAction::Read`] on the whole tree.
+    pub async fn materialize_local(
+        &self,
+        capabilities: &FsCapabilities,
+        source: impl Into<PathOrCid>,
+        local: impl AsRef<LocalPath>,
+        options: MaterializeOptions,
+    ) -> ServiceResult<MaterializeReport>
+    where
+        S: Send + Sync + 'static,
+    {
+        let source = source.into();
+        let path = match &source {
+            PathOrCid::Path(path) => path.clone(),
+            PathOrCid::Cid(_) => Path::default(),
+        };
+        self.authorize(capabilities, &path, FsAction::Read).await?;
+
+        let entity = self.get_entity(source).await?;
+        Ok(filesystem::materialize_local(entity, local, options).await?)
+    }
+
+    /// Compares the local directory at `local` with the directory at `path` and applies only the
+    /// differences in the given `direction`.
+    ///
+    /// When pushing, a missing directory at `path` is created and anything else at `path` is
+    /// replaced. If `delete_extraneous` is `true`, entries that only exist on the receiving side are
+    /// deleted. Immutable and append-only files are never replaced or deleted, see
+    /// [`filesystem::sync_local`]. The pushed file contents are split with the configured store
+    /// chunker unless `options` asks for another one.
+    ///
+    /// Pulling requires [`FsAction::Read`] on `path`. Pushing requires [`FsAction::Write`] and
+    /// [`FsAction::Create`] on `path`, plus [`FsAction::Delete`] if `delete_extraneous` is `true`.
+    pub async fn sync_local(
+        &mut self,
+        capabilities: &FsCapabilities,
+        local: impl AsRef<LocalPath>,
+        path: impl TryInto<Path, Error: Into<FsError>>,
+        direction: SyncDirection,
+        delete_extraneous: bool,
+        options: IngestOptions,
+    ) -> ServiceResult<SyncReport>
+    where
+        S: Send + Sync + 'static,
+    {
+        let path = path.try_into().map_err(Into::into)?;
+        match direction {
+            SyncDirection::Push => {
+                self.authorize(capabilities, &path, FsAction::Write).await?;
+                self.authorize(capabilities, &path, FsAction::Create)
+                    .await?;
+                if delete_extraneous {
+                    self.authorize(capabilities, &path, FsAction::Delete)
+                        .await?;
+                }
+                self.check_writable(&path)?;
+            }
+            SyncDirection::Pull => self.authorize(capabilities, &path, FsAction::Read).await?,
+        }
+
+        let dir = match direction {
+            SyncDirection::Push => match self.get_entity(PathOrCid::Path(path.clone())).await {
+                Ok(Entity::Dir(dir)) => dir,
+                // Only a missing directory, or something else in its place, starts out empty. A
+                // directory that cannot be read must not be mistaken for one.
+                Ok(entity) => {
+                    entity.check_replaceable(&path).await?;
+                    Dir::new(self.root_dir.get_store().clone())
+                }
+                Err(ServiceError::FsError(FsError::NotFound(_))) => {
+                    Dir::new(self.root_dir.get_store().clone())
+                }
+                Err(e) => return Err(e),
+            },
+            SyncDirection::Pull => self
+                .get_entity(PathOrCid::Path(path.clone()))
+                .await?
+                .as_dir()?,
+        };
+
+        let options = IngestOptions {
+            chunker: options.chunker.or(Some(self.config.store.chunker)),
+            ..options
+        };
+
+        let (dir, report) =
+            filesystem::sync_local(local, dir, direction, delete_extraneous, options).await?;
+
+        if direction == SyncDirection::Push {
+            let cid = dir.store().await?;
+            filesystem::check_limits(&self.root_dir, &path, &cid, &self.config.limits).await?;
+            self.root_dir = if path.is_empty() {
+                dir
+            } else {
+                self.root_dir.link_at(&path, cid).await?
+            };
+            self.record_operation("sync_local", &path).await?;
+        }
+
+        Ok(report)
+    }
+
+    /// Links the entity already stored at `cid` at `dest` in the file tree, returning the new root
+    /// [`Cid`].
+    ///
+    /// Missing intermediate directories in `dest` are created and an existing entity at `dest` is
+    /// replaced, unless it is or holds an immutable or append-only file.
+    /// Fails if the link would take the tree past the configured
+    /// [limits][crate::config::ZerofsLimitsConfig].
+    ///
+    /// Requires [`FsAction::Write`] on `dest` if something is already there and
+    /// [`FsAction::Create`] otherwise.
+    pub async fn link_at(
+        &mut self,
+        capabilities: &FsCapabilities,
+        dest: impl TryInto<Path, Error: Into<FsError>>,
+        cid: Cid,
+    ) -> ServiceResult<Cid>
+    where
+        S: Send + Sync + 'static,
+    {
+        let dest = dest.try_into().map_err(Into::into)?;
+        let action = self.get_write_action(&dest).await;
+        self.authorize(capabilities, &dest, action).await?;
+        self.check_writable(&dest)?;
+        self.root_dir.check_replaceable(&dest).await?;
+        filesystem::check_limits(&self.root_dir, &dest, &cid, &self.config.limits).await?;
+
+        self.root_dir = self.root_dir.link_at(&dest, cid).await?;
+        self.record_operation("link_at", &dest).await?;
+
+        Ok(self.root_dir.store().await?)
+    }
+
+    /// Links a new file at `dest` whose content is the content already stored at `content`, see
+    /// [`File::set_stored_content`], and returns the [`Cid`] of the file along with that of the new
+    /// root.
+    ///
+    /// The file replaces anything at `dest` as with [`link_at`][Self::link_at], and requires the
+    /// same capabilities. No content is uploaded, which lets clients skip sending content the
+    /// node already has.
+    pub async fn link_content_at(
+        &mut self,
+        capabilities: &FsCapabilities,
+        dest: impl TryInto<Path, Error: Into<FsError>>,
+        content: Cid,
+    ) -> ServiceResult<(Cid, Cid)>
+    where
+        S: Send + Sync + 'static,
+    {
+        let dest = dest.try_into().map_err(Into::into)?;
+        let mut file = File::new(self.root_dir.get_store().clone());
+        file.set_stored_content(content).await?;
+
+        let file_cid = file.store().await?;
+        let root_cid = self.link_at(capabilities, dest, file_cid).await?;
+
+        Ok((file_cid, root_cid))
+    }
+
+    /// Merges the tree whose root is the directory at `src` into the tree at `dest`, resolving
+    /// collisions according to `strategy`, see [`filesystem::merge_subtree_at`]. Returns the CID of
+    /// the new root along with what was merged.
+    ///
+    /// Requires [`FsAction::Read`] on the whole tree, as `src` can be any root in the store, and
+    /// the same capabilities on `dest` as [`link_at`][Self::link_at].
+    pub async fn merge_subtree_at(
+        &mut self,
+        capabilities: &FsCapabilities,
+        dest: impl TryInto<Path, Error: Into<FsError>>,
+        src: &Cid,
+        strategy: MergeStrategy,
+    ) -> ServiceResult<(Cid, MergeReport)>
+    where
+        S: Send + Sync + 'static,
+    {
+        let dest = dest.try_into().map_err(Into::into)?;
+        self.authorize(capabilities, &Path::default(), FsAction::Read)
+            .await?;
+        let action = self.get_write_action(&dest).await;
+        self.authorize(capabilities, &dest, action).await?;
+        self.check_writable(&dest)?;
+
+        let (root_dir, report) =
+            filesystem::merge_subtree_at(&self.root_dir, &dest, src, strategy).await?;
+        self.root_dir = root_dir;
+        self.record_operation("merge_subtree_at", &dest).await?;
+
+        Ok((self.root_dir.store().await?, report))
+    }
+
+    /// Creates the branch `name`, forked from the root `from`, and returns the CID of the root.
+    ///
+    /// Requires [`FsAction::Manage`] on the whole tree.
+    pub async fn create_branch(
+        &mut self,
+        capabilities: &FsCapabilities,
+        name: &str,
+        from: &Cid,
+    ) -> ServiceResult<Cid>
+    where
+        S: Send + Sync + 'static,
+    {
+        self.authorize(capabilities, &Path::default(), FsAction::Manage)
+            .await?;
+        self.check_writable(&REFS_PATH.parse()?)?;
+
+        if !self.config.store.is_consistent_with(from) {
+            return Err(ServiceError::StoreConfigMismatch(*from));
+        }
+
+        self.root_dir = filesystem::create_branch(&self.root_dir, name, *from).await?;
+        self.record_operation("create_branch", &REFS_PATH.parse()?)
+            .await?;
+
+        Ok(self.root_dir.store().await?)
+    }
+
+    /// Opens the branch `name` to make changes to it, which only touch the branch until they are
+    /// committed with [`commit_branch`][Self::commit_branch] and merged with
+    /// [`merge_branch`][Self::merge_branch].
+    ///
+    /// Requires [`FsAction::Read`] on the whole tree.
+    pub async fn open_branch(
+        &self,
+        capabilities: &FsCapabilities,
+        name: &str,
+    ) -> ServiceResult<Branch<S>>
+    where
+        S: Send + Sync + 'static,
+    {
+        self.authorize(capabilities, &Path::default(), FsAction::Read)
+            .await?;
+
+        Ok(Branch::open(&self.root_dir, name).await?)
+    }
+
+    /// Commits the changes made to `branch` to its ref, without touching the rest of the tree,
+    /// and returns the CID of the root.
+    ///
+    /// Requires [`FsAction::Write`] on the whole tree.
+    pub async fn commit_branch(
+        &mut self,
+        capabilities: &FsCapabilities,
+        branch: &mut Branch<S>,
+    ) -> ServiceResult<Cid>
+    where
+        S: Send + Sync + 'static,
+    {
+        self.authorize(capabilities, &Path::default(), FsAction::Write)
+            .await?;
+        self.check_writable(&REFS_PATH.parse()?)?;
+
+        self.root_dir = branch.commit(&self.root_dir).await?;
+        self.record_operation("commit_branch", &REFS_PATH.parse()?)
+            .await?;
+
+        Ok(self.root_dir.store().await?)
+    }
+
+    /// Folds the changes committed to the branch `name` into the file tree with a three-way merge.
+    ///
+    /// Changes made to the same entities in the tree since the branch was forked are kept, and the
+    /// changes of the branch that conflict with them are returned in the [`ConflictReport`] rather
+    /// than applied. Requires [`FsAction::Manage`] on the whole tree.
+    pub async fn merge_branch(
+        &mut self,
+        capabilities: &FsCapabilities,
+        name: &str,
+    ) -> ServiceResult<ConflictReport>
+    where
+        S: Send + Sync + 'static,
+    {
+        self.authorize(capabilities, &Path::default(), FsAction::Manage)
+            .await?;
+        self.check_writable(&Path::default())?;
+
+        let (root_dir, report) = filesystem::merge_branch(&self.root_dir, name).await?;
+        self.root_dir = root_dir;
+        self.record_operation("merge_branch", &Path::default())
+            .await?;
+
+        Ok(report)
+    }
+
+    /// Resolves the capabilities that `invoker` holds through `delegations`, including those
+    /// delegated to groups `invoker` is a member of according to the groups file of the tree.
+    pub async fn resolve_capabilities<'a>(
+        &self,
+        invoker: &str,
+        delegations: impl IntoIterator<Item = &'a FsDelegation>,
+    ) -> ServiceResult<FsCapabilities>
+    where
+        S: Send + Sync,
+    {
+        let groups = Groups::load(&self.root_dir).await?;
+        Ok(groups.resolve_capabilities(invoker, delegations))
+    }
+
+    /// Sets the members of the group with the DID `group` in the groups file of the tree, or
+    /// removes the group if `members` is `None`.
+    ///
+    /// Requires [`FsAction::Write`] on the groups file and the authority of the group itself: being
+    /// invoked by the group DID, or [`FsAction::Manage`] on the resource of the group DID. Write
+    /// access to the groups file alone would let anyone join a group that was delegated more than
+    /// they were.
+    pub async fn set_group(
+        &mut self,
+        capabilities: &FsCapabilities,
+        group: &str,
+        members: Option<Group>,
+    ) -> ServiceResult<()>
+    where
+        S: Send + Sync + 'static,
+    {
+        self.authorize(capabilities, &GROUPS_PATH.parse()?, FsAction::Write)
+            .await?;
+
+        let is_group = capabilities.get_invoker() == Some(group);
+        if !is_group && !capabilities.permits(group, &Path::default(), FsAction::Manage) {
+            return Err(FsError::from(PermissionError::CapabilityNotGranted(
+                FsResource::root(group),
+                FsAction::Manage,
+            ))
+            .into());
+        }
+
+        self.check_writable(&GROUPS_PATH.parse()?)?;
+
+        let mut groups = Groups::load(&self.root_dir).await?;
+        match members {
+            Some(members) => groups.set_group(group, members),
+            None => {
+                groups.remove_group(group);
+            }
+        }
+
+        self.root_dir = groups.store_at(&self.root_dir).await?;
+        self.record_operation("set_group", &GROUPS_PATH.parse()?)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Removes the entity at `path`.
+    ///
+    /// If the trash is enabled, the entity is moved into the trash and can be restored with
+    /// [`restore`][Self::restore] until it is purged. Entities that have been in the trash for
+    /// longer than the configured retention are purged along the way, at most once per purge
+    /// interval. Immutable and append-only files cannot be removed.
+    ///
+    /// A directory that is not empty is only removed if `options` asks for a recursive remove, and
+    /// then only if no file under it is protected. See [`filesystem::check_removable`].
+    ///
+    /// Requires [`FsAction::Delete`] on `path`.
+    pub async fn remove_at(
+        &mut self,
+        capabilities: &FsCapabilities,
+        path: impl TryInto<Path, Error: Into<FsError>>,
+        options: RemoveOptions,
+    ) -> ServiceResult<()>
+    where
+        S: Send + Sync + 'static,
+    {
+        let path = path.try_into().map_err(Into::into)?.canonicalize()?;
+        self.authorize(capabilities, &path, FsAction::Delete)
+            .await?;
+        self.check_writable(&path)?;
+        filesystem::check_removable(&self.root_dir, &path, &options).await?;
+        self.root_dir.check_replaceable(&path).await?;
+
+        if !self.config.trash.enabled {
+            self.root_dir = self.root_dir.unlink_at(&path).await?.0;
+            return self.record_operation("remove_at", &path).await;
+        }
+
+        self.root_dir = filesystem::trash_at(&self.root_dir, &path).await?.0;
+        self.purge_expired_trash().await?;
+        self.record_operation("remove_at", &path).await
+    }
+
+    /// Renames the entity at `path` to `name`, within the same directory, see [`Dir::rename_at`].
+    ///
+    /// A rename that only changes the case of the name keeps the entry under its new case.
+    /// Immutable and append-only files, and directories holding them, cannot be renamed.
+    ///
+    /// Requires [`FsAction::Delete`] on `path` and [`FsAction::Create`] on the renamed path.
+    pub async fn rename_at(
+        &mut self,
+        capabilities: &FsCapabilities,
+        path: impl TryInto<Path, Error: Into<FsError>>,
+        name: PathSegment,
+    ) -> ServiceResult<()>
+    where
+        S: Send + Sync + 'static,
+    {
+        let path = path.try_into().map_err(Into::into)?.canonicalize()?;
+        let mut new_path = path.clone();
+        new_path.pop();
+        new_path.push(name.clone());
+
+        self.authorize(capabilities, &path, FsAction::Delete)
+            .await?;
+        self.authorize(capabilities, &new_path, FsAction::Create)
+            .await?;
+        self.check_writable(&path)?;
+        self.root_dir.check_replaceable(&path).await?;
+
+        let limit = self.config.limits.max_name_length;
+        if name.as_str().len() > limit {
+            return Err(FsError::NameTooLong(new_path, limit).into());
+        }
+
+        self.root_dir = self.root_dir.rename_at(&path, name).await?;
+        self.record_operation("rename_at", &new_path).await
+    }
+
+    /// Sets the access time and the modification time of the entity at `path` to the ones given,
+    /// leaving the others as they are, see [`Dir::set_times_at`]. The times of immutable and
+    /// append-only files, and of directories holding them, cannot be changed.
+    ///
+    /// Requires [`FsAction::Write`] on `path`.
+    pub async fn set_times_at(
+        &mut self,
+        capabilities: &FsCapabilities,
+        path: impl TryInto<Path, Error: Into<FsError>>,
+        accessed_at: Option<DateTime<Utc>>,
+        modified_at: Option<DateTime<Utc>>,
+    ) -> ServiceResult<()>
+    where
+        S: Send + Sync + 'static,
+    {
+        let path = path.try_into().map_err(Into::into)?.canonicalize()?;
+        self.authorize(capabilities, &path, FsAction::Write).await?;
+        self.check_writable(&path)?;
+        self.root_dir.check_replaceable(&path).await?;
+
+        self.root_dir = self
+            .root_dir
+            .set_times_at(&path, accessed_at, modified_at)
+            .await?;
+        self.record_operation("set_times_at", &path).await
+    }
+
+    /// Moves the entity most recently removed from `path` out of the trash and back to `path`.
+    ///
+    /// Requires [`FsAction::Create`] on `path`.
+    pub async fn restore(
+        &mut self,
+        capabilities: &FsCapabilities,
+        path: impl TryInto<Path, Error: Into<FsError>>,
+    ) -> ServiceResult<()>
+    where
+        S: Send + Sync + 'static,
+    {
+        let path = path.try_into().map_err(Into::into)?.canonicalize()?;
+        self.authorize(capabilities, &path, FsAction::Create)
+            .await?;
+        self.check_writable(&path)?;
+
+        self.root_dir = filesystem::restore_from_trash(&self.root_dir, &path).await?;
+        self.record_operation("restore", &path).await?;
+
+        Ok(())
+    }
+
+    /// Permanently removes the entities that were moved into the trash before `older_than` and
+    /// returns how many were removed.
+    ///
+    /// Requires [`FsAction::Delete`] on the trash.
+    pub async fn purge(
+        &mut self,
+        capabilities: &FsCapabilities,
+        older_than: DateTime<Utc>,
+    ) -> ServiceResult<usize>
+    where
+        S: Send + Sync + 'static,
+    {
+        self.authorize(capabilities, &TRASH_PATH.parse()?, FsAction::Delete)
+            .await?;
+        self.check_writable(&TRASH_PATH.parse()?)?;
+
+        let (root_dir, purged) = filesystem::purge_trash(&self.root_dir, older_than).await?;
+        self.root_dir = root_dir;
+        self.record_operation("purge", &TRASH_PATH.parse()?).await?;
+
+        Ok(purged)
+    }
+
+    /// Returns the access control list of the entity at `path`.
+    ///
+    /// Requires [`FsAction::Read`] on `path`.
+    pub async fn get_acl_at(
+        &self,
+        capabilities: &FsCapabilities,
+        path: impl TryInto<Path, Error: Into<FsError>>,
+    ) -> ServiceResult<Acl>
+    where
+        S: Send + Sync,
+    {
+        let path = path.try_into().map_err(Into::into)?;
+        self.authorize(capabilities, &path, FsAction::Read).await?;
+
+        Ok(self.root_dir.get_acl_at(&path).await?)
+    }
+
+    /// Replaces the access control list of the entity at `path`.
+    ///
+    /// Requires [`FsAction::Manage`] on `path`.
+    pub async fn set_acl_at(
+        &mut self,
+        capabilities: &FsCapabilities,
+        path: impl TryInto<Path, Error: Into<FsError>>,
+        acl: Acl,
+    ) -> ServiceResult<()>
+    where
+        S: Send + Sync + 'static,
+    {
+        let path = path.try_into().map_err(Into::into)?;
+        self.authorize(capabilities, &path, FsAction::Manage)
+            .await?;
+        self.check_writable(&path)?;
+
+        self.root_dir = self.root_dir.set_acl_at(&path, acl).await?;
+        self.record_operation("set_acl_at", &path).await?;
+
+        Ok(())
+    }
+
+    /// Returns the grant of the content key of the entity at `path` to the invoker holding
+    /// `capabilities` or, failing that, the grant on the closest directory above it, along with the
+    /// path of the entity the grant is on, see [`Dir::find_key_grant_at`].
+    ///
+    /// The key of the entity at `path` is derived from the granted key with the key epochs from
+    /// [`get_key_epochs_at`][Self::get_key_epochs_at].
+    ///
+    /// Requires [`FsAction::Read`] on `path`.
+    pub async fn get_key_grant_at(
+        &self,
+        capabilities: &FsCapabilities,
+        path: impl TryInto<Path, Error: Into<FsError>>,
+    ) -> ServiceResult<Option<(Path, KeyGrant)>>
+    where
+        S: Send + Sync,
+    {
+        let path = path.try_into().map_err(Into::into)?;
+        self.authorize(capabilities, &path, FsAction::Read).await?;
+
+        let Some(did) = capabilities.get_invoker() else {
+            return Ok(None);
+        };
+
+        Ok(self.root_dir.find_key_grant_at(&path, did).await?)
+    }
+
+    /// Returns a proof that the entity at `path` is included under the current root, see
+    /// [`Dir::prove_at`].
+    ///
+    /// Requires [`FsAction::Read`] on `path`.
+    pub async fn prove_at(
+        &self,
+        capabilities: &FsCapabilities,
+        path: impl TryInto<Path, Error: Into<FsError>>,
+    ) -> ServiceResult<InclusionProof>
+    where
+        S: Send + Sync,
+    {
+        let path = path.try_into().map_err(Into::into)?;
+        self.authorize(capabilities, &path, FsAction::Read).await?;
+
+        Ok(self.root_dir.prove_at(&path).await?)
+    }
+
+    /// Returns the key epochs of the entities along `path`, see [`Dir::get_key_epochs_at`].
+    ///
+    /// Requires [`FsAction::Read`] on `path`.
+    pub async fn get_key_epochs_at(
+        &self,
+        capabilities: &FsCapabilities,
+        path: impl TryInto<Path, Error: Into<FsError>>,
+    ) -> ServiceResult<Vec<u32>>
+    where
+        S: Send + Sync,
+    {
+        let path = path.try_into().map_err(Into::into)?;
+        self.authorize(capabilities, &path, FsAction::Read).await?;
+
+        Ok(self.root_dir.get_key_epochs_at(&path).await?)
+    }
+
+    /// Grants the content key of the entity at `path` to `recipient`, wrapped to its public key by
+    /// the caller, in place of any grant it had.
+    ///
+    /// The grant is recorded with the invoker holding `capabilities` as its granter. This does not
+    /// give `recipient` access to the entity itself, which is still up to its capabilities and the
+    /// ACLs along `path`.
+    ///
+    /// Requires [`FsAction::Manage`] on `path`.
+    pub async fn grant_key_at(
+        &mut self,
+        capabilities: &FsCapabilities,
+        path: impl TryInto<Path, Error: Into<FsError>>,
+        recipient: impl Into<String>,
+        mut grant: KeyGrant,
+    ) -> ServiceResult<()>
+    where
+        S: Send + Sync + 'static,
+    {
+        let path = path.try_into().map_err(Into::into)?;
+        self.authorize(capabilities, &path, FsAction::Manage)
+            .await?;
+        self.check_writable(&path)?;
+
+        grant.granted_by = capabilities.get_invoker().map(ToOwned::to_owned);
+
+        let mut grants = self.root_dir.get_key_grants_at(&path).await?;
+        grants.grant(recipient, grant);
+
+        self.root_dir = self.root_dir.set_key_grants_at(&path, grants).await?;
+        self.record_operation("grant_key_at", &path).await
+    }
+
+    /// Removes the grant of the content key of the entity at `path` to `recipient`.
+    ///
+    /// Like any revocation of a key that was shared, this only stops the recipient from fetching the
+    /// wrapped key from now on. Content it could decrypt before stays readable to it until it is
+    /// encrypted again with a new key.
+    ///
+    /// Requires [`FsAction::Manage`] on `path`.
+    pub async fn revoke_key_at(
+        &mut self,
+        capabilities: &FsCapabilities,
+        path: impl TryInto<Path, Error: Into<FsError>>,
+        recipient: &str,
+    ) -> ServiceResult<()>
+    where
+        S: Send + Sync + 'static,
+    {
+        let path = path.try_into().map_err(Into::into)?;
+        self.authorize(capabilities, &path, FsAction::Manage)
+            .await?;
+        self.check_writable(&path)?;
+
+        let mut grants = self.root_dir.get_key_grants_at(&path).await?;
+        if grants.revoke(recipient).is_none() {
+            return Ok(());
+        }
+
+        self.root_dir = self.root_dir.set_key_grants_at(&path, grants).await?;
+        self.record_operation("revoke_key_at", &path).await
+    }
+
+    /// Rotates the content key of the entity at `path` and returns the DIDs whose grants in its
+    /// subtree were dropped, see [`Dir::rotate_key_at`].
+    ///
+    /// Requires [`FsAction::Manage`] on `path`.
+    pub async fn rotate_key_at(
+        &mut self,
+        capabilities: &FsCapabilities,
+        path: impl TryInto<Path, Error: Into<FsError>>,
+    ) -> ServiceResult<BTreeSet<String>>
+    where
+        S: Send + Sync + 'static,
+    {
+        let path = path.try_into().map_err(Into::into)?;
+        self.authorize(capabilities, &path, FsAction::Manage)
+            .await?;
+        self.check_writable(&path)?;
+
+        let (root, revoked) = self.root_dir.rotate_key_at(&path).await?;
+        self.root_dir = root;
+        self.record_operation("rotate_key_at", &path).await?;
+
+        Ok(revoked)
+    }
+
+    /// Returns the attributes of the entity at `path`.
+    ///
+    /// Requires [`FsAction::Read`] on `path`.
+    pub async fn get_attributes_at(
+        &self,
+        capabilities: &FsCapabilities,
+        path: impl TryInto<Path, Error: Into<FsError>>,
+    ) -> ServiceResult<EntityAttributes>
+    where
+        S: Send + Sync,
+    {
+        let path = path.try_into().map_err(Into::into)?;
+        self.authorize(capabilities, &path, FsAction::Read).await?;
+
+        Ok(self.root_dir.get_attributes_at(&path).await?)
+    }
+
+    /// Replaces the attributes of the file at `path`.
+    ///
+    /// Requires [`FsAction::Write`] on `path` to add attributes, and [`FsAction::Manage`] to clear
+    /// any, so a file made immutable or append-only stays that way for everyone who cannot manage
+    /// it.
+    pub async fn set_attributes_at(
+        &mut self,
+        capabilities: &FsCapabilities,
+        path: impl TryInto<Path, Error: Into<FsError>>,
+        attributes: EntityAttributes,
+    ) -> ServiceResult<()>
+    where
+        S: Send + Sync + 'static,
+    {
+        let path = path.try_into().map_err(Into::into)?;
+        let current = self.root_dir.get_attributes_at(&path).await?;
+        let action = if attributes.contains(current) {
+            FsAction::Write
+        } else {
+            FsAction::Manage
+        };
+
+        self.authorize(capabilities, &path, action).await?;
+        self.check_writable(&path)?;
+
+        self.root_dir = self.root_dir.set_attributes_at(&path, attributes).await?;
+        self.record_operation("set_attributes_at", &path).await?;
+
+        Ok(())
+    }
+
+    /// Returns the action needed to write an entity at `path`: [`FsAction::Write`] if something is
+    /// already there and [`FsAction::Create`] otherwise.
+    pub(crate) async fn get_write_action(&self, path: &Path) -> FsAction
+    where
+        S: Send + Sync,
+    {
+        match self.get_entity(PathOrCid::Path(path.clone())).await {
+            Ok(_) => FsAction::Write,
+            Err(_) => FsAction::Create,
+        }
+    }
+
+    /// Checks that `action` on `path` in this service's tree is allowed, either by `capabilities`
+    /// or, failing that, by the access control lists along `path` for the invoker holding
+    /// `capabilities`.
+    pub(crate) async fn authorize(
+        &self,
+        capabilities: &FsCapabilities,
+        path: &Path,
+        action: FsAction,
+    ) -> ServiceResult<()>
+    where
+        S: Send + Sync,
+    {
+        self.last_activity
+            .store(Utc::now().timestamp_millis(), Ordering::Relaxed);
+
+        let root = self.config.network.id.to_string();
+        let Err(err) = capabilities.check(&root, path, action) else {
+            return Ok(());
+        };
+
+        if let Some(invoker) = capabilities.get_invoker() {
+            let path = path.canonicalize()?;
+            if self.root_dir.acl_permits(&path, invoker, action).await? {
+                return Ok(());
+            }
+        }
+
+        Err(err.into())
+    }
+
+    /// Commits the current root to the change feed after `operation` on `path`, records it in the
+    /// journal as well if the service is offline, and brings the search index up to date. Nothing is
+    /// recorded if the operation did not change the tree.
+    pub(crate) async fn record_operation(
+        &mut self,
+        operation: &str,
+        path: &Path,
+    ) -> ServiceResult<()>
+    where
+        S: Send + Sync + 'static,
+    {
+        let root = self.root_dir.store().await?;
+        self.changes.commit(operation, path.clone(), root).await?;
+        self.record_commit(operation, path, root);
+
+        if let Some(search) = &mut self.search {
+            search.update(&self.root_dir).await?;
+        }
+
+        if let Some(journal) = &mut self.journal {
+            if journal.get_head() != &root {
+                journal.record(operation, path.clone(), root);
+            }
+        }
+
+        self.update_refcounts().await
+    }
+
+    /// Counts the references to the blocks under the roots in the [`RootRegistry`], if they are
+    /// counted, and notes the blocks that became unreferenced.
+    ///
+    /// The first update after the service starts counts the whole tree. Later ones only visit the
+    /// blocks that changed.
+    pub(crate) async fn update_refcounts(&mut self) -> ServiceResult<()>
+    where
+        S: Send + Sync + 'static,
+    {
+        let Some(refcounts) = &mut self.refcounts else {
+            return Ok(());
+        };
+
+        let roots = self.roots.collect(&self.root_dir).await?;
+
+        let delta = refcounts
+            .set_roots(roots, self.root_dir.get_store())
+            .await?;
+        for cid in &delta.referenced {
+            self.unreferenced.remove(cid);
+        }
+        self.unreferenced.extend(delta.unreferenced);
+
+        Ok(())
+    }
+
+    /// Returns the blocks that became unreferenced since they were last taken, for the caller to
+    /// remove from the store, or `None` if references are not counted.
+    ///
+    /// Blocks are not removed by the service itself, as [`IpldStore`] has no way to remove them;
+    /// stores that can, like [`DiskStore`][filesystem::DiskStore], are given them by whoever set
+    /// the store up.
+    pub fn take_unreferenced_blocks(&mut self) -> Option<BTreeSet<Cid>> {
+        self.refcounts.as_ref()?;
+        Some(std::mem::take(&mut self.unreferenced))
+    }
+
+    /// Registers `source` to list more roots whose trees are kept, in place of any source with the
+    /// same ID.
+    pub async fn register_root_source(
+        &mut self,
+        source: Arc<dyn RootSource<S>>,
+    ) -> ServiceResult<()>
+    where
+        S: Send + Sync + 'static,
+    {
+        self.roots.register_source(source);
+        self.update_refcounts().await
+    }
+
+    /// Unregisters the root source with ID `id`. The trees of its roots are not kept anymore,
+    /// unless something else keeps them.
+    pub async fn unregister_root_source(&mut self, id: &str) -> ServiceResult<()>
+    where
+        S: Send + Sync + 'static,
+    {
+        self.roots.unregister_source(id);
+        self.update_refcounts().await
+    }
+
+    /// Pins `cid` for `owner`, so its tree is kept until `owner` unpins it.
+    pub async fn pin_root(&mut self, owner: &str, cid: Cid) -> ServiceResult<()>
+    where
+        S: Send + Sync + 'static,
+    {
+        self.roots.pin(owner, cid);
+        self.update_refcounts().await
+    }
+
+    /// Unpins `cid` for `owner`. Returns `false` if `owner` had not pinned it.
+    pub async fn unpin_root(&mut self, owner: &str, cid: &Cid) -> ServiceResult<bool>
+    where
+        S: Send + Sync + 'static,
+    {
+        let unpinned = self.roots.unpin(owner, cid);
+        self.update_refcounts().await?;
+        Ok(unpinned)
+    }
+
+    /// Returns every root whose tree is kept: the current root, the roots of its snapshots, refs
+    /// and derived blobs, and the roots of the registered sources and pins.
+    pub async fn get_gc_roots(&self) -> ServiceResult<BTreeSet<Cid>>
+    where
+        S: Send + Sync + 'static,
+    {
+        Ok(self.roots.collect(&self.root_dir).await?)
+    }
+
+    /// Returns the number of references to the block of `cid`, or `None` if references are not
+    /// counted.
+    pub fn get_refcount(&self, cid: &Cid) -> Option<u64> {
+        self.refcounts
+            .as_ref()
+            .map(|refcounts| refcounts.get_count(cid))
+    }
+
+    /// Refuses operations that change the file tree with [`FsError::StoreFull`] while the disks of
+    /// the store, as seen through `disk_space`, are over their high watermark. Reads are still
+    /// served.
+    ///
+    /// The receiver typically comes from
+    /// [`DiskStore::subscribe_disk_space`][filesystem::DiskStore::subscribe_disk_space].
+    pub fn watch_disk_space(&mut self, disk_space: watch::Receiver<DiskSpaceStatus>) {
+        self.disk_space = Some(disk_space);
+    }
+
+    /// Fails with [`FsError::ReadOnlyFilesystem`] if the file tree is read-only, or with
+    /// [`FsError::StoreFull`] if the disks of the store are full.
+    pub(crate) fn check_writable(&self, path: &Path) -> ServiceResult<()> {
+        if self.is_read_only() {
+            return Err(FsError::ReadOnlyFilesystem(path.clone()).into());
+        }
+
+        if let Some(disk_space) = &self.disk_space {
+            let status = disk_space.borrow();
+            if status.level == DiskSpaceLevel::Full {
+                let volumes = status
+                    .volumes
+                    .iter()
+                    .map(|volume| {
+                        format!(
+                            "{} has {} bytes left",
+                            volume.path.display(),
+                            volume.available
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                return Err(FsError::StoreFull(volumes.join(", ")).into());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Purges the entities that have been in the trash for longer than the configured retention,
+    /// unless the last automatic purge was less than a purge interval ago.
+    async fn purge_expired_trash(&mut self) -> ServiceResult<()>
+    where
+        S: Send + Sync + 'static,
+    {
+        let Some(retention) = self.config.trash.retention else {
+            return Ok(());
+        };
+
+        let now = filesystem::current_time();
+        let interval = Duration::seconds(self.config.trash.purge_interval as i64);
+        if self
+            .last_trash_purge
+            .is_some_and(|last| now - last < interval)
+        {
+            return Ok(());
+        }
+
+        let older_than = now - Duration::seconds(retention as i64);
+        self.root_dir = filesystem::purge_trash(&self.root_dir, older_than).await?.0;
+        self.last_trash_purge = Some(now);
+
+        Ok(())
+    }
+
+    /// Gets the entity identified by `source`.
+    async fn get_entity(&self, source: PathOrCid) -> ServiceResult<Entity<S>>
+    where
+        S: Send + Sync,
+    {
+        match source {
+            PathOrCid::Path(path) if path.is_empty() => Ok(Entity::Dir(self.root_dir.clone())),
+            PathOrCid::Path(path) => match self.root_dir.trace_entity(&path).await? {
+                TraceResult::Found { entity, .. } => Ok(entity),
+                _ => Err(FsError::NotFound(path).into()),
+            },
+            PathOrCid::Cid(cid) => Ok(Entity::load(&cid, self.root_dir.get_store().clone()).await?),
+        }
+    }
+
     /// Starts the file system service.
     pub async fn start(&self) -> ServiceResult<()> {
         unimplemented!()
     }
 }
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl From<Path> for PathOrCid {
+    fn from(path: Path) -> Self {
+        PathOrCid::Path(path)
+    }
+}
+
+impl From<Cid> for PathOrCid {
+    fn from(cid: Cid) -> Self {
+        PathOrCid::Cid(cid)
+    }
+}
@@ -1,6 +1,21 @@
+use std::{future::Future, sync::Arc, time::Duration};
+
+use chrono::{DateTime, Utc};
 use zeroutils_store::IpldStore;
 
-use crate::filesystem::Dir;
+use crate::filesystem::{with_clock, Dir, FixedClock, FsResult};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The number of times [`FsStateMachine::apply_retrying`] tries an operation that keeps failing
+/// with retryable errors.
+pub const MAX_APPLY_ATTEMPTS: u32 = 5;
+
+/// How long [`FsStateMachine::apply_retrying`] waits before trying an operation again the first
+/// time. The wait doubles with each attempt.
+pub const APPLY_RETRY_BACKOFF: Duration = Duration::from_millis(50);
 
 //--------------------------------------------------------------------------------------------------
 // Types
@@ -11,5 +26,150 @@ pub struct FsStateMachine<S>
 where
     S: IpldStore,
 {
-    _root: Dir<S>,
+    root: Dir<S>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl<S> FsStateMachine<S>
+where
+    S: IpldStore,
+{
+    /// Creates a state machine starting from `root`.
+    pub fn new(root: Dir<S>) -> Self {
+        Self { root }
+    }
+
+    /// Returns the current root of the file tree.
+    pub fn get_root(&self) -> &Dir<S> {
+        &self.root
+    }
+
+    /// Applies an operation proposed at `proposed_at` to the file tree.
+    ///
+    /// The operation takes the current root and returns the updated one. Every timestamp it records
+    /// is `proposed_at`, the time the leader proposed the operation at, so that all replicas
+    /// applying it end up with the same root.
+    pub async fn apply<F, Fut>(&mut self, proposed_at: DateTime<Utc>, operation: F) -> FsResult<()>
+    where
+        F: FnOnce(Dir<S>) -> Fut,
+        Fut: Future<Output = FsResult<Dir<S>>>,
+    {
+        let clock = Arc::new(FixedClock::new(proposed_at));
+        self.root = with_clock(clock, operation(self.root.clone())).await?;
+
+        Ok(())
+    }
+
+    /// Applies an operation like [`apply`][Self::apply], trying it again while it fails with a
+    /// [retryable][crate::filesystem::FsError::is_retryable] error, up to [`MAX_APPLY_ATTEMPTS`]
+    /// times.
+    ///
+    /// An operation that fails validation fails the same way on every replica, so its error is
+    /// returned right away and the entry can be skipped. A transient error, like a store timeout,
+    /// only happened on this replica, which must not move past the entry without applying it.
+    pub async fn apply_retrying<F, Fut>(
+        &mut self,
+        proposed_at: DateTime<Utc>,
+        operation: F,
+    ) -> FsResult<()>
+    where
+        F: Fn(Dir<S>) -> Fut,
+        Fut: Future<Output = FsResult<Dir<S>>>,
+    {
+        let mut backoff = APPLY_RETRY_BACKOFF;
+        let mut attempt = 1;
+        loop {
+            match self.apply(proposed_at, &operation).await {
+                Err(e) if e.is_retryable() && attempt < MAX_APPLY_ATTEMPTS => {
+                    tracing::warn!("applying operation failed, attempt {}: {}", attempt, e);
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io,
+        sync::atomic::{AtomicU32, Ordering},
+    };
+
+    use zeroutils_store::{MemoryStore, Storable};
+
+    use crate::filesystem::{File, FsError, Path};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_replicas_apply_operations_identically() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let root = Dir::new(store.clone());
+        let mut replicas = [FsStateMachine::new(root.clone()), FsStateMachine::new(root)];
+
+        let proposed_at = Utc::now();
+        let path: Path = "public/notes".parse()?;
+        for replica in replicas.iter_mut() {
+            let path = &path;
+            replica
+                .apply(proposed_at, |root| async move {
+                    let file = File::new(root.get_store().clone());
+                    root.link_at(path, file.store().await?).await
+                })
+                .await?;
+        }
+
+        let [first, second] = replicas;
+        assert_eq!(
+            first.get_root().store().await?,
+            second.get_root().store().await?
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_apply_retries_transient_errors_only() -> anyhow::Result<()> {
+        let mut machine = FsStateMachine::new(Dir::new(MemoryStore::default()));
+
+        // A store timeout is tried again until the operation goes through.
+        let attempts = AtomicU32::new(0);
+        machine
+            .apply_retrying(Utc::now(), |root| {
+                let attempt = attempts.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    if attempt < 2 {
+                        return Err(io::Error::from(io::ErrorKind::TimedOut).into());
+                    }
+
+                    Ok(root)
+                }
+            })
+            .await?;
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+
+        // A validation failure is not.
+        let attempts = AtomicU32::new(0);
+        let result = machine
+            .apply_retrying(Utc::now(), |_| {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                async { Err(FsError::EmptyPath) }
+            })
+            .await;
+        assert!(matches!(result, Err(FsError::EmptyPath)));
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+
+        Ok(())
+    }
 }
@@ -0,0 +1,141 @@
+use axum::{extract::State, Extension, Json};
+use zeroutils_store::IpldStore;
+
+use crate::{
+    filesystem::FsCapabilities,
+    service::{
+        AclResponse, ErrorContext, GetAclAt, Problem, ServiceResultExt, SetAclAt, SharedService,
+    },
+};
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// This endpoint handler replaces the access control list of the entity at a specific path, and
+/// returns the new one.
+///
+/// Callers without [`FsAction::Manage`][crate::filesystem::FsAction::Manage] on the path are
+/// rejected with `403 Forbidden`.
+pub(crate) async fn set_acl_at<S>(
+    State(service): State<SharedService<S>>,
+    capabilities: Option<Extension<FsCapabilities>>,
+    Json(body): Json<SetAclAt>,
+) -> Result<Json<AclResponse>, Problem>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    let Extension(capabilities) = capabilities.ok_or_else(Problem::unauthenticated)?;
+    let SetAclAt { path, acl } = body;
+
+    service
+        .lock()
+        .await
+        .set_acl_at(&capabilities, path.clone(), acl.clone())
+        .await
+        .context(|| ErrorContext::new("set_acl_at").path(&path))?;
+
+    Ok(Json(AclResponse { acl }))
+}
+
+/// This endpoint handler returns the access control list of the entity at a specific path.
+pub(crate) async fn get_acl_at<S>(
+    State(service): State<SharedService<S>>,
+    capabilities: Option<Extension<FsCapabilities>>,
+    Json(body): Json<GetAclAt>,
+) -> Result<Json<AclResponse>, Problem>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    let Extension(capabilities) = capabilities.ok_or_else(Problem::unauthenticated)?;
+
+    let acl = service
+        .lock()
+        .await
+        .get_acl_at(&capabilities, body.path.clone())
+        .await
+        .context(|| ErrorContext::new("get_acl_at").path(&body.path))?;
+
+    Ok(Json(AclResponse { acl }))
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use tokio::sync::Mutex;
+    use zeroutils_store::{MemoryStore, Storable};
+
+    use crate::{
+        config::ZerofsConfig,
+        filesystem::{Acl, Dir, ErrorCode, File, FsAction, FsCapability},
+        service::FsService,
+    };
+
+    use super::*;
+
+    const ALICE: &str = "did:wk:z6MkoVs2h6TnfyY8fx2ZqpREWSLS8rBDQmGpyXgFpg63CSUb";
+
+    fn capabilities(action: FsAction) -> anyhow::Result<FsCapabilities> {
+        Ok([FsCapability {
+            resource: "zerofs://*".parse()?,
+            action,
+        }]
+        .iter()
+        .cloned()
+        .collect())
+    }
+
+    #[tokio::test]
+    async fn test_acl_at() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let file = File::new(store.clone()).store().await?;
+        let root = Dir::new(store).link_at(&"docs/a".parse()?, file).await?;
+        let service = Arc::new(Mutex::new(FsService::new(
+            root,
+            Arc::new(ZerofsConfig::default()),
+        )));
+
+        let mut acl = Acl::new();
+        acl.allow(ALICE, FsAction::Read);
+
+        let set = |capabilities: Option<FsCapabilities>| {
+            set_acl_at(
+                State(Arc::clone(&service)),
+                capabilities.map(Extension),
+                Json(SetAclAt::new("docs".parse().unwrap(), acl.clone())),
+            )
+        };
+        let get = |capabilities: Option<FsCapabilities>| {
+            get_acl_at(
+                State(Arc::clone(&service)),
+                capabilities.map(Extension),
+                Json(GetAclAt::new("docs".parse().unwrap())),
+            )
+        };
+
+        // Replacing an ACL takes the manage action, and reading it the read action.
+        let Json(response) = set(Some(capabilities(FsAction::Manage)?))
+            .await
+            .map_err(|problem| anyhow::anyhow!(problem.detail))?;
+        assert_eq!(response.acl, acl);
+
+        let Json(response) = get(Some(capabilities(FsAction::Read)?))
+            .await
+            .map_err(|problem| anyhow::anyhow!(problem.detail))?;
+        assert_eq!(response.acl, acl);
+
+        let problem = set(Some(capabilities(FsAction::Write)?)).await.unwrap_err();
+        assert_eq!(problem.code, ErrorCode::PermissionDenied);
+
+        // Requests without capabilities are rejected.
+        let problem = get(None).await.unwrap_err();
+        assert_eq!(problem.code, ErrorCode::Unauthenticated);
+
+        Ok(())
+    }
+}
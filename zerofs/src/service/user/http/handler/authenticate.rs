@@ -4,8 +4,8 @@ use axum::http::{HeaderMap, StatusCode};
 // Constants
 //--------------------------------------------------------------------------------------------------
 
-const AUTHN_USER_TOKEN: &str = "x-authn-user-token";
-const AUTHN_USER_TOKEN_MAP: &str = "x-authn-user-token-map";
+pub(crate) const AUTHN_USER_TOKEN: &str = "x-authn-user-token";
+pub(crate) const AUTHN_USER_TOKEN_MAP: &str = "x-authn-user-token-map";
 
 //--------------------------------------------------------------------------------------------------
 // Functions
@@ -0,0 +1,54 @@
+use axum::{
+    extract::{Query, State},
+    Extension, Json,
+};
+use serde::Deserialize;
+use zeroutils_store::IpldStore;
+
+use crate::{
+    filesystem::{ChangeSet, FsCapabilities},
+    service::{ErrorContext, Problem, ServiceResultExt, SharedService},
+};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The query parameters of a changes request.
+#[derive(Debug, Deserialize)]
+pub(crate) struct ChangesQuery {
+    /// The cursor of the last changes seen, or `0` to get every change.
+    #[serde(default)]
+    cursor: u64,
+
+    /// The maximum number of commits to return.
+    limit: Option<usize>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// This endpoint handler returns what changed in the file tree since a cursor, so a client that was
+/// offline can catch up by passing the cursor of the last changes it got.
+///
+/// A cursor past the head of the change feed is rejected with `400 Bad Request`.
+pub(crate) async fn changes<S>(
+    State(service): State<SharedService<S>>,
+    capabilities: Option<Extension<FsCapabilities>>,
+    Query(query): Query<ChangesQuery>,
+) -> Result<Json<ChangeSet>, Problem>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    let Extension(capabilities) = capabilities.ok_or_else(Problem::unauthenticated)?;
+
+    let changes = service
+        .lock()
+        .await
+        .changes_since(&capabilities, query.cursor, query.limit)
+        .await
+        .context(|| ErrorContext::new("changes_since"))?;
+
+    Ok(Json(changes))
+}
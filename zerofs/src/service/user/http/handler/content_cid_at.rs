@@ -0,0 +1,51 @@
+use axum::{
+    extract::{Query, State},
+    Extension, Json,
+};
+use serde::Deserialize;
+use zeroutils_store::IpldStore;
+
+use crate::{
+    filesystem::{FsCapabilities, Path},
+    service::{ContentCidResponse, ErrorContext, Problem, ServiceResultExt, SharedService},
+};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The query parameters of a content_cid_at request.
+#[derive(Debug, Deserialize)]
+pub(crate) struct ContentCidQuery {
+    /// The path to the file.
+    path: String,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// This endpoint handler returns the CID of the content of a file, as opposed to that of the file
+/// node, so a client can skip uploading content the node already has.
+///
+/// Paths that are not files are rejected with `400 Bad Request`.
+pub(crate) async fn content_cid_at<S>(
+    State(service): State<SharedService<S>>,
+    capabilities: Option<Extension<FsCapabilities>>,
+    Query(query): Query<ContentCidQuery>,
+) -> Result<Json<ContentCidResponse>, Problem>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    let Extension(capabilities) = capabilities.ok_or_else(Problem::unauthenticated)?;
+    let path: Path = query.path.parse()?;
+
+    let content = service
+        .lock()
+        .await
+        .content_cid_at(&capabilities, &path)
+        .await
+        .context(|| ErrorContext::new("content_cid_at").path(&path))?;
+
+    Ok(Json(ContentCidResponse { content }))
+}
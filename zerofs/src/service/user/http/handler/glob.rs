@@ -0,0 +1,57 @@
+use axum::{
+    extract::{Query, State},
+    Extension, Json,
+};
+use serde::Deserialize;
+use zeroutils_store::IpldStore;
+
+use crate::{
+    filesystem::{FsCapabilities, GlobPattern},
+    service::{ErrorContext, GlobMatch, GlobResponse, Problem, ServiceResultExt, SharedService},
+};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The query parameters of a glob request.
+#[derive(Debug, Deserialize)]
+pub(crate) struct GlobQuery {
+    /// The pattern to match paths against.
+    pattern: String,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// This endpoint handler returns the paths that match a glob pattern, out of those the caller can
+/// read, with the CIDs of the entities at them.
+///
+/// Invalid patterns, and patterns that would walk too much of the tree, are rejected with
+/// `400 Bad Request`.
+pub(crate) async fn glob<S>(
+    State(service): State<SharedService<S>>,
+    capabilities: Option<Extension<FsCapabilities>>,
+    Query(query): Query<GlobQuery>,
+) -> Result<Json<GlobResponse>, Problem>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    let Extension(capabilities) = capabilities.ok_or_else(Problem::unauthenticated)?;
+    let pattern: GlobPattern = query.pattern.parse()?;
+
+    let matches = service
+        .lock()
+        .await
+        .glob_at(&capabilities, &pattern)
+        .await
+        .context(|| ErrorContext::new("glob_at"))?;
+
+    let matches = matches
+        .into_iter()
+        .map(|(path, cid)| GlobMatch { path, cid })
+        .collect();
+
+    Ok(Json(GlobResponse { matches }))
+}
@@ -0,0 +1,132 @@
+use axum::{
+    extract::{Query, State},
+    Extension, Json,
+};
+use serde::Deserialize;
+use zeroutils_store::{ipld::cid::Cid, IpldStore};
+
+use crate::{
+    filesystem::{ErrorCode, FsCapabilities, Path},
+    service::{ErrorContext, Problem, ServiceResultExt, SharedService, WriteAtResponse},
+};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The query parameters of a link_content_at request.
+#[derive(Debug, Deserialize)]
+pub(crate) struct LinkContentQuery {
+    /// The path to link the file at.
+    path: String,
+
+    /// The CID of the content already in the store.
+    content: String,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// This endpoint handler links a file at a specific path whose content is content the node already
+/// stores, replacing anything already there, without the content being uploaded again.
+///
+/// Content that is not stored in full is rejected with `400 Bad Request`, and the write is
+/// otherwise rejected like one made with `/write_at`.
+pub(crate) async fn link_content_at<S>(
+    State(service): State<SharedService<S>>,
+    capabilities: Option<Extension<FsCapabilities>>,
+    Query(query): Query<LinkContentQuery>,
+) -> Result<Json<WriteAtResponse>, Problem>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    let Extension(capabilities) = capabilities.ok_or_else(Problem::unauthenticated)?;
+    let path: Path = query.path.parse()?;
+    let content: Cid = query
+        .content
+        .parse()
+        .map_err(|e| Problem::new(ErrorCode::InvalidArgument, format!("invalid CID: {e}")))?;
+
+    let context = || ErrorContext::new("link_content_at").path(&path);
+    let mut service = service.lock().await;
+    let (file_cid, root_cid) = service
+        .link_content_at(&capabilities, path.clone(), content)
+        .await
+        .context(context)?;
+
+    Ok(Json(WriteAtResponse {
+        file: file_cid.to_string(),
+        root: root_cid.to_string(),
+        receipt: service.sign_last_receipt().context(context)?,
+    }))
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use tokio::sync::Mutex;
+    use zeroutils_store::{MemoryStore, Storable};
+
+    use crate::{
+        config::ZerofsConfig,
+        filesystem::{Dir, FsAction, FsCapability},
+        service::FsService,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_link_content_at() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let content = store.put_raw_block(b"hello".to_vec()).await?;
+        let missing = MemoryStore::default()
+            .put_raw_block(b"elsewhere".to_vec())
+            .await?;
+
+        let service = Arc::new(Mutex::new(FsService::new(
+            Dir::new(store),
+            Arc::new(ZerofsConfig::default()),
+        )));
+        let capabilities: FsCapabilities = [FsCapability {
+            resource: "zerofs://*".parse()?,
+            action: FsAction::Create,
+        }]
+        .iter()
+        .cloned()
+        .collect();
+
+        let link = |path: &str, content: &Cid| {
+            link_content_at(
+                State(Arc::clone(&service)),
+                Some(Extension(capabilities.clone())),
+                Query(LinkContentQuery {
+                    path: path.into(),
+                    content: content.to_string(),
+                }),
+            )
+        };
+
+        // The stored content is linked without being sent again.
+        let Json(response) = link("docs/a", &content)
+            .await
+            .map_err(|problem| anyhow::anyhow!(problem.detail))?;
+        let root = service.lock().await.root_dir.clone();
+        assert_eq!(
+            root.content_cid_at(&"docs/a".parse()?).await?,
+            Some(content)
+        );
+        assert_eq!(root.store().await?.to_string(), response.root);
+
+        // Content that is not in the store is rejected.
+        let problem = link("docs/b", &missing).await.unwrap_err();
+        assert_eq!(problem.code, ErrorCode::InvalidArgument);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,26 @@
+use std::sync::Arc;
+
+use axum::{extract::State, http::header};
+
+use crate::service::middleware::RateLimiter;
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+const METRICS_CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// This endpoint handler returns the request counters of the server in the Prometheus text
+/// exposition format.
+pub(crate) async fn metrics(
+    State(limiter): State<Arc<RateLimiter>>,
+) -> ([(header::HeaderName, &'static str); 1], String) {
+    (
+        [(header::CONTENT_TYPE, METRICS_CONTENT_TYPE)],
+        limiter.render_metrics(),
+    )
+}
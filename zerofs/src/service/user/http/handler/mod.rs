@@ -1,9 +1,33 @@
+mod acl_at;
 mod authenticate;
+mod changes;
+mod content_cid_at;
+mod glob;
+mod link_content_at;
+mod metrics;
 mod open_at;
+mod read_dir;
+mod realpath_at;
+mod search;
+mod signed_root;
+mod stat_many;
+mod write_at;
 
 //--------------------------------------------------------------------------------------------------
 // Exports
 //--------------------------------------------------------------------------------------------------
 
+pub(crate) use acl_at::*;
 pub(crate) use authenticate::*;
+pub(crate) use changes::*;
+pub(crate) use content_cid_at::*;
+pub(crate) use glob::*;
+pub(crate) use link_content_at::*;
+pub(crate) use metrics::*;
 pub(crate) use open_at::*;
+pub(crate) use read_dir::*;
+pub(crate) use realpath_at::*;
+pub(crate) use search::*;
+pub(crate) use signed_root::*;
+pub(crate) use stat_many::*;
+pub(crate) use write_at::*;
@@ -0,0 +1,178 @@
+use axum::{
+    body::Body,
+    extract::{Query, State},
+    http::header,
+    response::{IntoResponse, Response},
+    BoxError, Extension,
+};
+use bytes::Bytes;
+use futures::{stream, StreamExt};
+use serde::Deserialize;
+use zeroutils_store::IpldStore;
+
+use crate::{
+    filesystem::{FsCapabilities, Path, PathSegment},
+    service::{ErrorContext, Problem, ReadDirEntry, ServiceResultExt, SharedService},
+};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The content type of a streamed directory listing, one JSON object per line.
+pub const NDJSON_CONTENT_TYPE: &str = "application/x-ndjson";
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The query parameters of a read_dir request.
+#[derive(Debug, Deserialize)]
+pub(crate) struct ReadDirQuery {
+    /// The path to the directory.
+    path: String,
+
+    /// The name of the last entry already seen, to resume the listing after it.
+    cursor: Option<String>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// This endpoint handler streams the entries of a directory as newline-delimited JSON, one
+/// [`ReadDirEntry`] per line in the order of their names, starting after the cursor if one is
+/// given.
+///
+/// Entries are written as their types are found out, so large directories are never buffered. If
+/// the listing fails part way, the response is cut short, and the name of the last entry received
+/// is the cursor to resume it with.
+pub(crate) async fn read_dir<S>(
+    State(service): State<SharedService<S>>,
+    capabilities: Option<Extension<FsCapabilities>>,
+    Query(query): Query<ReadDirQuery>,
+) -> Result<Response, Problem>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    let Extension(capabilities) = capabilities.ok_or_else(Problem::unauthenticated)?;
+    let path: Path = query.path.parse()?;
+    let cursor = query
+        .cursor
+        .map(|cursor| cursor.parse::<PathSegment>())
+        .transpose()?;
+
+    let dir = service
+        .lock()
+        .await
+        .get_dir_at(&capabilities, &path)
+        .await
+        .context(|| ErrorContext::new("read_dir").path(&path))?;
+
+    let entries = dir
+        .get_entries_after(cursor.as_ref())
+        .map(|(name, link)| (name.clone(), *link.get_cid()))
+        .collect::<Vec<_>>();
+
+    let lines = stream::iter(entries).then(move |(name, cid)| {
+        let dir = dir.clone();
+        async move {
+            let (entity_type, size) = match dir.get_hint(&name) {
+                Some(hint) => (Some(hint.entity_type), hint.size),
+                None => (dir.get_entity_type(&name).await?, None),
+            };
+
+            let entry = ReadDirEntry {
+                name,
+                cid,
+                entity_type,
+                size,
+            };
+
+            let mut line = serde_json::to_vec(&entry)?;
+            line.push(b'\n');
+            Ok::<_, BoxError>(Bytes::from(line))
+        }
+    });
+
+    Ok((
+        [(header::CONTENT_TYPE, NDJSON_CONTENT_TYPE)],
+        Body::from_stream(lines),
+    )
+        .into_response())
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use tokio::sync::Mutex;
+    use zeroutils_store::{MemoryStore, Storable};
+
+    use crate::{
+        config::ZerofsConfig,
+        filesystem::{Dir, EntityType, File, FsAction, FsCapability},
+        service::FsService,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_read_dir_streams_ndjson() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let file = File::new(store.clone()).store().await?;
+        let mut root = Dir::new(store.clone());
+        for name in ["docs/c", "docs/a", "docs/b"] {
+            root = root.link_at(&name.parse()?, file).await?;
+        }
+
+        let service = Arc::new(Mutex::new(FsService::new(
+            root,
+            Arc::new(ZerofsConfig::default()),
+        )));
+        let capabilities: FsCapabilities = [FsCapability {
+            resource: "zerofs://*".parse()?,
+            action: FsAction::Read,
+        }]
+        .iter()
+        .cloned()
+        .collect();
+
+        let response = read_dir(
+            State(service),
+            Some(Extension(capabilities)),
+            Query(ReadDirQuery {
+                path: "docs".into(),
+                cursor: Some("a".into()),
+            }),
+        )
+        .await
+        .map_err(|problem| anyhow::anyhow!(problem.detail))?;
+        assert_eq!(
+            response.headers().get(header::CONTENT_TYPE).unwrap(),
+            NDJSON_CONTENT_TYPE
+        );
+
+        // The listing resumes after the cursor, one entry per line.
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await?;
+        let entries = body
+            .split(|byte| *byte == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(serde_json::from_slice::<ReadDirEntry>)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let names = entries
+            .iter()
+            .map(|entry| entry.name.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["b", "c"]);
+        assert_eq!(entries[0].cid, file);
+        assert_eq!(entries[0].entity_type, Some(EntityType::File));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,51 @@
+use axum::{
+    extract::{Query, State},
+    Extension, Json,
+};
+use serde::Deserialize;
+use zeroutils_store::IpldStore;
+
+use crate::{
+    filesystem::{FsCapabilities, Path},
+    service::{ErrorContext, Problem, RealpathResponse, ServiceResultExt, SharedService},
+};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The query parameters of a realpath_at request.
+#[derive(Debug, Deserialize)]
+pub(crate) struct RealpathQuery {
+    /// The path to resolve.
+    path: String,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// This endpoint handler resolves a path to its canonical form by following its symbolic links,
+/// and returns it with the CID of the entity it leads to.
+///
+/// Links that lead to one another are rejected with `400 Bad Request`.
+pub(crate) async fn realpath_at<S>(
+    State(service): State<SharedService<S>>,
+    capabilities: Option<Extension<FsCapabilities>>,
+    Query(query): Query<RealpathQuery>,
+) -> Result<Json<RealpathResponse>, Problem>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    let Extension(capabilities) = capabilities.ok_or_else(Problem::unauthenticated)?;
+    let path: Path = query.path.parse()?;
+
+    let (path, cid) = service
+        .lock()
+        .await
+        .realpath_at(&capabilities, &path)
+        .await
+        .context(|| ErrorContext::new("realpath_at").path(&path))?;
+
+    Ok(Json(RealpathResponse { path, cid }))
+}
@@ -0,0 +1,53 @@
+use axum::{
+    extract::{Query, State},
+    Extension, Json,
+};
+use serde::Deserialize;
+use zeroutils_store::IpldStore;
+
+use crate::{
+    filesystem::FsCapabilities,
+    service::{ErrorContext, Problem, SearchResponse, ServiceResultExt, SharedService},
+};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The query parameters of a search request.
+#[derive(Debug, Deserialize)]
+pub(crate) struct SearchQuery {
+    /// The terms to search for.
+    q: String,
+
+    /// The maximum number of paths to return.
+    limit: Option<usize>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// This endpoint handler returns the paths whose names and attributes match a query, out of those
+/// the caller can read.
+///
+/// Searches are rejected with `501 Not Implemented` unless the search index is enabled.
+pub(crate) async fn search<S>(
+    State(service): State<SharedService<S>>,
+    capabilities: Option<Extension<FsCapabilities>>,
+    Query(query): Query<SearchQuery>,
+) -> Result<Json<SearchResponse>, Problem>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    let Extension(capabilities) = capabilities.ok_or_else(Problem::unauthenticated)?;
+
+    let paths = service
+        .lock()
+        .await
+        .search(&capabilities, &query.q, query.limit)
+        .await
+        .context(|| ErrorContext::new("search"))?;
+
+    Ok(Json(SearchResponse { paths }))
+}
@@ -0,0 +1,30 @@
+use axum::{extract::State, Json};
+use zeroutils_store::IpldStore;
+
+use crate::{
+    filesystem::ErrorCode,
+    service::{Problem, SharedService, SignedRoot},
+};
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// This endpoint handler returns the latest signed root of the file tree.
+///
+/// It needs no authentication, as the root is only of use with its signatures checked against the
+/// DIDs of the cluster. Returns `404 Not Found` if no root was announced yet.
+pub(crate) async fn signed_root<S>(
+    State(service): State<SharedService<S>>,
+) -> Result<Json<SignedRoot>, Problem>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    service
+        .lock()
+        .await
+        .get_signed_root()
+        .cloned()
+        .map(Json)
+        .ok_or_else(|| Problem::new(ErrorCode::NotFound, "no root was announced yet"))
+}
@@ -0,0 +1,57 @@
+use axum::{extract::State, Extension, Json};
+use zeroutils_store::IpldStore;
+
+use crate::{
+    filesystem::FsCapabilities,
+    service::{
+        ErrorContext, ExistsManyResponse, PathsRequest, Problem, ServiceResultExt, SharedService,
+        StatManyResponse,
+    },
+};
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// This endpoint handler returns the CID and metadata of the entity at each of a batch of paths,
+/// resolving the directories the paths share only once.
+pub(crate) async fn stat_many<S>(
+    State(service): State<SharedService<S>>,
+    capabilities: Option<Extension<FsCapabilities>>,
+    Json(body): Json<PathsRequest>,
+) -> Result<Json<StatManyResponse>, Problem>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    let Extension(capabilities) = capabilities.ok_or_else(Problem::unauthenticated)?;
+
+    let stats = service
+        .lock()
+        .await
+        .stat_many(&capabilities, &body.paths)
+        .await
+        .context(|| ErrorContext::new("stat_many"))?;
+
+    Ok(Json(StatManyResponse { stats }))
+}
+
+/// This endpoint handler returns whether there is an entity at each of a batch of paths.
+pub(crate) async fn exists_many<S>(
+    State(service): State<SharedService<S>>,
+    capabilities: Option<Extension<FsCapabilities>>,
+    Json(body): Json<PathsRequest>,
+) -> Result<Json<ExistsManyResponse>, Problem>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    let Extension(capabilities) = capabilities.ok_or_else(Problem::unauthenticated)?;
+
+    let exists = service
+        .lock()
+        .await
+        .exists_many(&capabilities, &body.paths)
+        .await
+        .context(|| ErrorContext::new("exists_many"))?;
+
+    Ok(Json(ExistsManyResponse { exists }))
+}
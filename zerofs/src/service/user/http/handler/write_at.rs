@@ -0,0 +1,217 @@
+use axum::{
+    body::Body,
+    extract::{Query, State},
+    http::{header, HeaderMap},
+    Extension, Json,
+};
+use futures::StreamExt;
+use serde::Deserialize;
+use tokio::io::{self, AsyncWriteExt, DuplexStream};
+use zeroutils_store::{IpldStore, Storable};
+
+use crate::{
+    config::ZerofsUploadConfig,
+    filesystem::{ErrorCode, File, FsCapabilities, Path, DEFAULT_OUTPUT_PIPE_CAPACITY},
+    service::{ErrorContext, Problem, ServiceResultExt, SharedService, WriteAtResponse},
+};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The query parameters of a write request.
+#[derive(Debug, Deserialize)]
+pub(crate) struct WriteAtQuery {
+    /// The path to write the file at.
+    path: String,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// This endpoint handler writes the request body as the content of the file at a specific path,
+/// replacing anything already there.
+///
+/// The body is streamed into the store through a bounded pipe, so the body is only read from the
+/// connection as fast as the store takes it and a slow store holds back the client instead of
+/// piling the body up in memory. Bodies larger than the configured maximum upload size, or with
+/// chunks larger than the configured maximum chunk size, are rejected with
+/// `413 Payload Too Large`.
+///
+/// Writes are rejected with `409 Conflict` while the file tree is read-only, or if the file at the
+/// path is immutable or append-only, and with `507 Insufficient Storage` while the store is full.
+///
+/// The service is only locked to authorize the write and to link the file once its content is
+/// stored, not while the body is being streamed. The response carries the signed receipt of the
+/// write if the node signs receipts.
+pub(crate) async fn write_at<S>(
+    State(service): State<SharedService<S>>,
+    capabilities: Option<Extension<FsCapabilities>>,
+    Query(query): Query<WriteAtQuery>,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<Json<WriteAtResponse>, Problem>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    let Extension(capabilities) = capabilities.ok_or_else(Problem::unauthenticated)?;
+    let path: Path = query.path.parse()?;
+    let context = || ErrorContext::new("write_at").path(&path);
+
+    let (store, chunker, limits) = {
+        let service = service.lock().await;
+        let action = service.get_write_action(&path).await;
+        service
+            .authorize(&capabilities, &path, action)
+            .await
+            .context(context)?;
+        service.check_writable(&path).context(context)?;
+
+        (
+            service.root_dir.get_store().clone(),
+            service.config.store.chunker,
+            service.config.upload.clone(),
+        )
+    };
+
+    let content_length = headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok());
+    if content_length.is_some_and(|length| length > limits.max_upload_size) {
+        return Err(upload_too_large(&limits));
+    }
+
+    let capacity = store
+        .get_raw_block_max_size()
+        .map(|size| size as usize)
+        .unwrap_or(DEFAULT_OUTPUT_PIPE_CAPACITY);
+    let (writer, reader) = io::duplex(capacity);
+
+    let mut file = File::new(store);
+    tokio::try_join!(pump_body(body, writer, &limits), async {
+        file.put_content(reader, &chunker)
+            .await
+            .context(context)
+            .map_err(Problem::from)
+    })?;
+
+    let file_cid = file.store().await.context(context)?;
+    let (root_cid, receipt) = {
+        let mut service = service.lock().await;
+        let root_cid = service
+            .link_at(&capabilities, path.clone(), file_cid)
+            .await
+            .context(context)?;
+        (root_cid, service.sign_last_receipt().context(context)?)
+    };
+
+    Ok(Json(WriteAtResponse {
+        file: file_cid.to_string(),
+        root: root_cid.to_string(),
+        receipt,
+    }))
+}
+
+/// Copies the chunks of `body` into `writer`, enforcing the upload limits, and closes `writer` at
+/// the end of the body.
+///
+/// Each chunk is only pulled from the body once the previous one has been written, so the pipe
+/// behind `writer` bounds how much of the body is held in memory.
+async fn pump_body(
+    body: Body,
+    mut writer: DuplexStream,
+    limits: &ZerofsUploadConfig,
+) -> Result<(), Problem> {
+    let mut stream = body.into_data_stream();
+    let mut total = 0u64;
+
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(|e| Problem::new(ErrorCode::InvalidArgument, e.to_string()))?;
+        if chunk.len() > limits.max_chunk_size {
+            return Err(Problem::new(
+                ErrorCode::PayloadTooLarge,
+                format!(
+                    "chunk of {} bytes is over the limit of {} bytes",
+                    chunk.len(),
+                    limits.max_chunk_size
+                ),
+            ));
+        }
+
+        total += chunk.len() as u64;
+        if total > limits.max_upload_size {
+            return Err(upload_too_large(limits));
+        }
+
+        writer
+            .write_all(&chunk)
+            .await
+            .map_err(|e| Problem::new(ErrorCode::Internal, e.to_string()))?;
+    }
+
+    writer
+        .shutdown()
+        .await
+        .map_err(|e| Problem::new(ErrorCode::Internal, e.to_string()))
+}
+
+/// Returns the problem of a body over the upload limit.
+fn upload_too_large(limits: &ZerofsUploadConfig) -> Problem {
+    Problem::new(
+        ErrorCode::PayloadTooLarge,
+        format!(
+            "body is over the upload limit of {} bytes",
+            limits.max_upload_size
+        ),
+    )
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::AsyncReadExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_pump_body_limits() -> anyhow::Result<()> {
+        let limits = ZerofsUploadConfig::builder()
+            .max_upload_size(8)
+            .max_chunk_size(4)
+            .build();
+
+        // A body within the limits is copied through, even through a pipe smaller than it.
+        let (writer, mut reader) = io::duplex(2);
+        let (pumped, content) =
+            tokio::join!(pump_body(Body::from("abcd"), writer, &limits), async {
+                let mut content = Vec::new();
+                reader.read_to_end(&mut content).await.map(|_| content)
+            });
+        assert!(pumped.is_ok());
+        assert_eq!(content?, b"abcd");
+
+        // A chunk over the chunk limit is rejected.
+        let (writer, _reader) = io::duplex(16);
+        let problem = pump_body(Body::from("abcdef"), writer, &limits)
+            .await
+            .unwrap_err();
+        assert_eq!(problem.code, ErrorCode::PayloadTooLarge);
+        assert_eq!(problem.status, 413);
+
+        // So is a body over the upload limit.
+        let chunks = futures::stream::iter(vec![Ok::<_, io::Error>("abcd"), Ok("efgh"), Ok("i")]);
+        let (writer, _reader) = io::duplex(16);
+        let problem = pump_body(Body::from_stream(chunks), writer, &limits)
+            .await
+            .unwrap_err();
+        assert_eq!(problem.code, ErrorCode::PayloadTooLarge);
+        assert_eq!(problem.status, 413);
+
+        Ok(())
+    }
+}
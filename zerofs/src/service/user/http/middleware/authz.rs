@@ -1,9 +1,15 @@
 use axum::{
     body::Body,
-    extract::Request,
+    extract::{Request, State},
     http::{Response, StatusCode},
     middleware::Next,
 };
+use zeroutils_store::IpldStore;
+
+use crate::{
+    filesystem::{FsCapabilities, FsDelegation},
+    service::SharedService,
+};
 
 //--------------------------------------------------------------------------------------------------
 // Constants
@@ -11,18 +17,88 @@ use axum::{
 
 const _AUTHZ_USER_TOKEN_NAME: &str = "x-authz-user-token";
 
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The DID of the user a request is authenticated as, set as a request extension by [`authorize`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct AuthenticatedDid(pub(crate) String);
+
+/// The DID a session was verified for and the delegations it carries, set as a request extension
+/// once the session token is verified. [`authorize`] resolves the capabilities it holds from them.
+// Nothing sets it until session tokens are verified.
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub(crate) struct VerifiedSession {
+    /// The DID of the invoker.
+    pub(crate) invoker: String,
+
+    /// The capabilities delegated to the invoker or to the groups it is a member of.
+    pub(crate) delegations: Vec<FsDelegation>,
+}
+
 //--------------------------------------------------------------------------------------------------
 // Functions
 //--------------------------------------------------------------------------------------------------
 
-pub(crate) async fn authorize(request: Request, next: Next) -> Result<Response<Body>, StatusCode> {
+/// Authenticates and authorizes requests to the user API.
+///
+/// The capabilities of a request with a [`VerifiedSession`] are resolved with
+/// [`resolve_capabilities`][crate::service::FsService::resolve_capabilities], which takes the
+/// groups of the invoker into account, and attached to it as [`FsCapabilities`]. The invoker they
+/// were resolved for is set as the [`AuthenticatedDid`] of the request, which scopes its rate
+/// limits and idempotency keys.
+///
+/// Session tokens are not verified yet, so no request has a [`VerifiedSession`] yet. Until then,
+/// requests are rate limited by IP address and their idempotency keys are ignored.
+pub(crate) async fn authorize<S>(
+    State(service): State<SharedService<S>>,
+    mut request: Request,
+    next: Next,
+) -> Result<Response<Body>, StatusCode>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
     // == Session Token ==
     // Extract token from x-authz-user-token http-only cookie.
     // Verify that token has the right delegation chain and session rights. root_user -> user -> server -> user
+    // Insert the invoker and the delegations of the session as a `VerifiedSession` extension.
 
     // == CSRF Token ==
     // Extract token from x-authz-csrf-token header
     // Extract token from x-authz-csrf-token cookie
     // Verify that token is valid and matches the session token
+
+    // == Capabilities ==
+    if let Some(session) = request.extensions().get::<VerifiedSession>().cloned() {
+        let capabilities = service
+            .lock()
+            .await
+            .resolve_capabilities(&session.invoker, &session.delegations)
+            .await
+            .map_err(|e| {
+                tracing::error!(
+                    "Failed to resolve the capabilities of {}: {}",
+                    session.invoker,
+                    e
+                );
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+
+        request.extensions_mut().insert(capabilities);
+    }
+
+    // == Authenticated DID ==
+    let did = request
+        .extensions()
+        .get::<FsCapabilities>()
+        .and_then(FsCapabilities::get_invoker)
+        .map(str::to_owned);
+
+    if let Some(did) = did {
+        request.extensions_mut().insert(AuthenticatedDid(did));
+    }
+
     Ok(next.run(request).await)
 }
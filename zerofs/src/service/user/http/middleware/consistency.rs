@@ -0,0 +1,65 @@
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{HeaderValue, Response},
+    middleware::Next,
+    response::IntoResponse,
+};
+use zeroutils_store::IpldStore;
+
+use crate::service::{Problem, ReadConsistency, ServiceError, SharedService};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The header carrying the [consistency][ReadConsistency] the reads of a request need, like
+/// `eventual`.
+pub(crate) const CONSISTENCY_HEADER: &str = "zerofs-consistency";
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Checks that the node can serve the reads of requests with the consistency they ask for in the
+/// `zerofs-consistency` header, and makes it available to handlers as an extension.
+///
+/// Requests asking for a consistency the node cannot serve, like linearizable reads of a read
+/// replica, are rejected with `503 Service Unavailable`, and unknown levels with
+/// `400 Bad Request`. Responses echo the consistency they were served with. Requests without the
+/// header are served as the node always does.
+pub(crate) async fn read_consistency<S>(
+    State(service): State<SharedService<S>>,
+    mut request: Request,
+    next: Next,
+) -> Response<Body>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    let Some(value) = request.headers().get(CONSISTENCY_HEADER) else {
+        return next.run(request).await;
+    };
+
+    let consistency = match value
+        .to_str()
+        .map_err(|_| {
+            ServiceError::InvalidConsistency(String::from_utf8_lossy(value.as_bytes()).into_owned())
+        })
+        .and_then(str::parse::<ReadConsistency>)
+    {
+        Ok(consistency) => consistency,
+        Err(e) => return Problem::from(e).into_response(),
+    };
+
+    if let Err(e) = service.lock().await.check_consistency(consistency) {
+        return Problem::from(e).into_response();
+    }
+
+    request.extensions_mut().insert(consistency);
+    let mut response = next.run(request).await;
+    if let Ok(value) = HeaderValue::from_str(&consistency.to_string()) {
+        response.headers_mut().insert(CONSISTENCY_HEADER, value);
+    }
+
+    response
+}
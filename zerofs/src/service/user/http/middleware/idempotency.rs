@@ -0,0 +1,164 @@
+use axum::{
+    body::{self, Body, HttpBody},
+    extract::{Request, State},
+    http::{header, HeaderValue, Response, StatusCode},
+    middleware::Next,
+    response::IntoResponse,
+};
+use zeroutils_store::IpldStore;
+
+use crate::service::{IdempotencyRecord, IdempotentResponse, SharedService};
+
+use super::AuthenticatedDid;
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The header carrying the idempotency key of a request.
+pub(crate) const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+/// The header set on responses that are replayed rather than produced by running the request.
+pub(crate) const IDEMPOTENT_REPLAYED_HEADER: &str = "idempotent-replayed";
+
+/// The largest response body recorded for replay, in bytes.
+pub(crate) const MAX_RECORDED_BODY_SIZE: usize = 64 * 1024;
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Replays the recorded response to requests that carry an `Idempotency-Key` header already used by
+/// the same DID, and records the response to the first successful request with a new key.
+///
+/// The key is reserved before the request runs, so a retry made while it runs is rejected with
+/// `409 Conflict` rather than run twice. A key is tied to the method and URI of the request it was
+/// first used with. Reusing it for a different request is rejected with
+/// `422 Unprocessable Entity`. Failed requests, and responses with a body larger than
+/// [`MAX_RECORDED_BODY_SIZE`], release the key instead of being recorded, so they can be retried
+/// with the same key. Requests without the header or an [`AuthenticatedDid`] run as they are, which
+/// until sessions are verified by [`authorize`][super::authorize] includes every request that does
+/// not carry capabilities.
+pub(crate) async fn idempotency<S>(
+    State(service): State<SharedService<S>>,
+    request: Request,
+    next: Next,
+) -> Response<Body>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    let Some(key) = request
+        .headers()
+        .get(IDEMPOTENCY_KEY_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_owned)
+    else {
+        return next.run(request).await;
+    };
+
+    // Keys are scoped to the DID that used them, so there is nothing to scope them to without one.
+    let Some(AuthenticatedDid(did)) = request.extensions().get::<AuthenticatedDid>().cloned()
+    else {
+        return next.run(request).await;
+    };
+
+    let fingerprint = format!("{} {}", request.method(), request.uri());
+    let reserved = service
+        .lock()
+        .await
+        .reserve_idempotency_key(&did, &key, fingerprint.clone())
+        .await;
+
+    match reserved {
+        Ok(Some(record)) if record.fingerprint != fingerprint => {
+            return StatusCode::UNPROCESSABLE_ENTITY.into_response();
+        }
+        Ok(Some(IdempotencyRecord {
+            response: Some(recorded),
+            ..
+        })) => {
+            let status = StatusCode::from_u16(recorded.status).unwrap_or(StatusCode::OK);
+            let mut response = (status, recorded.body).into_response();
+            let headers = response.headers_mut();
+            if let Some(content_type) = recorded
+                .content_type
+                .and_then(|content_type| HeaderValue::from_str(&content_type).ok())
+            {
+                headers.insert(header::CONTENT_TYPE, content_type);
+            }
+            headers.insert(IDEMPOTENT_REPLAYED_HEADER, HeaderValue::from_static("true"));
+            return response;
+        }
+        Ok(Some(_)) => return StatusCode::CONFLICT.into_response(),
+        Ok(None) => {}
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+
+    let response = next.run(request).await;
+    if !response.status().is_success() {
+        release(&service, &did, &key).await;
+        return response;
+    }
+
+    let (parts, body) = response.into_parts();
+    let too_large = body
+        .size_hint()
+        .upper()
+        .map_or(true, |size| size > MAX_RECORDED_BODY_SIZE as u64);
+    if too_large {
+        tracing::warn!("not recording idempotent response with a body that may be too large");
+        release(&service, &did, &key).await;
+        return Response::from_parts(parts, body);
+    }
+
+    let Ok(bytes) = body::to_bytes(body, MAX_RECORDED_BODY_SIZE).await else {
+        release(&service, &did, &key).await;
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    };
+
+    match std::str::from_utf8(&bytes) {
+        Ok(text) => {
+            let content_type = parts
+                .headers
+                .get(header::CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_owned);
+
+            let recorded = service
+                .lock()
+                .await
+                .record_idempotent_response(
+                    &did,
+                    &key,
+                    IdempotentResponse {
+                        status: parts.status.as_u16(),
+                        content_type,
+                        body: text.to_owned(),
+                    },
+                )
+                .await;
+
+            if let Err(e) = recorded {
+                tracing::warn!("failed to record idempotent response: {e}");
+            }
+        }
+        Err(_) => {
+            tracing::warn!("not recording idempotent response with a non-UTF-8 body");
+            release(&service, &did, &key).await;
+        }
+    }
+
+    Response::from_parts(parts, Body::from(bytes))
+}
+
+/// Releases `key` reserved by `did`, so the request can be retried with it.
+async fn release<S>(service: &SharedService<S>, did: &str, key: &str)
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    let released = service.lock().await.release_idempotency_key(did, key).await;
+
+    if let Err(e) = released {
+        tracing::warn!("failed to release idempotency key: {e}");
+    }
+}
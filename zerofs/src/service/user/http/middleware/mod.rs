@@ -1,7 +1,17 @@
 mod authz;
+mod consistency;
+mod idempotency;
+mod ratelimit;
+mod replica;
+mod timeout;
 
 //--------------------------------------------------------------------------------------------------
 // Exports
 //--------------------------------------------------------------------------------------------------
 
 pub(crate) use authz::*;
+pub(crate) use consistency::*;
+pub(crate) use idempotency::*;
+pub(crate) use ratelimit::*;
+pub(crate) use replica::*;
+pub(crate) use timeout::*;
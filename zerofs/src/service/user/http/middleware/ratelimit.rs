@@ -0,0 +1,332 @@
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    net::{IpAddr, SocketAddr},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, Request, State},
+    http::{header, HeaderValue, Response, StatusCode},
+    middleware::Next,
+    response::IntoResponse,
+};
+use futures::StreamExt;
+
+use crate::config::ZerofsRateLimitConfig;
+
+use super::AuthenticatedDid;
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The number of tracked clients above which clients with a full budget are forgotten.
+const PRUNE_THRESHOLD: usize = 10_000;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Enforces the request and bandwidth quotas of the user API.
+///
+/// Every client has a budget of requests and of request body bytes that refills continuously at the
+/// configured rate. A request is let through as long as the budget is not exhausted, and its body is
+/// charged as it is received, so a single large upload can overdraw the byte budget, and subsequent
+/// requests are then rejected until it has refilled.
+pub(crate) struct RateLimiter {
+    /// The configured quotas.
+    config: ZerofsRateLimitConfig,
+
+    /// The budgets of the clients seen recently.
+    budgets: Mutex<HashMap<ClientKey, Budget>>,
+
+    /// The request counters of authenticated clients.
+    did_counters: Counters,
+
+    /// The request counters of unauthenticated clients.
+    ip_counters: Counters,
+}
+
+/// Identifies the client a quota applies to.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum ClientKey {
+    Did(String),
+    Ip(IpAddr),
+}
+
+/// The remaining budget of a client.
+#[derive(Debug)]
+struct Budget {
+    requests: f64,
+    bytes: f64,
+    updated_at: Instant,
+}
+
+/// Request counters for a kind of client.
+#[derive(Debug, Default)]
+struct Counters {
+    allowed: AtomicU64,
+    limited: AtomicU64,
+    bytes: AtomicU64,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl RateLimiter {
+    /// Creates a rate limiter enforcing the given quotas.
+    pub(crate) fn new(config: ZerofsRateLimitConfig) -> Self {
+        Self {
+            config,
+            budgets: Mutex::new(HashMap::new()),
+            did_counters: Counters::default(),
+            ip_counters: Counters::default(),
+        }
+    }
+
+    /// Charges a request to `client`, returning how long the client has to wait if it is over
+    /// quota.
+    fn check(&self, client: &ClientKey) -> Result<(), Duration> {
+        let counters = self.counters(client);
+        let result = self.charge(client);
+        match result {
+            Ok(()) => counters.allowed.fetch_add(1, Ordering::Relaxed),
+            Err(_) => counters.limited.fetch_add(1, Ordering::Relaxed),
+        };
+
+        result
+    }
+
+    fn charge(&self, client: &ClientKey) -> Result<(), Duration> {
+        let request_rate = self.config.requests_per_second.map(f64::from);
+        let byte_rate = self.config.bytes_per_second.map(|rate| rate as f64);
+        if request_rate.is_none() && byte_rate.is_none() {
+            return Ok(());
+        }
+
+        let now = Instant::now();
+        let mut budgets = self.budgets.lock().unwrap();
+        if budgets.len() > PRUNE_THRESHOLD {
+            budgets.retain(|_, budget| !budget.is_full(now, request_rate, byte_rate));
+        }
+
+        let budget = budgets
+            .entry(client.clone())
+            .or_insert_with(|| Budget::full(now, request_rate, byte_rate));
+        budget.refill(now, request_rate, byte_rate);
+
+        // Wait for whichever budget takes longer to get back above what a request needs.
+        let mut wait = 0.0_f64;
+        if let Some(rate) = request_rate {
+            if budget.requests < 1.0 {
+                wait = wait.max((1.0 - budget.requests) / rate);
+            }
+        }
+        if let Some(rate) = byte_rate {
+            if budget.bytes <= 0.0 {
+                wait = wait.max(-budget.bytes / rate);
+            }
+        }
+
+        if wait > 0.0 {
+            return Err(Duration::from_secs_f64(wait));
+        }
+
+        budget.requests -= 1.0;
+
+        Ok(())
+    }
+
+    /// Charges `bytes` bytes of a request body to `client`, overdrawing its byte budget if need be.
+    fn charge_bytes(&self, client: &ClientKey, bytes: u64) {
+        self.counters(client)
+            .bytes
+            .fetch_add(bytes, Ordering::Relaxed);
+
+        let request_rate = self.config.requests_per_second.map(f64::from);
+        let Some(byte_rate) = self.config.bytes_per_second.map(|rate| rate as f64) else {
+            return;
+        };
+
+        let now = Instant::now();
+        let mut budgets = self.budgets.lock().unwrap();
+        let budget = budgets
+            .entry(client.clone())
+            .or_insert_with(|| Budget::full(now, request_rate, Some(byte_rate)));
+        budget.refill(now, request_rate, Some(byte_rate));
+        budget.bytes -= bytes as f64;
+    }
+
+    fn counters(&self, client: &ClientKey) -> &Counters {
+        match client {
+            ClientKey::Did(_) => &self.did_counters,
+            ClientKey::Ip(_) => &self.ip_counters,
+        }
+    }
+
+    /// Renders the request counters in the Prometheus text exposition format.
+    pub(crate) fn render_metrics(&self) -> String {
+        let clients = [("did", &self.did_counters), ("ip", &self.ip_counters)];
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+
+        let mut out = String::new();
+        write_counter(
+            &mut out,
+            "zerofs_http_requests_allowed_total",
+            "Requests let through by the rate limiter.",
+            clients.map(|(client, counters)| (client, load(&counters.allowed))),
+        );
+        write_counter(
+            &mut out,
+            "zerofs_http_requests_limited_total",
+            "Requests rejected by the rate limiter.",
+            clients.map(|(client, counters)| (client, load(&counters.limited))),
+        );
+        write_counter(
+            &mut out,
+            "zerofs_http_request_bytes_total",
+            "Request body bytes let through by the rate limiter.",
+            clients.map(|(client, counters)| (client, load(&counters.bytes))),
+        );
+
+        out
+    }
+}
+
+impl Budget {
+    fn full(now: Instant, request_rate: Option<f64>, byte_rate: Option<f64>) -> Self {
+        Self {
+            requests: request_rate.unwrap_or_default(),
+            bytes: byte_rate.unwrap_or_default(),
+            updated_at: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant, request_rate: Option<f64>, byte_rate: Option<f64>) {
+        let elapsed = now.duration_since(self.updated_at).as_secs_f64();
+        if let Some(rate) = request_rate {
+            self.requests = (self.requests + elapsed * rate).min(rate);
+        }
+        if let Some(rate) = byte_rate {
+            self.bytes = (self.bytes + elapsed * rate).min(rate);
+        }
+        self.updated_at = now;
+    }
+
+    fn is_full(&self, now: Instant, request_rate: Option<f64>, byte_rate: Option<f64>) -> bool {
+        let elapsed = now.duration_since(self.updated_at).as_secs_f64();
+        request_rate.is_none_or(|rate| self.requests + elapsed * rate >= rate)
+            && byte_rate.is_none_or(|rate| self.bytes + elapsed * rate >= rate)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Writes a counter with one sample per kind of client.
+fn write_counter(out: &mut String, name: &str, help: &str, values: [(&str, u64); 2]) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} counter");
+    for (client, value) in values {
+        let _ = writeln!(out, "{name}{{client=\"{client}\"}} {value}");
+    }
+}
+
+/// Rejects requests from clients that are over quota with `429 Too Many Requests` and a
+/// `Retry-After` header.
+///
+/// Requests are charged to the DID set by [`authorize`][super::authorize] or, if there is none, to
+/// the IP address of the client, which until sessions are verified is what every request without
+/// capabilities is charged to. The request body is charged as it is received rather than by its
+/// `Content-Length`, which a client can leave out or understate.
+pub(crate) async fn rate_limit(
+    State(limiter): State<Arc<RateLimiter>>,
+    request: Request,
+    next: Next,
+) -> Response<Body> {
+    let client = match request.extensions().get::<AuthenticatedDid>() {
+        Some(AuthenticatedDid(did)) => ClientKey::Did(did.clone()),
+        None => match request.extensions().get::<ConnectInfo<SocketAddr>>() {
+            Some(ConnectInfo(address)) => ClientKey::Ip(address.ip()),
+            None => return next.run(request).await,
+        },
+    };
+
+    if let Err(wait) = limiter.check(&client) {
+        // Round up so that retrying after the advertised delay succeeds.
+        let retry_after = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+        return (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(header::RETRY_AFTER, HeaderValue::from(retry_after))],
+        )
+            .into_response();
+    }
+
+    let (parts, body) = request.into_parts();
+    let body = body.into_data_stream().inspect(move |chunk| {
+        if let Ok(chunk) = chunk {
+            limiter.charge_bytes(&client, chunk.len() as u64);
+        }
+    });
+
+    next.run(Request::from_parts(parts, Body::from_stream(body)))
+        .await
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    #[test]
+    fn test_rate_limiter_quotas() {
+        let limiter = RateLimiter::new(
+            ZerofsRateLimitConfig::builder()
+                .requests_per_second(2)
+                .bytes_per_second(100)
+                .build(),
+        );
+
+        let alice =
+            ClientKey::Did("did:wk:z6MkoVs2h6TnfyY8fx2ZqpREWSLS8rBDQmGpyXgFpg63CSUb".into());
+        let ip = ClientKey::Ip(Ipv4Addr::LOCALHOST.into());
+
+        // The request budget holds one second's worth.
+        assert!(limiter.check(&alice).is_ok());
+        assert!(limiter.check(&alice).is_ok());
+        let wait = limiter.check(&alice).unwrap_err();
+        assert!(wait > Duration::ZERO && wait <= Duration::from_millis(500));
+
+        // A large body overdraws the byte budget and blocks the next request until it refills.
+        assert!(limiter.check(&ip).is_ok());
+        limiter.charge_bytes(&ip, 250);
+        let wait = limiter.check(&ip).unwrap_err();
+        assert!(wait > Duration::from_secs(1) && wait <= Duration::from_millis(1500));
+
+        let metrics = limiter.render_metrics();
+        assert!(metrics.contains("zerofs_http_requests_allowed_total{client=\"did\"} 2"));
+        assert!(metrics.contains("zerofs_http_requests_limited_total{client=\"did\"} 1"));
+        assert!(metrics.contains("zerofs_http_request_bytes_total{client=\"ip\"} 250"));
+
+        // Without quotas, everything is let through.
+        let limiter = RateLimiter::new(ZerofsRateLimitConfig::default());
+        for _ in 0..10 {
+            assert!(limiter
+                .check(&ClientKey::Ip(Ipv4Addr::LOCALHOST.into()))
+                .is_ok());
+        }
+    }
+}
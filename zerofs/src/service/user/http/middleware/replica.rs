@@ -0,0 +1,87 @@
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{HeaderValue, Response, StatusCode},
+    middleware::Next,
+    response::IntoResponse,
+};
+use zeroutils_store::IpldStore;
+
+use crate::service::SharedService;
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The header carrying the CID of the root a replica served the request from.
+pub(crate) const REPLICA_ROOT_HEADER: &str = "zerofs-replica-root";
+
+/// The header carrying the sequence number of the announcement of the root.
+pub(crate) const REPLICA_SEQUENCE_HEADER: &str = "zerofs-replica-sequence";
+
+/// The header carrying how long ago the root was announced, in seconds.
+pub(crate) const REPLICA_STALENESS_HEADER: &str = "zerofs-replica-staleness";
+
+/// The header carrying the configured maximum staleness of the replica, in seconds.
+pub(crate) const REPLICA_MAX_STALENESS_HEADER: &str = "zerofs-replica-max-staleness";
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Tells clients of a read replica how fresh the data they are served is.
+///
+/// Responses carry the root the replica follows, its sequence number and its staleness, along with
+/// the maximum staleness if one is configured. Requests are rejected with `503 Service Unavailable`
+/// while the replica follows no root or its root is older than the maximum. Requests to nodes that
+/// are not replicas are passed through untouched.
+pub(crate) async fn replica_staleness<S>(
+    State(service): State<SharedService<S>>,
+    request: Request,
+    next: Next,
+) -> Response<Body>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    let (headers, too_stale) = {
+        let service = service.lock().await;
+        let replica = &service.config.replica;
+        if !replica.enabled {
+            drop(service);
+            return next.run(request).await;
+        }
+
+        let mut headers = Vec::new();
+        if let (Some(signed), Some(staleness)) =
+            (service.get_signed_root(), service.get_staleness())
+        {
+            let announcement = &signed.announcement;
+            headers.push((REPLICA_ROOT_HEADER, announcement.root.to_string()));
+            headers.push((REPLICA_SEQUENCE_HEADER, announcement.sequence.to_string()));
+            headers.push((
+                REPLICA_STALENESS_HEADER,
+                staleness.num_seconds().max(0).to_string(),
+            ));
+        }
+
+        if let Some(max) = replica.max_staleness {
+            headers.push((REPLICA_MAX_STALENESS_HEADER, max.to_string()));
+        }
+
+        (headers, service.is_too_stale())
+    };
+
+    let mut response = if too_stale {
+        StatusCode::SERVICE_UNAVAILABLE.into_response()
+    } else {
+        next.run(request).await
+    };
+
+    for (name, value) in headers {
+        if let Ok(value) = HeaderValue::from_str(&value) {
+            response.headers_mut().insert(name, value);
+        }
+    }
+
+    response
+}
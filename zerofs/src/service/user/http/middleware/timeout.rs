@@ -0,0 +1,37 @@
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::Response,
+    middleware::Next,
+    response::IntoResponse,
+};
+
+use crate::{
+    filesystem::{self, FsError},
+    service::{Problem, SharedConfig},
+};
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Cancels requests that run past the timeout configured for their operation, which is named
+/// after the route, like `write_at` for `/write_at`. They are rejected with
+/// `504 Gateway Timeout`.
+///
+/// The rest of the request, up to the store work of the handler, runs as part of this future, so
+/// it is cancelled with it. The same goes for a request dropped because its client went away.
+pub(crate) async fn operation_timeout(
+    State(config): State<SharedConfig>,
+    request: Request,
+    next: Next,
+) -> Response<Body> {
+    let operation = request.uri().path().trim_start_matches('/').to_owned();
+    let timeout = config.timeouts.get_timeout(&operation);
+
+    filesystem::with_timeout(&operation, timeout, async {
+        Ok::<_, FsError>(next.run(request).await)
+    })
+    .await
+    .unwrap_or_else(|e| Problem::from(e).into_response())
+}
@@ -1,4 +1,6 @@
 mod server;
+#[cfg(feature = "tls")]
+mod tls;
 
 //--------------------------------------------------------------------------------------------------
 // Exports
@@ -9,3 +11,5 @@ pub(crate) mod middleware;
 pub(crate) mod router;
 
 pub use server::*;
+#[cfg(feature = "tls")]
+pub use tls::*;
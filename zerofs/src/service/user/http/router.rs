@@ -1,6 +1,12 @@
-use axum::{routing, Router};
+use std::{convert::TryFrom, sync::Arc};
 
-use crate::service::{middleware, SharedConfig};
+use axum::{extract::DefaultBodyLimit, routing, Router};
+use zeroutils_store::IpldStore;
+
+use crate::service::{
+    middleware::{self, RateLimiter},
+    SharedConfig, SharedService,
+};
 
 use super::handler;
 
@@ -8,12 +14,89 @@ use super::handler;
 // Functions
 //--------------------------------------------------------------------------------------------------
 
-pub(crate) fn router(_config: SharedConfig) -> Router {
+pub(crate) fn router<S>(
+    service: SharedService<S>,
+    config: SharedConfig,
+    limiter: Arc<RateLimiter>,
+) -> Router
+where
+    S: IpldStore + Send + Sync + 'static,
+{
     let authn_routes = Router::new().route("/authenticate", routing::get(handler::authenticate));
 
-    let operation_routes = Router::new()
+    let public_routes = Router::new()
+        .route("/signed_root", routing::get(handler::signed_root::<S>))
+        .with_state(Arc::clone(&service));
+
+    // Only mutations are replayed for retries made with the same idempotency key.
+    let mutation_routes = Router::new()
         .route("/open_at", routing::post(handler::open_at))
-        .layer(axum::middleware::from_fn(middleware::authorize));
+        .route("/write_at", routing::post(handler::write_at::<S>))
+        .route(
+            "/link_content_at",
+            routing::post(handler::link_content_at::<S>),
+        )
+        .route("/set_acl_at", routing::post(handler::set_acl_at::<S>))
+        .layer(axum::middleware::from_fn_with_state(
+            Arc::clone(&config),
+            middleware::operation_timeout,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            Arc::clone(&service),
+            middleware::idempotency::<S>,
+        ));
+
+    // These layers are added before `authorize` so that they run after it and see the DID.
+    let operation_routes = Router::new()
+        .route("/get_acl_at", routing::post(handler::get_acl_at::<S>))
+        .route("/changes", routing::get(handler::changes::<S>))
+        .route("/search", routing::get(handler::search::<S>))
+        .route("/glob", routing::get(handler::glob::<S>))
+        .route("/read_dir", routing::get(handler::read_dir::<S>))
+        .route(
+            "/content_cid_at",
+            routing::get(handler::content_cid_at::<S>),
+        )
+        .route("/realpath_at", routing::get(handler::realpath_at::<S>))
+        .route("/stat_many", routing::post(handler::stat_many::<S>))
+        .route("/exists_many", routing::post(handler::exists_many::<S>))
+        .layer(axum::middleware::from_fn_with_state(
+            Arc::clone(&config),
+            middleware::operation_timeout,
+        ))
+        .merge(mutation_routes)
+        .layer(axum::middleware::from_fn_with_state(
+            Arc::clone(&limiter),
+            middleware::rate_limit,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            Arc::clone(&service),
+            middleware::authorize::<S>,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            Arc::clone(&service),
+            middleware::read_consistency::<S>,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            Arc::clone(&service),
+            middleware::replica_staleness::<S>,
+        ))
+        .with_state(service);
+
+    let metrics_routes = Router::new()
+        .route("/metrics", routing::get(handler::metrics))
+        .with_state(Arc::clone(&limiter));
+
+    // Streamed bodies are limited by the handlers that stream them; this covers buffered ones.
+    let body_limit = usize::try_from(config.upload.max_upload_size).unwrap_or(usize::MAX);
 
-    authn_routes.merge(operation_routes)
+    authn_routes
+        .merge(metrics_routes)
+        .merge(public_routes)
+        .layer(axum::middleware::from_fn_with_state(
+            limiter,
+            middleware::rate_limit,
+        ))
+        .merge(operation_routes)
+        .layer(DefaultBodyLimit::max(body_limit))
 }
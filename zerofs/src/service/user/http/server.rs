@@ -1,8 +1,17 @@
-use std::sync::Arc;
+use std::{net::SocketAddr, sync::Arc};
 
 use tokio::net::TcpListener;
+use zeroutils_store::IpldStore;
 
-use crate::service::{router, ServiceResult, SharedConfig};
+#[cfg(not(feature = "tls"))]
+use crate::service::ServiceError;
+use crate::{
+    config::Frontend,
+    service::{middleware::RateLimiter, router, ServiceResult, SharedService},
+};
+
+#[cfg(feature = "tls")]
+use super::{serve_tls, TlsCertificate};
 
 //--------------------------------------------------------------------------------------------------
 // Types
@@ -16,32 +25,82 @@ use crate::service::{router, ServiceResult, SharedConfig};
 ///
 /// File input and output streams are treated as chunks of data with the support of the
 /// `Transfer-Encoding: chunked` header.
-pub struct FsHttpServer {
-    /// The configuration of the file system.
-    config: SharedConfig,
+///
+/// With a [TLS configuration][crate::config::ZerofsTlsConfig], the server only serves HTTPS, and
+/// picks up a renewed certificate without dropping the open connections.
+///
+/// Requests are subject to the quotas in the
+/// [rate limit configuration][crate::config::ZerofsRateLimitConfig] and uploads to the limits in
+/// the [upload configuration][crate::config::ZerofsUploadConfig]. The request counters are served
+/// at `/metrics`.
+pub struct FsHttpServer<S>
+where
+    S: IpldStore,
+{
+    /// The file system service to serve.
+    service: SharedService<S>,
+
+    /// The rate limiter shared by all routes.
+    limiter: Arc<RateLimiter>,
 }
 
 //--------------------------------------------------------------------------------------------------
 // Methods
 //--------------------------------------------------------------------------------------------------
 
-impl FsHttpServer {
+impl<S> FsHttpServer<S>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
     /// Creates a new HTTP server for the file system service.
-    pub fn new(config: SharedConfig) -> Self {
-        Self { config }
+    pub async fn new(service: SharedService<S>) -> Self {
+        let config = service.lock().await.config.clone();
+        let limiter = Arc::new(RateLimiter::new(config.rate_limit.clone()));
+        Self { service, limiter }
     }
 
-    /// Starts the HTTP server.
+    /// Starts the HTTP server, over TLS if the
+    /// [interface configuration][crate::config::ZerofsInterfaceConfig] has a certificate.
     pub async fn start(&self) -> ServiceResult<()> {
-        let router = router::router(Arc::clone(&self.config));
-        let listener = TcpListener::bind(self.config.network.get_user_address()).await?;
+        let config = self.service.lock().await.config.clone();
+        let address = config
+            .get_frontend_address(Frontend::Http)
+            .unwrap_or_else(|| config.network.get_user_address());
 
-        tracing::info!(
-            "HTTP server started at {}",
-            self.config.network.get_user_address()
+        // The certificate is read before binding, so that a broken one fails the start.
+        #[cfg(feature = "tls")]
+        let certificate = config
+            .interface
+            .tls
+            .clone()
+            .map(TlsCertificate::load)
+            .transpose()?
+            .map(Arc::new);
+        #[cfg(not(feature = "tls"))]
+        if config.interface.tls.is_some() {
+            return Err(ServiceError::InvalidConfig(vec![
+                "serving TLS needs zerofs to be built with the `tls` feature".to_owned(),
+            ]));
+        }
+
+        let router = router::router(
+            Arc::clone(&self.service),
+            Arc::clone(&config),
+            Arc::clone(&self.limiter),
         );
+        let listener = TcpListener::bind(address).await?;
+
+        // The client address is needed to rate limit unauthenticated requests.
+        let service = router.into_make_service_with_connect_info::<SocketAddr>();
+
+        #[cfg(feature = "tls")]
+        if let Some(certificate) = certificate {
+            tracing::info!("HTTPS server started at {}", address);
+            return serve_tls(listener, service, certificate).await;
+        }
 
-        axum::serve(listener, router).await?;
+        tracing::info!("HTTP server started at {}", address);
+        axum::serve(listener, service).await?;
 
         Ok(())
     }
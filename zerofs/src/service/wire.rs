@@ -0,0 +1,292 @@
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use super::{EntityOperation, ServiceError, ServiceResult};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The version of the operation wire format written by this version.
+///
+/// It only changes when the format changes in a way older readers cannot follow. New operations
+/// and new optional parameters do not change it, as older readers already skip unknown operations
+/// and ignore unknown parameters.
+pub const WIRE_VERSION: u32 = 1;
+
+/// The largest frame that is read, in bytes.
+pub const MAX_FRAME_SIZE: u64 = 16 * 1024 * 1024;
+
+/// The CBOR major type of a byte string.
+const CBOR_BYTES: u8 = 2;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// A value along with the version of the wire format it was written with.
+///
+/// Both the user and the peer protocols send operations in an envelope, in JSON and in CBOR
+/// respectively, so that they share one schema.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Envelope<T> {
+    /// The version of the wire format.
+    version: u32,
+
+    /// The enveloped value.
+    body: T,
+}
+
+/// Writes a batch of operations to a stream, one frame at a time.
+///
+/// Each frame is a CBOR byte string holding the DAG-CBOR encoding of an [`Envelope`] of one
+/// [`EntityOperation`], so the stream as a whole is a CBOR sequence and a reader never has to
+/// hold more than one operation in memory.
+pub struct OperationWriter<W> {
+    writer: W,
+}
+
+/// Reads a batch of operations written by an [`OperationWriter`], one frame at a time.
+pub struct OperationReader<R> {
+    reader: R,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl<T> Envelope<T> {
+    /// Wraps `body` in an envelope of the current [`WIRE_VERSION`].
+    pub fn new(body: T) -> Self {
+        Self {
+            version: WIRE_VERSION,
+            body,
+        }
+    }
+
+    /// Returns the version of the wire format the value was written with.
+    pub fn get_version(&self) -> u32 {
+        self.version
+    }
+
+    /// Returns the enveloped value if its version is one this version can read.
+    pub fn into_body(self) -> ServiceResult<T> {
+        if self.version > WIRE_VERSION {
+            return Err(ServiceError::UnsupportedWireVersion(self.version));
+        }
+
+        Ok(self.body)
+    }
+}
+
+impl<W> OperationWriter<W>
+where
+    W: AsyncWrite + Unpin,
+{
+    /// Creates a writer that writes operations to `writer`.
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    /// Writes `operation` as the next frame.
+    pub async fn write(&mut self, operation: &EntityOperation) -> ServiceResult<()> {
+        write_frame(&mut self.writer, &Envelope::new(operation)).await
+    }
+
+    /// Flushes the frames written so far and returns the underlying writer.
+    pub async fn finish(mut self) -> ServiceResult<W> {
+        self.writer.flush().await?;
+        Ok(self.writer)
+    }
+}
+
+impl<R> OperationReader<R>
+where
+    R: AsyncRead + Unpin,
+{
+    /// Creates a reader that reads operations from `reader`.
+    pub fn new(reader: R) -> Self {
+        Self { reader }
+    }
+
+    /// Reads the next operation, or returns `None` if the stream ended between frames.
+    ///
+    /// Operations this version does not know are returned as
+    /// [`EntityOperationKind::Unknown`][super::EntityOperationKind::Unknown], so the rest of the
+    /// batch can still be read.
+    pub async fn next(&mut self) -> ServiceResult<Option<EntityOperation>> {
+        read_frame::<_, Envelope<EntityOperation>>(&mut self.reader)
+            .await?
+            .map(Envelope::into_body)
+            .transpose()
+    }
+
+    /// Returns the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Writes `value` to `writer` as one frame: a CBOR byte string holding its DAG-CBOR encoding.
+pub(crate) async fn write_frame<W, T>(writer: &mut W, value: &T) -> ServiceResult<()>
+where
+    W: AsyncWrite + Unpin,
+    T: Serialize,
+{
+    let body =
+        serde_ipld_dagcbor::to_vec(value).map_err(|e| ServiceError::InvalidFrame(e.to_string()))?;
+
+    let mut head = Vec::with_capacity(9);
+    write_cbor_head(&mut head, CBOR_BYTES, body.len() as u64);
+
+    writer.write_all(&head).await?;
+    writer.write_all(&body).await?;
+
+    Ok(())
+}
+
+/// Reads one frame written by [`write_frame`] from `reader`, or returns `None` if the stream ended
+/// before it.
+pub(crate) async fn read_frame<R, T>(reader: &mut R) -> ServiceResult<Option<T>>
+where
+    R: AsyncRead + Unpin,
+    T: DeserializeOwned,
+{
+    let mut initial = [0; 1];
+    if reader.read(&mut initial).await? == 0 {
+        return Ok(None);
+    }
+
+    let major = initial[0] >> 5;
+    if major != CBOR_BYTES {
+        return Err(ServiceError::InvalidFrame(format!(
+            "expected a byte string, found major type {}",
+            major
+        )));
+    }
+
+    let len = match initial[0] & 0x1f {
+        info @ 0..=23 => info as u64,
+        info @ 24..=27 => {
+            let mut buf = [0; 8];
+            let size = 1 << (info - 24);
+            reader.read_exact(&mut buf[8 - size..]).await?;
+            u64::from_be_bytes(buf)
+        }
+        info => {
+            return Err(ServiceError::InvalidFrame(format!(
+                "unsupported byte string length encoding {}",
+                info
+            )))
+        }
+    };
+
+    if len > MAX_FRAME_SIZE {
+        return Err(ServiceError::FrameTooLarge(len));
+    }
+
+    let mut body = vec![0; len as usize];
+    reader.read_exact(&mut body).await?;
+
+    serde_ipld_dagcbor::from_slice(&body)
+        .map(Some)
+        .map_err(|e| ServiceError::InvalidFrame(e.to_string()))
+}
+
+/// Writes the head of a CBOR data item of the given major type and argument.
+fn write_cbor_head(buf: &mut Vec<u8>, major: u8, arg: u64) {
+    let major = major << 5;
+    if arg < 24 {
+        buf.push(major | arg as u8);
+    } else if arg <= u8::MAX as u64 {
+        buf.push(major | 24);
+        buf.push(arg as u8);
+    } else if arg <= u16::MAX as u64 {
+        buf.push(major | 25);
+        buf.extend_from_slice(&(arg as u16).to_be_bytes());
+    } else if arg <= u32::MAX as u64 {
+        buf.push(major | 26);
+        buf.extend_from_slice(&(arg as u32).to_be_bytes());
+    } else {
+        buf.push(major | 27);
+        buf.extend_from_slice(&arg.to_be_bytes());
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use crate::service::{CreateDirAt, EntityOperationKind, ReadAt};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_operation_stream() -> anyhow::Result<()> {
+        #[derive(Serialize)]
+        struct Future {
+            #[serde(rename = "type")]
+            kind: &'static str,
+            params: u64,
+        }
+
+        let operations = [
+            EntityOperation {
+                identifier: None,
+                operation: EntityOperationKind::CreateDirAt(CreateDirAt::new("docs".parse()?)),
+            },
+            EntityOperation {
+                identifier: None,
+                operation: EntityOperationKind::ReadAt(ReadAt::new("docs/a".parse()?, 4, Some(8))),
+            },
+        ];
+
+        let mut writer = OperationWriter::new(Vec::new());
+        writer.write(&operations[0]).await?;
+        writer.write(&operations[1]).await?;
+        let mut bytes = writer.finish().await?;
+
+        // An operation from a newer version is read without failing the rest of the batch.
+        let unknown = serde_ipld_dagcbor::to_vec(&Envelope::new(BTreeMap::from([(
+            "operation",
+            Future {
+                kind: "truncate_at",
+                params: 0,
+            },
+        )])))?;
+        write_cbor_head(&mut bytes, CBOR_BYTES, unknown.len() as u64);
+        bytes.extend_from_slice(&unknown);
+
+        let mut reader = OperationReader::new(bytes.as_slice());
+        assert_eq!(reader.next().await?.as_ref(), Some(&operations[0]));
+        assert_eq!(reader.next().await?.as_ref(), Some(&operations[1]));
+        assert_eq!(
+            reader.next().await?.map(|op| op.operation),
+            Some(EntityOperationKind::Unknown("truncate_at".into()))
+        );
+        assert!(reader.next().await?.is_none());
+
+        // A frame from a newer, incompatible version is rejected.
+        let newer = serde_ipld_dagcbor::to_vec(&Envelope {
+            version: WIRE_VERSION + 1,
+            body: &operations[0],
+        })?;
+        let mut bytes = Vec::new();
+        write_cbor_head(&mut bytes, CBOR_BYTES, newer.len() as u64);
+        bytes.extend_from_slice(&newer);
+        assert!(matches!(
+            OperationReader::new(bytes.as_slice()).next().await,
+            Err(ServiceError::UnsupportedWireVersion(v)) if v == WIRE_VERSION + 1
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,85 @@
+use zeroutils_store::MemoryStore;
+
+use crate::filesystem::{Dir, FsError, FsResult, Path};
+
+use super::{violation, InvariantResult};
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Checks the invariants of a parsed path:
+///
+/// - Displaying the path and parsing it again gives back the same path.
+/// - Canonicalizing a path that can be canonicalized is idempotent and only leaves named segments.
+pub fn check_path(path: &Path) -> InvariantResult<()> {
+    match path.to_string().parse::<Path>() {
+        Ok(reparsed) if reparsed == *path => {}
+        Ok(reparsed) => return violation(format!("{path} was reparsed as {reparsed}")),
+        Err(e) => return violation(format!("{path} could not be reparsed: {e}")),
+    }
+
+    let Ok(canonical) = path.canonicalize() else {
+        return Ok(());
+    };
+
+    if !canonical.iter().all(|segment| segment.is_named()) {
+        return violation(format!("{path} was canonicalized to {canonical}"));
+    }
+
+    match canonical.canonicalize() {
+        Ok(again) if again == canonical => Ok(()),
+        Ok(again) => violation(format!("canonicalizing {canonical} again gave {again}")),
+        Err(e) => violation(format!("{canonical} could not be canonicalized again: {e}")),
+    }
+}
+
+/// Decodes a directory from its DAG-CBOR encoding, the way it is decoded when loaded from a store.
+pub fn decode_dir(bytes: &[u8]) -> FsResult<Dir<MemoryStore>> {
+    let serializable = serde_ipld_dagcbor::from_slice(bytes).map_err(FsError::custom)?;
+    Dir::try_from_serializable(serializable, MemoryStore::default())
+}
+
+/// Checks that arbitrary bytes either fail to decode as a directory or decode to a directory whose
+/// encoding decodes back to the same directory.
+pub fn check_dir_encoding(bytes: &[u8]) -> InvariantResult<()> {
+    let Ok(dir) = decode_dir(bytes) else {
+        return Ok(());
+    };
+
+    let encoded = match serde_ipld_dagcbor::to_vec(&dir) {
+        Ok(encoded) => encoded,
+        Err(e) => return violation(format!("decoded directory could not be encoded: {e}")),
+    };
+
+    match decode_dir(&encoded) {
+        Ok(decoded) if decoded == dir => Ok(()),
+        Ok(decoded) => violation(format!("{dir:?} was decoded again as {decoded:?}")),
+        Err(e) => violation(format!("encoded directory could not be decoded: {e}")),
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use crate::testing::any_path;
+
+    use super::*;
+
+    proptest! {
+        #[test]
+        fn test_path_invariants(path in any_path(6)) {
+            prop_assert_eq!(check_path(&path), Ok(()));
+        }
+
+        #[test]
+        fn test_dir_encoding_invariants(bytes in prop::collection::vec(any::<u8>(), 0..256)) {
+            prop_assert_eq!(check_dir_encoding(&bytes), Ok(()));
+        }
+    }
+}
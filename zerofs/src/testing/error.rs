@@ -0,0 +1,22 @@
+use thiserror::Error;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The result of checking an invariant.
+pub type InvariantResult<T> = Result<T, InvariantViolation>;
+
+/// An invariant of the file system that does not hold.
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error("Invariant violated: {0}")]
+pub struct InvariantViolation(pub String);
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Creates an `Err` result with an [`InvariantViolation`].
+pub(crate) fn violation<T>(message: impl Into<String>) -> InvariantResult<T> {
+    Err(InvariantViolation(message.into()))
+}
@@ -0,0 +1,20 @@
+//! Property-based testing and fuzzing utilities for the file system.
+//!
+//! [`Simulation`] applies random sequences of operations to an in-memory file tree alongside a
+//! simple model of what the tree should contain, and checks the invariants of the tree after every
+//! operation. The strategies in this module generate the operations and paths, and the checks are
+//! shared with the fuzz targets under `fuzz/`.
+
+mod check;
+mod error;
+mod simulation;
+mod strategy;
+
+//--------------------------------------------------------------------------------------------------
+// Exports
+//--------------------------------------------------------------------------------------------------
+
+pub use check::*;
+pub use error::*;
+pub use simulation::*;
+pub use strategy::*;
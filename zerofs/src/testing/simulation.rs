@@ -0,0 +1,355 @@
+use std::{collections::BTreeMap, io::Cursor, sync::Arc};
+
+use chrono::{DateTime, Utc};
+use tokio::io::AsyncReadExt;
+use zeroutils_store::{MemoryStore, Storable};
+
+use crate::filesystem::{
+    with_clock, Chunker, Dir, Entity, File, FixedClock, FsResult, Path, TraceResult,
+};
+
+use super::{violation, InvariantResult, InvariantViolation};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// A deterministic simulation of a file tree held in memory.
+///
+/// Operations are applied both to a real [`Dir`] and to a model that only records which paths hold
+/// a file or a directory. The outcome of every operation is checked against the model, along with
+/// these invariants:
+///
+/// - Tracing a path the model holds finds an entity of the same kind, with the same content for
+///   files, and the tree holds no entity the model does not.
+/// - An operation leaves the subtrees that are not on its path untouched, down to their [`Cid`].
+///
+/// The simulation only depends on the operations applied to it, so a failing sequence found by
+/// proptest or a fuzzer replays the same way. Run it under a [`FixedClock`] to get the same roots
+/// as well, as [`run`][Self::run] does.
+///
+/// [`Cid`]: zeroutils_store::ipld::cid::Cid
+pub struct Simulation {
+    /// The root of the file tree.
+    root: Dir<MemoryStore>,
+
+    /// What the file tree should contain.
+    model: BTreeMap<Path, ModelEntry>,
+}
+
+/// An operation applied to a [`Simulation`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SimOp {
+    /// Creates a file with the given content at the path, replacing whatever is there.
+    CreateFile {
+        /// Where to create the file.
+        path: Path,
+
+        /// The content of the file.
+        content: Vec<u8>,
+    },
+
+    /// Creates an empty directory at the path, replacing whatever is there.
+    CreateDir {
+        /// Where to create the directory.
+        path: Path,
+    },
+
+    /// Removes the entity at the path.
+    Remove {
+        /// The entity to remove.
+        path: Path,
+    },
+}
+
+/// What the model expects at a path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ModelEntry {
+    File(Vec<u8>),
+    Dir,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl Simulation {
+    /// Creates a simulation of an empty file tree.
+    pub fn new() -> Self {
+        Self {
+            root: Dir::new(MemoryStore::default()),
+            model: BTreeMap::new(),
+        }
+    }
+
+    /// Creates a simulation and applies `ops` to it, checking the invariants after every operation.
+    ///
+    /// The clock is stopped at the Unix epoch for the whole run, so the same operations always lead
+    /// to the same root.
+    pub async fn run(ops: impl IntoIterator<Item = SimOp>) -> InvariantResult<Self> {
+        let clock = Arc::new(FixedClock::new(DateTime::<Utc>::UNIX_EPOCH));
+        with_clock(clock, async {
+            let mut simulation = Self::new();
+            for op in ops {
+                simulation.apply(&op).await?;
+                simulation.check_invariants().await?;
+            }
+
+            Ok(simulation)
+        })
+        .await
+    }
+
+    /// Returns the root of the file tree.
+    pub fn get_root(&self) -> &Dir<MemoryStore> {
+        &self.root
+    }
+
+    /// Applies `op` to the file tree and the model.
+    ///
+    /// The operation must succeed or fail as the model predicts, and must not touch the subtrees
+    /// that are not on its path.
+    pub async fn apply(&mut self, op: &SimOp) -> InvariantResult<()> {
+        let path = op.get_path();
+        let expected = self.is_expected_to_succeed(op);
+
+        let unrelated = self
+            .model
+            .keys()
+            .filter(|other| !is_prefix(path, other) && !is_prefix(other, path))
+            .cloned()
+            .collect::<Vec<_>>();
+        let before = self.get_cids(&unrelated).await?;
+
+        let result = match op {
+            SimOp::CreateFile { path, content } => self.create_file(path, content).await,
+            SimOp::CreateDir { path } => self.create_dir(path).await,
+            SimOp::Remove { path } => self.root.unlink_at(path).await.map(|(root, _)| root),
+        };
+
+        match (result, expected) {
+            (Ok(root), true) => self.root = root,
+            (Err(_), false) => return Ok(()),
+            (Ok(_), false) => {
+                return violation(format!("{op:?} succeeded but was expected to fail"))
+            }
+            (Err(e), true) => return violation(format!("{op:?} failed: {e}")),
+        }
+
+        self.update_model(op);
+
+        let after = self.get_cids(&unrelated).await?;
+        for ((path, before), after) in unrelated.iter().zip(before).zip(after) {
+            if before != after {
+                return violation(format!("{op:?} changed the unrelated entity at {path}"));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks that the file tree holds exactly what the model expects.
+    pub async fn check_invariants(&self) -> InvariantResult<()> {
+        for (path, expected) in self.model.iter() {
+            let entity = match self.root.trace_entity(path).await {
+                Ok(TraceResult::Found { entity, .. }) => entity,
+                Ok(_) => return violation(format!("{path} is not in the tree")),
+                Err(e) => return violation(format!("{path} could not be traced: {e}")),
+            };
+
+            match (entity, expected) {
+                (Entity::Dir(_), ModelEntry::Dir) => {}
+                (Entity::File(file), ModelEntry::File(content)) => {
+                    let actual = read_content(&file).await.map_err(to_violation)?;
+                    if actual != *content {
+                        return violation(format!("{path} does not have the expected content"));
+                    }
+                }
+                (entity, expected) => {
+                    return violation(format!("{path} holds {entity:?} instead of {expected:?}"))
+                }
+            }
+        }
+
+        // Walk the tree to check that it holds nothing the model does not know about.
+        let mut pending = vec![(Path::default(), self.root.clone())];
+        while let Some((path, dir)) = pending.pop() {
+            for (name, _) in dir.get_entries() {
+                let mut child_path = path.clone();
+                child_path.push(name.clone());
+
+                if !self.model.contains_key(&child_path) {
+                    return violation(format!("{child_path} is in the tree but not in the model"));
+                }
+
+                if let Some(Entity::Dir(child)) =
+                    dir.get_entity(name).await.map_err(to_violation)?
+                {
+                    pending.push((child_path, child.clone()));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn is_expected_to_succeed(&self, op: &SimOp) -> bool {
+        match op {
+            SimOp::CreateFile { path, .. } | SimOp::CreateDir { path } => {
+                !self.model.iter().any(|(other, entry)| {
+                    matches!(entry, ModelEntry::File(_))
+                        && other.len() < path.len()
+                        && is_prefix(other, path)
+                })
+            }
+            SimOp::Remove { path } => self.model.contains_key(path),
+        }
+    }
+
+    fn update_model(&mut self, op: &SimOp) {
+        let path = op.get_path();
+        self.model.retain(|other, _| !is_prefix(path, other));
+
+        let entry = match op {
+            SimOp::CreateFile { content, .. } => ModelEntry::File(content.clone()),
+            SimOp::CreateDir { .. } => ModelEntry::Dir,
+            SimOp::Remove { .. } => return,
+        };
+
+        for depth in 1..path.len() {
+            self.model
+                .entry(path.slice(..depth).to_owned())
+                .or_insert(ModelEntry::Dir);
+        }
+
+        self.model.insert(path.clone(), entry);
+    }
+
+    async fn create_file(&self, path: &Path, content: &[u8]) -> FsResult<Dir<MemoryStore>> {
+        let mut file = File::new(self.root.get_store().clone());
+        file.put_content(Cursor::new(content.to_vec()), &Chunker::Store)
+            .await?;
+
+        self.root.link_at(path, file.store().await?).await
+    }
+
+    async fn create_dir(&self, path: &Path) -> FsResult<Dir<MemoryStore>> {
+        let dir = Dir::new(self.root.get_store().clone());
+        self.root.link_at(path, dir.store().await?).await
+    }
+
+    async fn get_cids(&self, paths: &[Path]) -> InvariantResult<Vec<String>> {
+        let mut cids = Vec::with_capacity(paths.len());
+        for path in paths {
+            let entity = match self.root.trace_entity(path).await {
+                Ok(TraceResult::Found { entity, .. }) => entity,
+                Ok(_) => return violation(format!("{path} is not in the tree")),
+                Err(e) => return violation(format!("{path} could not be traced: {e}")),
+            };
+
+            let cid = entity.store().await.map_err(to_violation)?;
+            cids.push(cid.to_string());
+        }
+
+        Ok(cids)
+    }
+}
+
+impl SimOp {
+    /// Returns the path the operation applies to.
+    pub fn get_path(&self) -> &Path {
+        match self {
+            SimOp::CreateFile { path, .. } | SimOp::CreateDir { path } | SimOp::Remove { path } => {
+                path
+            }
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Returns `true` if `prefix` is `path` or one of its ancestors.
+fn is_prefix(prefix: &Path, path: &Path) -> bool {
+    prefix.len() <= path.len() && prefix.get_segments() == &path.get_segments()[..prefix.len()]
+}
+
+async fn read_content(file: &File<MemoryStore>) -> FsResult<Vec<u8>> {
+    let mut content = Vec::new();
+    file.get_content_reader()
+        .await?
+        .read_to_end(&mut content)
+        .await?;
+
+    Ok(content)
+}
+
+fn to_violation(error: impl std::fmt::Display) -> InvariantViolation {
+    InvariantViolation(error.to_string())
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl Default for Simulation {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use crate::testing::sim_ops;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_simulation_replaces_and_removes_subtrees() -> anyhow::Result<()> {
+        let ops = vec![
+            SimOp::CreateFile {
+                path: "a/b".parse()?,
+                content: b"hello".to_vec(),
+            },
+            SimOp::CreateDir { path: "c".parse()? },
+            // A file in the way of an intermediate directory fails the operation.
+            SimOp::CreateDir {
+                path: "a/b/c".parse()?,
+            },
+            // Paths are case-insensitive, so this replaces the `a` directory.
+            SimOp::CreateFile {
+                path: "A".parse()?,
+                content: Vec::new(),
+            },
+            // `a/b` went along with it.
+            SimOp::Remove {
+                path: "a/b".parse()?,
+            },
+            SimOp::Remove { path: "c".parse()? },
+        ];
+
+        let simulation = Simulation::run(ops).await?;
+        let entries = simulation.get_root().get_entries().count();
+        assert_eq!(entries, 1);
+
+        Ok(())
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn test_simulation_invariants(ops in sim_ops(24)) {
+            let runtime = tokio::runtime::Runtime::new().unwrap();
+            let result = runtime.block_on(Simulation::run(ops));
+            prop_assert!(result.is_ok(), "{}", result.err().unwrap());
+        }
+    }
+}
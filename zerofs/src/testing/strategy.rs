@@ -0,0 +1,52 @@
+use std::iter::FromIterator;
+
+use proptest::{collection, prelude::*};
+
+use crate::filesystem::{Path, PathSegment};
+
+use super::SimOp;
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Generates named path segments from a small alphabet in mixed case, so that generated paths
+/// often collide, including case-insensitively.
+pub fn named_segment() -> impl Strategy<Value = PathSegment> {
+    "[a-bA-B]{1,2}".prop_map(PathSegment::Named)
+}
+
+/// Generates any valid path segment, including `.` and `..`.
+pub fn any_segment() -> impl Strategy<Value = PathSegment> {
+    prop_oneof![
+        1 => Just(PathSegment::CurrentDir),
+        1 => Just(PathSegment::ParentDir),
+        4 => "[a-zA-Z0-9]{1,8}".prop_map(PathSegment::Named),
+    ]
+}
+
+/// Generates non-empty paths of named segments with at most `max_depth` segments.
+pub fn named_path(max_depth: usize) -> impl Strategy<Value = Path> {
+    collection::vec(named_segment(), 1..=max_depth).prop_map(Path::from_iter)
+}
+
+/// Generates any valid path with at most `max_depth` segments.
+pub fn any_path(max_depth: usize) -> impl Strategy<Value = Path> {
+    collection::vec(any_segment(), 0..=max_depth).prop_map(Path::from_iter)
+}
+
+/// Generates an operation to apply to a [`Simulation`][super::Simulation].
+pub fn sim_op() -> impl Strategy<Value = SimOp> {
+    prop_oneof![
+        (named_path(3), collection::vec(any::<u8>(), 0..64))
+            .prop_map(|(path, content)| SimOp::CreateFile { path, content }),
+        named_path(3).prop_map(|path| SimOp::CreateDir { path }),
+        named_path(3).prop_map(|path| SimOp::Remove { path }),
+    ]
+}
+
+/// Generates a sequence of at most `max_len` operations to apply to a
+/// [`Simulation`][super::Simulation].
+pub fn sim_ops(max_len: usize) -> impl Strategy<Value = Vec<SimOp>> {
+    collection::vec(sim_op(), 0..=max_len)
+}